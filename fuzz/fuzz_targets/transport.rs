@@ -0,0 +1,21 @@
+//! Feeds arbitrary server output to the framed reader, which must skip or reject it
+//! without panicking or buffering past the message size limit.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pathfinder::transport::FramedTransport;
+
+/// Small enough that bogus Content-Lengths are rejected rather than waited on.
+const MAX_MESSAGE_BYTES: usize = 1024 * 1024;
+
+fuzz_target!(|data: &[u8]| {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let mut transport =
+            FramedTransport::new(data, tokio::io::sink()).with_max_message_bytes(MAX_MESSAGE_BYTES);
+        while let Ok(Some(_)) = transport.read().await {}
+    });
+});
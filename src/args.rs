@@ -1,31 +1,317 @@
 //! Command-line argument parsing using clap.
 
+use std::io::Write;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
-use anyhow::{Result, anyhow};
-use clap::Parser;
+use anyhow::{Result, anyhow, bail};
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+
+use crate::position::PositionEncoding;
+
+/// Config file to load, like `--config`.
+pub const ENV_CONFIG: &str = "PATHFINDER_CONFIG";
+
+/// Server command line, split like a shell would, like `--server`.
+pub const ENV_SERVER: &str = "PATHFINDER_SERVER";
+
+/// Comma-separated file extensions for `PATHFINDER_SERVER`, like `--extension`.
+pub const ENV_EXTENSIONS: &str = "PATHFINDER_EXTENSIONS";
+
+/// Workspace base directory, like `--workspace`.
+pub const ENV_WORKSPACE: &str = "PATHFINDER_WORKSPACE";
 
 /// MCP server that bridges to Language Server Protocol servers
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 #[command(after_help = "EXAMPLES:\n  \
     pathfinder -e py -s pyright-langserver -- --stdio\n  \
     pathfinder -e py -e pyi -s uv run pyright -- --stdio\n  \
-    pathfinder -e rs -s rust-analyzer -w /path/to/project")]
+    pathfinder -e rs -s rust-analyzer -w /path/to/project\n  \
+    pathfinder -c pathfinder.toml\n  \
+    pathfinder --transport http --bind 127.0.0.1:8080 -c pathfinder.toml\n  \
+    pathfinder --attach -e rs -s rust-analyzer -w /path/to/project\n  \
+    pathfinder doctor --json -e rs -s rust-analyzer\n  \
+    pathfinder query definition --file src/main.rs --line 2 --col 17 -c pathfinder.toml\n  \
+    pathfinder init -w /path/to/project\n  \
+    pathfinder completions zsh > ~/.zfunc/_pathfinder\n\n\
+ENVIRONMENT:\n  \
+    PATHFINDER_CONFIG       Config file, when neither -c nor -e/-s is given\n  \
+    PATHFINDER_SERVER       Server command line, with PATHFINDER_EXTENSIONS (e.g. rs,ron)\n  \
+    PATHFINDER_WORKSPACE    Workspace directory, when -w is not given")]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub server_args: ServerArgs,
+
+    #[command(flatten)]
+    pub transport_args: TransportArgs,
+
+    #[command(flatten)]
+    pub log_args: LogArgs,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Diagnose the configured LSP server and workspace, then exit
+    Doctor(DoctorArgs),
+    /// Detect languages in the workspace and write a starter config file
+    Init(InitArgs),
+    /// Run one tool against the configured LSP server, print its result, then exit
+    Query(QueryArgs),
+    /// Print a shell completion script
+    Completions(CompletionsArgs),
+    /// Print the man page, or write one per subcommand to a directory
+    Man(ManArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct CompletionsArgs {
+    /// Shell to complete for
+    #[arg(value_enum)]
+    pub shell: Shell,
+}
+
+#[derive(Args, Debug)]
+pub struct ManArgs {
+    /// Write `pathfinder.1` and a `pathfinder-<subcommand>.1` page for each subcommand
+    /// here instead of printing `pathfinder.1`
+    #[arg(long, value_name = "DIR")]
+    pub dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct DoctorArgs {
+    /// Print the report as JSON instead of human-readable text
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub server_args: ServerArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct QueryArgs {
+    /// Tool to run
+    ///
+    /// Examples: definition, references, hover, find_symbol, read_file
+    #[arg(value_name = "TOOL")]
+    pub tool: String,
+
+    /// Document the tool works on, relative to the workspace
+    #[arg(long, value_name = "PATH")]
+    pub file: Option<String>,
+
+    /// Line, counted from 1
+    #[arg(long)]
+    pub line: Option<u32>,
+
+    /// Column, counted from 1 in the configured column encoding
+    #[arg(long, alias = "column")]
+    pub col: Option<u32>,
+
+    /// Any other tool argument (can be specified multiple times)
+    ///
+    /// VALUE is parsed as JSON if it can be, else taken as a string.
+    /// Example: --arg query=Config --arg kind=struct
+    #[arg(long = "arg", value_name = "KEY=VALUE", action = clap::ArgAction::Append)]
+    pub arguments: Vec<String>,
+
+    /// Print the structured result as JSON instead of a text summary
+    #[arg(long)]
+    pub json: bool,
+
+    #[command(flatten)]
+    pub server_args: ServerArgs,
+}
+
+#[derive(Args, Debug)]
+pub struct InitArgs {
+    /// Workspace to inspect (defaults to current directory)
+    #[arg(short, long, value_name = "PATH")]
+    pub workspace: Option<PathBuf>,
+
+    /// Config file format to write
+    #[arg(long, value_enum, default_value_t = ConfigFormat::Json)]
+    pub format: ConfigFormat,
+
+    /// Language to configure instead of the most common detected one
+    ///
+    /// Examples: rust, python, typescript, go, c
+    #[arg(short, long, value_name = "LANG")]
+    pub language: Option<String>,
+
+    /// Accept detected defaults without prompting
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// Overwrite an existing config file
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+/// How MCP clients reach pathfinder.
+#[derive(Args, Debug)]
+pub struct TransportArgs {
+    /// MCP transport to serve
+    ///
+    /// `stdio` serves one client over stdin/stdout; `http` serves any number of
+    /// clients over Streamable HTTP at `/mcp`, sharing one language server.
+    #[arg(long, value_enum, default_value_t = Transport::Stdio)]
+    pub transport: Transport,
+
+    /// Address to listen on with `--transport http`
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    pub bind: SocketAddr,
+
+    /// Share one language server between pathfinder instances for the same workspace
+    ///
+    /// The first instance serves its client and accepts the others on a local socket;
+    /// later instances forward their client to it instead of starting a server.
+    #[arg(long)]
+    pub attach: bool,
+
+    /// Control socket for `--attach` (defaults to one derived from workspace and server)
+    #[arg(long, value_name = "PATH", requires = "attach")]
+    pub socket: Option<PathBuf>,
+
+    /// Also serve metrics in the Prometheus text format at `/metrics` (with
+    /// `--transport http`)
+    #[arg(long)]
+    pub metrics: bool,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Stdio,
+    Http,
+}
+
+/// Where pathfinder's own log goes. stdout carries MCP traffic over stdio, so it
+/// goes to stderr unless `--log-file` is given.
+#[derive(Args, Debug, Default)]
+pub struct LogArgs {
+    /// Write the log to FILE instead of stderr
+    #[arg(long, value_name = "FILE")]
+    pub log_file: Option<PathBuf>,
+
+    /// Log line format
+    #[arg(long, value_enum, default_value_t = LogFormat::Text)]
+    pub log_format: LogFormat,
+
+    /// Start a new log file every hour or day, named FILE.YYYY-MM-DD-HH or
+    /// FILE.YYYY-MM-DD, instead of appending to FILE
+    #[arg(long, value_enum, default_value_t = LogRotation::Never, requires = "log_file")]
+    pub log_rotation: LogRotation,
+
+    /// Rotated log files to keep; older ones are deleted (default: keep all)
+    #[arg(long, value_name = "N", requires = "log_file")]
+    pub log_keep: Option<usize>,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Text,
+    /// One JSON object per event, with its span fields
+    Json,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+/// Arguments describing the LSP server to bridge to.
+#[derive(Args, Debug, Default)]
+pub struct ServerArgs {
     /// File extensions to handle (can be specified multiple times)
     ///
     /// Examples: py, rs, js, ts
-    #[arg(short, long, value_name = "EXT", action = clap::ArgAction::Append, required = true)]
+    #[arg(short, long, value_name = "EXT", action = clap::ArgAction::Append, requires = "backend")]
     pub extension: Vec<String>,
 
     /// LSP server command and arguments
     ///
     /// Everything after --server is passed to the LSP server.
     /// Use -- to clearly separate server flags: --server cmd -- --flag
-    #[arg(short, long, value_name = "CMD", num_args = 1.., required = true, allow_hyphen_values = true)]
+    #[arg(short, long, value_name = "CMD", num_args = 1.., group = "backend", requires = "extension", allow_hyphen_values = true)]
     pub server: Vec<String>,
 
+    /// Answer from a built-in mock language server instead of running one
+    ///
+    /// For trying pathfinder and MCP clients out without a language server installed.
+    #[arg(long, group = "backend", requires = "extension", conflicts_with_all = ["server", "config"])]
+    pub mock: bool,
+
+    /// Seconds to wait for server indexing before accepting empty results
+    #[arg(long, value_name = "SECS")]
+    pub indexing_wait: Option<u64>,
+
+    /// Default LSP request timeout in milliseconds
+    #[arg(long, value_name = "MS")]
+    pub timeout: Option<u64>,
+
+    /// Timeout override for one LSP method (can be specified multiple times)
+    ///
+    /// Example: --method-timeout workspace/symbol=60000
+    #[arg(long, value_name = "METHOD=MS", action = clap::ArgAction::Append)]
+    pub method_timeout: Vec<String>,
+
+    /// Environment variable for the server process (can be specified multiple times)
+    ///
+    /// Example: --env RA_LOG=info
+    #[arg(long, value_name = "KEY=VAL", action = clap::ArgAction::Append)]
+    pub env: Vec<String>,
+
+    /// Unit of the `character` columns in tool inputs and outputs
+    #[arg(long, value_enum, value_name = "ENCODING")]
+    pub column_encoding: Option<PositionEncoding>,
+
+    /// Append every message exchanged with the language server to FILE (JSON lines)
+    ///
+    /// Replay it later with `connect = { replay = "FILE" }` in a config file.
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Let tools open documents anywhere, not just under the workspace root and
+    /// `sandbox.allowedRoots`
+    #[arg(long)]
+    pub no_sandbox: bool,
+
+    /// Never change the workspace: reject server edits, drop `lsp_request`, and
+    /// refuse to record into the workspace
+    #[arg(long)]
+    pub readonly: bool,
+
+    /// MCP tool not to offer (can be specified multiple times)
+    ///
+    /// Adds to `tools.disabled` from the config file. Example: --disable-tool open_document
+    #[arg(long, value_name = "TOOL", action = clap::ArgAction::Append)]
+    pub disable_tool: Vec<String>,
+
+    /// Load the server configuration from a JSON or TOML file
+    ///
+    /// Without this or -e/-s, the first `.pathfinder.toml`, `.pathfinder.json`,
+    /// `pathfinder.toml`, or `pathfinder.json` found in the workspace or a parent
+    /// directory (up to the repository root) is used.
+    #[arg(short, long, value_name = "FILE", conflicts_with_all = ["extension", "server"])]
+    pub config: Option<PathBuf>,
+
     /// Workspace base directory (defaults to current directory)
     #[arg(short, long, value_name = "PATH")]
     pub workspace: Option<PathBuf>,
@@ -35,26 +321,142 @@ pub struct Cli {
 pub struct ServerSpec {
     pub extensions: Vec<String>,
     pub command: Vec<String>,
+    pub indexing_wait_secs: Option<u64>,
+    pub timeout_ms: Option<u64>,
+    pub method_timeouts: Vec<(String, u64)>,
+    pub column_encoding: Option<PositionEncoding>,
+    pub env: Vec<(String, String)>,
+    /// Use the mock server rather than `command`.
+    pub mock: bool,
 }
 
-impl Cli {
+impl ServerArgs {
+    /// Fills in what the command line left out from `PATHFINDER_*` variables, read
+    /// through `lookup`. Flags win over variables: a server given with `-c` or `-e`/`-s`
+    /// ignores `PATHFINDER_CONFIG` and `PATHFINDER_SERVER`, and `-w` ignores
+    /// `PATHFINDER_WORKSPACE`. Empty variables count as unset.
+    pub fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<()> {
+        let var = |name: &str| lookup(name).filter(|value| !value.trim().is_empty());
+        if self.workspace.is_none() {
+            self.workspace = var(ENV_WORKSPACE).map(PathBuf::from);
+        }
+        if self.config.is_some() || !self.extension.is_empty() {
+            return Ok(());
+        }
+        let extensions = var(ENV_EXTENSIONS);
+        match (var(ENV_CONFIG), var(ENV_SERVER)) {
+            (Some(_), Some(_)) => bail!("set {ENV_CONFIG} or {ENV_SERVER}, not both"),
+            (Some(config), None) => {
+                if extensions.is_some() {
+                    bail!("{ENV_EXTENSIONS} only applies with {ENV_SERVER}, not {ENV_CONFIG}");
+                }
+                self.config = Some(PathBuf::from(config));
+            }
+            (None, Some(server)) => {
+                self.server = shlex::split(&server)
+                    .filter(|words| !words.is_empty())
+                    .ok_or_else(|| anyhow!("{ENV_SERVER} is not a valid command line: {server}"))?;
+                self.extension = extensions
+                    .iter()
+                    .flat_map(|list| list.split(','))
+                    .map(|ext| ext.trim().trim_start_matches('.'))
+                    .filter(|ext| !ext.is_empty())
+                    .map(String::from)
+                    .collect();
+                if self.extension.is_empty() {
+                    bail!("{ENV_SERVER} needs {ENV_EXTENSIONS} too, e.g. {ENV_EXTENSIONS}=rs");
+                }
+            }
+            (None, None) => {
+                if extensions.is_some() {
+                    bail!("{ENV_EXTENSIONS} needs {ENV_SERVER}");
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Convert CLI args to server specifications
     pub fn to_server_specs(self) -> Result<Vec<ServerSpec>> {
         if self.extension.is_empty() {
             return Err(anyhow!("at least one --extension must be specified"));
         }
 
-        if self.server.is_empty() {
+        if self.server.is_empty() && !self.mock {
             return Err(anyhow!("--server command cannot be empty"));
         }
 
+        let method_timeouts = self
+            .method_timeout
+            .iter()
+            .map(|entry| parse_method_timeout(entry))
+            .collect::<Result<Vec<_>>>()?;
+        let env = self
+            .env
+            .iter()
+            .map(|entry| {
+                let (key, value) = split_assignment("--env", "KEY=VAL", entry)?;
+                Ok((key.to_string(), value.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         Ok(vec![ServerSpec {
             extensions: self.extension,
             command: self.server,
+            indexing_wait_secs: self.indexing_wait,
+            timeout_ms: self.timeout,
+            method_timeouts,
+            column_encoding: self.column_encoding,
+            env,
+            mock: self.mock,
         }])
     }
 }
 
+/// Writes the completion script for `shell` to `out`.
+pub fn write_completions(shell: Shell, out: &mut dyn Write) {
+    clap_complete::generate(shell, &mut Cli::command(), "pathfinder", out);
+}
+
+/// Man pages as `(file name, roff)`: `pathfinder.1`, then one per subcommand.
+pub fn man_pages() -> Result<Vec<(String, Vec<u8>)>> {
+    let command = Cli::command();
+    let mut pages = vec![("pathfinder.1".to_string(), render_man(command.clone())?)];
+    for subcommand in command.get_subcommands() {
+        let name = format!("pathfinder-{}", subcommand.get_name());
+        let page = subcommand.clone().name(name.clone());
+        pages.push((format!("{name}.1"), render_man(page)?));
+    }
+    Ok(pages)
+}
+
+fn render_man(command: clap::Command) -> Result<Vec<u8>> {
+    let mut roff = Vec::new();
+    clap_mangen::Man::new(command).render(&mut roff)?;
+    Ok(roff)
+}
+
+/// Parses a `METHOD=MS` pair from `--method-timeout`.
+fn parse_method_timeout(entry: &str) -> Result<(String, u64)> {
+    let (method, ms) = split_assignment("--method-timeout", "METHOD=MS", entry)?;
+    let ms = ms
+        .parse()
+        .map_err(|_| anyhow!("--method-timeout value must be milliseconds, got '{ms}'"))?;
+    Ok((method.to_string(), ms))
+}
+
+/// Splits `NAME=VALUE` at the first `=`; the name must be non-empty.
+pub(crate) fn split_assignment<'a>(
+    flag: &str,
+    shape: &str,
+    entry: &'a str,
+) -> Result<(&'a str, &'a str)> {
+    entry
+        .split_once('=')
+        .filter(|(name, _)| !name.is_empty())
+        .ok_or_else(|| anyhow!("{flag} expects {shape}, got '{entry}'"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -70,8 +472,11 @@ mod tests {
     #[test]
     fn python_single_extension() {
         let cli = parse_args(&["-e", "py", "-s", "pyright-langserver", "--", "--stdio"]).unwrap();
-        assert_eq!(cli.extension, vec!["py"]);
-        assert_eq!(cli.server, vec!["pyright-langserver", "--", "--stdio"]);
+        assert_eq!(cli.server_args.extension, vec!["py"]);
+        assert_eq!(
+            cli.server_args.server,
+            vec!["pyright-langserver", "--", "--stdio"]
+        );
     }
 
     #[test]
@@ -88,16 +493,22 @@ mod tests {
             "--stdio",
         ])
         .unwrap();
-        assert_eq!(cli.extension, vec!["py", "pyi"]);
-        assert_eq!(cli.server, vec!["pyright-langserver", "--", "--stdio"]);
+        assert_eq!(cli.server_args.extension, vec!["py", "pyi"]);
+        assert_eq!(
+            cli.server_args.server,
+            vec!["pyright-langserver", "--", "--stdio"]
+        );
     }
 
     #[test]
     fn rust_with_workspace() {
         let cli = parse_args(&["-w", "/tmp/myproject", "-e", "rs", "-s", "rust-analyzer"]).unwrap();
-        assert_eq!(cli.extension, vec!["rs"]);
-        assert_eq!(cli.server, vec!["rust-analyzer"]);
-        assert_eq!(cli.workspace, Some(PathBuf::from("/tmp/myproject")));
+        assert_eq!(cli.server_args.extension, vec!["rs"]);
+        assert_eq!(cli.server_args.server, vec!["rust-analyzer"]);
+        assert_eq!(
+            cli.server_args.workspace,
+            Some(PathBuf::from("/tmp/myproject"))
+        );
     }
 
     #[test]
@@ -112,9 +523,9 @@ mod tests {
             "--stdio",
         ])
         .unwrap();
-        assert_eq!(cli.extension, vec!["ts"]);
+        assert_eq!(cli.server_args.extension, vec!["ts"]);
         assert_eq!(
-            cli.server,
+            cli.server_args.server,
             vec!["typescript-language-server", "--", "--stdio"]
         );
     }
@@ -133,9 +544,9 @@ mod tests {
             "--stdio",
         ])
         .unwrap();
-        assert_eq!(cli.extension, vec!["py"]);
+        assert_eq!(cli.server_args.extension, vec!["py"]);
         assert_eq!(
-            cli.server,
+            cli.server_args.server,
             vec!["uv", "run", "pyright-langserver", "--", "--stdio"]
         );
     }
@@ -152,13 +563,247 @@ mod tests {
             "--stdio",
         ])
         .unwrap();
-        assert_eq!(cli.extension, vec!["jsx"]);
+        assert_eq!(cli.server_args.extension, vec!["jsx"]);
         assert_eq!(
-            cli.server,
+            cli.server_args.server,
             vec!["typescript-language-server", "--", "--stdio"]
         );
     }
 
+    #[test]
+    fn doctor_subcommand() {
+        let cli = parse_args(&["doctor", "--json", "-e", "rs", "-s", "rust-analyzer"]).unwrap();
+        let Some(Command::Doctor(doctor)) = cli.command else {
+            panic!("expected doctor subcommand");
+        };
+        assert!(doctor.json);
+        assert_eq!(doctor.server_args.extension, vec!["rs"]);
+        assert_eq!(doctor.server_args.server, vec!["rust-analyzer"]);
+    }
+
+    #[test]
+    fn query_subcommand() {
+        let cli = parse_args(&[
+            "query",
+            "definition",
+            "--file",
+            "src/main.rs",
+            "--line",
+            "2",
+            "--col",
+            "17",
+            "--arg",
+            "snap=false",
+            "-e",
+            "rs",
+            "-s",
+            "rust-analyzer",
+        ])
+        .unwrap();
+        let Some(Command::Query(query)) = cli.command else {
+            panic!("expected query subcommand");
+        };
+        assert_eq!(query.tool, "definition");
+        assert_eq!(query.file.as_deref(), Some("src/main.rs"));
+        assert_eq!((query.line, query.col), (Some(2), Some(17)));
+        assert_eq!(query.arguments, ["snap=false"]);
+        assert_eq!(query.server_args.server, vec!["rust-analyzer"]);
+    }
+
+    #[test]
+    fn completions_and_man_pages_cover_subcommands() {
+        let cli = parse_args(&["completions", "bash"]).unwrap();
+        let Some(Command::Completions(args)) = cli.command else {
+            panic!("expected completions subcommand");
+        };
+        let mut script = Vec::new();
+        write_completions(args.shell, &mut script);
+        let script = String::from_utf8(script).unwrap();
+        assert!(script.split_whitespace().any(|word| word == "query"));
+        assert!(script.contains("--column-encoding"));
+
+        let pages = man_pages().unwrap();
+        let names: Vec<&str> = pages.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(names[0], "pathfinder.1");
+        assert!(names.contains(&"pathfinder-doctor.1"));
+        assert!(names.contains(&"pathfinder-man.1"));
+        let query = &pages
+            .iter()
+            .find(|(name, _)| name == "pathfinder-query.1")
+            .unwrap()
+            .1;
+        assert!(String::from_utf8_lossy(query).contains("\\-\\-file"));
+    }
+
+    #[test]
+    fn doctor_requires_server() {
+        let result = parse_args(&["doctor", "-e", "rs"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn config_file_replaces_server_flags() {
+        let cli = parse_args(&["-c", "pathfinder.toml"]).unwrap();
+        assert_eq!(
+            cli.server_args.config,
+            Some(PathBuf::from("pathfinder.toml"))
+        );
+        assert!(parse_args(&["-c", "pathfinder.toml", "-e", "rs"]).is_err());
+    }
+
+    #[test]
+    fn disable_tool_combines_with_config_file() {
+        let cli = parse_args(&[
+            "--disable-tool",
+            "open_document",
+            "--disable-tool",
+            "update_document",
+            "-c",
+            "pathfinder.toml",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.server_args.disable_tool,
+            vec!["open_document", "update_document"]
+        );
+    }
+
+    #[test]
+    fn transport_defaults_to_stdio() {
+        let cli = parse_args(&["-e", "rs", "-s", "rust-analyzer"]).unwrap();
+        assert_eq!(cli.transport_args.transport, Transport::Stdio);
+    }
+
+    #[test]
+    fn http_transport_with_bind_address() {
+        let cli = parse_args(&[
+            "--transport",
+            "http",
+            "--bind",
+            "0.0.0.0:9000",
+            "-c",
+            "pathfinder.toml",
+        ])
+        .unwrap();
+        assert_eq!(cli.transport_args.transport, Transport::Http);
+        assert_eq!(cli.transport_args.bind, "0.0.0.0:9000".parse().unwrap());
+        assert!(parse_args(&["--transport", "http", "--bind", "localhost", "-c", "a"]).is_err());
+    }
+
+    #[test]
+    fn log_file_with_rotation() {
+        let cli = parse_args(&[
+            "--log-file",
+            "/var/log/pathfinder.log",
+            "--log-format",
+            "json",
+            "--log-rotation",
+            "daily",
+            "--log-keep",
+            "7",
+            "-c",
+            "pathfinder.toml",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.log_args.log_file,
+            Some(PathBuf::from("/var/log/pathfinder.log"))
+        );
+        assert_eq!(cli.log_args.log_format, LogFormat::Json);
+        assert_eq!(cli.log_args.log_rotation, LogRotation::Daily);
+        assert_eq!(cli.log_args.log_keep, Some(7));
+        assert!(parse_args(&["--log-rotation", "daily", "-c", "pathfinder.toml"]).is_err());
+    }
+
+    #[test]
+    fn attach_with_socket() {
+        let cli = parse_args(&[
+            "--attach",
+            "--socket",
+            "/tmp/pf.sock",
+            "-e",
+            "rs",
+            "-s",
+            "rust-analyzer",
+        ])
+        .unwrap();
+        assert!(cli.transport_args.attach);
+        assert_eq!(
+            cli.transport_args.socket,
+            Some(PathBuf::from("/tmp/pf.sock"))
+        );
+        assert!(parse_args(&["--socket", "/tmp/pf.sock", "-c", "pathfinder.toml"]).is_err());
+    }
+
+    #[test]
+    fn init_subcommand() {
+        let cli = parse_args(&["init", "--format", "toml", "-l", "rust", "-y"]).unwrap();
+        let Some(Command::Init(init)) = cli.command else {
+            panic!("expected init subcommand");
+        };
+        assert_eq!(init.format, ConfigFormat::Toml);
+        assert_eq!(init.language.as_deref(), Some("rust"));
+        assert!(init.yes);
+        assert!(!init.force);
+    }
+
+    #[test]
+    fn method_timeouts_are_parsed() {
+        let cli = parse_args(&[
+            "-e",
+            "rs",
+            "--timeout",
+            "5000",
+            "--method-timeout",
+            "workspace/symbol=60000",
+            "-s",
+            "rust-analyzer",
+        ])
+        .unwrap();
+        let spec = cli.server_args.to_server_specs().unwrap().remove(0);
+        assert_eq!(spec.timeout_ms, Some(5000));
+        assert_eq!(
+            spec.method_timeouts,
+            vec![("workspace/symbol".to_string(), 60000)]
+        );
+        assert!(parse_method_timeout("workspace/symbol").is_err());
+        assert!(parse_method_timeout("=5").is_err());
+        assert!(parse_method_timeout("hover=soon").is_err());
+    }
+
+    #[test]
+    fn env_assignments_are_parsed() {
+        let cli = parse_args(&[
+            "-e",
+            "go",
+            "--env",
+            "GOFLAGS=-tags=a,b",
+            "--env",
+            "EMPTY=",
+            "-s",
+            "gopls",
+        ])
+        .unwrap();
+        let spec = cli.server_args.to_server_specs().unwrap().remove(0);
+        assert_eq!(
+            spec.env,
+            vec![
+                ("GOFLAGS".to_string(), "-tags=a,b".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ]
+        );
+        assert!(split_assignment("--env", "KEY=VAL", "NOVALUE").is_err());
+    }
+
+    #[test]
+    fn mock_replaces_the_server_command() {
+        let cli = parse_args(&["-e", "rs", "--mock"]).unwrap();
+        let spec = cli.server_args.to_server_specs().unwrap().remove(0);
+        assert!(spec.mock);
+        assert!(spec.command.is_empty());
+        assert!(parse_args(&["-e", "rs", "--mock", "-s", "rust-analyzer"]).is_err());
+    }
+
     #[test]
     fn error_no_extension() {
         let result = parse_args(&["-s", "rust-analyzer"]);
@@ -170,4 +815,60 @@ mod tests {
         let result = parse_args(&["-e", "py"]);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn environment_fills_in_what_flags_leave_out() {
+        let env = |vars: &'static [(&'static str, &'static str)]| {
+            move |name: &str| {
+                vars.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            }
+        };
+        let mut args = parse_args(&[]).unwrap().server_args;
+        args.apply_env(env(&[
+            (ENV_SERVER, "uv run 'pyright-langserver' --stdio"),
+            (ENV_EXTENSIONS, "py, .pyi"),
+            (ENV_WORKSPACE, "/srv/app"),
+        ]))
+        .unwrap();
+        assert_eq!(args.server, ["uv", "run", "pyright-langserver", "--stdio"]);
+        assert_eq!(args.extension, ["py", "pyi"]);
+        assert_eq!(args.workspace, Some(PathBuf::from("/srv/app")));
+
+        let mut args = parse_args(&["-w", "here", "-c", "mine.toml"])
+            .unwrap()
+            .server_args;
+        args.apply_env(env(&[
+            (ENV_CONFIG, "theirs.toml"),
+            (ENV_WORKSPACE, "/srv/app"),
+        ]))
+        .unwrap();
+        assert_eq!(args.config, Some(PathBuf::from("mine.toml")));
+        assert_eq!(args.workspace, Some(PathBuf::from("here")));
+
+        let mut args = parse_args(&[]).unwrap().server_args;
+        args.apply_env(env(&[(ENV_CONFIG, "team.toml"), (ENV_SERVER, "")]))
+            .unwrap();
+        assert_eq!(args.config, Some(PathBuf::from("team.toml")));
+
+        let mut args = parse_args(&[]).unwrap().server_args;
+        let err = args
+            .apply_env(env(&[(ENV_SERVER, "rust-analyzer")]))
+            .unwrap_err();
+        assert!(err.to_string().contains(ENV_EXTENSIONS));
+        let mut args = parse_args(&[]).unwrap().server_args;
+        assert!(
+            args.apply_env(env(&[(ENV_CONFIG, "a.toml"), (ENV_SERVER, "b")]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn no_server_flags_leave_the_config_to_discovery() {
+        let cli = parse_args(&["-w", "/tmp/myproject"]).unwrap();
+        assert!(cli.server_args.config.is_none());
+        assert!(cli.server_args.extension.is_empty());
+        assert!(parse_args(&["doctor"]).is_ok());
+    }
 }
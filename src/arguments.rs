@@ -0,0 +1,109 @@
+//! The document arguments of tool calls.
+//!
+//! The service handles document arguments the same way for all tools, once, before
+//! the call is routed: each is canonicalized and checked against the sandbox, and
+//! tools that ask the server about their documents have all of them synced first.
+//! A new tool naming documents adds its arguments to [`Documents::of`] instead of
+//! repeating either step.
+
+use rmcp::model::JsonObject;
+use serde_json::Value;
+
+/// How a tool's document arguments are handled before the call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Documents {
+    /// Arguments holding a document (file:// URI or path), or a list of them
+    names: &'static [&'static str],
+    /// Whether the documents are synced on the server before the call; tools that
+    /// manage the open documents themselves only get a ready server
+    pub sync: bool,
+}
+
+impl Documents {
+    const SYNCED_URI: Self = Self {
+        names: &["uri"],
+        sync: true,
+    };
+
+    /// The document arguments of `tool`, or None for tools naming no documents.
+    pub fn of(tool: &str) -> Option<Self> {
+        match tool {
+            "definition"
+            | "references"
+            | "rename"
+            | "code_action"
+            | "expand_macro"
+            | "view_hir"
+            | "switch_source_header"
+            | "go_to_source_definition"
+            | "lsp_request" => Some(Self::SYNCED_URI),
+            "open_document" | "update_document" | "check_edit" => Some(Self {
+                names: &["uri"],
+                sync: false,
+            }),
+            "read_file_range" => Some(Self {
+                names: &["path"],
+                sync: false,
+            }),
+            "dependency_graph" => Some(Self {
+                names: &["files"],
+                sync: false,
+            }),
+            _ => None,
+        }
+    }
+
+    /// The documents named in `arguments`. Values of the wrong type are left for the
+    /// tool's own argument parsing to reject.
+    pub fn documents_mut<'a>(&self, arguments: &'a mut JsonObject) -> Vec<&'a mut String> {
+        let mut documents = Vec::new();
+        for (name, value) in arguments.iter_mut() {
+            if !self.names.contains(&name.as_str()) {
+                continue;
+            }
+            match value {
+                Value::String(uri) => documents.push(uri),
+                Value::Array(uris) => {
+                    documents.extend(uris.iter_mut().filter_map(|uri| match uri {
+                        Value::String(uri) => Some(uri),
+                        _ => None,
+                    }))
+                }
+                _ => {}
+            }
+        }
+        documents
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn arguments(value: Value) -> JsonObject {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn finds_single_and_listed_documents() {
+        let mut args = arguments(json!({"uri": "src/lib.rs", "line": 1}));
+        let documents = Documents::of("definition").unwrap();
+        assert_eq!(documents.documents_mut(&mut args), vec!["src/lib.rs"]);
+
+        let mut args = arguments(json!({"files": ["a.rs", "b.rs"], "uri": "c.rs"}));
+        let documents = Documents::of("dependency_graph").unwrap();
+        assert!(!documents.sync);
+        assert_eq!(documents.documents_mut(&mut args), vec!["a.rs", "b.rs"]);
+    }
+
+    #[test]
+    fn leaves_missing_and_mistyped_arguments_alone() {
+        let documents = Documents::of("lsp_request").unwrap();
+        let mut args = arguments(json!({"method": "x", "uri": null}));
+        assert!(documents.documents_mut(&mut args).is_empty());
+        let mut args = arguments(json!({"uri": 3}));
+        assert!(documents.documents_mut(&mut args).is_empty());
+        assert_eq!(Documents::of("search_text"), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! Caching of LSP results that only depend on the workspace's content.
+//!
+//! For unchanged documents, hover, definition, and documentSymbol requests answer the
+//! same params the same way, and agents often ask the same question more than once.
+//! The bridge keeps their results in a [`ResultCache`] keyed by method and params, and
+//! empties it whenever it sends a notification that can change what the server sees:
+//! didOpen/didChange/didClose of text and notebook documents, watched-file events,
+//! and configuration changes. A restarted server starts with an empty cache along
+//! with its new bridge.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde_json::Value;
+
+/// Requests whose results are cached.
+pub const CACHED_METHODS: &[&str] = &[
+    "textDocument/hover",
+    "textDocument/definition",
+    "textDocument/documentSymbol",
+];
+
+/// Whether sending the notification `method` may change cached results.
+pub fn invalidates(method: &str) -> bool {
+    ["textDocument/", "notebookDocument/", "workspace/"]
+        .iter()
+        .any(|prefix| method.starts_with(prefix))
+}
+
+#[derive(Debug, Default)]
+pub struct ResultCache {
+    /// Most results kept; 0 turns caching off.
+    capacity: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Bumped on every invalidation, so results requested before one are not stored
+    /// after it.
+    generation: u64,
+    entries: HashMap<String, Value>,
+}
+
+impl ResultCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Whether results of `method` are cached.
+    pub fn caches(&self, method: &str) -> bool {
+        self.capacity > 0 && CACHED_METHODS.contains(&method)
+    }
+
+    /// The current generation, to pass to [`ResultCache::insert`] once the result
+    /// of a request sent now arrives.
+    pub fn generation(&self) -> u64 {
+        self.state
+            .lock()
+            .expect("result cache lock poisoned")
+            .generation
+    }
+
+    pub fn get(&self, method: &str, params: &Value) -> Option<Value> {
+        let state = self.state.lock().expect("result cache lock poisoned");
+        state.entries.get(&key(method, params)).cloned()
+    }
+
+    /// Stores `result` unless the cache was invalidated since `generation` or the
+    /// result is empty, which may just mean the server has not caught up yet.
+    pub fn insert(&self, generation: u64, method: &str, params: &Value, result: &Value) {
+        let empty = match result {
+            Value::Null => true,
+            Value::Array(items) => items.is_empty(),
+            _ => false,
+        };
+        if empty {
+            return;
+        }
+        let mut state = self.state.lock().expect("result cache lock poisoned");
+        if state.generation != generation {
+            return;
+        }
+        if state.entries.len() >= self.capacity {
+            state.entries.clear();
+        }
+        state.entries.insert(key(method, params), result.clone());
+    }
+
+    /// Drops every result.
+    pub fn invalidate(&self) {
+        let mut state = self.state.lock().expect("result cache lock poisoned");
+        state.generation += 1;
+        state.entries.clear();
+    }
+}
+
+fn key(method: &str, params: &Value) -> String {
+    format!("{method} {params}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn results_last_until_invalidated() {
+        let cache = ResultCache::new(2);
+        let method = "textDocument/definition";
+        let params = json!({"textDocument": {"uri": "file:///a.rs"}, "position": {"line": 1, "character": 2}});
+        let result = json!([{"uri": "file:///b.rs"}]);
+        assert!(cache.caches(method));
+        assert!(!cache.caches("textDocument/references"));
+        assert!(!ResultCache::new(0).caches(method));
+
+        let generation = cache.generation();
+        cache.insert(generation, method, &params, &json!([]));
+        assert_eq!(cache.get(method, &params), None);
+        cache.insert(generation, method, &params, &result);
+        assert_eq!(cache.get(method, &params), Some(result.clone()));
+        assert_eq!(cache.get("textDocument/hover", &params), None);
+
+        // A result requested before a change is not stored after it
+        let before_change = cache.generation();
+        cache.invalidate();
+        assert_eq!(cache.get(method, &params), None);
+        cache.insert(before_change, method, &params, &result);
+        assert_eq!(cache.get(method, &params), None);
+
+        assert!(invalidates("textDocument/didChange"));
+        assert!(invalidates("workspace/didChangeWatchedFiles"));
+        assert!(!invalidates("$/cancelRequest"));
+        assert!(!invalidates("initialized"));
+    }
+
+    #[test]
+    fn notebook_sync_invalidates() {
+        // Cells are synced through the notebook, so their cached results go stale
+        // on these alone
+        for method in [
+            "notebookDocument/didOpen",
+            "notebookDocument/didChange",
+            "notebookDocument/didSave",
+            "notebookDocument/didClose",
+        ] {
+            assert!(invalidates(method), "{method}");
+        }
+    }
+}
@@ -0,0 +1,375 @@
+//! LSP capability negotiation.
+//!
+//! This module builds the `ClientCapabilities` payload pathfinder advertises in
+//! `initialize`, and wraps the `ServerCapabilities` returned by the server so tools
+//! can check whether a feature is available before sending a request.
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+use crate::position::PositionEncoding;
+
+/// Applies the JSON merge patch (RFC 7396) `patch` to `target`: objects are merged
+/// key by key, a `null` value removes the key, and anything else replaces the value.
+/// This is how the `clientCapabilities` config section overrides the defaults.
+pub fn merge_patch(target: &mut Value, patch: &Value) {
+    let Value::Object(patch) = patch else {
+        *target = patch.clone();
+        return;
+    };
+    if !target.is_object() {
+        *target = json!({});
+    }
+    if let Value::Object(target) = target {
+        for (key, value) in patch {
+            if value.is_null() {
+                target.remove(key);
+            } else {
+                merge_patch(target.entry(key.clone()).or_insert(Value::Null), value);
+            }
+        }
+    }
+}
+
+/// Builds the client capabilities sent with the `initialize` request.
+///
+/// Only features pathfinder actually understands are advertised; servers use this
+/// to decide response shapes (e.g. `LocationLink` vs `Location`, hierarchical vs
+/// flat document symbols, markdown vs plaintext hover). `apply_edit` is whether
+/// `workspace/applyEdit` requests can succeed.
+pub fn client_capabilities(apply_edit: bool) -> Value {
+    json!({
+        "general": {
+            // In order of preference; columns are converted for whichever the server picks.
+            "positionEncodings": ["utf-16", "utf-8", "utf-32"],
+        },
+        "textDocument": {
+            "synchronization": {
+                "dynamicRegistration": false,
+                "willSave": false,
+                "willSaveWaitUntil": false,
+                "didSave": true,
+            },
+            "definition": { "dynamicRegistration": false, "linkSupport": true },
+            "declaration": { "dynamicRegistration": false, "linkSupport": true },
+            "typeDefinition": { "dynamicRegistration": false, "linkSupport": true },
+            "implementation": { "dynamicRegistration": false, "linkSupport": true },
+            "references": { "dynamicRegistration": false },
+            "hover": {
+                "dynamicRegistration": false,
+                "contentFormat": ["markdown", "plaintext"],
+            },
+            "documentSymbol": {
+                "dynamicRegistration": false,
+                "hierarchicalDocumentSymbolSupport": true,
+            },
+            "publishDiagnostics": { "relatedInformation": true },
+            "rename": { "dynamicRegistration": false, "prepareSupport": false },
+            "codeAction": {
+                "dynamicRegistration": false,
+                "codeActionLiteralSupport": {
+                    "codeActionKind": {
+                        "valueSet": [
+                            "",
+                            "quickfix",
+                            "refactor",
+                            "refactor.extract",
+                            "refactor.inline",
+                            "refactor.rewrite",
+                            "source",
+                            "source.organizeImports",
+                            "source.fixAll",
+                        ],
+                    },
+                },
+                "isPreferredSupport": true,
+                "disabledSupport": true,
+                "dataSupport": true,
+                // Edits are resolved lazily for the one action a caller picks
+                "resolveSupport": { "properties": ["edit"] },
+            },
+        },
+        "workspace": {
+            "workspaceFolders": true,
+            "configuration": true,
+            "applyEdit": apply_edit,
+            "workspaceEdit": {
+                "documentChanges": true,
+                "resourceOperations": ["create", "rename", "delete"],
+                "failureHandling": "abort",
+            },
+            "symbol": { "dynamicRegistration": false },
+            "didChangeWatchedFiles": {
+                "dynamicRegistration": true,
+                "relativePatternSupport": true,
+            },
+        },
+        "notebookDocument": {
+            "synchronization": {
+                "dynamicRegistration": false,
+                "executionSummarySupport": false,
+            },
+        },
+        "window": {
+            "workDoneProgress": true,
+            "showMessage": {
+                "messageActionItem": { "additionalPropertiesSupport": false },
+            },
+        },
+    })
+}
+
+/// How the server wants document changes delivered (`TextDocumentSyncKind`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TextDocumentSyncKind {
+    /// Documents are not synced; no `didChange` is sent
+    None,
+    /// `didChange` carries the full document text
+    Full,
+    /// `didChange` carries ranged edits
+    Incremental,
+}
+
+/// Capabilities advertised by the server in its `InitializeResult`.
+#[derive(Debug, Clone, Default)]
+pub struct ServerCapabilities {
+    raw: Value,
+}
+
+impl ServerCapabilities {
+    /// Extracts the `capabilities` object from an `InitializeResult`.
+    pub fn from_initialize_result(result: &Value) -> Self {
+        Self {
+            raw: result.get("capabilities").cloned().unwrap_or(Value::Null),
+        }
+    }
+
+    /// Returns the raw capabilities JSON as sent by the server.
+    pub fn raw(&self) -> &Value {
+        &self.raw
+    }
+
+    /// The `positionEncoding` the server chose; UTF-16 when omitted, per the spec.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.raw
+            .get("positionEncoding")
+            .and_then(|kind| kind.as_str())
+            .and_then(PositionEncoding::from_lsp)
+            .unwrap_or_default()
+    }
+
+    /// The change sync kind from `textDocumentSync`, given either as a bare kind or
+    /// as `TextDocumentSyncOptions.change`. Full sync when absent or unknown.
+    pub fn text_document_sync_kind(&self) -> TextDocumentSyncKind {
+        let sync = self.raw.get("textDocumentSync");
+        let kind = sync
+            .and_then(|sync| sync.as_u64())
+            .or_else(|| sync.and_then(|sync| sync.get("change")?.as_u64()));
+        match kind {
+            Some(0) => TextDocumentSyncKind::None,
+            Some(2) => TextDocumentSyncKind::Incremental,
+            _ => TextDocumentSyncKind::Full,
+        }
+    }
+
+    /// Whether the server wants `didOpen` and `didClose`: `TextDocumentSyncOptions`
+    /// say so with `openClose` (false when omitted, per the spec), while a bare kind,
+    /// or no `textDocumentSync` at all, is taken as yes, as clients commonly do.
+    pub fn open_close(&self) -> bool {
+        match self.raw.get("textDocumentSync") {
+            Some(options @ Value::Object(_)) => options
+                .get("openClose")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            _ => true,
+        }
+    }
+
+    /// Whether the server wants `didSave`, and if so whether with the saved text.
+    ///
+    /// Returns `Some(include_text)` when `textDocumentSync.save` is `true` or a
+    /// `SaveOptions` object, `None` otherwise (including the bare-kind form).
+    pub fn save_include_text(&self) -> Option<bool> {
+        match self.raw.get("textDocumentSync")?.get("save")? {
+            Value::Bool(true) => Some(false),
+            options @ Value::Object(_) => Some(
+                options
+                    .get("includeText")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Checks whether a provider capability (e.g. `definitionProvider`) is enabled.
+    ///
+    /// Providers may be advertised as `true` or as an options object; both count
+    /// as supported, while a missing key, `null`, or `false` do not.
+    pub fn supports(&self, provider: &str) -> bool {
+        self.raw
+            .get(provider)
+            .is_some_and(|value| !matches!(value, Value::Null | Value::Bool(false)))
+    }
+
+    /// Whether the server syncs notebooks as notebook documents
+    /// (`notebookDocumentSync`), rather than as their raw JSON.
+    pub fn syncs_notebooks(&self) -> bool {
+        self.supports("notebookDocumentSync")
+    }
+
+    /// Whether the server wants `notebookDocument/didSave`.
+    pub fn saves_notebooks(&self) -> bool {
+        self.raw
+            .pointer("/notebookDocumentSync/save")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Whether the server fills in the edits of code actions on `codeAction/resolve`.
+    pub fn resolves_code_actions(&self) -> bool {
+        self.raw
+            .pointer("/codeActionProvider/resolveProvider")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// Returns an error naming the LSP method if the provider is not supported.
+    pub fn ensure(&self, provider: &str, method: &str) -> Result<()> {
+        if self.supports(provider) {
+            Ok(())
+        } else {
+            Err(anyhow!("language server does not support {method}"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_overrides_merge_over_default_capabilities() {
+        let mut capabilities = client_capabilities(true);
+        merge_patch(
+            &mut capabilities,
+            &json!({
+                "experimental": {"serverStatusNotification": true},
+                "textDocument": {
+                    "hover": {"contentFormat": ["plaintext"]},
+                    "documentSymbol": null,
+                },
+            }),
+        );
+        assert_eq!(
+            capabilities["experimental"]["serverStatusNotification"],
+            true
+        );
+        assert_eq!(
+            capabilities["textDocument"]["hover"],
+            json!({"dynamicRegistration": false, "contentFormat": ["plaintext"]})
+        );
+        assert!(capabilities["textDocument"].get("documentSymbol").is_none());
+        assert_eq!(
+            capabilities["textDocument"]["references"]["dynamicRegistration"],
+            false
+        );
+    }
+
+    #[test]
+    fn supports_boolean_and_options_providers() {
+        let capabilities = ServerCapabilities::from_initialize_result(&json!({
+            "capabilities": {
+                "definitionProvider": true,
+                "hoverProvider": {"workDoneProgress": false},
+                "renameProvider": false,
+                "referencesProvider": null,
+            }
+        }));
+        assert!(capabilities.supports("definitionProvider"));
+        assert!(capabilities.supports("hoverProvider"));
+        assert!(!capabilities.supports("renameProvider"));
+        assert!(!capabilities.supports("referencesProvider"));
+        assert!(!capabilities.supports("documentSymbolProvider"));
+        assert!(!capabilities.syncs_notebooks());
+    }
+
+    #[test]
+    fn position_encoding_defaults_to_utf16() {
+        let negotiated = ServerCapabilities::from_initialize_result(&json!({
+            "capabilities": {"positionEncoding": "utf-8"}
+        }));
+        assert_eq!(negotiated.position_encoding(), PositionEncoding::Utf8);
+        assert_eq!(
+            ServerCapabilities::default().position_encoding(),
+            PositionEncoding::Utf16
+        );
+    }
+
+    #[test]
+    fn text_document_sync_kind_accepts_both_shapes() {
+        let caps = |sync: Value| {
+            ServerCapabilities::from_initialize_result(
+                &json!({ "capabilities": { "textDocumentSync": sync } }),
+            )
+        };
+        assert_eq!(
+            caps(json!(2)).text_document_sync_kind(),
+            TextDocumentSyncKind::Incremental
+        );
+        assert_eq!(
+            caps(json!({ "openClose": true, "change": 0 })).text_document_sync_kind(),
+            TextDocumentSyncKind::None
+        );
+        assert_eq!(
+            ServerCapabilities::default().text_document_sync_kind(),
+            TextDocumentSyncKind::Full
+        );
+    }
+
+    #[test]
+    fn open_close_defaults_by_shape() {
+        let caps = |sync: Value| {
+            ServerCapabilities::from_initialize_result(
+                &json!({ "capabilities": { "textDocumentSync": sync } }),
+            )
+        };
+        assert!(caps(json!(2)).open_close());
+        assert!(caps(json!({ "openClose": true, "change": 2 })).open_close());
+        assert!(!caps(json!({ "change": 2 })).open_close());
+        assert!(ServerCapabilities::default().open_close());
+    }
+
+    #[test]
+    fn save_options() {
+        let caps = |sync: Value| {
+            ServerCapabilities::from_initialize_result(
+                &json!({ "capabilities": { "textDocumentSync": sync } }),
+            )
+        };
+        assert_eq!(caps(json!(1)).save_include_text(), None);
+        assert_eq!(caps(json!({ "save": false })).save_include_text(), None);
+        assert_eq!(
+            caps(json!({ "save": true })).save_include_text(),
+            Some(false)
+        );
+        assert_eq!(
+            caps(json!({ "save": { "includeText": true } })).save_include_text(),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn ensure_names_the_missing_method() {
+        let capabilities = ServerCapabilities::default();
+        let err = capabilities
+            .ensure("definitionProvider", "textDocument/definition")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "language server does not support textDocument/definition"
+        );
+    }
+}
@@ -0,0 +1,187 @@
+//! Forwarding of log events to the MCP client.
+//!
+//! A [`ClientLog`] is installed as a `tracing` layer next to the stderr logger. It
+//! picks up pathfinder's own events, including the language server's
+//! `window/logMessage` and `window/showMessage` notifications (logged under
+//! [`LSP_LOG_TARGET`]), and broadcasts those at or above the client's level. The
+//! service sends them on as MCP `notifications/message`.
+//!
+//! Clients choose the level with `logging/setLevel`; until then warnings and errors
+//! are forwarded.
+
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use rmcp::model::LoggingLevel;
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// `tracing` target of messages the language server asked the client to log or show.
+pub const LSP_LOG_TARGET: &str = "pathfinder::lsp";
+
+/// Records buffered per subscriber before the oldest are dropped.
+const LOG_CAPACITY: usize = 256;
+
+/// One log event, ready to send as `notifications/message`.
+#[derive(Debug, Clone)]
+pub struct LogRecord {
+    pub level: LoggingLevel,
+    /// `lsp` for server messages, else the pathfinder module.
+    pub logger: String,
+    pub data: Value,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClientLog {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    /// Most verbose level forwarded, as a [`rank`].
+    threshold: AtomicU8,
+    records: broadcast::Sender<LogRecord>,
+}
+
+impl Default for ClientLog {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                threshold: AtomicU8::new(rank(&Level::WARN)),
+                records: broadcast::Sender::new(LOG_CAPACITY),
+            }),
+        }
+    }
+}
+
+impl ClientLog {
+    /// Applies a `logging/setLevel` request.
+    pub fn set_level(&self, level: LoggingLevel) {
+        let level = match level {
+            LoggingLevel::Debug => Level::DEBUG,
+            LoggingLevel::Info | LoggingLevel::Notice => Level::INFO,
+            LoggingLevel::Warning => Level::WARN,
+            _ => Level::ERROR,
+        };
+        self.inner.threshold.store(rank(&level), Ordering::Relaxed);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<LogRecord> {
+        self.inner.records.subscribe()
+    }
+
+    fn wants(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target().starts_with("pathfinder")
+            && rank(metadata.level()) >= self.inner.threshold.load(Ordering::Relaxed)
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ClientLog {
+    fn on_event(&self, event: &Event<'_>, _context: Context<'_, S>) {
+        let metadata = event.metadata();
+        // Without a connected client there is nobody to format the event for
+        if self.inner.records.receiver_count() == 0 || !self.wants(metadata) {
+            return;
+        }
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        let logger = if metadata.target() == LSP_LOG_TARGET {
+            "lsp".to_string()
+        } else {
+            metadata.target().to_string()
+        };
+        let _ = self.inner.records.send(LogRecord {
+            level: logging_level(metadata.level()),
+            logger,
+            data: Value::String(visitor.finish()),
+        });
+    }
+}
+
+/// Orders levels from least (trace) to most (error) severe.
+fn rank(level: &Level) -> u8 {
+    match *level {
+        Level::TRACE => 0,
+        Level::DEBUG => 1,
+        Level::INFO => 2,
+        Level::WARN => 3,
+        Level::ERROR => 4,
+    }
+}
+
+fn logging_level(level: &Level) -> LoggingLevel {
+    match *level {
+        Level::ERROR => LoggingLevel::Error,
+        Level::WARN => LoggingLevel::Warning,
+        Level::INFO => LoggingLevel::Info,
+        _ => LoggingLevel::Debug,
+    }
+}
+
+/// Renders an event as its message followed by `key=value` fields.
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl MessageVisitor {
+    fn finish(self) -> String {
+        if self.fields.is_empty() {
+            self.message
+        } else {
+            format!("{}{}", self.message, self.fields)
+        }
+    }
+}
+
+impl Visit for MessageVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            let _ = write!(self.fields, " {}={value}", field.name());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{value:?}");
+        } else {
+            let _ = write!(self.fields, " {}={value:?}", field.name());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn forwards_pathfinder_events_at_the_client_level() {
+        let log = ClientLog::default();
+        let mut records = log.subscribe();
+        let subscriber = tracing_subscriber::registry().with(log.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("not yet");
+            tracing::warn!(uri = "file:///a.rs", "sync failed");
+            tracing::error!(target: "rmcp::service", "other crate");
+            log.set_level(LoggingLevel::Debug);
+            tracing::debug!(target: LSP_LOG_TARGET, "LSP: indexing");
+        });
+
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.level, LoggingLevel::Warning);
+        assert_eq!(record.logger, "pathfinder::client_log::tests");
+        assert_eq!(record.data, "sync failed uri=file:///a.rs");
+        let record = records.try_recv().unwrap();
+        assert_eq!(record.logger, "lsp");
+        assert_eq!(record.data, "LSP: indexing");
+        assert!(records.try_recv().is_err());
+    }
+}
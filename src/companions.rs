@@ -0,0 +1,456 @@
+//! Companion language servers, queried alongside the main one.
+//!
+//! Some files are understood by more than one server: a TypeScript file by
+//! typescript-language-server and by a framework's own server, a Python file by a
+//! type checker and by a linter that resolves names. Each entry of the config's
+//! `companions` list starts another supervised server in the same workspace, and
+//! `definition` and `references` ask every companion configured for the document
+//! as well as the main server. Their targets are merged, deduplicated, and labeled
+//! with the server that found them.
+//!
+//! A reloaded config starts companions added to the list, shuts down those removed
+//! from it, and reconfigures the rest, matched by name.
+//!
+//! Companions only answer those two tools; everything else, including overlays and
+//! `restart_server`, concerns the main server. A companion that fails or lacks the
+//! capability is left out of the result rather than failing the call.
+
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use anyhow::{Context, Result};
+use tokio::task::JoinSet;
+
+use crate::config::ServerConfig;
+use crate::lsp_bridge::LspBridge;
+use crate::supervisor::LspSupervisor;
+use crate::tools::definition::{
+    DefinitionRequest, DefinitionResponse, DefinitionTool, TargetOptions,
+};
+use crate::tools::locations::DefinitionTarget;
+use crate::tools::references::{FoundReferences, ReferencesRequest, ReferencesTool};
+
+/// The companion servers of a service; empty if none are configured.
+#[derive(Default)]
+pub struct Companions {
+    /// Replaced as a whole when a reloaded config changes the list
+    servers: RwLock<Vec<Arc<LspSupervisor>>>,
+}
+
+impl Companions {
+    /// Starts a server for each of `configs` in `workspace`, the main server's root.
+    pub async fn start(configs: &[ServerConfig], workspace: &Path) -> Result<Self> {
+        let mut servers = Vec::with_capacity(configs.len());
+        for config in configs {
+            servers.push(start(config, workspace).await?);
+        }
+        Ok(Self {
+            servers: RwLock::new(servers),
+        })
+    }
+
+    /// Switches to `configs`, the `companions` list of a reloaded config.
+    ///
+    /// Companions are matched by name (`ServerConfig::label`): a new name starts a
+    /// server, a name no longer listed shuts its server down, and a kept one is
+    /// reconfigured like the main server (see [`LspSupervisor::reconfigure`]), keeping
+    /// its startup settings with a warning. If a new companion fails to start, those
+    /// started before it are shut down and the running ones are left as they were.
+    pub async fn reconfigure(&self, configs: &[ServerConfig], workspace: &Path) -> Result<()> {
+        let mut running = self.servers();
+        let mut started = Vec::new();
+        for config in configs {
+            if running
+                .iter()
+                .any(|server| server.config().label() == config.label())
+            {
+                continue;
+            }
+            match start(config, workspace).await {
+                Ok(server) => started.push(server),
+                Err(err) => {
+                    for server in &started {
+                        server.shutdown().await;
+                    }
+                    return Err(err);
+                }
+            }
+        }
+
+        let mut servers = Vec::with_capacity(configs.len());
+        for config in configs {
+            let label = config.label();
+            let kept = running
+                .iter()
+                .position(|server| server.config().label() == label);
+            let Some(kept) = kept else {
+                let index = started
+                    .iter()
+                    .position(|server| server.config().label() == label)
+                    .expect("new companions were started above");
+                tracing::info!(companion = %label, "Started companion added to the config");
+                servers.push(started.swap_remove(index));
+                continue;
+            };
+            let server = running.swap_remove(kept);
+            let mut config = config.clone();
+            for setting in config.keep_startup_settings(&server.config()) {
+                tracing::warn!(
+                    companion = %label,
+                    setting,
+                    "Config change takes effect when pathfinder restarts"
+                );
+            }
+            if let Err(err) = server.reconfigure(config).await {
+                tracing::warn!(companion = %label, "Failed to reconfigure companion: {err:#}");
+            }
+            servers.push(server);
+        }
+        for server in running {
+            tracing::info!(
+                companion = %server.config().label(),
+                "Shutting down companion removed from the config"
+            );
+            server.shutdown().await;
+        }
+        *self.servers.write().expect("companions lock poisoned") = servers;
+        Ok(())
+    }
+
+    /// The companions, in config order.
+    pub fn servers(&self) -> Vec<Arc<LspSupervisor>> {
+        self.servers
+            .read()
+            .expect("companions lock poisoned")
+            .clone()
+    }
+
+    /// The companion with the label `name`.
+    pub fn get(&self, name: &str) -> Option<Arc<LspSupervisor>> {
+        self.servers()
+            .into_iter()
+            .find(|server| server.config().label() == name)
+    }
+
+    /// Whether any companion is configured for the document at `uri`.
+    pub fn serves(&self, uri: &str) -> bool {
+        self.servers().iter().any(|server| server.serves(uri))
+    }
+
+    /// Definition targets from each companion configured for the request's document;
+    /// none if the request names a `server`.
+    pub async fn definitions(&self, request: &DefinitionRequest) -> Vec<DefinitionTarget> {
+        if request.server.is_some() {
+            return Vec::new();
+        }
+        let (uri, request) = (request.uri.clone(), request.clone());
+        self.fan_out(&uri, "definitionProvider", move |lsp| {
+            let request = request.clone();
+            async move {
+                let response = DefinitionTool::new()
+                    .execute(&lsp, request, Default::default())
+                    .await?;
+                Ok(response.targets)
+            }
+        })
+        .await
+    }
+
+    /// References from each companion configured for the request's document; none if
+    /// the request names a `server`.
+    pub async fn references(&self, request: &ReferencesRequest) -> Vec<DefinitionTarget> {
+        if request.server.is_some() {
+            return Vec::new();
+        }
+        let (uri, request) = (request.uri.clone(), request.clone());
+        self.fan_out(&uri, "referencesProvider", move |lsp| {
+            let request = request.clone();
+            async move {
+                let found = ReferencesTool::new()
+                    .execute(&lsp, request, Default::default())
+                    .await?;
+                Ok(found.references)
+            }
+        })
+        .await
+    }
+
+    /// Runs `query` on every companion configured for `uri` that has `capability`,
+    /// concurrently, and collects the targets each finds labeled with its name.
+    async fn fan_out<F, Fut>(&self, uri: &str, capability: &str, query: F) -> Vec<DefinitionTarget>
+    where
+        F: Fn(Arc<LspBridge>) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = Result<Vec<DefinitionTarget>>> + Send,
+    {
+        let mut queries = JoinSet::new();
+        for server in self
+            .servers()
+            .into_iter()
+            .filter(|server| server.serves(uri))
+        {
+            let uri = uri.to_string();
+            let capability = capability.to_string();
+            let query = query.clone();
+            queries.spawn(async move {
+                let lsp = server.ready_bridge().await?;
+                if !lsp.server_capabilities().supports(&capability) {
+                    return Ok(Vec::new());
+                }
+                server.documents().ensure_open(&lsp, &uri).await?;
+                let mut targets = query(lsp).await?;
+                label(&server, &mut targets);
+                anyhow::Ok(targets)
+            });
+        }
+
+        let mut found = Vec::new();
+        while let Some(joined) = queries.join_next().await {
+            match joined {
+                Ok(Ok(targets)) => found.extend(targets),
+                Ok(Err(err)) => tracing::warn!("Companion query failed: {err:#}"),
+                Err(err) => tracing::warn!(?err, "Companion query panicked"),
+            }
+        }
+        found
+    }
+
+    /// Stops every companion.
+    pub async fn shutdown(&self) {
+        for server in self.servers() {
+            server.shutdown().await;
+        }
+    }
+}
+
+/// Starts the companion for `config` in `workspace`.
+async fn start(config: &ServerConfig, workspace: &Path) -> Result<Arc<LspSupervisor>> {
+    let label = config.label();
+    LspSupervisor::start(config.clone(), workspace.to_path_buf())
+        .await
+        .with_context(|| format!("failed to start companion '{label}'"))
+}
+
+/// Asks `server` alone for the request's definition, with its targets labeled;
+/// unlike the fan-out, its failures are returned.
+pub async fn definition_from(
+    server: &LspSupervisor,
+    request: DefinitionRequest,
+) -> Result<DefinitionResponse> {
+    let lsp = server.ready_bridge().await?;
+    server.documents().ensure_open(&lsp, &request.uri).await?;
+    let mut response = DefinitionTool::new()
+        .execute(&lsp, request, Default::default())
+        .await?;
+    label(server, &mut response.targets);
+    Ok(response)
+}
+
+/// Asks `server` alone for the request's references, labeled like
+/// [`definition_from`]'s targets.
+pub async fn references_from(
+    server: &LspSupervisor,
+    request: ReferencesRequest,
+) -> Result<FoundReferences> {
+    let lsp = server.ready_bridge().await?;
+    server.documents().ensure_open(&lsp, &request.uri).await?;
+    let mut found = ReferencesTool::new()
+        .execute(&lsp, request, Default::default())
+        .await?;
+    label(server, &mut found.references);
+    Ok(found)
+}
+
+fn label(server: &LspSupervisor, targets: &mut [DefinitionTarget]) {
+    let label = server.config().label();
+    for target in targets {
+        target.server = Some(label.clone());
+    }
+}
+
+/// Adds the companions' targets to those `server` found, labeling them with its
+/// name, then deduplicates, sorts, filters, and caps the lot with `options`. A
+/// target both found keeps the main server's label. Returns true if the cap cut any.
+pub fn merge(
+    server: &ServerConfig,
+    targets: &mut Vec<DefinitionTarget>,
+    companions: Vec<DefinitionTarget>,
+    options: &TargetOptions,
+    workspace: &Path,
+) -> bool {
+    let label = server.label();
+    for target in targets.iter_mut() {
+        target.server = Some(label.clone());
+    }
+    targets.extend(companions);
+    options.apply(targets, workspace)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rmcp::model::{CallToolRequestParam, CallToolResult};
+    use rmcp::{Peer, RoleClient, ServiceExt};
+    use serde_json::{Value, json};
+
+    use crate::config::Config;
+    use crate::service::PathfinderService;
+    use crate::tools::locations::TextRange;
+
+    fn target(uri: &url::Url, line: u32, server: Option<&str>) -> DefinitionTarget {
+        DefinitionTarget {
+            server: server.map(str::to_string),
+            ..DefinitionTarget::new(
+                uri.to_string(),
+                TextRange {
+                    start_line: line,
+                    start_character: 0,
+                    end_line: line,
+                    end_character: 3,
+                },
+            )
+        }
+    }
+
+    async fn call(client: &Peer<RoleClient>, tool: &str, arguments: Value) -> CallToolResult {
+        client
+            .call_tool(CallToolRequestParam {
+                name: tool.to_string().into(),
+                arguments: arguments.as_object().cloned(),
+            })
+            .await
+            .unwrap()
+    }
+
+    /// The `server` of each target in a definition or references result.
+    fn servers(result: &CallToolResult, key: &str) -> Vec<String> {
+        let targets = result.structured_content.as_ref().unwrap()[key]
+            .as_array()
+            .unwrap();
+        targets
+            .iter()
+            .map(|target| target["server"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn calls_reach_every_server_or_the_named_one() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let config = Config::from_json_str(
+            r#"{
+                "server": {"extensions": ["rs"], "connect": "mock"},
+                "companions": [{"extensions": ["rs"], "connect": "mock", "name": "second"}]
+            }"#,
+        )
+        .unwrap();
+        let service = PathfinderService::new(config, dir.path().into())
+            .await
+            .unwrap();
+        let (client_stream, server_stream) = tokio::io::duplex(64 * 1024);
+        let (server, client) = tokio::join!(
+            service.clone().serve(tokio::io::split(server_stream)),
+            ().serve(tokio::io::split(client_stream)),
+        );
+        let (server, client) = (server.unwrap(), client.unwrap());
+
+        let listed = call(&client, "list_servers", json!({})).await;
+        let names: Vec<_> = listed.structured_content.unwrap()["servers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|server| server["name"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["mock", "second"]);
+
+        // Both servers find the same location; the main server's label is kept
+        let position = json!({"uri": "main.rs", "line": 0, "character": 3});
+        let merged = call(&client, "definition", position.clone()).await;
+        assert_eq!(servers(&merged, "targets"), ["mock"]);
+
+        let mut named = position.clone();
+        named["server"] = json!("second");
+        let references = call(&client, "references", named.clone()).await;
+        assert_eq!(servers(&references, "references"), ["second", "second"]);
+
+        named["server"] = json!("nope");
+        let unknown = call(&client, "definition", named).await;
+        assert_eq!(unknown.is_error, Some(true));
+
+        let _ = client.cancel().await;
+        let _ = server.cancel().await;
+        service.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn reload_starts_and_stops_companions() {
+        let dir = tempfile::tempdir().unwrap();
+        let companion = |name: &str| {
+            serde_json::from_value::<ServerConfig>(json!({
+                "extensions": ["rs"],
+                "connect": "mock",
+                "name": name,
+            }))
+            .unwrap()
+        };
+        let companions = Companions::start(&[companion("first")], dir.path())
+            .await
+            .unwrap();
+        let first = companions.get("first").unwrap();
+
+        companions
+            .reconfigure(&[companion("first"), companion("second")], dir.path())
+            .await
+            .unwrap();
+        let names: Vec<_> = companions
+            .servers()
+            .iter()
+            .map(|server| server.config().label())
+            .collect();
+        assert_eq!(names, ["first", "second"]);
+        assert!(Arc::ptr_eq(&companions.get("first").unwrap(), &first));
+
+        companions
+            .reconfigure(&[companion("second")], dir.path())
+            .await
+            .unwrap();
+        assert!(companions.get("first").is_none());
+        assert!(first.bridge().has_exited());
+        companions.shutdown().await;
+    }
+
+    #[test]
+    fn merged_targets_are_labeled_and_deduplicated() {
+        let workspace = std::env::temp_dir().join("project");
+        let uri = url::Url::from_file_path(workspace.join("src/app.ts")).unwrap();
+        let server: ServerConfig = serde_json::from_value(serde_json::json!({
+            "command": ["/usr/bin/typescript-language-server", "--stdio"],
+            "extensions": ["ts"],
+        }))
+        .unwrap();
+
+        let mut targets = vec![target(&uri, 4, None)];
+        let companions = vec![
+            target(&uri, 4, Some("eslint")),
+            target(&uri, 1, Some("eslint")),
+        ];
+        let truncated = merge(
+            &server,
+            &mut targets,
+            companions,
+            &TargetOptions::default(),
+            &workspace,
+        );
+
+        assert!(!truncated);
+        let found: Vec<_> = targets
+            .iter()
+            .map(|target| (target.range.start_line, target.server.as_deref()))
+            .collect();
+        assert_eq!(
+            found,
+            [(1, Some("eslint")), (4, Some("typescript-language-server"))]
+        );
+    }
+}
@@ -1,24 +1,900 @@
 //! Configuration management for LSP server specification.
 //!
-//! This module handles parsing and validating configuration from both JSON files
+//! This module handles parsing and validating configuration from JSON or TOML files
 //! and command-line arguments.
 
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::{Context, Result, anyhow};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
-#[derive(Debug, Deserialize, Clone)]
+use crate::position::PositionEncoding;
+
+/// Config files [`Config::discover`] looks for, in order of preference.
+pub const DISCOVERED_FILE_NAMES: &[&str] = &[
+    ".pathfinder.toml",
+    ".pathfinder.json",
+    "pathfinder.toml",
+    "pathfinder.json",
+];
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Config {
     pub server: ServerConfig,
+    /// More servers for some of `server`'s extensions, e.g. a linter's language
+    /// server next to the compiler's; see [`crate::companions`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub companions: Vec<ServerConfig>,
+    /// Which MCP tools are offered.
+    #[serde(default, skip_serializing_if = "ToolsConfig::is_default")]
+    pub tools: ToolsConfig,
+    /// How tool results report paths and positions.
+    #[serde(default, skip_serializing_if = "OutputConfig::is_default")]
+    pub output: OutputConfig,
+    /// Where documents passed to tools may be.
+    #[serde(default, skip_serializing_if = "SandboxConfig::is_default")]
+    pub sandbox: SandboxConfig,
+    /// Pushing the server's diagnostics to MCP clients.
+    #[serde(default, skip_serializing_if = "DiagnosticsConfig::is_default")]
+    pub diagnostics: DiagnosticsConfig,
+    /// Never change the workspace: see [`Config::restrict_to_read_only`].
+    #[serde(rename = "readOnly", default, skip_serializing_if = "is_false")]
+    pub read_only: bool,
+}
+
+/// Restricts document arguments to the workspace and other allowed directories.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct SandboxConfig {
+    /// Reject documents outside the allowed roots (turned off by `--no-sandbox`).
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Directories besides the workspace root that documents may be in, e.g. a
+    /// vendored dependency tree; relative paths resolve against the workspace root.
+    #[serde(
+        rename = "allowedRoots",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub allowed_roots: Vec<PathBuf>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            allowed_roots: Vec::new(),
+        }
+    }
+}
+
+impl SandboxConfig {
+    fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Sends the diagnostics the server publishes to MCP clients as they change; see
+/// [`crate::diagnostics`].
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct DiagnosticsConfig {
+    /// Send changed diagnostics as `notifications/message` with the logger
+    /// `diagnostics`; off by default.
+    #[serde(default)]
+    pub push: bool,
+    /// Quiet period after the last update before sending, in milliseconds.
+    #[serde(rename = "debounceMs", default = "default_diagnostics_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self {
+            push: false,
+            debounce_ms: default_diagnostics_debounce_ms(),
+        }
+    }
+}
+
+impl DiagnosticsConfig {
+    fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+}
+
+/// Default formatting of tool results; each call can override it.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputConfig {
+    /// How documents are identified in results.
+    #[serde(default)]
+    pub paths: PathStyle,
+    /// Count lines and columns from 1, in tool inputs as well as results.
+    #[serde(rename = "oneBased", default)]
+    pub one_based: bool,
+    /// What tool results carry as their text content.
+    #[serde(default)]
+    pub format: ResultFormat,
+}
+
+impl OutputConfig {
+    fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// How tool results identify documents.
+#[derive(
+    Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum PathStyle {
+    /// file:// URIs
+    #[default]
+    Uri,
+    /// Paths relative to the workspace root; documents outside it keep their URI
+    Relative,
+}
+
+/// The text content of tool results.
+#[derive(
+    Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, schemars::JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum ResultFormat {
+    /// The structured result as JSON
+    #[default]
+    Json,
+    /// A compact summary such as `src/main.rs:6:4`
+    Text,
+}
+
+/// Filters the MCP tools the service registers.
+///
+/// A tool is offered if `enabled` is unset or lists it, and `disabled` does not.
+/// `lsp_request` is additionally only offered with `lspRequest = true`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct ToolsConfig {
+    /// Only these tools are offered; all tools when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<Vec<String>>,
+    /// Tools that are never offered.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub disabled: Vec<String>,
+    /// Offer `lsp_request`, which forwards any request to the server as is.
+    #[serde(rename = "lspRequest", default, skip_serializing_if = "is_false")]
+    pub lsp_request: bool,
+    /// Largest tool result, in serialized bytes; longer lists in larger results are
+    /// cut short. 0 means no limit.
+    #[serde(rename = "maxResponseBytes", default = "default_max_response_bytes")]
+    pub max_response_bytes: usize,
+    /// Per-tool overrides of `maxResponseBytes`, keyed by tool name.
+    #[serde(
+        rename = "responseLimits",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub response_limits: BTreeMap<String, usize>,
+    /// Answer `definition` and the `symbol://` resource from built-in declaration
+    /// patterns when the server has no answer or none is configured for the file.
+    #[serde(default = "default_true")]
+    pub fallback: bool,
+    /// Tools that send one LSP request built from a params template.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub custom: Vec<CustomToolConfig>,
+    /// Hooks around every tool call, outermost first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub middleware: Vec<MiddlewareConfig>,
+}
+
+/// A tool declared in the config; see [`crate::plugins::TemplateTool`].
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct CustomToolConfig {
+    pub name: String,
+    pub description: String,
+    /// LSP method the tool calls.
+    pub method: String,
+    /// Request params, with `${argument}` placeholders for the tool's arguments.
+    #[serde(default)]
+    pub params: Value,
+    /// The request changes nothing; otherwise the tool is annotated as destructive.
+    #[serde(rename = "readOnly", default = "default_true")]
+    pub read_only: bool,
+}
+
+/// Built-in middleware, by `name`; see [`crate::middleware`].
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "name", rename_all = "snake_case")]
+pub enum MiddlewareConfig {
+    /// Log each call's arguments and outcome.
+    Audit,
+    /// Refuse documents matching `globs` (relative to the workspace) and drop them from
+    /// results.
+    Redact { globs: Vec<String> },
+    /// Map paths under `client`, the workspace as the client sees it, to `server`,
+    /// where pathfinder sees it, and back in results.
+    RewritePaths { client: String, server: String },
+}
+
+impl Default for ToolsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: None,
+            disabled: Vec::new(),
+            lsp_request: false,
+            max_response_bytes: default_max_response_bytes(),
+            response_limits: BTreeMap::new(),
+            fallback: true,
+            custom: Vec::new(),
+            middleware: Vec::new(),
+        }
+    }
+}
+
+impl ToolsConfig {
+    pub fn is_enabled(&self, tool: &str) -> bool {
+        if tool == "lsp_request" && !self.lsp_request {
+            return false;
+        }
+        let listed = |names: &[String]| names.iter().any(|name| name == tool);
+        self.enabled.as_deref().is_none_or(listed) && !listed(&self.disabled)
+    }
+
+    /// Every tool name the config mentions.
+    pub fn named_tools(&self) -> impl Iterator<Item = &str> {
+        self.enabled
+            .iter()
+            .flatten()
+            .chain(&self.disabled)
+            .chain(self.response_limits.keys())
+            .map(String::as_str)
+    }
+
+    /// Largest result of `tool`, in serialized bytes; 0 means no limit.
+    pub fn response_limit(&self, tool: &str) -> usize {
+        self.response_limits
+            .get(tool)
+            .copied()
+            .unwrap_or(self.max_response_bytes)
+    }
+
+    fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ServerConfig {
     pub extensions: Vec<String>,
+    /// Command that starts the server; empty when `connect` is set.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub command: Vec<String>,
-    #[serde(rename = "rootDir")]
+    /// Connect to a server that is already listening instead of spawning `command`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connect: Option<ConnectTarget>,
+    /// Where `command` runs.
+    #[serde(default, skip_serializing_if = "Runtime::is_local")]
+    pub runtime: Runtime,
+    /// Container settings for `runtime = "docker"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub docker: Option<DockerConfig>,
+    /// Remote host settings for `runtime = "ssh"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ssh: Option<SshConfig>,
+    /// Append every message exchanged with the server to this file (JSON lines);
+    /// relative paths are resolved against the workspace root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub record: Option<PathBuf>,
+    /// Workspace root, relative to the config file's directory (default `.`); ignored
+    /// for companions, which run in `server`'s.
+    #[serde(rename = "rootDir", default = "default_root_dir")]
     pub root_dir: PathBuf,
+    /// Name for the server in results and in tools' `server` argument; defaults to
+    /// the program `command` runs, else the `connect` target.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Settings served to `workspace/configuration` requests, keyed by section.
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub settings: Value,
+    /// Merged over the `ClientCapabilities` sent in `initialize`, as a JSON merge
+    /// patch: objects merge key by key, `null` removes a key, anything else replaces it.
+    #[serde(
+        rename = "clientCapabilities",
+        default,
+        skip_serializing_if = "Value::is_null"
+    )]
+    pub client_capabilities: Value,
+    /// Seconds tools wait for server indexing to finish before accepting empty results.
+    #[serde(rename = "indexingWaitSecs", default = "default_indexing_wait_secs")]
+    pub indexing_wait_secs: u64,
+    /// Most hover, definition, and documentSymbol results kept until a document
+    /// changes; 0 turns the cache off.
+    #[serde(rename = "resultCacheSize", default = "default_result_cache_size")]
+    pub result_cache_size: usize,
+    /// Largest message accepted from the server; a larger Content-Length closes the
+    /// connection instead of being read into memory.
+    #[serde(rename = "maxMessageBytes", default = "default_max_message_bytes")]
+    pub max_message_bytes: usize,
+    /// How to answer `window/showMessageRequest`: `dismiss`, `first`, or an action title.
+    #[serde(rename = "messageAction", default = "default_message_action")]
+    pub message_action: String,
+    /// What to do with `workspace/applyEdit` requests from the server.
+    #[serde(rename = "applyEdits", default)]
+    pub apply_edits: ApplyEditPolicy,
+    /// LSP request timeouts.
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+    /// Unit of the `character` columns tool callers send and receive
+    /// (`utf-8`, `utf-16`, or `utf-32`); converted to the server's encoding.
+    #[serde(rename = "columnEncoding", default)]
+    pub column_encoding: PositionEncoding,
+    /// `languageId`s sent in `didOpen` by file extension, overriding the built-in
+    /// table (e.g. `vue = "vue"`).
+    #[serde(
+        rename = "languageIds",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub language_ids: BTreeMap<String, String>,
+    /// Environment variables set for the server process.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Environment variables removed from the inherited environment.
+    #[serde(rename = "envRemove", default, skip_serializing_if = "Vec::is_empty")]
+    pub env_remove: Vec<String>,
+    /// Working directory for the server process; relative paths are resolved against
+    /// the workspace root. Defaults to the workspace root.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+    /// How crashed servers are restarted.
+    #[serde(default)]
+    pub restart: RestartPolicy,
+    /// Limits on documents kept open on the server.
+    #[serde(default)]
+    pub documents: DocumentLimits,
+    /// Workspace file watching.
+    #[serde(default)]
+    pub watch: WatchConfig,
+    /// Documents to open right after startup.
+    #[serde(default)]
+    pub warmup: WarmupConfig,
+    /// Concurrency limit and prioritization of requests to the server.
+    #[serde(default)]
+    pub queue: QueueConfig,
+    /// Pathfinder's own symbol index, searched by `find_symbol`.
+    #[serde(default)]
+    pub index: IndexConfig,
+}
+
+/// Where to reach a language server that accepts connections itself, e.g.
+/// `{"tcp": "127.0.0.1:9257"}` or `{"unix": "/tmp/lsp.sock"}`, or a recording to
+/// replay in its place (`{"replay": "session.jsonl"}`), or `"mock"` for the built-in
+/// mock server.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ConnectTarget {
+    /// `host:port` of a TCP listener
+    Tcp(String),
+    /// Unix domain socket; relative paths are resolved against the workspace root
+    Unix(PathBuf),
+    /// A file written with `record`, answered from without running a server;
+    /// relative paths are resolved against the workspace root
+    Replay(PathBuf),
+    /// The in-process [`crate::testing::MockServer`] with its default answers
+    Mock,
+}
+
+impl fmt::Display for ConnectTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(address) => write!(f, "tcp:{address}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+            Self::Replay(path) => write!(f, "replay:{}", path.display()),
+            Self::Mock => write!(f, "mock"),
+        }
+    }
+}
+
+/// Where the server process runs.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Runtime {
+    /// Spawn `command` directly
+    #[default]
+    Local,
+    /// Run `command` in a container with `docker run -i`
+    Docker,
+    /// Run `command` on another machine with `ssh -T`
+    Ssh,
+}
+
+impl Runtime {
+    fn is_local(&self) -> bool {
+        *self == Self::Local
+    }
+}
+
+/// The container a `docker` runtime server runs in.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct DockerConfig {
+    pub image: String,
+    /// Where the workspace root is mounted inside the container; file URIs are
+    /// translated between the two.
+    #[serde(rename = "mountPath", default = "default_docker_mount_path")]
+    pub mount_path: String,
+    /// Extra `docker run` arguments, placed before the image.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+}
+
+/// The host an `ssh` runtime server runs on.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct SshConfig {
+    pub host: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub port: Option<u16>,
+    /// Extra `ssh` arguments, placed before the host.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    /// Local directory -> the directory it is on the remote host. Relative local
+    /// paths resolve against the workspace root; unmapped paths are sent as they are.
+    #[serde(
+        rename = "pathMap",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub path_map: BTreeMap<PathBuf, String>,
+}
+
+/// Request timeouts in milliseconds.
+///
+/// The most specific setting wins: a tool's entry, then the LSP method's entry, then,
+/// when `adaptive`, one derived from the method's observed latency, then `default`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct TimeoutConfig {
+    #[serde(default = "default_timeout_ms")]
+    pub default: u64,
+    /// Per LSP method, e.g. `"workspace/symbol" = 60000`. Pins the method's timeout.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub methods: HashMap<String, u64>,
+    /// Per MCP tool, applied to every LSP request the tool issues.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tools: HashMap<String, u64>,
+    /// Time methods out at `factor` times their recent p99 latency, within
+    /// `floorMs..=ceilingMs`, once enough of their requests have been seen.
+    #[serde(default = "default_true")]
+    pub adaptive: bool,
+    #[serde(rename = "floorMs", default = "default_adaptive_floor_ms")]
+    pub floor_ms: u64,
+    #[serde(rename = "ceilingMs", default = "default_adaptive_ceiling_ms")]
+    pub ceiling_ms: u64,
+    #[serde(default = "default_adaptive_factor")]
+    pub factor: u32,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            default: default_timeout_ms(),
+            methods: HashMap::new(),
+            tools: HashMap::new(),
+            adaptive: true,
+            floor_ms: default_adaptive_floor_ms(),
+            ceiling_ms: default_adaptive_ceiling_ms(),
+            factor: default_adaptive_factor(),
+        }
+    }
+}
+
+impl TimeoutConfig {
+    /// Timeout for an LSP request issued outside of any tool, ignoring latency.
+    pub fn for_method(&self, method: &str) -> Duration {
+        self.resolve(None, method, None)
+    }
+
+    /// Timeout for an LSP request issued by `tool`, ignoring latency.
+    pub fn for_tool(&self, tool: &str, method: &str) -> Duration {
+        self.resolve(Some(tool), method, None)
+    }
+
+    /// Timeout for a request for `method`, issued by `tool` if any, given the p99
+    /// of the method's recent latencies if enough are known.
+    pub fn resolve(&self, tool: Option<&str>, method: &str, p99: Option<Duration>) -> Duration {
+        if let Some(ms) = tool.and_then(|tool| self.tools.get(tool)) {
+            return Duration::from_millis(*ms);
+        }
+        if let Some(ms) = self.methods.get(method) {
+            return Duration::from_millis(*ms);
+        }
+        match p99 {
+            Some(p99) if self.adaptive => {
+                let ms = u64::try_from(p99.as_millis())
+                    .unwrap_or(u64::MAX)
+                    .saturating_mul(u64::from(self.factor));
+                Duration::from_millis(ms.max(self.floor_ms).min(self.ceiling_ms))
+            }
+            _ => Duration::from_millis(self.default),
+        }
+    }
+}
+
+/// Restart behavior after the server process exits unexpectedly.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Restarts allowed within `windowSecs`; 0 disables restarting.
+    #[serde(rename = "maxRestarts", default = "default_max_restarts")]
+    pub max_restarts: u32,
+    /// Sliding window for `maxRestarts`, in seconds; 0 counts restarts forever.
+    #[serde(rename = "windowSecs", default = "default_restart_window_secs")]
+    pub window_secs: u64,
+    #[serde(default)]
+    pub backoff: BackoffStrategy,
+    /// Delay before the first restart attempt, in milliseconds.
+    #[serde(rename = "initialDelayMs", default = "default_initial_delay_ms")]
+    pub initial_delay_ms: u64,
+    /// Upper bound for exponential backoff, in milliseconds.
+    #[serde(rename = "maxDelayMs", default = "default_max_delay_ms")]
+    pub max_delay_ms: u64,
+    /// What tool calls do while the server is restarting.
+    #[serde(rename = "whileRestarting", default)]
+    pub while_restarting: WhileRestarting,
+    /// How long queued tool calls wait for the restart, in seconds.
+    #[serde(rename = "queueTimeoutSecs", default = "default_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BackoffStrategy {
+    /// Double the delay after every failed attempt, up to `maxDelayMs`
+    #[default]
+    Exponential,
+    /// Always wait `initialDelayMs`
+    Fixed,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum WhileRestarting {
+    /// Fail immediately with an error saying the server is restarting
+    #[default]
+    Fail,
+    /// Wait for the restarted server, up to `queueTimeoutSecs`
+    Queue,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            max_restarts: default_max_restarts(),
+            window_secs: default_restart_window_secs(),
+            backoff: BackoffStrategy::default(),
+            initial_delay_ms: default_initial_delay_ms(),
+            max_delay_ms: default_max_delay_ms(),
+            while_restarting: WhileRestarting::default(),
+            queue_timeout_secs: default_queue_timeout_secs(),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Delay before restart attempt `attempt` (0-based) of one recovery.
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let initial = Duration::from_millis(self.initial_delay_ms);
+        match self.backoff {
+            BackoffStrategy::Fixed => initial,
+            BackoffStrategy::Exponential => initial
+                .saturating_mul(2u32.saturating_pow(attempt))
+                .min(Duration::from_millis(self.max_delay_ms)),
+        }
+    }
+}
+
+/// How many documents stay open on the server, and for how long.
+///
+/// Documents past either limit are closed with `didClose` and reopened on next use.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct DocumentLimits {
+    /// Most documents open at once, least recently used closed first; 0 means no cap.
+    #[serde(rename = "maxOpen", default = "default_max_open_documents")]
+    pub max_open: usize,
+    /// Close documents unused for this many seconds; 0 keeps them open.
+    #[serde(rename = "idleTimeoutSecs", default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// Largest file, in bytes, that is sent to the server; 0 means no limit.
+    #[serde(rename = "maxFileBytes", default = "default_max_file_bytes")]
+    pub max_file_bytes: u64,
+}
+
+impl Default for DocumentLimits {
+    fn default() -> Self {
+        Self {
+            max_open: default_max_open_documents(),
+            idle_timeout_secs: default_idle_timeout_secs(),
+            max_file_bytes: default_max_file_bytes(),
+        }
+    }
+}
+
+impl DocumentLimits {
+    /// No cap, no idle timeout, and no file size limit.
+    pub fn unlimited() -> Self {
+        Self {
+            max_open: 0,
+            idle_timeout_secs: 0,
+            max_file_bytes: 0,
+        }
+    }
+
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        (self.idle_timeout_secs > 0).then(|| Duration::from_secs(self.idle_timeout_secs))
+    }
+}
+
+/// Watching the workspace for changes made outside pathfinder.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct WatchConfig {
+    /// Refresh open documents and send `workspace/didChangeWatchedFiles` on changes.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Quiet period, in milliseconds, that ends a batch of file events.
+    #[serde(rename = "debounceMs", default = "default_watch_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+impl Default for WatchConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            debounce_ms: default_watch_debounce_ms(),
+        }
+    }
+}
+
+impl WatchConfig {
+    pub fn debounce(&self) -> Duration {
+        Duration::from_millis(self.debounce_ms)
+    }
+}
+
+/// Declarations indexed in the background after startup, for `find_symbol`. Disabled
+/// by default; the index follows the workspace through the file watcher.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub struct IndexConfig {
+    /// Build the index and offer `find_symbol`.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Most files indexed.
+    #[serde(rename = "maxFiles", default = "default_index_max_files")]
+    pub max_files: usize,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_files: default_index_max_files(),
+        }
+    }
+}
+
+/// Documents opened right after `initialize` so the server starts on them before
+/// the first tool call. Disabled unless `globs` or `recentFiles` is set.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct WarmupConfig {
+    /// Glob patterns, relative to the root directory, of files to open.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub globs: Vec<String>,
+    /// Also open this many most recently modified files with a configured extension.
+    #[serde(rename = "recentFiles", default)]
+    pub recent_files: usize,
+}
+
+impl WarmupConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.globs.is_empty() || self.recent_files > 0
+    }
+}
+
+/// Requests in flight at once, and which wait first when the limit is reached.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct QueueConfig {
+    /// Most requests sent to the server at once; 0 means no limit.
+    #[serde(rename = "maxConcurrent", default = "default_max_concurrent_requests")]
+    pub max_concurrent: usize,
+    /// Classes overriding the built-in ones, keyed by LSP method.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub priorities: BTreeMap<String, Priority>,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent: default_max_concurrent_requests(),
+            priorities: BTreeMap::new(),
+        }
+    }
+}
+
+/// Scheduling class of an LSP request; higher classes are sent first.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Bulk,
+    Normal,
+    Interactive,
+}
+
+fn default_docker_mount_path() -> String {
+    "/workspace".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// `path` with `.` and `..` components folded away, without touching the filesystem.
+fn lexical(path: &Path) -> PathBuf {
+    let mut folded = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                folded.pop();
+            }
+            other => folded.push(other),
+        }
+    }
+    folded
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn default_watch_debounce_ms() -> u64 {
+    200
+}
+
+fn default_index_max_files() -> usize {
+    50_000
+}
+
+fn default_max_open_documents() -> usize {
+    200
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    900
+}
+
+fn default_max_file_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_max_restarts() -> u32 {
+    5
+}
+
+fn default_restart_window_secs() -> u64 {
+    600
+}
+
+fn default_initial_delay_ms() -> u64 {
+    500
+}
+
+fn default_max_delay_ms() -> u64 {
+    30_000
+}
+
+fn default_queue_timeout_secs() -> u64 {
+    60
+}
+
+fn default_timeout_ms() -> u64 {
+    crate::lsp_bridge::DEFAULT_REQUEST_TIMEOUT.as_millis() as u64
+}
+
+fn default_adaptive_floor_ms() -> u64 {
+    5000
+}
+
+fn default_adaptive_ceiling_ms() -> u64 {
+    120_000
+}
+
+fn default_adaptive_factor() -> u32 {
+    3
+}
+
+/// Handling of server-initiated `workspace/applyEdit` requests. `Reject` also refuses
+/// `apply: true` in the `rename` and `code_action` tools.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplyEditPolicy {
+    /// Write the edits to disk and resync affected documents.
+    #[default]
+    Apply,
+    /// Keep the edits in memory for later review without touching disk.
+    Queue,
+    /// Refuse the edits.
+    Reject,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            extensions: Vec::new(),
+            command: Vec::new(),
+            connect: None,
+            runtime: Runtime::default(),
+            docker: None,
+            ssh: None,
+            record: None,
+            root_dir: default_root_dir(),
+            name: None,
+            settings: Value::Null,
+            client_capabilities: Value::Null,
+            indexing_wait_secs: default_indexing_wait_secs(),
+            result_cache_size: default_result_cache_size(),
+            max_message_bytes: default_max_message_bytes(),
+            message_action: default_message_action(),
+            apply_edits: ApplyEditPolicy::default(),
+            timeouts: TimeoutConfig::default(),
+            column_encoding: PositionEncoding::default(),
+            language_ids: BTreeMap::new(),
+            env: BTreeMap::new(),
+            env_remove: Vec::new(),
+            cwd: None,
+            restart: RestartPolicy::default(),
+            documents: DocumentLimits::default(),
+            watch: WatchConfig::default(),
+            warmup: WarmupConfig::default(),
+            queue: QueueConfig::default(),
+            index: IndexConfig::default(),
+        }
+    }
+}
+
+fn default_indexing_wait_secs() -> u64 {
+    crate::lsp_bridge::DEFAULT_INDEXING_WAIT.as_secs()
+}
+
+fn default_result_cache_size() -> usize {
+    256
+}
+
+fn default_max_concurrent_requests() -> usize {
+    8
+}
+
+fn default_max_message_bytes() -> usize {
+    crate::transport::DEFAULT_MAX_MESSAGE_BYTES
+}
+
+fn default_max_response_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_diagnostics_debounce_ms() -> u64 {
+    500
+}
+
+fn default_root_dir() -> PathBuf {
+    PathBuf::from(".")
+}
+
+fn default_message_action() -> String {
+    "dismiss".to_string()
 }
 
 impl Config {
@@ -28,26 +904,134 @@ impl Config {
         Ok(config)
     }
 
+    pub fn from_toml_str(toml: &str) -> Result<Self> {
+        let config: Config = toml::from_str(toml).context("failed to parse config TOML")?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Loads a configuration file, choosing the format from its extension.
+    ///
+    /// Files ending in `.toml` are parsed as TOML; everything else as JSON.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file: {}", path.display()))?;
+        let config = if path.extension().is_some_and(|ext| ext == "toml") {
+            Self::from_toml_str(&contents)
+        } else {
+            Self::from_json_str(&contents)
+        };
+        config.with_context(|| format!("invalid config file: {}", path.display()))
+    }
+
+    /// The config file a repository carries for pathfinder: the first of
+    /// [`DISCOVERED_FILE_NAMES`] in `dir` or the nearest parent that has one. The
+    /// search stops at the repository root (a directory with `.git`), so a file in some
+    /// enclosing directory never applies to an unrelated checkout.
+    pub fn discover(dir: &Path) -> Option<PathBuf> {
+        for dir in dir.ancestors() {
+            let found = DISCOVERED_FILE_NAMES
+                .iter()
+                .map(|name| dir.join(name))
+                .find(|path| path.is_file());
+            if found.is_some() {
+                return found;
+            }
+            if dir.join(".git").exists() {
+                return None;
+            }
+        }
+        None
+    }
+
     /// Builds a configuration from command-line server specification.
     pub fn from_server_spec(spec: crate::args::ServerSpec) -> Result<Self> {
-        let server = ServerConfig {
+        let mut server = ServerConfig {
             extensions: spec.extensions,
             command: spec.command,
-            root_dir: PathBuf::from("."),
+            ..ServerConfig::default()
         };
+        if let Some(secs) = spec.indexing_wait_secs {
+            server.indexing_wait_secs = secs;
+        }
+        if let Some(ms) = spec.timeout_ms {
+            server.timeouts.default = ms;
+        }
+        server.timeouts.methods.extend(spec.method_timeouts);
+        if let Some(encoding) = spec.column_encoding {
+            server.column_encoding = encoding;
+        }
+        server.env.extend(spec.env);
+        if spec.mock {
+            server.connect = Some(ConnectTarget::Mock);
+        }
 
-        let config = Config { server };
+        let config = Config {
+            server,
+            companions: Vec::new(),
+            tools: ToolsConfig::default(),
+            output: OutputConfig::default(),
+            sandbox: SandboxConfig::default(),
+            diagnostics: DiagnosticsConfig::default(),
+            read_only: false,
+        };
         config.validate()?;
         Ok(config)
     }
 
-    fn validate(&self) -> Result<()> {
-        if self.server.extensions.is_empty() {
-            return Err(anyhow!("server has no extensions"));
+    pub(crate) fn validate(&self) -> Result<()> {
+        self.server.validate()?;
+        let mut labels = vec![self.server.label()];
+        for companion in &self.companions {
+            let label = companion.label();
+            if labels.contains(&label) {
+                return Err(anyhow!(
+                    "two servers are named '{label}'; give companions distinct names with `name`"
+                ));
+            }
+            labels.push(label.clone());
+            companion
+                .validate()
+                .with_context(|| format!("invalid companion '{label}'"))?;
+            if let Some(extension) = companion
+                .extensions
+                .iter()
+                .find(|extension| !self.server.extensions.contains(extension))
+            {
+                return Err(anyhow!(
+                    "companion '{label}' lists extension '{extension}', which server does not"
+                ));
+            }
+            if companion.column_encoding != self.server.column_encoding {
+                return Err(anyhow!(
+                    "companion '{label}' needs the same columnEncoding as server"
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `readOnly` (or `--readonly`): server edits are rejected whatever
+    /// `applyEdits` says and `lsp_request`, which could send anything, is not offered.
+    ///
+    /// Fails if `record` would write the recording inside `workspace`.
+    pub fn restrict_to_read_only(&mut self, workspace: &Path) -> Result<()> {
+        if !self.read_only {
+            return Ok(());
         }
-        if self.server.command.is_empty() {
-            return Err(anyhow!("server has empty command"));
+        for server in std::iter::once(&mut self.server).chain(&mut self.companions) {
+            if let Some(record) = &server.record
+                && lexical(&workspace.join(record)).starts_with(workspace)
+            {
+                return Err(anyhow!(
+                    "read-only mode cannot record into the workspace ({}); record to a path outside it",
+                    record.display()
+                ));
+            }
+            server.apply_edits = ApplyEditPolicy::Reject;
         }
+        self.tools.lsp_request = false;
+        self.tools.custom.retain(|tool| tool.read_only);
         Ok(())
     }
 
@@ -57,15 +1041,100 @@ impl Config {
 }
 
 impl ServerConfig {
+    fn validate(&self) -> Result<()> {
+        if self.extensions.is_empty() {
+            return Err(anyhow!("server has no extensions"));
+        }
+        match (&self.connect, self.command.is_empty()) {
+            (None, true) => return Err(anyhow!("server has empty command and no connect")),
+            (Some(_), false) => {
+                return Err(anyhow!(
+                    "server sets both command and connect; use one or the other"
+                ));
+            }
+            _ => {}
+        }
+        if !matches!(self.client_capabilities, Value::Null | Value::Object(_)) {
+            return Err(anyhow!("clientCapabilities must be an object"));
+        }
+        let runtime_section = match self.runtime {
+            Runtime::Local => None,
+            Runtime::Docker => Some(("docker", self.docker.is_some())),
+            Runtime::Ssh => Some(("ssh", self.ssh.is_some())),
+        };
+        if let Some((name, present)) = runtime_section {
+            if !present {
+                return Err(anyhow!("runtime \"{name}\" needs a {name} section"));
+            }
+            if self.connect.is_some() {
+                return Err(anyhow!("connect cannot be combined with a {name} runtime"));
+            }
+        }
+        Ok(())
+    }
+
     pub fn resolve_root_dir(&self, base: &Path) -> Result<PathBuf> {
         let path = if self.root_dir.is_absolute() {
             self.root_dir.clone()
         } else {
             base.join(&self.root_dir)
         };
-        path.canonicalize()
+        crate::utils::canonicalize(&path)
             .with_context(|| format!("failed to resolve root directory: {}", path.display()))
     }
+
+    /// Resolves the server's working directory against the workspace root.
+    pub fn resolve_cwd(&self, workspace: &Path) -> PathBuf {
+        match &self.cwd {
+            Some(cwd) => workspace.join(cwd),
+            None => workspace.to_path_buf(),
+        }
+    }
+
+    /// Short name for the server in results and logs: its `name`, else the program
+    /// `command` runs, else the `connect` target.
+    pub fn label(&self) -> String {
+        if let Some(name) = &self.name {
+            return name.clone();
+        }
+        match (self.command.first(), &self.connect) {
+            (Some(program), _) => Path::new(program).file_name().map_or_else(
+                || program.clone(),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+            (None, Some(target)) => target.to_string(),
+            (None, None) => "server".to_string(),
+        }
+    }
+
+    /// Keeps `running`'s `rootDir`, `documents`, `watch`, `warmup`, and `index`,
+    /// which are only read when pathfinder starts, and returns the names of those
+    /// that differed.
+    pub fn keep_startup_settings(&mut self, running: &ServerConfig) -> Vec<&'static str> {
+        fn keep<T: PartialEq + Clone>(
+            kept: &mut Vec<&'static str>,
+            name: &'static str,
+            reloaded: &mut T,
+            running: &T,
+        ) {
+            if reloaded != running {
+                *reloaded = running.clone();
+                kept.push(name);
+            }
+        }
+        let mut kept = Vec::new();
+        keep(&mut kept, "rootDir", &mut self.root_dir, &running.root_dir);
+        keep(
+            &mut kept,
+            "documents",
+            &mut self.documents,
+            &running.documents,
+        );
+        keep(&mut kept, "watch", &mut self.watch, &running.watch);
+        keep(&mut kept, "warmup", &mut self.warmup, &running.warmup);
+        keep(&mut kept, "index", &mut self.index, &running.index);
+        kept
+    }
 }
 
 #[cfg(test)]
@@ -85,6 +1154,428 @@ mod tests {
         assert_eq!(config.server.extensions, vec!["js", "ts"]);
     }
 
+    #[test]
+    fn parse_toml_config() {
+        let toml = r#"
+            [server]
+            extensions = ["rs"]
+            command = ["rust-analyzer"]
+            rootDir = "."
+        "#;
+        let config = Config::from_toml_str(toml).unwrap();
+        assert_eq!(config.server.extensions, vec!["rs"]);
+        assert_eq!(config.server.command, vec!["rust-analyzer"]);
+    }
+
+    #[test]
+    fn parse_settings() {
+        let json = r#"{
+            "server": {
+                "extensions": ["py"],
+                "command": ["pyright-langserver", "--stdio"],
+                "rootDir": ".",
+                "settings": {"python": {"analysis": {"typeCheckingMode": "strict"}}}
+            }
+        }"#;
+        let config = Config::from_json_str(json).unwrap();
+        assert_eq!(
+            config.server.settings["python"]["analysis"]["typeCheckingMode"],
+            "strict"
+        );
+    }
+
+    #[test]
+    fn parse_apply_edit_policy() {
+        let toml = r#"
+            [server]
+            extensions = ["rs"]
+            command = ["rust-analyzer"]
+            rootDir = "."
+            applyEdits = "queue"
+            columnEncoding = "utf-32"
+        "#;
+        let config = Config::from_toml_str(toml).unwrap();
+        assert_eq!(config.server.apply_edits, ApplyEditPolicy::Queue);
+        assert_eq!(config.server.column_encoding, PositionEncoding::Utf32);
+    }
+
+    #[test]
+    fn parse_language_ids() {
+        let toml = r#"
+            [server]
+            extensions = ["vue", "ts"]
+            command = ["vue-language-server", "--stdio"]
+            rootDir = "."
+            languageIds = { vue = "vue", ".mts" = "typescript" }
+        "#;
+        let config = Config::from_toml_str(toml).unwrap();
+        assert_eq!(config.server.language_ids["vue"], "vue");
+        assert_eq!(config.server.language_ids[".mts"], "typescript");
+    }
+
+    #[test]
+    fn parse_tools_filter() {
+        let toml = r#"
+            [server]
+            extensions = ["rs"]
+            command = ["rust-analyzer"]
+            rootDir = "."
+
+            [tools]
+            enabled = ["definition", "references", "open_document"]
+            disabled = ["open_document"]
+        "#;
+        let tools = Config::from_toml_str(toml).unwrap().tools;
+        assert!(tools.is_enabled("definition"));
+        assert!(!tools.is_enabled("open_document"));
+        assert!(!tools.is_enabled("server_logs"));
+        assert!(ToolsConfig::default().is_enabled("server_logs"));
+        assert_eq!(tools.response_limit("definition"), 1024 * 1024);
+
+        let tools: ToolsConfig = serde_json::from_str(
+            r#"{"maxResponseBytes": 0, "responseLimits": {"references": 4096}}"#,
+        )
+        .unwrap();
+        assert_eq!(tools.response_limit("definition"), 0);
+        assert_eq!(tools.response_limit("references"), 4096);
+        assert!(tools.named_tools().any(|tool| tool == "references"));
+    }
+
+    #[test]
+    fn parse_middleware_by_name() {
+        let toml = r#"
+            [server]
+            extensions = ["rs"]
+            command = ["rust-analyzer"]
+            rootDir = "."
+
+            [[tools.middleware]]
+            name = "rewrite_paths"
+            client = "/home/me/project"
+            server = "/workspace"
+
+            [[tools.middleware]]
+            name = "audit"
+        "#;
+        let tools = Config::from_toml_str(toml).unwrap().tools;
+        assert_eq!(
+            tools.middleware,
+            [
+                MiddlewareConfig::RewritePaths {
+                    client: "/home/me/project".to_string(),
+                    server: "/workspace".to_string(),
+                },
+                MiddlewareConfig::Audit,
+            ]
+        );
+        let unknown: Result<ToolsConfig, _> =
+            serde_json::from_str(r#"{"middleware": [{"name": "compress"}]}"#);
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn lsp_request_is_opt_in() {
+        assert!(!ToolsConfig::default().is_enabled("lsp_request"));
+        let tools: ToolsConfig = serde_json::from_str(r#"{"lspRequest": true}"#).unwrap();
+        assert!(tools.is_enabled("lsp_request"));
+        let tools: ToolsConfig =
+            serde_json::from_str(r#"{"lspRequest": true, "disabled": ["lsp_request"]}"#).unwrap();
+        assert!(!tools.is_enabled("lsp_request"));
+    }
+
+    #[test]
+    fn read_only_rejects_edits_and_raw_requests() {
+        let json = r#"{
+            "server": {
+                "extensions": ["rs"],
+                "command": ["rust-analyzer"],
+                "rootDir": ".",
+                "applyEdits": "apply"
+            },
+            "tools": {"lspRequest": true},
+            "readOnly": true
+        }"#;
+        let workspace = Path::new("/project");
+        let mut config = Config::from_json_str(json).unwrap();
+        config.restrict_to_read_only(workspace).unwrap();
+        assert_eq!(config.server.apply_edits, ApplyEditPolicy::Reject);
+        assert!(!config.tools.is_enabled("lsp_request"));
+
+        config.server.record = Some(PathBuf::from("session.jsonl"));
+        assert!(config.restrict_to_read_only(workspace).is_err());
+        config.server.record = Some(PathBuf::from("sub/../../session.jsonl"));
+        assert!(config.restrict_to_read_only(workspace).is_ok());
+    }
+
+    #[test]
+    fn timeouts_prefer_tool_then_method_then_default() {
+        let toml = r#"
+            [server]
+            extensions = ["rs"]
+            command = ["rust-analyzer"]
+            rootDir = "."
+
+            [server.timeouts]
+            default = 10000
+            methods = { "workspace/symbol" = 60000, "textDocument/hover" = 2000 }
+            tools = { definition = 5000 }
+        "#;
+        let timeouts = Config::from_toml_str(toml).unwrap().server.timeouts;
+        assert_eq!(
+            timeouts.for_method("workspace/symbol"),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            timeouts.for_method("textDocument/references"),
+            Duration::from_secs(10)
+        );
+        assert_eq!(
+            timeouts.for_tool("definition", "textDocument/definition"),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            timeouts.for_tool("hover", "textDocument/hover"),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            ServerConfig::default().timeouts.for_method("initialize"),
+            Duration::from_secs(15)
+        );
+
+        // Observed latency only moves methods without a pinned timeout
+        let fast = Some(Duration::from_millis(100));
+        let slow = Some(Duration::from_secs(30));
+        assert_eq!(
+            timeouts.resolve(None, "textDocument/definition", fast),
+            Duration::from_secs(5)
+        );
+        assert_eq!(
+            timeouts.resolve(None, "workspace/executeCommand", slow),
+            Duration::from_secs(90)
+        );
+        assert_eq!(
+            timeouts.resolve(None, "textDocument/hover", slow),
+            Duration::from_secs(2)
+        );
+        assert_eq!(
+            timeouts.resolve(
+                None,
+                "callHierarchy/incomingCalls",
+                Some(Duration::from_secs(60))
+            ),
+            Duration::from_secs(120)
+        );
+        let fixed = TimeoutConfig {
+            adaptive: false,
+            ..TimeoutConfig::default()
+        };
+        assert_eq!(
+            fixed.resolve(None, "workspace/symbol", slow),
+            Duration::from_secs(15)
+        );
+    }
+
+    #[test]
+    fn parse_process_environment() {
+        let json = r#"{
+            "server": {
+                "extensions": ["go"],
+                "command": ["gopls"],
+                "rootDir": ".",
+                "env": {"GOFLAGS": "-tags=integration"},
+                "envRemove": ["GOPATH"],
+                "cwd": "tools"
+            }
+        }"#;
+        let server = Config::from_json_str(json).unwrap().server;
+        assert_eq!(server.env["GOFLAGS"], "-tags=integration");
+        assert_eq!(server.env_remove, vec!["GOPATH"]);
+        assert_eq!(
+            server.resolve_cwd(Path::new("/repo")),
+            PathBuf::from("/repo/tools")
+        );
+        assert_eq!(
+            ServerConfig::default().resolve_cwd(Path::new("/repo")),
+            PathBuf::from("/repo")
+        );
+    }
+
+    #[test]
+    fn restart_policy_backoff() {
+        let toml = r#"
+            [server]
+            extensions = ["rs"]
+            command = ["rust-analyzer"]
+            rootDir = "."
+
+            [server.restart]
+            maxRestarts = 2
+            whileRestarting = "queue"
+        "#;
+        let restart = Config::from_toml_str(toml).unwrap().server.restart;
+        assert_eq!(restart.max_restarts, 2);
+        assert_eq!(restart.while_restarting, WhileRestarting::Queue);
+        assert_eq!(restart.delay(0), Duration::from_millis(500));
+        assert_eq!(restart.delay(1), Duration::from_secs(1));
+        assert_eq!(restart.delay(40), Duration::from_secs(30));
+
+        let fixed = RestartPolicy {
+            backoff: BackoffStrategy::Fixed,
+            ..RestartPolicy::default()
+        };
+        assert_eq!(fixed.delay(3), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn parse_connect_targets() {
+        let json = r#"{
+            "server": {
+                "extensions": ["java"],
+                "connect": {"tcp": "127.0.0.1:9257"},
+                "rootDir": "."
+            }
+        }"#;
+        let server = Config::from_json_str(json).unwrap().server;
+        assert!(server.command.is_empty());
+        assert_eq!(
+            server.connect,
+            Some(ConnectTarget::Tcp("127.0.0.1:9257".into()))
+        );
+
+        let toml = r#"
+            [server]
+            extensions = ["c"]
+            rootDir = "."
+            connect = { unix = "/tmp/clangd.sock" }
+        "#;
+        let connect = Config::from_toml_str(toml).unwrap().server.connect.unwrap();
+        assert_eq!(connect.to_string(), "unix:/tmp/clangd.sock");
+
+        let json = r#"{
+            "server": {
+                "extensions": ["rs"],
+                "connect": {"replay": "tests/fixtures/hover.jsonl"},
+                "rootDir": "."
+            }
+        }"#;
+        let connect = Config::from_json_str(json).unwrap().server.connect;
+        assert_eq!(
+            connect,
+            Some(ConnectTarget::Replay("tests/fixtures/hover.jsonl".into()))
+        );
+
+        let toml = r#"
+            [server]
+            extensions = ["rs"]
+            rootDir = "."
+            connect = "mock"
+        "#;
+        let connect = Config::from_toml_str(toml).unwrap().server.connect;
+        assert_eq!(connect, Some(ConnectTarget::Mock));
+    }
+
+    #[test]
+    fn reject_command_with_connect_or_neither() {
+        let both = r#"{
+            "server": {
+                "extensions": ["c"],
+                "command": ["clangd"],
+                "connect": {"tcp": "localhost:1"},
+                "rootDir": "."
+            }
+        }"#;
+        assert!(Config::from_json_str(both).is_err());
+        let neither = r#"{"server": {"extensions": ["c"], "rootDir": "."}}"#;
+        assert!(Config::from_json_str(neither).is_err());
+    }
+
+    #[test]
+    fn parse_companions() {
+        let toml = r#"
+            [server]
+            extensions = ["ts", "tsx"]
+            command = ["typescript-language-server", "--stdio"]
+            rootDir = "."
+
+            [[companions]]
+            extensions = ["ts"]
+            command = ["/opt/bin/eslint-lsp", "--stdio"]
+        "#;
+        let config = Config::from_toml_str(toml).unwrap();
+        assert_eq!(config.companions.len(), 1);
+        assert_eq!(config.companions[0].label(), "eslint-lsp");
+        assert_eq!(config.server.label(), "typescript-language-server");
+
+        let foreign = toml.replace(r#"extensions = ["ts"]"#, r#"extensions = ["py"]"#);
+        let err = Config::from_toml_str(&foreign).unwrap_err();
+        assert!(format!("{err:#}").contains("extension 'py'"));
+
+        let invalid = toml.replace(r#"command = ["/opt/bin/eslint-lsp", "--stdio"]"#, "");
+        let err = Config::from_toml_str(&invalid).unwrap_err();
+        assert!(format!("{err:#}").contains("invalid companion"));
+    }
+
+    #[test]
+    fn parse_docker_runtime() {
+        let toml = r#"
+            [server]
+            extensions = ["c", "h"]
+            command = ["clangd"]
+            rootDir = "."
+            runtime = "docker"
+            docker = { image = "clangd:18" }
+        "#;
+        let server = Config::from_toml_str(toml).unwrap().server;
+        assert_eq!(server.runtime, Runtime::Docker);
+        let docker = server.docker.unwrap();
+        assert_eq!(docker.image, "clangd:18");
+        assert_eq!(docker.mount_path, "/workspace");
+
+        let missing = r#"{
+            "server": {
+                "extensions": ["c"],
+                "command": ["clangd"],
+                "rootDir": ".",
+                "runtime": "docker"
+            }
+        }"#;
+        assert!(Config::from_json_str(missing).is_err());
+    }
+
+    #[test]
+    fn parse_ssh_runtime() {
+        let toml = r#"
+            [server]
+            extensions = ["cpp"]
+            command = ["clangd"]
+            rootDir = "."
+            runtime = "ssh"
+
+            [server.ssh]
+            host = "build01"
+            user = "ci"
+            pathMap = { "." = "/srv/src/app", "/opt/sdk" = "/usr/local/sdk" }
+        "#;
+        let server = Config::from_toml_str(toml).unwrap().server;
+        assert_eq!(server.runtime, Runtime::Ssh);
+        let ssh = server.ssh.unwrap();
+        assert_eq!(ssh.host, "build01");
+        assert_eq!(ssh.user.as_deref(), Some("ci"));
+        assert_eq!(ssh.port, None);
+        assert_eq!(ssh.path_map[Path::new(".")], "/srv/src/app");
+
+        let missing = r#"{
+            "server": {
+                "extensions": ["cpp"],
+                "command": ["clangd"],
+                "rootDir": ".",
+                "runtime": "ssh",
+                "docker": { "image": "clangd" }
+            }
+        }"#;
+        assert!(Config::from_json_str(missing).is_err());
+    }
+
     #[test]
     fn reject_empty_extensions() {
         let json = r#"{
@@ -97,4 +1588,48 @@ mod tests {
         let config: Config = serde_json::from_str(json).unwrap();
         assert!(config.validate().is_err());
     }
+
+    #[test]
+    fn discovers_the_nearest_config_within_the_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        let nested = repo.join("crates/core/src");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::create_dir(repo.join(".git")).unwrap();
+        std::fs::write(dir.path().join("pathfinder.toml"), "").unwrap();
+        assert_eq!(Config::discover(&nested), None);
+
+        std::fs::write(repo.join("pathfinder.json"), "{}").unwrap();
+        std::fs::write(repo.join(".pathfinder.toml"), "").unwrap();
+        assert_eq!(
+            Config::discover(&nested),
+            Some(repo.join(".pathfinder.toml"))
+        );
+        std::fs::write(repo.join("crates/core/pathfinder.json"), "{}").unwrap();
+        assert_eq!(
+            Config::discover(&nested),
+            Some(repo.join("crates/core/pathfinder.json"))
+        );
+    }
+
+    #[test]
+    fn reloads_keep_settings_read_at_startup() {
+        let running = ServerConfig {
+            extensions: vec!["rs".to_string()],
+            ..ServerConfig::default()
+        };
+        let mut reloaded = running.clone();
+        reloaded.extensions.push("toml".to_string());
+        assert!(reloaded.keep_startup_settings(&running).is_empty());
+
+        reloaded.root_dir = PathBuf::from("crates/core");
+        reloaded.index.enabled = !running.index.enabled;
+        assert_eq!(
+            reloaded.keep_startup_settings(&running),
+            ["rootDir", "index"]
+        );
+        assert_eq!(reloaded.root_dir, running.root_dir);
+        assert_eq!(reloaded.index, running.index);
+        assert_eq!(reloaded.extensions, ["rs", "toml"]);
+    }
 }
@@ -0,0 +1,242 @@
+//! Attach mode: sharing one language server between pathfinder instances.
+//!
+//! With `--attach`, the first pathfinder started for a workspace and server becomes
+//! the daemon: besides its own stdio client, it listens on a local unix socket and
+//! serves every connection there as another MCP session. Later instances find the
+//! socket, connect, and just pipe their stdin/stdout through it, so the editor or
+//! agent behind them uses the daemon's language server instead of spawning (and
+//! re-indexing) a second one.
+//!
+//! The daemon keeps running after its own client leaves until the last attached
+//! session has ended.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+use crate::config::Config;
+use crate::service::PathfinderService;
+
+/// Default control socket for `config` in `workspace_base`: one per workspace root
+/// and server, under `$XDG_RUNTIME_DIR` or the temporary directory.
+pub fn socket_path(config: &Config, workspace_base: &Path) -> Result<PathBuf> {
+    let root = config.server.resolve_root_dir(workspace_base)?;
+    let mut key = root.display().to_string();
+    for part in config
+        .server
+        .command
+        .iter()
+        .chain(&config.server.extensions)
+    {
+        key.push('\0');
+        key.push_str(part);
+    }
+    if let Some(target) = &config.server.connect {
+        key.push('\0');
+        key.push_str(&target.to_string());
+    }
+    let dir = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir);
+    Ok(dir.join(format!("pathfinder-{:016x}.sock", fnv1a(key.as_bytes()))))
+}
+
+/// Forwards this process's stdin and stdout to the daemon listening at `path`
+/// until either side hangs up.
+///
+/// Returns `false` without doing anything if no daemon is listening there.
+pub async fn attach(path: &Path) -> Result<bool> {
+    #[cfg(unix)]
+    {
+        use tokio::io::AsyncWriteExt;
+
+        let stream = match tokio::net::UnixStream::connect(path).await {
+            Ok(stream) => stream,
+            Err(err) if is_absent(&err) => return Ok(false),
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!(
+                        "failed to connect to pathfinder daemon at {}",
+                        path.display()
+                    )
+                });
+            }
+        };
+        tracing::info!(socket = %path.display(), "Attached to running pathfinder daemon");
+        let (mut from_daemon, mut to_daemon) = stream.into_split();
+        let upstream = tokio::spawn(async move {
+            let _ = tokio::io::copy(&mut tokio::io::stdin(), &mut to_daemon).await;
+            // Tell the daemon the client is gone so it ends the session
+            let _ = to_daemon.shutdown().await;
+        });
+        let copied = tokio::io::copy(&mut from_daemon, &mut tokio::io::stdout()).await;
+        upstream.abort();
+        copied.context("lost connection to pathfinder daemon")?;
+        Ok(true)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        anyhow::bail!("--attach is only supported on unix platforms")
+    }
+}
+
+/// A daemon's control socket and the sessions attached through it.
+pub struct Daemon {
+    path: PathBuf,
+    sessions: watch::Receiver<usize>,
+    accept_task: JoinHandle<()>,
+}
+
+impl Daemon {
+    /// Listens at `path` and serves each connection as a new session of `service`.
+    ///
+    /// A leftover socket file from a daemon that no longer runs is replaced.
+    pub fn listen(path: &Path, service: &PathfinderService) -> Result<Self> {
+        #[cfg(unix)]
+        {
+            if path.exists() {
+                std::fs::remove_file(path).with_context(|| {
+                    format!("failed to remove stale daemon socket {}", path.display())
+                })?;
+            }
+            let listener = tokio::net::UnixListener::bind(path)
+                .with_context(|| format!("failed to listen on {}", path.display()))?;
+            tracing::info!(socket = %path.display(), "Accepting attached pathfinder instances");
+            let (sessions_sender, sessions) = watch::channel(0);
+            let accept_task = tokio::spawn(accept_loop(listener, service.clone(), sessions_sender));
+            Ok(Self {
+                path: path.to_path_buf(),
+                sessions,
+                accept_task,
+            })
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = (path, service);
+            anyhow::bail!("--attach is only supported on unix platforms")
+        }
+    }
+
+    /// Waits until no attached session is left, or `interrupted` fires, then stops
+    /// accepting connections.
+    pub async fn finish(self, interrupted: &CancellationToken) {
+        let mut sessions = self.sessions.clone();
+        if *sessions.borrow() > 0 {
+            tracing::info!(
+                sessions = *sessions.borrow(),
+                "Waiting for attached sessions to end"
+            );
+        }
+        tokio::select! {
+            _ = sessions.wait_for(|count| *count == 0) => {}
+            _ = interrupted.cancelled() => {}
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+async fn accept_loop(
+    listener: tokio::net::UnixListener,
+    service: PathfinderService,
+    sessions: watch::Sender<usize>,
+) {
+    use rmcp::ServiceExt;
+
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(err) => {
+                tracing::warn!(?err, "Failed to accept attached pathfinder instance");
+                continue;
+            }
+        };
+        sessions.send_modify(|count| *count += 1);
+        let service = service.new_session();
+        let sessions = sessions.clone();
+        tokio::spawn(async move {
+            match service.serve(stream.into_split()).await {
+                Ok(server) => {
+                    let quit_reason = server.waiting().await;
+                    tracing::debug!(?quit_reason, "Attached session ended");
+                }
+                Err(err) => tracing::debug!(?err, "Attached session failed to initialize"),
+            }
+            sessions.send_modify(|count| *count -= 1);
+        });
+    }
+}
+
+/// Whether connecting failed because nothing is listening.
+fn is_absent(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+    )
+}
+
+/// 64-bit FNV-1a, stable across builds so every instance derives the same socket.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ServerConfig;
+
+    fn config(command: &str) -> Config {
+        Config {
+            server: ServerConfig {
+                extensions: vec!["rs".to_string()],
+                command: vec![command.to_string()],
+                ..ServerConfig::default()
+            },
+            companions: Vec::new(),
+            tools: Default::default(),
+            output: Default::default(),
+            sandbox: Default::default(),
+            diagnostics: Default::default(),
+            read_only: false,
+        }
+    }
+
+    #[test]
+    fn socket_path_depends_on_workspace_and_server() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let path = socket_path(&config("rust-analyzer"), first.path()).unwrap();
+        assert_eq!(
+            path,
+            socket_path(&config("rust-analyzer"), first.path()).unwrap()
+        );
+        assert_ne!(
+            path,
+            socket_path(&config("rust-analyzer"), second.path()).unwrap()
+        );
+        assert_ne!(
+            path,
+            socket_path(&config("ra-multiplex"), first.path()).unwrap()
+        );
+        assert!(path.to_string_lossy().ends_with(".sock"));
+    }
+
+    #[test]
+    fn fnv1a_matches_reference_values() {
+        assert_eq!(fnv1a(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv1a(b"a"), 0xaf63_dc4c_8601_ec8c);
+    }
+}
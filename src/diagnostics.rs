@@ -0,0 +1,389 @@
+//! Diagnostics the language server publishes, and pushing them to MCP clients.
+//!
+//! Servers send `textDocument/publishDiagnostics` for the documents they have open
+//! whenever their analysis changes. The supervisor keeps the latest set per document
+//! in a [`DiagnosticsStore`], fed from every bridge it launches. With
+//! `diagnostics.push` on, each session sends the documents whose diagnostics changed
+//! to its client as `notifications/message` (logger [`DIAGNOSTICS_LOGGER`]) once the
+//! server has been quiet for `debounceMs`, so an agent hears about errors its own
+//! edits introduced without asking. Each message carries the document's full set; an
+//! empty one means its earlier diagnostics are gone.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::sync::broadcast;
+
+use crate::lsp_bridge::{LspBridge, Notification};
+use crate::position::PositionConverter;
+
+/// `logger` of the `notifications/message` carrying diagnostics.
+pub const DIAGNOSTICS_LOGGER: &str = "diagnostics";
+
+/// Changed documents buffered per subscriber before the oldest are dropped.
+const UPDATE_CAPACITY: usize = 1024;
+
+/// A diagnostic as clients see it, with columns in their encoding.
+#[derive(Debug, Serialize, Clone, PartialEq, schemars::JsonSchema)]
+pub struct Diagnostic {
+    pub start_line: u32,
+    pub start_character: u32,
+    pub end_line: u32,
+    pub end_character: u32,
+    /// `error`, `warning`, `information`, or `hint`
+    pub severity: String,
+    pub message: String,
+    /// What produced it, such as the compiler or a linter
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The rule or error code, a string or a number
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<Value>,
+}
+
+/// The latest diagnostics of each document, shared by all sessions.
+#[derive(Debug)]
+pub struct DiagnosticsStore {
+    latest: StdMutex<HashMap<String, Vec<Value>>>,
+    /// URIs of documents whose diagnostics changed.
+    updates: broadcast::Sender<String>,
+}
+
+impl Default for DiagnosticsStore {
+    fn default() -> Self {
+        Self {
+            latest: StdMutex::new(HashMap::new()),
+            updates: broadcast::Sender::new(UPDATE_CAPACITY),
+        }
+    }
+}
+
+impl DiagnosticsStore {
+    /// Records the params of a `publishDiagnostics` notification, announcing the
+    /// document to subscribers if its diagnostics changed.
+    pub fn publish(&self, params: &Value) {
+        let Some(uri) = params["uri"].as_str() else {
+            return;
+        };
+        let diagnostics = params["diagnostics"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let previous = self
+            .latest
+            .lock()
+            .expect("diagnostics lock poisoned")
+            .insert(uri.to_string(), diagnostics.clone());
+        if previous.as_ref() != Some(&diagnostics) {
+            // Nobody listening is fine
+            let _ = self.updates.send(uri.to_string());
+        }
+    }
+
+    /// The latest diagnostics for `uri`, in the server's encoding; empty if the
+    /// server published none.
+    pub fn get(&self, uri: &str) -> Vec<Value> {
+        self.latest
+            .lock()
+            .expect("diagnostics lock poisoned")
+            .get(uri)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Whether the server has published diagnostics for `uri`, even an empty set.
+    pub fn contains(&self, uri: &str) -> bool {
+        self.latest
+            .lock()
+            .expect("diagnostics lock poisoned")
+            .contains_key(uri)
+    }
+
+    /// Subscribes to the URIs of documents whose diagnostics change.
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.updates.subscribe()
+    }
+}
+
+/// Feeds `store` from the server's notifications until the bridge goes away.
+pub fn spawn_collector(lsp: &Arc<LspBridge>, store: &Arc<DiagnosticsStore>) {
+    let mut notifications = lsp.subscribe();
+    let store = store.clone();
+    tokio::spawn(async move {
+        loop {
+            match notifications.recv().await {
+                Ok(notification) if notification.method == "textDocument/publishDiagnostics" => {
+                    store.publish(&notification.params);
+                }
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(
+                        skipped,
+                        "Missed server notifications; diagnostics may be stale"
+                    );
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// The documents changed next: waits for one, then collects more until `debounce`
+/// passes without another. None once the store is gone.
+pub async fn next_batch(
+    updates: &mut broadcast::Receiver<String>,
+    debounce: Duration,
+) -> Option<BTreeSet<String>> {
+    let mut batch = BTreeSet::new();
+    loop {
+        let received = if batch.is_empty() {
+            updates.recv().await
+        } else {
+            match tokio::time::timeout(debounce, updates.recv()).await {
+                Ok(received) => received,
+                Err(_) => return Some(batch),
+            }
+        };
+        match received {
+            Ok(uri) => {
+                batch.insert(uri);
+            }
+            // Updates were dropped, but the documents still hold their latest state
+            Err(broadcast::error::RecvError::Lagged(_)) => {}
+            Err(broadcast::error::RecvError::Closed) if batch.is_empty() => return None,
+            Err(broadcast::error::RecvError::Closed) => return Some(batch),
+        }
+    }
+}
+
+/// The `data` of a push for `uri`: its diagnostics as [`Diagnostic`]s, before the
+/// output settings apply.
+pub async fn update(converter: &mut PositionConverter, uri: &str, diagnostics: &[Value]) -> Value {
+    let converted = convert(converter, uri, diagnostics).await;
+    json!({ "uri": uri, "diagnostics": converted })
+}
+
+/// `diagnostics` of the document at `uri`, as published, with columns converted to
+/// the client's encoding.
+pub async fn convert(
+    converter: &mut PositionConverter,
+    uri: &str,
+    diagnostics: &[Value],
+) -> Vec<Diagnostic> {
+    let mut converted = Vec::with_capacity(diagnostics.len());
+    for diagnostic in diagnostics {
+        let range = &diagnostic["range"];
+        let position = |end: &str, key: &str| range[end][key].as_u64().unwrap_or(0) as u32;
+        let (start_line, end_line) = (position("start", "line"), position("end", "line"));
+        let start_character = converter
+            .to_client(uri, start_line, position("start", "character"))
+            .await;
+        let end_character = converter
+            .to_client(uri, end_line, position("end", "character"))
+            .await;
+        converted.push(Diagnostic {
+            start_line,
+            start_character,
+            end_line,
+            end_character,
+            severity: severity(diagnostic).to_string(),
+            message: diagnostic["message"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            source: diagnostic["source"].as_str().map(str::to_string),
+            code: diagnostic.get("code").cloned(),
+        });
+    }
+    converted
+}
+
+/// Waits up to `timeout` for the server to publish diagnostics for `uri`, at
+/// `version` or later if given and the server reports versions. None on timeout.
+pub async fn next_published(
+    notifications: &mut broadcast::Receiver<Notification>,
+    uri: &str,
+    version: Option<i32>,
+    timeout: Duration,
+) -> Option<Vec<Value>> {
+    let wait = async {
+        loop {
+            let notification = match notifications.recv().await {
+                Ok(notification) => notification,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return None,
+            };
+            let params = &notification.params;
+            if notification.method != "textDocument/publishDiagnostics"
+                || params["uri"].as_str() != Some(uri)
+            {
+                continue;
+            }
+            let published = params["version"].as_i64();
+            if let (Some(version), Some(published)) = (version, published)
+                && published < i64::from(version)
+            {
+                continue;
+            }
+            return Some(
+                params["diagnostics"]
+                    .as_array()
+                    .cloned()
+                    .unwrap_or_default(),
+            );
+        }
+    };
+    tokio::time::timeout(timeout, wait).await.ok().flatten()
+}
+
+/// What changed between two published sets of a document: the diagnostics of
+/// `after` that `before` lacks, and those of `before` gone from `after`. Edits move
+/// diagnostics around, so they are compared without their ranges.
+pub fn delta(before: &[Value], after: &[Value]) -> (Vec<Value>, Vec<Value>) {
+    let identity = |diagnostic: &Value| {
+        let mut identity = diagnostic.clone();
+        if let Some(fields) = identity.as_object_mut() {
+            fields.remove("range");
+            fields.remove("relatedInformation");
+            fields.remove("data");
+        }
+        identity
+    };
+    let keys: Vec<Value> = before.iter().map(identity).collect();
+    let mut matched = vec![false; before.len()];
+    let mut introduced = Vec::new();
+    for diagnostic in after {
+        let key = identity(diagnostic);
+        match (0..keys.len()).find(|&index| !matched[index] && keys[index] == key) {
+            Some(index) => matched[index] = true,
+            None => introduced.push(diagnostic.clone()),
+        }
+    }
+    let resolved = before
+        .iter()
+        .zip(matched)
+        .filter(|(_, matched)| !matched)
+        .map(|(diagnostic, _)| diagnostic.clone())
+        .collect();
+    (introduced, resolved)
+}
+
+/// Whether any of `diagnostics` is an error.
+pub fn has_errors(diagnostics: &[Value]) -> bool {
+    diagnostics
+        .iter()
+        .any(|diagnostic| severity(diagnostic) == "error")
+}
+
+/// LSP's numeric severity by name; servers that leave it out mean an error.
+fn severity(diagnostic: &Value) -> &'static str {
+    match diagnostic["severity"].as_u64() {
+        Some(2) => "warning",
+        Some(3) => "information",
+        Some(4) => "hint",
+        _ => "error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::position::PositionEncoding;
+
+    fn published(uri: &str, messages: &[&str]) -> Value {
+        let diagnostics: Vec<Value> = messages
+            .iter()
+            .map(|message| {
+                json!({
+                    "range": {"start": {"line": 1, "character": 4}, "end": {"line": 1, "character": 9}},
+                    "severity": 1,
+                    "message": message,
+                    "source": "mock",
+                })
+            })
+            .collect();
+        json!({ "uri": uri, "diagnostics": diagnostics })
+    }
+
+    #[tokio::test]
+    async fn changes_are_batched_until_quiet() {
+        let store = DiagnosticsStore::default();
+        let mut updates = store.subscribe();
+        store.publish(&published("file:///a.rs", &["unused"]));
+        store.publish(&published("file:///b.rs", &["mismatched types"]));
+        // Unchanged diagnostics are not announced again
+        store.publish(&published("file:///a.rs", &["unused"]));
+
+        let batch = next_batch(&mut updates, Duration::from_millis(20))
+            .await
+            .unwrap();
+        assert_eq!(
+            batch.into_iter().collect::<Vec<_>>(),
+            ["file:///a.rs", "file:///b.rs"]
+        );
+
+        store.publish(&published("file:///a.rs", &[]));
+        let batch = next_batch(&mut updates, Duration::from_millis(20))
+            .await
+            .unwrap();
+        assert_eq!(batch.len(), 1);
+        assert!(store.get("file:///a.rs").is_empty());
+        assert!(has_errors(&store.get("file:///b.rs")));
+    }
+
+    #[test]
+    fn delta_ignores_moved_diagnostics() {
+        let diagnostic = |line: u32, message: &str| {
+            json!({
+                "range": {"start": {"line": line, "character": 0}, "end": {"line": line, "character": 1}},
+                "severity": 1,
+                "message": message,
+            })
+        };
+        let before = [
+            diagnostic(1, "unused"),
+            diagnostic(4, "unused"),
+            diagnostic(6, "gone"),
+        ];
+        let after = [
+            diagnostic(2, "unused"),
+            diagnostic(5, "unused"),
+            diagnostic(7, "new"),
+        ];
+        let (introduced, resolved) = delta(&before, &after);
+        assert_eq!(introduced, [diagnostic(7, "new")]);
+        assert_eq!(resolved, [diagnostic(6, "gone")]);
+    }
+
+    #[tokio::test]
+    async fn updates_carry_the_client_view() {
+        let mut converter =
+            PositionConverter::new(PositionEncoding::Utf16, PositionEncoding::Utf16);
+        let diagnostics = published("file:///a.rs", &["unused"])["diagnostics"].clone();
+        let update = update(
+            &mut converter,
+            "file:///a.rs",
+            diagnostics.as_array().unwrap(),
+        )
+        .await;
+        assert_eq!(
+            update,
+            json!({
+                "uri": "file:///a.rs",
+                "diagnostics": [{
+                    "start_line": 1,
+                    "start_character": 4,
+                    "end_line": 1,
+                    "end_character": 9,
+                    "severity": "error",
+                    "message": "unused",
+                    "source": "mock",
+                }],
+            })
+        );
+    }
+}
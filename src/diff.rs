@@ -0,0 +1,219 @@
+//! Unified diffs of file contents, for previewing edits before they are written.
+//!
+//! Lines are compared with Myers' algorithm after trimming the common prefix and
+//! suffix, which keeps the search small for the scattered one-line changes renames and
+//! code actions produce.
+
+/// Unchanged lines shown around each change.
+const CONTEXT_LINES: usize = 3;
+
+/// One line of the edit script, by index into the old or new lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// A unified diff turning `old` into `new`, with `--- {old_label}` and
+/// `+++ {new_label}` headers; empty if the texts are equal.
+pub fn unified_diff(old_label: &str, new_label: &str, old: &str, new: &str) -> String {
+    if old == new {
+        return String::new();
+    }
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let mut out = format!("--- {old_label}\n+++ {new_label}\n");
+    let changes: Vec<usize> = ops
+        .iter()
+        .enumerate()
+        .filter(|(_, op)| !matches!(op, Op::Equal(..)))
+        .map(|(index, _)| index)
+        .collect();
+    let mut group_start = 0;
+    while group_start < changes.len() {
+        // Changes closer than two contexts apart share a hunk
+        let mut group_end = group_start;
+        while group_end + 1 < changes.len()
+            && changes[group_end + 1] - changes[group_end] <= 2 * CONTEXT_LINES + 1
+        {
+            group_end += 1;
+        }
+        let start = changes[group_start].saturating_sub(CONTEXT_LINES);
+        let end = (changes[group_end] + CONTEXT_LINES + 1).min(ops.len());
+        write_hunk(&mut out, &ops[..end], start, &old_lines, &new_lines);
+        group_start = group_end + 1;
+    }
+    out
+}
+
+/// Appends the hunk of `ops[start..]`; `ops[..start]` only positions it.
+fn write_hunk(out: &mut String, ops: &[Op], start: usize, old: &[&str], new: &[&str]) {
+    let counts = |ops: &[Op]| {
+        ops.iter().fold((0, 0), |(old, new), op| match op {
+            Op::Equal(..) => (old + 1, new + 1),
+            Op::Delete(_) => (old + 1, new),
+            Op::Insert(_) => (old, new + 1),
+        })
+    };
+    let (old_before, new_before) = counts(&ops[..start]);
+    let (old_count, new_count) = counts(&ops[start..]);
+    // An empty side is numbered by the line before it
+    let first = |before: usize, count: usize| if count == 0 { before } else { before + 1 };
+    out.push_str(&format!(
+        "@@ -{},{old_count} +{},{new_count} @@\n",
+        first(old_before, old_count),
+        first(new_before, new_count),
+    ));
+    for op in &ops[start..] {
+        let (marker, line) = match *op {
+            Op::Equal(index, _) => (' ', old[index]),
+            Op::Delete(index) => ('-', old[index]),
+            Op::Insert(index) => ('+', new[index]),
+        };
+        out.push(marker);
+        out.push_str(line);
+        if !line.ends_with('\n') {
+            out.push_str("\n\\ No newline at end of file\n");
+        }
+    }
+}
+
+/// A shortest edit script from `old` to `new`.
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let middle = myers(
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut ops: Vec<Op> = (0..prefix).map(|index| Op::Equal(index, index)).collect();
+    ops.extend(middle.into_iter().map(|op| match op {
+        Op::Equal(old, new) => Op::Equal(old + prefix, new + prefix),
+        Op::Delete(old) => Op::Delete(old + prefix),
+        Op::Insert(new) => Op::Insert(new + prefix),
+    }));
+    let (old_end, new_end) = (old.len() - suffix, new.len() - suffix);
+    ops.extend((0..suffix).map(|index| Op::Equal(old_end + index, new_end + index)));
+    ops
+}
+
+/// Myers' O((N+M)D) greedy search, keeping each round's furthest reaching paths to
+/// walk the script back from the end.
+fn myers(old: &[&str], new: &[&str]) -> Vec<Op> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut furthest = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    'search: for d in 0..=max {
+        trace.push(furthest.clone());
+        for k in (-d..=d).step_by(2) {
+            let index = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && furthest[index - 1] < furthest[index + 1]) {
+                furthest[index + 1]
+            } else {
+                furthest[index - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            furthest[index] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, furthest) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let index = (k + offset) as usize;
+        let previous_k = if k == -d || (k != d && furthest[index - 1] < furthest[index + 1]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let previous_x = furthest[(previous_k + offset) as usize];
+        let previous_y = previous_x - previous_k;
+        while x > previous_x && y > previous_y {
+            x -= 1;
+            y -= 1;
+            ops.push(Op::Equal(x as usize, y as usize));
+        }
+        if d > 0 {
+            if x == previous_x {
+                ops.push(Op::Insert(previous_y as usize));
+            } else {
+                ops.push(Op::Delete(previous_x as usize));
+            }
+        }
+        (x, y) = (previous_x, previous_y);
+    }
+    ops.reverse();
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_changed_lines_with_context() {
+        let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm\nn\n";
+        let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nM\nn\nadded\n";
+        assert_eq!(
+            unified_diff("a/x.rs", "b/x.rs", old, new),
+            "--- a/x.rs\n+++ b/x.rs\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -10,5 +10,6 @@\n j\n k\n l\n-m\n+M\n n\n+added\n"
+        );
+        assert_eq!(unified_diff("a", "b", old, old), "");
+    }
+
+    #[test]
+    fn diffs_created_deleted_and_unterminated_files() {
+        assert_eq!(
+            unified_diff("/dev/null", "b/new.rs", "", "fn f() {}\n"),
+            "--- /dev/null\n+++ b/new.rs\n@@ -0,0 +1,1 @@\n+fn f() {}\n"
+        );
+        assert_eq!(
+            unified_diff("a/x.rs", "b/x.rs", "x\ny", "x\nz"),
+            "--- a/x.rs\n+++ b/x.rs\n@@ -1,2 +1,2 @@\n x\n-y\n\\ No newline at end of file\n+z\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn finds_a_shortest_script() {
+        let old = ["a\n", "b\n", "c\n", "a\n", "b\n", "b\n", "a\n"];
+        let new = ["c\n", "b\n", "a\n", "b\n", "a\n", "c\n"];
+        let ops = diff_lines(&old, &new);
+        let edits = ops.iter().filter(|op| !matches!(op, Op::Equal(..))).count();
+        assert_eq!(edits, 5);
+        let rebuilt: Vec<&str> = ops
+            .iter()
+            .filter_map(|op| match *op {
+                Op::Equal(index, _) => Some(old[index]),
+                Op::Insert(index) => Some(new[index]),
+                Op::Delete(_) => None,
+            })
+            .collect();
+        assert_eq!(rebuilt, new);
+    }
+}
@@ -0,0 +1,247 @@
+//! Environment diagnosis for the `pathfinder doctor` subcommand.
+//!
+//! This module runs a series of checks against the configured LSP server without
+//! starting the MCP service: it validates the workspace root, resolves the server
+//! binary (unless connecting to a listening server), and performs a full
+//! initialize/shutdown handshake. The resulting report
+//! can be rendered as human-readable text or serialized as JSON.
+
+use std::fmt::Write as _;
+use std::path::PathBuf;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::capabilities::ServerCapabilities;
+use crate::config::Config;
+use crate::lsp_bridge::LspBridge;
+use crate::runtime;
+use crate::utils::resolve_workspace_base;
+
+/// Server stderr lines quoted when initialize fails.
+const STDERR_TAIL_LINES: usize = 10;
+
+/// Server capabilities worth reporting, as (capability key, display name) pairs.
+const KEY_CAPABILITIES: &[(&str, &str)] = &[
+    ("definitionProvider", "definition"),
+    ("declarationProvider", "declaration"),
+    ("typeDefinitionProvider", "typeDefinition"),
+    ("implementationProvider", "implementation"),
+    ("referencesProvider", "references"),
+    ("hoverProvider", "hover"),
+    ("documentSymbolProvider", "documentSymbol"),
+    ("workspaceSymbolProvider", "workspaceSymbol"),
+    ("renameProvider", "rename"),
+    ("codeActionProvider", "codeAction"),
+    ("documentFormattingProvider", "formatting"),
+];
+
+#[derive(Debug, Serialize)]
+pub struct DoctorReport {
+    pub checks: Vec<Check>,
+    pub server: Option<ServerIdentity>,
+    pub capabilities: Vec<CapabilityStatus>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ServerIdentity {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CapabilityStatus {
+    pub name: &'static str,
+    pub supported: bool,
+}
+
+impl DoctorReport {
+    /// Returns true if every check passed.
+    pub fn is_healthy(&self) -> bool {
+        self.checks.iter().all(|check| check.ok)
+    }
+
+    /// Renders the report as human-readable text.
+    pub fn render_text(&self) -> String {
+        let mut out = String::new();
+        for check in &self.checks {
+            let status = if check.ok { "ok" } else { "FAIL" };
+            let _ = writeln!(out, "[{status:>4}] {}: {}", check.name, check.detail);
+        }
+        if let Some(server) = &self.server {
+            let version = server.version.as_deref().unwrap_or("unknown version");
+            let _ = writeln!(out, "\nserver: {} ({version})", server.name);
+        }
+        if !self.capabilities.is_empty() {
+            let _ = writeln!(out, "\ncapabilities:");
+            for capability in &self.capabilities {
+                let mark = if capability.supported { "yes" } else { "no" };
+                let _ = writeln!(out, "  {:<16} {mark}", capability.name);
+            }
+        }
+        out
+    }
+
+    fn push(&mut self, name: &'static str, ok: bool, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            ok,
+            detail: detail.into(),
+        });
+    }
+}
+
+/// Runs all diagnostics for the given configuration.
+///
+/// Later checks are skipped when an earlier one they depend on has failed, so the
+/// report always explains the first problem a user needs to fix.
+pub async fn diagnose(config: &Config, workspace: Option<PathBuf>) -> DoctorReport {
+    let mut report = DoctorReport {
+        checks: Vec::new(),
+        server: None,
+        capabilities: Vec::new(),
+    };
+
+    let workspace = match resolve_workspace_base(workspace)
+        .and_then(|base| config.server.resolve_root_dir(&base))
+    {
+        Ok(path) if path.is_dir() => {
+            report.push("workspace", true, path.display().to_string());
+            path
+        }
+        Ok(path) => {
+            report.push(
+                "workspace",
+                false,
+                format!("not a directory: {}", path.display()),
+            );
+            return report;
+        }
+        Err(err) => {
+            report.push("workspace", false, format!("{err:#}"));
+            return report;
+        }
+    };
+
+    if config.server.connect.is_none() {
+        // With a docker runtime this checks for `docker` itself
+        let command = match runtime::launch(&config.server, &workspace) {
+            Ok(launch) => launch.command[0].clone(),
+            Err(err) => {
+                report.push("binary", false, format!("{err:#}"));
+                return report;
+            }
+        };
+        match which::which(&command) {
+            Ok(path) => report.push("binary", true, path.display().to_string()),
+            Err(err) => {
+                report.push("binary", false, format!("{command}: {err}"));
+                return report;
+            }
+        }
+    }
+
+    let (check, started) = match &config.server.connect {
+        Some(target) => ("connect", format!("connected to {target}")),
+        None => ("spawn", "language server process started".to_string()),
+    };
+    let mut lsp = match LspBridge::from_config(&config.server, workspace).await {
+        Ok(lsp) => lsp,
+        Err(err) => {
+            report.push(check, false, format!("{err:#}"));
+            return report;
+        }
+    };
+    report.push(check, true, started);
+
+    let init = match lsp.initialize().await {
+        Ok(init) => init,
+        Err(err) => {
+            let mut detail = format!("{err:#}");
+            // The server usually explains why it failed on stderr.
+            for line in lsp.stderr_log().tail(STDERR_TAIL_LINES, None) {
+                detail.push_str("\n    ");
+                detail.push_str(&line.text);
+            }
+            report.push("initialize", false, detail);
+            let _ = lsp.shutdown().await;
+            return report;
+        }
+    };
+    report.push("initialize", true, "handshake completed");
+    report.server = server_identity(&init);
+    report.capabilities = capability_statuses(&init);
+
+    match lsp.shutdown().await {
+        Ok(()) => report.push("shutdown", true, "server exited"),
+        Err(err) => report.push("shutdown", false, format!("{err:#}")),
+    }
+
+    report
+}
+
+fn server_identity(init: &Value) -> Option<ServerIdentity> {
+    let info = init.get("serverInfo")?;
+    Some(ServerIdentity {
+        name: info.get("name")?.as_str()?.to_string(),
+        version: info
+            .get("version")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+    })
+}
+
+fn capability_statuses(init: &Value) -> Vec<CapabilityStatus> {
+    let capabilities = ServerCapabilities::from_initialize_result(init);
+    KEY_CAPABILITIES
+        .iter()
+        .map(|(key, name)| CapabilityStatus {
+            name,
+            supported: capabilities.supports(key),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn capabilities_treat_options_objects_as_supported() {
+        let init = json!({
+            "capabilities": {
+                "definitionProvider": true,
+                "hoverProvider": {"workDoneProgress": false},
+                "renameProvider": false,
+            }
+        });
+        let statuses = capability_statuses(&init);
+        let supported = |name: &str| {
+            statuses
+                .iter()
+                .find(|status| status.name == name)
+                .is_some_and(|status| status.supported)
+        };
+        assert!(supported("definition"));
+        assert!(supported("hover"));
+        assert!(!supported("rename"));
+        assert!(!supported("references"));
+    }
+
+    #[test]
+    fn server_identity_from_server_info() {
+        let init = json!({"serverInfo": {"name": "rust-analyzer", "version": "1.0"}});
+        let identity = server_identity(&init).unwrap();
+        assert_eq!(identity.name, "rust-analyzer");
+        assert_eq!(identity.version.as_deref(), Some("1.0"));
+        assert!(server_identity(&json!({})).is_none());
+    }
+}
@@ -1,35 +1,191 @@
 //! Document synchronization management.
 //!
 //! This module tracks open documents and ensures they are synchronized with the
-//! LSP server. It handles didOpen, didChange, and didClose notifications,
-//! and manages document versioning based on file modification times.
+//! LSP server. It handles didOpen, didChange, and didClose notifications (typed in
+//! [`crate::protocol`]), and manages document versioning based on file modification
+//! times.
+//!
+//! Notifications follow the server's `textDocumentSync` capability. The last text
+//! synced for each document is kept so that, for servers using incremental sync,
+//! `didChange` carries only the edited range instead of the whole file; servers that
+//! set `openClose` to false get no `didOpen` or `didClose`, and `didSave` is only
+//! sent, with the text only included, when the server asks for it. Documents are
+//! tracked the same way whatever the server wants.
+//!
+//! Open documents are limited by [`DocumentLimits`]: past the cap the least recently
+//! used document is closed, and documents idle for too long are closed by
+//! [`DocumentManager::evict_idle`]. Closed documents reopen transparently on next use.
+//!
+//! A document whose file has disappeared (deleted or renamed away) is closed on the
+//! server, and syncing it fails with [`DocumentMissing`].
+//!
+//! MCP clients can also push unsaved buffer content as *overlay* documents. Overlays
+//! are never re-read from disk; their in-memory text is what the server sees until
+//! the session that pushed it ends.
+//!
+//! Every document remembers which MCP sessions have used it. When a session ends,
+//! [`DocumentManager::release_session`] reverts its overlays to the file on disk and
+//! closes the documents no other session still uses. Documents opened outside any
+//! session (warm-up) are left to the usual limits.
+//!
+//! The manager is shared without an outer lock. Each URI has its own async mutex,
+//! held from reading the file until the notification is written, so versions and
+//! notifications stay ordered per document while different documents sync in
+//! parallel. Replaying documents after a restart and closing them at shutdown take
+//! every document at once.
+//!
+//! Jupyter notebooks are synced as notebook documents when the server supports them
+//! (see [`crate::notebook`]): the manager tracks the `.ipynb` file like any other
+//! document, and only the notifications differ. A tool naming one of its cells opens
+//! the whole notebook.
 
-use std::collections::HashMap;
-use std::time::SystemTime;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex as StdMutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime};
 
-use anyhow::{Context, Result};
-use serde_json::json;
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::{Value, json};
 use tokio::fs;
+use tokio::sync::{Mutex, RwLock};
+use tracing::Instrument;
 
+use crate::capabilities::TextDocumentSyncKind;
+use crate::config::DocumentLimits;
+use crate::edits::apply_edits_to_text;
+use crate::encoding;
 use crate::lsp_bridge::LspBridge;
-use crate::utils::{language_id_for_path, uri_to_path};
+use crate::notebook::{self, Notebook};
+use crate::position::{PositionEncoding, byte_to_column};
+use crate::protocol::{
+    DidChangeTextDocument, DidChangeTextDocumentParams, DidCloseTextDocument,
+    DidCloseTextDocumentParams, DidOpenTextDocument, DidOpenTextDocumentParams,
+    DidSaveTextDocument, DidSaveTextDocumentParams, Position, Range,
+    TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem,
+    VersionedTextDocumentIdentifier,
+};
+use crate::session::SessionId;
+use crate::utils::{uri_to_file_path, uri_to_path};
+
+/// The file behind a document no longer exists, e.g. it was deleted or renamed.
+///
+/// The document has been closed on the server by the time this is returned; callers
+/// can downcast to it to tell a vanished file from a sync failure.
+#[derive(Debug, Clone)]
+pub struct DocumentMissing {
+    pub path: PathBuf,
+}
+
+impl fmt::Display for DocumentMissing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "file no longer exists: {} (deleted or renamed?)",
+            self.path.display()
+        )
+    }
+}
+
+impl std::error::Error for DocumentMissing {}
 
 #[derive(Debug)]
 struct DocumentState {
     version: i32,
     mtime: SystemTime,
+    /// Content as last sent to the server
+    text: String,
+    /// Session whose client supplied the content, rather than the file on disk
+    overlay: Option<SessionId>,
+    /// Warning that the file is not UTF-8 and `text` was decoded from it
+    note: Option<String>,
+}
+
+/// What eviction and status need to know, readable while a sync is in progress.
+#[derive(Debug, Clone, Copy)]
+struct DocumentInfo {
+    open: bool,
+    overlay: bool,
+    last_used: Instant,
+}
+
+/// One URI's sync state behind its own lock.
+#[derive(Debug)]
+struct Document {
+    /// `None` while the document is not open on the server.
+    state: Mutex<Option<DocumentState>>,
+    info: StdMutex<DocumentInfo>,
+    /// Sessions that have used the document since it was last released.
+    sessions: StdMutex<HashSet<SessionId>>,
+}
+
+impl Document {
+    fn new() -> Self {
+        Self {
+            state: Mutex::new(None),
+            info: StdMutex::new(DocumentInfo {
+                open: false,
+                overlay: false,
+                last_used: Instant::now(),
+            }),
+            sessions: StdMutex::new(HashSet::new()),
+        }
+    }
+
+    fn info(&self) -> MutexGuard<'_, DocumentInfo> {
+        self.info.lock().expect("document info lock poisoned")
+    }
+
+    fn sessions(&self) -> MutexGuard<'_, HashSet<SessionId>> {
+        self.sessions
+            .lock()
+            .expect("document sessions lock poisoned")
+    }
+
+    /// Replaces the state held in `slot` (this document's locked state).
+    fn store(&self, slot: &mut Option<DocumentState>, state: Option<DocumentState>) {
+        let mut info = self.info();
+        info.open = state.is_some();
+        info.overlay = state.as_ref().is_some_and(|state| state.overlay.is_some());
+        *slot = state;
+    }
+
+    fn touch(&self) {
+        self.info().last_used = Instant::now();
+    }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct DocumentManager {
-    open: HashMap<String, DocumentState>,
+    documents: StdMutex<HashMap<String, Arc<Document>>>,
+    /// Held shared by per-document syncs and exclusively by operations spanning
+    /// every document, so a restart replay never interleaves with a sync.
+    gate: RwLock<()>,
+    limits: DocumentLimits,
+}
+
+impl Default for DocumentManager {
+    fn default() -> Self {
+        Self::with_limits(DocumentLimits::unlimited())
+    }
 }
 
 impl DocumentManager {
+    /// A manager that never closes documents on its own.
     pub fn new() -> Self {
         Self::default()
     }
 
+    pub fn with_limits(limits: DocumentLimits) -> Self {
+        Self {
+            documents: StdMutex::new(HashMap::new()),
+            gate: RwLock::new(()),
+            limits,
+        }
+    }
+
     /// Ensures a document is opened and synchronized with the LSP server.
     ///
     /// This method:
@@ -37,117 +193,796 @@ impl DocumentManager {
     /// 2. Sends didOpen if the document is new
     /// 3. Sends didChange if the file has been modified since last sync
     /// 4. Skips sync if the document is already up-to-date
-    pub async fn ensure_open(&mut self, lsp: &mut LspBridge, uri: &str) -> Result<()> {
+    ///
+    /// Overlay documents are already in sync by definition and are left alone.
+    pub async fn ensure_open(&self, lsp: &LspBridge, uri: &str) -> Result<()> {
+        self.open_synced(lsp, uri, None).await
+    }
+
+    /// Like [`ensure_open`](Self::ensure_open), and records that `session` uses the
+    /// document, so it is closed when the last session using it ends.
+    pub async fn ensure_open_for(
+        &self,
+        lsp: &LspBridge,
+        uri: &str,
+        session: SessionId,
+    ) -> Result<()> {
+        self.open_synced(lsp, uri, Some(session)).await
+    }
+
+    async fn open_synced(
+        &self,
+        lsp: &LspBridge,
+        uri: &str,
+        session: Option<SessionId>,
+    ) -> Result<()> {
+        let uri = &synced_uri(lsp, uri)?;
+        let _gate = self.gate.read().await;
+        let document = self.document(uri);
+        let opened = self
+            .sync(lsp, uri, &document)
+            .instrument(tracing::info_span!("document_sync", uri))
+            .await;
+        if opened.is_ok()
+            && let Some(session) = session
+        {
+            document.sessions().insert(session);
+        }
+        self.forget(uri, &document);
+        if opened? {
+            self.enforce_cap(lsp, uri).await;
+        }
+        Ok(())
+    }
+
+    /// Brings `document` up to date with the file. Returns whether it was opened.
+    async fn sync(&self, lsp: &LspBridge, uri: &str, document: &Document) -> Result<bool> {
+        let mut slot = document.state.lock().await;
+        document.touch();
+        if slot.as_ref().is_some_and(|state| state.overlay.is_some()) {
+            return Ok(false);
+        }
+
         // Get file metadata to check modification time
-        let path = uri_to_path(uri)?;
-        let metadata = fs::metadata(&path)
-            .await
-            .with_context(|| format!("failed to read metadata for {}", path.display()))?;
+        let path = uri_to_file_path(uri)?;
+        let Some(metadata) = file_metadata(&path).await? else {
+            if let Some(state) = slot.take() {
+                tracing::debug!("Document removed, sending didClose: {}", uri);
+                document.store(&mut slot, None);
+                self.send_did_close(lsp, uri, &state.text).await?;
+            }
+            return Err(DocumentMissing { path }.into());
+        };
         let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
 
-        match self.open.get(uri) {
+        match slot.as_ref() {
             // Document is already open and unchanged - no action needed
             Some(state) if !is_newer(modified, state.mtime)? => {
                 tracing::trace!("Document already synchronized: {}", uri);
-                return Ok(());
+                Ok(false)
             }
             // Document is open but has been modified - send didChange
             Some(state) => {
                 tracing::debug!("Document modified, sending didChange: {}", uri);
-                let text = fs::read_to_string(&path)
-                    .await
-                    .with_context(|| format!("failed to read {}", path.display()))?;
+                let (text, note) = self.read_text(&path, &metadata).await?;
                 let next_version = state.version + 1;
-                self.send_did_change(lsp, uri, next_version, &text).await?;
-                self.open.insert(
-                    uri.to_string(),
-                    DocumentState {
-                        version: next_version,
-                        mtime: modified,
-                    },
-                );
+                self.send_did_change(lsp, uri, next_version, &state.text, &text)
+                    .await?;
+                self.send_did_save(lsp, uri, &text).await?;
+                let state = DocumentState {
+                    version: next_version,
+                    mtime: modified,
+                    text,
+                    overlay: None,
+                    note,
+                };
+                document.store(&mut slot, Some(state));
+                Ok(false)
             }
             // Document is not yet open - send didOpen
             None => {
                 tracing::debug!("Opening new document: {}", uri);
-                let text = fs::read_to_string(&path)
-                    .await
-                    .with_context(|| format!("failed to read {}", path.display()))?;
+                let (text, note) = self.read_text(&path, &metadata).await?;
 
                 // The LSP protocol requires a languageId in textDocument/didOpen.
                 // This tells the server which parser to use and enables proper handling
                 // for polyglot servers (e.g., typescript-language-server needs to know
                 // whether to parse as "typescript" or "typescriptreact").
-                let language_id = language_id_for_path(&path);
+                let language_id = lsp.language_id(&path, &text);
                 let version = 1;
                 self.send_did_open(lsp, uri, language_id, version, &text)
                     .await?;
-                self.open.insert(
-                    uri.to_string(),
-                    DocumentState {
-                        version,
-                        mtime: modified,
-                    },
-                );
+                let state = DocumentState {
+                    version,
+                    mtime: modified,
+                    text,
+                    overlay: None,
+                    note,
+                };
+                document.store(&mut slot, Some(state));
+                Ok(true)
             }
         }
+    }
+
+    /// Re-sends the on-disk content of an open document, ignoring mtimes.
+    ///
+    /// Used after pathfinder itself wrote the file (e.g. a server-requested edit),
+    /// where the modification may fall within the same mtime tick as the last sync,
+    /// and when the file watcher reports a change. Nothing is sent if the content is
+    /// unchanged. Documents whose file no longer exists are closed; unopened and
+    /// overlay documents are skipped.
+    pub async fn resync(&self, lsp: &LspBridge, uri: &str) -> Result<()> {
+        let _gate = self.gate.read().await;
+        let Some(document) = self.lookup(uri) else {
+            return Ok(());
+        };
+        let mut slot = document.state.lock().await;
+        let Some(state) = slot.as_ref().filter(|state| state.overlay.is_none()) else {
+            return Ok(());
+        };
+        let next_version = state.version + 1;
+
+        let path = uri_to_file_path(uri)?;
+        let Some(metadata) = file_metadata(&path).await? else {
+            tracing::debug!("Document removed, sending didClose: {}", uri);
+            let text = state.text.clone();
+            document.store(&mut slot, None);
+            drop(slot);
+            self.forget(uri, &document);
+            return self.send_did_close(lsp, uri, &text).await;
+        };
+        let (text, note) = self.read_text(&path, &metadata).await?;
+        let mtime = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        if text == state.text {
+            if let Some(state) = slot.as_mut() {
+                state.mtime = mtime;
+            }
+            return Ok(());
+        }
+
+        tracing::debug!("Resyncing edited document: {}", uri);
+        self.send_did_change(lsp, uri, next_version, &state.text, &text)
+            .await?;
+        self.send_did_save(lsp, uri, &text).await?;
+        let state = DocumentState {
+            version: next_version,
+            mtime,
+            text,
+            overlay: None,
+            note,
+        };
+        document.store(&mut slot, Some(state));
+        Ok(())
+    }
+
+    /// Opens `uri` with client-supplied `text`, or replaces the text of an open
+    /// document, and marks it as an overlay owned by `session`. Returns the new
+    /// document version.
+    ///
+    /// The file does not need to exist, so agents can work on files they have not
+    /// written yet. An overlay pushed by another session cannot be replaced.
+    pub async fn open_overlay(
+        &self,
+        lsp: &LspBridge,
+        uri: &str,
+        session: SessionId,
+        text: String,
+    ) -> Result<i32> {
+        let _gate = self.gate.read().await;
+        let document = self.document(uri);
+        let mut slot = document.state.lock().await;
+        let version = match slot.as_ref().and_then(|state| state.overlay) {
+            Some(owner) if owner != session => Err(anyhow!(
+                "{uri} is an overlay document of another MCP session"
+            )),
+            _ => {
+                self.replace_overlay(lsp, uri, &document, &mut slot, session, text)
+                    .await
+            }
+        };
+        drop(slot);
+        self.forget(uri, &document);
+        let version = version?;
+        self.enforce_cap(lsp, uri).await;
+        Ok(version)
+    }
+
+    /// Applies LSP `TextEdit`s to an overlay document `session` owns. Returns the
+    /// new version.
+    ///
+    /// Edit columns are in the caller's column encoding.
+    pub async fn edit_overlay(
+        &self,
+        lsp: &LspBridge,
+        uri: &str,
+        session: SessionId,
+        edits: &[Value],
+    ) -> Result<i32> {
+        let _gate = self.gate.read().await;
+        let not_overlay = || {
+            anyhow!("{uri} is not an overlay document of this session; call open_document first")
+        };
+        let document = self.lookup(uri).ok_or_else(not_overlay)?;
+        let mut slot = document.state.lock().await;
+        let state = slot
+            .as_ref()
+            .filter(|state| state.overlay == Some(session))
+            .ok_or_else(not_overlay)?;
+        let text = apply_edits_to_text(&state.text, edits, lsp.column_encoding())?;
+        self.replace_overlay(lsp, uri, &document, &mut slot, session, text)
+            .await
+    }
+
+    /// Sends `text` as the new content of `document`, whose state lock is `slot`,
+    /// on behalf of `session`.
+    async fn replace_overlay(
+        &self,
+        lsp: &LspBridge,
+        uri: &str,
+        document: &Document,
+        slot: &mut Option<DocumentState>,
+        session: SessionId,
+        text: String,
+    ) -> Result<i32> {
+        let path = uri_to_file_path(uri)?;
+        check_size(&path, text.len() as u64, self.limits.max_file_bytes)?;
+        let version = match slot.as_ref() {
+            Some(state) => {
+                let version = state.version + 1;
+                self.send_did_change(lsp, uri, version, &state.text, &text)
+                    .await?;
+                version
+            }
+            None => {
+                tracing::debug!("Opening overlay document: {}", uri);
+                let version = 1;
+                self.send_did_open(lsp, uri, lsp.language_id(&path, &text), version, &text)
+                    .await?;
+                version
+            }
+        };
+        let state = DocumentState {
+            version,
+            mtime: SystemTime::UNIX_EPOCH,
+            text,
+            overlay: Some(session),
+            note: None,
+        };
+        document.store(slot, Some(state));
+        document.sessions().insert(session);
+        document.touch();
+        Ok(version)
+    }
+
+    /// Text of every overlay document, keyed by URI, and of every cell of an open
+    /// notebook, keyed by cell URI.
+    ///
+    /// Includes other sessions' overlays: the server sees them too, so positions it
+    /// returns into those files refer to the overlay text. Cells have no file of
+    /// their own, so their text is only found here.
+    pub async fn overlay_texts(&self) -> HashMap<String, String> {
+        let mut texts = HashMap::new();
+        for (uri, document) in self.snapshot() {
+            let overlay = document.info().overlay;
+            let is_notebook = notebook::is_notebook(&uri);
+            if !overlay && !is_notebook {
+                continue;
+            }
+            if let Some(state) = document.state.lock().await.as_ref() {
+                if is_notebook && let Ok(cells) = Notebook::parse(&state.text) {
+                    texts.extend(cells.cell_texts(&uri));
+                }
+                if overlay {
+                    texts.insert(uri, state.text.clone());
+                }
+            }
+        }
+        texts
+    }
+
+    /// Current text of the file at `path`: its overlay text if `uri` is an overlay
+    /// document, else the file on disk, refused on the same grounds as opening it
+    /// (too large or binary) and decoded the same way.
+    pub async fn current_text(&self, uri: &str, path: &Path) -> Result<String> {
+        if let Some(document) = self.lookup(uri)
+            && document.info().overlay
+            && let Some(state) = document.state.lock().await.as_ref()
+        {
+            return Ok(state.text.clone());
+        }
+        let metadata = fs::metadata(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(self.read_text(path, &metadata).await?.0)
+    }
+
+    /// The warning for an open document whose file is not UTF-8: the server was sent
+    /// decoded text, which positions refer to.
+    pub async fn decoding_note(&self, uri: &str) -> Option<String> {
+        let document = self.lookup(uri)?;
+        let slot = document.state.lock().await;
+        slot.as_ref()?.note.clone()
+    }
+
+    /// Re-opens every tracked document on a freshly started server.
+    ///
+    /// Used after the language server was restarted: the new process knows nothing
+    /// about previously opened documents, so each one is sent again with `didOpen`
+    /// from its current on-disk content, or its overlay text. Documents that can no
+    /// longer be read are forgotten.
+    ///
+    /// `swap` runs before any other sync may proceed, so the caller can install the
+    /// new bridge without a tool syncing against it half-replayed.
+    pub async fn reopen_all(&self, lsp: &LspBridge, swap: impl FnOnce()) -> Result<()> {
+        let _gate = self.gate.write().await;
+        for (uri, document) in self.snapshot() {
+            let mut slot = document.state.lock().await;
+            let Some(state) = slot.as_ref() else {
+                continue;
+            };
+            let reopened = async {
+                if state.overlay.is_some() {
+                    let path = uri_to_file_path(&uri)?;
+                    self.send_did_open(
+                        lsp,
+                        &uri,
+                        lsp.language_id(&path, &state.text),
+                        1,
+                        &state.text,
+                    )
+                    .await?;
+                    return anyhow::Ok(DocumentState {
+                        version: 1,
+                        mtime: state.mtime,
+                        text: state.text.clone(),
+                        overlay: state.overlay,
+                        note: None,
+                    });
+                }
+                let path = uri_to_path(&uri)?;
+                let metadata = fs::metadata(&path).await?;
+                let (text, note) = self.read_text(&path, &metadata).await?;
+                let version = 1;
+                self.send_did_open(lsp, &uri, lsp.language_id(&path, &text), version, &text)
+                    .await?;
+                anyhow::Ok(DocumentState {
+                    version,
+                    mtime: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    text,
+                    overlay: None,
+                    note,
+                })
+            }
+            .await;
+
+            match reopened {
+                Ok(state) => document.store(&mut slot, Some(state)),
+                Err(err) => {
+                    tracing::debug!(?err, %uri, "Dropping document that could not be reopened");
+                    document.store(&mut slot, None);
+                    drop(slot);
+                    self.forget(&uri, &document);
+                }
+            }
+        }
+        swap();
         Ok(())
     }
 
-    pub async fn close_all(&mut self, lsp: &mut LspBridge) -> Result<()> {
-        for uri in self.open.keys().cloned().collect::<Vec<_>>() {
-            let _ = self.send_did_close(lsp, &uri).await;
+    /// Closes documents that have not been used within the idle timeout.
+    ///
+    /// Overlay documents are kept, since their content exists only here, and so are
+    /// documents in the middle of a sync. Returns the number of documents closed.
+    pub async fn evict_idle(&self, lsp: &LspBridge) -> usize {
+        let Some(timeout) = self.limits.idle_timeout() else {
+            return 0;
+        };
+        let _gate = self.gate.read().await;
+        let mut closed = 0;
+        for uri in self.idle_documents(Instant::now(), timeout) {
+            tracing::debug!("Closing idle document: {}", uri);
+            if self.evict(lsp, &uri).await {
+                closed += 1;
+            }
+        }
+        closed
+    }
+
+    /// Closes least recently used documents until the cap is met, sparing `keep`.
+    ///
+    /// Callers hold the gate shared.
+    async fn enforce_cap(&self, lsp: &LspBridge, keep: &str) {
+        for uri in self.over_cap(keep) {
+            tracing::debug!("Closing least recently used document: {}", uri);
+            self.evict(lsp, &uri).await;
+        }
+    }
+
+    /// Closes a disk-backed document unless another task is syncing it.
+    async fn evict(&self, lsp: &LspBridge, uri: &str) -> bool {
+        let Some(document) = self.lookup(uri) else {
+            return false;
+        };
+        let Ok(mut slot) = document.state.try_lock() else {
+            return false;
+        };
+        let Some(state) = slot.as_ref().filter(|state| state.overlay.is_none()) else {
+            return false;
+        };
+        if let Err(err) = self.send_did_close(lsp, uri, &state.text).await {
+            tracing::debug!(?err, %uri, "Failed to close evicted document");
+        }
+        document.store(&mut slot, None);
+        drop(slot);
+        self.forget(uri, &document);
+        true
+    }
+
+    /// Drops `session`'s claims on its documents. Returns the number closed.
+    ///
+    /// Documents no other session uses are closed. Overlays `session` owns that
+    /// other sessions still use revert to the file on disk, or are closed if there
+    /// is no readable file.
+    pub async fn release_session(&self, lsp: &LspBridge, session: SessionId) -> usize {
+        let _gate = self.gate.read().await;
+        let mut closed = 0;
+        for (uri, document) in self.snapshot() {
+            let mut slot = document.state.lock().await;
+            let unused = {
+                let mut sessions = document.sessions();
+                if !sessions.remove(&session) {
+                    continue;
+                }
+                sessions.is_empty()
+            };
+            if let Some(state) = slot.as_ref() {
+                let owned = state.overlay == Some(session);
+                let reverted = if owned && !unused {
+                    self.revert_overlay(lsp, &uri, state).await
+                } else {
+                    None
+                };
+                if let Some(state) = reverted {
+                    document.store(&mut slot, Some(state));
+                } else if owned || unused {
+                    tracing::debug!("Closing document released by session {session}: {uri}");
+                    if let Err(err) = self.send_did_close(lsp, &uri, &state.text).await {
+                        tracing::debug!(?err, %uri, "Failed to close released document");
+                    }
+                    document.store(&mut slot, None);
+                    closed += 1;
+                }
+            }
+            drop(slot);
+            self.forget(&uri, &document);
+        }
+        closed
+    }
+
+    /// Ends `session`'s overlay of `uri`: the document reverts to the file on disk, or
+    /// is closed if there is no readable file. Does nothing if `session` does not own
+    /// an overlay of `uri`.
+    pub async fn discard_overlay(&self, lsp: &LspBridge, uri: &str, session: SessionId) {
+        let _gate = self.gate.read().await;
+        let Some(document) = self.lookup(uri) else {
+            return;
+        };
+        let mut slot = document.state.lock().await;
+        if let Some(state) = slot.take_if(|state| state.overlay == Some(session)) {
+            let reverted = self.revert_overlay(lsp, uri, &state).await;
+            if reverted.is_none() {
+                if let Err(err) = self.send_did_close(lsp, uri, &state.text).await {
+                    tracing::debug!(?err, %uri, "Failed to close discarded overlay");
+                }
+                document.sessions().remove(&session);
+            }
+            document.store(&mut slot, reverted);
+        }
+        drop(slot);
+        self.forget(uri, &document);
+    }
+
+    /// Replaces an overlay's text on the server with the file on disk. Returns the
+    /// new disk-backed state, or `None` if the file cannot be used.
+    async fn revert_overlay(
+        &self,
+        lsp: &LspBridge,
+        uri: &str,
+        overlay: &DocumentState,
+    ) -> Option<DocumentState> {
+        let reverted = async {
+            let path = uri_to_file_path(uri)?;
+            let metadata = fs::metadata(&path).await?;
+            let (text, note) = self.read_text(&path, &metadata).await?;
+            let version = overlay.version + 1;
+            self.send_did_change(lsp, uri, version, &overlay.text, &text)
+                .await?;
+            anyhow::Ok(DocumentState {
+                version,
+                mtime: metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                text,
+                overlay: None,
+                note,
+            })
+        }
+        .await;
+        reverted
+            .inspect_err(|err| tracing::debug!(?err, %uri, "Overlay cannot revert to disk"))
+            .ok()
+    }
+
+    fn idle_documents(&self, now: Instant, timeout: Duration) -> Vec<String> {
+        self.infos()
+            .into_iter()
+            .filter(|(_, info)| {
+                info.open && !info.overlay && now.duration_since(info.last_used) >= timeout
+            })
+            .map(|(uri, _)| uri)
+            .collect()
+    }
+
+    /// Disk-backed documents to close, oldest first, to get within `maxOpen`.
+    fn over_cap(&self, keep: &str) -> Vec<String> {
+        let infos: Vec<_> = self
+            .infos()
+            .into_iter()
+            .filter(|(_, info)| info.open)
+            .collect();
+        if self.limits.max_open == 0 || infos.len() <= self.limits.max_open {
+            return Vec::new();
         }
-        self.open.clear();
+        let excess = infos.len() - self.limits.max_open;
+        let mut candidates: Vec<_> = infos
+            .into_iter()
+            .filter(|(uri, info)| !info.overlay && uri != keep)
+            .collect();
+        candidates.sort_by_key(|(_, info)| info.last_used);
+        candidates
+            .into_iter()
+            .take(excess)
+            .map(|(uri, _)| uri)
+            .collect()
+    }
+
+    /// Whether `uri` is open on the server.
+    pub fn is_open(&self, uri: &str) -> bool {
+        self.lookup(uri)
+            .is_some_and(|document| document.info().open)
+    }
+
+    /// Number of documents currently open on the server.
+    pub fn open_count(&self) -> usize {
+        self.infos().iter().filter(|(_, info)| info.open).count()
+    }
+
+    pub async fn close_all(&self, lsp: &LspBridge) -> Result<()> {
+        let _gate = self.gate.write().await;
+        for (uri, document) in self.snapshot() {
+            let mut slot = document.state.lock().await;
+            if let Some(state) = slot.as_ref() {
+                let _ = self.send_did_close(lsp, &uri, &state.text).await;
+                document.store(&mut slot, None);
+            }
+        }
+        self.entries().clear();
         Ok(())
     }
 
+    fn entries(&self) -> MutexGuard<'_, HashMap<String, Arc<Document>>> {
+        self.documents.lock().expect("document map lock poisoned")
+    }
+
+    /// The entry for `uri`, created if it is not tracked yet.
+    fn document(&self, uri: &str) -> Arc<Document> {
+        self.entries()
+            .entry(uri.to_string())
+            .or_insert_with(|| Arc::new(Document::new()))
+            .clone()
+    }
+
+    fn lookup(&self, uri: &str) -> Option<Arc<Document>> {
+        self.entries().get(uri).cloned()
+    }
+
+    fn snapshot(&self) -> Vec<(String, Arc<Document>)> {
+        self.entries()
+            .iter()
+            .map(|(uri, document)| (uri.clone(), document.clone()))
+            .collect()
+    }
+
+    fn infos(&self) -> Vec<(String, DocumentInfo)> {
+        self.entries()
+            .iter()
+            .map(|(uri, document)| (uri.clone(), *document.info()))
+            .collect()
+    }
+
+    /// Drops the entry for `uri` if the document is not open and nobody else holds it.
+    fn forget(&self, uri: &str, document: &Arc<Document>) {
+        let mut entries = self.entries();
+        // One reference is the map's, one the caller's; any other means a waiter
+        if Arc::strong_count(document) == 2 && !document.info().open {
+            entries.remove(uri);
+        }
+    }
+
     async fn send_did_open(
-        &mut self,
-        lsp: &mut LspBridge,
+        &self,
+        lsp: &LspBridge,
         uri: &str,
         language_id: &str,
         version: i32,
         text: &str,
     ) -> Result<()> {
-        let params = json!({
-            "textDocument": {
-                "uri": uri,
-                "languageId": language_id,
-                "version": version,
-                "text": text,
-            }
-        });
-        lsp.notify("textDocument/didOpen", params).await
+        if let Some(notebook) = synced_notebook(lsp, uri, text)? {
+            let params = notebook.did_open(uri, version);
+            return lsp.notify("notebookDocument/didOpen", params).await;
+        }
+        if !lsp.server_capabilities().open_close() {
+            return Ok(());
+        }
+        let params = DidOpenTextDocumentParams {
+            text_document: TextDocumentItem {
+                uri: uri.to_string(),
+                language_id: language_id.to_string(),
+                version,
+                text: text.to_string(),
+            },
+        };
+        lsp.send::<DidOpenTextDocument>(&params).await
     }
 
+    /// Sends `didChange` in the form the server's `TextDocumentSyncKind` asks for.
+    ///
+    /// Incremental servers get a single ranged change covering everything between
+    /// the common prefix and suffix of `previous` and `text`.
     async fn send_did_change(
-        &mut self,
-        lsp: &mut LspBridge,
+        &self,
+        lsp: &LspBridge,
         uri: &str,
         version: i32,
+        previous: &str,
         text: &str,
     ) -> Result<()> {
-        let params = json!({
-            "textDocument": {
-                "uri": uri,
-                "version": version,
+        if let Some(notebook) = synced_notebook(lsp, uri, text)? {
+            let params = notebook.did_change(&Notebook::parse(previous)?, uri, version);
+            return lsp.notify("notebookDocument/didChange", params).await;
+        }
+        let change = match lsp.server_capabilities().text_document_sync_kind() {
+            TextDocumentSyncKind::None => return Ok(()),
+            TextDocumentSyncKind::Full => TextDocumentContentChangeEvent {
+                range: None,
+                text: text.to_string(),
             },
-            "contentChanges": [{
-                "text": text
-            }]
-        });
-        lsp.notify("textDocument/didChange", params).await
+            TextDocumentSyncKind::Incremental => {
+                ranged_change(previous, text, lsp.position_encoding())
+            }
+        };
+        let params = DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.to_string(),
+                version,
+            },
+            content_changes: vec![change],
+        };
+        lsp.send::<DidChangeTextDocument>(&params).await
+    }
+
+    /// Reads a document's text, refusing files the server should never see: larger
+    /// than `maxFileBytes` or binary. Files that are not UTF-8 are decoded (see
+    /// [`crate::encoding`]) and come with a note saying so.
+    async fn read_text(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+    ) -> Result<(String, Option<String>)> {
+        check_size(path, metadata.len(), self.limits.max_file_bytes)?;
+        let bytes = fs::read(path)
+            .await
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        check_size(path, bytes.len() as u64, self.limits.max_file_bytes)?;
+        let decoded = encoding::decode_text(&bytes).ok_or_else(|| {
+            anyhow!(
+                "document rejected: {} looks like a binary file (it contains NUL bytes)",
+                path.display()
+            )
+        })?;
+        if !decoded.is_utf8() {
+            tracing::debug!(path = %path.display(), encoding = decoded.encoding.name(), lossy = decoded.lossy, "Decoded non-UTF-8 document");
+        }
+        let note = decoded.note(path);
+        Ok((decoded.text, note))
     }
 
-    async fn send_did_close(&mut self, lsp: &mut LspBridge, uri: &str) -> Result<()> {
-        let params = json!({
-            "textDocument": {
-                "uri": uri
+    /// Tells the server the file on disk now has `text`, if it asked for `didSave`.
+    ///
+    /// Sent after changes read from disk, never for overlays, which are unsaved.
+    async fn send_did_save(&self, lsp: &LspBridge, uri: &str, text: &str) -> Result<()> {
+        if synced_notebook(lsp, uri, text)?.is_some() {
+            if !lsp.server_capabilities().saves_notebooks() {
+                return Ok(());
             }
-        });
-        lsp.notify("textDocument/didClose", params).await
+            let params = json!({ "notebookDocument": { "uri": uri } });
+            return lsp.notify("notebookDocument/didSave", params).await;
+        }
+        let Some(include_text) = lsp.server_capabilities().save_include_text() else {
+            return Ok(());
+        };
+        let params = DidSaveTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri),
+            text: include_text.then(|| text.to_string()),
+        };
+        lsp.send::<DidSaveTextDocument>(&params).await
     }
+
+    /// `text` is the content last sent, which names a notebook's cells. Like
+    /// `didOpen`, skipped for servers that do not want either.
+    async fn send_did_close(&self, lsp: &LspBridge, uri: &str, text: &str) -> Result<()> {
+        if let Some(notebook) = synced_notebook(lsp, uri, text)? {
+            return lsp
+                .notify("notebookDocument/didClose", notebook.did_close(uri))
+                .await;
+        }
+        if !lsp.server_capabilities().open_close() {
+            return Ok(());
+        }
+        let params = DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier::new(uri),
+        };
+        lsp.send::<DidCloseTextDocument>(&params).await
+    }
+}
+
+/// The document a tool's URI is synced as: the notebook, for a notebook cell.
+fn synced_uri(lsp: &LspBridge, uri: &str) -> Result<String> {
+    let Some((notebook, _)) = notebook::split_cell_uri(uri) else {
+        return Ok(uri.to_string());
+    };
+    if !lsp.server_capabilities().syncs_notebooks() {
+        bail!("language server does not support notebook documents, so {uri} cannot be opened");
+    }
+    Ok(notebook)
+}
+
+/// `text` parsed as a notebook, if `uri` is one and the server syncs notebooks.
+fn synced_notebook(lsp: &LspBridge, uri: &str, text: &str) -> Result<Option<Notebook>> {
+    if !notebook::is_notebook(uri) || !lsp.server_capabilities().syncs_notebooks() {
+        return Ok(None);
+    }
+    Notebook::parse(text)
+        .map(Some)
+        .with_context(|| format!("failed to parse notebook {uri}"))
+}
+
+/// A file's metadata, or `None` if it does not exist.
+async fn file_metadata(path: &Path) -> Result<Option<Metadata>> {
+    match fs::metadata(path).await {
+        Ok(metadata) => Ok(Some(metadata)),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => {
+            Err(err).with_context(|| format!("failed to read metadata for {}", path.display()))
+        }
+    }
+}
+
+/// Bytes inspected by the binary-file heuristic.
+const BINARY_SNIFF_LEN: usize = 8192;
+
+fn check_size(path: &Path, len: u64, max: u64) -> Result<()> {
+    if max > 0 && len > max {
+        bail!(
+            "document rejected: {} is {len} bytes, over the {max}-byte limit (documents.maxFileBytes)",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Treats content with a NUL byte near the start as binary, like git does.
+pub(crate) fn looks_binary(bytes: &[u8]) -> bool {
+    bytes[..bytes.len().min(BINARY_SNIFF_LEN)].contains(&0)
 }
 
 /// Checks if timestamp `a` is newer than timestamp `b`.
@@ -156,3 +991,242 @@ fn is_newer(a: SystemTime, b: SystemTime) -> Result<bool> {
         .map(|d| d.as_nanos() > 0)
         .unwrap_or(false))
 }
+
+/// Builds a `TextDocumentContentChangeEvent` replacing the part of `previous` that
+/// differs from `text`.
+///
+/// Positions use `encoding` and count lines by `\n`, like [`crate::edits`]. The
+/// changed region never starts or ends inside a `\r\n` pair.
+fn ranged_change(
+    previous: &str,
+    text: &str,
+    encoding: PositionEncoding,
+) -> TextDocumentContentChangeEvent {
+    let mut prefix = previous
+        .char_indices()
+        .zip(text.chars())
+        .find(|((_, old), new)| old != new)
+        .map_or(previous.len().min(text.len()), |((offset, _), _)| offset);
+    if previous[..prefix].ends_with('\r') {
+        prefix -= 1;
+    }
+
+    // Comparing only what follows the prefix keeps the two regions disjoint
+    let mut suffix: usize = previous[prefix..]
+        .chars()
+        .rev()
+        .zip(text[prefix..].chars().rev())
+        .take_while(|(old, new)| old == new)
+        .map(|(ch, _)| ch.len_utf8())
+        .sum();
+    let suffix_start = previous.len() - suffix;
+    if previous[suffix_start..].starts_with('\n') && previous[..suffix_start].ends_with('\r') {
+        suffix -= 1;
+    }
+
+    let end = previous.len() - suffix;
+    TextDocumentContentChangeEvent {
+        range: Some(Range {
+            start: position_at(previous, prefix, encoding),
+            end: position_at(previous, end, encoding),
+        }),
+        text: text[prefix..text.len() - suffix].to_string(),
+    }
+}
+
+/// The LSP position of byte offset `byte` in `text`.
+fn position_at(text: &str, byte: usize, encoding: PositionEncoding) -> Position {
+    let before = &text[..byte];
+    let line = before.matches('\n').count();
+    let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+    let character = byte_to_column(&text[line_start..], byte - line_start, encoding);
+    Position {
+        line: line as u32,
+        character,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies a change produced by `ranged_change` the way a server would.
+    fn apply(previous: &str, change: &TextDocumentContentChangeEvent) -> String {
+        let edit = json!({ "range": change.range, "newText": change.text });
+        crate::edits::apply_edits_to_text(previous, &[edit], PositionEncoding::Utf16).unwrap()
+    }
+
+    fn manager(limits: DocumentLimits, docs: &[(&str, u64, bool)]) -> (DocumentManager, Instant) {
+        let now = Instant::now() + Duration::from_secs(1000);
+        let manager = DocumentManager::with_limits(limits);
+        for (uri, age_secs, overlay) in docs {
+            let document = manager.document(uri);
+            let state = DocumentState {
+                version: 1,
+                mtime: SystemTime::UNIX_EPOCH,
+                text: String::new(),
+                overlay: overlay.then(SessionId::next),
+                note: None,
+            };
+            document.store(&mut document.state.try_lock().unwrap(), Some(state));
+            document.info().last_used = now - Duration::from_secs(*age_secs);
+        }
+        (manager, now)
+    }
+
+    #[test]
+    fn cap_evicts_least_recently_used_disk_documents() {
+        let limits = DocumentLimits {
+            max_open: 2,
+            ..DocumentLimits::unlimited()
+        };
+        let (manager, _) = manager(
+            limits,
+            &[
+                ("a", 30, false),
+                ("b", 20, true),
+                ("c", 10, false),
+                ("d", 40, false),
+            ],
+        );
+        let mut evicted = manager.over_cap("d");
+        evicted.sort();
+        assert_eq!(evicted, ["a", "c"]);
+    }
+
+    #[test]
+    fn closed_documents_are_not_counted_and_get_forgotten() {
+        let limits = DocumentLimits {
+            max_open: 1,
+            ..DocumentLimits::unlimited()
+        };
+        let (manager, _) = manager(limits, &[("a", 10, false), ("b", 20, false)]);
+        let document = manager.lookup("b").unwrap();
+        document.store(&mut document.state.try_lock().unwrap(), None);
+        assert_eq!(manager.open_count(), 1);
+        assert!(manager.over_cap("a").is_empty());
+
+        manager.forget("b", &document);
+        assert!(manager.lookup("b").is_none());
+        // Still open, so kept
+        manager.forget("a", &manager.lookup("a").unwrap());
+        assert!(manager.lookup("a").is_some());
+    }
+
+    #[test]
+    fn idle_documents_skip_overlays() {
+        let (manager, now) = manager(
+            DocumentLimits::default(),
+            &[
+                ("old", 1000, false),
+                ("overlay", 1000, true),
+                ("new", 5, false),
+            ],
+        );
+        assert_eq!(
+            manager.idle_documents(now, Duration::from_secs(900)),
+            ["old"]
+        );
+    }
+
+    #[tokio::test]
+    async fn missing_files_have_no_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("gone.rs");
+        assert!(file_metadata(&path).await.unwrap().is_none());
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+        assert!(file_metadata(&path).await.unwrap().is_some());
+
+        let err = anyhow::Error::from(DocumentMissing { path });
+        assert!(err.is::<DocumentMissing>());
+        assert!(err.to_string().starts_with("file no longer exists"));
+    }
+
+    /// Whether syncing a file sends the mock anything it answers with diagnostics,
+    /// which it does for `didOpen` and `didChange`.
+    async fn publishes_on_open(capabilities: Value) -> bool {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {}\n").unwrap();
+        let uri = url::Url::from_file_path(&path).unwrap().to_string();
+        let mock = crate::testing::MockServer::new().capabilities(capabilities);
+        let mut lsp = LspBridge::from_mock(mock, dir.path().to_path_buf());
+        lsp.initialize().await.unwrap();
+        let mut notifications = lsp.subscribe();
+
+        let manager = DocumentManager::new();
+        manager.ensure_open(&lsp, &uri).await.unwrap();
+        assert!(manager.is_open(&uri));
+        // The mock answers in order, so anything the open caused has arrived by now
+        lsp.request(
+            "textDocument/definition",
+            json!({"textDocument": {"uri": uri}, "position": {"line": 0, "character": 3}}),
+        )
+        .await
+        .unwrap();
+        std::iter::from_fn(|| notifications.try_recv().ok())
+            .any(|notification| notification.method == "textDocument/publishDiagnostics")
+    }
+
+    #[tokio::test]
+    async fn did_open_follows_the_open_close_option() {
+        let sync = |sync: Value| json!({"textDocumentSync": sync, "definitionProvider": true});
+        assert!(publishes_on_open(sync(json!(1))).await);
+        assert!(publishes_on_open(sync(json!({"openClose": true, "change": 1}))).await);
+        assert!(!publishes_on_open(sync(json!({"change": 1}))).await);
+    }
+
+    #[test]
+    fn oversized_and_binary_files_are_detected() {
+        let path = Path::new("/ws/big.bin");
+        assert!(check_size(path, 10, 10).is_ok());
+        assert!(check_size(path, 11, 0).is_ok());
+        let err = check_size(path, 11, 10).unwrap_err().to_string();
+        assert!(err.contains("maxFileBytes"), "{err}");
+
+        assert!(looks_binary(b"\x7fELF\x02\x01\x00"));
+        assert!(!looks_binary("plain text, é".as_bytes()));
+        let mut late_nul = vec![b'a'; BINARY_SNIFF_LEN];
+        late_nul.push(0);
+        assert!(!looks_binary(&late_nul));
+    }
+
+    #[test]
+    fn ranged_change_covers_only_the_edit() {
+        let previous = "fn main() {\n    let x = 1;\n}\n";
+        let text = "fn main() {\n    let x = 42;\n}\n";
+        let change = ranged_change(previous, text, PositionEncoding::Utf16);
+        assert_eq!(
+            change.range,
+            Some(Range {
+                start: Position {
+                    line: 1,
+                    character: 12
+                },
+                end: Position {
+                    line: 1,
+                    character: 13
+                },
+            })
+        );
+        assert_eq!(change.text, "42");
+        assert_eq!(apply(previous, &change), text);
+    }
+
+    #[test]
+    fn ranged_change_round_trips() {
+        let cases = [
+            ("", "hello\n"),
+            ("hello\n", ""),
+            ("aaa", "aaaa"),
+            ("a\r\nb", "a\nb"),
+            ("a\nb", "a\r\nb"),
+            ("é😀x", "é😁x"),
+            ("line1\nline2\n", "line1\ninserted\nline2\n"),
+        ];
+        for (previous, text) in cases {
+            let change = ranged_change(previous, text, PositionEncoding::Utf16);
+            assert_eq!(apply(previous, &change), text, "{previous:?} -> {text:?}");
+        }
+    }
+}
@@ -0,0 +1,781 @@
+//! Applying LSP `WorkspaceEdit`s to files on disk.
+//!
+//! Supports both the `changes` map and `documentChanges` (text document edits plus
+//! create/rename/delete resource operations). Text edit positions are interpreted in
+//! the position encoding negotiated with the server (UTF-16 unless agreed otherwise).
+//!
+//! An edit is first worked out in memory by [`preview_workspace_edit`], so the tools
+//! can show it as a diff, and then written by [`write_changes`] all or nothing: new
+//! contents are staged in temp files next to their targets, the originals are moved
+//! aside as backups, and the temp files renamed into place. If any step fails, every
+//! file is restored from its backup, so a failed edit never leaves the workspace half
+//! changed.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_json::Value;
+
+use crate::diff::unified_diff;
+use crate::encoding;
+use crate::position::{PositionEncoding, column_to_byte};
+use crate::utils::{path_to_uri, uri_to_file_path};
+
+/// Applies a `WorkspaceEdit` and returns the URIs of all files it changed.
+///
+/// The edit is applied entirely or not at all: see [`write_changes`].
+pub fn apply_workspace_edit(edit: &Value, encoding: PositionEncoding) -> Result<Vec<String>> {
+    let changes = preview_workspace_edit(edit, encoding, &HashMap::new())?;
+    write_changes(&changes)?;
+    Ok(changes
+        .files
+        .iter()
+        .filter_map(|change| path_to_uri(&change.path).ok())
+        .collect())
+}
+
+/// What a `WorkspaceEdit` does to the files on disk.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WorkspaceChanges {
+    /// Files whose content changes, in the order the edit first touches them.
+    pub files: Vec<FileChange>,
+    /// Directories the edit deletes or renames away; removed once their files are.
+    pub removed_dirs: Vec<PathBuf>,
+}
+
+/// What a `WorkspaceEdit` does to one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub path: PathBuf,
+    /// Content before the edit; `None` if the edit creates the file.
+    pub before: Option<String>,
+    /// Content after the edit; `None` if the edit deletes the file.
+    pub after: Option<String>,
+}
+
+impl FileChange {
+    /// The change as a unified diff, with paths under `workspace` relative to it.
+    pub fn diff(&self, workspace: &Path) -> String {
+        let name = match self.path.strip_prefix(workspace) {
+            Ok(relative) => relative.display().to_string(),
+            Err(_) => self.path.display().to_string(),
+        };
+        let label = |prefix: &str, content: &Option<String>| match content {
+            Some(_) => format!("{prefix}/{name}"),
+            None => "/dev/null".to_string(),
+        };
+        let (old_label, new_label) = (label("a", &self.before), label("b", &self.after));
+        let diff = unified_diff(
+            &old_label,
+            &new_label,
+            self.before.as_deref().unwrap_or_default(),
+            self.after.as_deref().unwrap_or_default(),
+        );
+        if diff.is_empty() {
+            // Creating or deleting an empty file changes no lines
+            return format!("--- {old_label}\n+++ {new_label}\n");
+        }
+        diff
+    }
+}
+
+/// Works out the files `edit` would change, reading their current contents and
+/// applying it in memory; nothing is written. Files with an entry in `overlays` (text
+/// by URI) start from that text instead of the disk. Fails if any operation cannot
+/// apply, e.g. an edit to a missing file.
+pub fn preview_workspace_edit(
+    edit: &Value,
+    encoding: PositionEncoding,
+    overlays: &HashMap<String, String>,
+) -> Result<WorkspaceChanges> {
+    let mut files = VirtualFiles {
+        overlays: overlays
+            .iter()
+            .filter_map(|(uri, text)| Some((uri_to_file_path(uri).ok()?, text.clone())))
+            .collect(),
+        ..VirtualFiles::default()
+    };
+    if let Some(document_changes) = edit.get("documentChanges").and_then(|v| v.as_array()) {
+        for change in document_changes {
+            match change.get("kind").and_then(|k| k.as_str()) {
+                Some("create") => files.create(change)?,
+                Some("rename") => files.rename(change)?,
+                Some("delete") => files.delete(change)?,
+                Some(other) => bail!("unsupported resource operation '{other}'"),
+                None => {
+                    let uri = change
+                        .pointer("/textDocument/uri")
+                        .and_then(|v| v.as_str())
+                        .ok_or_else(|| anyhow!("textDocumentEdit.textDocument.uri missing"))?;
+                    files.edit(uri, change.get("edits"), encoding)?;
+                }
+            }
+        }
+    } else if let Some(changes) = edit.get("changes").and_then(|v| v.as_object()) {
+        for (uri, edits) in changes {
+            files.edit(uri, Some(edits), encoding)?;
+        }
+    }
+    Ok(files.changes())
+}
+
+/// The files an edit touches, as read from disk and as changed so far.
+#[derive(Default)]
+struct VirtualFiles {
+    /// Unsaved texts that stand in for the disk.
+    overlays: HashMap<PathBuf, String>,
+    /// Contents on disk, by path, in the order the edit touches them.
+    original: Vec<(PathBuf, Option<String>)>,
+    current: BTreeMap<PathBuf, Option<String>>,
+    /// Directories deleted or renamed away.
+    removed_dirs: Vec<PathBuf>,
+    /// Files read that are not UTF-8, with the encoding they were decoded from.
+    not_utf8: HashMap<PathBuf, &'static str>,
+}
+
+impl VirtualFiles {
+    fn read(&mut self, path: &Path) -> Option<String> {
+        if let Some(content) = self.current.get(path) {
+            return content.clone();
+        }
+        let decoded = path
+            .is_file()
+            .then(|| encoding::read_file_sync(path).ok())
+            .flatten();
+        if let Some(decoded) = &decoded
+            && !decoded.is_utf8()
+        {
+            self.not_utf8
+                .insert(path.to_path_buf(), decoded.encoding.name());
+        }
+        let content = match self.overlays.get(path) {
+            Some(text) => Some(text.clone()),
+            None => decoded.map(|decoded| decoded.text),
+        };
+        self.original.push((path.to_path_buf(), content.clone()));
+        self.current.insert(path.to_path_buf(), content.clone());
+        content
+    }
+
+    fn write(&mut self, path: &Path, content: Option<String>) {
+        self.read(path);
+        self.current.insert(path.to_path_buf(), content);
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.is_dir() && !self.current.contains_key(path)
+    }
+
+    /// Refuses to carry the decoded text of a file that is not UTF-8 into a write,
+    /// which would silently change its encoding.
+    fn ensure_utf8(&self, path: &Path) -> Result<()> {
+        match self.not_utf8.get(path) {
+            Some(encoding) => bail!(
+                "cannot edit {}: it is encoded as {encoding}, and edits are only written as UTF-8",
+                path.display()
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn exists(&mut self, path: &Path) -> bool {
+        self.is_dir(path) || self.read(path).is_some()
+    }
+
+    /// Files under the directory `dir`, on disk or created by the edit, that still
+    /// exist.
+    fn files_under(&mut self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(next) = pending.pop() {
+            let entries = std::fs::read_dir(&next)
+                .with_context(|| format!("failed to read {}", next.display()))?;
+            for entry in entries {
+                let path = entry?.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    found.push(path);
+                }
+            }
+        }
+        found.extend(
+            self.current
+                .keys()
+                .filter(|path| path.starts_with(dir))
+                .cloned()
+                .collect::<Vec<_>>(),
+        );
+        found.sort();
+        found.dedup();
+        found.retain(|path| self.read(path).is_some());
+        Ok(found)
+    }
+
+    fn edit(&mut self, uri: &str, edits: Option<&Value>, encoding: PositionEncoding) -> Result<()> {
+        let edits = edits
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow!("edits for {uri} must be an array"))?;
+        let path = uri_to_file_path(uri)?;
+        let text = self
+            .read(&path)
+            .ok_or_else(|| anyhow!("document path does not exist: {}", path.display()))?;
+        self.ensure_utf8(&path)?;
+        let updated = apply_edits_to_text(&text, edits, encoding)?;
+        self.write(&path, Some(updated));
+        Ok(())
+    }
+
+    fn create(&mut self, op: &Value) -> Result<()> {
+        let path = uri_to_file_path(op_uri(op, "uri")?)?;
+        if self.exists(&path) {
+            if option(op, "ignoreIfExists") {
+                return Ok(());
+            }
+            if !option(op, "overwrite") {
+                bail!("cannot create {}: file exists", path.display());
+            }
+        }
+        self.write(&path, Some(String::new()));
+        Ok(())
+    }
+
+    fn rename(&mut self, op: &Value) -> Result<()> {
+        let old_path = uri_to_file_path(op_uri(op, "oldUri")?)?;
+        let new_path = uri_to_file_path(op_uri(op, "newUri")?)?;
+        if self.exists(&new_path) {
+            if option(op, "ignoreIfExists") {
+                return Ok(());
+            }
+            if !option(op, "overwrite") {
+                bail!("cannot rename to {}: file exists", new_path.display());
+            }
+        }
+        if self.is_dir(&old_path) {
+            for path in self.files_under(&old_path)? {
+                let content = self.read(&path);
+                self.ensure_utf8(&path)?;
+                let moved = new_path.join(path.strip_prefix(&old_path)?);
+                self.write(&path, None);
+                self.write(&moved, content);
+            }
+            self.removed_dirs.push(old_path);
+            return Ok(());
+        }
+        let content = self
+            .read(&old_path)
+            .ok_or_else(|| anyhow!("cannot rename {}: file does not exist", old_path.display()))?;
+        self.ensure_utf8(&old_path)?;
+        self.write(&old_path, None);
+        self.write(&new_path, Some(content));
+        Ok(())
+    }
+
+    fn delete(&mut self, op: &Value) -> Result<()> {
+        let path = uri_to_file_path(op_uri(op, "uri")?)?;
+        if !self.exists(&path) {
+            if option(op, "ignoreIfNotExists") {
+                return Ok(());
+            }
+            bail!("cannot delete {}: file does not exist", path.display());
+        }
+        if self.is_dir(&path) {
+            let files = self.files_under(&path)?;
+            if !files.is_empty() && !option(op, "recursive") {
+                bail!("cannot delete {}: directory not empty", path.display());
+            }
+            for file in files {
+                self.write(&file, None);
+            }
+            self.removed_dirs.push(path);
+            return Ok(());
+        }
+        self.write(&path, None);
+        Ok(())
+    }
+
+    /// The files whose content differs from disk, in the order first touched.
+    fn changes(self) -> WorkspaceChanges {
+        let mut current = self.current;
+        let files = self
+            .original
+            .into_iter()
+            .filter_map(|(path, before)| {
+                let after = current.remove(&path)?;
+                (before != after).then_some(FileChange {
+                    path,
+                    before,
+                    after,
+                })
+            })
+            .collect();
+        WorkspaceChanges {
+            files,
+            removed_dirs: self.removed_dirs,
+        }
+    }
+}
+
+/// Distinguishes the temp and backup files of concurrent writes.
+static STAGING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A step of [`write_changes`] that rollback undoes.
+enum Step {
+    /// A directory created for a new file.
+    CreatedDir(PathBuf),
+    /// New content written to `temp`, not yet renamed into place.
+    Staged(PathBuf),
+    /// The original `path` moved aside to `backup`.
+    BackedUp { path: PathBuf, backup: PathBuf },
+    /// Staged content renamed to this path.
+    Installed(PathBuf),
+}
+
+/// Writes previewed changes to disk, all or nothing.
+///
+/// New contents are first written to temp files in their target directories (with
+/// the permissions of the files they replace), then each original is renamed to a
+/// backup and its temp file renamed into place. Only when every file is in place are
+/// the backups deleted and the emptied `removed_dirs` removed. If any step fails, the
+/// steps taken so far are undone in reverse and the error says so.
+pub fn write_changes(changes: &WorkspaceChanges) -> Result<()> {
+    let mut steps = Vec::new();
+    let result = stage(changes, &mut steps).and_then(|temps| commit(temps, &mut steps));
+    if let Err(err) = result {
+        return Err(if rollback(steps) {
+            err.context("edit not applied; all files were restored")
+        } else {
+            err.context("edit not applied, and restoring some files failed")
+        });
+    }
+    for step in steps {
+        if let Step::BackedUp { backup, .. } = step
+            && let Err(err) = std::fs::remove_file(&backup)
+        {
+            tracing::warn!(?err, backup = %backup.display(), "Failed to remove edit backup");
+        }
+    }
+    // Deepest first, so nested directories go before their parents
+    let mut dirs = changes.removed_dirs.clone();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    for dir in dirs {
+        remove_empty_dirs(&dir);
+    }
+    Ok(())
+}
+
+/// Writes every new content to a temp file beside its target; returns each changed
+/// path with its temp file, or `None` for a deleted file.
+fn stage(
+    changes: &WorkspaceChanges,
+    steps: &mut Vec<Step>,
+) -> Result<Vec<(PathBuf, Option<PathBuf>)>> {
+    let mut temps = Vec::new();
+    for change in &changes.files {
+        let path = &change.path;
+        let Some(text) = &change.after else {
+            temps.push((path.clone(), None));
+            continue;
+        };
+        let parent = path
+            .parent()
+            .ok_or_else(|| anyhow!("cannot write {}: no parent directory", path.display()))?;
+        create_dirs(parent, steps)?;
+        let temp = sibling(path, "tmp")?;
+        steps.push(Step::Staged(temp.clone()));
+        std::fs::write(&temp, text)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        if let Ok(metadata) = std::fs::metadata(path) {
+            std::fs::set_permissions(&temp, metadata.permissions())
+                .with_context(|| format!("failed to copy permissions of {}", path.display()))?;
+        }
+        temps.push((path.clone(), Some(temp)));
+    }
+    Ok(temps)
+}
+
+/// Moves each original aside and its staged content into place.
+fn commit(temps: Vec<(PathBuf, Option<PathBuf>)>, steps: &mut Vec<Step>) -> Result<()> {
+    for (path, temp) in temps {
+        if std::fs::symlink_metadata(&path).is_ok() {
+            let backup = sibling(&path, "bak")?;
+            std::fs::rename(&path, &backup)
+                .with_context(|| format!("failed to back up {}", path.display()))?;
+            steps.push(Step::BackedUp {
+                path: path.clone(),
+                backup,
+            });
+        }
+        if let Some(temp) = temp {
+            std::fs::rename(&temp, &path)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            steps.push(Step::Installed(path));
+        }
+    }
+    Ok(())
+}
+
+/// Undoes `steps` in reverse; false if anything could not be restored.
+fn rollback(steps: Vec<Step>) -> bool {
+    let mut restored = true;
+    for step in steps.into_iter().rev() {
+        let (undone, path) = match step {
+            Step::CreatedDir(dir) => (std::fs::remove_dir(&dir), dir),
+            // Already renamed into place, and undone as `Installed`
+            Step::Staged(temp) if !temp.exists() => continue,
+            Step::Staged(temp) => (std::fs::remove_file(&temp), temp),
+            Step::BackedUp { path, backup } => (std::fs::rename(&backup, &path), path),
+            Step::Installed(path) => (std::fs::remove_file(&path), path),
+        };
+        if let Err(err) = undone {
+            tracing::error!(?err, path = %path.display(), "Failed to roll back edit");
+            restored = false;
+        }
+    }
+    restored
+}
+
+/// Creates `dir` and its missing ancestors, recording each for rollback.
+fn create_dirs(dir: &Path, steps: &mut Vec<Step>) -> Result<()> {
+    let missing: Vec<&Path> = dir
+        .ancestors()
+        .take_while(|ancestor| !ancestor.exists())
+        .collect();
+    for dir in missing.into_iter().rev() {
+        std::fs::create_dir(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        steps.push(Step::CreatedDir(dir.to_path_buf()));
+    }
+    Ok(())
+}
+
+/// A hidden file next to `path` holding its staged content or backup.
+fn sibling(path: &Path, suffix: &str) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| anyhow!("cannot write {}: no file name", path.display()))?;
+    let unique = STAGING_COUNTER.fetch_add(1, Ordering::Relaxed);
+    Ok(path.with_file_name(format!(
+        ".{}.pathfinder-{}-{unique}.{suffix}",
+        name.to_string_lossy(),
+        std::process::id()
+    )))
+}
+
+/// Removes `dir` and the directories under it, as long as they hold no files.
+fn remove_empty_dirs(dir: &Path) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+            remove_empty_dirs(&entry.path());
+        }
+    }
+    if let Err(err) = std::fs::remove_dir(dir) {
+        tracing::debug!(?err, dir = %dir.display(), "Left directory of edit in place");
+    }
+}
+
+/// Applies `TextEdit[]` to `text` in memory.
+///
+/// Edits are applied back to front so earlier offsets stay valid; per the LSP spec
+/// the ranges of a single edit array never overlap.
+pub fn apply_edits_to_text(
+    text: &str,
+    edits: &[Value],
+    encoding: PositionEncoding,
+) -> Result<String> {
+    let mut resolved = edits
+        .iter()
+        .map(|edit| {
+            let range = edit
+                .get("range")
+                .ok_or_else(|| anyhow!("textEdit.range missing"))?;
+            let start = offset_at(text, range.get("start"), encoding)?;
+            let end = offset_at(text, range.get("end"), encoding)?;
+            if end < start {
+                return Err(anyhow!("textEdit range end precedes start"));
+            }
+            let new_text = edit
+                .get("newText")
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow!("textEdit.newText must be a string"))?;
+            Ok((start, end, new_text))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Stable sort keeps inserts at the same position in their original order.
+    resolved.sort_by_key(|(start, _, _)| *start);
+    let mut result = text.to_string();
+    for (start, end, new_text) in resolved.into_iter().rev() {
+        result.replace_range(start..end, new_text);
+    }
+    Ok(result)
+}
+
+/// Converts an LSP `Position` to a byte offset in `text`.
+///
+/// Positions past the end of a line clamp to the line end, and lines past the end
+/// of the document clamp to the document end, as the spec requires.
+fn offset_at(text: &str, position: Option<&Value>, encoding: PositionEncoding) -> Result<usize> {
+    let position = position.ok_or_else(|| anyhow!("range position missing"))?;
+    let coord = |key: &str| {
+        position
+            .get(key)
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| anyhow!("position.{key} must be an unsigned integer"))
+    };
+    let line = coord("line")? as usize;
+    let character = coord("character")? as u32;
+
+    let mut line_start = 0;
+    for _ in 0..line {
+        match text[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return Ok(text.len()),
+        }
+    }
+
+    let line_text = &text[line_start..];
+    let line_end = line_text
+        .find('\n')
+        .map(|i| i - usize::from(line_text[..i].ends_with('\r')))
+        .unwrap_or(line_text.len());
+
+    Ok(line_start + column_to_byte(&line_text[..line_end], character, encoding))
+}
+
+fn op_uri<'a>(op: &'a Value, key: &str) -> Result<&'a str> {
+    op.get(key)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| anyhow!("resource operation {key} must be a string"))
+}
+
+fn option(op: &Value, key: &str) -> bool {
+    op.get("options")
+        .and_then(|options| options.get(key))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PositionEncoding::Utf16;
+    use serde_json::json;
+
+    fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> Value {
+        json!({
+            "range": {
+                "start": {"line": start.0, "character": start.1},
+                "end": {"line": end.0, "character": end.1},
+            },
+            "newText": new_text,
+        })
+    }
+
+    #[test]
+    fn applies_multiple_edits_back_to_front() {
+        let text = "fn add(a: i32) {}\nfn main() { add(1); }\n";
+        let edits = [edit((0, 3), (0, 6), "sum"), edit((1, 12), (1, 15), "sum")];
+        assert_eq!(
+            apply_edits_to_text(text, &edits, Utf16).unwrap(),
+            "fn sum(a: i32) {}\nfn main() { sum(1); }\n"
+        );
+    }
+
+    #[test]
+    fn columns_are_utf16_code_units() {
+        // '😀' is two UTF-16 units and four UTF-8 bytes
+        let text = "let s = \"😀\"; let x = 1;\n";
+        let edits = [edit((0, 18), (0, 19), "y")];
+        assert_eq!(
+            apply_edits_to_text(text, &edits, Utf16).unwrap(),
+            "let s = \"😀\"; let y = 1;\n"
+        );
+    }
+
+    #[test]
+    fn honors_negotiated_utf8_columns() {
+        let text = "let s = \"é\"; let x = 1;\n";
+        let x = text.find('x').unwrap() as u32;
+        let edits = [edit((0, x), (0, x + 1), "y")];
+        assert_eq!(
+            apply_edits_to_text(text, &edits, PositionEncoding::Utf8).unwrap(),
+            "let s = \"é\"; let y = 1;\n"
+        );
+    }
+
+    #[test]
+    fn positions_clamp_to_line_and_document_end() {
+        let text = "ab\r\ncd";
+        assert_eq!(
+            apply_edits_to_text(text, &[edit((0, 99), (0, 99), "!")], Utf16).unwrap(),
+            "ab!\r\ncd"
+        );
+        assert_eq!(
+            apply_edits_to_text(text, &[edit((9, 0), (9, 0), "!")], Utf16).unwrap(),
+            "ab\r\ncd!"
+        );
+    }
+
+    #[test]
+    fn previews_edits_without_writing() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("old.rs"), "fn old() {}\n").unwrap();
+        std::fs::create_dir(root.join("gone")).unwrap();
+        std::fs::write(root.join("gone/a.rs"), "a\n").unwrap();
+        let uri = |name: &str| {
+            url::Url::from_file_path(root.join(name))
+                .unwrap()
+                .to_string()
+        };
+
+        let workspace_edit = json!({
+            "documentChanges": [
+                {
+                    "textDocument": {"uri": uri("old.rs"), "version": 1},
+                    "edits": [edit((0, 3), (0, 6), "new")],
+                },
+                {"kind": "rename", "oldUri": uri("old.rs"), "newUri": uri("new.rs")},
+                {"kind": "create", "uri": uri("empty.rs")},
+                {"kind": "delete", "uri": uri("gone"), "options": {"recursive": true}},
+            ]
+        });
+        let changes = preview_workspace_edit(&workspace_edit, Utf16, &HashMap::new()).unwrap();
+
+        let summary: Vec<_> = changes
+            .files
+            .iter()
+            .map(|change| {
+                let name = change
+                    .path
+                    .strip_prefix(root)
+                    .unwrap()
+                    .display()
+                    .to_string();
+                (name, change.before.as_deref(), change.after.as_deref())
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("old.rs".to_string(), Some("fn old() {}\n"), None),
+                ("new.rs".to_string(), None, Some("fn new() {}\n")),
+                ("empty.rs".to_string(), None, Some("")),
+                ("gone/a.rs".to_string(), Some("a\n"), None),
+            ]
+        );
+        assert_eq!(
+            changes.files[1].diff(root),
+            "--- /dev/null\n+++ b/new.rs\n@@ -0,0 +1,1 @@\n+fn new() {}\n"
+        );
+        assert!(root.join("old.rs").exists());
+        assert!(!root.join("new.rs").exists());
+
+        let missing = json!({"changes": {uri("missing.rs"): [edit((0, 0), (0, 0), "x")]}});
+        assert!(preview_workspace_edit(&missing, Utf16, &HashMap::new()).is_err());
+
+        write_changes(&changes).unwrap();
+        assert!(!root.join("old.rs").exists());
+        assert_eq!(
+            std::fs::read_to_string(root.join("new.rs")).unwrap(),
+            "fn new() {}\n"
+        );
+        assert!(!root.join("gone").exists());
+    }
+
+    #[test]
+    fn applies_document_changes_with_resource_operations() {
+        let dir = tempfile::tempdir().unwrap();
+        let old = dir.path().join("old.rs");
+        std::fs::write(&old, "fn old() {}\n").unwrap();
+        let uri = |name: &str| {
+            url::Url::from_file_path(dir.path().join(name))
+                .unwrap()
+                .to_string()
+        };
+
+        let workspace_edit = json!({
+            "documentChanges": [
+                {
+                    "textDocument": {"uri": uri("old.rs"), "version": 1},
+                    "edits": [edit((0, 3), (0, 6), "new")],
+                },
+                {"kind": "rename", "oldUri": uri("old.rs"), "newUri": uri("new.rs")},
+                {"kind": "create", "uri": uri("sub/empty.rs")},
+            ]
+        });
+        let touched = apply_workspace_edit(&workspace_edit, Utf16).unwrap();
+
+        assert_eq!(touched.len(), 3);
+        assert!(!old.exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("new.rs")).unwrap(),
+            "fn new() {}\n"
+        );
+        assert!(dir.path().join("sub/empty.rs").exists());
+    }
+
+    #[test]
+    fn failed_writes_leave_every_file_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("a.rs"), "a\n").unwrap();
+        std::fs::write(root.join("b.rs"), "b\n").unwrap();
+        // A regular file where the new file's directory should be
+        std::fs::write(root.join("blocked"), "").unwrap();
+
+        let changes = WorkspaceChanges {
+            files: vec![
+                FileChange {
+                    path: root.join("a.rs"),
+                    before: Some("a\n".to_string()),
+                    after: Some("A\n".to_string()),
+                },
+                FileChange {
+                    path: root.join("b.rs"),
+                    before: Some("b\n".to_string()),
+                    after: None,
+                },
+                FileChange {
+                    path: root.join("new/c.rs"),
+                    before: None,
+                    after: Some("c\n".to_string()),
+                },
+                FileChange {
+                    path: root.join("blocked/d.rs"),
+                    before: None,
+                    after: Some("d\n".to_string()),
+                },
+            ],
+            removed_dirs: Vec::new(),
+        };
+        let err = write_changes(&changes).unwrap_err();
+
+        assert!(format!("{err:#}").contains("all files were restored"));
+        assert_eq!(std::fs::read_to_string(root.join("a.rs")).unwrap(), "a\n");
+        assert_eq!(std::fs::read_to_string(root.join("b.rs")).unwrap(), "b\n");
+        let mut left: Vec<_> = std::fs::read_dir(root)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        left.sort();
+        assert_eq!(left, ["a.rs", "b.rs", "blocked"]);
+    }
+
+    #[test]
+    fn refuses_to_rewrite_files_that_are_not_utf8() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("latin1.py");
+        std::fs::write(&path, b"# caf\xe9 au lait, tr\xe8s cher\n").unwrap();
+        let uri = url::Url::from_file_path(&path).unwrap().to_string();
+
+        let workspace_edit = json!({"changes": {uri: [edit((0, 0), (0, 1), "//")]}});
+        let err = preview_workspace_edit(&workspace_edit, Utf16, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("windows-1252"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Pathfinder as a library: LSP-backed navigation for other Rust tools, without MCP.
+//!
+//! [`Pathfinder`] runs a language server under the same supervision as the MCP server
+//! (crash restarts, document sync, file watching, the sandbox) and answers the tools'
+//! queries directly:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use pathfinder::Pathfinder;
+//!
+//! let pathfinder = Pathfinder::builder("/path/to/project")
+//!     .server(["rust-analyzer"], ["rs"])
+//!     .build()
+//!     .await?;
+//! let definition = pathfinder.definition("src/main.rs", 1, 16).await?;
+//! for target in &definition.targets {
+//!     println!("{}:{}", target.uri, target.range.start_line);
+//! }
+//! pathfinder.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Documents are file:// URIs, absolute paths, or paths relative to the workspace.
+//! Lines and columns are zero-based, with columns in the configured column encoding
+//! (UTF-16 unless `columnEncoding` says otherwise), as in the tools.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use crate::config::{
+    Config, ConnectTarget, DiagnosticsConfig, OutputConfig, SandboxConfig, ServerConfig,
+    ToolsConfig,
+};
+use crate::lsp_bridge::LspBridge;
+use crate::position::PositionEncoding;
+use crate::sandbox::Sandbox;
+use crate::supervisor::LspSupervisor;
+use crate::tools::definition::{DefinitionRequest, DefinitionResponse, DefinitionTool};
+use crate::tools::references::{FoundReferences, ReferencesRequest, ReferencesTool};
+use crate::tools::status::{ServerStatus, StatusTool};
+
+/// Configures a [`Pathfinder`]; see [`Pathfinder::builder`].
+pub struct PathfinderBuilder {
+    workspace: PathBuf,
+    config: Config,
+}
+
+impl PathfinderBuilder {
+    /// Replaces the whole configuration, e.g. one read with [`Config::from_file`].
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Runs `command` as the language server for documents with `extensions`.
+    pub fn server(
+        mut self,
+        command: impl IntoIterator<Item = impl Into<String>>,
+        extensions: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.config.server.command = command.into_iter().map(Into::into).collect();
+        self.config.server.extensions = extensions.into_iter().map(Into::into).collect();
+        self.config.server.connect = None;
+        self
+    }
+
+    /// Talks to the built-in [`crate::testing::MockServer`] instead of running a
+    /// server, for documents with `extensions`.
+    pub fn mock(mut self, extensions: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.config.server.command.clear();
+        self.config.server.extensions = extensions.into_iter().map(Into::into).collect();
+        self.config.server.connect = Some(ConnectTarget::Mock);
+        self
+    }
+
+    /// The encoding of columns in requests and results.
+    pub fn column_encoding(mut self, encoding: PositionEncoding) -> Self {
+        self.config.server.column_encoding = encoding;
+        self
+    }
+
+    /// Starts and initializes the server.
+    pub async fn build(self) -> Result<Pathfinder> {
+        let mut config = self.config;
+        config.validate()?;
+        let workspace = config.server.resolve_root_dir(&self.workspace)?;
+        config.restrict_to_read_only(&workspace)?;
+        let sandbox = Sandbox::new(&config.sandbox, &workspace);
+        let supervisor = LspSupervisor::start(config.server, workspace).await?;
+        Ok(Pathfinder {
+            supervisor,
+            sandbox,
+        })
+    }
+}
+
+/// A supervised language server answering navigation queries; see the
+/// [module docs](self).
+pub struct Pathfinder {
+    supervisor: Arc<LspSupervisor>,
+    sandbox: Sandbox,
+}
+
+impl Pathfinder {
+    /// A builder for a server in `workspace`, configured with
+    /// [`PathfinderBuilder::server`] or [`PathfinderBuilder::config`].
+    pub fn builder(workspace: impl Into<PathBuf>) -> PathfinderBuilder {
+        PathfinderBuilder {
+            workspace: workspace.into(),
+            config: Config {
+                server: ServerConfig::default(),
+                companions: Vec::new(),
+                tools: ToolsConfig::default(),
+                output: OutputConfig::default(),
+                sandbox: SandboxConfig::default(),
+                diagnostics: DiagnosticsConfig::default(),
+                read_only: false,
+            },
+        }
+    }
+
+    /// The workspace root the server runs in (canonical).
+    pub fn workspace(&self) -> &Path {
+        self.supervisor.workspace()
+    }
+
+    /// The bridge to the running server, for requests without a method here. A
+    /// restart replaces it, so fetch it again rather than keeping it.
+    pub fn lsp(&self) -> Arc<LspBridge> {
+        self.supervisor.bridge()
+    }
+
+    /// Opens `document` on the server (or resyncs it) and returns its URI.
+    pub async fn open(&self, document: &str) -> Result<String> {
+        let (_, uri, _) = self.prepare(document).await?;
+        Ok(uri)
+    }
+
+    /// Definition targets of the symbol at `line`/`character` of `document`.
+    pub async fn definition(
+        &self,
+        document: &str,
+        line: u32,
+        character: u32,
+    ) -> Result<DefinitionResponse> {
+        let (lsp, uri, overlays) = self.prepare(document).await?;
+        let request = DefinitionRequest {
+            uri,
+            line,
+            character,
+            snap: None,
+            server: None,
+            options: Default::default(),
+        };
+        DefinitionTool::new().execute(&lsp, request, overlays).await
+    }
+
+    /// References to the symbol at `line`/`character` of `document`, its declaration
+    /// included.
+    pub async fn references(
+        &self,
+        document: &str,
+        line: u32,
+        character: u32,
+    ) -> Result<FoundReferences> {
+        let (lsp, uri, overlays) = self.prepare(document).await?;
+        let request = ReferencesRequest {
+            uri,
+            line,
+            character,
+            include_declaration: None,
+            snap: None,
+            server: None,
+            filter: Default::default(),
+            options: Default::default(),
+        };
+        ReferencesTool::new().execute(&lsp, request, overlays).await
+    }
+
+    /// Health and state of the server, as reported by `server_status`.
+    pub async fn status(&self) -> ServerStatus {
+        StatusTool::new().execute(&self.supervisor).await
+    }
+
+    /// Closes open documents and shuts the server down.
+    pub async fn shutdown(self) {
+        self.supervisor.shutdown().await;
+    }
+
+    /// Waits for a ready bridge and opens `document` on it, returning the bridge, the
+    /// document's URI, and the overlay texts.
+    async fn prepare(
+        &self,
+        document: &str,
+    ) -> Result<(Arc<LspBridge>, String, HashMap<String, String>)> {
+        let uri = self.sandbox.document_uri(document)?;
+        let lsp = self.supervisor.ready_bridge().await?;
+        let documents = self.supervisor.documents();
+        documents.ensure_open(&lsp, &uri).await?;
+        Ok((lsp, uri, documents.overlay_texts().await))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_navigation_queries_from_a_mock_server() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        let pathfinder = Pathfinder::builder(dir.path())
+            .mock(["rs"])
+            .build()
+            .await
+            .unwrap();
+
+        let definition = pathfinder.definition("src/main.rs", 0, 3).await.unwrap();
+        assert!(definition.targets[0].uri.ends_with("/src/main.rs"));
+        assert_eq!(definition.targets[0].range.start_line, 0);
+        let references = pathfinder.references("src/main.rs", 0, 3).await.unwrap();
+        assert_eq!(references.references.len(), 2);
+        assert!(pathfinder.status().await.alive);
+
+        assert!(pathfinder.definition("/etc/passwd", 0, 0).await.is_err());
+        pathfinder.shutdown().await;
+    }
+}
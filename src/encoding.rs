@@ -0,0 +1,147 @@
+//! Decoding source files that are not UTF-8.
+//!
+//! LSP documents are Unicode text, so a file in a legacy encoding (Latin-1,
+//! Windows-1252, Shift_JIS, ...) or in UTF-16 has to be decoded before it is sent to
+//! the server. A byte order mark decides the encoding when there is one; otherwise
+//! valid UTF-8 is taken as is and anything else is detected from the bytes, the way
+//! browsers guess the encoding of unlabeled pages. Bytes the detected encoding cannot
+//! map become U+FFFD, and the result says so.
+//!
+//! Every place that reads a file to find or convert positions goes through
+//! [`read_file`], so lines and columns always refer to the same decoded text the
+//! server was given.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::documents::looks_binary;
+
+/// A file's text and how it was obtained.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    pub text: String,
+    pub encoding: &'static Encoding,
+    /// Some bytes were not valid in `encoding` and were replaced with U+FFFD.
+    pub lossy: bool,
+}
+
+impl Decoded {
+    /// Whether the file was plain UTF-8, read without any conversion.
+    pub fn is_utf8(&self) -> bool {
+        self.encoding == UTF_8 && !self.lossy
+    }
+
+    /// A warning for tool results about a file at `path` that was not plain UTF-8.
+    pub fn note(&self, path: &Path) -> Option<String> {
+        if self.is_utf8() {
+            return None;
+        }
+        let mut note = format!(
+            "{} is not UTF-8; it was decoded as {}",
+            path.display(),
+            self.encoding.name()
+        );
+        if self.lossy {
+            note.push_str(", and bytes invalid in that encoding were replaced with U+FFFD");
+        }
+        Some(note)
+    }
+}
+
+/// Whether `bytes` start with a UTF-16 byte order mark. UTF-16 text is full of NUL
+/// bytes, so it must be recognized before the binary-file check.
+fn has_utf16_bom(bytes: &[u8]) -> bool {
+    Encoding::for_bom(bytes).is_some_and(|(encoding, _)| encoding != UTF_8)
+}
+
+/// Decodes `bytes` unless they look binary: NUL bytes without a UTF-16 BOM.
+pub fn decode_text(bytes: &[u8]) -> Option<Decoded> {
+    if !has_utf16_bom(bytes) && looks_binary(bytes) {
+        return None;
+    }
+    Some(decode(bytes))
+}
+
+/// Decodes `bytes` as described in the module docs.
+pub fn decode(bytes: &[u8]) -> Decoded {
+    let encoding = match Encoding::for_bom(bytes) {
+        // A UTF-8 BOM stays in the text, as it always has
+        Some((encoding, _)) if encoding != UTF_8 => encoding,
+        _ => match std::str::from_utf8(bytes) {
+            Ok(text) => {
+                return Decoded {
+                    text: text.to_string(),
+                    encoding: UTF_8,
+                    lossy: false,
+                };
+            }
+            Err(_) => {
+                let mut detector = chardetng::EncodingDetector::new();
+                detector.feed(bytes, true);
+                detector.guess(None, true)
+            }
+        },
+    };
+    // Strips the BOM the encoding was chosen by, if any
+    let (text, encoding, lossy) = encoding.decode(bytes);
+    Decoded {
+        text: text.into_owned(),
+        encoding,
+        lossy,
+    }
+}
+
+/// Reads and decodes the file at `path`.
+pub async fn read_file(path: &Path) -> Result<Decoded> {
+    let bytes = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(decode(&bytes))
+}
+
+/// Blocking [`read_file`].
+pub fn read_file_sync(path: &Path) -> Result<Decoded> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    Ok(decode(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_legacy_and_utf16_text() {
+        let utf8 = decode("fn café() {}\n".as_bytes());
+        assert!(utf8.is_utf8());
+        assert_eq!(utf8.note(Path::new("a.rs")), None);
+
+        // "café" in Latin-1 / Windows-1252
+        let latin1 = decode(b"# caf\xe9 au lait, tr\xe8s cher\nx = 1\n");
+        assert_eq!(latin1.text, "# café au lait, très cher\nx = 1\n");
+        assert_eq!(latin1.encoding.name(), "windows-1252");
+        assert!(!latin1.lossy);
+        assert!(
+            latin1
+                .note(Path::new("a.py"))
+                .unwrap()
+                .contains("decoded as windows-1252")
+        );
+
+        // "日本語のコメント" in Shift_JIS
+        let line: &[u8] = b"// \x93\xfa\x96\x7b\x8c\xea\x82\xcc\x83\x52\x83\x81\x83\x93\x83\x67\n";
+        let sjis = decode(&line.repeat(3));
+        assert_eq!(sjis.encoding.name(), "Shift_JIS");
+        assert_eq!(sjis.text, "// 日本語のコメント\n".repeat(3));
+
+        let utf16 = decode(b"\xff\xfea\x00\n\x00");
+        assert_eq!(utf16.text, "a\n");
+        assert_eq!(utf16.encoding.name(), "UTF-16LE");
+        assert!(has_utf16_bom(b"\xff\xfea\x00"));
+        assert!(!has_utf16_bom(b"\xef\xbb\xbfa"));
+        assert!(decode_text(b"\xff\xfea\x00").is_some());
+        assert!(decode_text(b"\x7fELF\x00\x01").is_none());
+    }
+}
@@ -0,0 +1,349 @@
+//! How tool results report documents and positions.
+//!
+//! By default results carry file:// URIs and zero-based lines and columns, as LSP
+//! does. The `[output]` config section, or the `paths` and `one_based` inputs of a
+//! single call, switch to workspace-relative paths and to lines and columns counted
+//! from 1. One-based numbering applies to a tool's position inputs as well, so a
+//! location from one result can be passed straight to the next call.
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::config::{OutputConfig, PathStyle, ResultFormat};
+use crate::notebook;
+use crate::tools::check::CheckEditRequest;
+use crate::tools::definition::DefinitionRequest;
+use crate::tools::dependencies::DependencyGraphRequest;
+use crate::tools::documents::{OpenDocumentRequest, UpdateDocumentRequest};
+use crate::tools::outline::OutlineRequest;
+use crate::tools::read::ReadFileRequest;
+use crate::tools::refactor::{CodeActionRequest, RenameRequest};
+use crate::tools::references::ReferencesRequest;
+use crate::tools::search::SearchTextRequest;
+use crate::tools::symbols::FindSymbolRequest;
+use crate::tools::unused::UnusedSymbolsRequest;
+use crate::tools::vendor::{DocumentRequest, PositionRequest};
+use crate::utils::{strip_path_prefix, uri_to_file_path};
+
+/// Result fields holding a line or column.
+const POSITION_KEYS: &[&str] = &[
+    "line",
+    "character",
+    "column",
+    "start_line",
+    "start_character",
+    "end_line",
+    "end_character",
+];
+
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct FormatRequest {
+    /// Report documents as file:// URIs (`uri`) or as paths relative to the workspace
+    /// root (`relative`); defaults to the `output.paths` setting
+    pub paths: Option<PathStyle>,
+    /// Count lines and columns from 1, in this call's inputs and results; defaults to
+    /// the `output.oneBased` setting
+    pub one_based: Option<bool>,
+    /// Return a compact text summary (`text`) instead of JSON (`json`) as the result's
+    /// content; defaults to the `output.format` setting
+    pub format: Option<ResultFormat>,
+}
+
+/// A tool request with output format inputs alongside its own.
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct Formatted<T> {
+    #[serde(flatten)]
+    pub request: T,
+    #[serde(flatten)]
+    pub format: FormatRequest,
+}
+
+/// Requests carrying lines or columns.
+pub trait Positions {
+    fn positions_mut(&mut self) -> Vec<&mut u32>;
+}
+
+/// The format of one call's result.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OutputFormat {
+    pub paths: PathStyle,
+    pub one_based: bool,
+    pub format: ResultFormat,
+}
+
+impl OutputFormat {
+    /// `config`, with what the call asked for taking precedence.
+    pub fn resolve(config: &OutputConfig, request: &FormatRequest) -> Self {
+        Self {
+            paths: request.paths.unwrap_or(config.paths),
+            one_based: request.one_based.unwrap_or(config.one_based),
+            format: request.format.unwrap_or(config.format),
+        }
+    }
+
+    /// Converts `request`'s positions to the zero-based ones the tools work with.
+    pub fn to_zero_based(&self, request: &mut impl Positions) -> Result<()> {
+        if !self.one_based {
+            return Ok(());
+        }
+        for position in request.positions_mut() {
+            if *position == 0 {
+                bail!("lines and columns are one-based, so 0 is not a valid position");
+            }
+            *position -= 1;
+        }
+        Ok(())
+    }
+
+    /// Rewrites a serialized result: URIs under `workspace` become relative paths
+    /// and positions become one-based, as configured.
+    pub fn apply(&self, workspace: &Path, value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                for (key, field) in fields.iter_mut() {
+                    match field {
+                        Value::String(uri) if key == "uri" && self.paths == PathStyle::Relative => {
+                            if let Some(path) = relative_path(workspace, uri) {
+                                *uri = path;
+                            }
+                        }
+                        Value::Number(number)
+                            if self.one_based && POSITION_KEYS.contains(&key.as_str()) =>
+                        {
+                            if let Some(position) = number.as_u64() {
+                                *field = Value::from(position + 1);
+                            }
+                        }
+                        _ => self.apply(workspace, field),
+                    }
+                }
+            }
+            Value::Array(items) => {
+                for item in items {
+                    self.apply(workspace, item);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// `uri` as a path relative to `workspace`, if it names a file inside it. A notebook
+/// cell becomes `notebook.ipynb#cell<index>`.
+pub fn relative_path(workspace: &Path, uri: &str) -> Option<String> {
+    if let Some((notebook, index)) = notebook::split_cell_uri(uri) {
+        return Some(notebook::cell_reference(
+            &relative_path(workspace, &notebook)?,
+            index,
+        ));
+    }
+    let path = uri_to_file_path(uri).ok()?;
+    let relative = strip_path_prefix(&path, workspace)?;
+    if relative.as_os_str().is_empty() {
+        return Some(".".to_string());
+    }
+    Some(relative.to_string_lossy().replace('\\', "/"))
+}
+
+impl Positions for DefinitionRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        vec![&mut self.line, &mut self.character]
+    }
+}
+
+impl Positions for ReferencesRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        vec![&mut self.line, &mut self.character]
+    }
+}
+
+impl Positions for RenameRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        vec![&mut self.line, &mut self.character]
+    }
+}
+
+impl Positions for CodeActionRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        [&mut self.start_line, &mut self.start_character]
+            .into_iter()
+            .chain(self.end_line.as_mut())
+            .chain(self.end_character.as_mut())
+            .collect()
+    }
+}
+
+impl Positions for PositionRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        vec![&mut self.line, &mut self.character]
+    }
+}
+
+impl Positions for ReadFileRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        std::iter::once(&mut self.start_line)
+            .chain(self.end_line.as_mut())
+            .collect()
+    }
+}
+
+impl Positions for UpdateDocumentRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        self.edits
+            .iter_mut()
+            .flatten()
+            .flat_map(|edit| {
+                [
+                    &mut edit.start_line,
+                    &mut edit.start_character,
+                    &mut edit.end_line,
+                    &mut edit.end_character,
+                ]
+            })
+            .collect()
+    }
+}
+
+impl Positions for OpenDocumentRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        Vec::new()
+    }
+}
+
+impl Positions for CheckEditRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        Vec::new()
+    }
+}
+
+impl Positions for DependencyGraphRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        Vec::new()
+    }
+}
+
+impl Positions for OutlineRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        Vec::new()
+    }
+}
+
+impl Positions for UnusedSymbolsRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        Vec::new()
+    }
+}
+
+impl Positions for DocumentRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        Vec::new()
+    }
+}
+
+impl Positions for SearchTextRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        Vec::new()
+    }
+}
+
+impl Positions for FindSymbolRequest {
+    fn positions_mut(&mut self) -> Vec<&mut u32> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use url::Url;
+
+    use crate::tools::definition::DefinitionResponse;
+    use crate::tools::locations::{DefinitionTarget, TextRange};
+
+    #[test]
+    fn relative_one_based_results() {
+        let workspace = std::env::temp_dir().join("project");
+        let inside = Url::from_file_path(workspace.join("src/lib.rs")).unwrap();
+        let outside = Url::from_file_path(std::env::temp_dir().join("other/lib.rs")).unwrap();
+        let target = |uri: &Url| {
+            DefinitionTarget::new(
+                uri.to_string(),
+                TextRange {
+                    start_line: 0,
+                    start_character: 4,
+                    end_line: 2,
+                    end_character: 0,
+                },
+            )
+        };
+        let response = DefinitionResponse {
+            targets: vec![target(&inside), target(&outside)],
+            next_cursor: None,
+            snapped: None,
+            truncated: false,
+            fallback: false,
+        };
+        let mut value = serde_json::to_value(&response).unwrap();
+        let format = OutputFormat {
+            paths: PathStyle::Relative,
+            one_based: true,
+            format: ResultFormat::Json,
+        };
+        format.apply(&workspace, &mut value);
+        assert_eq!(value["targets"][0]["uri"], "src/lib.rs");
+        assert_eq!(value["targets"][1]["uri"], outside.as_str());
+        assert_eq!(
+            value["targets"][0]["range"],
+            json!({"start_line": 1, "start_character": 5, "end_line": 3, "end_character": 1})
+        );
+
+        let cell = notebook::cell_uri(
+            Url::from_file_path(workspace.join("nb.ipynb"))
+                .unwrap()
+                .as_str(),
+            1,
+        );
+        assert_eq!(
+            relative_path(&workspace, &cell).as_deref(),
+            Some("nb.ipynb#cell1")
+        );
+
+        let unchanged = serde_json::to_value(&response).unwrap();
+        let mut value = unchanged.clone();
+        OutputFormat::default().apply(&workspace, &mut value);
+        assert_eq!(value, unchanged);
+    }
+
+    #[test]
+    fn one_based_inputs_convert_and_reject_zero() {
+        let config = OutputConfig {
+            paths: PathStyle::Uri,
+            one_based: true,
+            format: ResultFormat::Json,
+        };
+        let format = OutputFormat::resolve(&config, &FormatRequest::default());
+        let mut request = ReadFileRequest {
+            path: "src/lib.rs".to_string(),
+            start_line: 10,
+            end_line: Some(12),
+            context: Some(1),
+        };
+        format.to_zero_based(&mut request).unwrap();
+        assert_eq!((request.start_line, request.end_line), (9, Some(11)));
+        assert_eq!(request.context, Some(1));
+
+        request.start_line = 0;
+        assert!(format.to_zero_based(&mut request).is_err());
+
+        let per_call = FormatRequest {
+            paths: None,
+            one_based: Some(false),
+            format: None,
+        };
+        assert!(!OutputFormat::resolve(&config, &per_call).one_based);
+    }
+}
@@ -0,0 +1,211 @@
+//! Golden-file tests replaying recorded language server sessions.
+//!
+//! Available with the `test-util` feature. A case is a directory holding:
+//!
+//! - `case.json`: the pathfinder config (without `connect`) and the tool calls to
+//!   make, e.g. `{"config": {...}, "calls": [{"tool": "definition", "arguments": {...}}]}`
+//! - `workspace/`: the files the session was recorded against
+//! - `session.jsonl`: the recording (see [`crate::recording`]), with the recorded
+//!   workspace root replaced by `${workspace}`
+//! - `expected.json`: what each call returned
+//!
+//! [`check`] copies the workspace to a temporary directory, starts a
+//! [`PathfinderService`] replaying the session in place of the server, makes the
+//! calls through an MCP client, and compares their results (with the workspace root
+//! again replaced by `${workspace}`) to `expected.json`. With `UPDATE_GOLDEN` set, it
+//! writes `expected.json` instead, for reviewing the diff after an intended change.
+
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+use rmcp::ServiceExt;
+use rmcp::model::{CallToolRequestParam, CallToolResult, JsonObject};
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use crate::config::{Config, ConnectTarget};
+use crate::service::PathfinderService;
+
+/// Stands for the workspace root in recordings and expected results.
+pub const WORKSPACE_PLACEHOLDER: &str = "${workspace}";
+
+/// Variable that makes [`check`] write expected results instead of comparing them.
+pub const UPDATE_GOLDEN: &str = "UPDATE_GOLDEN";
+
+/// Buffer between the MCP client and the service.
+const MCP_BUFFER: usize = 64 * 1024;
+
+/// `case.json`.
+#[derive(Debug, Deserialize)]
+struct Case {
+    config: Config,
+    calls: Vec<Call>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Call {
+    tool: String,
+    #[serde(default)]
+    arguments: JsonObject,
+}
+
+/// Replays the case in `dir` and compares (or with [`UPDATE_GOLDEN`], writes) its
+/// expected results.
+pub async fn check(dir: &Path) -> Result<()> {
+    let case_path = dir.join("case.json");
+    let case: Case = serde_json::from_str(
+        &std::fs::read_to_string(&case_path)
+            .with_context(|| format!("failed to read {}", case_path.display()))?,
+    )
+    .with_context(|| format!("invalid {}", case_path.display()))?;
+
+    let temp = tempfile::tempdir().context("failed to create the test workspace")?;
+    let workspace = crate::utils::canonicalize(temp.path())?.join("workspace");
+    copy_dir(&dir.join("workspace"), &workspace)?;
+    // Outside the workspace, so the file watcher leaves it alone
+    let recording = temp.path().join("session.jsonl");
+    let session = std::fs::read_to_string(dir.join("session.jsonl"))
+        .with_context(|| format!("failed to read the recording in {}", dir.display()))?;
+    std::fs::write(
+        &recording,
+        session.replace(WORKSPACE_PLACEHOLDER, &workspace.display().to_string()),
+    )?;
+
+    let mut config = case.config;
+    config.server.connect = Some(ConnectTarget::Replay(recording));
+    let actual = replay(config, &workspace, case.calls).await?;
+    let actual = redact_workspace(actual, &workspace.display().to_string());
+
+    let expected_path = dir.join("expected.json");
+    if std::env::var_os(UPDATE_GOLDEN).is_some() {
+        let mut text = serde_json::to_string_pretty(&actual)?;
+        text.push('\n');
+        std::fs::write(&expected_path, text)?;
+        return Ok(());
+    }
+    let expected: Value =
+        serde_json::from_str(&std::fs::read_to_string(&expected_path).with_context(|| {
+            format!(
+                "failed to read {} (run with {UPDATE_GOLDEN}=1 to create it)",
+                expected_path.display()
+            )
+        })?)
+        .with_context(|| format!("invalid {}", expected_path.display()))?;
+    if actual != expected {
+        bail!(
+            "results differ from {} (run with {UPDATE_GOLDEN}=1 to accept them)\nexpected: {}\nactual: {}",
+            expected_path.display(),
+            serde_json::to_string_pretty(&expected)?,
+            serde_json::to_string_pretty(&actual)?
+        );
+    }
+    Ok(())
+}
+
+/// Makes `calls` through an MCP client connected to a service for `config`, and
+/// returns what each one returned.
+async fn replay(config: Config, workspace: &Path, calls: Vec<Call>) -> Result<Value> {
+    let service = PathfinderService::new(config, workspace.to_path_buf()).await?;
+    let (client_stream, server_stream) = tokio::io::duplex(MCP_BUFFER);
+    let (server, client) = tokio::join!(
+        service.clone().serve(tokio::io::split(server_stream)),
+        ().serve(tokio::io::split(client_stream)),
+    );
+    let server = server.map_err(|err| anyhow!("MCP server failed to start: {err}"))?;
+    let client = client.map_err(|err| anyhow!("MCP client failed to connect: {err}"))?;
+
+    let mut results = Vec::new();
+    for call in calls {
+        let outcome = client
+            .call_tool(CallToolRequestParam {
+                name: call.tool.clone().into(),
+                arguments: Some(call.arguments),
+            })
+            .await;
+        results.push(match outcome {
+            Ok(result) => call_result(&call.tool, result),
+            Err(err) => json!({ "tool": call.tool, "error": err.to_string() }),
+        });
+    }
+
+    let _ = client.cancel().await;
+    let _ = server.cancel().await;
+    service.shutdown().await;
+    Ok(Value::Array(results))
+}
+
+/// A call's result as kept in `expected.json`: the structured result if there is one
+/// (the text content mirrors or summarizes it), the text content otherwise.
+fn call_result(tool: &str, result: CallToolResult) -> Value {
+    let mut entry = json!({ "tool": tool, "isError": result.is_error.unwrap_or(false) });
+    match result.structured_content {
+        Some(structured) => entry["structuredContent"] = structured,
+        None => {
+            let texts: Vec<&str> = result
+                .content
+                .iter()
+                .filter_map(|content| content.as_text())
+                .map(|text| text.text.as_str())
+                .collect();
+            entry["content"] = json!(texts);
+        }
+    }
+    entry
+}
+
+/// Replaces `workspace` in every string of `value` with [`WORKSPACE_PLACEHOLDER`].
+fn redact_workspace(value: Value, workspace: &str) -> Value {
+    match value {
+        Value::String(text) => Value::String(text.replace(workspace, WORKSPACE_PLACEHOLDER)),
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| redact_workspace(item, workspace))
+                .collect(),
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, field)| (key, redact_workspace(field, workspace)))
+                .collect(),
+        ),
+        other => other,
+    }
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in
+        std::fs::read_dir(from).with_context(|| format!("failed to read {}", from.display()))?
+    {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)
+                .with_context(|| format!("failed to copy {}", entry.path().display()))?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_the_workspace_root_in_strings() {
+        let result = json!({
+            "targets": [{"uri": "file:///tmp/abc/workspace/src/main.rs", "line": 5}],
+            "note": "/tmp/abc/workspace",
+        });
+        assert_eq!(
+            redact_workspace(result, "/tmp/abc/workspace"),
+            json!({
+                "targets": [{"uri": "file://${workspace}/src/main.rs", "line": 5}],
+                "note": "${workspace}",
+            })
+        );
+    }
+}
@@ -0,0 +1,315 @@
+//! Builder for integration tests against real (or mock) language servers.
+//!
+//! Available with the `test-util` feature. A [`TestHarness`] owns a temporary
+//! workspace with the language's project file and the test's fixtures, a bridge to
+//! an initialized server running in it, and a [`DocumentManager`]:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use pathfinder::harness::{Language, TestHarness};
+//!
+//! let Some(builder) = TestHarness::builder(Language::Rust)
+//!     .file("src/main.rs", "fn main() { helper(); }\nfn helper() {}\n")
+//!     .enabled()
+//! else {
+//!     return Ok(());
+//! };
+//! let harness = builder.build().await?;
+//! let uri = harness.open("src/main.rs").await?;
+//! let definition = harness.definition(&uri, 0, 12).await?;
+//! assert_eq!(definition.targets[0].range.start_line, 1);
+//! harness.shutdown().await;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Suites against real servers only run with `RUN_LSP_TESTS` set, since they need
+//! the server installed; [`HarnessBuilder::enabled`] returns `None` otherwise. With
+//! [`HarnessBuilder::mock`], the harness talks to a [`MockServer`] and always runs.
+
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use tempfile::TempDir;
+use url::Url;
+
+use crate::config::ServerConfig;
+use crate::documents::DocumentManager;
+use crate::lsp_bridge::LspBridge;
+use crate::testing::MockServer;
+use crate::tools::{DefinitionRequest, DefinitionResponse, DefinitionTool};
+
+/// Variable that turns on suites needing a real language server.
+pub const RUN_LSP_TESTS: &str = "RUN_LSP_TESTS";
+
+/// How often [`TestHarness::eventually`] tries before giving up.
+const ATTEMPTS: u32 = 10;
+
+/// Pause between attempts, e.g. while the server is still indexing.
+const RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// A language the harness can set up a workspace and server for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Rust,
+    Python,
+    Go,
+    C,
+}
+
+impl Language {
+    /// The server command run unless [`HarnessBuilder::server`] overrides it.
+    pub fn command(self) -> &'static [&'static str] {
+        match self {
+            Self::Rust => &["rust-analyzer"],
+            Self::Python => &["pyright-langserver", "--stdio"],
+            Self::Go => &["gopls"],
+            Self::C => &["clangd"],
+        }
+    }
+
+    pub fn extensions(self) -> &'static [&'static str] {
+        match self {
+            Self::Rust => &["rs"],
+            Self::Python => &["py", "pyi"],
+            Self::Go => &["go"],
+            Self::C => &["c", "h"],
+        }
+    }
+
+    /// Project files the server needs to treat the workspace as a project.
+    fn project_files(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Self::Rust => &[(
+                "Cargo.toml",
+                "[package]\nname = \"pathfinder_fixture\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\n",
+            )],
+            Self::Python => &[],
+            Self::Go => &[("go.mod", "module pathfinder.test/fixture\n\ngo 1.21\n")],
+            Self::C => &[("compile_flags.txt", "-std=c11\n")],
+        }
+    }
+}
+
+/// Configures a [`TestHarness`]; see [`TestHarness::builder`].
+pub struct HarnessBuilder {
+    language: Language,
+    command: Vec<String>,
+    files: Vec<(PathBuf, String)>,
+    mock: Option<MockServer>,
+}
+
+impl HarnessBuilder {
+    /// Adds a fixture file, relative to the workspace root.
+    pub fn file(mut self, path: impl AsRef<Path>, contents: impl Into<String>) -> Self {
+        self.files
+            .push((path.as_ref().to_path_buf(), contents.into()));
+        self
+    }
+
+    /// Runs `command` instead of the language's usual server.
+    pub fn server(mut self, command: &[&str]) -> Self {
+        self.command = command.iter().map(|part| part.to_string()).collect();
+        self
+    }
+
+    /// Talks to `mock` instead of running a server.
+    pub fn mock(mut self, mock: MockServer) -> Self {
+        self.mock = Some(mock);
+        self
+    }
+
+    /// The builder if the suite should run: always with a mock, otherwise only with
+    /// [`RUN_LSP_TESTS`] set.
+    pub fn enabled(self) -> Option<Self> {
+        if self.mock.is_none() && std::env::var_os(RUN_LSP_TESTS).is_none() {
+            eprintln!(
+                "skipping {:?} language server tests (set {RUN_LSP_TESTS}=1)",
+                self.language
+            );
+            return None;
+        }
+        Some(self)
+    }
+
+    /// Writes the workspace and starts and initializes the server in it.
+    pub async fn build(self) -> Result<TestHarness> {
+        let dir = tempfile::tempdir().context("failed to create the test workspace")?;
+        // Servers report canonical paths, e.g. /private/var rather than /var on macOS
+        let workspace = crate::utils::canonicalize(dir.path())?;
+        let project_files = self
+            .language
+            .project_files()
+            .iter()
+            .map(|(path, contents)| (PathBuf::from(path), contents.to_string()));
+        for (path, contents) in project_files.chain(self.files) {
+            let path = workspace.join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, contents)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+
+        let mut lsp = match self.mock {
+            Some(mock) => LspBridge::from_mock(mock, workspace.clone()),
+            None => {
+                which::which(&self.command[0])
+                    .with_context(|| format!("{} not found on PATH", self.command[0]))?;
+                let server = ServerConfig {
+                    extensions: self
+                        .language
+                        .extensions()
+                        .iter()
+                        .map(|ext| ext.to_string())
+                        .collect(),
+                    command: self.command,
+                    ..ServerConfig::default()
+                };
+                LspBridge::from_config(&server, workspace.clone()).await?
+            }
+        };
+        lsp.initialize().await?;
+        Ok(TestHarness {
+            _dir: dir,
+            workspace,
+            lsp,
+            documents: DocumentManager::new(),
+        })
+    }
+}
+
+/// A temporary workspace with an initialized server; see the [module docs](self).
+pub struct TestHarness {
+    /// Removes the workspace when the harness is dropped.
+    _dir: TempDir,
+    workspace: PathBuf,
+    lsp: LspBridge,
+    documents: DocumentManager,
+}
+
+impl TestHarness {
+    pub fn builder(language: Language) -> HarnessBuilder {
+        HarnessBuilder {
+            language,
+            command: language
+                .command()
+                .iter()
+                .map(|part| part.to_string())
+                .collect(),
+            files: Vec::new(),
+            mock: None,
+        }
+    }
+
+    pub fn workspace(&self) -> &Path {
+        &self.workspace
+    }
+
+    pub fn lsp(&self) -> &LspBridge {
+        &self.lsp
+    }
+
+    pub fn documents(&self) -> &DocumentManager {
+        &self.documents
+    }
+
+    /// The file:// URI of `path`, relative to the workspace root.
+    pub fn uri(&self, path: impl AsRef<Path>) -> String {
+        Url::from_file_path(self.workspace.join(path))
+            .expect("workspace paths are absolute")
+            .to_string()
+    }
+
+    /// Opens `path` on the server and returns its URI.
+    pub async fn open(&self, path: impl AsRef<Path>) -> Result<String> {
+        let uri = self.uri(path);
+        self.documents.ensure_open(&self.lsp, &uri).await?;
+        Ok(uri)
+    }
+
+    /// Calls `attempt` until it returns `Some`, pausing between tries, since servers
+    /// answer with nothing until they have indexed the workspace.
+    pub async fn eventually<T, F, Fut>(&self, what: &str, mut attempt: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<Option<T>>>,
+    {
+        for _ in 0..ATTEMPTS {
+            if let Some(found) = attempt().await? {
+                return Ok(found);
+            }
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+        Err(anyhow!("{what} never came back after {ATTEMPTS} attempts"))
+    }
+
+    /// Definition targets of the position, waiting until there are some.
+    pub async fn definition(
+        &self,
+        uri: &str,
+        line: u32,
+        character: u32,
+    ) -> Result<DefinitionResponse> {
+        let tool = DefinitionTool::new();
+        let request = DefinitionRequest {
+            uri: uri.to_string(),
+            line,
+            character,
+            snap: None,
+            server: None,
+            options: Default::default(),
+        };
+        self.eventually("definition targets", || async {
+            let response = tool
+                .execute(&self.lsp, request.clone(), Default::default())
+                .await?;
+            Ok((!response.targets.is_empty()).then_some(response))
+        })
+        .await
+    }
+
+    /// Closes the documents and shuts the server down; failures are ignored.
+    pub async fn shutdown(self) {
+        self.documents.close_all(&self.lsp).await.ok();
+        self.lsp.shutdown().await.ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn builds_a_workspace_around_a_mock_server() {
+        let harness = TestHarness::builder(Language::Rust)
+            .file("src/main.rs", "fn main() {}\n")
+            .mock(MockServer::new())
+            .enabled()
+            .unwrap()
+            .build()
+            .await
+            .unwrap();
+        assert!(harness.workspace().join("Cargo.toml").is_file());
+
+        let uri = harness.open("src/main.rs").await.unwrap();
+        assert!(uri.ends_with("/src/main.rs"));
+        let definition = harness.definition(&uri, 0, 3).await.unwrap();
+        assert_eq!(definition.targets[0].uri, uri);
+        assert_eq!(definition.targets[0].range.start_line, 0);
+
+        let mut calls = 0;
+        let found = harness
+            .eventually("the third call", || {
+                calls += 1;
+                let done = calls == 3;
+                async move { Ok(done.then_some(calls)) }
+            })
+            .await
+            .unwrap();
+        assert_eq!(found, 3);
+        harness.shutdown().await;
+    }
+}
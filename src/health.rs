@@ -0,0 +1,133 @@
+//! Language server health checks.
+//!
+//! A process can be alive yet wedged (deadlocked, stuck in a GC loop), so besides
+//! watching for exit the supervisor periodically sends a probe request and records
+//! how quickly the server answered. The most recent failure of any kind is kept for
+//! the `server_status` tool.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::lsp_bridge::{LspBridge, LspResponseError};
+
+/// How often the supervisor probes the running server.
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Method used to probe the server.
+///
+/// LSP has no ping. Servers must answer unknown `$/` requests with MethodNotFound,
+/// and any answer, error or not, shows the server is processing messages. Its
+/// timeout can be tuned through `timeouts.methods`.
+pub const PROBE_METHOD: &str = "$/pathfinder/ping";
+
+/// Sends a probe and returns the round-trip latency.
+pub async fn probe(lsp: &LspBridge) -> Result<Duration> {
+    if lsp.has_exited() {
+        return Err(anyhow!("language server process has exited"));
+    }
+    let started = Instant::now();
+    let limit = lsp.timeouts().for_method(PROBE_METHOD);
+    match lsp
+        .request_with_timeout(PROBE_METHOD, Value::Null, limit)
+        .await
+    {
+        Ok(_) => Ok(started.elapsed()),
+        Err(err) if err.is::<LspResponseError>() => Ok(started.elapsed()),
+        Err(err) => Err(err),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Health {
+    state: Mutex<HealthState>,
+}
+
+#[derive(Debug, Default)]
+struct HealthState {
+    last_probe: Option<(Instant, Result<Duration, String>)>,
+    last_error: Option<(Instant, String)>,
+}
+
+/// Serializable snapshot of [`Health`], with ages relative to now.
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct HealthReport {
+    pub last_probe: Option<ProbeReport>,
+    pub last_error: Option<ErrorReport>,
+}
+
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct ProbeReport {
+    pub ok: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+    pub age_secs: u64,
+}
+
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct ErrorReport {
+    pub message: String,
+    pub age_secs: u64,
+}
+
+impl Health {
+    /// Records a probe outcome; failures also become the last error.
+    pub fn record_probe(&self, outcome: &Result<Duration>) {
+        let outcome = outcome.as_ref().copied().map_err(|err| format!("{err:#}"));
+        if let Err(message) = &outcome {
+            tracing::warn!(%message, "Language server health check failed");
+            self.record_error(format!("health check failed: {message}"));
+        }
+        let mut state = self.state.lock().expect("health lock poisoned");
+        state.last_probe = Some((Instant::now(), outcome));
+    }
+
+    /// Remembers `message` as the most recent error.
+    pub fn record_error(&self, message: impl Into<String>) {
+        let mut state = self.state.lock().expect("health lock poisoned");
+        state.last_error = Some((Instant::now(), message.into()));
+    }
+
+    pub fn report(&self) -> HealthReport {
+        let state = self.state.lock().expect("health lock poisoned");
+        HealthReport {
+            last_probe: state.last_probe.as_ref().map(|(at, outcome)| ProbeReport {
+                ok: outcome.is_ok(),
+                latency_ms: outcome.as_ref().ok().map(|d| d.as_millis() as u64),
+                error: outcome.as_ref().err().cloned(),
+                age_secs: at.elapsed().as_secs(),
+            }),
+            last_error: state.last_error.as_ref().map(|(at, message)| ErrorReport {
+                message: message.clone(),
+                age_secs: at.elapsed().as_secs(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failed_probe_becomes_last_error() {
+        let health = Health::default();
+        health.record_probe(&Ok(Duration::from_millis(12)));
+        let report = health.report();
+        let probe = report.last_probe.unwrap();
+        assert!(probe.ok);
+        assert_eq!(probe.latency_ms, Some(12));
+        assert!(report.last_error.is_none());
+
+        health.record_probe(&Err(anyhow!("timed out")));
+        let report = health.report();
+        assert!(!report.last_probe.unwrap().ok);
+        assert_eq!(
+            report.last_error.unwrap().message,
+            "health check failed: timed out"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+//! Recent LSP traffic, kept for crash reports.
+//!
+//! Every bridge keeps the last [`MAX_MESSAGES`] framed messages in both directions,
+//! at most [`MAX_BYTES`] in total, with document text redacted. When a request times
+//! out or the server dies, [`MessageHistory::dump`] writes them to a file in the
+//! temporary directory, in the JSON lines format of a recording (see
+//! [`crate::recording`]), and the error names the file.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+
+use crate::recording::{Direction, Entry};
+
+/// Most messages kept.
+pub const MAX_MESSAGES: usize = 200;
+
+/// Most bytes kept, counting each message as serialized.
+pub const MAX_BYTES: usize = 1024 * 1024;
+
+/// Messages larger than this after redaction are kept as just their id and method.
+const MAX_MESSAGE_BYTES: usize = 64 * 1024;
+
+/// Fields holding document text, replaced by their length.
+const REDACTED_KEYS: &[&str] = &["text", "newText"];
+
+/// Most dumps written per server, so a wedged server timing out every request does
+/// not fill the temporary directory; later dumps reuse the last file's path.
+const MAX_DUMPS: usize = 5;
+
+/// Dumps written by this process, numbering their files.
+static DUMPS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Default)]
+pub struct MessageHistory {
+    state: Mutex<HistoryState>,
+}
+
+#[derive(Debug, Default)]
+struct HistoryState {
+    /// Serialized [`Entry`] lines, oldest first.
+    lines: VecDeque<String>,
+    bytes: usize,
+    /// Messages seen so far, to tell whether anything happened since the last dump.
+    seen: u64,
+    last_dump: Option<(u64, PathBuf)>,
+    dumps: usize,
+}
+
+impl MessageHistory {
+    /// Remembers `message`, dropping the oldest ones past the limits.
+    pub fn push(&self, direction: Direction, message: &Value) {
+        let mut message = message.clone();
+        redact(&mut message);
+        let mut line = entry_line(direction, message.clone());
+        if line.len() > MAX_MESSAGE_BYTES {
+            line = entry_line(direction, summary(&message, line.len()));
+        }
+
+        let mut state = self.state.lock().expect("message history lock poisoned");
+        state.seen += 1;
+        state.bytes += line.len();
+        state.lines.push_back(line);
+        while state.lines.len() > MAX_MESSAGES || state.bytes > MAX_BYTES {
+            let Some(oldest) = state.lines.pop_front() else {
+                break;
+            };
+            state.bytes -= oldest.len();
+        }
+    }
+
+    /// Writes the kept messages to a new file in the temporary directory and returns
+    /// its path. Without new messages since the last dump, or after [`MAX_DUMPS`],
+    /// returns the last dump's path instead.
+    pub fn dump(&self) -> Result<PathBuf> {
+        let mut state = self.state.lock().expect("message history lock poisoned");
+        if let Some((seen, path)) = &state.last_dump
+            && (*seen == state.seen || state.dumps >= MAX_DUMPS)
+        {
+            return Ok(path.clone());
+        }
+        let path = std::env::temp_dir().join(format!(
+            "pathfinder-lsp-{}-{}-{}.jsonl",
+            std::process::id(),
+            now_ms(),
+            DUMPS.fetch_add(1, Ordering::Relaxed)
+        ));
+        let mut contents = String::new();
+        for line in &state.lines {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        std::fs::write(&path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        state.last_dump = Some((state.seen, path.clone()));
+        state.dumps += 1;
+        Ok(path)
+    }
+
+    /// `message` followed by where recent traffic was saved, for errors about the
+    /// server. Failing to save is logged and leaves `message` as is.
+    pub fn annotate(&self, message: &str) -> String {
+        match self.dump() {
+            Ok(path) => format!(
+                "{message} (recent LSP messages saved to {})",
+                path.display()
+            ),
+            Err(err) => {
+                tracing::warn!(?err, "Failed to save recent LSP messages");
+                message.to_string()
+            }
+        }
+    }
+}
+
+fn entry_line(direction: Direction, message: Value) -> String {
+    let entry = Entry {
+        ms: now_ms(),
+        direction,
+        message,
+    };
+    serde_json::to_string(&entry).unwrap_or_default()
+}
+
+/// Replaces document text anywhere in `value` with a note of its length.
+fn redact(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                match field {
+                    Value::String(text) if REDACTED_KEYS.contains(&key.as_str()) => {
+                        *field = Value::String(format!("<{} bytes redacted>", text.len()));
+                    }
+                    _ => redact(field),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact),
+        _ => {}
+    }
+}
+
+/// What is kept of a message too large to keep whole.
+fn summary(message: &Value, bytes: usize) -> Value {
+    json!({
+        "id": message.get("id"),
+        "method": message.get("method"),
+        "omittedBytes": bytes,
+    })
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_recent_redacted_messages_and_dumps_them() {
+        let history = MessageHistory::default();
+        history.push(
+            Direction::Sent,
+            &json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": "file:///src/lib.rs", "text": "fn secret() {}"},
+            }}),
+        );
+        for id in 0..MAX_MESSAGES {
+            history.push(Direction::Received, &json!({"id": id, "result": null}));
+        }
+        history.push(
+            Direction::Received,
+            &json!({"id": "big", "result": "x".repeat(MAX_MESSAGE_BYTES)}),
+        );
+
+        let path = history.dump().unwrap();
+        let entries = crate::recording::load(&path).unwrap();
+        assert_eq!(entries.len(), MAX_MESSAGES);
+        assert_eq!(entries[0].message["id"], 1);
+        let oversized = &entries[MAX_MESSAGES - 1].message;
+        assert_eq!(oversized["id"], "big");
+        assert!(oversized.get("result").is_none());
+        assert!(oversized["omittedBytes"].as_u64().unwrap() > MAX_MESSAGE_BYTES as u64);
+        assert_eq!(history.dump().unwrap(), path);
+
+        let history = MessageHistory::default();
+        history.push(
+            Direction::Sent,
+            &json!({"method": "textDocument/didChange", "params": {
+                "contentChanges": [{"text": "fn secret() {}"}],
+            }}),
+        );
+        let redacted = history.dump().unwrap();
+        let dumped = std::fs::read_to_string(&redacted).unwrap();
+        assert!(!dumped.contains("secret"));
+        assert!(dumped.contains("<14 bytes redacted>"));
+        std::fs::remove_file(path).unwrap();
+        std::fs::remove_file(redacted).unwrap();
+    }
+}
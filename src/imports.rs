@@ -0,0 +1,221 @@
+//! Import statements, found per language with patterns.
+//!
+//! `dependency_graph` resolves each import to the file it names by asking the server
+//! for the definition of the imported module (or its document links), so these
+//! patterns only need to find where the imports are, not what they mean. Each one
+//! captures the imported module or path as `target`; the server is asked at its last
+//! identifier (`config` in `crate::config::`, `path` in `os.path`) or, for a quoted
+//! module, at the start of the string.
+
+use std::sync::LazyLock;
+
+use regex::Regex;
+
+use crate::position::{PositionEncoding, byte_to_column};
+
+/// Import patterns of one language.
+struct Language {
+    extensions: &'static [&'static str],
+    patterns: Vec<Regex>,
+    /// Lines opening and closing a block whose lines are each an import, as in Go's
+    /// `import (...)`, and the pattern of those lines.
+    block: Option<(&'static str, &'static str, Regex)>,
+}
+
+impl Language {
+    fn new(extensions: &'static [&'static str], patterns: &[&str]) -> Self {
+        Self {
+            extensions,
+            patterns: patterns
+                .iter()
+                .map(|pattern| Regex::new(pattern).expect("valid import pattern"))
+                .collect(),
+            block: None,
+        }
+    }
+
+    fn with_block(mut self, start: &'static str, end: &'static str, line: &str) -> Self {
+        let line = Regex::new(line).expect("valid import pattern");
+        self.block = Some((start, end, line));
+        self
+    }
+}
+
+static LANGUAGES: LazyLock<Vec<Language>> = LazyLock::new(|| {
+    vec![
+        Language::new(
+            &["rs"],
+            &[
+                r"^\s*(?:pub(?:\([^)]*\))?\s+)?use\s+(?P<target>(?:\w+::)*\w+)",
+                r"^\s*(?:pub(?:\([^)]*\))?\s+)?mod\s+(?P<target>\w+)\s*;",
+            ],
+        ),
+        Language::new(
+            &["py", "pyi"],
+            &[
+                r"^\s*from\s+(?P<target>\.*[\w.]*\w)\s+import\b",
+                r"^\s*import\s+(?P<target>[\w.]+)",
+            ],
+        ),
+        Language::new(
+            &["go"],
+            &[r#"^\s*import\s+(?:[\w.]+\s+)?"(?P<target>[^"]+)""#],
+        )
+        .with_block("import (", ")", r#"^\s*(?:[\w.]+\s+)?"(?P<target>[^"]+)""#),
+        Language::new(
+            &["js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts"],
+            &[
+                r#"^\s*import\s+(?:type\s+)?(?:[\w*{}\s,$]+\s+from\s+)?['"](?P<target>[^'"]+)['"]"#,
+                r#"^\s*export\s+(?:type\s+)?[\w*{}\s,$]+\s+from\s+['"](?P<target>[^'"]+)['"]"#,
+                r#"\brequire\(\s*['"](?P<target>[^'"]+)['"]\s*\)"#,
+            ],
+        ),
+        Language::new(
+            &["c", "h", "cc", "cpp", "cxx", "hpp", "hh", "hxx"],
+            &[r#"^\s*#\s*include\s*["<](?P<target>[^">]+)[">]"#],
+        ),
+        Language::new(
+            &["java"],
+            &[r"^\s*import\s+(?:static\s+)?(?P<target>[\w.]+)"],
+        ),
+        Language::new(
+            &["rb"],
+            &[r#"^\s*require(?:_relative)?\s*\(?\s*['"](?P<target>[^'"]+)['"]"#],
+        ),
+    ]
+});
+
+/// An import statement, and where to ask the server what it imports.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Import {
+    /// The imported module or path as written.
+    pub target: String,
+    pub line: u32,
+    /// Column to ask at, in `encoding` units.
+    pub character: u32,
+}
+
+/// Whether imports of files with `extension` are recognized.
+pub fn supports(extension: &str) -> bool {
+    language(extension).is_some()
+}
+
+fn language(extension: &str) -> Option<&'static Language> {
+    LANGUAGES
+        .iter()
+        .find(|language| language.extensions.contains(&extension))
+}
+
+/// The imports in `text`, a file with `extension`, with columns in `encoding`.
+pub fn find(extension: &str, text: &str, encoding: PositionEncoding) -> Vec<Import> {
+    let Some(language) = language(extension) else {
+        return Vec::new();
+    };
+    let mut imports = Vec::new();
+    let mut in_block = false;
+    for (number, line) in text.lines().enumerate() {
+        let patterns: Vec<&Regex> = match &language.block {
+            Some((_, end, pattern)) if in_block => {
+                if line.trim() == *end {
+                    in_block = false;
+                    continue;
+                }
+                vec![pattern]
+            }
+            Some((start, _, _)) if line.trim_start().starts_with(start) => {
+                in_block = true;
+                continue;
+            }
+            _ => language.patterns.iter().collect(),
+        };
+        let Some(target) = patterns
+            .iter()
+            .find_map(|pattern| pattern.captures(line)?.name("target"))
+        else {
+            continue;
+        };
+        imports.push(Import {
+            target: target.as_str().to_string(),
+            line: number as u32,
+            character: byte_to_column(line, ask_at(line, target), encoding),
+        });
+    }
+    imports
+}
+
+/// Byte offset in `line` of the last identifier of a path `target`, or of the start
+/// of a quoted one.
+fn ask_at(line: &str, target: regex::Match<'_>) -> usize {
+    let quoted = line[..target.start()].ends_with(['"', '\'', '<']);
+    if quoted {
+        return target.start();
+    }
+    let text = target.as_str();
+    let last = text
+        .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .map_or(0, |separator| separator + 1);
+    target.start() + last
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn imports(extension: &str, text: &str) -> Vec<(String, u32, u32)> {
+        find(extension, text, PositionEncoding::Utf16)
+            .into_iter()
+            .map(|import| (import.target, import.line, import.character))
+            .collect()
+    }
+
+    #[test]
+    fn finds_imports_per_language() {
+        let rust = "use crate::config::Config;\npub(crate) mod tools;\nfn main() {}\n";
+        assert_eq!(
+            imports("rs", rust),
+            [
+                ("crate::config::Config".to_string(), 0, 19),
+                ("tools".to_string(), 1, 15),
+            ]
+        );
+
+        let python = "from .models import User\nimport os.path\n";
+        assert_eq!(
+            imports("py", python),
+            [
+                (".models".to_string(), 0, 6),
+                ("os.path".to_string(), 1, 10)
+            ]
+        );
+
+        let typescript =
+            "import { a } from './a';\nexport * from \"../b\";\nconst c = require('c');\n";
+        assert_eq!(
+            imports("ts", typescript),
+            [
+                ("./a".to_string(), 0, 19),
+                ("../b".to_string(), 1, 15),
+                ("c".to_string(), 2, 19),
+            ]
+        );
+
+        let go = "import (\n\t\"fmt\"\n\tcfg \"example.com/app/config\"\n)\nimport \"os\"\n";
+        assert_eq!(
+            imports("go", go),
+            [
+                ("fmt".to_string(), 1, 2),
+                ("example.com/app/config".to_string(), 2, 6),
+                ("os".to_string(), 4, 8),
+            ]
+        );
+
+        assert_eq!(
+            imports("c", "#include \"util.h\"\n#include <stdio.h>\n"),
+            [
+                ("util.h".to_string(), 0, 10),
+                ("stdio.h".to_string(), 1, 10)
+            ]
+        );
+        assert!(imports("txt", "use std::fs;").is_empty());
+    }
+}
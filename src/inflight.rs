@@ -0,0 +1,180 @@
+//! Coalescing of identical LSP requests in flight.
+//!
+//! Clients that retry, or agents that fire the same tool call twice in parallel, make
+//! the bridge send the same query twice while the first is still running. The bridge
+//! registers each query in an [`InFlight`] map keyed by method and params: the first
+//! caller becomes the [`Lead`] and sends the request, later callers with the same key
+//! wait for its result instead of sending their own. If the lead gives up before a
+//! response arrives (timeout or cancellation), the waiting callers send the request
+//! themselves.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde_json::Value;
+use tokio::sync::watch;
+
+/// Method prefixes of requests that only query the server and so can share one
+/// response. `workspace/executeCommand` and the like are never coalesced.
+const COALESCED_PREFIXES: &[&str] = &["textDocument/", "callHierarchy/", "typeHierarchy/"];
+
+/// Whether identical requests for `method` may share one round trip.
+pub fn coalesces(method: &str) -> bool {
+    method == "workspace/symbol"
+        || COALESCED_PREFIXES
+            .iter()
+            .any(|prefix| method.starts_with(prefix))
+}
+
+/// Requests in flight, mapping each key to the channel its result is published on.
+#[derive(Debug)]
+pub struct InFlight<T> {
+    calls: Mutex<HashMap<String, watch::Sender<Option<T>>>>,
+}
+
+impl<T> Default for InFlight<T> {
+    fn default() -> Self {
+        Self {
+            calls: Mutex::default(),
+        }
+    }
+}
+
+/// How a caller takes part in a request.
+pub enum Call<'a, T> {
+    /// No identical request is in flight; send it and [`Lead::finish`] with the result.
+    Lead(Lead<'a, T>),
+    /// An identical request is in flight; wait for its result with [`follow`].
+    Follow(watch::Receiver<Option<T>>),
+}
+
+impl<T: Clone> InFlight<T> {
+    /// Joins the request identified by `method` and `params`. `generation` separates
+    /// requests sent before and after a document change, which may be answered
+    /// differently.
+    pub fn join(&self, generation: u64, method: &str, params: &Value) -> Call<'_, T> {
+        let key = format!("{generation} {method} {params}");
+        let mut calls = self.calls.lock().expect("in-flight requests lock poisoned");
+        if let Some(sender) = calls.get(&key) {
+            return Call::Follow(sender.subscribe());
+        }
+        calls.insert(key.clone(), watch::Sender::new(None));
+        Call::Lead(Lead {
+            calls: self,
+            key,
+            finished: false,
+        })
+    }
+
+    /// Number of distinct requests in flight.
+    pub fn len(&self) -> usize {
+        self.calls
+            .lock()
+            .expect("in-flight requests lock poisoned")
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The caller sending a request on behalf of everyone waiting on its key. Dropping it
+/// without [`Lead::finish`] releases the waiting callers to send their own.
+pub struct Lead<'a, T> {
+    calls: &'a InFlight<T>,
+    key: String,
+    finished: bool,
+}
+
+impl<T> Lead<'_, T> {
+    /// Hands the result to the callers waiting on this request, if any; `result` is
+    /// only called when there are some, sparing a copy of large responses.
+    pub fn finish(mut self, result: impl FnOnce() -> T) {
+        self.finished = true;
+        let sender = self
+            .calls
+            .calls
+            .lock()
+            .expect("in-flight requests lock poisoned")
+            .remove(&self.key);
+        if let Some(sender) = sender
+            && sender.receiver_count() > 0
+        {
+            sender.send_replace(Some(result()));
+        }
+    }
+}
+
+impl<T> Drop for Lead<'_, T> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.calls
+                .calls
+                .lock()
+                .expect("in-flight requests lock poisoned")
+                .remove(&self.key);
+        }
+    }
+}
+
+/// Waits for the lead's result; `None` if it gave up without one.
+pub async fn follow<T: Clone>(mut receiver: watch::Receiver<Option<T>>) -> Option<T> {
+    receiver
+        .wait_for(Option::is_some)
+        .await
+        .ok()
+        .and_then(|result| result.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn identical_requests_share_the_lead_result() {
+        let calls = InFlight::<u32>::default();
+        let params = json!({"textDocument": {"uri": "file:///a.rs"}});
+        let Call::Lead(lead) = calls.join(0, "textDocument/hover", &params) else {
+            panic!("first caller should lead");
+        };
+        let Call::Follow(first) = calls.join(0, "textDocument/hover", &params) else {
+            panic!("identical request should follow");
+        };
+        let Call::Follow(second) = calls.join(0, "textDocument/hover", &params) else {
+            panic!("identical request should follow");
+        };
+        // Other params, methods, or generations do not share
+        assert!(matches!(
+            calls.join(0, "textDocument/hover", &json!({})),
+            Call::Lead(_)
+        ));
+        assert!(matches!(
+            calls.join(1, "textDocument/hover", &params),
+            Call::Lead(_)
+        ));
+        assert_eq!(calls.len(), 1);
+
+        lead.finish(|| 7);
+        assert!(calls.is_empty());
+        assert_eq!(follow(first).await, Some(7));
+        assert_eq!(follow(second).await, Some(7));
+
+        // A lead that gives up releases its followers
+        let Call::Lead(lead) = calls.join(0, "textDocument/hover", &params) else {
+            panic!("finished request should not be joined");
+        };
+        let Call::Follow(follower) = calls.join(0, "textDocument/hover", &params) else {
+            panic!("identical request should follow");
+        };
+        drop(lead);
+        assert_eq!(follow(follower).await, None);
+        assert!(calls.is_empty());
+
+        assert!(coalesces("textDocument/references"));
+        assert!(coalesces("workspace/symbol"));
+        assert!(!coalesces("workspace/executeCommand"));
+        assert!(!coalesces("shutdown"));
+    }
+}
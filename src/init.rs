@@ -0,0 +1,313 @@
+//! Config scaffolding for the `pathfinder init` subcommand.
+//!
+//! This module scans a workspace for source files and project marker files,
+//! picks a language server for the detected language, and writes a starter
+//! `pathfinder.json` or `pathfinder.toml` that can be passed to `--config`.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow};
+
+use crate::args::{ConfigFormat, InitArgs};
+use crate::config::{
+    Config, DiagnosticsConfig, OutputConfig, SandboxConfig, ServerConfig, ToolsConfig,
+};
+use crate::utils::{resolve_workspace_base, walk_source_files};
+
+/// Upper bound on files inspected, so `init` stays fast in huge monorepos.
+const MAX_SCANNED_FILES: usize = 20_000;
+
+/// A known language and the servers that can handle it.
+#[derive(Debug)]
+pub struct LanguagePreset {
+    pub language: &'static str,
+    pub extensions: &'static [&'static str],
+    pub markers: &'static [&'static str],
+    /// Candidate server commands, in order of preference.
+    pub commands: &'static [&'static [&'static str]],
+}
+
+pub const PRESETS: &[LanguagePreset] = &[
+    LanguagePreset {
+        language: "rust",
+        extensions: &["rs"],
+        markers: &["Cargo.toml"],
+        commands: &[&["rust-analyzer"]],
+    },
+    LanguagePreset {
+        language: "python",
+        extensions: &["py", "pyi"],
+        markers: &["pyproject.toml", "setup.py", "requirements.txt"],
+        commands: &[
+            &["pyright-langserver", "--stdio"],
+            &["basedpyright-langserver", "--stdio"],
+            &["pylsp"],
+        ],
+    },
+    LanguagePreset {
+        language: "typescript",
+        extensions: &["ts", "tsx", "js", "jsx"],
+        markers: &["package.json", "tsconfig.json"],
+        commands: &[&["typescript-language-server", "--stdio"]],
+    },
+    LanguagePreset {
+        language: "go",
+        extensions: &["go"],
+        markers: &["go.mod"],
+        commands: &[&["gopls"]],
+    },
+    LanguagePreset {
+        language: "c",
+        extensions: &["c", "h", "cc", "cpp", "hpp"],
+        markers: &["compile_commands.json", "CMakeLists.txt"],
+        commands: &[&["clangd"]],
+    },
+];
+
+/// A language found in the workspace.
+#[derive(Debug)]
+pub struct Detection {
+    pub preset: &'static LanguagePreset,
+    pub files: usize,
+    pub markers: Vec<&'static str>,
+}
+
+/// Runs `pathfinder init`: detect, choose, and write the config file.
+pub fn run(args: InitArgs) -> Result<()> {
+    let workspace = resolve_workspace_base(args.workspace)?;
+    let detections = detect_languages(&workspace)?;
+
+    let preset = match &args.language {
+        Some(language) => find_preset(language)?,
+        None if detections.is_empty() => {
+            return Err(anyhow!(
+                "no supported languages detected in {} (use --language to pick one)",
+                workspace.display()
+            ));
+        }
+        None if detections.len() > 1 && !args.yes && io::stdin().is_terminal() => {
+            prompt_choice(&detections)?
+        }
+        None => detections[0].preset,
+    };
+
+    let path = workspace.join(file_name(args.format));
+    if path.exists() && !args.force {
+        return Err(anyhow!(
+            "{} already exists (use --force to overwrite)",
+            path.display()
+        ));
+    }
+
+    let config = Config {
+        server: server_config_for(preset),
+        companions: Vec::new(),
+        tools: ToolsConfig::default(),
+        output: OutputConfig::default(),
+        sandbox: SandboxConfig::default(),
+        diagnostics: DiagnosticsConfig::default(),
+        read_only: false,
+    };
+    std::fs::write(&path, render(&config, args.format)?)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+
+    println!(
+        "Wrote {} ({}: {})",
+        path.display(),
+        preset.language,
+        config.server.command.join(" ")
+    );
+    for other in detections
+        .iter()
+        .filter(|d| d.preset.language != preset.language)
+    {
+        println!(
+            "Also detected {} ({} files); run a separate pathfinder instance for it",
+            other.preset.language, other.files
+        );
+    }
+    println!("Start with: pathfinder --config {}", path.display());
+    Ok(())
+}
+
+/// Scans the workspace and returns detected languages, most prominent first.
+///
+/// A language is detected if it has at least one source file or one of its
+/// marker files sits in the workspace root.
+pub fn detect_languages(workspace: &Path) -> Result<Vec<Detection>> {
+    let counts = count_extensions(workspace)?;
+
+    let mut detections: Vec<Detection> = PRESETS
+        .iter()
+        .filter_map(|preset| {
+            let files = preset
+                .extensions
+                .iter()
+                .map(|ext| counts.get(*ext).copied().unwrap_or(0))
+                .sum();
+            let markers: Vec<&'static str> = preset
+                .markers
+                .iter()
+                .copied()
+                .filter(|marker| workspace.join(marker).exists())
+                .collect();
+            (files > 0 || !markers.is_empty()).then_some(Detection {
+                preset,
+                files,
+                markers,
+            })
+        })
+        .collect();
+
+    detections.sort_by(|a, b| {
+        b.files
+            .cmp(&a.files)
+            .then_with(|| b.markers.len().cmp(&a.markers.len()))
+    });
+    Ok(detections)
+}
+
+/// Builds a server configuration, preferring the first candidate found on PATH.
+pub fn server_config_for(preset: &LanguagePreset) -> ServerConfig {
+    let command = preset
+        .commands
+        .iter()
+        .find(|command| which::which(command[0]).is_ok())
+        .unwrap_or(&preset.commands[0]);
+
+    ServerConfig {
+        extensions: preset.extensions.iter().map(|s| s.to_string()).collect(),
+        command: command.iter().map(|s| s.to_string()).collect(),
+        ..ServerConfig::default()
+    }
+}
+
+pub fn render(config: &Config, format: ConfigFormat) -> Result<String> {
+    match format {
+        ConfigFormat::Json => {
+            let mut out =
+                serde_json::to_string_pretty(config).context("failed to serialize config")?;
+            out.push('\n');
+            Ok(out)
+        }
+        ConfigFormat::Toml => toml::to_string_pretty(config).context("failed to serialize config"),
+    }
+}
+
+pub fn file_name(format: ConfigFormat) -> &'static str {
+    match format {
+        ConfigFormat::Json => "pathfinder.json",
+        ConfigFormat::Toml => "pathfinder.toml",
+    }
+}
+
+fn find_preset(language: &str) -> Result<&'static LanguagePreset> {
+    PRESETS
+        .iter()
+        .find(|preset| preset.language.eq_ignore_ascii_case(language))
+        .ok_or_else(|| {
+            let known: Vec<&str> = PRESETS.iter().map(|preset| preset.language).collect();
+            anyhow!(
+                "unknown language '{language}' (known: {})",
+                known.join(", ")
+            )
+        })
+}
+
+fn prompt_choice(detections: &[Detection]) -> Result<&'static LanguagePreset> {
+    println!("Detected languages:");
+    for (index, detection) in detections.iter().enumerate() {
+        println!(
+            "  {}) {} ({} files)",
+            index + 1,
+            detection.preset.language,
+            detection.files
+        );
+    }
+    print!("Configure which language? [1]: ");
+    io::stdout().flush().context("failed to flush prompt")?;
+
+    let mut line = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut line)
+        .context("failed to read selection")?;
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(detections[0].preset);
+    }
+
+    line.parse::<usize>()
+        .ok()
+        .and_then(|choice| detections.get(choice.wrapping_sub(1)))
+        .map(|detection| detection.preset)
+        .ok_or_else(|| anyhow!("invalid selection: {line}"))
+}
+
+fn count_extensions(workspace: &Path) -> Result<HashMap<String, usize>> {
+    let mut counts = HashMap::new();
+    walk_source_files(workspace, MAX_SCANNED_FILES, |entry| {
+        if let Some(ext) = entry.path().extension() {
+            *counts
+                .entry(ext.to_string_lossy().into_owned())
+                .or_insert(0) += 1;
+        }
+    })?;
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_languages_by_file_count_and_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::write(root.join("Cargo.toml"), "").unwrap();
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "").unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+        std::fs::write(root.join("script.py"), "").unwrap();
+        std::fs::create_dir_all(root.join("target/debug")).unwrap();
+        std::fs::write(root.join("target/debug/build.py"), "").unwrap();
+        std::fs::write(root.join("target/debug/other.py"), "").unwrap();
+
+        let detections = detect_languages(root).unwrap();
+        let languages: Vec<&str> = detections.iter().map(|d| d.preset.language).collect();
+        assert_eq!(languages, vec!["rust", "python"]);
+        assert_eq!(detections[0].files, 2);
+        assert_eq!(detections[0].markers, vec!["Cargo.toml"]);
+        assert_eq!(detections[1].files, 1);
+    }
+
+    #[test]
+    fn rendered_configs_round_trip() {
+        let config = Config {
+            server: server_config_for(find_preset("Go").unwrap()),
+            companions: Vec::new(),
+            tools: ToolsConfig::default(),
+            output: OutputConfig::default(),
+            sandbox: SandboxConfig::default(),
+            diagnostics: DiagnosticsConfig::default(),
+            read_only: false,
+        };
+        let json = render(&config, ConfigFormat::Json).unwrap();
+        let toml = render(&config, ConfigFormat::Toml).unwrap();
+        assert_eq!(
+            Config::from_json_str(&json).unwrap().server.command,
+            vec!["gopls"]
+        );
+        assert_eq!(
+            Config::from_toml_str(&toml).unwrap().server.extensions,
+            vec!["go"]
+        );
+    }
+
+    #[test]
+    fn unknown_language_is_rejected() {
+        assert!(find_preset("cobol").is_err());
+    }
+}
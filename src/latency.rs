@@ -0,0 +1,75 @@
+//! Rolling latency windows behind adaptive request timeouts.
+//!
+//! The bridge records how long the server took to answer (or time out on) each
+//! request, per LSP method, keeping the last [`WINDOW`] samples. Once a method has
+//! [`MIN_SAMPLES`] of them, [`crate::config::TimeoutConfig::resolve`] derives its
+//! timeout from their 99th percentile instead of the flat default, so a hover that
+//! usually takes 50ms fails fast while workspace/symbol on a large repository gets the
+//! time it usually needs.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Samples kept per method.
+pub const WINDOW: usize = 100;
+
+/// Samples needed before a method's timeout adapts.
+pub const MIN_SAMPLES: usize = 20;
+
+#[derive(Debug, Default)]
+pub struct LatencyWindow {
+    samples: Mutex<HashMap<String, VecDeque<Duration>>>,
+}
+
+impl LatencyWindow {
+    pub fn record(&self, method: &str, elapsed: Duration) {
+        let mut samples = self.samples.lock().expect("latency window lock poisoned");
+        let window = samples.entry(method.to_string()).or_default();
+        if window.len() == WINDOW {
+            window.pop_front();
+        }
+        window.push_back(elapsed);
+    }
+
+    /// The 99th percentile of `method`'s recent latencies, once there are enough.
+    pub fn p99(&self, method: &str) -> Option<Duration> {
+        let samples = self.samples.lock().expect("latency window lock poisoned");
+        let window = samples
+            .get(method)
+            .filter(|window| window.len() >= MIN_SAMPLES)?;
+        let mut sorted: Vec<Duration> = window.iter().copied().collect();
+        sorted.sort_unstable();
+        let rank = (sorted.len() * 99).div_ceil(100);
+        Some(sorted[rank - 1])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn p99_of_the_recent_window() {
+        let latencies = LatencyWindow::default();
+        for ms in 1..MIN_SAMPLES as u64 {
+            latencies.record("textDocument/hover", Duration::from_millis(ms));
+        }
+        assert_eq!(latencies.p99("textDocument/hover"), None);
+        latencies.record("textDocument/hover", Duration::from_millis(500));
+        assert_eq!(
+            latencies.p99("textDocument/hover"),
+            Some(Duration::from_millis(500))
+        );
+        assert_eq!(latencies.p99("workspace/symbol"), None);
+
+        // Old samples fall out of the window
+        for _ in 0..WINDOW {
+            latencies.record("textDocument/hover", Duration::from_millis(10));
+        }
+        assert_eq!(
+            latencies.p99("textDocument/hover"),
+            Some(Duration::from_millis(10))
+        );
+    }
+}
@@ -1,8 +1,62 @@
 pub mod args;
+pub mod arguments;
+pub mod cache;
+pub mod capabilities;
+pub mod client_log;
+pub mod companions;
 pub mod config;
+pub mod daemon;
+pub mod diagnostics;
+pub mod diff;
+pub mod doctor;
 pub mod documents;
+pub mod edits;
+pub mod embed;
+pub mod encoding;
+pub mod format;
+#[cfg(feature = "test-util")]
+pub mod golden;
+#[cfg(feature = "test-util")]
+pub mod harness;
+pub mod health;
+pub mod history;
+pub mod imports;
+pub mod inflight;
+pub mod init;
+pub mod latency;
 pub mod lsp_bridge;
+pub mod metrics;
+pub mod middleware;
+pub mod notebook;
+pub mod pagination;
+pub mod plugins;
+pub mod position;
+pub mod progress;
+pub mod prompts;
+pub mod protocol;
+pub mod query;
+pub mod queue;
+pub mod recording;
+pub mod reload;
+pub mod resources;
+pub mod runtime;
+pub mod sandbox;
+pub mod server_log;
+pub mod server_requests;
 pub mod service;
+pub mod session;
+pub mod sniff;
+pub mod summary;
+pub mod supervisor;
+pub mod symbol_index;
+pub mod tags;
+pub mod telemetry;
+pub mod testing;
 pub mod tools;
 pub mod transport;
+pub mod truncate;
 pub mod utils;
+pub mod warmup;
+pub mod watcher;
+
+pub use embed::{Pathfinder, PathfinderBuilder};
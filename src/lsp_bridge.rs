@@ -1,46 +1,280 @@
 //! LSP bridge implementation.
 //!
 //! This module provides the `LspBridge` type which manages a single LSP server process.
-//! It handles process spawning, JSON-RPC communication, initialization handshake,
-//! and graceful shutdown. Each bridge maintains its own request ID sequence and
-//! enforces a 15-second timeout on all requests.
+//! It handles process spawning (or connecting to a server listening on a TCP port or
+//! unix socket), JSON-RPC communication, initialization handshake, and graceful
+//! shutdown. Each bridge maintains its own request ID sequence and
+//! enforces a configurable timeout (15 seconds by default) on all requests.
+//!
+//! Incoming messages are read by a dedicated background task which routes responses
+//! to the request awaiting them, broadcasts notifications to subscribers, and answers
+//! server-initiated requests, so any number of requests can be in flight at once.
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
-use std::time::Duration;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock};
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, anyhow};
 use serde_json::{Value, json};
-use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, broadcast, oneshot, watch};
+use tokio::task::JoinHandle;
 use tokio::time::timeout;
+use tracing::Instrument;
 use url::Url;
 
-use crate::transport::FramedTransport;
+use crate::cache::{self, ResultCache};
+use crate::capabilities::{ServerCapabilities, client_capabilities, merge_patch};
+use crate::config::{ConnectTarget, ServerConfig, TimeoutConfig};
+use crate::history::MessageHistory;
+use crate::inflight::{self, Call, InFlight};
+use crate::latency::LatencyWindow;
+use crate::metrics::{Metrics, Outcome};
+use crate::position::PositionEncoding;
+use crate::progress::ProgressTracker;
+use crate::protocol::{
+    self, DidChangeConfiguration, DidChangeConfigurationParams, Initialize, InitializeParams,
+    Initialized, InitializedParams, Request as _, WorkspaceFolder,
+};
+use crate::queue::RequestQueue;
+use crate::recording::{self, Recorder};
+use crate::runtime::{self, PathMap};
+use crate::server_log::{ServerLog, spawn_capture};
+use crate::server_requests::{QueuedEdit, ServerRequestHandler};
+use crate::sniff;
+use crate::testing::MockServer;
+use crate::transport::{DEFAULT_MAX_MESSAGE_BYTES, FramedReader, FramedTransport, FramedWriter};
+use crate::utils::language_id_for_extension;
+use crate::watcher::WatchRegistry;
+
+/// Request timeout used unless configured otherwise.
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long tools wait for server indexing to finish unless configured otherwise.
+pub const DEFAULT_INDEXING_WAIT: Duration = Duration::from_secs(60);
+
+/// Number of notifications buffered per subscriber before the oldest are dropped.
+const NOTIFICATION_CAPACITY: usize = 256;
+
+/// Server output: the child's stdout or the read half of a socket.
+type ServerReader = Box<dyn AsyncRead + Send + Unpin>;
+/// Server input: the child's stdin or the write half of a socket.
+type ServerWriter = Box<dyn AsyncWrite + Send + Unpin>;
+
+type SharedWriter = Arc<Mutex<FramedWriter<ServerWriter>>>;
+
+/// Error response returned by the server for one of our requests.
+///
+/// Kept distinct from transport failures and timeouts so callers can tell a
+/// responsive server that declined a request from one that did not answer.
+#[derive(Debug, Clone)]
+pub struct LspResponseError {
+    pub method: String,
+    pub error: Value,
+}
+
+impl fmt::Display for LspResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LSP error for '{}': {:?}", self.method, self.error)
+    }
+}
+
+impl std::error::Error for LspResponseError {}
+
+/// A response as handed to callers that joined an identical request in flight.
+type SharedResponse = Result<Value, SharedError>;
+
+/// The parts of a request error that survive being shared between callers.
+#[derive(Debug, Clone)]
+enum SharedError {
+    Response(LspResponseError),
+    Other(String),
+}
+
+impl SharedError {
+    fn new(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<LspResponseError>() {
+            Some(response) => Self::Response(response.clone()),
+            None => Self::Other(format!("{err:#}")),
+        }
+    }
+
+    fn into_error(self) -> anyhow::Error {
+        match self {
+            Self::Response(response) => response.into(),
+            Self::Other(message) => anyhow!(message),
+        }
+    }
+}
+
+/// A notification received from the LSP server.
+#[derive(Debug, Clone)]
+pub struct Notification {
+    pub method: String,
+    pub params: Value,
+}
 
 pub struct LspBridge {
     workspace: PathBuf,
-    child: Child,
-    transport: FramedTransport<ChildStdout, ChildStdin>,
-    next_request_id: i64,
+    /// The server process, or `None` when connected to a server over a socket.
+    child: Mutex<Option<Child>>,
+    pid: Option<u32>,
+    started_at: Instant,
+    writer: SharedWriter,
+    pending: Arc<PendingRequests>,
+    notifications: broadcast::Sender<Notification>,
+    reader_task: JoinHandle<()>,
+    exited: watch::Receiver<bool>,
+    stderr_log: Arc<ServerLog>,
+    /// Recent messages, saved to a file when a request times out or the server dies.
+    history: Arc<MessageHistory>,
+    handler: Arc<ServerRequestHandler>,
+    /// Set when the server sees the workspace under other paths (e.g. in a container).
+    path_map: Option<Arc<PathMap>>,
+    next_request_id: AtomicI64,
+    server_capabilities: ServerCapabilities,
+    server_info: Option<Value>,
+    indexing_wait: Duration,
+    /// Replaced when the config file is reloaded.
+    timeouts: StdRwLock<TimeoutConfig>,
+    column_encoding: PositionEncoding,
+    /// `languageIds` overrides, keyed by extension without the dot.
+    language_ids: HashMap<String, String>,
+    /// `clientCapabilities` from the config, merged over the defaults.
+    capability_overrides: Value,
+    metrics: Arc<Metrics>,
+    /// Recent latencies per method, for adaptive timeouts.
+    latencies: LatencyWindow,
+    results: ResultCache,
+    /// Limits requests in flight, sending interactive ones first.
+    queue: RequestQueue,
+    /// Query requests awaiting a response, shared by identical callers.
+    in_flight: InFlight<SharedResponse>,
 }
 
-const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
-
 impl LspBridge {
+    /// Spawns (or connects to) the server described by `server`, answering its
+    /// requests from the config (e.g. `settings` for `workspace/configuration`).
+    pub async fn from_config(server: &ServerConfig, workspace: PathBuf) -> Result<Self> {
+        Self::from_config_with_log(server, workspace, Arc::new(ServerLog::default())).await
+    }
+
+    /// Like [`LspBridge::from_config`], appending server stderr to an existing log
+    /// (e.g. one kept across restarts).
+    pub async fn from_config_with_log(
+        server: &ServerConfig,
+        workspace: PathBuf,
+        stderr_log: Arc<ServerLog>,
+    ) -> Result<Self> {
+        let handler = ServerRequestHandler::from_config(server);
+        let recorder = match &server.record {
+            Some(path) => Some(Arc::new(Recorder::open(&workspace.join(path))?)),
+            None => None,
+        };
+        let framing = TransportOptions {
+            recorder,
+            max_message_bytes: server.max_message_bytes,
+        };
+        let mut bridge = match &server.connect {
+            Some(target) => Self::connect(target, workspace, handler, stderr_log, framing).await?,
+            None => {
+                let launch = runtime::launch(server, &workspace)?;
+                let command = &launch.command[0];
+                let args = launch.command[1..].to_vec();
+                let process = ProcessOptions {
+                    env: launch.env,
+                    env_remove: launch.env_remove,
+                    cwd: launch.cwd,
+                    path_map: launch.path_map,
+                };
+                Self::spawn(
+                    command, args, workspace, process, handler, stderr_log, framing,
+                )
+                .await?
+            }
+        };
+        bridge.indexing_wait = Duration::from_secs(server.indexing_wait_secs);
+        bridge.results = ResultCache::new(server.result_cache_size);
+        bridge.queue = RequestQueue::new(&server.queue);
+        bridge.set_timeouts(server.timeouts.clone());
+        bridge.column_encoding = server.column_encoding;
+        bridge.language_ids = server
+            .language_ids
+            .iter()
+            .map(|(ext, id)| (ext.trim_start_matches('.').to_string(), id.clone()))
+            .collect();
+        bridge.capability_overrides = server.client_capabilities.clone();
+        Ok(bridge)
+    }
+
     pub async fn new_with_command(
         command: &str,
         args: Vec<String>,
         workspace: PathBuf,
     ) -> Result<Self> {
-        tracing::debug!(command = %command, ?args, "Spawning LSP child process");
+        let process = ProcessOptions::in_dir(&workspace);
+        Self::spawn(
+            command,
+            args,
+            workspace,
+            process,
+            ServerRequestHandler::default(),
+            Arc::new(ServerLog::default()),
+            TransportOptions::default(),
+        )
+        .await
+    }
+
+    /// Talks to `mock` over an in-memory stream instead of a server process.
+    pub fn from_mock(mock: MockServer, workspace: PathBuf) -> Self {
+        let (reader, writer) = tokio::io::split(mock.spawn());
+        let transport = TransportOptions::default().frame(Box::new(reader), Box::new(writer));
+        Self::start(
+            transport,
+            None,
+            workspace,
+            ServerRequestHandler::default(),
+            Arc::new(ServerLog::default()),
+            None,
+        )
+    }
+
+    async fn spawn(
+        command: &str,
+        args: Vec<String>,
+        workspace: PathBuf,
+        process: ProcessOptions,
+        handler: ServerRequestHandler,
+        stderr_log: Arc<ServerLog>,
+        framing: TransportOptions,
+    ) -> Result<Self> {
+        tracing::debug!(command = %command, ?args, cwd = %process.cwd.display(), "Spawning LSP child process");
+        if !process.cwd.is_dir() {
+            return Err(anyhow!(
+                "server working directory {} is not a directory",
+                process.cwd.display()
+            ));
+        }
         let mut cmd = Command::new(command);
         if !args.is_empty() {
             cmd.args(&args);
         }
-        cmd.current_dir(&workspace);
+        cmd.current_dir(&process.cwd);
+        for key in &process.env_remove {
+            cmd.env_remove(key);
+        }
+        cmd.envs(process.env);
+        // Never leave an orphaned server behind if the bridge is dropped unexpectedly
+        cmd.kill_on_drop(true);
         cmd.stdin(Stdio::piped())
             .stdout(Stdio::piped())
-            .stderr(Stdio::inherit());
+            .stderr(Stdio::piped());
 
         let mut child = cmd
             .spawn()
@@ -53,18 +287,140 @@ impl LspBridge {
             .stdin
             .take()
             .context("language server stdin not captured")?;
+        let stderr = child
+            .stderr
+            .take()
+            .context("language server stderr not captured")?;
+        let pid = child.id();
+        spawn_capture(stderr, server_name(command), pid, stderr_log.clone());
+
+        let (reader, writer): (ServerReader, ServerWriter) = (Box::new(stdout), Box::new(stdin));
+        let transport = framing.frame(reader, writer);
+        Ok(Self::start(
+            transport,
+            Some(child),
+            workspace,
+            handler,
+            stderr_log,
+            process.path_map.map(Arc::new),
+        ))
+    }
+
+    /// Connects to a server already listening at `target`, or replays a recording,
+    /// or starts the mock server.
+    ///
+    /// The server's stderr is not ours to capture, so nothing is added to `stderr_log`.
+    async fn connect(
+        target: &ConnectTarget,
+        workspace: PathBuf,
+        handler: ServerRequestHandler,
+        stderr_log: Arc<ServerLog>,
+        framing: TransportOptions,
+    ) -> Result<Self> {
+        tracing::debug!(%target, "Connecting to LSP server");
+        let (reader, writer): (ServerReader, ServerWriter) = match target {
+            ConnectTarget::Tcp(address) => {
+                let stream = TcpStream::connect(address).await.with_context(|| {
+                    format!("failed to connect to language server at {address}")
+                })?;
+                let (reader, writer) = stream.into_split();
+                (Box::new(reader), Box::new(writer))
+            }
+            #[cfg(unix)]
+            ConnectTarget::Unix(path) => {
+                let path = workspace.join(path);
+                let stream = tokio::net::UnixStream::connect(&path)
+                    .await
+                    .with_context(|| {
+                        format!("failed to connect to language server at {}", path.display())
+                    })?;
+                let (reader, writer) = stream.into_split();
+                (Box::new(reader), Box::new(writer))
+            }
+            #[cfg(not(unix))]
+            ConnectTarget::Unix(_) => {
+                return Err(anyhow!(
+                    "unix socket connections are not supported on this platform"
+                ));
+            }
+            ConnectTarget::Replay(path) => {
+                let (reader, writer) = tokio::io::split(recording::replay(&workspace.join(path))?);
+                (Box::new(reader), Box::new(writer))
+            }
+            ConnectTarget::Mock => {
+                let (reader, writer) = tokio::io::split(MockServer::new().spawn());
+                (Box::new(reader), Box::new(writer))
+            }
+        };
+        let transport = framing.frame(reader, writer);
+        Ok(Self::start(
+            transport, None, workspace, handler, stderr_log, None,
+        ))
+    }
+
+    /// Starts reading from the server and assembles the bridge around its transport.
+    fn start(
+        transport: FramedTransport<ServerReader, ServerWriter>,
+        child: Option<Child>,
+        workspace: PathBuf,
+        handler: ServerRequestHandler,
+        stderr_log: Arc<ServerLog>,
+        path_map: Option<Arc<PathMap>>,
+    ) -> Self {
+        let pid = child.as_ref().and_then(Child::id);
+        let history = Arc::new(MessageHistory::default());
+        let (reader, writer) = transport.with_history(history.clone()).into_split();
+        let writer = Arc::new(Mutex::new(writer));
+        let pending = Arc::new(PendingRequests::default());
+        let (notifications, _) = broadcast::channel(NOTIFICATION_CAPACITY);
+        let handler = Arc::new(handler);
+        let (exit_sender, exited) = watch::channel(false);
 
-        let transport = FramedTransport::new(stdout, stdin);
+        let reader_task = tokio::spawn(read_loop(
+            reader,
+            writer.clone(),
+            pending.clone(),
+            notifications.clone(),
+            handler.clone(),
+            path_map.clone(),
+            exit_sender,
+        ));
 
-        Ok(Self {
+        Self {
             workspace,
-            child,
-            transport,
-            next_request_id: 1,
-        })
+            child: Mutex::new(child),
+            pid,
+            started_at: Instant::now(),
+            writer,
+            pending,
+            notifications,
+            reader_task,
+            exited,
+            stderr_log,
+            history,
+            handler,
+            path_map,
+            next_request_id: AtomicI64::new(1),
+            server_capabilities: ServerCapabilities::default(),
+            server_info: None,
+            indexing_wait: DEFAULT_INDEXING_WAIT,
+            timeouts: StdRwLock::new(TimeoutConfig::default()),
+            column_encoding: PositionEncoding::default(),
+            language_ids: HashMap::new(),
+            capability_overrides: Value::Null,
+            metrics: Arc::default(),
+            latencies: LatencyWindow::default(),
+            results: ResultCache::default(),
+            queue: RequestQueue::default(),
+            in_flight: InFlight::default(),
+        }
     }
 
-    pub async fn initialize(&mut self) -> Result<()> {
+    /// Performs the LSP initialize handshake and returns the server's `InitializeResult`.
+    ///
+    /// The server's capabilities are retained and available afterwards through
+    /// [`LspBridge::server_capabilities`].
+    pub async fn initialize(&mut self) -> Result<Value> {
         let root_uri = Url::from_directory_path(&self.workspace)
             .map_err(|_| anyhow!("workspace path cannot be expressed as file URI"))?;
         let workspace_name = self
@@ -72,105 +428,393 @@ impl LspBridge {
             .file_name()
             .and_then(|s| s.to_str())
             .unwrap_or("workspace");
+        // rootUri is mapped with the rest of the params, but rootPath is a plain path
+        let root_path = match &self.path_map {
+            Some(map) => map.server_path(&self.workspace)?,
+            None => self.workspace.display().to_string(),
+        };
 
-        let params = json!({
-            "processId": std::process::id(),
-            "rootUri": root_uri,
-            "rootPath": self.workspace,
-            "capabilities": serde_json::Map::new(),
-            "workspaceFolders": [{
-                "name": workspace_name,
-                "uri": root_uri,
-            }]
-        });
+        let mut capabilities = client_capabilities(self.handler.applies_edits());
+        if !self.capability_overrides.is_null() {
+            merge_patch(&mut capabilities, &self.capability_overrides);
+        }
+        let params = InitializeParams {
+            process_id: std::process::id(),
+            root_uri: root_uri.to_string(),
+            root_path,
+            capabilities,
+            workspace_folders: vec![WorkspaceFolder {
+                uri: root_uri.to_string(),
+                name: workspace_name.to_string(),
+            }],
+        };
 
-        let _ = self.request("initialize", params).await?;
-        self.notify("initialized", json!({})).await?;
-        Ok(())
+        let result = self
+            .call::<Initialize>(&params, self.method_timeout(Initialize::METHOD))
+            .await?;
+        self.server_capabilities = ServerCapabilities::from_initialize_result(&result);
+        self.server_info = result.get("serverInfo").cloned();
+        self.handler
+            .set_position_encoding(self.server_capabilities.position_encoding());
+        self.send::<Initialized>(&InitializedParams::default())
+            .await?;
+
+        // Push settings for servers that read them from didChangeConfiguration
+        // rather than pulling them with workspace/configuration.
+        let settings = self.handler.settings();
+        if !settings.is_null() {
+            self.send::<DidChangeConfiguration>(&DidChangeConfigurationParams {
+                settings: settings.clone(),
+            })
+            .await?;
+        }
+        Ok(result)
+    }
+
+    /// Capabilities reported by the server during `initialize`.
+    ///
+    /// Empty until [`LspBridge::initialize`] has completed.
+    pub fn server_capabilities(&self) -> &ServerCapabilities {
+        &self.server_capabilities
+    }
+
+    /// Position encoding negotiated with the server.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.server_capabilities.position_encoding()
+    }
+
+    /// Root directory of the workspace the server was started for.
+    pub fn workspace(&self) -> &Path {
+        &self.workspace
+    }
+
+    /// Encoding of the columns tool callers use.
+    pub fn column_encoding(&self) -> PositionEncoding {
+        self.column_encoding
+    }
+
+    /// The `languageId` for a document: the configured override for its extension,
+    /// else the built-in table. A file without an extension is identified by its
+    /// `text` (see [`crate::sniff`]).
+    pub fn language_id<'a>(&'a self, path: &'a Path, text: &str) -> &'a str {
+        let extension = match path.extension().and_then(|ext| ext.to_str()) {
+            Some(extension) => extension,
+            None => sniff::sniff_text(text).unwrap_or(""),
+        };
+        self.language_ids
+            .get(extension)
+            .map_or_else(|| language_id_for_extension(extension), String::as_str)
+    }
+
+    /// The `serverInfo` (name and optional version) from the initialize result.
+    pub fn server_info(&self) -> Option<&Value> {
+        self.server_info.as_ref()
+    }
+
+    /// OS process id of the server, if the platform reported one at spawn time.
+    /// `None` when connected over a socket.
+    pub fn pid(&self) -> Option<u32> {
+        self.pid
+    }
+
+    /// Time since the server process was spawned (or connected to).
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    /// Work-done progress (e.g. indexing) currently reported by the server.
+    pub fn progress(&self) -> &ProgressTracker {
+        self.handler.progress()
+    }
+
+    /// Returns true while the server reports unfinished work such as indexing.
+    pub fn is_indexing(&self) -> bool {
+        self.progress().is_busy()
+    }
+
+    /// Waits for the server to finish reported work, up to the configured budget.
+    ///
+    /// Returns true if the server is idle, false if the budget ran out first.
+    pub async fn wait_until_ready(&self) -> bool {
+        self.progress().wait_idle(self.indexing_wait).await
+    }
+
+    /// Configured request timeouts.
+    pub fn timeouts(&self) -> TimeoutConfig {
+        self.timeouts
+            .read()
+            .expect("bridge timeouts lock poisoned")
+            .clone()
+    }
+
+    /// Applies `timeouts` to requests sent from now on.
+    pub fn set_timeouts(&self, timeouts: TimeoutConfig) {
+        *self
+            .timeouts
+            .write()
+            .expect("bridge timeouts lock poisoned") = timeouts;
+    }
+
+    /// Timeout for a request for `method` issued outside of any tool: its pinned
+    /// timeout, one adapted to its recent latency, or the default.
+    pub fn method_timeout(&self, method: &str) -> Duration {
+        self.timeouts
+            .read()
+            .expect("bridge timeouts lock poisoned")
+            .resolve(None, method, self.latencies.p99(method))
+    }
+
+    /// Timeout for a request for `method` issued by `tool`.
+    pub fn tool_timeout(&self, tool: &str, method: &str) -> Duration {
+        self.timeouts
+            .read()
+            .expect("bridge timeouts lock poisoned")
+            .resolve(Some(tool), method, self.latencies.p99(method))
+    }
+
+    /// Records requests into `metrics` (e.g. the supervisor's, kept across restarts)
+    /// instead of the bridge's own.
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// The most recent messages exchanged with the server.
+    pub fn history(&self) -> &Arc<MessageHistory> {
+        &self.history
+    }
+
+    /// Request counts and latencies, shared with the supervisor.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Files the server asked to hear about via `workspace/didChangeWatchedFiles`.
+    pub fn watched_files(&self) -> &WatchRegistry {
+        self.handler.watched_files()
+    }
+
+    /// Subscribes to batches of URIs modified by server-requested workspace edits.
+    pub fn subscribe_applied_edits(&self) -> broadcast::Receiver<Vec<String>> {
+        self.handler.subscribe_applied_edits()
+    }
+
+    /// Removes and returns server edits queued for review (`applyEdits = "queue"`).
+    pub fn take_queued_edits(&self) -> Vec<QueuedEdit> {
+        self.handler.take_queued_edits()
+    }
+
+    /// Returns true once the server's output stream has closed, usually because the
+    /// process exited. No further responses will arrive on this bridge.
+    pub fn has_exited(&self) -> bool {
+        *self.exited.borrow()
+    }
+
+    /// Watches the connection state; the value flips to `true` when the server exits.
+    ///
+    /// The channel closes without flipping if the bridge itself is dropped first.
+    pub fn watch_exit(&self) -> watch::Receiver<bool> {
+        self.exited.clone()
+    }
+
+    /// Lines the server has written to stderr.
+    pub fn stderr_log(&self) -> &Arc<ServerLog> {
+        &self.stderr_log
+    }
+
+    /// Subscribes to notifications sent by the LSP server.
+    ///
+    /// Only notifications received after subscribing are delivered. Slow subscribers
+    /// lose the oldest messages rather than blocking the reader.
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.notifications.subscribe()
     }
 
     /// Sends a JSON-RPC request to the LSP server and waits for the response.
     ///
     /// This method handles the request-response cycle, including:
     /// - Assigning a unique request ID
+    /// - Registering a waiter the background reader resolves with the response
     /// - Writing the request to the LSP server
-    /// - Waiting for and filtering the matching response
-    /// - Discarding unrelated notifications during the wait
-    pub async fn request(&mut self, method: &str, params: Value) -> Result<Value> {
-        let id = self.next_request_id;
-        self.next_request_id += 1;
-        let payload = json!({
-            "jsonrpc": "2.0",
-            "id": id,
-            "method": method,
-            "params": params,
-        });
-        self.transport.write(&payload).await?;
+    /// - Waiting for the response up to the request timeout
+    ///
+    /// Multiple requests may be in flight concurrently; responses are matched by id.
+    /// The timeout is the one configured for `method` (or the default). Hover,
+    /// definition, and documentSymbol results may come from the [`ResultCache`], and
+    /// identical queries already in flight share one response.
+    pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        self.request_with_timeout(method, params, self.method_timeout(method))
+            .await
+    }
+
+    /// Sends the typed request `R` with the timeout `limit`, see
+    /// [`LspBridge::request_with_timeout`]. A result not of `R`'s result type is an
+    /// error.
+    pub async fn call<R: protocol::Request>(
+        &self,
+        params: &R::Params,
+        limit: Duration,
+    ) -> Result<R::Result> {
+        let params = serde_json::to_value(params)
+            .with_context(|| format!("failed to encode {} params", R::METHOD))?;
+        let result = self.request_with_timeout(R::METHOD, params, limit).await?;
+        serde_json::from_value(result)
+            .with_context(|| format!("unexpected {} response from the language server", R::METHOD))
+    }
 
-        // Wait for the response, filtering out unrelated messages
+    /// Like [`LspBridge::request`] but with an explicit timeout, e.g. a tool's own.
+    pub async fn request_with_timeout(
+        &self,
+        method: &str,
+        params: Value,
+        limit: Duration,
+    ) -> Result<Value> {
+        if self.results.caches(method)
+            && let Some(result) = self.results.get(method, &params)
+        {
+            tracing::debug!(method, "Answered LSP request from cache");
+            return Ok(result);
+        }
+        let generation = self.results.generation();
+        if !inflight::coalesces(method) {
+            return self.round_trip(method, params, limit, generation).await;
+        }
         loop {
-            let read = timeout(REQUEST_TIMEOUT, self.transport.read()).await;
-            let message = match read {
-                Ok(inner) => inner?,
-                Err(_) => {
-                    return Err(anyhow!(
-                        "timed out after {:?} waiting for LSP response to '{}'",
-                        REQUEST_TIMEOUT,
-                        method
-                    ));
+            match self.in_flight.join(generation, method, &params) {
+                Call::Lead(lead) => {
+                    let result = self.round_trip(method, params, limit, generation).await;
+                    lead.finish(|| match &result {
+                        Ok(value) => Ok(value.clone()),
+                        Err(err) => Err(SharedError::new(err)),
+                    });
+                    return result;
                 }
-            };
-
-            match message {
-                Some(Value::Object(obj)) => {
-                    // Check if this is a response (has an "id" field)
-                    if let Some(response_id) = obj.get("id") {
-                        // Skip responses for different requests (can happen with concurrent requests)
-                        if !matches_id(response_id, id) {
-                            tracing::trace!("Skipping response for different id: {response_id:?}");
-                            continue;
+                Call::Follow(receiver) => {
+                    tracing::debug!(method, "Joined identical LSP request in flight");
+                    match timeout(limit, inflight::follow(receiver)).await {
+                        Ok(Some(result)) => return result.map_err(SharedError::into_error),
+                        // The lead gave up; send the request ourselves
+                        Ok(None) => continue,
+                        Err(_) => {
+                            return Err(anyhow!(
+                                "timed out after {limit:?} waiting for LSP response to '{method}'"
+                            ));
                         }
+                    }
+                }
+            }
+        }
+    }
 
-                        // Return successful result
-                        if let Some(result) = obj.get("result") {
-                            return Ok(result.clone());
-                        }
+    /// Sends one request once the [`RequestQueue`] admits it, and caches its result
+    /// if its method is cached.
+    async fn round_trip(
+        &self,
+        method: &str,
+        params: Value,
+        limit: Duration,
+        generation: u64,
+    ) -> Result<Value> {
+        let key_params = self.results.caches(method).then(|| params.clone());
+        let Ok(_permit) = timeout(limit, self.queue.admit(method)).await else {
+            return Err(anyhow!(
+                "timed out after {limit:?} waiting behind other requests to send '{method}'"
+            ));
+        };
 
-                        // Return error if present
-                        if let Some(error) = obj.get("error") {
-                            return Err(anyhow!("LSP error for '{}': {error:?}", method));
-                        }
+        let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
+        let span = tracing::info_span!("lsp_request", lsp.method = method, lsp.request_id = id);
+        let result = self
+            .send_request(id, method, params, limit)
+            .instrument(span)
+            .await;
+        // Answers given mid-indexing may still change without any document changing
+        if let (Some(params), Ok(value)) = (&key_params, &result)
+            && !self.is_indexing()
+        {
+            self.results.insert(generation, method, params, value);
+        }
+        result
+    }
 
-                        // Invalid response format
-                        return Err(anyhow!(
-                            "invalid LSP response for '{}': missing both result and error fields",
-                            method
-                        ));
-                    }
+    /// The body of [`LspBridge::round_trip`], run in its `lsp_request` span.
+    async fn send_request(
+        &self,
+        id: i64,
+        method: &str,
+        params: Value,
+        limit: Duration,
+    ) -> Result<Value> {
+        let (receiver, mut guard) = self.pending.register(id, method)?;
 
-                    // Discard notifications (messages without "id")
-                    tracing::trace!("discarding notification: {obj:?}");
-                }
-                Some(other) => {
-                    tracing::warn!("received unexpected non-object message: {other:?}");
-                }
-                None => {
-                    return Err(anyhow!(
-                        "LSP server terminated unexpectedly before responding to '{}'",
-                        method
-                    ));
-                }
-            }
+        let mut params = params;
+        if let Some(map) = &self.path_map {
+            map.to_server(&mut params);
         }
+        let payload = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        self.writer.lock().await.write(&payload).await?;
+        // From here on, dropping this future (timeout or MCP cancellation) tells the
+        // server to stop working on the request.
+        guard.cancel_on_drop(self.writer.clone());
+
+        let started = Instant::now();
+        let (result, outcome) = match timeout(limit, receiver).await {
+            Ok(Ok(response)) => {
+                let outcome = if response.is_ok() {
+                    Outcome::Ok
+                } else {
+                    Outcome::Error
+                };
+                self.latencies.record(method, started.elapsed());
+                (response, outcome)
+            }
+            Ok(Err(_)) => (
+                Err(anyhow!(self.history.annotate(&format!(
+                    "LSP server terminated unexpectedly before responding to '{method}'"
+                )))),
+                Outcome::Error,
+            ),
+            Err(_) => {
+                // The server needed at least this long; longer timeouts follow
+                self.latencies.record(method, limit);
+                (
+                    Err(anyhow!(self.history.annotate(&format!(
+                        "timed out after {limit:?} waiting for LSP response to '{method}'"
+                    )))),
+                    Outcome::Timeout,
+                )
+            }
+        };
+        self.metrics
+            .record_lsp_request(method, started.elapsed(), outcome);
+        result
+    }
+
+    /// Sends the typed notification `N`, see [`LspBridge::notify`].
+    pub async fn send<N: protocol::Notification>(&self, params: &N::Params) -> Result<()> {
+        let params = serde_json::to_value(params)
+            .with_context(|| format!("failed to encode {} params", N::METHOD))?;
+        self.notify(N::METHOD, params).await
     }
 
-    pub async fn notify(&mut self, method: &str, params: Value) -> Result<()> {
+    pub async fn notify(&self, method: &str, mut params: Value) -> Result<()> {
+        if cache::invalidates(method) {
+            self.results.invalidate();
+        }
+        if let Some(map) = &self.path_map {
+            map.to_server(&mut params);
+        }
         let payload = json!({
             "jsonrpc": "2.0",
             "method": method,
             "params": params,
         });
-        self.transport.write(&payload).await
+        self.writer.lock().await.write(&payload).await
     }
 
     /// Gracefully shuts down the LSP server process.
@@ -180,16 +824,27 @@ impl LspBridge {
     /// 2. Send "exit" notification
     /// 3. Wait for process to terminate
     /// 4. Force kill if any step fails or times out
-    pub async fn shutdown(mut self) -> Result<()> {
+    ///
+    /// Takes `&self` so a shared bridge can be shut down; requests issued afterwards
+    /// fail once the process is gone.
+    ///
+    /// A server reached over a socket was not started by us and is never killed; we
+    /// only wait for it to close the connection.
+    pub async fn shutdown(&self) -> Result<()> {
         tracing::debug!("Initiating graceful LSP shutdown");
+        let mut child = self.child.lock().await;
 
         // Step 1: Send shutdown request (LSP protocol requirement)
         if let Err(err) = self.request("shutdown", Value::Null).await {
-            tracing::warn!(?err, "LSP shutdown request failed; forcing kill");
-            self.child
-                .kill()
-                .await
-                .context("failed to kill LSP child after shutdown failure")?;
+            if let Some(child) = child.as_mut() {
+                tracing::warn!(?err, "LSP shutdown request failed; forcing kill");
+                child
+                    .kill()
+                    .await
+                    .context("failed to kill LSP child after shutdown failure")?;
+            } else {
+                tracing::warn!(?err, "LSP shutdown request failed");
+            }
             return Ok(());
         }
 
@@ -202,23 +857,34 @@ impl LspBridge {
         }
 
         // Step 3: Wait for process to exit, with timeout
-        match timeout(REQUEST_TIMEOUT, self.child.wait()).await {
+        let exit_timeout = self.timeouts().for_method("exit");
+        let Some(child) = child.as_mut() else {
+            let mut exited = self.watch_exit();
+            if timeout(exit_timeout, exited.wait_for(|exited| *exited))
+                .await
+                .is_err()
+            {
+                tracing::debug!("LSP server kept the connection open after exit");
+            }
+            return Ok(());
+        };
+        match timeout(exit_timeout, child.wait()).await {
             Ok(Ok(status)) => {
                 tracing::debug!(?status, "LSP server exited cleanly");
             }
             Ok(Err(err)) => {
                 tracing::warn!(?err, "Error waiting for LSP process; forcing kill");
-                self.child
+                child
                     .kill()
                     .await
                     .context("failed to kill unresponsive LSP process")?;
             }
             Err(_) => {
                 tracing::warn!(
-                    timeout = ?REQUEST_TIMEOUT,
+                    timeout = ?exit_timeout,
                     "Timed out waiting for LSP to exit; forcing kill"
                 );
-                self.child
+                child
                     .kill()
                     .await
                     .context("failed to kill timed-out LSP process")?;
@@ -228,9 +894,342 @@ impl LspBridge {
     }
 }
 
-/// Checks if a JSON value matches the expected request ID.
+impl Drop for LspBridge {
+    fn drop(&mut self) {
+        self.reader_task.abort();
+    }
+}
+
+/// How the server process is launched, beyond its command line.
+struct ProcessOptions {
+    env: Vec<(String, String)>,
+    env_remove: Vec<String>,
+    cwd: PathBuf,
+    path_map: Option<PathMap>,
+}
+
+impl ProcessOptions {
+    /// Inherits pathfinder's environment and runs in `dir`.
+    fn in_dir(dir: &std::path::Path) -> Self {
+        Self {
+            env: Vec::new(),
+            env_remove: Vec::new(),
+            cwd: dir.to_path_buf(),
+            path_map: None,
+        }
+    }
+}
+
+/// How messages to and from the server are framed.
+struct TransportOptions {
+    recorder: Option<Arc<Recorder>>,
+    max_message_bytes: usize,
+}
+
+impl Default for TransportOptions {
+    fn default() -> Self {
+        Self {
+            recorder: None,
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+        }
+    }
+}
+
+impl TransportOptions {
+    fn frame(
+        self,
+        reader: ServerReader,
+        writer: ServerWriter,
+    ) -> FramedTransport<ServerReader, ServerWriter> {
+        FramedTransport::new(reader, writer)
+            .with_recorder(self.recorder)
+            .with_max_message_bytes(self.max_message_bytes)
+    }
+}
+
+/// Requests awaiting a response, keyed by JSON-RPC id.
+#[derive(Default)]
+struct PendingRequests {
+    state: StdMutex<PendingState>,
+}
+
+#[derive(Default)]
+struct PendingState {
+    waiters: HashMap<i64, Waiter>,
+    /// Set once the reader has stopped; no further responses will arrive.
+    closed: bool,
+}
+
+struct Waiter {
+    method: String,
+    sender: oneshot::Sender<Result<Value>>,
+}
+
+impl PendingRequests {
+    /// Registers a waiter for `id`. The returned guard removes it again if the
+    /// request is abandoned (timeout or cancellation) before a response arrives.
+    fn register(
+        self: &Arc<Self>,
+        id: i64,
+        method: &str,
+    ) -> Result<(oneshot::Receiver<Result<Value>>, PendingGuard)> {
+        let mut state = self.state.lock().expect("pending requests lock poisoned");
+        if state.closed {
+            return Err(anyhow!(
+                "LSP server terminated unexpectedly before responding to '{}'",
+                method
+            ));
+        }
+        let (sender, receiver) = oneshot::channel();
+        state.waiters.insert(
+            id,
+            Waiter {
+                method: method.to_string(),
+                sender,
+            },
+        );
+        let guard = PendingGuard {
+            pending: self.clone(),
+            id,
+            writer: None,
+        };
+        Ok((receiver, guard))
+    }
+
+    fn take(&self, id: i64) -> Option<Waiter> {
+        let mut state = self.state.lock().expect("pending requests lock poisoned");
+        state.waiters.remove(&id)
+    }
+
+    /// Fails every waiter with `reason`, e.g. why the connection could not be read.
+    fn fail_all(&self, reason: &str) {
+        let mut state = self.state.lock().expect("pending requests lock poisoned");
+        for (_, waiter) in state.waiters.drain() {
+            let _ = waiter.sender.send(Err(anyhow!(
+                "connection to the LSP server failed before it responded to '{}': {reason}",
+                waiter.method
+            )));
+        }
+    }
+
+    /// Marks the connection closed and drops every waiter, failing their requests.
+    fn close(&self) {
+        let mut state = self.state.lock().expect("pending requests lock poisoned");
+        state.closed = true;
+        state.waiters.clear();
+    }
+}
+
+struct PendingGuard {
+    pending: Arc<PendingRequests>,
+    id: i64,
+    /// Set once the request has been sent; used to send `$/cancelRequest`.
+    writer: Option<SharedWriter>,
+}
+
+impl PendingGuard {
+    fn cancel_on_drop(&mut self, writer: SharedWriter) {
+        self.writer = Some(writer);
+    }
+}
+
+impl Drop for PendingGuard {
+    /// Unregisters the waiter. If no response arrived yet the request was abandoned,
+    /// so the server is asked to cancel it; the write happens on a spawned task since
+    /// `drop` cannot await.
+    fn drop(&mut self) {
+        let Some(waiter) = self.pending.take(self.id) else {
+            return;
+        };
+        let (Some(writer), Ok(runtime)) =
+            (self.writer.take(), tokio::runtime::Handle::try_current())
+        else {
+            return;
+        };
+        let id = self.id;
+        tracing::debug!(id, method = %waiter.method, "Cancelling abandoned LSP request");
+        runtime.spawn(async move {
+            let payload = json!({
+                "jsonrpc": "2.0",
+                "method": "$/cancelRequest",
+                "params": { "id": id },
+            });
+            if let Err(err) = writer.lock().await.write(&payload).await {
+                tracing::debug!(?err, id, "Failed to send $/cancelRequest");
+            }
+        });
+    }
+}
+
+/// Background task that owns the read half of the transport.
+///
+/// Runs until the server closes its output or sends an unreadable frame, then fails
+/// every outstanding request so callers don't wait for the full timeout and flags
+/// the connection as exited.
+async fn read_loop(
+    mut reader: FramedReader<ServerReader>,
+    writer: SharedWriter,
+    pending: Arc<PendingRequests>,
+    notifications: broadcast::Sender<Notification>,
+    handler: Arc<ServerRequestHandler>,
+    path_map: Option<Arc<PathMap>>,
+    exited: watch::Sender<bool>,
+) {
+    loop {
+        let mut message = match reader.read().await {
+            Ok(Some(message)) => message,
+            Ok(None) => {
+                tracing::debug!("LSP server closed its output stream");
+                break;
+            }
+            Err(err) => {
+                tracing::error!(?err, "Failed to read from LSP server; closing connection");
+                pending.fail_all(&format!("{err:#}"));
+                break;
+            }
+        };
+
+        if let Some(map) = &path_map {
+            map.to_client(&mut message);
+        }
+        let Value::Object(mut obj) = message else {
+            tracing::warn!("received unexpected non-object message: {message:?}");
+            continue;
+        };
+
+        match (obj.remove("id"), obj.remove("method")) {
+            // Response to one of our requests
+            (Some(id), None) => dispatch_response(&pending, &id, obj),
+            // Request initiated by the server
+            (Some(id), Some(Value::String(method))) => {
+                tracing::debug!(%method, "Handling server request");
+                let params = obj.remove("params").unwrap_or(Value::Null);
+                let reply = match handler.handle(&method, params).await {
+                    Ok(mut result) => {
+                        if let Some(map) = &path_map {
+                            map.to_server(&mut result);
+                        }
+                        json!({ "jsonrpc": "2.0", "id": id, "result": result })
+                    }
+                    Err(error) => {
+                        tracing::debug!(%method, ?error, "Declining server request");
+                        json!({ "jsonrpc": "2.0", "id": id, "error": error.to_json() })
+                    }
+                };
+                if let Err(err) = writer.lock().await.write(&reply).await {
+                    tracing::warn!(?err, %method, "Failed to answer server request");
+                }
+            }
+            // Notification
+            (None, Some(Value::String(method))) => {
+                tracing::trace!(%method, "received notification");
+                let params = obj.remove("params").unwrap_or(Value::Null);
+                handler.observe_notification(&method, &params);
+                // Sending only fails when nobody is subscribed, which is fine.
+                let _ = notifications.send(Notification { method, params });
+            }
+            _ => {
+                tracing::warn!("received malformed JSON-RPC message: {obj:?}");
+            }
+        }
+    }
+
+    pending.close();
+    exited.send_replace(true);
+}
+
+fn dispatch_response(
+    pending: &PendingRequests,
+    id: &Value,
+    mut obj: serde_json::Map<String, Value>,
+) {
+    let Some(waiter) = parse_id(id).and_then(|id| pending.take(id)) else {
+        tracing::trace!("Dropping response with no pending request: {id:?}");
+        return;
+    };
+
+    let method = &waiter.method;
+    let response = if let Some(result) = obj.remove("result") {
+        Ok(result)
+    } else if let Some(error) = obj.remove("error") {
+        Err(LspResponseError {
+            method: method.clone(),
+            error,
+        }
+        .into())
+    } else {
+        Err(anyhow!(
+            "invalid LSP response for '{}': missing both result and error fields",
+            method
+        ))
+    };
+    // The requester may have given up (timeout or cancellation) in the meantime.
+    let _ = waiter.sender.send(response);
+}
+
+/// Name used to tag server output: the command's file name, e.g. `rust-analyzer`.
+fn server_name(command: &str) -> String {
+    std::path::Path::new(command)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| command.to_string())
+}
+
+/// Extracts a request ID from a JSON value.
 ///
 /// LSP allows IDs to be either numbers or strings, so we handle both.
-fn matches_id(candidate: &Value, id: i64) -> bool {
-    candidate.as_i64() == Some(id) || candidate.as_str() == Some(&id.to_string())
+fn parse_id(candidate: &Value) -> Option<i64> {
+    candidate
+        .as_i64()
+        .or_else(|| candidate.as_str().and_then(|s| s.parse().ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numeric_and_string_ids() {
+        assert_eq!(parse_id(&json!(7)), Some(7));
+        assert_eq!(parse_id(&json!("7")), Some(7));
+        assert_eq!(parse_id(&json!("abc")), None);
+        assert_eq!(parse_id(&Value::Null), None);
+    }
+
+    #[tokio::test]
+    async fn responses_are_routed_to_their_waiter() {
+        let pending = Arc::new(PendingRequests::default());
+        let (first, _first_guard) = pending.register(1, "first").unwrap();
+        let (second, _second_guard) = pending.register(2, "second").unwrap();
+
+        // Responses arrive out of order
+        let mut response = serde_json::Map::new();
+        response.insert("result".into(), json!("two"));
+        dispatch_response(&pending, &json!(2), response);
+        let mut response = serde_json::Map::new();
+        response.insert("error".into(), json!({"code": 1, "message": "boom"}));
+        dispatch_response(&pending, &json!("1"), response);
+
+        assert_eq!(second.await.unwrap().unwrap(), json!("two"));
+        let err = first.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("LSP error for 'first'"));
+        assert!(err.downcast_ref::<LspResponseError>().is_some());
+    }
+
+    #[tokio::test]
+    async fn abandoned_requests_are_unregistered() {
+        let pending = Arc::new(PendingRequests::default());
+        let (_receiver, guard) = pending.register(1, "hover").unwrap();
+        drop(guard);
+        assert!(pending.take(1).is_none());
+    }
+
+    #[tokio::test]
+    async fn closing_fails_outstanding_and_future_requests() {
+        let pending = Arc::new(PendingRequests::default());
+        let (receiver, _guard) = pending.register(1, "definition").unwrap();
+        pending.close();
+        assert!(receiver.await.is_err());
+        assert!(pending.register(2, "definition").is_err());
+    }
 }
@@ -1,72 +1,480 @@
 use std::env;
-use std::path::PathBuf;
+use std::future::IntoFuture;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
-use anyhow::{Result, anyhow};
+use anyhow::{Context, Result, anyhow, bail};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
 use tracing_subscriber::{EnvFilter, fmt};
 
+use rmcp::transport::streamable_http_server::{
+    StreamableHttpServerConfig, StreamableHttpService, session::local::LocalSessionManager,
+};
 use rmcp::{ServiceExt, transport::stdio};
+use tokio_util::sync::CancellationToken;
 
 use clap::Parser;
 
-use pathfinder::args::Cli;
-use pathfinder::config::Config;
+use pathfinder::args::{
+    Cli, Command, DoctorArgs, ENV_CONFIG, ENV_SERVER, LogArgs, LogFormat, LogRotation, ManArgs,
+    QueryArgs, ServerArgs, Transport, TransportArgs, man_pages, write_completions,
+};
+use pathfinder::client_log::ClientLog;
+use pathfinder::config::{Config, DISCOVERED_FILE_NAMES};
+use pathfinder::daemon::{self, Daemon};
+use pathfinder::doctor;
+use pathfinder::init;
+use pathfinder::query;
+use pathfinder::reload::{self, ConfigOverrides};
 use pathfinder::service::PathfinderService;
+use pathfinder::telemetry::{self, Telemetry};
+use pathfinder::utils::resolve_workspace_base;
+
+/// Path of the Streamable HTTP endpoint.
+const HTTP_MCP_PATH: &str = "/mcp";
+
+/// Path of the Prometheus endpoint served with `--metrics`.
+const HTTP_METRICS_PATH: &str = "/metrics";
+
+/// How long to wait for HTTP clients to disconnect after a shutdown signal.
+const HTTP_SHUTDOWN_GRACE: Duration = Duration::from_secs(5);
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    init_tracing()?;
-
     let cli = Cli::parse();
-    let workspace_arg = cli.workspace.clone();
-    let server_specs = cli.to_server_specs()?;
+    let (client_log, telemetry) = init_tracing(&cli.log_args)?;
 
-    // Extract the single server spec (CLI always produces one spec)
-    let server_spec = server_specs
-        .into_iter()
-        .next()
-        .ok_or_else(|| anyhow!("no server specification provided"))?;
+    let result = match cli.command {
+        Some(Command::Doctor(args)) => run_doctor(args).await,
+        Some(Command::Init(args)) => init::run(args),
+        Some(Command::Query(args)) => run_query(args).await,
+        Some(Command::Completions(args)) => {
+            write_completions(args.shell, &mut std::io::stdout());
+            Ok(())
+        }
+        Some(Command::Man(args)) => run_man(args),
+        None => run_server(cli.server_args, cli.transport_args, client_log, &telemetry).await,
+    };
+    telemetry.shutdown();
+    result
+}
 
-    let config = Config::from_server_spec(server_spec)?;
+async fn run_server(
+    mut server_args: ServerArgs,
+    transport_args: TransportArgs,
+    client_log: ClientLog,
+    telemetry: &Telemetry,
+) -> Result<()> {
+    resolve_server_args(&mut server_args)?;
+    let workspace_arg = workspace_from_args(&server_args);
+    let config_file = server_args.config.clone();
+    let overrides = overrides_from_args(&server_args)?;
+    let config = config_from_args(server_args)?;
+    let workspace_base = resolve_workspace_base(workspace_arg)?;
 
-    let workspace_base = if let Some(ws) = workspace_arg {
-        canonical_path(ws)?
+    if transport_args.metrics && transport_args.transport != Transport::Http {
+        bail!("--metrics only works with --transport http");
+    }
+    let daemon_socket = if transport_args.attach {
+        if transport_args.transport != Transport::Stdio {
+            bail!("--attach only works with --transport stdio");
+        }
+        let socket = match transport_args.socket {
+            Some(socket) => socket,
+            None => daemon::socket_path(&config, &workspace_base)?,
+        };
+        if daemon::attach(&socket).await? {
+            return Ok(());
+        }
+        Some(socket)
     } else {
-        env::current_dir().map_err(|err| anyhow!("failed to get current directory: {err}"))?
+        None
     };
 
     tracing::info!(
         workspace_base = %workspace_base.display(),
         extensions = ?config.server.extensions,
         command = ?config.server.command,
+        connect = ?config.server.connect,
+        transport = ?transport_args.transport,
         "Starting pathfinder"
     );
 
-    let service = PathfinderService::new(config, workspace_base).await?;
-    let server = service.serve(stdio()).await?;
-    server.waiting().await?;
+    let service = PathfinderService::new(config, workspace_base)
+        .await?
+        .with_client_log(client_log);
+    let daemon = match &daemon_socket {
+        Some(socket) => Some(Daemon::listen(socket, &service)?),
+        None => None,
+    };
+    let reloader = config_file.and_then(|path| {
+        reload::watch(&path, overrides, service.clone())
+            .inspect_err(|err| tracing::warn!("Config reloading disabled: {err:#}"))
+            .ok()
+    });
+    // Stop serving on SIGINT/SIGTERM, even mid-handshake; stdin EOF ends a stdio
+    // session on its own.
+    let interrupted = CancellationToken::new();
+    let signal = tokio::spawn({
+        let interrupted = interrupted.clone();
+        async move {
+            let code = wait_for_signal().await;
+            interrupted.cancel();
+            code
+        }
+    });
+
+    let result = match transport_args.transport {
+        Transport::Stdio => serve_stdio(service.clone(), &interrupted).await,
+        Transport::Http => {
+            serve_http(
+                service.clone(),
+                transport_args.bind,
+                transport_args.metrics,
+                &interrupted,
+            )
+            .await
+        }
+    };
+    if let Some(daemon) = daemon {
+        daemon.finish(&interrupted).await;
+    }
+    if let Some(reloader) = reloader {
+        reloader.abort();
+    }
+    service.shutdown().await;
+    telemetry.shutdown();
+
+    // Interrupted: exit like a killed process would. Exiting directly also avoids
+    // waiting on the runtime's blocked stdin reader thread.
+    if interrupted.is_cancelled() {
+        std::process::exit(signal.await.unwrap_or(1));
+    }
+    if let Err(err) = result {
+        tracing::error!("{err:#}");
+        std::process::exit(1);
+    }
+    signal.abort();
+    Ok(())
+}
+
+/// Serves a single MCP client over stdin/stdout until it disconnects or
+/// `interrupted` fires.
+async fn serve_stdio(service: PathfinderService, interrupted: &CancellationToken) -> Result<()> {
+    let session = tokio::select! {
+        session = service.serve(stdio()) => session,
+        _ = interrupted.cancelled() => return Ok(()),
+    };
+    let server = session.map_err(|err| anyhow!(err).context("MCP initialization failed"))?;
+    let session_token = server.cancellation_token();
+    let forward = tokio::spawn({
+        let interrupted = interrupted.clone();
+        async move {
+            interrupted.cancelled().await;
+            session_token.cancel();
+        }
+    });
+    let waited = server.waiting().await;
+    forward.abort();
+    match waited {
+        Ok(quit_reason) => {
+            tracing::info!(?quit_reason, "MCP session ended; shutting down");
+            Ok(())
+        }
+        Err(err) => Err(anyhow!(err).context("MCP session task failed")),
+    }
+}
+
+/// Serves MCP over Streamable HTTP at [`HTTP_MCP_PATH`] until `interrupted` fires.
+///
+/// Every client session gets its own service from [`PathfinderService::new_session`]:
+/// sessions share the language server but keep their own cursors and overlays.
+async fn serve_http(
+    service: PathfinderService,
+    bind: SocketAddr,
+    metrics: bool,
+    interrupted: &CancellationToken,
+) -> Result<()> {
+    let mut router = axum::Router::new();
+    if metrics {
+        let service = service.clone();
+        router = router.route(
+            HTTP_METRICS_PATH,
+            axum::routing::get(move || async move {
+                (
+                    [(
+                        axum::http::header::CONTENT_TYPE,
+                        "text/plain; version=0.0.4",
+                    )],
+                    service.metrics().to_prometheus(),
+                )
+            }),
+        );
+    }
+    let mcp = StreamableHttpService::new(
+        move || Ok(service.new_session()),
+        Arc::new(LocalSessionManager::default()),
+        StreamableHttpServerConfig::default(),
+    );
+    let router = router.nest_service(HTTP_MCP_PATH, mcp);
+    let listener = tokio::net::TcpListener::bind(bind)
+        .await
+        .with_context(|| format!("failed to listen on {bind}"))?;
+    let address = listener.local_addr().unwrap_or(bind);
+    tracing::info!("Serving MCP over HTTP at http://{address}{HTTP_MCP_PATH}");
+    if metrics {
+        tracing::info!("Serving metrics at http://{address}{HTTP_METRICS_PATH}");
+    }
+
+    let server = axum::serve(listener, router)
+        .with_graceful_shutdown(interrupted.clone().cancelled_owned())
+        .into_future();
+    // Open SSE streams keep connections alive indefinitely, so stop waiting for
+    // them after a grace period.
+    let deadline = async {
+        interrupted.cancelled().await;
+        tokio::time::sleep(HTTP_SHUTDOWN_GRACE).await;
+    };
+    tokio::select! {
+        served = server => served.context("HTTP server failed"),
+        _ = deadline => {
+            tracing::info!("Closing remaining HTTP connections");
+            Ok(())
+        }
+    }
+}
+
+/// Waits for SIGINT or SIGTERM and returns the conventional exit code (128 + signal).
+#[cfg(unix)]
+async fn wait_for_signal() -> i32 {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let (Ok(mut interrupt), Ok(mut terminate)) = (
+        signal(SignalKind::interrupt()),
+        signal(SignalKind::terminate()),
+    ) else {
+        tracing::warn!("Failed to install signal handlers");
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = interrupt.recv() => {
+            tracing::info!("Received SIGINT");
+            130
+        }
+        _ = terminate.recv() => {
+            tracing::info!("Received SIGTERM");
+            143
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() -> i32 {
+    match tokio::signal::ctrl_c().await {
+        Ok(()) => {
+            tracing::info!("Received Ctrl-C");
+            130
+        }
+        Err(err) => {
+            tracing::warn!(?err, "Failed to listen for Ctrl-C");
+            std::future::pending().await
+        }
+    }
+}
+
+async fn run_doctor(mut args: DoctorArgs) -> Result<()> {
+    resolve_server_args(&mut args.server_args)?;
+    let workspace_arg = workspace_from_args(&args.server_args);
+    let config = config_from_args(args.server_args)?;
+
+    let report = doctor::diagnose(&config, workspace_arg).await;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print!("{}", report.render_text());
+    }
+
+    if report.is_healthy() {
+        Ok(())
+    } else {
+        Err(anyhow!("doctor found problems with the server setup"))
+    }
+}
+
+async fn run_query(mut args: QueryArgs) -> Result<()> {
+    resolve_server_args(&mut args.server_args)?;
+    let workspace_arg = workspace_from_args(&args.server_args);
+    let server_args = std::mem::take(&mut args.server_args);
+    let config = config_from_args(server_args)?;
+    let workspace_base = resolve_workspace_base(workspace_arg)?;
+
+    let result = query::run(config, workspace_base, &args).await?;
+    let output = query::render(&result, args.json)?;
+    if result.is_error == Some(true) {
+        bail!("{output}");
+    }
+    println!("{output}");
+    Ok(())
+}
+
+fn run_man(args: ManArgs) -> Result<()> {
+    let pages = man_pages()?;
+    let Some(dir) = args.dir else {
+        let (_, page) = &pages[0];
+        std::io::stdout().write_all(page)?;
+        return Ok(());
+    };
+    std::fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    for (name, page) in pages {
+        let path = dir.join(name);
+        std::fs::write(&path, page)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn config_from_args(server_args: ServerArgs) -> Result<Config> {
+    let overrides = overrides_from_args(&server_args)?;
+    if let Some(path) = &server_args.config {
+        return overrides.load(path);
+    }
+    let server_specs = server_args.to_server_specs()?;
+
+    // Extract the single server spec (CLI always produces one spec)
+    let server_spec = server_specs
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("no server specification provided"))?;
+
+    let mut config = Config::from_server_spec(server_spec)?;
+    overrides.apply(&mut config);
+    Ok(config)
+}
+
+/// The flags applied over the config, whether it comes from a file or the command
+/// line.
+fn overrides_from_args(server_args: &ServerArgs) -> Result<ConfigOverrides> {
+    Ok(ConfigOverrides {
+        disabled_tools: server_args.disable_tool.clone(),
+        no_sandbox: server_args.no_sandbox,
+        read_only: server_args.readonly,
+        // Relative to where pathfinder was started, not the workspace root
+        record: match &server_args.record {
+            Some(path) => Some(std::path::absolute(path)?),
+            None => None,
+        },
+    })
+}
+
+/// Completes the server flags, in order of precedence: the command line, then
+/// `PATHFINDER_*` variables, then the workspace's own config file (see
+/// [`Config::discover`]).
+fn resolve_server_args(server_args: &mut ServerArgs) -> Result<()> {
+    server_args.apply_env(|name| env::var(name).ok())?;
+    if server_args.config.is_some() || !server_args.extension.is_empty() {
+        return Ok(());
+    }
+    let start = match &server_args.workspace {
+        Some(workspace) => workspace.clone(),
+        None => env::current_dir()?,
+    };
+    let path = Config::discover(&start).ok_or_else(|| {
+        anyhow!(
+            "no language server given: pass -e and -s or -c FILE, set {ENV_CONFIG} or {ENV_SERVER}, or add one of {} to the workspace",
+            DISCOVERED_FILE_NAMES.join(", ")
+        )
+    })?;
+    tracing::info!(config = %path.display(), "Using workspace config");
+    server_args.config = Some(path);
     Ok(())
 }
 
-fn init_tracing() -> Result<()> {
+/// Picks the workspace base: `--workspace` if given, otherwise the directory
+/// containing `--config` so relative `rootDir` values resolve next to the file.
+fn workspace_from_args(server_args: &ServerArgs) -> Option<PathBuf> {
+    server_args.workspace.clone().or_else(|| {
+        server_args
+            .config
+            .as_ref()
+            .and_then(|path| path.parent())
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+    })
+}
+
+/// Logs to stderr, or to `--log-file`, per `RUST_LOG`/`LOG_LEVEL` and in the
+/// `--log-format`, and to the MCP client through the returned [`ClientLog`] at
+/// whatever level the client asks for. Spans are also exported over OTLP if
+/// configured (see [`pathfinder::telemetry`]).
+fn init_tracing(log: &LogArgs) -> Result<(ClientLog, Telemetry)> {
     let env_filter = EnvFilter::try_from_default_env()
         .or_else(|_| EnvFilter::try_new(env::var("LOG_LEVEL").unwrap_or_else(|_| "info".into())))?;
 
-    fmt::Subscriber::builder()
-        .with_env_filter(env_filter)
-        .with_target(false)
-        .with_writer(std::io::stderr)
+    let client_log = ClientLog::default();
+    let writer = match &log.log_file {
+        Some(path) => BoxMakeWriter::new(log_file(path, log.log_rotation, log.log_keep)?),
+        None => BoxMakeWriter::new(std::io::stderr),
+    };
+    let output = match log.log_format {
+        LogFormat::Text => fmt::layer()
+            .with_target(false)
+            .with_ansi(log.log_file.is_none())
+            .with_writer(writer)
+            .with_filter(env_filter)
+            .boxed(),
+        LogFormat::Json => fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .with_writer(writer)
+            .with_filter(env_filter)
+            .boxed(),
+    };
+    let (otlp, telemetry) = Telemetry::init()?;
+    tracing_subscriber::registry()
+        .with(output)
+        .with(client_log.clone())
+        .with(otlp)
         .init();
-    Ok(())
+    if telemetry::requested() && !telemetry::AVAILABLE {
+        tracing::warn!(
+            "OTEL_EXPORTER_OTLP_ENDPOINT is set, but pathfinder was built without the otel feature; traces are not exported"
+        );
+    }
+    Ok((client_log, telemetry))
 }
 
-fn canonical_path(path: PathBuf) -> Result<PathBuf> {
-    let abs = if path.is_absolute() {
-        path
-    } else {
-        env::current_dir()
-            .map_err(|err| anyhow!("failed to read current directory: {err}"))?
-            .join(path)
+/// A writer appending to `path`, starting a new file per hour or day with
+/// `rotation` and keeping at most `keep` of them.
+fn log_file(
+    path: &Path,
+    rotation: LogRotation,
+    keep: Option<usize>,
+) -> Result<RollingFileAppender> {
+    let prefix = path
+        .file_name()
+        .ok_or_else(|| anyhow!("--log-file {} is not a file path", path.display()))?;
+    let dir = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let rotation = match rotation {
+        LogRotation::Never => Rotation::NEVER,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
     };
-    abs.canonicalize()
-        .map_err(|err| anyhow!("failed to canonicalize path: {err}"))
+    let mut builder = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(prefix.to_string_lossy());
+    if let Some(keep) = keep {
+        builder = builder.max_log_files(keep);
+    }
+    builder
+        .build(dir)
+        .with_context(|| format!("failed to open log file {}", path.display()))
 }
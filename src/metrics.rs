@@ -0,0 +1,303 @@
+//! Counters and latency histograms for tool calls and LSP requests.
+//!
+//! The supervisor owns one [`Metrics`] for the life of the process and hands it to
+//! every bridge it launches, so counts carry over server restarts. The `get_metrics`
+//! tool returns a [`MetricsReport`]; with `--metrics`, the HTTP transport also serves
+//! it in the Prometheus text format at `/metrics`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// Upper bounds of the latency histogram buckets, in milliseconds.
+pub const LATENCY_BUCKETS_MS: &[u64] =
+    &[5, 10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000];
+
+/// How a tool call or LSP request ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Error,
+    /// No response within the request's timeout (LSP requests only).
+    Timeout,
+}
+
+/// Why the language server was replaced.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestartReason {
+    /// It exited or stopped responding.
+    Crash,
+    /// `restart_server` was called.
+    Manual,
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    state: Mutex<MetricsReport>,
+}
+
+/// Serializable snapshot of [`Metrics`].
+#[derive(Debug, Clone, Default, Serialize, schemars::JsonSchema)]
+pub struct MetricsReport {
+    /// MCP tool calls, by tool
+    pub tools: BTreeMap<String, Latency>,
+    /// Requests sent to the language server, by LSP method
+    pub lsp_requests: BTreeMap<String, Latency>,
+    /// Tool-level retries (e.g. `definition` retrying an empty result), by tool
+    pub retries: BTreeMap<String, u64>,
+    /// Server restarts after a crash
+    pub crash_restarts: u64,
+    /// Server restarts requested through `restart_server`
+    pub manual_restarts: u64,
+    /// Upper bounds of the `buckets` of every latency histogram, in milliseconds
+    pub bucket_bounds_ms: Vec<u64>,
+}
+
+/// Call counts and a latency histogram for one tool or LSP method.
+#[derive(Debug, Clone, Default, Serialize, schemars::JsonSchema)]
+pub struct Latency {
+    pub count: u64,
+    /// Calls that failed, timeouts included
+    pub errors: u64,
+    /// Calls that got no response in time (LSP requests only)
+    pub timeouts: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+    /// Calls that took at most the matching `bucket_bounds_ms` entry; cumulative,
+    /// like Prometheus buckets
+    pub buckets: Vec<u64>,
+}
+
+impl Latency {
+    fn record(&mut self, elapsed: Duration, outcome: Outcome) {
+        let ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS_MS.len()];
+        }
+        self.count += 1;
+        match outcome {
+            Outcome::Ok => {}
+            Outcome::Error => self.errors += 1,
+            Outcome::Timeout => {
+                self.errors += 1;
+                self.timeouts += 1;
+            }
+        }
+        self.total_ms = self.total_ms.saturating_add(ms);
+        self.max_ms = self.max_ms.max(ms);
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS_MS) {
+            if ms <= *bound {
+                *bucket += 1;
+            }
+        }
+    }
+}
+
+impl Metrics {
+    /// Records one MCP tool call.
+    pub fn record_tool(&self, tool: &str, elapsed: Duration, outcome: Outcome) {
+        let mut state = self.state.lock().expect("metrics lock poisoned");
+        state
+            .tools
+            .entry(tool.to_string())
+            .or_default()
+            .record(elapsed, outcome);
+    }
+
+    /// Records one request to the language server.
+    pub fn record_lsp_request(&self, method: &str, elapsed: Duration, outcome: Outcome) {
+        let mut state = self.state.lock().expect("metrics lock poisoned");
+        state
+            .lsp_requests
+            .entry(method.to_string())
+            .or_default()
+            .record(elapsed, outcome);
+    }
+
+    /// Records that `tool` tried again after an unusable result.
+    pub fn record_retry(&self, tool: &str) {
+        let mut state = self.state.lock().expect("metrics lock poisoned");
+        *state.retries.entry(tool.to_string()).or_default() += 1;
+    }
+
+    pub fn record_restart(&self, reason: RestartReason) {
+        let mut state = self.state.lock().expect("metrics lock poisoned");
+        match reason {
+            RestartReason::Crash => state.crash_restarts += 1,
+            RestartReason::Manual => state.manual_restarts += 1,
+        }
+    }
+
+    pub fn report(&self) -> MetricsReport {
+        let state = self.state.lock().expect("metrics lock poisoned");
+        MetricsReport {
+            bucket_bounds_ms: LATENCY_BUCKETS_MS.to_vec(),
+            ..state.clone()
+        }
+    }
+}
+
+impl MetricsReport {
+    /// The report in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        histogram(
+            &mut out,
+            "pathfinder_tool_call",
+            "MCP tool call latency",
+            "tool",
+            &self.tools,
+        );
+        histogram(
+            &mut out,
+            "pathfinder_lsp_request",
+            "Language server request latency",
+            "method",
+            &self.lsp_requests,
+        );
+        counter_header(
+            &mut out,
+            "pathfinder_tool_retries_total",
+            "Tool-level retries",
+        );
+        for (tool, retries) in &self.retries {
+            let _ = writeln!(
+                out,
+                "pathfinder_tool_retries_total{{tool=\"{}\"}} {retries}",
+                escape(tool)
+            );
+        }
+        counter_header(
+            &mut out,
+            "pathfinder_server_restarts_total",
+            "Language server restarts",
+        );
+        for (reason, restarts) in [
+            ("crash", self.crash_restarts),
+            ("manual", self.manual_restarts),
+        ] {
+            let _ = writeln!(
+                out,
+                "pathfinder_server_restarts_total{{reason=\"{reason}\"}} {restarts}"
+            );
+        }
+        out
+    }
+}
+
+/// A histogram in seconds plus error and timeout counters, one series per key.
+fn histogram(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    entries: &BTreeMap<String, Latency>,
+) {
+    let _ = writeln!(out, "# HELP {name}_duration_seconds {help}");
+    let _ = writeln!(out, "# TYPE {name}_duration_seconds histogram");
+    for (key, latency) in entries {
+        let key = escape(key);
+        for (bucket, bound) in latency.buckets.iter().zip(LATENCY_BUCKETS_MS) {
+            let _ = writeln!(
+                out,
+                "{name}_duration_seconds_bucket{{{label}=\"{key}\",le=\"{}\"}} {bucket}",
+                seconds(*bound)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{name}_duration_seconds_bucket{{{label}=\"{key}\",le=\"+Inf\"}} {}",
+            latency.count
+        );
+        let _ = writeln!(
+            out,
+            "{name}_duration_seconds_sum{{{label}=\"{key}\"}} {}",
+            seconds(latency.total_ms)
+        );
+        let _ = writeln!(
+            out,
+            "{name}_duration_seconds_count{{{label}=\"{key}\"}} {}",
+            latency.count
+        );
+    }
+    for (suffix, what) in [("errors", "failed"), ("timeouts", "timed out")] {
+        let metric = format!("{name}_{suffix}_total");
+        counter_header(out, &metric, &format!("Calls that {what}"));
+        for (key, latency) in entries {
+            let count = match suffix {
+                "errors" => latency.errors,
+                _ => latency.timeouts,
+            };
+            let _ = writeln!(out, "{metric}{{{label}=\"{}\"}} {count}", escape(key));
+        }
+    }
+}
+
+fn counter_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {name} {help}");
+    let _ = writeln!(out, "# TYPE {name} counter");
+}
+
+fn seconds(ms: u64) -> f64 {
+    ms as f64 / 1000.0
+}
+
+/// Escapes a label value for the text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_latency_and_renders_prometheus() {
+        let metrics = Metrics::default();
+        metrics.record_lsp_request(
+            "textDocument/definition",
+            Duration::from_millis(40),
+            Outcome::Ok,
+        );
+        metrics.record_lsp_request(
+            "textDocument/definition",
+            Duration::from_secs(20),
+            Outcome::Timeout,
+        );
+        metrics.record_tool("definition", Duration::from_millis(3), Outcome::Error);
+        metrics.record_retry("definition");
+        metrics.record_restart(RestartReason::Manual);
+
+        let report = metrics.report();
+        let definition = &report.lsp_requests["textDocument/definition"];
+        assert_eq!(
+            (definition.count, definition.errors, definition.timeouts),
+            (2, 1, 1)
+        );
+        assert_eq!(definition.max_ms, 20000);
+        // 40ms falls in the 50ms bucket and everything above it
+        assert_eq!(definition.buckets[..5], [0, 0, 0, 1, 1]);
+        assert_eq!(definition.buckets[LATENCY_BUCKETS_MS.len() - 1], 2);
+        assert_eq!(report.tools["definition"].errors, 1);
+        assert_eq!(report.retries["definition"], 1);
+        assert_eq!((report.crash_restarts, report.manual_restarts), (0, 1));
+
+        let text = report.to_prometheus();
+        assert!(text.contains(
+            "pathfinder_lsp_request_duration_seconds_bucket{method=\"textDocument/definition\",le=\"0.05\"} 1\n"
+        ));
+        assert!(text.contains(
+            "pathfinder_lsp_request_duration_seconds_count{method=\"textDocument/definition\"} 2\n"
+        ));
+        assert!(text.contains(
+            "pathfinder_lsp_request_timeouts_total{method=\"textDocument/definition\"} 1\n"
+        ));
+        assert!(text.contains("pathfinder_server_restarts_total{reason=\"manual\"} 1\n"));
+    }
+}
@@ -0,0 +1,421 @@
+//! Middleware around tool calls: an ordered list of hooks that see, and may change,
+//! each call's arguments before the tool runs and its result afterwards.
+//!
+//! Arguments pass through the middleware in order and results in reverse order, so the
+//! first middleware is the outermost. The built-in middleware is named in the `tools`
+//! config:
+//!
+//! ```toml
+//! [[tools.middleware]]
+//! name = "rewrite_paths"
+//! client = "/home/me/project"   # the workspace as the client sees it
+//! server = "/workspace"         # and as pathfinder does, e.g. in a container
+//!
+//! [[tools.middleware]]
+//! name = "redact"
+//! globs = ["**/.env", "secrets/**"]
+//!
+//! [[tools.middleware]]
+//! name = "audit"
+//! ```
+//!
+//! Crates running the service add their own with
+//! [`crate::service::PathfinderService::with_middleware`], after the configured ones.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
+use globset::GlobSet;
+use rmcp::model::{CallToolResult, Content, JsonObject, RawContent};
+use serde_json::Value;
+
+use crate::config::MiddlewareConfig;
+use crate::tools::files::build_globs;
+use crate::utils::{strip_path_prefix, uri_to_file_path};
+
+/// A hook around every tool call.
+pub trait Middleware: Send + Sync {
+    /// Name used in logs and in the error of a refused call.
+    fn name(&self) -> &str;
+
+    /// Sees the arguments of a call to `tool` before it runs; an error refuses the
+    /// call, which then fails with the error's message.
+    fn on_request(&self, _tool: &str, _arguments: &mut JsonObject) -> Result<()> {
+        Ok(())
+    }
+
+    /// Sees the result of a call to `tool`, refused calls included. If the structured
+    /// content changes, the service redoes the text content from it.
+    fn on_response(&self, _tool: &str, _result: &mut CallToolResult) {}
+}
+
+/// The middleware of a service, in order.
+#[derive(Clone, Default)]
+pub struct MiddlewareChain {
+    layers: Vec<Arc<dyn Middleware>>,
+}
+
+impl MiddlewareChain {
+    /// The built-in middleware the config names, for the workspace at `workspace`.
+    pub fn from_config(configs: &[MiddlewareConfig], workspace: &Path) -> Result<Self> {
+        let mut chain = Self::default();
+        for config in configs {
+            let layer: Arc<dyn Middleware> = match config {
+                MiddlewareConfig::Audit => Arc::new(Audit),
+                MiddlewareConfig::Redact { globs } => Arc::new(Redact::new(globs, workspace)?),
+                MiddlewareConfig::RewritePaths { client, server } => {
+                    Arc::new(RewritePaths::new(client, server)?)
+                }
+            };
+            chain.push(layer);
+        }
+        Ok(chain)
+    }
+
+    pub fn push(&mut self, layer: Arc<dyn Middleware>) {
+        self.layers.push(layer);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.layers.is_empty()
+    }
+
+    /// Runs the request hooks in order, stopping at the first refusal, which is
+    /// returned as an error result.
+    pub fn request(
+        &self,
+        tool: &str,
+        arguments: &mut Option<JsonObject>,
+    ) -> Result<(), CallToolResult> {
+        if self.layers.is_empty() {
+            return Ok(());
+        }
+        let mut object = arguments.take().unwrap_or_default();
+        let outcome = self.layers.iter().try_for_each(|layer| {
+            layer.on_request(tool, &mut object).map_err(|err| {
+                tracing::info!(tool, middleware = layer.name(), "Tool call refused");
+                CallToolResult::error(vec![Content::text(format!(
+                    "{tool} refused by {}: {err:#}",
+                    layer.name()
+                ))])
+            })
+        });
+        // Calls without arguments stay without, unless a hook added some
+        if !object.is_empty() {
+            *arguments = Some(object);
+        }
+        outcome
+    }
+
+    /// Runs the response hooks in reverse order.
+    pub fn response(&self, tool: &str, result: &mut CallToolResult) {
+        for layer in self.layers.iter().rev() {
+            layer.on_response(tool, result);
+        }
+    }
+}
+
+/// Logs every call's arguments and outcome at info level, under the
+/// `pathfinder::audit` target.
+struct Audit;
+
+impl Middleware for Audit {
+    fn name(&self) -> &str {
+        "audit"
+    }
+
+    fn on_request(&self, tool: &str, arguments: &mut JsonObject) -> Result<()> {
+        let arguments = Value::Object(arguments.clone());
+        tracing::info!(target: "pathfinder::audit", tool, %arguments, "Tool call");
+        Ok(())
+    }
+
+    fn on_response(&self, tool: &str, result: &mut CallToolResult) {
+        let error = result.is_error == Some(true);
+        let bytes = result
+            .structured_content
+            .as_ref()
+            .map_or(0, |structured| structured.to_string().len());
+        tracing::info!(target: "pathfinder::audit", tool, error, bytes, "Tool result");
+    }
+}
+
+/// Hides files matching globs (relative to the workspace): calls naming one in a
+/// `uri` or `path` argument are refused, and results drop the list items that
+/// refer to one.
+struct Redact {
+    workspace: PathBuf,
+    globs: GlobSet,
+}
+
+impl Redact {
+    fn new(globs: &[String], workspace: &Path) -> Result<Self> {
+        let Some(globs) = build_globs(globs)? else {
+            bail!("redact middleware needs at least one glob");
+        };
+        Ok(Self {
+            workspace: workspace.to_path_buf(),
+            globs,
+        })
+    }
+
+    /// Whether `value`, a file:// URI or a path, names a redacted file. A file://
+    /// URI that does not parse is treated as redacted, since what it names is unknown.
+    fn redacted(&self, value: &str) -> bool {
+        let path = if value.starts_with("file://") {
+            match uri_to_file_path(value) {
+                Ok(path) => path,
+                Err(_) => return true,
+            }
+        } else {
+            PathBuf::from(value)
+        };
+        let relative = if path.is_absolute() {
+            match strip_path_prefix(&path, &self.workspace) {
+                Some(relative) => relative,
+                None => return false,
+            }
+        } else {
+            &path
+        };
+        self.globs.is_match(relative)
+    }
+
+    /// Whether a list item refers to a redacted file: a path, or an object with a
+    /// `uri` or `path` field naming one.
+    fn redacted_item(&self, item: &Value) -> bool {
+        match item {
+            Value::String(path) => self.redacted(path),
+            Value::Object(object) => ["uri", "path"].iter().any(|key| {
+                object
+                    .get(*key)
+                    .and_then(Value::as_str)
+                    .is_some_and(|value| self.redacted(value))
+            }),
+            _ => false,
+        }
+    }
+
+    /// Finds a `uri` or `path` argument naming a redacted file, at any depth.
+    fn find_redacted<'a>(&self, object: &'a JsonObject) -> Option<&'a str> {
+        object.iter().find_map(|(key, value)| match value {
+            Value::String(path) if (key == "uri" || key == "path") && self.redacted(path) => {
+                Some(path.as_str())
+            }
+            Value::Object(object) => self.find_redacted(object),
+            Value::Array(items) => items
+                .iter()
+                .filter_map(Value::as_object)
+                .find_map(|object| self.find_redacted(object)),
+            _ => None,
+        })
+    }
+
+    /// Drops redacted items from every list in `value`, returning how many.
+    fn scrub(&self, value: &mut Value) -> usize {
+        match value {
+            Value::Array(items) => {
+                let before = items.len();
+                items.retain(|item| !self.redacted_item(item));
+                let dropped = before - items.len();
+                dropped + items.iter_mut().map(|item| self.scrub(item)).sum::<usize>()
+            }
+            Value::Object(object) => object.values_mut().map(|value| self.scrub(value)).sum(),
+            _ => 0,
+        }
+    }
+}
+
+impl Middleware for Redact {
+    fn name(&self) -> &str {
+        "redact"
+    }
+
+    fn on_request(&self, _tool: &str, arguments: &mut JsonObject) -> Result<()> {
+        match self.find_redacted(arguments) {
+            Some(path) => bail!("{path} is redacted"),
+            None => Ok(()),
+        }
+    }
+
+    fn on_response(&self, tool: &str, result: &mut CallToolResult) {
+        if let Some(structured) = &mut result.structured_content {
+            let dropped = self.scrub(structured);
+            if dropped > 0 {
+                tracing::debug!(tool, dropped, "Redacted result items");
+            }
+        }
+    }
+}
+
+/// Maps absolute paths and file:// URIs between the client's view of the workspace
+/// and pathfinder's, e.g. when pathfinder runs in a container with the project
+/// mounted elsewhere: client paths in arguments become server paths, and server paths
+/// in results become client paths.
+struct RewritePaths {
+    client: String,
+    server: String,
+}
+
+impl RewritePaths {
+    fn new(client: &str, server: &str) -> Result<Self> {
+        let trim = |prefix: &str| prefix.trim_end_matches('/').to_string();
+        let (client, server) = (trim(client), trim(server));
+        if !client.starts_with('/') || !server.starts_with('/') {
+            bail!("rewrite_paths middleware needs absolute client and server paths");
+        }
+        Ok(Self { client, server })
+    }
+
+    /// Replaces a leading `from` (as a path or in a file:// URI) in every string in
+    /// `value` with `to`.
+    fn rewrite(value: &mut Value, from: &str, to: &str) {
+        match value {
+            Value::String(text) => {
+                for scheme in ["", "file://"] {
+                    let prefix = format!("{scheme}{from}");
+                    if let Some(rest) = text.strip_prefix(&prefix)
+                        && (rest.is_empty() || rest.starts_with('/'))
+                    {
+                        *text = format!("{scheme}{to}{rest}");
+                        break;
+                    }
+                }
+            }
+            Value::Array(items) => items
+                .iter_mut()
+                .for_each(|item| Self::rewrite(item, from, to)),
+            Value::Object(object) => object
+                .values_mut()
+                .for_each(|value| Self::rewrite(value, from, to)),
+            _ => {}
+        }
+    }
+}
+
+impl Middleware for RewritePaths {
+    fn name(&self) -> &str {
+        "rewrite_paths"
+    }
+
+    fn on_request(&self, _tool: &str, arguments: &mut JsonObject) -> Result<()> {
+        for value in arguments.values_mut() {
+            Self::rewrite(value, &self.client, &self.server);
+        }
+        Ok(())
+    }
+
+    fn on_response(&self, _tool: &str, result: &mut CallToolResult) {
+        if let Some(structured) = &mut result.structured_content {
+            Self::rewrite(structured, &self.server, &self.client);
+        }
+        // Error messages and summaries mention paths anywhere in their text
+        let (server, client) = (format!("{}/", self.server), format!("{}/", self.client));
+        for content in &mut result.content {
+            if let RawContent::Text(text) = &mut content.raw {
+                text.text = text.text.replace(&server, &client);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn arguments(value: Value) -> Option<JsonObject> {
+        value.as_object().cloned()
+    }
+
+    #[test]
+    fn rewrites_paths_both_ways() {
+        let config = [MiddlewareConfig::RewritePaths {
+            client: "/home/me/project/".to_string(),
+            server: "/workspace".to_string(),
+        }];
+        let chain = MiddlewareChain::from_config(&config, Path::new("/workspace")).unwrap();
+
+        let mut request = arguments(json!({
+            "uri": "file:///home/me/project/src/main.rs",
+            "path": "/home/me/projects/other",
+        }));
+        chain.request("definition", &mut request).unwrap();
+        let request = request.unwrap();
+        assert_eq!(request["uri"], json!("file:///workspace/src/main.rs"));
+        assert_eq!(request["path"], json!("/home/me/projects/other"));
+
+        let mut result = CallToolResult::structured(json!({
+            "targets": [{"uri": "file:///workspace/src/lib.rs"}],
+        }));
+        chain.response("definition", &mut result);
+        assert_eq!(
+            result.structured_content.unwrap()["targets"][0]["uri"],
+            json!("file:///home/me/project/src/lib.rs")
+        );
+        let text = result.content[0].as_text().unwrap();
+        assert!(text.text.contains("file:///home/me/project/src/lib.rs"));
+    }
+
+    #[test]
+    fn redacts_requests_and_results() {
+        let config = [MiddlewareConfig::Redact {
+            globs: vec!["**/.env".to_string(), "secrets/**".to_string()],
+        }];
+        let chain = MiddlewareChain::from_config(&config, Path::new("/workspace")).unwrap();
+
+        let mut request = arguments(json!({"uri": "file:///workspace/secrets/key.pem"}));
+        let refused = chain.request("read_file_range", &mut request).unwrap_err();
+        assert_eq!(refused.is_error, Some(true));
+        let mut request = arguments(json!({"path": "src/main.rs", "query": ".env"}));
+        assert!(chain.request("search_text", &mut request).is_ok());
+        let mut request = None;
+        assert!(chain.request("server_status", &mut request).is_ok());
+        assert!(request.is_none());
+
+        let mut result = CallToolResult::structured(json!({
+            "files": ["src/main.rs", "config/.env", "secrets/key.pem"],
+            "matches": [
+                {"path": "src/main.rs", "line": 1},
+                {"uri": "file:///workspace/.env", "line": 2},
+            ],
+        }));
+        chain.response("list_workspace_files", &mut result);
+        assert_eq!(
+            result.structured_content.unwrap(),
+            json!({"files": ["src/main.rs"], "matches": [{"path": "src/main.rs", "line": 1}]})
+        );
+    }
+
+    #[test]
+    fn runs_requests_in_order_and_responses_in_reverse() {
+        struct Tag(&'static str);
+        impl Middleware for Tag {
+            fn name(&self) -> &str {
+                self.0
+            }
+            fn on_request(&self, _tool: &str, arguments: &mut JsonObject) -> Result<()> {
+                let seen = arguments.entry("seen").or_insert(json!(""));
+                *seen = json!(format!("{}{}", seen.as_str().unwrap(), self.0));
+                Ok(())
+            }
+            fn on_response(&self, _tool: &str, result: &mut CallToolResult) {
+                let structured = result.structured_content.get_or_insert(json!({}));
+                let seen = structured["seen"].as_str().unwrap_or("").to_string();
+                structured["seen"] = json!(format!("{seen}{}", self.0));
+            }
+        }
+        let mut chain = MiddlewareChain::default();
+        chain.push(Arc::new(Tag("a")));
+        chain.push(Arc::new(Tag("b")));
+
+        let mut request = None;
+        chain.request("custom", &mut request).unwrap();
+        assert_eq!(request.unwrap()["seen"], json!("ab"));
+        let mut result = CallToolResult::success(Vec::new());
+        chain.response("custom", &mut result);
+        assert_eq!(result.structured_content.unwrap()["seen"], json!("ba"));
+    }
+}
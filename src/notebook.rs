@@ -0,0 +1,302 @@
+//! Jupyter notebooks (`.ipynb`) as LSP notebook documents.
+//!
+//! Servers that advertise `notebookDocumentSync` (pylance and other
+//! pyright-compatible servers) are sent notebooks with `notebookDocument/didOpen`,
+//! `didChange`, `didSave`, and `didClose` instead of the raw JSON. Each cell becomes a
+//! virtual text document with a `vscode-notebook-cell:` URI, the scheme those servers
+//! expect, whose fragment names the cell: `#cell3` is the fourth cell of the notebook.
+//!
+//! Tools address a cell as `path/to/notebook.ipynb#cell3` (or by its cell URI), and
+//! lines and columns are relative to the cell, as the server counts them. Locations
+//! the server returns in cells are reported back in the same form.
+
+use anyhow::{Context, Result, anyhow};
+use serde_json::{Value, json};
+
+/// URI scheme of notebook cells.
+const CELL_SCHEME: &str = "vscode-notebook-cell:";
+
+/// Fragment prefix naming a cell by index.
+const CELL_FRAGMENT: &str = "#cell";
+
+/// Language of code cells when the notebook does not say.
+const DEFAULT_LANGUAGE: &str = "python";
+
+/// LSP `NotebookCellKind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellKind {
+    /// Markdown and raw cells
+    Markup = 1,
+    Code = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub kind: CellKind,
+    pub language: String,
+    pub text: String,
+}
+
+/// The cells of a parsed `.ipynb` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notebook {
+    pub cells: Vec<Cell>,
+}
+
+impl Notebook {
+    /// Parses the JSON of an `.ipynb` file (nbformat 4).
+    pub fn parse(text: &str) -> Result<Self> {
+        let json: Value = serde_json::from_str(text).context("notebook is not valid JSON")?;
+        let language = json
+            .pointer("/metadata/kernelspec/language")
+            .or_else(|| json.pointer("/metadata/language_info/name"))
+            .and_then(Value::as_str)
+            .unwrap_or(DEFAULT_LANGUAGE);
+        let cells = json
+            .get("cells")
+            .and_then(Value::as_array)
+            .ok_or_else(|| anyhow!("notebook has no cells array"))?;
+        let cells = cells
+            .iter()
+            .map(|cell| {
+                let (kind, language) = match cell.get("cell_type").and_then(Value::as_str) {
+                    Some("code") => (CellKind::Code, language),
+                    Some("markdown") => (CellKind::Markup, "markdown"),
+                    _ => (CellKind::Markup, "plaintext"),
+                };
+                // nbformat stores sources as a string or as a list of lines
+                let text = match cell.get("source") {
+                    Some(Value::String(text)) => text.clone(),
+                    Some(Value::Array(lines)) => lines.iter().filter_map(Value::as_str).collect(),
+                    _ => String::new(),
+                };
+                Cell {
+                    kind,
+                    language: language.to_string(),
+                    text,
+                }
+            })
+            .collect();
+        Ok(Self { cells })
+    }
+
+    /// `notebookDocument/didOpen` params, opening every cell at `version`.
+    pub fn did_open(&self, uri: &str, version: i32) -> Value {
+        json!({
+            "notebookDocument": {
+                "uri": uri,
+                "notebookType": "jupyter-notebook",
+                "version": version,
+                "cells": self.notebook_cells(uri),
+            },
+            "cellTextDocuments": self.cell_documents(uri, version),
+        })
+    }
+
+    /// `notebookDocument/didChange` params turning `previous` into `self`.
+    ///
+    /// When the cells are the same in number and kind, only the cells whose text
+    /// changed are sent, each as a full-text change. Otherwise every cell is replaced,
+    /// since cells are identified by index.
+    pub fn did_change(&self, previous: &Notebook, uri: &str, version: i32) -> Value {
+        let same_layout = self.cells.len() == previous.cells.len()
+            && self
+                .cells
+                .iter()
+                .zip(&previous.cells)
+                .all(|(cell, old)| cell.kind == old.kind && cell.language == old.language);
+        let cells = if same_layout {
+            let text_content: Vec<Value> = self
+                .cells
+                .iter()
+                .zip(&previous.cells)
+                .enumerate()
+                .filter(|(_, (cell, old))| cell.text != old.text)
+                .map(|(index, (cell, _))| {
+                    json!({
+                        "document": { "uri": cell_uri(uri, index), "version": version },
+                        "changes": [{ "text": cell.text }],
+                    })
+                })
+                .collect();
+            json!({ "textContent": text_content })
+        } else {
+            json!({
+                "structure": {
+                    "array": {
+                        "start": 0,
+                        "deleteCount": previous.cells.len(),
+                        "cells": self.notebook_cells(uri),
+                    },
+                    "didOpen": self.cell_documents(uri, version),
+                    "didClose": previous.cell_identifiers(uri),
+                },
+            })
+        };
+        json!({
+            "notebookDocument": { "uri": uri, "version": version },
+            "change": { "cells": cells },
+        })
+    }
+
+    /// `notebookDocument/didClose` params, closing every cell.
+    pub fn did_close(&self, uri: &str) -> Value {
+        json!({
+            "notebookDocument": { "uri": uri },
+            "cellTextDocuments": self.cell_identifiers(uri),
+        })
+    }
+
+    /// Text of every cell, keyed by cell URI.
+    pub fn cell_texts(&self, uri: &str) -> impl Iterator<Item = (String, String)> {
+        self.cells
+            .iter()
+            .enumerate()
+            .map(move |(index, cell)| (cell_uri(uri, index), cell.text.clone()))
+    }
+
+    /// The cell at `index`, or an error naming how many there are.
+    pub fn cell(&self, index: usize) -> Result<&Cell> {
+        self.cells.get(index).ok_or_else(|| {
+            anyhow!(
+                "no cell {index}: the notebook has {} cells (counted from 0)",
+                self.cells.len()
+            )
+        })
+    }
+
+    fn notebook_cells(&self, uri: &str) -> Vec<Value> {
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| json!({ "kind": cell.kind as u8, "document": cell_uri(uri, index) }))
+            .collect()
+    }
+
+    fn cell_documents(&self, uri: &str, version: i32) -> Vec<Value> {
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                json!({
+                    "uri": cell_uri(uri, index),
+                    "languageId": cell.language,
+                    "version": version,
+                    "text": cell.text,
+                })
+            })
+            .collect()
+    }
+
+    fn cell_identifiers(&self, uri: &str) -> Vec<Value> {
+        (0..self.cells.len())
+            .map(|index| json!({ "uri": cell_uri(uri, index) }))
+            .collect()
+    }
+}
+
+/// Whether `uri` (or path) names a notebook file.
+pub fn is_notebook(uri: &str) -> bool {
+    uri.rsplit(['/', '\\'])
+        .next()
+        .is_some_and(|name| name.to_ascii_lowercase().ends_with(".ipynb"))
+}
+
+/// URI of cell `index` of the notebook at the file:// URI `notebook`.
+pub fn cell_uri(notebook: &str, index: usize) -> String {
+    let path = notebook.strip_prefix("file://").unwrap_or(notebook);
+    format!("{CELL_SCHEME}{path}{CELL_FRAGMENT}{index}")
+}
+
+/// The notebook's file:// URI and the cell index of a cell URI.
+pub fn split_cell_uri(uri: &str) -> Option<(String, usize)> {
+    let rest = uri.strip_prefix(CELL_SCHEME)?;
+    let (path, index) = split_fragment(rest)?;
+    Some((format!("file://{path}"), index))
+}
+
+/// A tool's reference to a cell, as a cell URI or as a notebook path or file:// URI
+/// followed by `#cell<index>`: the notebook part, unnormalized, and the index.
+pub fn split_cell_reference(input: &str) -> Option<(String, usize)> {
+    if let Some(split) = split_cell_uri(input) {
+        return Some(split);
+    }
+    let (notebook, index) = split_fragment(input)?;
+    is_notebook(notebook).then(|| (notebook.to_string(), index))
+}
+
+/// `notebook.ipynb#cell3` for a cell URI in the notebook at `relative` (a path
+/// relative to the workspace).
+pub fn cell_reference(relative: &str, index: usize) -> String {
+    format!("{relative}{CELL_FRAGMENT}{index}")
+}
+
+fn split_fragment(input: &str) -> Option<(&str, usize)> {
+    let (path, index) = input.rsplit_once(CELL_FRAGMENT)?;
+    Some((path, index.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTEBOOK: &str = r##"{
+        "metadata": {"kernelspec": {"language": "python", "name": "python3"}},
+        "nbformat": 4,
+        "cells": [
+            {"cell_type": "markdown", "source": "# Title"},
+            {"cell_type": "code", "source": ["import os\n", "os.getcwd()"]}
+        ]
+    }"##;
+
+    #[test]
+    fn parses_cells_and_sync_params() {
+        let notebook = Notebook::parse(NOTEBOOK).unwrap();
+        assert_eq!(notebook.cells[0].kind, CellKind::Markup);
+        assert_eq!(notebook.cells[1].language, "python");
+        assert_eq!(notebook.cells[1].text, "import os\nos.getcwd()");
+
+        let uri = "file:///work/nb.ipynb";
+        let open = notebook.did_open(uri, 1);
+        assert_eq!(
+            open["notebookDocument"]["cells"][1],
+            json!({"kind": 2, "document": "vscode-notebook-cell:/work/nb.ipynb#cell1"})
+        );
+        assert_eq!(open["cellTextDocuments"][0]["languageId"], "markdown");
+
+        let mut edited = notebook.clone();
+        edited.cells[1].text = "import sys\n".to_string();
+        let change = edited.did_change(&notebook, uri, 2);
+        assert_eq!(
+            change["change"]["cells"]["textContent"],
+            json!([{
+                "document": {"uri": "vscode-notebook-cell:/work/nb.ipynb#cell1", "version": 2},
+                "changes": [{"text": "import sys\n"}],
+            }])
+        );
+
+        edited.cells.pop();
+        let change = edited.did_change(&notebook, uri, 3);
+        let structure = &change["change"]["cells"]["structure"];
+        assert_eq!(structure["array"]["deleteCount"], 2);
+        assert_eq!(structure["didClose"].as_array().unwrap().len(), 2);
+        assert_eq!(structure["didOpen"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn splits_cell_uris_and_references() {
+        let uri = cell_uri("file:///work/my%20nb.ipynb", 3);
+        assert_eq!(uri, "vscode-notebook-cell:/work/my%20nb.ipynb#cell3");
+        assert_eq!(
+            split_cell_uri(&uri),
+            Some(("file:///work/my%20nb.ipynb".to_string(), 3))
+        );
+        assert_eq!(
+            split_cell_reference("notebooks/nb.ipynb#cell0"),
+            Some(("notebooks/nb.ipynb".to_string(), 0))
+        );
+        assert_eq!(split_cell_reference("src/main.rs#cell0"), None);
+        assert_eq!(split_cell_reference("nb.ipynb#cellX"), None);
+        assert!(is_notebook("file:///work/NB.IPYNB"));
+    }
+}
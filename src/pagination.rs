@@ -0,0 +1,243 @@
+//! Cursor-based pagination of list-returning tools.
+//!
+//! A query whose results do not fit in one page is cached in a [`ResultPages`] and
+//! the caller gets a `next_cursor`. Passing that cursor back (with the same query
+//! inputs) returns the following page from the cache instead of asking the language
+//! server again. Cursors expire after [`CURSOR_TTL`] without use, and a query's cache
+//! entry is dropped once its last page has been served.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, anyhow, bail};
+use serde::Deserialize;
+
+/// Results per page when the caller gives no `limit`.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+/// Upper bound on `limit`.
+pub const MAX_PAGE_SIZE: usize = 1000;
+/// How long an unused cursor stays valid.
+pub const CURSOR_TTL: Duration = Duration::from_secs(300);
+/// Paginated queries kept at once; the one expiring first is dropped beyond this.
+const MAX_CACHED_QUERIES: usize = 32;
+
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct PageRequest {
+    /// `next_cursor` from a previous call with the same inputs, to fetch the next page
+    pub cursor: Option<String>,
+    /// Maximum number of results to return (default 100, at most 1000)
+    pub limit: Option<usize>,
+}
+
+impl PageRequest {
+    fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+}
+
+/// A tool request with pagination inputs alongside its own.
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct Paged<T> {
+    #[serde(flatten)]
+    pub request: T,
+    #[serde(flatten)]
+    pub page: PageRequest,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Number of results the query produced, across all pages
+    pub total: usize,
+    pub next_cursor: Option<String>,
+}
+
+/// Cached results of paginated queries, addressed by cursor.
+#[derive(Debug)]
+pub struct ResultPages<T> {
+    queries: StdMutex<Queries<T>>,
+}
+
+#[derive(Debug)]
+struct Queries<T> {
+    next_id: u64,
+    entries: HashMap<u64, CachedQuery<T>>,
+}
+
+#[derive(Debug)]
+struct CachedQuery<T> {
+    /// Identifies the query inputs, so a cursor is not replayed against another query
+    key: String,
+    items: Arc<Vec<T>>,
+    expires: Instant,
+}
+
+impl<T> Default for ResultPages<T> {
+    fn default() -> Self {
+        Self {
+            queries: StdMutex::new(Queries {
+                next_id: 0,
+                entries: HashMap::new(),
+            }),
+        }
+    }
+}
+
+impl<T: Clone> ResultPages<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Serves the page `page.cursor` points at, or `None` when there is no cursor
+    /// and the query has to be run.
+    pub fn resume(&self, key: &str, page: &PageRequest) -> Result<Option<Page<T>>> {
+        let Some(cursor) = &page.cursor else {
+            return Ok(None);
+        };
+        let (id, offset) = parse_cursor(cursor)?;
+        let mut queries = self.queries.lock().expect("result pages lock poisoned");
+        let now = Instant::now();
+        queries.entries.retain(|_, query| query.expires > now);
+        let query = queries.entries.get_mut(&id).ok_or_else(|| {
+            anyhow!("cursor has expired or is unknown; repeat the query without a cursor")
+        })?;
+        if query.key != key {
+            bail!("cursor belongs to a different query");
+        }
+        if offset > query.items.len() {
+            bail!("cursor is out of range");
+        }
+
+        let items = Arc::clone(&query.items);
+        let end = (offset + page.limit()).min(items.len());
+        let next_cursor = if end < items.len() {
+            query.expires = now + CURSOR_TTL;
+            Some(format_cursor(id, end))
+        } else {
+            queries.entries.remove(&id);
+            None
+        };
+        Ok(Some(Page {
+            items: items[offset..end].to_vec(),
+            total: items.len(),
+            next_cursor,
+        }))
+    }
+
+    /// Returns the first page of a query's results, caching the rest under `key`.
+    pub fn first(&self, key: String, items: Vec<T>, page: &PageRequest) -> Page<T> {
+        let limit = page.limit();
+        let total = items.len();
+        if total <= limit {
+            return Page {
+                items,
+                total,
+                next_cursor: None,
+            };
+        }
+
+        let first = items[..limit].to_vec();
+        let mut queries = self.queries.lock().expect("result pages lock poisoned");
+        let now = Instant::now();
+        queries.entries.retain(|_, query| query.expires > now);
+        while queries.entries.len() >= MAX_CACHED_QUERIES {
+            let Some(oldest) = queries
+                .entries
+                .iter()
+                .min_by_key(|(_, query)| query.expires)
+                .map(|(id, _)| *id)
+            else {
+                break;
+            };
+            queries.entries.remove(&oldest);
+        }
+        let id = queries.next_id;
+        queries.next_id += 1;
+        queries.entries.insert(
+            id,
+            CachedQuery {
+                key,
+                items: Arc::new(items),
+                expires: now + CURSOR_TTL,
+            },
+        );
+        Page {
+            items: first,
+            total,
+            next_cursor: Some(format_cursor(id, limit)),
+        }
+    }
+}
+
+fn format_cursor(id: u64, offset: usize) -> String {
+    format!("{id}:{offset}")
+}
+
+fn parse_cursor(cursor: &str) -> Result<(u64, usize)> {
+    cursor
+        .split_once(':')
+        .and_then(|(id, offset)| Some((id.parse().ok()?, offset.parse().ok()?)))
+        .ok_or_else(|| anyhow!("malformed cursor: {cursor}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(cursor: Option<String>, limit: usize) -> PageRequest {
+        PageRequest {
+            cursor,
+            limit: Some(limit),
+        }
+    }
+
+    #[test]
+    fn pages_through_cached_results() {
+        let pages = ResultPages::new();
+        let first = pages.first("q".into(), (0..5).collect(), &page(None, 2));
+        assert_eq!(first.items, vec![0, 1]);
+        assert_eq!(first.total, 5);
+
+        let second = pages
+            .resume("q", &page(first.next_cursor, 2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(second.items, vec![2, 3]);
+        let last = pages
+            .resume("q", &page(second.next_cursor.clone(), 2))
+            .unwrap()
+            .unwrap();
+        assert_eq!(last.items, vec![4]);
+        assert_eq!(last.next_cursor, None);
+
+        // Serving the last page drops the query
+        assert!(pages.resume("q", &page(second.next_cursor, 2)).is_err());
+        assert!(pages.resume("q", &page(None, 2)).unwrap().is_none());
+    }
+
+    #[test]
+    fn small_results_are_not_cached() {
+        let pages = ResultPages::new();
+        let only = pages.first("q".into(), vec![1, 2], &PageRequest::default());
+        assert_eq!(only.next_cursor, None);
+        assert!(pages.queries.lock().unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn rejects_foreign_and_expired_cursors() {
+        let pages = ResultPages::new();
+        let first = pages.first("q".into(), (0..5).collect(), &page(None, 2));
+        let cursor = first.next_cursor;
+        assert!(pages.resume("other", &page(cursor.clone(), 2)).is_err());
+        assert!(pages.resume("q", &page(Some("bogus".into()), 2)).is_err());
+
+        for query in pages.queries.lock().unwrap().entries.values_mut() {
+            query.expires = Instant::now();
+        }
+        let err = pages.resume("q", &page(cursor, 2)).unwrap_err();
+        assert!(err.to_string().contains("expired"));
+    }
+}
@@ -0,0 +1,420 @@
+//! Tools added without changing `service.rs`.
+//!
+//! A [`PathfinderTool`] is offered next to the built-in tools: `tools/list` includes
+//! its definition and `call_tool` routes calls by name to it, inside the same
+//! `tool_call` span and under the same response limit and metrics. Downstream crates
+//! register theirs with
+//! [`PathfinderService::with_tool`](crate::service::PathfinderService::with_tool). The
+//! `custom` list of the `tools` config declares [`TemplateTool`]s, each sending one LSP
+//! request built from a params template:
+//!
+//! ```toml
+//! [[tools.custom]]
+//! name = "expand_macro_at"
+//! description = "Expand the macro call at a position"
+//! method = "rust-analyzer/expandMacro"
+//! params = { textDocument = { uri = "${uri}" }, position = { line = "${line}", character = "${character}" } }
+//! ```
+//!
+//! A string that is exactly `${name}` is replaced by the argument's JSON value, and
+//! `${name}` within a longer string by its text. Every placeholder is a required
+//! argument: `uri` is a document, normalized, sandboxed, and synced to the server like
+//! the built-in tools' `uri`; `line` and `character` are zero-based integers, with
+//! `character` in the configured column encoding and converted to the server's; any
+//! other argument is a string.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{Arc, LazyLock};
+
+use anyhow::{Result, anyhow, bail};
+use regex::Regex;
+use rmcp::model::{JsonObject, Tool, ToolAnnotations};
+use serde_json::{Value, json};
+
+use crate::config::CustomToolConfig;
+use crate::lsp_bridge::LspBridge;
+use crate::position::PositionConverter;
+use crate::sandbox::Sandbox;
+use crate::session::SessionId;
+use crate::supervisor::LspSupervisor;
+use crate::tools::request::RESERVED_METHODS;
+
+/// `${name}` placeholders in templates.
+static PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\$\{(\w+)\}").expect("valid placeholder pattern"));
+
+/// The result of [`PathfinderTool::call`].
+pub type ToolFuture<'a> = Pin<Box<dyn Future<Output = Result<Value>> + Send + 'a>>;
+
+/// A tool offered alongside the built-in ones.
+pub trait PathfinderTool: Send + Sync {
+    /// The name clients call the tool by; must not clash with another tool.
+    fn name(&self) -> &str;
+
+    fn description(&self) -> &str;
+
+    /// JSON Schema of the arguments; any object by default.
+    fn input_schema(&self) -> Value {
+        json!({ "type": "object" })
+    }
+
+    /// Whether calls leave the workspace and the server's state alone (default true);
+    /// other tools are annotated as destructive.
+    fn read_only(&self) -> bool {
+        true
+    }
+
+    /// Runs the tool. The result is sent as structured content, so it must be an
+    /// object; an error becomes an error result naming the tool.
+    fn call<'a>(&'a self, context: ToolContext<'a>, arguments: JsonObject) -> ToolFuture<'a>;
+}
+
+/// What a [`PathfinderTool`] can reach during a call.
+pub struct ToolContext<'a> {
+    supervisor: &'a LspSupervisor,
+    sandbox: &'a Sandbox,
+    session: SessionId,
+}
+
+impl<'a> ToolContext<'a> {
+    pub(crate) fn new(
+        supervisor: &'a LspSupervisor,
+        sandbox: &'a Sandbox,
+        session: SessionId,
+    ) -> Self {
+        Self {
+            supervisor,
+            sandbox,
+            session,
+        }
+    }
+
+    /// The workspace root (canonical).
+    pub fn workspace(&self) -> &Path {
+        self.supervisor.workspace()
+    }
+
+    /// The bridge to the server, once it is ready.
+    pub async fn lsp(&self) -> Result<Arc<LspBridge>> {
+        self.supervisor.ready_bridge().await
+    }
+
+    /// Checks a document argument against the sandbox and syncs the document to the
+    /// server, like the built-in tools do; returns its file:// URI.
+    pub async fn open(&self, document: &str) -> Result<String> {
+        let uri = self.sandbox.document_uri(document)?;
+        let lsp = self.lsp().await?;
+        self.supervisor
+            .documents()
+            .ensure_open_for(&lsp, &uri, self.session)
+            .await?;
+        Ok(uri)
+    }
+
+    /// Unsaved texts of overlay documents, by URI.
+    pub async fn overlay_texts(&self) -> HashMap<String, String> {
+        self.supervisor.documents().overlay_texts().await
+    }
+}
+
+/// Registered tools, by name.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn PathfinderTool>>,
+}
+
+impl ToolRegistry {
+    /// A registry with the tools declared in the config.
+    pub fn from_config(custom: &[CustomToolConfig]) -> Result<Self> {
+        let mut registry = Self::default();
+        for config in custom {
+            registry.register(Arc::new(TemplateTool::new(config.clone())?))?;
+        }
+        Ok(registry)
+    }
+
+    /// Adds `tool`; fails if another has its name.
+    pub fn register(&mut self, tool: Arc<dyn PathfinderTool>) -> Result<()> {
+        let name = tool.name();
+        if name.is_empty() {
+            bail!("custom tool has an empty name");
+        }
+        if self.get(name).is_some() {
+            bail!("tool '{name}' is registered twice");
+        }
+        self.tools.push(tool);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn PathfinderTool>> {
+        self.tools.iter().find(|tool| tool.name() == name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.tools.iter().map(|tool| tool.name())
+    }
+
+    /// Drops the tools `keep` says no to.
+    pub fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        self.tools.retain(|tool| keep(tool.name()));
+    }
+
+    /// The tools as listed in `tools/list`.
+    pub fn definitions(&self) -> Vec<Tool> {
+        self.tools
+            .iter()
+            .map(|tool| definition(tool.as_ref()))
+            .collect()
+    }
+}
+
+fn definition(tool: &dyn PathfinderTool) -> Tool {
+    let schema = match tool.input_schema() {
+        Value::Object(schema) => schema,
+        _ => JsonObject::new(),
+    };
+    let read_only = tool.read_only();
+    let mut definition = Tool::new(
+        tool.name().to_string(),
+        tool.description().to_string(),
+        Arc::new(schema),
+    );
+    definition.annotations = Some(ToolAnnotations {
+        title: None,
+        read_only_hint: Some(read_only),
+        destructive_hint: Some(!read_only),
+        idempotent_hint: None,
+        open_world_hint: Some(false),
+    });
+    definition
+}
+
+/// A config-declared tool sending one LSP request; see the [module docs](self).
+pub struct TemplateTool {
+    config: CustomToolConfig,
+    /// Argument names, each once.
+    arguments: Vec<String>,
+}
+
+impl TemplateTool {
+    pub fn new(config: CustomToolConfig) -> Result<Self> {
+        if RESERVED_METHODS.contains(&config.method.as_str()) {
+            bail!(
+                "custom tool '{}' calls {}, which pathfinder manages itself",
+                config.name,
+                config.method
+            );
+        }
+        let mut arguments = Vec::new();
+        placeholders(&config.params, &mut arguments);
+        Ok(Self { config, arguments })
+    }
+}
+
+impl PathfinderTool for TemplateTool {
+    fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    fn description(&self) -> &str {
+        &self.config.description
+    }
+
+    fn input_schema(&self) -> Value {
+        let properties: serde_json::Map<String, Value> = self
+            .arguments
+            .iter()
+            .map(|name| {
+                let schema = match name.as_str() {
+                    "uri" => json!({
+                        "type": "string",
+                        "description": "file:// URI of the document, or its absolute or workspace-relative path",
+                    }),
+                    "line" => json!({
+                        "type": "integer",
+                        "minimum": 0,
+                        "description": "Zero-based line index",
+                    }),
+                    "character" => json!({
+                        "type": "integer",
+                        "minimum": 0,
+                        "description": "Zero-based character index, in the configured column encoding",
+                    }),
+                    _ => json!({ "type": "string" }),
+                };
+                (name.clone(), schema)
+            })
+            .collect();
+        json!({
+            "type": "object",
+            "properties": properties,
+            "required": self.arguments,
+        })
+    }
+
+    fn read_only(&self) -> bool {
+        self.config.read_only
+    }
+
+    fn call<'a>(&'a self, context: ToolContext<'a>, mut arguments: JsonObject) -> ToolFuture<'a> {
+        Box::pin(async move {
+            if let Some(missing) = self
+                .arguments
+                .iter()
+                .find(|name| !arguments.contains_key(name.as_str()))
+            {
+                bail!("missing argument '{missing}'");
+            }
+            if let Some(document) = arguments.get("uri").and_then(Value::as_str) {
+                let uri = context.open(document).await?;
+                arguments.insert("uri".to_string(), Value::String(uri));
+            }
+            let lsp = context.lsp().await?;
+            let position = |key: &str| arguments.get(key).and_then(Value::as_u64);
+            if let Some(uri) = arguments.get("uri").and_then(Value::as_str)
+                && let (Some(line), Some(character)) = (position("line"), position("character"))
+            {
+                let mut converter =
+                    PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+                        .with_texts(context.overlay_texts().await);
+                let character = converter
+                    .to_server(uri, line as u32, character as u32)
+                    .await;
+                arguments.insert("character".to_string(), Value::from(character));
+            }
+            let params = fill(&self.config.params, &arguments)?;
+            let result = lsp.request(&self.config.method, params).await?;
+            Ok(json!({ "result": result }))
+        })
+    }
+}
+
+/// Collects the placeholder names in `template`, each once.
+fn placeholders(template: &Value, names: &mut Vec<String>) {
+    match template {
+        Value::String(text) => {
+            for found in PLACEHOLDER.captures_iter(text) {
+                let name = &found[1];
+                if !names.iter().any(|known| known == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| placeholders(item, names)),
+        Value::Object(fields) => fields.values().for_each(|field| placeholders(field, names)),
+        _ => {}
+    }
+}
+
+/// `template` with its placeholders replaced by `arguments`.
+fn fill(template: &Value, arguments: &JsonObject) -> Result<Value> {
+    let argument = |name: &str| {
+        arguments
+            .get(name)
+            .ok_or_else(|| anyhow!("missing argument '{name}'"))
+    };
+    Ok(match template {
+        Value::String(text) => match PLACEHOLDER.captures(text) {
+            Some(found) if found[0].len() == text.len() => argument(&found[1])?.clone(),
+            Some(_) => {
+                let mut filled = String::new();
+                let mut last = 0;
+                for found in PLACEHOLDER.captures_iter(text) {
+                    let whole = found.get(0).expect("match has a whole group");
+                    filled.push_str(&text[last..whole.start()]);
+                    match argument(&found[1])? {
+                        Value::String(value) => filled.push_str(value),
+                        value => filled.push_str(&value.to_string()),
+                    }
+                    last = whole.end();
+                }
+                filled.push_str(&text[last..]);
+                Value::String(filled)
+            }
+            None => template.clone(),
+        },
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| fill(item, arguments))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(fields) => Value::Object(
+            fields
+                .iter()
+                .map(|(key, field)| Ok((key.clone(), fill(field, arguments)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn custom(params: Value) -> CustomToolConfig {
+        CustomToolConfig {
+            name: "expand_macro_at".to_string(),
+            description: "Expand the macro call at a position".to_string(),
+            method: "rust-analyzer/expandMacro".to_string(),
+            params,
+            read_only: true,
+        }
+    }
+
+    #[test]
+    fn fills_templates_with_arguments() {
+        let template = json!({
+            "textDocument": {"uri": "${uri}"},
+            "position": {"line": "${line}", "character": "${character}"},
+            "label": "${name} at ${line}",
+            "fixed": [1, "plain"],
+        });
+        let tool = TemplateTool::new(custom(template.clone())).unwrap();
+        // Object fields are visited in key order
+        assert_eq!(tool.arguments, ["name", "line", "character", "uri"]);
+        assert_eq!(tool.input_schema()["properties"]["line"]["type"], "integer");
+
+        let arguments =
+            json!({"uri": "file:///src/main.rs", "line": 3, "character": 7, "name": "vec"});
+        let Value::Object(arguments) = arguments else {
+            unreachable!()
+        };
+        assert_eq!(
+            fill(&template, &arguments).unwrap(),
+            json!({
+                "textDocument": {"uri": "file:///src/main.rs"},
+                "position": {"line": 3, "character": 7},
+                "label": "vec at 3",
+                "fixed": [1, "plain"],
+            })
+        );
+        assert!(fill(&json!("${other}"), &arguments).is_err());
+    }
+
+    #[test]
+    fn registers_each_name_once() {
+        let mut registry = ToolRegistry::from_config(&[custom(json!({}))]).unwrap();
+        let duplicate = TemplateTool::new(custom(json!({}))).unwrap();
+        assert!(registry.register(Arc::new(duplicate)).is_err());
+
+        let definitions = registry.definitions();
+        assert_eq!(definitions[0].name, "expand_macro_at");
+        let annotations = definitions[0].annotations.as_ref().unwrap();
+        assert_eq!(annotations.read_only_hint, Some(true));
+
+        registry.retain(|name| name != "expand_macro_at");
+        assert_eq!(registry.names().count(), 0);
+        assert!(
+            TemplateTool::new(CustomToolConfig {
+                method: "shutdown".to_string(),
+                ..custom(json!({}))
+            })
+            .is_err()
+        );
+    }
+}
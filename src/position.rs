@@ -0,0 +1,275 @@
+//! Position encodings and column conversion.
+//!
+//! LSP `character` offsets count UTF-16 code units unless client and server agree
+//! on another `positionEncoding` during `initialize`. MCP clients, on the other
+//! hand, usually count Unicode scalars (or bytes). This module converts columns
+//! between the encoding tool callers use and the one the server negotiated, reading
+//! the affected lines from the documents themselves.
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::encoding;
+use crate::utils::uri_to_path;
+
+/// Unit in which a position's `character` offset is counted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize, clap::ValueEnum)]
+pub enum PositionEncoding {
+    /// Bytes of UTF-8
+    #[serde(rename = "utf-8")]
+    #[value(name = "utf-8")]
+    Utf8,
+    /// UTF-16 code units, the LSP default
+    #[default]
+    #[serde(rename = "utf-16")]
+    #[value(name = "utf-16")]
+    Utf16,
+    /// Unicode scalar values, i.e. characters
+    #[serde(rename = "utf-32")]
+    #[value(name = "utf-32")]
+    Utf32,
+}
+
+impl PositionEncoding {
+    /// Parses a `PositionEncodingKind` as sent by the server.
+    pub fn from_lsp(kind: &str) -> Option<Self> {
+        match kind {
+            "utf-8" => Some(Self::Utf8),
+            "utf-16" => Some(Self::Utf16),
+            "utf-32" => Some(Self::Utf32),
+            _ => None,
+        }
+    }
+
+    pub fn as_lsp(self) -> &'static str {
+        match self {
+            Self::Utf8 => "utf-8",
+            Self::Utf16 => "utf-16",
+            Self::Utf32 => "utf-32",
+        }
+    }
+
+    fn width(self, ch: char) -> usize {
+        match self {
+            Self::Utf8 => ch.len_utf8(),
+            Self::Utf16 => ch.len_utf16(),
+            Self::Utf32 => 1,
+        }
+    }
+}
+
+/// Converts a column in `line` to a byte offset.
+///
+/// Columns past the end of the line clamp to its end; a column inside a multi-unit
+/// character moves to the start of the next character.
+pub fn column_to_byte(line: &str, column: u32, encoding: PositionEncoding) -> usize {
+    let column = column as usize;
+    let mut units = 0;
+    for (offset, ch) in line.char_indices() {
+        if units >= column {
+            return offset;
+        }
+        units += encoding.width(ch);
+    }
+    line.len()
+}
+
+/// Converts a byte offset in `line` to a column.
+pub fn byte_to_column(line: &str, byte: usize, encoding: PositionEncoding) -> u32 {
+    line.char_indices()
+        .take_while(|(offset, _)| *offset < byte)
+        .map(|(_, ch)| encoding.width(ch))
+        .sum::<usize>() as u32
+}
+
+/// Re-expresses a column of `line` counted in `from` units as `to` units.
+pub fn convert_column(
+    line: &str,
+    column: u32,
+    from: PositionEncoding,
+    to: PositionEncoding,
+) -> u32 {
+    if from == to {
+        return column;
+    }
+    if line.is_ascii() {
+        return column.min(line.len() as u32);
+    }
+    byte_to_column(line, column_to_byte(line, column, from), to)
+}
+
+/// The column of the identifier character nearest to `column` in `line`, when
+/// `column` is not on one already (e.g. on whitespace, or just past a name).
+///
+/// Ties go to the left, since callers pointing just past a name mean that name.
+pub fn snap_to_identifier(line: &str, column: u32, encoding: PositionEncoding) -> Option<u32> {
+    let chars: Vec<(usize, char)> = line.char_indices().collect();
+    let byte = column_to_byte(line, column, encoding);
+    let index = chars
+        .iter()
+        .position(|(offset, _)| *offset >= byte)
+        .unwrap_or(chars.len());
+    let is_identifier = |index: usize| {
+        chars
+            .get(index)
+            .is_some_and(|(_, ch)| ch.is_alphanumeric() || *ch == '_')
+    };
+    if is_identifier(index) {
+        return None;
+    }
+    let nearest = (1..=chars.len()).find_map(|distance| {
+        let left = index
+            .checked_sub(distance)
+            .filter(|&left| is_identifier(left));
+        left.or_else(|| Some(index + distance).filter(|&right| is_identifier(right)))
+    })?;
+    Some(byte_to_column(line, chars[nearest].0, encoding))
+}
+
+/// Converts positions between the tool caller's encoding and the server's.
+///
+/// Document text is read from disk on first use (unless supplied up front through
+/// [`PositionConverter::with_texts`]) and cached for the lifetime of the converter,
+/// which is meant to live for a single tool call. Positions in documents that cannot
+/// be read, or on lines past their end, are passed through unchanged.
+#[derive(Debug)]
+pub struct PositionConverter {
+    client: PositionEncoding,
+    server: PositionEncoding,
+    texts: HashMap<String, Option<String>>,
+}
+
+impl PositionConverter {
+    pub fn new(client: PositionEncoding, server: PositionEncoding) -> Self {
+        Self {
+            client,
+            server,
+            texts: HashMap::new(),
+        }
+    }
+
+    /// Uses the given texts instead of reading those documents from disk, e.g. for
+    /// overlay documents whose content exists only in memory.
+    pub fn with_texts(mut self, texts: HashMap<String, String>) -> Self {
+        self.texts
+            .extend(texts.into_iter().map(|(uri, text)| (uri, Some(text))));
+        self
+    }
+
+    /// Converts a caller-supplied column to the server's encoding.
+    pub async fn to_server(&mut self, uri: &str, line: u32, character: u32) -> u32 {
+        let (from, to) = (self.client, self.server);
+        self.convert(uri, line, character, from, to).await
+    }
+
+    /// Converts a server-reported column to the caller's encoding.
+    pub async fn to_client(&mut self, uri: &str, line: u32, character: u32) -> u32 {
+        let (from, to) = (self.server, self.client);
+        self.convert(uri, line, character, from, to).await
+    }
+
+    /// Moves a caller-supplied column onto the nearest identifier on its line (see
+    /// [`snap_to_identifier`]). Returns `None` if it is on one already, or the line
+    /// cannot be read.
+    pub async fn snap(&mut self, uri: &str, line: u32, character: u32) -> Option<u32> {
+        let encoding = self.client;
+        let line_text = self.line(uri, line).await?;
+        snap_to_identifier(line_text, character, encoding)
+    }
+
+    async fn convert(
+        &mut self,
+        uri: &str,
+        line: u32,
+        character: u32,
+        from: PositionEncoding,
+        to: PositionEncoding,
+    ) -> u32 {
+        if from == to {
+            return character;
+        }
+        match self.line(uri, line).await {
+            Some(line_text) => convert_column(line_text, character, from, to),
+            None => character,
+        }
+    }
+
+    /// Line `line` of the document, without its line ending.
+    async fn line(&mut self, uri: &str, line: u32) -> Option<&str> {
+        if !self.texts.contains_key(uri) {
+            let text = read_document(uri).await;
+            if let Err(err) = &text {
+                tracing::debug!(?err, %uri, "Cannot read document; leaving columns unconverted");
+            }
+            self.texts.insert(uri.to_string(), text.ok());
+        }
+        let line_text = self.texts[uri].as_deref()?.split('\n').nth(line as usize)?;
+        Some(line_text.strip_suffix('\r').unwrap_or(line_text))
+    }
+}
+
+async fn read_document(uri: &str) -> Result<String> {
+    let path = uri_to_path(uri)?;
+    Ok(encoding::read_file(&path).await?.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use PositionEncoding::*;
+
+    #[test]
+    fn converts_between_encodings() {
+        // 'é' is 2 UTF-8 bytes / 1 UTF-16 unit, '😀' is 4 bytes / 2 units
+        let line = "let é = \"😀\"; x";
+        let x_utf32 = line.chars().position(|c| c == 'x').unwrap() as u32;
+        let x_utf16 = line.encode_utf16().count() as u32 - 1;
+        let x_utf8 = line.find('x').unwrap() as u32;
+
+        assert_eq!(convert_column(line, x_utf32, Utf32, Utf16), x_utf16);
+        assert_eq!(convert_column(line, x_utf16, Utf16, Utf8), x_utf8);
+        assert_eq!(convert_column(line, x_utf8, Utf8, Utf32), x_utf32);
+    }
+
+    #[test]
+    fn clamps_and_rounds_to_character_boundaries() {
+        let line = "a😀b";
+        // Column 2 (UTF-16) falls inside the emoji; it moves to the next character.
+        assert_eq!(column_to_byte(line, 2, Utf16), 5);
+        assert_eq!(column_to_byte(line, 99, Utf32), line.len());
+        assert_eq!(byte_to_column(line, line.len(), Utf16), 4);
+    }
+
+    #[test]
+    fn snaps_to_the_nearest_identifier() {
+        let line = "    let total = count + 1;";
+        // On the name already
+        assert_eq!(snap_to_identifier(line, 9, Utf16), None);
+        // Just past `total`, and in the indentation before `let`
+        assert_eq!(snap_to_identifier(line, 13, Utf16), Some(12));
+        assert_eq!(snap_to_identifier(line, 1, Utf16), Some(4));
+        // Past the end of the line
+        assert_eq!(snap_to_identifier(line, 40, Utf16), Some(24));
+        assert_eq!(snap_to_identifier("   ", 1, Utf16), None);
+        // Columns count in the caller's encoding
+        assert_eq!(snap_to_identifier("\"😀\" x", 4, Utf16), Some(5));
+        assert_eq!(snap_to_identifier("\"😀\" x", 3, Utf32), Some(4));
+    }
+
+    #[tokio::test]
+    async fn converter_reads_the_document() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("main.rs");
+        std::fs::write(&path, "fn main() {}\r\nlet ü = 1;\n").unwrap();
+        let uri = url::Url::from_file_path(&path).unwrap().to_string();
+
+        let mut converter = PositionConverter::new(Utf32, Utf8);
+        assert_eq!(converter.to_server(&uri, 1, 8).await, 9);
+        assert_eq!(converter.to_client(&uri, 1, 9).await, 8);
+        // Lines past the end and unreadable documents pass through
+        assert_eq!(converter.to_server(&uri, 7, 3).await, 3);
+        assert_eq!(converter.to_server("file:///missing.rs", 0, 3).await, 3);
+    }
+}
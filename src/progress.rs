@@ -0,0 +1,239 @@
+//! Work-done progress tracking.
+//!
+//! Language servers report long-running work such as indexing through
+//! `window/workDoneProgress/create` and `$/progress` messages. The tracker keeps the
+//! set of active progress tokens so tools can tell "no result" apart from "no result
+//! yet, the server is still indexing" and wait for the latter to settle.
+//!
+//! [`ToolProgress`] carries status updates the other way, from a running tool call
+//! to whoever reports them to the MCP client.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{mpsc, watch};
+use tokio::time::timeout;
+
+/// A piece of work the server has announced and not yet finished.
+#[derive(Debug, Clone, Default, Serialize, schemars::JsonSchema)]
+pub struct ProgressEntry {
+    pub title: String,
+    pub message: Option<String>,
+    pub percentage: Option<u64>,
+}
+
+impl ProgressEntry {
+    /// One-line summary, e.g. `Indexing: 3/10 (30%)`.
+    pub fn describe(&self) -> String {
+        let mut text = self.title.clone();
+        if let Some(message) = &self.message {
+            text = format!("{text}: {message}");
+        }
+        if let Some(percentage) = self.percentage {
+            text = format!("{text} ({percentage}%)");
+        }
+        text
+    }
+}
+
+#[derive(Debug)]
+pub struct ProgressTracker {
+    active: watch::Sender<BTreeMap<String, ProgressEntry>>,
+}
+
+impl Default for ProgressTracker {
+    fn default() -> Self {
+        Self {
+            active: watch::Sender::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl ProgressTracker {
+    /// Applies a `$/progress` notification.
+    ///
+    /// Only work-done progress (`begin`/`report`/`end` values) is tracked; partial
+    /// result progress uses the same method with arbitrary payloads and is ignored.
+    pub fn update(&self, params: &Value) {
+        let Some(token) = params.get("token").map(token_key) else {
+            return;
+        };
+        let Some(value) = params.get("value") else {
+            return;
+        };
+        let text = |key: &str| value.get(key).and_then(|v| v.as_str()).map(str::to_string);
+        let percentage = value.get("percentage").and_then(|v| v.as_u64());
+
+        match value.get("kind").and_then(|kind| kind.as_str()) {
+            Some("begin") => {
+                tracing::debug!(%token, title = ?text("title"), "Server work started");
+                self.active.send_modify(|active| {
+                    active.insert(
+                        token,
+                        ProgressEntry {
+                            title: text("title").unwrap_or_default(),
+                            message: text("message"),
+                            percentage,
+                        },
+                    );
+                });
+            }
+            Some("report") => {
+                self.active.send_if_modified(|active| {
+                    let Some(entry) = active.get_mut(&token) else {
+                        return false;
+                    };
+                    if let Some(message) = text("message") {
+                        entry.message = Some(message);
+                    }
+                    if percentage.is_some() {
+                        entry.percentage = percentage;
+                    }
+                    true
+                });
+            }
+            Some("end") => {
+                tracing::debug!(%token, "Server work finished");
+                self.active
+                    .send_if_modified(|active| active.remove(&token).is_some());
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns true while the server has unfinished work-done progress.
+    pub fn is_busy(&self) -> bool {
+        !self.active.borrow().is_empty()
+    }
+
+    /// Returns a snapshot of the work currently in progress.
+    pub fn active(&self) -> BTreeMap<String, ProgressEntry> {
+        self.active.borrow().clone()
+    }
+
+    /// Watches the work in progress; the receiver sees every begin, report, and end.
+    pub fn subscribe(&self) -> watch::Receiver<BTreeMap<String, ProgressEntry>> {
+        self.active.subscribe()
+    }
+
+    /// Waits until no progress is active, for at most `budget`.
+    ///
+    /// Returns true if the server became idle, false if the budget ran out.
+    pub async fn wait_idle(&self, budget: Duration) -> bool {
+        let mut receiver = self.active.subscribe();
+        timeout(budget, receiver.wait_for(|active| active.is_empty()))
+            .await
+            .is_ok_and(|result| result.is_ok())
+    }
+}
+
+/// Status messages from a running tool call, such as retries or waiting for indexing.
+///
+/// The default value discards messages. Tools report without knowing whether, or
+/// how, anyone is listening.
+#[derive(Debug, Clone, Default)]
+pub struct ToolProgress {
+    sender: Option<mpsc::UnboundedSender<String>>,
+}
+
+impl ToolProgress {
+    /// A reporter and the receiving end of its messages. The receiver closes once
+    /// every clone of the reporter is dropped.
+    pub fn channel() -> (Self, mpsc::UnboundedReceiver<String>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (
+            Self {
+                sender: Some(sender),
+            },
+            receiver,
+        )
+    }
+
+    pub fn report(&self, message: impl Into<String>) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(message.into());
+        }
+    }
+}
+
+/// Progress tokens may be integers or strings; normalize both to a map key.
+fn token_key(token: &Value) -> String {
+    match token {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn progress(token: Value, value: Value) -> Value {
+        json!({ "token": token, "value": value })
+    }
+
+    #[test]
+    fn tracks_begin_report_end() {
+        let tracker = ProgressTracker::default();
+        tracker.update(&progress(
+            json!("rustAnalyzer/Indexing"),
+            json!({"kind": "begin", "title": "Indexing", "percentage": 0}),
+        ));
+        assert!(tracker.is_busy());
+
+        tracker.update(&progress(
+            json!("rustAnalyzer/Indexing"),
+            json!({"kind": "report", "message": "3/10", "percentage": 30}),
+        ));
+        let entry = tracker.active()["rustAnalyzer/Indexing"].clone();
+        assert_eq!(entry.title, "Indexing");
+        assert_eq!(entry.message.as_deref(), Some("3/10"));
+        assert_eq!(entry.percentage, Some(30));
+
+        tracker.update(&progress(
+            json!("rustAnalyzer/Indexing"),
+            json!({"kind": "end"}),
+        ));
+        assert!(!tracker.is_busy());
+    }
+
+    #[tokio::test]
+    async fn tool_progress_closes_with_last_reporter() {
+        let (progress, mut messages) = ToolProgress::channel();
+        let entry = ProgressEntry {
+            title: "Indexing".to_string(),
+            message: Some("3/10".to_string()),
+            percentage: Some(30),
+        };
+        progress.clone().report(entry.describe());
+        ToolProgress::default().report("dropped");
+        drop(progress);
+        assert_eq!(
+            messages.recv().await.as_deref(),
+            Some("Indexing: 3/10 (30%)")
+        );
+        assert_eq!(messages.recv().await, None);
+    }
+
+    #[test]
+    fn ignores_partial_result_progress() {
+        let tracker = ProgressTracker::default();
+        tracker.update(&progress(json!(1), json!([{"uri": "file:///a.rs"}])));
+        assert!(!tracker.is_busy());
+    }
+
+    #[tokio::test]
+    async fn wait_idle_respects_budget() {
+        let tracker = ProgressTracker::default();
+        assert!(tracker.wait_idle(Duration::from_millis(10)).await);
+
+        tracker.update(&progress(
+            json!(7),
+            json!({"kind": "begin", "title": "Loading"}),
+        ));
+        assert!(!tracker.wait_idle(Duration::from_millis(10)).await);
+    }
+}
@@ -0,0 +1,457 @@
+//! MCP prompts for common code-navigation workflows.
+//!
+//! Each prompt gathers LSP results for a symbol up front and bundles them into a
+//! single user message, so a chat client can start a rich flow in one step:
+//!
+//! - `explain_symbol`: hover, definition, and references of the symbol at a position
+//! - `rename_impact`: references grouped by file plus existing symbols with the new
+//!   name, framed as an impact analysis for the rename
+//!
+//! A failing or unsupported LSP request leaves a note in its section instead of
+//! failing the whole prompt.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use anyhow::{Context, Result, anyhow, bail};
+use rmcp::model::{
+    GetPromptResult, JsonObject, Prompt, PromptArgument, PromptMessage, PromptMessageRole,
+};
+use serde_json::{Value, json};
+
+use crate::lsp_bridge::LspBridge;
+use crate::position::PositionConverter;
+use crate::tools::definition::{DefinitionRequest, DefinitionTool};
+use crate::tools::locations::DefinitionTarget;
+use crate::tools::references::{ReferencesRequest, ReferencesTool};
+use crate::utils::uri_to_file_path;
+
+const EXPLAIN_SYMBOL: &str = "explain_symbol";
+const RENAME_IMPACT: &str = "rename_impact";
+
+/// References listed individually before the rest are summarized.
+const MAX_LISTED_REFERENCES: usize = 50;
+
+/// The prompts advertised in `prompts/list`.
+pub fn list() -> Vec<Prompt> {
+    let argument = |name: &str, description: &str| PromptArgument {
+        name: name.to_string(),
+        title: None,
+        description: Some(description.to_string()),
+        required: Some(true),
+    };
+    let position = || {
+        vec![
+            argument(
+                "uri",
+                "file:// URI of the document, or its absolute or workspace-relative path",
+            ),
+            argument("line", "Zero-based line index"),
+            argument(
+                "character",
+                "Zero-based character index, in the configured column encoding",
+            ),
+        ]
+    };
+    let mut rename_arguments = position();
+    rename_arguments.push(argument("new_name", "Proposed new name"));
+    vec![
+        Prompt::new(
+            EXPLAIN_SYMBOL,
+            Some("Explain the symbol at a position using its hover, definition, and references"),
+            Some(position()),
+        ),
+        Prompt::new(
+            RENAME_IMPACT,
+            Some("Analyze the impact of renaming the symbol at a position"),
+            Some(rename_arguments),
+        ),
+    ]
+}
+
+/// A parsed `prompts/get` request.
+#[derive(Debug, Clone)]
+pub enum PromptRequest {
+    ExplainSymbol(DefinitionRequest),
+    RenameImpact {
+        position: DefinitionRequest,
+        new_name: String,
+    },
+}
+
+impl PromptRequest {
+    pub fn parse(name: &str, arguments: Option<&JsonObject>) -> Result<Self> {
+        let empty = JsonObject::new();
+        let arguments = arguments.unwrap_or(&empty);
+        match name {
+            EXPLAIN_SYMBOL => Ok(Self::ExplainSymbol(position(arguments)?)),
+            RENAME_IMPACT => Ok(Self::RenameImpact {
+                position: position(arguments)?,
+                new_name: string_argument(arguments, "new_name")?.to_string(),
+            }),
+            other => bail!("unknown prompt: {other}"),
+        }
+    }
+
+    /// The document the prompt is about, which must be synced first.
+    pub fn uri(&self) -> &str {
+        match self {
+            Self::ExplainSymbol(position) | Self::RenameImpact { position, .. } => &position.uri,
+        }
+    }
+
+    /// The document argument, for normalizing it before use.
+    pub fn uri_mut(&mut self) -> &mut String {
+        match self {
+            Self::ExplainSymbol(position) | Self::RenameImpact { position, .. } => {
+                &mut position.uri
+            }
+        }
+    }
+}
+
+fn string_argument<'a>(arguments: &'a JsonObject, name: &str) -> Result<&'a str> {
+    arguments
+        .get(name)
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| anyhow!("missing argument: {name}"))
+}
+
+/// Prompt arguments arrive as strings; numbers are accepted too.
+fn number_argument(arguments: &JsonObject, name: &str) -> Result<u32> {
+    match arguments.get(name) {
+        Some(Value::Number(number)) => number
+            .as_u64()
+            .and_then(|n| u32::try_from(n).ok())
+            .ok_or_else(|| anyhow!("invalid {name}: {number}")),
+        Some(Value::String(text)) => text
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid {name}: {text}")),
+        _ => bail!("missing argument: {name}"),
+    }
+}
+
+fn position(arguments: &JsonObject) -> Result<DefinitionRequest> {
+    Ok(DefinitionRequest {
+        uri: string_argument(arguments, "uri")?.to_string(),
+        line: number_argument(arguments, "line")?,
+        character: number_argument(arguments, "character")?,
+        snap: None,
+        server: None,
+        options: Default::default(),
+    })
+}
+
+/// Gathers the LSP context for `request` and renders the prompt.
+///
+/// `overlays` holds the text of overlay documents, used in place of the file on disk.
+pub async fn render(
+    lsp: &LspBridge,
+    request: PromptRequest,
+    overlays: HashMap<String, String>,
+) -> Result<GetPromptResult> {
+    let (description, text) = match request {
+        PromptRequest::ExplainSymbol(position) => (
+            "Explain a symbol",
+            explain_symbol(lsp, position, overlays).await,
+        ),
+        PromptRequest::RenameImpact { position, new_name } => (
+            "Rename impact analysis",
+            rename_impact(lsp, position, &new_name, overlays).await,
+        ),
+    };
+    Ok(GetPromptResult {
+        description: Some(description.to_string()),
+        messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+    })
+}
+
+async fn explain_symbol(
+    lsp: &LspBridge,
+    position: DefinitionRequest,
+    overlays: HashMap<String, String>,
+) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "Explain the symbol at {} (zero-based line and column): what it is, what it is \
+         for, and how it is used. Base the answer on the language server results below.",
+        location(&position.uri, position.line, position.character)
+    );
+    source_line(&mut text, &position, &overlays).await;
+
+    text.push_str("\n## Hover\n\n");
+    match hover(lsp, &position, &overlays).await {
+        Ok(Some(hover)) => text.push_str(hover.trim()),
+        Ok(None) => text.push_str("_No hover information._"),
+        Err(err) => unavailable(&mut text, &err),
+    }
+    text.push('\n');
+
+    text.push_str("\n## Definition\n\n");
+    match DefinitionTool::new()
+        .execute(lsp, position.clone(), overlays.clone())
+        .await
+    {
+        Ok(response) if response.targets.is_empty() => text.push_str("_No definition found._\n"),
+        Ok(response) => list_targets(&mut text, &response.targets),
+        Err(err) => unavailable(&mut text, &err),
+    }
+
+    text.push_str("\n## References\n\n");
+    match references(lsp, &position, &overlays).await {
+        Ok(references) if references.is_empty() => text.push_str("_No references found._\n"),
+        Ok(references) => list_targets(&mut text, &references),
+        Err(err) => unavailable(&mut text, &err),
+    }
+    text
+}
+
+async fn rename_impact(
+    lsp: &LspBridge,
+    position: DefinitionRequest,
+    new_name: &str,
+    overlays: HashMap<String, String>,
+) -> String {
+    let mut text = String::new();
+    let _ = writeln!(
+        text,
+        "Analyze the impact of renaming the symbol at {} (zero-based line and column) to \
+         `{new_name}`. List the files that must change, flag risks such as public API \
+         changes, name collisions, and mentions the language server cannot see (strings, \
+         docs, configuration), and suggest how to carry out the rename.",
+        location(&position.uri, position.line, position.character)
+    );
+    source_line(&mut text, &position, &overlays).await;
+
+    text.push_str("\n## References by file\n\n");
+    match references(lsp, &position, &overlays).await {
+        Ok(references) if references.is_empty() => text.push_str("_No references found._\n"),
+        Ok(references) => {
+            let mut by_file: BTreeMap<&str, Vec<&DefinitionTarget>> = BTreeMap::new();
+            for reference in &references {
+                by_file.entry(&reference.uri).or_default().push(reference);
+            }
+            let _ = writeln!(
+                text,
+                "{} references in {} files.\n",
+                references.len(),
+                by_file.len()
+            );
+            for (uri, references) in by_file {
+                let lines: Vec<String> = references
+                    .iter()
+                    .map(|reference| reference.range.start_line.to_string())
+                    .collect();
+                let _ = writeln!(
+                    text,
+                    "- {} ({}): lines {}",
+                    display_path(uri),
+                    references.len(),
+                    lines.join(", ")
+                );
+            }
+        }
+        Err(err) => unavailable(&mut text, &err),
+    }
+
+    let _ = write!(text, "\n## Existing symbols named `{new_name}`\n\n");
+    match crate::resources::symbols(lsp, new_name, false).await {
+        Ok(result) => {
+            let collisions: Vec<&Value> = result["symbols"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter(|symbol| symbol["name"] == new_name)
+                .collect();
+            if collisions.is_empty() {
+                text.push_str("_None found._\n");
+            }
+            for symbol in collisions {
+                let uri = symbol["uri"].as_str().unwrap_or_default();
+                let line = symbol["range"]["start_line"].as_u64();
+                let _ = writeln!(
+                    text,
+                    "- {} `{new_name}` in {}{}",
+                    symbol["kind"].as_str().unwrap_or("symbol"),
+                    display_path(uri),
+                    line.map(|line| format!(":{line}")).unwrap_or_default()
+                );
+            }
+        }
+        Err(err) => unavailable(&mut text, &err),
+    }
+    text
+}
+
+/// Quotes the line under the cursor, if it can be read.
+async fn source_line(
+    text: &mut String,
+    position: &DefinitionRequest,
+    overlays: &HashMap<String, String>,
+) {
+    let content = match overlays.get(&position.uri) {
+        Some(content) => Some(content.clone()),
+        None => match uri_to_file_path(&position.uri) {
+            Ok(path) => crate::encoding::read_file(&path)
+                .await
+                .ok()
+                .map(|decoded| decoded.text),
+            Err(_) => None,
+        },
+    };
+    if let Some(line) = content
+        .as_deref()
+        .and_then(|content| content.lines().nth(position.line as usize))
+    {
+        let _ = write!(text, "\n```\n{}\n```\n", line.trim_end());
+    }
+}
+
+async fn hover(
+    lsp: &LspBridge,
+    position: &DefinitionRequest,
+    overlays: &HashMap<String, String>,
+) -> Result<Option<String>> {
+    lsp.server_capabilities()
+        .ensure("hoverProvider", "textDocument/hover")?;
+    let params = server_position(lsp, position, overlays).await;
+    let raw = lsp
+        .request("textDocument/hover", params)
+        .await
+        .context("LSP hover request failed")?;
+    Ok(hover_text(&raw["contents"]))
+}
+
+/// Flattens `MarkupContent`, `MarkedString`, or `MarkedString[]` into markdown.
+fn hover_text(contents: &Value) -> Option<String> {
+    match contents {
+        Value::String(text) => Some(text.clone()),
+        Value::Array(items) => {
+            let parts: Vec<String> = items.iter().filter_map(hover_text).collect();
+            (!parts.is_empty()).then(|| parts.join("\n\n"))
+        }
+        Value::Object(object) => {
+            let value = object.get("value")?.as_str()?;
+            match object
+                .get("language")
+                .and_then(|language| language.as_str())
+            {
+                Some(language) => Some(format!("```{language}\n{value}\n```")),
+                None => Some(value.to_string()),
+            }
+        }
+        _ => None,
+    }
+    .filter(|text| !text.trim().is_empty())
+}
+
+async fn references(
+    lsp: &LspBridge,
+    position: &DefinitionRequest,
+    overlays: &HashMap<String, String>,
+) -> Result<Vec<DefinitionTarget>> {
+    let request = ReferencesRequest {
+        uri: position.uri.clone(),
+        line: position.line,
+        character: position.character,
+        include_declaration: Some(true),
+        snap: None,
+        server: None,
+        filter: Default::default(),
+        options: Default::default(),
+    };
+    let found = ReferencesTool::new()
+        .execute(lsp, request, overlays.clone())
+        .await?;
+    Ok(found.references)
+}
+
+/// `TextDocumentPositionParams` for `position`, with the column in the server's encoding.
+async fn server_position(
+    lsp: &LspBridge,
+    position: &DefinitionRequest,
+    overlays: &HashMap<String, String>,
+) -> Value {
+    let mut converter = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+        .with_texts(overlays.clone());
+    let character = converter
+        .to_server(&position.uri, position.line, position.character)
+        .await;
+    json!({
+        "textDocument": { "uri": position.uri },
+        "position": { "line": position.line, "character": character },
+    })
+}
+
+fn list_targets(text: &mut String, targets: &[DefinitionTarget]) {
+    for target in targets.iter().take(MAX_LISTED_REFERENCES) {
+        let range = &target.range;
+        let _ = writeln!(
+            text,
+            "- {}",
+            location(&target.uri, range.start_line, range.start_character)
+        );
+    }
+    if targets.len() > MAX_LISTED_REFERENCES {
+        let _ = writeln!(
+            text,
+            "- ... and {} more",
+            targets.len() - MAX_LISTED_REFERENCES
+        );
+    }
+}
+
+fn unavailable(text: &mut String, err: &anyhow::Error) {
+    let _ = writeln!(text, "_Unavailable: {err:#}_");
+}
+
+fn location(uri: &str, line: u32, character: u32) -> String {
+    format!("{}:{line}:{character}", display_path(uri))
+}
+
+fn display_path(uri: &str) -> String {
+    uri_to_file_path(uri)
+        .map(|path| path.display().to_string())
+        .unwrap_or_else(|_| uri.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_string_and_number_arguments() {
+        let arguments =
+            json!({ "uri": "file:///ws/a.rs", "line": "3", "character": 7, "new_name": "b" });
+        let arguments = arguments.as_object().unwrap();
+        let PromptRequest::RenameImpact { position, new_name } =
+            PromptRequest::parse(RENAME_IMPACT, Some(arguments)).unwrap()
+        else {
+            panic!("expected rename_impact");
+        };
+        assert_eq!((position.line, position.character), (3, 7));
+        assert_eq!(new_name, "b");
+
+        let err = PromptRequest::parse(EXPLAIN_SYMBOL, None).unwrap_err();
+        assert_eq!(err.to_string(), "missing argument: uri");
+        assert!(PromptRequest::parse("summarize", Some(arguments)).is_err());
+    }
+
+    #[test]
+    fn hover_text_accepts_all_shapes() {
+        assert_eq!(
+            hover_text(&json!({ "kind": "markdown", "value": "**fn** main" })).as_deref(),
+            Some("**fn** main")
+        );
+        assert_eq!(
+            hover_text(&json!([{ "language": "rust", "value": "fn main()" }, "Entry point"]))
+                .as_deref(),
+            Some("```rust\nfn main()\n```\n\nEntry point")
+        );
+        assert_eq!(hover_text(&json!("")), None);
+        assert_eq!(hover_text(&Value::Null), None);
+    }
+}
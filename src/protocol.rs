@@ -0,0 +1,451 @@
+//! Typed LSP messages.
+//!
+//! The messages pathfinder itself builds or reads are declared here as serde types,
+//! so a misspelled field or a wrong nesting fails to compile instead of reaching the
+//! server. Each request implements [`Request`], naming its method, params, and
+//! result, and is sent with [`LspBridge::call`](crate::lsp_bridge::LspBridge::call);
+//! each notification implements [`Notification`] and is sent with
+//! [`LspBridge::send`](crate::lsp_bridge::LspBridge::send).
+//!
+//! Only what pathfinder uses is declared, with the field names of the
+//! specification. Results handed on as the server sent them (the initialize result,
+//! workspace edits, code actions, document symbols) are typed as
+//! `serde_json::Value` within their request's shape, as are messages passed through
+//! unchanged (`lsp_request`, the vendor extensions, and notebook sync).
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A request: its method, the params sent, and the result expected back.
+pub trait Request {
+    const METHOD: &'static str;
+    type Params: Serialize;
+    type Result: DeserializeOwned;
+}
+
+/// A notification: its method and the params sent.
+pub trait Notification {
+    const METHOD: &'static str;
+    type Params: Serialize;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    /// Column in the server's position encoding
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Location {
+    pub uri: String,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocationLink {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_selection_range: Option<Range>,
+    pub target_uri: String,
+    pub target_range: Range,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_selection_range: Option<Range>,
+}
+
+/// One entry of a definition-like result, which servers send in either form.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum LocationOrLink {
+    Location(Location),
+    Link(LocationLink),
+}
+
+/// A definition-like result: a single `Location`, or an array of `Location`s or
+/// `LocationLink`s.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Locations {
+    One(LocationOrLink),
+    Many(Vec<LocationOrLink>),
+}
+
+impl Locations {
+    pub fn into_vec(self) -> Vec<LocationOrLink> {
+        match self {
+            Locations::One(location) => vec![location],
+            Locations::Many(locations) => locations,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextDocumentIdentifier {
+    pub uri: String,
+}
+
+impl TextDocumentIdentifier {
+    pub fn new(uri: impl Into<String>) -> Self {
+        Self { uri: uri.into() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VersionedTextDocumentIdentifier {
+    pub uri: String,
+    pub version: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentItem {
+    pub uri: String,
+    pub language_id: String,
+    pub version: i32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocumentPositionParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Position,
+}
+
+impl TextDocumentPositionParams {
+    pub fn new(uri: impl Into<String>, line: u32, character: u32) -> Self {
+        Self {
+            text_document: TextDocumentIdentifier::new(uri),
+            position: Position { line, character },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceParams {
+    #[serde(flatten)]
+    pub text_document_position: TextDocumentPositionParams,
+    pub context: ReferenceContext,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReferenceContext {
+    pub include_declaration: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentLinkParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DocumentLink {
+    pub range: Range,
+    /// Absent until the link is resolved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InitializeParams {
+    pub process_id: u32,
+    pub root_uri: String,
+    /// Deprecated in favor of `rootUri`, still read by older servers
+    pub root_path: String,
+    /// The client capabilities, see [`crate::capabilities::client_capabilities`]
+    pub capabilities: Value,
+    pub workspace_folders: Vec<WorkspaceFolder>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkspaceFolder {
+    pub uri: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InitializedParams {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DidChangeConfigurationParams {
+    pub settings: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentSymbolParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenameParams {
+    #[serde(flatten)]
+    pub text_document_position: TextDocumentPositionParams,
+    pub new_name: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeActionParams {
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+    pub context: CodeActionContext,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeActionContext {
+    /// Diagnostics the actions are asked for; pathfinder sends none
+    pub diagnostics: Vec<Value>,
+    /// Kinds of actions wanted, all when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub only: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trigger_kind: Option<CodeActionTriggerKind>,
+}
+
+/// Why actions are asked for, sent as its number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(into = "u8", try_from = "u8")]
+pub enum CodeActionTriggerKind {
+    Invoked,
+    Automatic,
+}
+
+impl From<CodeActionTriggerKind> for u8 {
+    fn from(kind: CodeActionTriggerKind) -> Self {
+        match kind {
+            CodeActionTriggerKind::Invoked => 1,
+            CodeActionTriggerKind::Automatic => 2,
+        }
+    }
+}
+
+impl TryFrom<u8> for CodeActionTriggerKind {
+    type Error = String;
+
+    fn try_from(kind: u8) -> Result<Self, Self::Error> {
+        match kind {
+            1 => Ok(CodeActionTriggerKind::Invoked),
+            2 => Ok(CodeActionTriggerKind::Automatic),
+            other => Err(format!("unknown code action trigger kind {other}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidOpenTextDocumentParams {
+    pub text_document: TextDocumentItem,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidChangeTextDocumentParams {
+    pub text_document: VersionedTextDocumentIdentifier,
+    pub content_changes: Vec<TextDocumentContentChangeEvent>,
+}
+
+/// The whole new text of a document, or, with `range`, the text replacing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextDocumentContentChangeEvent {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidSaveTextDocumentParams {
+    pub text_document: TextDocumentIdentifier,
+    /// Sent only to servers that asked for it with `includeText`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DidCloseTextDocumentParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+pub enum Initialize {}
+
+/// The result is read by [`crate::capabilities::ServerCapabilities`], which keeps
+/// the capabilities as sent.
+impl Request for Initialize {
+    const METHOD: &'static str = "initialize";
+    type Params = InitializeParams;
+    type Result = Value;
+}
+
+pub enum Initialized {}
+
+impl Notification for Initialized {
+    const METHOD: &'static str = "initialized";
+    type Params = InitializedParams;
+}
+
+pub enum DidChangeConfiguration {}
+
+impl Notification for DidChangeConfiguration {
+    const METHOD: &'static str = "workspace/didChangeConfiguration";
+    type Params = DidChangeConfigurationParams;
+}
+
+pub enum Definition {}
+
+impl Request for Definition {
+    const METHOD: &'static str = "textDocument/definition";
+    type Params = TextDocumentPositionParams;
+    type Result = Option<Locations>;
+}
+
+pub enum References {}
+
+impl Request for References {
+    const METHOD: &'static str = "textDocument/references";
+    type Params = ReferenceParams;
+    type Result = Option<Vec<Location>>;
+}
+
+pub enum DocumentLinks {}
+
+impl Request for DocumentLinks {
+    const METHOD: &'static str = "textDocument/documentLink";
+    type Params = DocumentLinkParams;
+    type Result = Option<Vec<DocumentLink>>;
+}
+
+pub enum DocumentSymbols {}
+
+/// Either `DocumentSymbol`s or `SymbolInformation`s; the outline tools read both.
+impl Request for DocumentSymbols {
+    const METHOD: &'static str = "textDocument/documentSymbol";
+    type Params = DocumentSymbolParams;
+    type Result = Option<Vec<Value>>;
+}
+
+pub enum Rename {}
+
+/// The result is a `WorkspaceEdit`, which [`crate::edits`] applies as sent.
+impl Request for Rename {
+    const METHOD: &'static str = "textDocument/rename";
+    type Params = RenameParams;
+    type Result = Option<Value>;
+}
+
+pub enum CodeActions {}
+
+/// Each entry is a `Command` or a `CodeAction`, kept as sent so that the chosen
+/// one can be resolved or executed unchanged.
+impl Request for CodeActions {
+    const METHOD: &'static str = "textDocument/codeAction";
+    type Params = CodeActionParams;
+    type Result = Option<Vec<Value>>;
+}
+
+pub enum DidOpenTextDocument {}
+
+impl Notification for DidOpenTextDocument {
+    const METHOD: &'static str = "textDocument/didOpen";
+    type Params = DidOpenTextDocumentParams;
+}
+
+pub enum DidChangeTextDocument {}
+
+impl Notification for DidChangeTextDocument {
+    const METHOD: &'static str = "textDocument/didChange";
+    type Params = DidChangeTextDocumentParams;
+}
+
+pub enum DidSaveTextDocument {}
+
+impl Notification for DidSaveTextDocument {
+    const METHOD: &'static str = "textDocument/didSave";
+    type Params = DidSaveTextDocumentParams;
+}
+
+pub enum DidCloseTextDocument {}
+
+impl Notification for DidCloseTextDocument {
+    const METHOD: &'static str = "textDocument/didClose";
+    type Params = DidCloseTextDocumentParams;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn messages_have_the_wire_shape() {
+        let params = ReferenceParams {
+            text_document_position: TextDocumentPositionParams::new("file:///a.rs", 1, 2),
+            context: ReferenceContext {
+                include_declaration: false,
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(params).unwrap(),
+            json!({
+                "textDocument": {"uri": "file:///a.rs"},
+                "position": {"line": 1, "character": 2},
+                "context": {"includeDeclaration": false},
+            })
+        );
+
+        let position = Position {
+            line: 0,
+            character: 1,
+        };
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new("file:///a.rs"),
+            range: Range {
+                start: position,
+                end: position,
+            },
+            context: CodeActionContext {
+                diagnostics: Vec::new(),
+                only: None,
+                trigger_kind: Some(CodeActionTriggerKind::Invoked),
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(params).unwrap()["context"],
+            json!({"diagnostics": [], "triggerKind": 1})
+        );
+
+        let range =
+            json!({"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 1}});
+        let parse = |value: Value| serde_json::from_value::<Option<Locations>>(value).unwrap();
+        assert_eq!(parse(Value::Null), None);
+        let location = json!({"uri": "file:///a.rs", "range": range});
+        assert!(matches!(
+            parse(location.clone()).unwrap().into_vec().as_slice(),
+            [LocationOrLink::Location(_)]
+        ));
+        let link = json!({"targetUri": "file:///b.rs", "targetRange": range});
+        assert!(matches!(
+            parse(json!([location, link]))
+                .unwrap()
+                .into_vec()
+                .as_slice(),
+            [LocationOrLink::Location(_), LocationOrLink::Link(_)]
+        ));
+        assert!(serde_json::from_value::<Locations>(json!([{"uri": "file:///a.rs"}])).is_err());
+    }
+}
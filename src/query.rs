@@ -0,0 +1,217 @@
+//! `pathfinder query`: one tool call from the command line, without an MCP client.
+//!
+//! The service starts in-process and the call goes through an MCP client over an
+//! in-memory pipe, so a tool behaves exactly as it does for any other client: the
+//! same argument checks, sandbox, output formats, and middleware. `--file`,
+//! `--line`, and `--col` fill in whichever of a tool's inputs name a document and a
+//! position, and `--arg KEY=VALUE` sets anything else.
+//!
+//! Unlike tool calls from agents, positions default to counting from 1 and documents
+//! to paths relative to the workspace, as editors and compilers print them; an
+//! explicit `--arg one_based=false` or `--arg paths=uri` still wins.
+
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow, bail};
+use rmcp::ServiceExt;
+use rmcp::model::{CallToolRequestParam, CallToolResult, JsonObject};
+use serde_json::Value;
+
+use crate::args::{QueryArgs, split_assignment};
+use crate::config::Config;
+use crate::service::PathfinderService;
+
+/// Buffer between the MCP client and the service.
+const MCP_BUFFER: usize = 64 * 1024;
+
+/// Inputs `--file` fills, in order of preference.
+const FILE_KEYS: &[&str] = &["uri", "path"];
+
+/// Inputs `--line` fills, in order of preference.
+const LINE_KEYS: &[&str] = &["line", "start_line"];
+
+/// Inputs `--col` fills, in order of preference.
+const COLUMN_KEYS: &[&str] = &["character", "start_character"];
+
+/// Starts a service for `config`, makes the call `query` describes, and shuts the
+/// service down again.
+pub async fn run(
+    config: Config,
+    workspace_base: PathBuf,
+    query: &QueryArgs,
+) -> Result<CallToolResult> {
+    let service = PathfinderService::new(config, workspace_base).await?;
+    let (client_stream, server_stream) = tokio::io::duplex(MCP_BUFFER);
+    let (server, client) = tokio::join!(
+        service.clone().serve(tokio::io::split(server_stream)),
+        ().serve(tokio::io::split(client_stream)),
+    );
+    let server = server.map_err(|err| anyhow!("MCP server failed to start: {err}"))?;
+    let client = client.map_err(|err| anyhow!("MCP client failed to connect: {err}"))?;
+
+    let called = async {
+        let tools = client.list_all_tools().await?;
+        let Some(tool) = tools.iter().find(|tool| tool.name == query.tool) else {
+            let mut names: Vec<&str> = tools.iter().map(|tool| tool.name.as_ref()).collect();
+            names.sort_unstable();
+            bail!(
+                "no tool named '{}'; available: {}",
+                query.tool,
+                names.join(", ")
+            );
+        };
+        let arguments = arguments(query, &tool.input_schema)?;
+        Ok(client
+            .call_tool(CallToolRequestParam {
+                name: tool.name.clone(),
+                arguments: Some(arguments),
+            })
+            .await?)
+    }
+    .await;
+
+    let _ = client.cancel().await;
+    let _ = server.cancel().await;
+    service.shutdown().await;
+    called
+}
+
+/// The tool arguments for `query`, given the tool's input schema.
+fn arguments(query: &QueryArgs, schema: &JsonObject) -> Result<JsonObject> {
+    let properties = schema
+        .get("properties")
+        .and_then(Value::as_object)
+        .cloned()
+        .unwrap_or_default();
+    let input = |keys: &[&str], flag: &str| {
+        keys.iter()
+            .find(|key| properties.contains_key(**key))
+            .map(|key| key.to_string())
+            .ok_or_else(|| anyhow!("{} has no input for {flag}", query.tool))
+    };
+
+    let mut arguments = JsonObject::new();
+    arguments.insert("one_based".to_string(), Value::Bool(true));
+    arguments.insert("paths".to_string(), Value::from("relative"));
+    let format = if query.json { "json" } else { "text" };
+    arguments.insert("format".to_string(), Value::from(format));
+    if let Some(file) = &query.file {
+        arguments.insert(input(FILE_KEYS, "--file")?, Value::from(file.as_str()));
+    }
+    if let Some(line) = query.line {
+        arguments.insert(input(LINE_KEYS, "--line")?, Value::from(line));
+    }
+    if let Some(column) = query.col {
+        arguments.insert(input(COLUMN_KEYS, "--col")?, Value::from(column));
+    }
+    for entry in &query.arguments {
+        let (key, value) = split_assignment("--arg", "KEY=VALUE", entry)?;
+        let value = serde_json::from_str(value).unwrap_or_else(|_| Value::from(value));
+        arguments.insert(key.to_string(), value);
+    }
+    Ok(arguments)
+}
+
+/// What to print for `result`: the structured result as pretty JSON with `json`,
+/// else the text content.
+pub fn render(result: &CallToolResult, json: bool) -> Result<String> {
+    if json
+        && result.is_error != Some(true)
+        && let Some(structured) = &result.structured_content
+    {
+        return Ok(serde_json::to_string_pretty(structured)?);
+    }
+    let texts: Vec<&str> = result
+        .content
+        .iter()
+        .filter_map(|content| content.as_text())
+        .map(|text| text.text.as_str())
+        .collect();
+    Ok(texts.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use clap::Parser;
+
+    use crate::args::{Cli, Command};
+    use crate::config::{ConnectTarget, ServerConfig};
+
+    fn query(tool: &str, json: bool) -> QueryArgs {
+        let mut args = vec!["pathfinder", "query", tool, "--file", "src/main.rs"];
+        args.extend(["--line", "1", "--col", "4", "--mock", "-e", "rs"]);
+        if json {
+            args.push("--json");
+        }
+        match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Query(query)) => query,
+            _ => panic!("expected query subcommand"),
+        }
+    }
+
+    #[test]
+    fn flags_fill_the_tools_own_inputs() {
+        let schema = |keys: &[&str]| {
+            let properties: JsonObject = keys
+                .iter()
+                .map(|key| (key.to_string(), json!({})))
+                .collect();
+            let mut schema = JsonObject::new();
+            schema.insert("properties".to_string(), Value::Object(properties));
+            schema
+        };
+        let mut code_action = query("code_action", false);
+        code_action.arguments = vec!["kind=quickfix".to_string(), "end_line=3".to_string()];
+        let arguments = arguments(
+            &code_action,
+            &schema(&["uri", "start_line", "start_character", "end_line", "kind"]),
+        )
+        .unwrap();
+        assert_eq!(
+            Value::Object(arguments),
+            json!({
+                "uri": "src/main.rs",
+                "start_line": 1,
+                "start_character": 4,
+                "end_line": 3,
+                "kind": "quickfix",
+                "one_based": true,
+                "paths": "relative",
+                "format": "text",
+            })
+        );
+
+        let err = super::arguments(&query("find_symbol", false), &schema(&["query"])).unwrap_err();
+        assert_eq!(err.to_string(), "find_symbol has no input for --file");
+    }
+
+    #[tokio::test]
+    async fn runs_one_tool_against_the_mock_server() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        let config = Config {
+            server: ServerConfig {
+                extensions: vec!["rs".to_string()],
+                connect: Some(ConnectTarget::Mock),
+                ..ServerConfig::default()
+            },
+            companions: Vec::new(),
+            tools: Default::default(),
+            output: Default::default(),
+            sandbox: Default::default(),
+            diagnostics: Default::default(),
+            read_only: false,
+        };
+
+        let result = run(config, dir.path().to_path_buf(), &query("definition", true))
+            .await
+            .unwrap();
+        let printed: Value = serde_json::from_str(&render(&result, true).unwrap()).unwrap();
+        assert_eq!(printed["targets"][0]["uri"], "src/main.rs");
+        assert_eq!(printed["targets"][0]["range"]["start_line"], 1);
+    }
+}
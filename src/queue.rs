@@ -0,0 +1,265 @@
+//! Prioritized admission of requests to the language server.
+//!
+//! Several MCP sessions can share one server, and a client expanding a call graph or
+//! collecting references across a workspace can keep it busy with slow requests. The
+//! bridge lets at most `maxConcurrent` requests be in flight at once; the rest wait in
+//! a [`RequestQueue`] that admits interactive ones (hover, definition, ...) before
+//! normal ones, and normal ones before bulk ones (references, workspace/symbol,
+//! hierarchies, commands), first come first served within a class. Lifecycle
+//! requests such as `initialize`, `shutdown`, and `$/` extensions are never queued.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::Mutex;
+
+use tokio::sync::oneshot;
+
+use crate::config::{Priority, QueueConfig};
+
+/// Requests answered while the user waits on them.
+const INTERACTIVE_METHODS: &[&str] = &[
+    "textDocument/hover",
+    "textDocument/definition",
+    "textDocument/declaration",
+    "textDocument/typeDefinition",
+    "textDocument/implementation",
+    "textDocument/signatureHelp",
+    "textDocument/completion",
+    "textDocument/documentHighlight",
+    "textDocument/switchSourceHeader",
+];
+
+/// Method prefixes of requests that tend to be slow and issued in bulk.
+const BULK_PREFIXES: &[&str] = &[
+    "textDocument/references",
+    "textDocument/semanticTokens/",
+    "workspace/symbol",
+    "workspace/executeCommand",
+    "callHierarchy/",
+    "typeHierarchy/",
+];
+
+/// The default class of `method`, or `None` if it bypasses the queue.
+pub fn default_priority(method: &str) -> Option<Priority> {
+    if !method.contains('/') || method.starts_with("$/") {
+        None
+    } else if INTERACTIVE_METHODS.contains(&method) {
+        Some(Priority::Interactive)
+    } else if BULK_PREFIXES
+        .iter()
+        .any(|prefix| method.starts_with(prefix))
+    {
+        Some(Priority::Bulk)
+    } else {
+        Some(Priority::Normal)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RequestQueue {
+    /// Most requests in flight; 0 admits every request at once.
+    limit: usize,
+    /// Classes overriding [`default_priority`], keyed by method.
+    priorities: BTreeMap<String, Priority>,
+    state: Mutex<QueueState>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    running: usize,
+    waiting: BinaryHeap<Waiter>,
+    /// Arrival order, so waiters of one class are admitted first come first served.
+    next_seq: u64,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    priority: Priority,
+    seq: u64,
+    admit: oneshot::Sender<()>,
+}
+
+impl PartialEq for Waiter {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiter {}
+
+impl PartialOrd for Waiter {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Waiter {
+    /// Higher priority first, then earlier arrival.
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+impl RequestQueue {
+    pub fn new(config: &QueueConfig) -> Self {
+        Self {
+            limit: config.max_concurrent,
+            priorities: config.priorities.clone(),
+            state: Mutex::default(),
+        }
+    }
+
+    /// The class of `method`, or `None` if it bypasses the queue.
+    pub fn priority(&self, method: &str) -> Option<Priority> {
+        default_priority(method)
+            .map(|default| self.priorities.get(method).copied().unwrap_or(default))
+    }
+
+    /// Waits until a request for `method` may be sent. The request counts as in
+    /// flight until the returned permit is dropped.
+    pub async fn admit(&self, method: &str) -> Permit<'_> {
+        let Some(priority) = self.priority(method).filter(|_| self.limit > 0) else {
+            return Permit { queue: None };
+        };
+        let admitted = {
+            let mut state = self.state.lock().expect("request queue lock poisoned");
+            if state.running < self.limit {
+                state.running += 1;
+                return Permit { queue: Some(self) };
+            }
+            let (admit, admitted) = oneshot::channel();
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            state.waiting.push(Waiter {
+                priority,
+                seq,
+                admit,
+            });
+            admitted
+        };
+        let mut ticket = Ticket {
+            queue: self,
+            admitted,
+            done: false,
+        };
+        // Senders are only dropped after a successful send
+        let _ = (&mut ticket.admitted).await;
+        ticket.done = true;
+        Permit { queue: Some(self) }
+    }
+
+    /// Requests waiting for a slot.
+    pub fn waiting(&self) -> usize {
+        self.state
+            .lock()
+            .expect("request queue lock poisoned")
+            .waiting
+            .len()
+    }
+
+    /// Hands a finished request's slot to the next waiter still waiting, or frees it.
+    fn release(&self) {
+        let mut state = self.state.lock().expect("request queue lock poisoned");
+        while let Some(waiter) = state.waiting.pop() {
+            if waiter.admit.send(()).is_ok() {
+                return;
+            }
+        }
+        state.running -= 1;
+    }
+}
+
+/// A slot for one request in flight.
+#[must_use]
+pub struct Permit<'a> {
+    queue: Option<&'a RequestQueue>,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue {
+            queue.release();
+        }
+    }
+}
+
+/// A place in the queue. Dropped while waiting (timeout or cancellation), it passes
+/// on a slot it was handed in the meantime.
+struct Ticket<'a> {
+    queue: &'a RequestQueue,
+    admitted: oneshot::Receiver<()>,
+    done: bool,
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        if !self.done && self.admitted.try_recv().is_ok() {
+            self.queue.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn admits_interactive_requests_first() {
+        let queue = RequestQueue::new(&QueueConfig {
+            max_concurrent: 1,
+            priorities: BTreeMap::from([("workspace/symbol".to_string(), Priority::Normal)]),
+        });
+        assert_eq!(queue.priority("workspace/symbol"), Some(Priority::Normal));
+        assert_eq!(
+            queue.priority("textDocument/references"),
+            Some(Priority::Bulk)
+        );
+        assert_eq!(queue.priority("shutdown"), None);
+
+        let running = queue.admit("textDocument/references").await;
+        let order = Mutex::new(Vec::new());
+        let waiter = |method: &'static str| {
+            let (queue, order) = (&queue, &order);
+            async move {
+                let _permit = queue.admit(method).await;
+                order.lock().unwrap().push(method);
+            }
+        };
+        let bulk = waiter("callHierarchy/incomingCalls");
+        let normal = waiter("textDocument/documentSymbol");
+        let interactive = waiter("textDocument/hover");
+        // Lifecycle requests are never held up
+        let _shutdown = queue.admit("shutdown").await;
+
+        let waiters = async {
+            tokio::join!(bulk, normal, interactive);
+        };
+        let release = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!(queue.waiting(), 3);
+            drop(running);
+        };
+        tokio::join!(waiters, release);
+        assert_eq!(
+            *order.lock().unwrap(),
+            [
+                "textDocument/hover",
+                "textDocument/documentSymbol",
+                "callHierarchy/incomingCalls"
+            ]
+        );
+
+        // A waiter that gives up does not keep its slot
+        let running = queue.admit("textDocument/hover").await;
+        let abandoned =
+            tokio::time::timeout(Duration::from_millis(10), queue.admit("textDocument/hover"))
+                .await;
+        assert!(abandoned.is_err());
+        drop(running);
+        let _again = queue.admit("textDocument/hover").await;
+        assert_eq!(queue.waiting(), 0);
+    }
+}
@@ -0,0 +1,310 @@
+//! Recording LSP traffic and replaying it without a server.
+//!
+//! With `record` set (or `--record <file>`), every framed message exchanged with the
+//! server is appended to the file as one JSON line:
+//!
+//! ```text
+//! {"ms":1718000000123,"direction":"sent","message":{"jsonrpc":"2.0","id":1,...}}
+//! ```
+//!
+//! `connect = { replay = "<file>" }` then stands in for the server: each request is
+//! answered with the response recorded for the same method (and the same params,
+//! when such a request was recorded), and whatever the server sent on its own after
+//! a message, such as diagnostics or progress, is sent again at the same point. A
+//! bug report can thus be reproduced, and integration tests run, without the
+//! language server installed.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::Mutex as StdMutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::io::DuplexStream;
+
+use crate::transport::FramedTransport;
+
+/// Buffer between the bridge and a replayed server.
+const REPLAY_BUFFER: usize = 64 * 1024;
+
+/// Which way a recorded message went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// From pathfinder to the server
+    Sent,
+    /// From the server to pathfinder
+    Received,
+}
+
+/// One line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// Milliseconds since the Unix epoch.
+    pub ms: u64,
+    pub direction: Direction,
+    pub message: Value,
+}
+
+/// Appends messages to a recording file.
+pub struct Recorder {
+    file: StdMutex<File>,
+}
+
+impl Recorder {
+    /// Opens `path` for appending, creating it if needed, so a restarted server's
+    /// traffic follows the previous one's.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open recording {}", path.display()))?;
+        Ok(Self {
+            file: StdMutex::new(file),
+        })
+    }
+
+    /// Appends `message`. A failed write is logged rather than failing the
+    /// exchange with the server.
+    pub fn record(&self, direction: Direction, message: &Value) {
+        let ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as u64);
+        let entry = json!({ "ms": ms, "direction": direction, "message": message });
+        let mut line = entry.to_string();
+        line.push('\n');
+        let mut file = self.file.lock().expect("recording lock poisoned");
+        if let Err(err) = file.write_all(line.as_bytes()) {
+            tracing::warn!(?err, "Failed to record LSP message");
+        }
+    }
+}
+
+/// Reads a recording written by [`Recorder`].
+pub fn load(path: &Path) -> Result<Vec<Entry>> {
+    let file =
+        File::open(path).with_context(|| format!("failed to open recording {}", path.display()))?;
+    let mut entries = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.with_context(|| format!("failed to read {}", path.display()))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str(&line).with_context(|| {
+            format!("{}:{}: invalid recording entry", path.display(), number + 1)
+        })?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Starts a fake server answering from the recording at `path` and returns the
+/// stream to talk to it over.
+pub fn replay(path: &Path) -> Result<DuplexStream> {
+    let replay = Replay::new(load(path)?);
+    let (client, server) = tokio::io::duplex(REPLAY_BUFFER);
+    tokio::spawn(serve(replay, server));
+    Ok(client)
+}
+
+/// Answers framed messages until the client sends `exit` or hangs up.
+async fn serve(mut replay: Replay, stream: DuplexStream) {
+    let (reader, writer) = tokio::io::split(stream);
+    let (mut reader, mut writer) = FramedTransport::new(reader, writer).into_split();
+    while let Ok(Some(message)) = reader.read().await {
+        if message.get("method").and_then(Value::as_str) == Some("exit") {
+            break;
+        }
+        for reply in replay.answer(&message) {
+            if writer.write(&reply).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+/// The recording and how far it has been played back.
+struct Replay {
+    entries: Vec<Entry>,
+    played: Vec<bool>,
+}
+
+impl Replay {
+    fn new(entries: Vec<Entry>) -> Self {
+        let played = vec![false; entries.len()];
+        Self { entries, played }
+    }
+
+    /// What the server sends back after `message`: the recorded response to a
+    /// request (under the request's id), then the messages it sent on its own
+    /// before the next recorded client message, in recorded order.
+    fn answer(&mut self, message: &Value) -> Vec<Value> {
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // Our reply to a replayed server request
+            return Vec::new();
+        };
+        let id = message.get("id");
+        let Some(sent) = self.find_sent(method, message.get("params")) else {
+            return match id {
+                Some(id) => vec![json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": -32603,
+                        "message": format!("no recorded response to {method}"),
+                    },
+                })],
+                None => Vec::new(),
+            };
+        };
+        self.played[sent] = true;
+
+        let mut indices: Vec<usize> = self.entries[sent + 1..]
+            .iter()
+            .take_while(|entry| entry.direction == Direction::Received)
+            .enumerate()
+            .map(|(offset, _)| sent + 1 + offset)
+            .filter(|&index| self.entries[index].message.get("method").is_some())
+            .collect();
+        // Ids restart with each server, so only look past the request
+        let response = self.entries[sent].message.get("id").and_then(|recorded| {
+            let offset = self.entries[sent + 1..].iter().position(|entry| {
+                entry.direction == Direction::Received
+                    && entry.message.get("method").is_none()
+                    && entry.message.get("id") == Some(recorded)
+            })?;
+            Some(sent + 1 + offset)
+        });
+        indices.extend(response);
+        indices.sort_unstable();
+        indices.dedup();
+
+        indices
+            .into_iter()
+            .filter(|&index| !std::mem::replace(&mut self.played[index], true))
+            .map(|index| {
+                let mut reply = self.entries[index].message.clone();
+                if Some(index) == response
+                    && let (Some(id), Value::Object(fields)) = (id, &mut reply)
+                {
+                    fields.insert("id".to_string(), id.clone());
+                }
+                reply
+            })
+            .collect()
+    }
+
+    /// The first unplayed message we sent with `method` and `params`, or failing
+    /// that just with `method`.
+    fn find_sent(&self, method: &str, params: Option<&Value>) -> Option<usize> {
+        let unplayed = || {
+            self.entries.iter().enumerate().filter(|(index, entry)| {
+                !self.played[*index]
+                    && entry.direction == Direction::Sent
+                    && entry.message.get("method").and_then(Value::as_str) == Some(method)
+            })
+        };
+        unplayed()
+            .find(|(_, entry)| entry.message.get("params") == params)
+            .or_else(|| unplayed().next())
+            .map(|(index, _)| index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(direction: Direction, message: Value) -> Entry {
+        Entry {
+            ms: 0,
+            direction,
+            message,
+        }
+    }
+
+    fn recording() -> Vec<Entry> {
+        vec![
+            entry(
+                Direction::Sent,
+                json!({"jsonrpc": "2.0", "id": 1, "method": "textDocument/hover", "params": {"line": 1}}),
+            ),
+            entry(
+                Direction::Received,
+                json!({"jsonrpc": "2.0", "method": "window/logMessage", "params": {"message": "hi"}}),
+            ),
+            entry(
+                Direction::Received,
+                json!({"jsonrpc": "2.0", "id": 1, "result": "first"}),
+            ),
+            entry(
+                Direction::Sent,
+                json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {"line": 2}}),
+            ),
+            entry(
+                Direction::Received,
+                json!({"jsonrpc": "2.0", "id": 2, "result": "second"}),
+            ),
+        ]
+    }
+
+    #[test]
+    fn requests_get_the_recorded_response_under_their_own_id() {
+        let mut replay = Replay::new(recording());
+        let replies = replay.answer(
+            &json!({"jsonrpc": "2.0", "id": 40, "method": "textDocument/hover", "params": {"line": 2}}),
+        );
+        assert_eq!(
+            replies,
+            [json!({"jsonrpc": "2.0", "id": 40, "result": "second"})]
+        );
+
+        // No exact match left, so the next recorded hover answers, notification first
+        let replies = replay.answer(
+            &json!({"jsonrpc": "2.0", "id": 41, "method": "textDocument/hover", "params": {"line": 9}}),
+        );
+        assert_eq!(replies.len(), 2);
+        assert_eq!(replies[0]["method"], "window/logMessage");
+        assert_eq!(
+            replies[1],
+            json!({"jsonrpc": "2.0", "id": 41, "result": "first"})
+        );
+
+        let replies =
+            replay.answer(&json!({"jsonrpc": "2.0", "id": 42, "method": "textDocument/hover"}));
+        assert_eq!(replies[0]["error"]["code"], -32603);
+    }
+
+    #[tokio::test]
+    async fn recorder_output_replays() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("session.jsonl");
+        let recorder = Recorder::open(&path).unwrap();
+        for entry in recording() {
+            recorder.record(entry.direction, &entry.message);
+        }
+        assert_eq!(load(&path).unwrap().len(), 5);
+
+        let (reader, writer) = tokio::io::split(replay(&path).unwrap());
+        let mut transport = FramedTransport::new(reader, writer);
+        transport
+            .write(&json!({"jsonrpc": "2.0", "id": 7, "method": "textDocument/hover", "params": {"line": 1}}))
+            .await
+            .unwrap();
+        let notification = transport.read().await.unwrap().unwrap();
+        assert_eq!(notification["method"], "window/logMessage");
+        let response = transport.read().await.unwrap().unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"], "first");
+
+        transport
+            .write(&json!({"jsonrpc": "2.0", "method": "exit"}))
+            .await
+            .unwrap();
+        assert!(transport.read().await.unwrap().is_none());
+    }
+}
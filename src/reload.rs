@@ -0,0 +1,179 @@
+//! Hot reload of the config file.
+//!
+//! When pathfinder runs from a config file (`--config`, `PATHFINDER_CONFIG`, or one
+//! found in the workspace), [`watch`] reloads it whenever it changes and hands the
+//! result to [`PathfinderService::reload`]: tool filters, custom tools, middleware,
+//! the sandbox, and output defaults are rebuilt, timeouts and extensions apply to the
+//! running language server, and any other change to the `server` section restarts
+//! it. Clients are sent `notifications/tools/list_changed` when the tools they see
+//! change.
+//!
+//! The command-line flags that adjust the config ([`ConfigOverrides`]) are applied
+//! again to every reloaded config. A file that fails to load or validate is logged
+//! and the config in effect is kept.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::task::JoinHandle;
+
+use crate::config::Config;
+use crate::service::PathfinderService;
+use crate::watcher::FileWatcher;
+
+/// Quiet period after the last change before reloading, so an editor saving the
+/// file in several steps causes one reload.
+const RELOAD_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Command-line flags applied over the config file, at startup and on every reload.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOverrides {
+    /// `--disable-tool`
+    pub disabled_tools: Vec<String>,
+    /// `--no-sandbox`
+    pub no_sandbox: bool,
+    /// `--readonly`
+    pub read_only: bool,
+    /// `--record`, already absolute.
+    pub record: Option<PathBuf>,
+}
+
+impl ConfigOverrides {
+    pub fn apply(&self, config: &mut Config) {
+        config
+            .tools
+            .disabled
+            .extend(self.disabled_tools.iter().cloned());
+        if self.no_sandbox {
+            config.sandbox.enabled = false;
+        }
+        if self.read_only {
+            config.read_only = true;
+        }
+        if let Some(path) = &self.record {
+            config.server.record = Some(path.clone());
+        }
+    }
+
+    /// Loads the config file at `path` with the overrides applied.
+    pub fn load(&self, path: &Path) -> Result<Config> {
+        let mut config = Config::from_file(path)?;
+        self.apply(&mut config);
+        Ok(config)
+    }
+}
+
+/// Reloads `service` from the config file at `path` each time the file changes,
+/// until the returned task is aborted.
+pub fn watch(
+    path: &Path,
+    overrides: ConfigOverrides,
+    service: PathfinderService,
+) -> Result<JoinHandle<()>> {
+    // Watch the directory, since editors often save by replacing the file
+    let path = std::path::absolute(path)?;
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} is not a file", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("/"));
+    let dir = crate::utils::canonicalize(dir)
+        .with_context(|| format!("failed to resolve {}", dir.display()))?;
+    let path = dir.join(name);
+    let mut watcher = FileWatcher::start_dir(&dir)?;
+    tracing::info!(config = %path.display(), "Watching config file for changes");
+
+    Ok(tokio::spawn(async move {
+        while let Some(changes) = watcher.next_batch(RELOAD_DEBOUNCE).await {
+            if !changes.iter().any(|change| change.path == path) {
+                continue;
+            }
+            let reloaded = match overrides.load(&path) {
+                Ok(config) => service.reload(config).await,
+                Err(err) => Err(err),
+            };
+            if let Err(err) = reloaded {
+                tracing::warn!(
+                    config = %path.display(),
+                    "Failed to reload config; keeping the current one: {err:#}"
+                );
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rmcp::{Peer, RoleClient, ServiceExt};
+
+    const CONFIG: &str = r#"{
+        "server": {"extensions": ["rs"], "connect": "mock", "rootDir": "."},
+        "tools": {"disabled": ["read_file_range"]}
+    }"#;
+
+    async fn tool_names(client: &Peer<RoleClient>) -> Vec<String> {
+        let tools = client.list_all_tools().await.unwrap();
+        tools
+            .into_iter()
+            .map(|tool| tool.name.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn overrides_apply_to_every_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pathfinder.json");
+        std::fs::write(&path, CONFIG).unwrap();
+        let overrides = ConfigOverrides {
+            disabled_tools: vec!["search_text".to_string()],
+            read_only: true,
+            ..ConfigOverrides::default()
+        };
+
+        let config = overrides.load(&path).unwrap();
+        assert_eq!(config.tools.disabled, ["read_file_range", "search_text"]);
+        assert!(config.read_only);
+        assert!(config.sandbox.enabled);
+    }
+
+    #[tokio::test]
+    async fn reload_changes_the_tools_clients_see() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pathfinder.json");
+        std::fs::write(&path, CONFIG).unwrap();
+        let overrides = ConfigOverrides::default();
+        let service = PathfinderService::new(overrides.load(&path).unwrap(), dir.path().into())
+            .await
+            .unwrap();
+        let (client_stream, server_stream) = tokio::io::duplex(64 * 1024);
+        let (server, client) = tokio::join!(
+            service.clone().serve(tokio::io::split(server_stream)),
+            ().serve(tokio::io::split(client_stream)),
+        );
+        let (server, client) = (server.unwrap(), client.unwrap());
+        assert!(
+            !tool_names(&client)
+                .await
+                .contains(&"read_file_range".to_string())
+        );
+
+        let mut config = overrides.load(&path).unwrap();
+        config.tools.disabled = vec!["definition".to_string()];
+        service.reload(config).await.unwrap();
+        let listed = tool_names(&client).await;
+        assert!(listed.contains(&"read_file_range".to_string()));
+        assert!(!listed.contains(&"definition".to_string()));
+
+        // A rejected config leaves the current one in effect
+        let mut config = overrides.load(&path).unwrap();
+        config.tools.disabled = vec!["no_such_tool".to_string()];
+        assert!(service.reload(config).await.is_err());
+        assert_eq!(tool_names(&client).await, listed);
+
+        let _ = client.cancel().await;
+        let _ = server.cancel().await;
+        service.shutdown().await;
+    }
+}
@@ -0,0 +1,295 @@
+//! MCP resource templates.
+//!
+//! Resource-oriented MCP clients can read LSP results by URI instead of calling tools:
+//!
+//! - `symbol://{query}`: `workspace/symbol` matches for a percent-encoded query, or
+//!   declarations found by [`crate::tags`] if the server has none (`fallback: true`)
+//! - `definition://{uri}#L{line}:{character}`: the definition targets of a position,
+//!   each with a snippet of the target's source
+//!
+//! Positions follow the tool conventions: zero-based lines, and columns in the
+//! configured column encoding.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result, anyhow, bail};
+use percent_encoding::percent_decode_str;
+use rmcp::model::{AnnotateAble, RawResourceTemplate, ResourceTemplate};
+use serde_json::{Value, json};
+
+use crate::lsp_bridge::LspBridge;
+use crate::position::PositionConverter;
+use crate::tags;
+use crate::tools::definition::{DefinitionRequest, DefinitionTool};
+use crate::utils::symbol_kind_name;
+
+const SYMBOL_SCHEME: &str = "symbol://";
+const DEFINITION_SCHEME: &str = "definition://";
+
+/// Lines shown before and after a definition target's range.
+const SNIPPET_CONTEXT: u32 = 2;
+/// Upper bound on snippet length, for targets spanning whole modules.
+const MAX_SNIPPET_LINES: u32 = 40;
+/// Matches returned from the built-in tags, which match by substring and can be many.
+const MAX_FALLBACK_SYMBOLS: usize = 200;
+
+/// The templates advertised in `resources/templates/list`.
+pub fn templates() -> Vec<ResourceTemplate> {
+    let template = |uri_template: &str, name: &str, description: &str| {
+        RawResourceTemplate {
+            uri_template: uri_template.to_string(),
+            name: name.to_string(),
+            title: None,
+            description: Some(description.to_string()),
+            mime_type: Some("application/json".to_string()),
+        }
+        .no_annotation()
+    };
+    vec![
+        template(
+            "symbol://{query}",
+            "symbol",
+            "Workspace symbols matching a query (workspace/symbol)",
+        ),
+        template(
+            "definition://{uri}#L{line}:{character}",
+            "definition",
+            "Definition targets of a zero-based position in a file:// URI, with source snippets",
+        ),
+    ]
+}
+
+/// A parsed resource URI.
+#[derive(Debug, Clone)]
+pub enum ResourceRequest {
+    Symbol { query: String },
+    Definition(DefinitionRequest),
+}
+
+impl ResourceRequest {
+    pub fn parse(uri: &str) -> Result<Self> {
+        if let Some(query) = uri.strip_prefix(SYMBOL_SCHEME) {
+            let query = percent_decode_str(query)
+                .decode_utf8()
+                .context("symbol query is not valid UTF-8")?;
+            return Ok(Self::Symbol {
+                query: query.into_owned(),
+            });
+        }
+        if let Some(rest) = uri.strip_prefix(DEFINITION_SCHEME) {
+            let (document, position) = rest
+                .rsplit_once("#L")
+                .ok_or_else(|| anyhow!("definition resource must end in #L<line>:<character>"))?;
+            let (line, character) = position
+                .split_once(':')
+                .ok_or_else(|| anyhow!("definition position must be <line>:<character>"))?;
+            return Ok(Self::Definition(DefinitionRequest {
+                uri: document.to_string(),
+                line: line.parse().context("invalid line")?,
+                character: character.parse().context("invalid character")?,
+                snap: None,
+                server: None,
+                options: Default::default(),
+            }));
+        }
+        bail!("unknown resource URI: {uri}")
+    }
+}
+
+/// Runs `workspace/symbol` and flattens the matches. With `fallback`, answers from
+/// the built-in [`tags`] when the server has no symbol support or no matches.
+pub async fn symbols(lsp: &LspBridge, query: &str, fallback: bool) -> Result<Value> {
+    if !lsp
+        .server_capabilities()
+        .supports("workspaceSymbolProvider")
+        && fallback
+    {
+        return fallback_symbols(lsp, query).await;
+    }
+    lsp.server_capabilities()
+        .ensure("workspaceSymbolProvider", "workspace/symbol")?;
+    let raw = lsp
+        .request("workspace/symbol", json!({ "query": query }))
+        .await
+        .context("LSP workspace/symbol request failed")?;
+
+    let mut converter = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding());
+    let mut symbols = Vec::new();
+    for symbol in raw.as_array().map(Vec::as_slice).unwrap_or_default() {
+        symbols.push(convert_symbol(&mut converter, symbol).await?);
+    }
+    if symbols.is_empty() && fallback {
+        return fallback_symbols(lsp, query).await;
+    }
+    Ok(json!({ "symbols": symbols }))
+}
+
+/// Declarations matching `query` in the same shape as server symbols, flagged as
+/// `fallback`.
+async fn fallback_symbols(lsp: &LspBridge, query: &str) -> Result<Value> {
+    let found = tags::symbols(
+        lsp.workspace(),
+        lsp.column_encoding(),
+        query,
+        MAX_FALLBACK_SYMBOLS,
+    )
+    .await?;
+    let symbols: Vec<Value> = found
+        .iter()
+        .map(|tag| {
+            json!({
+                "name": tag.name,
+                "kind": tag.kind,
+                "container": Value::Null,
+                "uri": tag.uri(),
+                "range": {
+                    "start_line": tag.line,
+                    "start_character": tag.start_character,
+                    "end_line": tag.line,
+                    "end_character": tag.end_character,
+                },
+            })
+        })
+        .collect();
+    Ok(json!({ "symbols": symbols, "fallback": true }))
+}
+
+/// Converts a `SymbolInformation` or `WorkspaceSymbol`; the latter may omit the range.
+async fn convert_symbol(converter: &mut PositionConverter, symbol: &Value) -> Result<Value> {
+    let name = symbol
+        .get("name")
+        .and_then(|name| name.as_str())
+        .ok_or_else(|| anyhow!("symbol is missing name: {symbol}"))?;
+    let location = symbol
+        .get("location")
+        .ok_or_else(|| anyhow!("symbol is missing location: {symbol}"))?;
+    let uri = location
+        .get("uri")
+        .and_then(|uri| uri.as_str())
+        .ok_or_else(|| anyhow!("symbol location is missing uri: {symbol}"))?;
+    let kind = symbol
+        .get("kind")
+        .and_then(|kind| kind.as_u64())
+        .map(symbol_kind_name)
+        .unwrap_or("unknown");
+
+    let range = match location.get("range") {
+        Some(range) => {
+            let position = |key: &str, field: &str| {
+                range[key][field]
+                    .as_u64()
+                    .map(|value| value as u32)
+                    .ok_or_else(|| anyhow!("symbol range is missing {key}.{field}"))
+            };
+            let (start_line, end_line) = (position("start", "line")?, position("end", "line")?);
+            let start_character = converter
+                .to_client(uri, start_line, position("start", "character")?)
+                .await;
+            let end_character = converter
+                .to_client(uri, end_line, position("end", "character")?)
+                .await;
+            json!({
+                "start_line": start_line,
+                "start_character": start_character,
+                "end_line": end_line,
+                "end_character": end_character,
+            })
+        }
+        None => Value::Null,
+    };
+    Ok(json!({
+        "name": name,
+        "kind": kind,
+        "container": symbol.get("containerName"),
+        "uri": uri,
+        "range": range,
+    }))
+}
+
+/// Resolves a definition and attaches a snippet of source around each target.
+///
+/// `overlays` holds the text of overlay documents, used in place of the file on disk.
+pub async fn definition(
+    lsp: &LspBridge,
+    request: DefinitionRequest,
+    overlays: HashMap<String, String>,
+) -> Result<Value> {
+    let response = DefinitionTool::new()
+        .execute(lsp, request, overlays.clone())
+        .await?;
+    let mut targets = Vec::new();
+    for target in response.targets {
+        let text = match overlays.get(&target.uri) {
+            Some(text) => Some(text.clone()),
+            None => read_target(&target.uri).await,
+        };
+        let snippet =
+            text.map(|text| snippet(&text, target.range.start_line, target.range.end_line));
+        targets.push(json!({
+            "uri": target.uri,
+            "range": target.range,
+            "snippet": snippet,
+        }));
+    }
+    Ok(json!({ "targets": targets }))
+}
+
+async fn read_target(uri: &str) -> Option<String> {
+    let path = crate::utils::uri_to_file_path(uri).ok()?;
+    Some(crate::encoding::read_file(&path).await.ok()?.text)
+}
+
+/// Lines `start..=end` of `text` plus context, as `{ start_line, text }`.
+fn snippet(text: &str, start: u32, end: u32) -> Value {
+    let first = start.saturating_sub(SNIPPET_CONTEXT);
+    let last = end
+        .saturating_add(SNIPPET_CONTEXT)
+        .min(first + MAX_SNIPPET_LINES - 1);
+    let lines: Vec<&str> = text
+        .lines()
+        .skip(first as usize)
+        .take((last - first + 1) as usize)
+        .collect();
+    json!({ "start_line": first, "text": lines.join("\n") })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_resource_uris() {
+        let ResourceRequest::Symbol { query } =
+            ResourceRequest::parse("symbol://Foo%20bar").unwrap()
+        else {
+            panic!("expected a symbol resource");
+        };
+        assert_eq!(query, "Foo bar");
+
+        let ResourceRequest::Definition(request) =
+            ResourceRequest::parse("definition://file:///ws/src/a.rs#L10:4").unwrap()
+        else {
+            panic!("expected a definition resource");
+        };
+        assert_eq!(request.uri, "file:///ws/src/a.rs");
+        assert_eq!((request.line, request.character), (10, 4));
+
+        assert!(ResourceRequest::parse("definition://file:///ws/a.rs").is_err());
+        assert!(ResourceRequest::parse("hover://x").is_err());
+    }
+
+    #[test]
+    fn snippet_adds_bounded_context() {
+        let text = (0..100)
+            .map(|n| format!("line {n}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(
+            snippet(&text, 1, 1),
+            json!({ "start_line": 0, "text": "line 0\nline 1\nline 2\nline 3" })
+        );
+        let long = snippet(&text, 10, 90);
+        assert_eq!(long["start_line"], 8);
+        assert_eq!(long["text"].as_str().unwrap().lines().count(), 40);
+    }
+}
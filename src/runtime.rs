@@ -0,0 +1,404 @@
+//! Where the language server process runs.
+//!
+//! By default the server is spawned directly on this machine. With
+//! `runtime = "docker"` it runs in a container instead, started with `docker run -i`
+//! and the workspace root bind-mounted at `docker.mountPath`; with `runtime = "ssh"`
+//! it runs on another host over `ssh -T`. Either way the server sees different paths
+//! than pathfinder does, so a [`PathMap`] rewrites file URIs in every message between
+//! the two.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow};
+use percent_encoding::percent_decode_str;
+use serde_json::Value;
+use url::Url;
+
+use crate::config::{DockerConfig, Runtime, ServerConfig, SshConfig};
+
+/// Keys whose string values are document content, never rewritten.
+const CONTENT_KEYS: &[&str] = &["text", "newText"];
+
+/// The process to spawn for a server, with the environment to give it.
+#[derive(Debug)]
+pub struct ServerLaunch {
+    /// Program and arguments.
+    pub command: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub env_remove: Vec<String>,
+    pub cwd: PathBuf,
+    /// Translation between our paths and the server's, if they differ.
+    pub path_map: Option<PathMap>,
+}
+
+/// Builds the launch for `server` in `workspace` according to its runtime.
+pub fn launch(server: &ServerConfig, workspace: &Path) -> Result<ServerLaunch> {
+    match server.runtime {
+        Runtime::Local => Ok(ServerLaunch {
+            command: server.command.clone(),
+            env: server.env.clone().into_iter().collect(),
+            env_remove: server.env_remove.clone(),
+            cwd: server.resolve_cwd(workspace),
+            path_map: None,
+        }),
+        Runtime::Docker => {
+            let docker = server
+                .docker
+                .as_ref()
+                .ok_or_else(|| anyhow!("runtime \"docker\" needs a docker section"))?;
+            docker_launch(server, docker, workspace)
+        }
+        Runtime::Ssh => {
+            let ssh = server
+                .ssh
+                .as_ref()
+                .ok_or_else(|| anyhow!("runtime \"ssh\" needs an ssh section"))?;
+            ssh_launch(server, ssh, workspace)
+        }
+    }
+}
+
+/// `docker run -i` with the workspace mounted, the server's environment passed as
+/// `-e` flags, and the working directory set inside the container.
+fn docker_launch(
+    server: &ServerConfig,
+    docker: &DockerConfig,
+    workspace: &Path,
+) -> Result<ServerLaunch> {
+    let mount = docker.mount_path.as_str();
+    let workdir = match &server.cwd {
+        Some(cwd) if cwd.is_absolute() => cwd.display().to_string(),
+        Some(cwd) => format!("{}/{}", mount.trim_end_matches('/'), cwd.display()),
+        None => mount.to_string(),
+    };
+    let mut command: Vec<String> = ["docker", "run", "-i", "--rm", "--init"]
+        .map(String::from)
+        .into();
+    command.push("-v".to_string());
+    command.push(format!("{}:{mount}", workspace.display()));
+    command.push("-w".to_string());
+    command.push(workdir);
+    for (key, value) in &server.env {
+        command.push("-e".to_string());
+        command.push(format!("{key}={value}"));
+    }
+    command.extend(docker.args.iter().cloned());
+    command.push(docker.image.clone());
+    command.extend(server.command.iter().cloned());
+
+    Ok(ServerLaunch {
+        command,
+        env: Vec::new(),
+        env_remove: Vec::new(),
+        cwd: workspace.to_path_buf(),
+        path_map: Some(PathMap::new([(workspace, mount)])?),
+    })
+}
+
+/// `ssh -T` running the server in its working directory on the remote host.
+///
+/// The remote directory is the workspace root as mapped by `ssh.pathMap`; an absolute
+/// `cwd` is taken as a remote path, a relative one is joined onto it.
+fn ssh_launch(server: &ServerConfig, ssh: &SshConfig, workspace: &Path) -> Result<ServerLaunch> {
+    let path_map = if ssh.path_map.is_empty() {
+        None
+    } else {
+        let pairs: Vec<(PathBuf, &str)> = ssh
+            .path_map
+            .iter()
+            .map(|(local, remote)| (workspace.join(local), remote.as_str()))
+            .collect();
+        Some(PathMap::new(
+            pairs
+                .iter()
+                .map(|(local, remote)| (local.as_path(), *remote)),
+        )?)
+    };
+    let remote_root = match &path_map {
+        Some(map) => map.server_path(workspace)?,
+        None => workspace.display().to_string(),
+    };
+    let workdir = match &server.cwd {
+        Some(cwd) if cwd.is_absolute() => cwd.display().to_string(),
+        Some(cwd) => format!("{}/{}", remote_root.trim_end_matches('/'), cwd.display()),
+        None => remote_root,
+    };
+
+    // ssh hands the remote command to a shell, so it is sent as one quoted string
+    let mut remote = format!("cd {} && exec", shell_quote(&workdir));
+    if !server.env.is_empty() {
+        remote.push_str(" env");
+        for (key, value) in &server.env {
+            remote.push(' ');
+            remote.push_str(&shell_quote(&format!("{key}={value}")));
+        }
+    }
+    for part in &server.command {
+        remote.push(' ');
+        remote.push_str(&shell_quote(part));
+    }
+
+    let mut command: Vec<String> = ["ssh", "-T", "-o", "BatchMode=yes"]
+        .map(String::from)
+        .into();
+    if let Some(port) = ssh.port {
+        command.push("-p".to_string());
+        command.push(port.to_string());
+    }
+    command.extend(ssh.args.iter().cloned());
+    command.push(match &ssh.user {
+        Some(user) => format!("{user}@{}", ssh.host),
+        None => ssh.host.clone(),
+    });
+    command.push(remote);
+
+    Ok(ServerLaunch {
+        command,
+        env: Vec::new(),
+        env_remove: Vec::new(),
+        cwd: workspace.to_path_buf(),
+        path_map,
+    })
+}
+
+/// Quotes `text` as one word for a POSIX shell.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// Rewrites file URIs between our side and the server's.
+///
+/// Each entry maps a local directory to the directory it appears as to the server.
+/// A URI is rewritten when it is one of the directories or lies below it; the
+/// longest matching directory wins.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathMap {
+    /// (local URI prefix, server URI prefix), without trailing slashes, longest
+    /// local prefix first.
+    to_server: Vec<(String, String)>,
+    /// The same pairs swapped, longest server prefix first.
+    to_client: Vec<(String, String)>,
+}
+
+impl PathMap {
+    /// Builds a map from (local directory, server directory) pairs. Local
+    /// directories must be absolute; server directories are absolute paths on the
+    /// server's machine.
+    pub fn new<'a>(pairs: impl IntoIterator<Item = (&'a Path, &'a str)>) -> Result<Self> {
+        let mut to_server = Vec::new();
+        for (local, remote) in pairs {
+            let local_uri = Url::from_file_path(local)
+                .map_err(|_| anyhow!("not an absolute path: {}", local.display()))?;
+            let mut remote_uri = Url::parse("file:///").expect("static URL parses");
+            if !remote.starts_with('/') {
+                return Err(anyhow!("server path must be absolute: {remote}"));
+            }
+            remote_uri.set_path(remote);
+            to_server.push((prefix(&local_uri), prefix(&remote_uri)));
+        }
+        let mut to_client: Vec<_> = to_server
+            .iter()
+            .map(|(local, remote)| (remote.clone(), local.clone()))
+            .collect();
+        to_server.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+        to_client.sort_by_key(|(from, _)| std::cmp::Reverse(from.len()));
+        Ok(Self {
+            to_server,
+            to_client,
+        })
+    }
+
+    /// Rewrites our URIs in a message bound for the server.
+    pub fn to_server(&self, value: &mut Value) {
+        rewrite(value, &self.to_server);
+    }
+
+    /// Rewrites the server's URIs in a message it sent.
+    pub fn to_client(&self, value: &mut Value) {
+        rewrite(value, &self.to_client);
+    }
+
+    /// Where the local `path` is on the server, e.g. for `rootPath`.
+    pub fn server_path(&self, path: &Path) -> Result<String> {
+        let uri = Url::from_file_path(path)
+            .map_err(|_| anyhow!("not an absolute path: {}", path.display()))?;
+        let mapped = map_uri(uri.as_str(), &self.to_server).unwrap_or_else(|| uri.to_string());
+        let mapped = Url::parse(&mapped).context("mapped path is not a valid URI")?;
+        Ok(percent_decode_str(mapped.path())
+            .decode_utf8_lossy()
+            .into_owned())
+    }
+}
+
+/// A directory URI without its trailing slash.
+fn prefix(uri: &Url) -> String {
+    uri.as_str().trim_end_matches('/').to_string()
+}
+
+fn map_uri(uri: &str, pairs: &[(String, String)]) -> Option<String> {
+    pairs.iter().find_map(|(from, to)| {
+        let rest = uri.strip_prefix(from.as_str())?;
+        (rest.is_empty() || rest.starts_with('/')).then(|| format!("{to}{rest}"))
+    })
+}
+
+/// Maps every string and object key in `value`, skipping document content.
+fn rewrite(value: &mut Value, pairs: &[(String, String)]) {
+    match value {
+        Value::String(text) => {
+            if let Some(mapped) = map_uri(text, pairs) {
+                *text = mapped;
+            }
+        }
+        Value::Array(items) => {
+            for item in items {
+                rewrite(item, pairs);
+            }
+        }
+        Value::Object(fields) => {
+            // Keys matter too: `WorkspaceEdit.changes` is keyed by URI
+            for (key, mut field) in std::mem::take(fields) {
+                if !CONTENT_KEYS.contains(&key.as_str()) {
+                    rewrite(&mut field, pairs);
+                }
+                fields.insert(map_uri(&key, pairs).unwrap_or(key), field);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn map() -> PathMap {
+        PathMap::new([(Path::new("/home/me/proj"), "/workspace")]).unwrap()
+    }
+
+    #[test]
+    fn uris_are_rewritten_both_ways() {
+        let mut params = json!({
+            "textDocument": { "uri": "file:///home/me/proj/src/main.rs", "text": "file:///home/me/proj" },
+            "rootUri": "file:///home/me/proj",
+            "other": "file:///home/me/project2/a.rs",
+        });
+        map().to_server(&mut params);
+        assert_eq!(
+            params["textDocument"]["uri"],
+            "file:///workspace/src/main.rs"
+        );
+        assert_eq!(params["textDocument"]["text"], "file:///home/me/proj");
+        assert_eq!(params["rootUri"], "file:///workspace");
+        assert_eq!(params["other"], "file:///home/me/project2/a.rs");
+
+        let mut edit = json!({ "changes": { "file:///workspace/lib.rs": [] } });
+        map().to_client(&mut edit);
+        assert!(edit["changes"]["file:///home/me/proj/lib.rs"].is_array());
+    }
+
+    #[test]
+    fn longest_prefix_wins() {
+        let map = PathMap::new([
+            (Path::new("/src"), "/remote/src"),
+            (Path::new("/src/vendor"), "/opt/vendor"),
+        ])
+        .unwrap();
+        let mut value = json!(["file:///src/vendor/x.c", "file:///src/main.c"]);
+        map.to_server(&mut value);
+        assert_eq!(
+            value,
+            json!(["file:///opt/vendor/x.c", "file:///remote/src/main.c"])
+        );
+        assert!(PathMap::new([(Path::new("/src"), "relative")]).is_err());
+    }
+
+    #[test]
+    fn server_path_decodes_the_mapped_uri() {
+        let map = PathMap::new([(Path::new("/home/me/my proj"), "/work space")]).unwrap();
+        assert_eq!(
+            map.server_path(Path::new("/home/me/my proj")).unwrap(),
+            "/work space"
+        );
+    }
+
+    #[test]
+    fn docker_launch_mounts_the_workspace() {
+        let server = ServerConfig {
+            command: vec!["clangd".to_string(), "--background-index".to_string()],
+            runtime: Runtime::Docker,
+            docker: Some(DockerConfig {
+                image: "clangd:18".to_string(),
+                mount_path: "/workspace".to_string(),
+                args: vec!["--network=none".to_string()],
+            }),
+            env: [("CC".to_string(), "clang".to_string())].into(),
+            cwd: Some(PathBuf::from("build")),
+            ..ServerConfig::default()
+        };
+        let launch = launch(&server, Path::new("/home/me/proj")).unwrap();
+        assert_eq!(
+            launch.command,
+            [
+                "docker",
+                "run",
+                "-i",
+                "--rm",
+                "--init",
+                "-v",
+                "/home/me/proj:/workspace",
+                "-w",
+                "/workspace/build",
+                "-e",
+                "CC=clang",
+                "--network=none",
+                "clangd:18",
+                "clangd",
+                "--background-index",
+            ]
+        );
+        assert!(launch.env.is_empty());
+        assert_eq!(launch.cwd, PathBuf::from("/home/me/proj"));
+        assert!(launch.path_map.is_some());
+    }
+
+    #[test]
+    fn ssh_launch_runs_the_command_remotely() {
+        let server = ServerConfig {
+            command: vec!["clangd".to_string(), "--log=error".to_string()],
+            runtime: Runtime::Ssh,
+            ssh: Some(SshConfig {
+                host: "build01".to_string(),
+                user: Some("ci".to_string()),
+                port: Some(2222),
+                args: Vec::new(),
+                path_map: [(PathBuf::from("."), "/srv/app".to_string())].into(),
+            }),
+            env: [("CC".to_string(), "clang".to_string())].into(),
+            ..ServerConfig::default()
+        };
+        let launch = launch(&server, Path::new("/home/me/app")).unwrap();
+        assert_eq!(
+            launch.command,
+            [
+                "ssh",
+                "-T",
+                "-o",
+                "BatchMode=yes",
+                "-p",
+                "2222",
+                "ci@build01",
+                "cd '/srv/app' && exec env 'CC=clang' 'clangd' '--log=error'",
+            ]
+        );
+        let mut uri = json!("file:///home/me/app/main.cpp");
+        launch.path_map.unwrap().to_server(&mut uri);
+        assert_eq!(uri, "file:///srv/app/main.cpp");
+    }
+
+    #[test]
+    fn shell_quote_escapes_single_quotes() {
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+    }
+}
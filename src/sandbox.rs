@@ -0,0 +1,122 @@
+//! Restricting which documents tools may touch.
+//!
+//! Every document argument of a tool, prompt, or resource goes through
+//! [`Sandbox::document_uri`] before the document is read or sent to the language
+//! server. Unless the sandbox is turned off, the document must resolve (symlinks
+//! followed) to a path under the workspace root or one of `sandbox.allowedRoots`, so
+//! a client cannot have `open_document` or `definition` read `/etc/passwd`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Result, bail};
+
+use crate::config::SandboxConfig;
+use crate::notebook;
+use crate::utils::{canonicalize, normalize_document_uri, strip_path_prefix, uri_to_file_path};
+
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    workspace: PathBuf,
+    /// Directories documents must be under; `None` when the sandbox is off
+    roots: Option<Vec<PathBuf>>,
+}
+
+impl Sandbox {
+    /// Allowed roots are the workspace and `config.allowed_roots`, the latter
+    /// relative to the workspace.
+    pub fn new(config: &SandboxConfig, workspace: &Path) -> Self {
+        let roots = config.enabled.then(|| {
+            std::iter::once(workspace.to_path_buf())
+                .chain(config.allowed_roots.iter().map(|root| workspace.join(root)))
+                .map(|root| resolve(&root))
+                .collect()
+        });
+        Self {
+            workspace: workspace.to_path_buf(),
+            roots,
+        }
+    }
+
+    /// Canonicalizes a document argument (see [`normalize_document_uri`]) and checks
+    /// that the sandbox allows it. A notebook cell reference becomes the cell's URI,
+    /// checked by its notebook.
+    pub fn document_uri(&self, input: &str) -> Result<String> {
+        if let Some((notebook, index)) = notebook::split_cell_reference(input.trim()) {
+            return Ok(notebook::cell_uri(&self.document_uri(&notebook)?, index));
+        }
+        let uri = normalize_document_uri(&self.workspace, input)?;
+        if let Some(roots) = &self.roots {
+            let path = resolve(&uri_to_file_path(&uri)?);
+            if !roots
+                .iter()
+                .any(|root| strip_path_prefix(&path, root).is_some())
+            {
+                bail!(
+                    "{} is outside the workspace; add its directory to sandbox.allowedRoots to allow it",
+                    path.display()
+                );
+            }
+        }
+        Ok(uri)
+    }
+}
+
+/// `path` with symlinks resolved as far as it exists, so a document that does not
+/// exist yet cannot escape through a symlinked directory.
+fn resolve(path: &Path) -> PathBuf {
+    for existing in path.ancestors() {
+        if let Ok(resolved) = canonicalize(existing) {
+            let rest = path.strip_prefix(existing).unwrap_or(Path::new(""));
+            return resolved.join(rest);
+        }
+    }
+    path.to_path_buf()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn documents_must_be_under_an_allowed_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let workspace = root.join("project");
+        let vendor = root.join("vendor");
+        std::fs::create_dir_all(workspace.join("src")).unwrap();
+        std::fs::create_dir_all(&vendor).unwrap();
+        std::fs::write(workspace.join("src/main.rs"), "").unwrap();
+        std::fs::write(vendor.join("dep.rs"), "").unwrap();
+
+        let sandbox = Sandbox::new(&SandboxConfig::default(), &workspace);
+        assert!(sandbox.document_uri("src/main.rs").is_ok());
+        assert!(sandbox.document_uri("src/new.rs").is_ok());
+        let cell = sandbox.document_uri("src/nb.ipynb#cell2").unwrap();
+        assert!(cell.starts_with("vscode-notebook-cell:/"));
+        assert!(cell.ends_with("/project/src/nb.ipynb#cell2"));
+        assert_eq!(sandbox.document_uri(&cell).unwrap(), cell);
+        assert!(sandbox.document_uri("../vendor/nb.ipynb#cell0").is_err());
+        assert!(sandbox.document_uri("../vendor/dep.rs").is_err());
+        assert!(sandbox.document_uri("file:///etc/passwd").is_err());
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&vendor, workspace.join("linked")).unwrap();
+            assert!(sandbox.document_uri("linked/dep.rs").is_err());
+            assert!(sandbox.document_uri("linked/new.rs").is_err());
+        }
+
+        let config = SandboxConfig {
+            allowed_roots: vec![PathBuf::from("../vendor")],
+            ..SandboxConfig::default()
+        };
+        let sandbox = Sandbox::new(&config, &workspace);
+        assert!(sandbox.document_uri("../vendor/dep.rs").is_ok());
+
+        let config = SandboxConfig {
+            enabled: false,
+            ..SandboxConfig::default()
+        };
+        let sandbox = Sandbox::new(&config, &workspace);
+        assert!(sandbox.document_uri("file:///etc/passwd").is_ok());
+    }
+}
@@ -0,0 +1,150 @@
+//! Capture of the language server's stderr.
+//!
+//! Server stderr is read line by line, forwarded to `tracing` tagged with the server
+//! name, and kept in a bounded ring buffer so the `server_logs` tool can show what
+//! the server printed around a failed request. The buffer is shared across restarts,
+//! so the output of a crashed process is still available afterwards.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+
+/// Number of stderr lines retained.
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+/// One line of server stderr.
+#[derive(Debug, Clone, Serialize, schemars::JsonSchema)]
+pub struct LogLine {
+    /// Process that printed the line, to tell output from before a restart apart
+    pub pid: Option<u32>,
+    pub text: String,
+}
+
+#[derive(Debug)]
+pub struct ServerLog {
+    capacity: usize,
+    lines: Mutex<VecDeque<LogLine>>,
+}
+
+impl Default for ServerLog {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl ServerLog {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            capacity,
+            lines: Mutex::new(VecDeque::with_capacity(capacity.min(DEFAULT_CAPACITY))),
+        }
+    }
+
+    /// Appends a line, evicting the oldest once the buffer is full.
+    pub fn push(&self, line: LogLine) {
+        let mut lines = self.lines.lock().expect("server log lock poisoned");
+        if lines.len() == self.capacity {
+            lines.pop_front();
+        }
+        lines.push_back(line);
+    }
+
+    /// Returns up to `limit` of the most recent lines containing `filter`, oldest first.
+    pub fn tail(&self, limit: usize, filter: Option<&str>) -> Vec<LogLine> {
+        let lines = self.lines.lock().expect("server log lock poisoned");
+        let mut matching = lines
+            .iter()
+            .rev()
+            .filter(|line| filter.is_none_or(|filter| line.text.contains(filter)))
+            .take(limit)
+            .cloned()
+            .collect::<Vec<_>>();
+        matching.reverse();
+        matching
+    }
+
+    /// Number of lines currently buffered.
+    pub fn len(&self) -> usize {
+        self.lines.lock().expect("server log lock poisoned").len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Reads `stderr` until EOF, forwarding each line to `tracing` and `log`.
+///
+/// Invalid UTF-8 is replaced rather than ending the capture.
+pub fn spawn_capture<R>(stderr: R, server: String, pid: Option<u32>, log: Arc<ServerLog>)
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut reader = BufReader::new(stderr);
+        let mut buf = Vec::new();
+        loop {
+            buf.clear();
+            match reader.read_until(b'\n', &mut buf).await {
+                Ok(0) => break,
+                Ok(_) => {
+                    let text = String::from_utf8_lossy(&buf).trim_end().to_string();
+                    tracing::info!(%server, "{text}");
+                    log.push(LogLine { pid, text });
+                }
+                Err(err) => {
+                    tracing::debug!(?err, %server, "Failed to read language server stderr");
+                    break;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str) -> LogLine {
+        LogLine {
+            pid: Some(1),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn evicts_oldest_and_filters_tail() {
+        let log = ServerLog::with_capacity(3);
+        for text in ["a: one", "b: two", "a: three", "a: four"] {
+            log.push(line(text));
+        }
+        assert_eq!(log.len(), 3);
+
+        let texts = |lines: Vec<LogLine>| lines.into_iter().map(|l| l.text).collect::<Vec<_>>();
+        assert_eq!(texts(log.tail(2, None)), ["a: three", "a: four"]);
+        assert_eq!(texts(log.tail(10, Some("a:"))), ["a: three", "a: four"]);
+    }
+
+    #[tokio::test]
+    async fn captures_lines_from_a_stream() {
+        let log = Arc::new(ServerLog::default());
+        let (mut writer, reader) = tokio::io::duplex(64);
+        spawn_capture(reader, "mock".to_string(), None, log.clone());
+
+        tokio::io::AsyncWriteExt::write_all(&mut writer, b"first\nsecond \xff\n")
+            .await
+            .unwrap();
+        drop(writer);
+        for _ in 0..50 {
+            if log.len() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let lines = log.tail(10, None);
+        assert_eq!(lines[0].text, "first");
+        assert_eq!(lines[1].text, "second \u{fffd}");
+    }
+}
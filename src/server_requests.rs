@@ -0,0 +1,488 @@
+//! Handling of requests initiated by the LSP server.
+//!
+//! Language servers send requests to the client (e.g. `workspace/configuration`) and
+//! some stall until they get a reply. The bridge's reader task hands every such
+//! request to a [`ServerRequestHandler`], which produces the result or a JSON-RPC
+//! error to send back. Notifications that affect client-side state (such as
+//! `$/progress`) are observed by the handler as well.
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use serde_json::{Value, json};
+use tokio::sync::broadcast;
+
+use crate::client_log::LSP_LOG_TARGET;
+use crate::config::{ApplyEditPolicy, ServerConfig};
+use crate::edits::apply_workspace_edit;
+use crate::position::PositionEncoding;
+use crate::progress::ProgressTracker;
+use crate::watcher::WatchRegistry;
+
+/// Number of applied-edit batches buffered per subscriber.
+const APPLIED_EDITS_CAPACITY: usize = 64;
+
+/// JSON-RPC error code for requests the client does not implement.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// JSON-RPC error code for malformed request parameters.
+pub const INVALID_PARAMS: i64 = -32602;
+
+/// Error returned to the server in place of a result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl ResponseError {
+    pub fn to_json(&self) -> Value {
+        json!({ "code": self.code, "message": self.message })
+    }
+}
+
+/// A `workspace/applyEdit` request held back by [`ApplyEditPolicy::Queue`].
+#[derive(Debug, Clone)]
+pub struct QueuedEdit {
+    pub label: Option<String>,
+    pub edit: Value,
+}
+
+/// Answers server-initiated requests on behalf of the client.
+#[derive(Debug)]
+pub struct ServerRequestHandler {
+    settings: Value,
+    message_action: String,
+    apply_edits: ApplyEditPolicy,
+    progress: Arc<ProgressTracker>,
+    queued_edits: Mutex<Vec<QueuedEdit>>,
+    applied_edits: broadcast::Sender<Vec<String>>,
+    /// Set once `initialize` reveals the negotiated encoding.
+    position_encoding: OnceLock<PositionEncoding>,
+    watched_files: WatchRegistry,
+}
+
+impl Default for ServerRequestHandler {
+    fn default() -> Self {
+        Self::from_config(&ServerConfig::default())
+    }
+}
+
+impl ServerRequestHandler {
+    /// Creates a handler answering from the server's config: `settings` for
+    /// `workspace/configuration`, `messageAction` for `window/showMessageRequest`,
+    /// and `applyEdits` for `workspace/applyEdit`.
+    pub fn from_config(server: &ServerConfig) -> Self {
+        Self {
+            settings: server.settings.clone(),
+            message_action: server.message_action.clone(),
+            apply_edits: server.apply_edits,
+            progress: Arc::default(),
+            queued_edits: Mutex::default(),
+            applied_edits: broadcast::channel(APPLIED_EDITS_CAPACITY).0,
+            position_encoding: OnceLock::new(),
+            watched_files: WatchRegistry::default(),
+        }
+    }
+
+    /// Records the position encoding negotiated during `initialize`, used to
+    /// interpret server-requested edits.
+    pub fn set_position_encoding(&self, encoding: PositionEncoding) {
+        let _ = self.position_encoding.set(encoding);
+    }
+
+    pub fn settings(&self) -> &Value {
+        &self.settings
+    }
+
+    /// Work-done progress reported by the server.
+    pub fn progress(&self) -> &ProgressTracker {
+        &self.progress
+    }
+
+    /// File watchers registered through `client/registerCapability`.
+    pub fn watched_files(&self) -> &WatchRegistry {
+        &self.watched_files
+    }
+
+    /// Subscribes to the URIs touched by each edit applied on the server's behalf,
+    /// so open documents can be resynchronized.
+    pub fn subscribe_applied_edits(&self) -> broadcast::Receiver<Vec<String>> {
+        self.applied_edits.subscribe()
+    }
+
+    /// Whether `workspace/applyEdit` requests are applied or queued rather than
+    /// rejected outright.
+    pub fn applies_edits(&self) -> bool {
+        self.apply_edits != ApplyEditPolicy::Reject
+    }
+
+    /// Removes and returns edits held back under [`ApplyEditPolicy::Queue`].
+    pub fn take_queued_edits(&self) -> Vec<QueuedEdit> {
+        std::mem::take(
+            &mut *self
+                .queued_edits
+                .lock()
+                .expect("queued edits lock poisoned"),
+        )
+    }
+
+    pub async fn handle(&self, method: &str, params: Value) -> Result<Value, ResponseError> {
+        match method {
+            "workspace/configuration" => self.configuration(&params),
+            // Tokens are tracked once `$/progress` begins; creation just needs an ack.
+            "window/workDoneProgress/create" => Ok(Value::Null),
+            "window/showMessageRequest" => Ok(self.show_message_request(&params)),
+            "workspace/applyEdit" => Ok(self.apply_edit(params)),
+            "client/registerCapability" => self.register_capability(&params),
+            "client/unregisterCapability" => self.unregister_capability(&params),
+            _ => Err(ResponseError {
+                code: METHOD_NOT_FOUND,
+                message: format!("pathfinder does not handle '{method}'"),
+            }),
+        }
+    }
+
+    /// Updates client-side state from a server notification.
+    pub fn observe_notification(&self, method: &str, params: &Value) {
+        match method {
+            "$/progress" => self.progress.update(params),
+            "window/showMessage" | "window/logMessage" => log_message(params),
+            _ => {}
+        }
+    }
+
+    /// Handles `workspace/applyEdit` according to the configured policy.
+    fn apply_edit(&self, params: Value) -> Value {
+        let label = params
+            .get("label")
+            .and_then(|l| l.as_str())
+            .map(str::to_string);
+        let edit = params.get("edit").cloned().unwrap_or(Value::Null);
+
+        match self.apply_edits {
+            ApplyEditPolicy::Reject => {
+                tracing::info!(?label, "Rejected workspace edit from server");
+                json!({ "applied": false, "failureReason": "workspace edits are disabled" })
+            }
+            ApplyEditPolicy::Queue => {
+                tracing::info!(?label, "Queued workspace edit from server for review");
+                self.queued_edits
+                    .lock()
+                    .expect("queued edits lock poisoned")
+                    .push(QueuedEdit { label, edit });
+                json!({ "applied": false, "failureReason": "edit queued for review" })
+            }
+            ApplyEditPolicy::Apply => match apply_workspace_edit(
+                &edit,
+                self.position_encoding.get().copied().unwrap_or_default(),
+            ) {
+                Ok(touched) => {
+                    tracing::info!(?label, files = touched.len(), "Applied workspace edit");
+                    // Nobody listening just means no documents need resyncing.
+                    let _ = self.applied_edits.send(touched);
+                    json!({ "applied": true })
+                }
+                Err(err) => {
+                    tracing::warn!(?label, ?err, "Failed to apply workspace edit");
+                    json!({ "applied": false, "failureReason": format!("{err:#}") })
+                }
+            },
+        }
+    }
+
+    /// Picks the action to answer a `window/showMessageRequest` with.
+    ///
+    /// `messageAction` is `"dismiss"` (reply `null`), `"first"` (first offered
+    /// action), or the title of an action to choose whenever the server offers it.
+    fn show_message_request(&self, params: &Value) -> Value {
+        log_message(params);
+        let actions = params
+            .get("actions")
+            .and_then(|actions| actions.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let title = |action: &Value| {
+            action
+                .get("title")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let chosen = match self.message_action.as_str() {
+            "" | "dismiss" => None,
+            "first" => actions.first(),
+            wanted => actions
+                .iter()
+                .find(|action| title(action).eq_ignore_ascii_case(wanted)),
+        };
+        match chosen {
+            Some(action) => {
+                tracing::info!(action = %title(action), "Answered server message request");
+                json!({ "title": title(action) })
+            }
+            None => Value::Null,
+        }
+    }
+
+    /// Records `workspace/didChangeWatchedFiles` registrations.
+    ///
+    /// Other registrations are acknowledged without effect: pathfinder advertises
+    /// dynamic registration for file watching only, but refusing would make some
+    /// servers give up on initialization.
+    fn register_capability(&self, params: &Value) -> Result<Value, ResponseError> {
+        for registration in registration_list(params, "registrations")? {
+            let id = registration.get("id").and_then(|id| id.as_str());
+            let method = registration.get("method").and_then(|m| m.as_str());
+            match (id, method) {
+                (Some(id), Some("workspace/didChangeWatchedFiles")) => {
+                    let options = registration.get("registerOptions").unwrap_or(&Value::Null);
+                    self.watched_files.register(id, options);
+                }
+                _ => tracing::debug!(?method, "Ignoring capability registration"),
+            }
+        }
+        Ok(Value::Null)
+    }
+
+    fn unregister_capability(&self, params: &Value) -> Result<Value, ResponseError> {
+        // The LSP spec spells this field "unregisterations".
+        for registration in registration_list(params, "unregisterations")? {
+            if let Some(id) = registration.get("id").and_then(|id| id.as_str()) {
+                self.watched_files.unregister(id);
+            }
+        }
+        Ok(Value::Null)
+    }
+
+    /// Returns one settings value per requested item, `null` where nothing is configured.
+    fn configuration(&self, params: &Value) -> Result<Value, ResponseError> {
+        let items = params
+            .get("items")
+            .and_then(|items| items.as_array())
+            .ok_or_else(|| ResponseError {
+                code: INVALID_PARAMS,
+                message: "workspace/configuration params must contain an items array".into(),
+            })?;
+
+        let values = items
+            .iter()
+            .map(|item| {
+                let section = item.get("section").and_then(|s| s.as_str());
+                lookup_section(&self.settings, section)
+                    .cloned()
+                    .unwrap_or(Value::Null)
+            })
+            .collect();
+        Ok(Value::Array(values))
+    }
+}
+
+fn registration_list<'a>(params: &'a Value, key: &str) -> Result<&'a [Value], ResponseError> {
+    params
+        .get(key)
+        .and_then(|list| list.as_array())
+        .map(Vec::as_slice)
+        .ok_or_else(|| ResponseError {
+            code: INVALID_PARAMS,
+            message: format!("params must contain a {key} array"),
+        })
+}
+
+/// Logs a `window/showMessage` or `window/logMessage` payload at the matching tracing
+/// level.
+fn log_message(params: &Value) {
+    let message = params
+        .get("message")
+        .and_then(|m| m.as_str())
+        .unwrap_or_default();
+    match params.get("type").and_then(|t| t.as_u64()) {
+        Some(1) => tracing::error!(target: LSP_LOG_TARGET, "LSP: {message}"),
+        Some(2) => tracing::warn!(target: LSP_LOG_TARGET, "LSP: {message}"),
+        Some(3) => tracing::info!(target: LSP_LOG_TARGET, "LSP: {message}"),
+        _ => tracing::debug!(target: LSP_LOG_TARGET, "LSP: {message}"),
+    }
+}
+
+/// Resolves a dotted section name (e.g. `python.analysis`) within the settings tree.
+///
+/// A missing or empty section refers to the whole settings object.
+fn lookup_section<'a>(settings: &'a Value, section: Option<&str>) -> Option<&'a Value> {
+    match section {
+        None | Some("") => Some(settings),
+        Some(section) => section
+            .split('.')
+            .try_fold(settings, |value, key| value.get(key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler() -> ServerRequestHandler {
+        handler_with_action("dismiss")
+    }
+
+    fn handler_with_action(message_action: &str) -> ServerRequestHandler {
+        ServerRequestHandler::from_config(&ServerConfig {
+            settings: json!({
+                "python": {
+                    "analysis": { "typeCheckingMode": "strict" },
+                    "pythonPath": "/usr/bin/python3",
+                }
+            }),
+            message_action: message_action.to_string(),
+            ..ServerConfig::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn configuration_resolves_dotted_sections() {
+        let params = json!({
+            "items": [
+                { "section": "python.analysis" },
+                { "section": "python.pythonPath", "scopeUri": "file:///tmp" },
+                { "section": "gopls" },
+            ]
+        });
+        let result = handler()
+            .handle("workspace/configuration", params)
+            .await
+            .unwrap();
+        assert_eq!(
+            result,
+            json!([{ "typeCheckingMode": "strict" }, "/usr/bin/python3", null])
+        );
+    }
+
+    #[tokio::test]
+    async fn configuration_without_section_returns_everything() {
+        let result = handler()
+            .handle("workspace/configuration", json!({ "items": [{}] }))
+            .await
+            .unwrap();
+        assert_eq!(result[0], *handler().settings());
+    }
+
+    #[tokio::test]
+    async fn progress_creation_is_acknowledged_and_tracked() {
+        let handler = handler();
+        let result = handler
+            .handle("window/workDoneProgress/create", json!({"token": 1}))
+            .await
+            .unwrap();
+        assert_eq!(result, Value::Null);
+
+        handler.observe_notification(
+            "$/progress",
+            &json!({"token": 1, "value": {"kind": "begin", "title": "Indexing"}}),
+        );
+        assert!(handler.progress().is_busy());
+    }
+
+    #[tokio::test]
+    async fn message_requests_follow_the_configured_action() {
+        let params = json!({
+            "type": 2,
+            "message": "Reload workspace?",
+            "actions": [{"title": "Reload"}, {"title": "Ignore"}],
+        });
+        let answer = |action: &str| {
+            let handler = handler_with_action(action);
+            let params = params.clone();
+            async move {
+                handler
+                    .handle("window/showMessageRequest", params)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert_eq!(answer("dismiss").await, Value::Null);
+        assert_eq!(answer("first").await, json!({"title": "Reload"}));
+        assert_eq!(answer("ignore").await, json!({"title": "Ignore"}));
+        assert_eq!(answer("Restart").await, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn apply_edit_policies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("lib.rs");
+        std::fs::write(&path, "fn old() {}\n").unwrap();
+        let uri = url::Url::from_file_path(&path).unwrap().to_string();
+        let params = json!({
+            "label": "Rename",
+            "edit": {"changes": {uri.clone(): [{
+                "range": {"start": {"line": 0, "character": 3}, "end": {"line": 0, "character": 6}},
+                "newText": "new",
+            }]}},
+        });
+        let handler_with_policy = |apply_edits| {
+            ServerRequestHandler::from_config(&ServerConfig {
+                apply_edits,
+                ..ServerConfig::default()
+            })
+        };
+
+        let rejecting = handler_with_policy(ApplyEditPolicy::Reject);
+        let result = rejecting
+            .handle("workspace/applyEdit", params.clone())
+            .await
+            .unwrap();
+        assert_eq!(result["applied"], false);
+        assert!(!rejecting.applies_edits());
+
+        let queueing = handler_with_policy(ApplyEditPolicy::Queue);
+        queueing
+            .handle("workspace/applyEdit", params.clone())
+            .await
+            .unwrap();
+        let queued = queueing.take_queued_edits();
+        assert_eq!(queued.len(), 1);
+        assert_eq!(queued[0].label.as_deref(), Some("Rename"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fn old() {}\n");
+
+        let applying = handler_with_policy(ApplyEditPolicy::Apply);
+        let mut applied = applying.subscribe_applied_edits();
+        let result = applying
+            .handle("workspace/applyEdit", params)
+            .await
+            .unwrap();
+        assert_eq!(result, json!({"applied": true}));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "fn new() {}\n");
+        assert_eq!(applied.recv().await.unwrap(), vec![uri]);
+    }
+
+    #[tokio::test]
+    async fn watched_file_registrations_are_tracked() {
+        let handler = handler();
+        let register = json!({ "registrations": [
+            { "id": "w", "method": "workspace/didChangeWatchedFiles",
+              "registerOptions": { "watchers": [{ "globPattern": "**/*.go" }] } },
+            { "id": "f", "method": "textDocument/formatting" },
+        ]});
+        assert_eq!(
+            handler.handle("client/registerCapability", register).await,
+            Ok(Value::Null)
+        );
+        assert!(!handler.watched_files().is_empty());
+
+        let unregister = json!({ "unregisterations": [
+            { "id": "w", "method": "workspace/didChangeWatchedFiles" },
+        ]});
+        handler
+            .handle("client/unregisterCapability", unregister)
+            .await
+            .unwrap();
+        assert!(handler.watched_files().is_empty());
+    }
+
+    #[tokio::test]
+    async fn unknown_methods_are_declined() {
+        let err = handler()
+            .handle("custom/request", Value::Null)
+            .await
+            .unwrap_err();
+        assert_eq!(err.code, METHOD_NOT_FOUND);
+    }
+}
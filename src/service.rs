@@ -3,111 +3,2097 @@
 //! This module implements the Model Context Protocol (MCP) server that exposes
 //! LSP functionality as MCP tools. It manages the LSP bridge and document synchronization,
 //! and routes MCP tool calls to the LSP server.
+//!
+//! The bridge multiplexes requests itself, so tool calls share it without locking and
+//! run in parallel; only syncs of the same document are serialized. The bridge is owned
+//! by an [`LspSupervisor`], which replaces it if the server crashes.
+//!
+//! Each MCP session gets its own clone of the service with a fresh [`Session`]
+//! (pagination cursors and document claims), while the supervisor is shared.
+//!
+//! What the service takes from the config (the tools offered, sandbox, output
+//! defaults, middleware) is shared by all sessions too, and replaced as a whole by
+//! [`PathfinderService::reload`]; sessions whose tool list changes get
+//! `notifications/tools/list_changed`.
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::Result;
+use anyhow::{Result, anyhow, bail};
 use rmcp::{
-    ErrorData as McpError, RoleServer, ServerHandler,
-    handler::server::{router::tool::ToolRouter, wrapper::Parameters},
+    ErrorData as McpError, Peer, RoleServer, ServerHandler,
+    handler::server::{
+        router::tool::ToolRouter,
+        tool::{Extension, ToolCallContext, cached_schema_for_type},
+        wrapper::Parameters,
+    },
     model::*,
     service::RequestContext,
-    tool, tool_handler, tool_router,
+    tool, tool_router,
 };
-use tokio::sync::Mutex;
+use tokio::sync::{broadcast, watch};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
 
-use crate::config::Config;
-use crate::documents::DocumentManager;
+use crate::arguments::Documents;
+use crate::client_log::{ClientLog, LogRecord};
+use crate::companions::{self, Companions};
+use crate::config::{
+    ApplyEditPolicy, Config, DiagnosticsConfig, OutputConfig, ResultFormat, ToolsConfig,
+};
+use crate::diagnostics;
+use crate::documents::DocumentMissing;
+use crate::edits;
+use crate::format::{FormatRequest, Formatted, OutputFormat, Positions};
 use crate::lsp_bridge::LspBridge;
-use crate::tools::definition::{DefinitionRequest, DefinitionTool};
+use crate::metrics::{MetricsReport, Outcome};
+use crate::middleware::{Middleware, MiddlewareChain};
+use crate::pagination::{PageRequest, Paged};
+use crate::plugins::{PathfinderTool, ToolContext, ToolRegistry};
+use crate::position::PositionConverter;
+use crate::progress::{ProgressEntry, ToolProgress};
+use crate::prompts::{self, PromptRequest};
+use crate::resources::{self, ResourceRequest};
+use crate::sandbox::Sandbox;
+use crate::session::Session;
+use crate::summary;
+use crate::supervisor::LspSupervisor;
+use crate::tags;
+use crate::tools::capabilities::{CapabilitiesReport, CapabilitiesTool};
+use crate::tools::check::{CheckEditRequest, CheckEditResponse, CheckTool};
+use crate::tools::definition::{DefinitionRequest, DefinitionResponse, DefinitionTool};
+use crate::tools::dependencies::{
+    DependenciesTool, DependencyGraphRequest, DependencyGraphResponse,
+};
+use crate::tools::documents::{
+    DocumentResponse, DocumentsTool, OpenDocumentRequest, UpdateDocumentRequest,
+};
+use crate::tools::files::{FilesTool, ListFilesRequest, ListFilesResponse};
+use crate::tools::logs::{LogsTool, ServerLogsRequest, ServerLogsResponse};
+use crate::tools::metrics::MetricsTool;
+use crate::tools::outline::{OutlineRequest, OutlineResponse, OutlineTool};
+use crate::tools::read::{ReadFileRequest, ReadFileResponse, ReadTool};
+use crate::tools::refactor::{
+    CodeActionRequest, CodeActionResponse, EditPreview, PlannedEdit, RefactorTool, RenameRequest,
+};
+use crate::tools::references::{ReferencesRequest, ReferencesResponse, ReferencesTool};
+use crate::tools::request::{LspRequestRequest, LspRequestResponse, LspRequestTool};
+use crate::tools::restart::{RestartResponse, RestartTool};
+use crate::tools::search::{SearchTextRequest, SearchTextResponse, SearchTool};
+use crate::tools::servers::{ListServersResponse, ServersTool};
+use crate::tools::status::{ServerStatus, StatusTool};
+use crate::tools::symbols::{FindSymbolRequest, FindSymbolResponse, SymbolsTool};
+use crate::tools::unused::{UnusedSymbolsRequest, UnusedSymbolsResponse, UnusedTool};
+use crate::tools::vendor::{
+    DocumentRequest, ExpandMacroResponse, PositionRequest, SourceDefinitionResponse,
+    SwitchSourceHeaderResponse, Vendor, VendorTool, ViewHirResponse,
+};
+use crate::truncate;
 
 #[derive(Clone)]
 pub struct PathfinderService {
-    lsp: Arc<Mutex<LspBridge>>,
-    documents: Arc<Mutex<DocumentManager>>,
+    supervisor: Arc<LspSupervisor>,
+    /// Servers asked alongside `supervisor`'s by `definition` and `references`.
+    companions: Arc<Companions>,
+    client_log: ClientLog,
+    session: Arc<Session>,
+    /// The current [`Configured`], shared by all sessions and replaced on reload.
+    configured: Arc<watch::Sender<Arc<Configured>>>,
+    /// Tools and middleware from the builder methods, kept across reloads.
+    added: Added,
+}
+
+/// What the service takes from the config, rebuilt when it is reloaded.
+#[derive(Clone)]
+struct Configured {
+    output: OutputConfig,
+    sandbox: Arc<Sandbox>,
+    /// Tool filter and response size limits.
+    tools: ToolsConfig,
+    /// `reject` refuses `apply: true` in the editing tools.
+    apply_edits: ApplyEditPolicy,
+    /// Whether and how often diagnostics are pushed to clients.
+    diagnostics: DiagnosticsConfig,
     tool_router: ToolRouter<PathfinderService>,
+    /// Tools from the config's `custom` list and [`PathfinderService::with_tool`].
+    plugins: ToolRegistry,
+    /// Hooks around every tool call: the config's `middleware` list, then those added
+    /// with [`PathfinderService::with_middleware`].
+    middleware: MiddlewareChain,
+}
+
+/// The bridge a call naming documents runs on, with its documents synced, handed to
+/// the tool through the request context (see [`PathfinderService::prepare`]).
+#[derive(Clone)]
+struct Prepared {
+    lsp: Arc<LspBridge>,
+    /// Overlay texts at the time of the sync; empty for tools that do not sync
+    overlays: HashMap<String, String>,
+}
+
+/// Tools and middleware added with [`PathfinderService::with_tool`] and
+/// [`PathfinderService::with_middleware`].
+#[derive(Clone, Default)]
+struct Added {
+    tools: Vec<Arc<dyn PathfinderTool>>,
+    middleware: Vec<Arc<dyn Middleware>>,
+}
+
+impl Configured {
+    fn build(config: &Config, workspace: &Path, added: &Added) -> Result<Self> {
+        let mut tool_router = PathfinderService::tool_router();
+        let vendor = Vendor::detect(&config.server.command);
+        let mut plugins = ToolRegistry::from_config(&config.tools.custom)?;
+        filter_tools(&mut tool_router, &mut plugins, &config.tools, vendor)?;
+        if !config.server.index.enabled {
+            // Nothing would ever be indexed
+            tool_router.remove_route("find_symbol");
+        }
+        let mut middleware = MiddlewareChain::from_config(&config.tools.middleware, workspace)?;
+        for layer in &added.middleware {
+            middleware.push(layer.clone());
+        }
+        let mut configured = Self {
+            output: config.output,
+            sandbox: Arc::new(Sandbox::new(&config.sandbox, workspace)),
+            tools: config.tools.clone(),
+            apply_edits: config.server.apply_edits,
+            diagnostics: config.diagnostics,
+            tool_router,
+            plugins,
+            middleware,
+        };
+        for tool in &added.tools {
+            configured.add_tool(tool.clone())?;
+        }
+        Ok(configured)
+    }
+
+    /// Offers `tool` alongside the built-in tools, unless the `tools` config turns it
+    /// off. Fails if a tool with its name already exists.
+    fn add_tool(&mut self, tool: Arc<dyn PathfinderTool>) -> Result<()> {
+        let name = tool.name().to_string();
+        if self
+            .tool_router
+            .list_all()
+            .iter()
+            .any(|builtin| builtin.name == name)
+        {
+            bail!("tool '{name}' is already built in");
+        }
+        if !self.tools.is_enabled(&name) {
+            tracing::info!(tool = %name, "Tool disabled by configuration");
+            return Ok(());
+        }
+        self.plugins.register(tool)
+    }
+
+    /// The tools offered in `tools/list`.
+    fn tool_list(&self) -> Vec<Tool> {
+        let mut tools = self.tool_router.list_all();
+        tools.extend(self.plugins.definitions());
+        tools
+    }
 }
 
 #[tool_router]
 impl PathfinderService {
-    pub async fn new(config: Config, workspace_base: PathBuf) -> Result<Self> {
-        // Initialize the LSP bridge
+    pub async fn new(mut config: Config, workspace_base: PathBuf) -> Result<Self> {
         let workspace = config.server.resolve_root_dir(&workspace_base)?;
-        let command = &config.server.command[0];
-        let args: Vec<String> = config.server.command[1..].to_vec();
-
-        let mut lsp = LspBridge::new_with_command(command, args, workspace).await?;
-        lsp.initialize().await?;
-
-        let documents = DocumentManager::new();
+        config.restrict_to_read_only(&workspace)?;
+        let configured = Configured::build(&config, &workspace, &Added::default())?;
+        // Start the LSP server under supervision so crashes are recovered from
+        let supervisor = LspSupervisor::start(config.server, workspace).await?;
+        let companions = match Companions::start(&config.companions, supervisor.workspace()).await {
+            Ok(companions) => companions,
+            Err(err) => {
+                supervisor.shutdown().await;
+                return Err(err);
+            }
+        };
 
         Ok(Self {
-            lsp: Arc::new(Mutex::new(lsp)),
-            documents: Arc::new(Mutex::new(documents)),
-            tool_router: Self::tool_router(),
+            session: Arc::new(Session::new(&supervisor)),
+            supervisor,
+            companions: Arc::new(companions),
+            client_log: ClientLog::default(),
+            configured: Arc::new(watch::Sender::new(Arc::new(configured))),
+            added: Added::default(),
         })
     }
 
+    /// Applies a reloaded `config` to this service and every session sharing its
+    /// language server.
+    ///
+    /// The tools, sandbox, output defaults, and middleware are rebuilt, and the server
+    /// and companions are reconfigured (see [`LspSupervisor::reconfigure`] and
+    /// [`Companions::reconfigure`]); settings only read at startup keep their current
+    /// values, with a warning. If the new config is
+    /// rejected, the current one stays in effect.
+    pub async fn reload(&self, mut config: Config) -> Result<()> {
+        let workspace = self.supervisor.workspace();
+        config.restrict_to_read_only(workspace)?;
+        for setting in config
+            .server
+            .keep_startup_settings(&self.supervisor.config())
+        {
+            tracing::warn!(
+                setting,
+                "Config change takes effect when pathfinder restarts"
+            );
+        }
+        let configured = Configured::build(&config, workspace, &self.added)?;
+        self.companions
+            .reconfigure(&config.companions, workspace)
+            .await?;
+        self.configured.send_replace(Arc::new(configured));
+        self.supervisor.reconfigure(config.server).await?;
+        tracing::info!("Reloaded config");
+        Ok(())
+    }
+
+    /// The config-derived parts of the service in effect.
+    fn configured(&self) -> Arc<Configured> {
+        self.configured.borrow().clone()
+    }
+
+    /// Tool and LSP request metrics since startup, across all sessions.
+    pub fn metrics(&self) -> MetricsReport {
+        MetricsTool::new().execute(&self.supervisor)
+    }
+
+    /// A service for another MCP session, sharing this one's language server but
+    /// with its own cursors and documents.
+    pub fn new_session(&self) -> Self {
+        Self {
+            session: Arc::new(Session::new(&self.supervisor)),
+            ..self.clone()
+        }
+    }
+
+    /// Offers `tool` alongside the built-in tools, unless the `tools` config turns it
+    /// off. Fails if a tool with its name already exists.
+    pub fn with_tool(mut self, tool: impl PathfinderTool + 'static) -> Result<Self> {
+        let tool: Arc<dyn PathfinderTool> = Arc::new(tool);
+        let mut configured = Configured::clone(&self.configured());
+        configured.add_tool(tool.clone())?;
+        self.configured.send_replace(Arc::new(configured));
+        self.added.tools.push(tool);
+        Ok(self)
+    }
+
+    /// Runs `middleware` around every tool call, inside the middleware added before it.
+    pub fn with_middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        let middleware: Arc<dyn Middleware> = Arc::new(middleware);
+        let mut configured = Configured::clone(&self.configured());
+        configured.middleware.push(middleware.clone());
+        self.configured.send_replace(Arc::new(configured));
+        self.added.middleware.push(middleware);
+        self
+    }
+
+    /// Forwards log events from `client_log` (the installed tracing layer) to the
+    /// MCP client once it connects.
+    pub fn with_client_log(mut self, client_log: ClientLog) -> Self {
+        self.client_log = client_log;
+        self
+    }
+
+    /// Closes open documents and shuts the language server and its companions down.
+    pub async fn shutdown(&self) {
+        self.supervisor.shutdown().await;
+        self.companions.shutdown().await;
+    }
+
+    /// Canonicalizes the document arguments of a call in place and checks them against
+    /// the sandbox (see [`Sandbox::document_uri`]), then, for calls the main server
+    /// answers, waits for a ready bridge and syncs the documents on it. On failure,
+    /// returns the tool result to send instead.
+    async fn prepare(
+        &self,
+        tool: &str,
+        arguments: &mut Option<JsonObject>,
+        cancellation: &CancellationToken,
+    ) -> Result<Option<Prepared>, CallToolResult> {
+        let (Some(documents), Some(arguments)) = (Documents::of(tool), arguments.as_mut()) else {
+            return Ok(None);
+        };
+        let configured = self.configured();
+        for uri in documents.documents_mut(arguments) {
+            *uri = configured.sandbox.document_uri(uri).map_err(|err| {
+                CallToolResult::error(vec![Content::text(format!("{tool} failed: {err:#}"))])
+            })?;
+        }
+        // A companion named by `server` or a page of earlier results answers
+        // without the main server, and so does definition's fallback
+        let argument = |name: &str| arguments.get(name).and_then(serde_json::Value::as_str);
+        let label = self.supervisor.config().label();
+        let companion = argument("server").is_some_and(|name| name != label);
+        let resumed = argument("cursor").is_some();
+        let fallback = tool == "definition"
+            && configured.tools.fallback
+            && argument("uri").is_some_and(|uri| !self.supervisor.serves(uri));
+        if companion || resumed || fallback {
+            return Ok(None);
+        }
+
+        let ready = tokio::select! {
+            ready = self.supervisor.ready_bridge() => ready,
+            _ = cancellation.cancelled() => {
+                return Err(CallToolResult::error(vec![Content::text(format!(
+                    "{tool} cancelled by client"
+                ))]));
+            }
+        };
+        let lsp = ready.map_err(|err| {
+            CallToolResult::error(vec![Content::text(format!("{tool} failed: {err}"))])
+        })?;
+        if !documents.sync {
+            return Ok(Some(Prepared {
+                lsp,
+                overlays: HashMap::new(),
+            }));
+        }
+
+        let open = self.supervisor.documents();
+        for uri in documents.documents_mut(arguments) {
+            let Err(err) = open.ensure_open_for(&lsp, uri, self.session.id()).await else {
+                continue;
+            };
+            // A vanished file is the caller's problem, not the server's
+            if err.is::<DocumentMissing>() {
+                return Err(CallToolResult::error(vec![Content::text(format!(
+                    "{tool} failed: {err}"
+                ))]));
+            }
+            tracing::warn!(?err, "Failed to sync document before {tool} call");
+            self.supervisor
+                .health()
+                .record_error(format!("{tool}: failed to prepare document: {err:#}"));
+            return Err(CallToolResult::error(vec![Content::text(format!(
+                "failed to prepare document: {err}"
+            ))]));
+        }
+        Ok(Some(Prepared {
+            lsp,
+            overlays: open.overlay_texts().await,
+        }))
+    }
+
+    /// The companion a request's `server` argument names, or None for the main server
+    /// (named or by default). On an unknown name, returns the tool result to send.
+    fn named_companion(
+        &self,
+        tool: &str,
+        server: Option<&str>,
+    ) -> Result<Option<Arc<LspSupervisor>>, CallToolResult> {
+        let Some(name) = server else {
+            return Ok(None);
+        };
+        if name == self.supervisor.config().label() {
+            return Ok(None);
+        }
+        match self.companions.get(name) {
+            Some(companion) => Ok(Some(companion)),
+            None => Err(CallToolResult::error(vec![Content::text(format!(
+                "{tool} failed: no server named '{name}'; list_servers shows the configured ones"
+            ))])),
+        }
+    }
+
     /// Return LSP-backed jump-to-definition targets for a given URI and position
     #[tool(
-        description = "Return LSP-backed jump-to-definition targets for a given URI and position"
+        description = "Return LSP-backed jump-to-definition targets for a given URI and position",
+        output_schema = output_schema::<DefinitionResponse>(),
+        annotations(title = "Go to Definition", read_only_hint = true, open_world_hint = false)
     )]
     async fn definition(
         &self,
-        Parameters(request): Parameters<DefinitionRequest>,
+        Parameters(Paged {
+            request: Formatted {
+                mut request,
+                format,
+            },
+            page,
+        }): Parameters<Paged<Formatted<DefinitionRequest>>>,
+        cancellation: CancellationToken,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+        extensions: Extensions,
     ) -> Result<CallToolResult, McpError> {
-        // Ensure document is open
-        {
-            let mut documents = self.documents.lock().await;
-            let mut lsp = self.lsp.lock().await;
-            if let Err(err) = documents.ensure_open(&mut lsp, &request.uri).await {
-                tracing::warn!(?err, "Failed to sync document before definition call");
+        let format = match self.output_format("definition", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let key = request.cache_key();
+        match self.session.definition_pages().resume(&key, &page) {
+            Ok(Some(page)) => {
+                return self.formatted_result("definition", format, DefinitionResponse::from(page));
+            }
+            Ok(None) => {}
+            Err(err) => {
                 return Ok(CallToolResult::error(vec![Content::text(format!(
-                    "failed to prepare document: {err}"
+                    "definition failed: {err}"
                 ))]));
             }
         }
+        let companion = match self.named_companion("definition", request.server.as_deref()) {
+            Ok(companion) => companion,
+            Err(result) => return Ok(result),
+        };
+        if let Some(companion) = companion {
+            let result = tokio::select! {
+                result = companions::definition_from(&companion, request) => result,
+                _ = cancellation.cancelled() => {
+                    return Ok(CallToolResult::error(vec![Content::text(
+                        "definition cancelled by client",
+                    )]));
+                }
+            };
+            return match result {
+                Ok(response) => {
+                    let page = self
+                        .session
+                        .definition_pages()
+                        .first(key, response.targets, &page);
+                    let response = DefinitionResponse {
+                        snapped: response.snapped,
+                        truncated: response.truncated,
+                        ..DefinitionResponse::from(page)
+                    };
+                    self.formatted_result("definition", format, response)
+                }
+                Err(err) => {
+                    companion
+                        .health()
+                        .record_error(format!("definition: {err:#}"));
+                    Ok(CallToolResult::error(vec![Content::text(format!(
+                        "definition failed: {err}"
+                    ))]))
+                }
+            };
+        }
+        let fallback = self.configured().tools.fallback;
+        if fallback && !self.supervisor.serves(&request.uri) {
+            return self.fallback_definition(format, request, key, &page).await;
+        }
+        let Some(Prepared { lsp, overlays }) = extensions.get::<Prepared>().cloned() else {
+            return Err(McpError::internal_error(
+                "definition: documents were not synced",
+                None,
+            ));
+        };
+        if fallback && !lsp.server_capabilities().supports("definitionProvider") {
+            return self.fallback_definition(format, request, key, &page).await;
+        }
+        let retry = fallback.then(|| request.clone());
+        let merge = (request.server.is_none() && self.companions.serves(&request.uri))
+            .then(|| request.options.clone());
 
-        // Execute definition tool
-        let tool = DefinitionTool::new();
-        let mut lsp = self.lsp.lock().await;
-        match tool.execute(&mut lsp, request).await {
+        // Execute definition tool, asking the companions at the same time
+        // Dropping the in-flight LSP request on cancellation sends $/cancelRequest
+        let forwarder = ProgressForwarder::start(&meta, peer, &lsp);
+        let tool = DefinitionTool::new().with_progress(forwarder.progress());
+        let queries = async {
+            tokio::join!(
+                self.companions.definitions(&request),
+                tool.execute(&lsp, request.clone(), overlays)
+            )
+        };
+        let result = tokio::select! {
+            result = queries => Some(result),
+            _ = cancellation.cancelled() => None,
+        };
+        drop(tool);
+        forwarder.finish().await;
+        let Some((found, mut result)) = result else {
+            tracing::debug!("definition call cancelled by client");
+            return Ok(CallToolResult::error(vec![Content::text(
+                "definition cancelled by client",
+            )]));
+        };
+        if let Ok(response) = &mut result
+            && let Some(options) = merge
+        {
+            response.truncated |= companions::merge(
+                &self.supervisor.config(),
+                &mut response.targets,
+                found,
+                &options,
+                lsp.workspace(),
+            );
+        }
+        if let Ok(response) = &result
+            && response.targets.is_empty()
+            && let Some(request) = retry
+        {
+            return self.fallback_definition(format, request, key, &page).await;
+        }
+        match result {
             Ok(response) => {
-                let json_value = serde_json::to_value(response).map_err(|e| {
-                    McpError::internal_error(format!("serialization failed: {e}"), None)
-                })?;
-                let content = Content::json(json_value).map_err(|e| {
-                    McpError::internal_error(format!("content creation failed: {e}"), None)
-                })?;
-                Ok(CallToolResult::success(vec![content]))
+                let page = self
+                    .session
+                    .definition_pages()
+                    .first(key, response.targets, &page);
+                let response = DefinitionResponse {
+                    snapped: response.snapped,
+                    truncated: response.truncated,
+                    ..DefinitionResponse::from(page)
+                };
+                self.formatted_result("definition", format, response)
+            }
+            Err(err) => {
+                self.supervisor
+                    .health()
+                    .record_error(format!("definition: {err:#}"));
+                Ok(CallToolResult::error(vec![Content::text(format!(
+                    "definition failed: {err}"
+                ))]))
+            }
+        }
+    }
+
+    /// Runs a registered tool; its errors become error results.
+    async fn call_plugin(
+        &self,
+        plugin: Arc<dyn PathfinderTool>,
+        arguments: Option<JsonObject>,
+    ) -> CallToolResult {
+        let configured = self.configured();
+        let context = ToolContext::new(&self.supervisor, &configured.sandbox, self.session.id());
+        match plugin.call(context, arguments.unwrap_or_default()).await {
+            Ok(result) => CallToolResult::structured(result),
+            Err(err) => CallToolResult::error(vec![Content::text(format!(
+                "{} failed: {err:#}",
+                plugin.name()
+            ))]),
+        }
+    }
+
+    /// Answers `definition` from the declarations [`tags`] finds, for documents the
+    /// server is not configured for or positions it has no answer at.
+    async fn fallback_definition(
+        &self,
+        format: OutputFormat,
+        request: DefinitionRequest,
+        key: String,
+        page: &PageRequest,
+    ) -> Result<CallToolResult, McpError> {
+        let workspace = self.supervisor.workspace();
+        let found = tags::definitions(
+            workspace,
+            self.supervisor.bridge().column_encoding(),
+            &request.uri,
+            request.line,
+            request.character,
+            self.supervisor.documents().overlay_texts().await,
+        )
+        .await;
+        let mut targets = match found {
+            Ok(targets) => targets,
+            Err(err) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "definition failed: {err}"
+                ))]));
+            }
+        };
+        let truncated = request.options.apply(&mut targets, workspace);
+        let page = self.session.definition_pages().first(key, targets, page);
+        let response = DefinitionResponse {
+            truncated,
+            fallback: true,
+            ..DefinitionResponse::from(page)
+        };
+        self.formatted_result("definition", format, response)
+    }
+
+    /// Return LSP-backed references to the symbol at a given URI and position
+    #[tool(
+        description = "Return LSP-backed references to the symbol at a given URI and position; large result sets are paginated with cursor/limit",
+        output_schema = output_schema::<ReferencesResponse>(),
+        annotations(title = "Find References", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn references(
+        &self,
+        Parameters(Paged {
+            request: Formatted {
+                mut request,
+                format,
+            },
+            page,
+        }): Parameters<Paged<Formatted<ReferencesRequest>>>,
+        cancellation: CancellationToken,
+        extensions: Extensions,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("references", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let key = request.cache_key();
+        match self.session.reference_pages().resume(&key, &page) {
+            Ok(Some(page)) => {
+                return self.formatted_result("references", format, ReferencesResponse::from(page));
+            }
+            Ok(None) => {}
+            Err(err) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "references failed: {err}"
+                ))]));
+            }
+        }
+        let companion = match self.named_companion("references", request.server.as_deref()) {
+            Ok(companion) => companion,
+            Err(result) => return Ok(result),
+        };
+        if let Some(companion) = companion {
+            let result = tokio::select! {
+                result = companions::references_from(&companion, request) => result,
+                _ = cancellation.cancelled() => {
+                    return Ok(CallToolResult::error(vec![Content::text(
+                        "references cancelled by client",
+                    )]));
+                }
+            };
+            return match result {
+                Ok(found) => {
+                    let page = self
+                        .session
+                        .reference_pages()
+                        .first(key, found.references, &page);
+                    let response = ReferencesResponse {
+                        snapped: found.snapped,
+                        truncated: found.truncated,
+                        ..ReferencesResponse::from(page)
+                    };
+                    self.formatted_result("references", format, response)
+                }
+                Err(err) => {
+                    companion
+                        .health()
+                        .record_error(format!("references: {err:#}"));
+                    Ok(CallToolResult::error(vec![Content::text(format!(
+                        "references failed: {err}"
+                    ))]))
+                }
+            };
+        }
+        let Some(Prepared { lsp, overlays }) = extensions.get::<Prepared>().cloned() else {
+            return Err(McpError::internal_error(
+                "references: documents were not synced",
+                None,
+            ));
+        };
+
+        let merge = (request.server.is_none() && self.companions.serves(&request.uri))
+            .then(|| request.options.clone());
+
+        // Ask the companions at the same time
+        // Dropping the in-flight LSP request on cancellation sends $/cancelRequest
+        let tool = ReferencesTool::new();
+        let queries = async {
+            tokio::join!(
+                self.companions.references(&request),
+                tool.execute(&lsp, request.clone(), overlays)
+            )
+        };
+        let (found, mut result) = tokio::select! {
+            result = queries => result,
+            _ = cancellation.cancelled() => {
+                tracing::debug!("references call cancelled by client");
+                return Ok(CallToolResult::error(vec![Content::text(
+                    "references cancelled by client",
+                )]));
+            }
+        };
+        if let Ok(references) = &mut result
+            && let Some(options) = merge
+        {
+            references.truncated |= companions::merge(
+                &self.supervisor.config(),
+                &mut references.references,
+                found,
+                &options,
+                lsp.workspace(),
+            );
+        }
+        match result {
+            Ok(found) => {
+                let page = self
+                    .session
+                    .reference_pages()
+                    .first(key, found.references, &page);
+                let response = ReferencesResponse {
+                    snapped: found.snapped,
+                    truncated: found.truncated,
+                    ..ReferencesResponse::from(page)
+                };
+                self.formatted_result("references", format, response)
+            }
+            Err(err) => {
+                self.supervisor
+                    .health()
+                    .record_error(format!("references: {err:#}"));
+                Ok(CallToolResult::error(vec![Content::text(format!(
+                    "references failed: {err}"
+                ))]))
+            }
+        }
+    }
+
+    /// Open a document with unsaved content
+    #[tool(
+        description = "Open a document on the language server with in-memory content instead of the file on disk; later tool calls see this content",
+        output_schema = output_schema::<DocumentResponse>(),
+        annotations(
+            title = "Open Document",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = true,
+            open_world_hint = false,
+        )
+    )]
+    async fn open_document(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<OpenDocumentRequest>>,
+        Extension(Prepared { lsp, .. }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("open_document", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let documents = self.supervisor.documents();
+        let result = DocumentsTool::new()
+            .open(&lsp, documents, self.session.id(), request)
+            .await;
+        self.document_result("open_document", format, result)
+    }
+
+    /// Replace or edit the content of an overlay document
+    #[tool(
+        description = "Update an in-memory document opened with open_document, with either its full new text or a list of range edits",
+        output_schema = output_schema::<DocumentResponse>(),
+        annotations(
+            title = "Update Document",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = false,
+            open_world_hint = false,
+        )
+    )]
+    async fn update_document(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<UpdateDocumentRequest>>,
+        Extension(Prepared { lsp, .. }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("update_document", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let documents = self.supervisor.documents();
+        let result = DocumentsTool::new()
+            .update(&lsp, documents, self.session.id(), request)
+            .await;
+        self.document_result("update_document", format, result)
+    }
+
+    /// Check proposed content against the server's diagnostics
+    #[tool(
+        description = "Check proposed content for a document before writing it: the server analyzes it in memory, and the diagnostics it reports are returned along with those introduced and resolved compared with the current content. The document is left as it was",
+        output_schema = output_schema::<CheckEditResponse>(),
+        annotations(title = "Check Edit", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn check_edit(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<CheckEditRequest>>,
+        Extension(Prepared { lsp, .. }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("check_edit", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let timeout = self
+            .supervisor
+            .config()
+            .timeouts
+            .for_tool("check_edit", "textDocument/publishDiagnostics");
+        let result = CheckTool::new()
+            .execute(
+                &lsp,
+                self.supervisor.documents(),
+                self.supervisor.diagnostics(),
+                self.session.id(),
+                request,
+                timeout,
+            )
+            .await;
+        match result {
+            Ok(response) => self.formatted_result("check_edit", format, response),
+            Err(err) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "check_edit failed: {err:#}"
+            ))])),
+        }
+    }
+
+    /// List files in the workspace
+    #[tool(
+        description = "List workspace files (relative paths), honoring .gitignore, optionally filtered by directory, glob, and extension",
+        output_schema = output_schema::<ListFilesResponse>(),
+        annotations(title = "List Workspace Files", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn list_workspace_files(
+        &self,
+        Parameters(request): Parameters<ListFilesRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        match FilesTool::new()
+            .execute(self.supervisor.workspace(), request)
+            .await
+        {
+            Ok(response) => json_result(response),
+            Err(err) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "list_workspace_files failed: {err:#}"
+            ))])),
+        }
+    }
+
+    /// Read lines of a workspace file
+    #[tool(
+        description = "Read a range of lines (zero-based, inclusive) from a workspace file, with optional context lines; returns the text and the file's language id",
+        output_schema = output_schema::<ReadFileResponse>(),
+        annotations(title = "Read File Range", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn read_file_range(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<ReadFileRequest>>,
+        Extension(Prepared { lsp, .. }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("read_file_range", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let result = ReadTool::new()
+            .execute(&lsp, self.supervisor.documents(), request)
+            .await;
+        match result {
+            Ok(response) => self.formatted_result("read_file_range", format, response),
+            Err(err) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "read_file_range failed: {err:#}"
+            ))])),
+        }
+    }
+
+    /// Search workspace files for text
+    #[tool(
+        description = "Search workspace files for a regular expression or literal text, honoring .gitignore; returns path, line, column, and matched text. Works for any language and for strings and comments",
+        output_schema = output_schema::<SearchTextResponse>(),
+        annotations(title = "Search Text", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn search_text(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<SearchTextRequest>>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("search_text", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let encoding = self.supervisor.bridge().column_encoding();
+        match SearchTool::new()
+            .execute(self.supervisor.workspace(), encoding, request)
+            .await
+        {
+            Ok(response) => self.formatted_result("search_text", format, response),
+            Err(err) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "search_text failed: {err:#}"
+            ))])),
+        }
+    }
+
+    /// Fuzzy-search pathfinder's own index of workspace declarations
+    #[tool(
+        description = "Fuzzy-search workspace declarations (functions, types, constants, modules) by name or abbreviation in pathfinder's own index, without asking the language server; returns name, kind, uri, and range, best matches first",
+        output_schema = output_schema::<FindSymbolResponse>(),
+        annotations(title = "Find Symbol", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn find_symbol(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<FindSymbolRequest>>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("find_symbol", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        match SymbolsTool::new().execute(self.supervisor.symbol_index(), request) {
+            Ok(response) => self.formatted_result("find_symbol", format, response),
+            Err(err) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "find_symbol failed: {err:#}"
+            ))])),
+        }
+    }
+
+    /// Outline the symbols of a directory's files
+    #[tool(
+        description = "Outline a directory: the symbols (textDocument/documentSymbol) of each of its source files, honoring .gitignore, as a compact tree of name, kind, and line per file",
+        output_schema = output_schema::<OutlineResponse>(),
+        annotations(title = "Outline Directory", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn outline(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<OutlineRequest>>,
+        cancellation: CancellationToken,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("outline", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let lsp = match self.supervisor.ready_bridge().await {
+            Ok(lsp) => lsp,
+            Err(err) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "outline failed: {err}"
+                ))]));
+            }
+        };
+        let extensions = self.supervisor.config().extensions.clone();
+        let forwarder = ProgressForwarder::start(&meta, peer, &lsp);
+        let tool = OutlineTool::new().with_progress(forwarder.progress());
+        let result = tokio::select! {
+            result = tool.execute(&lsp, self.supervisor.documents(), &extensions, request) => Some(result),
+            _ = cancellation.cancelled() => None,
+        };
+        drop(tool);
+        forwarder.finish().await;
+        match result {
+            Some(Ok(response)) => self.formatted_result("outline", format, response),
+            Some(Err(err)) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "outline failed: {err:#}"
+            ))])),
+            None => Ok(CallToolResult::error(vec![Content::text(
+                "outline cancelled by client",
+            )])),
+        }
+    }
+
+    /// List symbols nothing outside their declaration refers to
+    #[tool(
+        description = "Find dead-code candidates: the symbols (textDocument/documentSymbol) of a file or directory that have no references outside their own declaration. Entry points, trait implementations, and symbols used only from outside the workspace show up too, so review each before removing it",
+        output_schema = output_schema::<UnusedSymbolsResponse>(),
+        annotations(title = "Find Unused Symbols", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn find_unused_symbols(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<UnusedSymbolsRequest>>,
+        cancellation: CancellationToken,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("find_unused_symbols", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let lsp = match self.supervisor.ready_bridge().await {
+            Ok(lsp) => lsp,
+            Err(err) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "find_unused_symbols failed: {err}"
+                ))]));
+            }
+        };
+        let extensions = self.supervisor.config().extensions.clone();
+        let forwarder = ProgressForwarder::start(&meta, peer, &lsp);
+        let tool = UnusedTool::new().with_progress(forwarder.progress());
+        let result = tokio::select! {
+            result = tool.execute(&lsp, self.supervisor.documents(), &extensions, request) => Some(result),
+            _ = cancellation.cancelled() => None,
+        };
+        drop(tool);
+        forwarder.finish().await;
+        match result {
+            Some(Ok(response)) => self.formatted_result("find_unused_symbols", format, response),
+            Some(Err(err)) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "find_unused_symbols failed: {err:#}"
+            ))])),
+            None => Ok(CallToolResult::error(vec![Content::text(
+                "find_unused_symbols cancelled by client",
+            )])),
+        }
+    }
+
+    /// Build the file-level import graph of a directory or list of files
+    #[tool(
+        description = "Build the file-level import graph of a directory or list of files: the import statements of each file are found per language and resolved by the language server (textDocument/documentLink or textDocument/definition), giving the workspace files each one imports and is imported by, to judge the impact of a change",
+        output_schema = output_schema::<DependencyGraphResponse>(),
+        annotations(title = "Dependency Graph", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn dependency_graph(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<DependencyGraphRequest>>,
+        cancellation: CancellationToken,
+        meta: Meta,
+        peer: Peer<RoleServer>,
+        Extension(Prepared { lsp, .. }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("dependency_graph", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let extensions = self.supervisor.config().extensions.clone();
+        let forwarder = ProgressForwarder::start(&meta, peer, &lsp);
+        let tool = DependenciesTool::new().with_progress(forwarder.progress());
+        let result = tokio::select! {
+            result = tool.execute(&lsp, self.supervisor.documents(), &extensions, request) => Some(result),
+            _ = cancellation.cancelled() => None,
+        };
+        drop(tool);
+        forwarder.finish().await;
+        match result {
+            Some(Ok(response)) => self.formatted_result("dependency_graph", format, response),
+            Some(Err(err)) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "dependency_graph failed: {err:#}"
+            ))])),
+            None => Ok(CallToolResult::error(vec![Content::text(
+                "dependency_graph cancelled by client",
+            )])),
+        }
+    }
+
+    /// Rename a symbol, previewing the changes as a diff
+    #[tool(
+        description = "Rename the symbol at a URI and position across the workspace (textDocument/rename). Returns the files it changes and a unified diff; files are only written with apply: true",
+        output_schema = output_schema::<EditPreview>(),
+        annotations(
+            title = "Rename Symbol",
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = false,
+            open_world_hint = false,
+        )
+    )]
+    async fn rename(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<RenameRequest>>,
+        cancellation: CancellationToken,
+        Extension(Prepared { lsp, overlays }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("rename", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let workspace = self.supervisor.workspace();
+        let tool = RefactorTool::new();
+        let planned = tokio::select! {
+            planned = tool.rename(&lsp, &request, overlays, workspace) => planned,
+            _ = cancellation.cancelled() => return Ok(cancelled_result("rename")),
+        };
+        let result = match planned {
+            Ok(planned) if request.apply == Some(true) => self.apply_planned(&lsp, planned).await,
+            Ok(planned) => Ok(planned.preview),
+            Err(err) => Err(err),
+        };
+        self.vendor_result("rename", format, result)
+    }
+
+    /// List code actions for a range, and preview or apply one
+    #[tool(
+        description = "List the code actions (quick fixes, refactorings, source actions) for a range of a document (textDocument/codeAction). Pass action to preview one as a unified diff; files are only written with apply: true",
+        output_schema = output_schema::<CodeActionResponse>(),
+        annotations(
+            title = "Code Actions",
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = false,
+            open_world_hint = false,
+        )
+    )]
+    async fn code_action(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<CodeActionRequest>>,
+        cancellation: CancellationToken,
+        Extension(Prepared { lsp, overlays }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("code_action", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let workspace = self.supervisor.workspace();
+        let tool = RefactorTool::new();
+        let found = tokio::select! {
+            found = tool.code_actions(&lsp, &request, overlays, workspace) => found,
+            _ = cancellation.cancelled() => return Ok(cancelled_result("code_action")),
+        };
+        let result = match found {
+            Ok((mut response, Some(planned))) if request.apply == Some(true) => {
+                match self.apply_planned(&lsp, planned).await {
+                    Ok(preview) => {
+                        response.selected = Some(preview);
+                        Ok(response)
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+            Ok((_, None)) if request.apply == Some(true) => Err(anyhow!(
+                "choose the action to apply with action (its index in the list)"
+            )),
+            Ok((response, _)) => Ok(response),
+            Err(err) => Err(err),
+        };
+        self.vendor_result("code_action", format, result)
+    }
+
+    /// Report language server health and state
+    #[tool(
+        description = "Report language server health: process id, uptime, indexing progress, open documents, restarts, and the last error",
+        output_schema = output_schema::<ServerStatus>(),
+        annotations(title = "Server Status", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn server_status(&self) -> Result<CallToolResult, McpError> {
+        let status = StatusTool::new().execute(&self.supervisor).await;
+        json_result(status)
+    }
+
+    /// List the configured language servers
+    #[tool(
+        description = "List the configured language servers by the name the `server` argument of definition and references takes, with the extensions each handles",
+        output_schema = output_schema::<ListServersResponse>(),
+        annotations(title = "List Servers", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn list_servers(&self) -> Result<CallToolResult, McpError> {
+        json_result(ServersTool::new().execute(&self.supervisor, &self.companions))
+    }
+
+    /// Report what the language server supports
+    #[tool(
+        description = "Report the language server's capabilities from its initialize result: server name and version, position encoding, document sync, the providers it enables, and which of the offered tools it supports",
+        output_schema = output_schema::<CapabilitiesReport>(),
+        annotations(title = "Server Capabilities", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn server_capabilities(&self) -> Result<CallToolResult, McpError> {
+        let lsp = match self.supervisor.ready_bridge().await {
+            Ok(lsp) => lsp,
+            Err(err) => {
+                return Ok(CallToolResult::error(vec![Content::text(format!(
+                    "server_capabilities failed: {err}"
+                ))]));
+            }
+        };
+        let offered: Vec<String> = self
+            .configured()
+            .tool_list()
+            .into_iter()
+            .map(|tool| tool.name.to_string())
+            .collect();
+        json_result(CapabilitiesTool::new().execute(&lsp, &offered))
+    }
+
+    /// Report tool and LSP request metrics
+    #[tool(
+        description = "Report tool call and LSP request counts, errors, timeouts, and latency histograms, plus retries and server restarts",
+        output_schema = output_schema::<MetricsReport>(),
+        annotations(title = "Get Metrics", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn get_metrics(&self) -> Result<CallToolResult, McpError> {
+        json_result(self.metrics())
+    }
+
+    /// Restart the language server
+    #[tool(
+        description = "Shut down the language server and start a fresh one, reopening open documents; use when the server is stuck or returning stale results",
+        output_schema = output_schema::<RestartResponse>(),
+        annotations(
+            title = "Restart Server",
+            read_only_hint = false,
+            destructive_hint = false,
+            idempotent_hint = false,
+            open_world_hint = false,
+        )
+    )]
+    async fn restart_server(&self) -> Result<CallToolResult, McpError> {
+        match RestartTool::new().execute(&self.supervisor).await {
+            Ok(response) => json_result(response),
+            Err(err) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "restart_server failed: {err:#}"
+            ))])),
+        }
+    }
+
+    /// Return recent language server stderr output
+    #[tool(
+        description = "Return recent stderr output of the language server, optionally filtered by substring",
+        output_schema = output_schema::<ServerLogsResponse>(),
+        annotations(title = "Server Logs", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn server_logs(
+        &self,
+        Parameters(request): Parameters<ServerLogsRequest>,
+    ) -> Result<CallToolResult, McpError> {
+        let response = LogsTool::new().execute(self.supervisor.stderr_log(), request);
+        json_result(response)
+    }
+
+    /// Expand the macro call at a position (rust-analyzer)
+    #[tool(
+        description = "Show the recursive expansion of the macro call at a URI and position (rust-analyzer/expandMacro)",
+        output_schema = output_schema::<ExpandMacroResponse>(),
+        annotations(title = "Expand Macro", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn expand_macro(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<PositionRequest>>,
+        cancellation: CancellationToken,
+        Extension(Prepared { lsp, overlays }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("expand_macro", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let tool = VendorTool::new();
+        let result = tokio::select! {
+            result = tool.expand_macro(&lsp, request, overlays) => result,
+            _ = cancellation.cancelled() => return Ok(cancelled_result("expand_macro")),
+        };
+        self.vendor_result("expand_macro", format, result)
+    }
+
+    /// Show rust-analyzer's HIR for the function at a position
+    #[tool(
+        description = "Show rust-analyzer's high-level IR (HIR) of the function at a URI and position (rust-analyzer/viewHir)",
+        output_schema = output_schema::<ViewHirResponse>(),
+        annotations(title = "View HIR", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn view_hir(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<PositionRequest>>,
+        cancellation: CancellationToken,
+        Extension(Prepared { lsp, overlays }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("view_hir", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let tool = VendorTool::new();
+        let result = tokio::select! {
+            result = tool.view_hir(&lsp, request, overlays) => result,
+            _ = cancellation.cancelled() => return Ok(cancelled_result("view_hir")),
+        };
+        self.vendor_result("view_hir", format, result)
+    }
+
+    /// Find the header for a source file or the other way round (clangd)
+    #[tool(
+        description = "Return the header for a C/C++ source file, or the source file for a header (clangd textDocument/switchSourceHeader)",
+        output_schema = output_schema::<SwitchSourceHeaderResponse>(),
+        annotations(title = "Switch Source/Header", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn switch_source_header(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<DocumentRequest>>,
+        cancellation: CancellationToken,
+        Extension(Prepared { lsp, .. }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("switch_source_header", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let tool = VendorTool::new();
+        let result = tokio::select! {
+            result = tool.switch_source_header(&lsp, request) => result,
+            _ = cancellation.cancelled() => return Ok(cancelled_result("switch_source_header")),
+        };
+        self.vendor_result("switch_source_header", format, result)
+    }
+
+    /// Jump to the implementation behind a type declaration (typescript-language-server)
+    #[tool(
+        description = "Jump to the JavaScript/TypeScript source implementing a symbol instead of its .d.ts declaration (typescript-language-server _typescript.goToSourceDefinition)",
+        output_schema = output_schema::<SourceDefinitionResponse>(),
+        annotations(title = "Go to Source Definition", read_only_hint = true, open_world_hint = false)
+    )]
+    async fn go_to_source_definition(
+        &self,
+        Parameters(Formatted {
+            mut request,
+            format,
+        }): Parameters<Formatted<PositionRequest>>,
+        cancellation: CancellationToken,
+        Extension(Prepared { lsp, overlays }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        let format = match self.output_format("go_to_source_definition", &mut request, &format) {
+            Ok(format) => format,
+            Err(result) => return Ok(result),
+        };
+        let tool = VendorTool::new();
+        let result = tokio::select! {
+            result = tool.go_to_source_definition(&lsp, request, overlays) => result,
+            _ = cancellation.cancelled() => {
+                return Ok(cancelled_result("go_to_source_definition"));
+            }
+        };
+        self.vendor_result("go_to_source_definition", format, result)
+    }
+
+    /// Forward a raw request to the language server
+    #[tool(
+        description = "Send any LSP request (e.g. a server-specific extension like rust-analyzer/expandMacro) with raw params and return the server's raw result; pass uri to sync that document first. Positions are not converted",
+        output_schema = output_schema::<LspRequestResponse>(),
+        annotations(
+            title = "Raw LSP Request",
+            read_only_hint = false,
+            destructive_hint = true,
+            idempotent_hint = false,
+            open_world_hint = false,
+        )
+    )]
+    async fn lsp_request(
+        &self,
+        Parameters(request): Parameters<LspRequestRequest>,
+        cancellation: CancellationToken,
+        Extension(Prepared { lsp, .. }): Extension<Prepared>,
+    ) -> Result<CallToolResult, McpError> {
+        // Dropping the in-flight LSP request on cancellation sends $/cancelRequest
+        let tool = LspRequestTool::new();
+        let result = tokio::select! {
+            result = tool.execute(&lsp, request) => result,
+            _ = cancellation.cancelled() => {
+                return Ok(CallToolResult::error(vec![Content::text(
+                    "lsp_request cancelled by client",
+                )]));
             }
+        };
+        match result {
+            Ok(response) => json_result(response),
             Err(err) => Ok(CallToolResult::error(vec![Content::text(format!(
-                "definition failed: {err}"
+                "lsp_request failed: {err:#}"
             ))])),
         }
     }
 }
 
-#[tool_handler]
+impl PathfinderService {
+    /// The output format a call asked for, with its position inputs converted to
+    /// zero-based. On failure, returns the tool result to send instead.
+    fn output_format(
+        &self,
+        tool: &str,
+        request: &mut impl Positions,
+        format: &FormatRequest,
+    ) -> Result<OutputFormat, CallToolResult> {
+        let format = OutputFormat::resolve(&self.configured().output, format);
+        format.to_zero_based(request).map_err(|err| {
+            CallToolResult::error(vec![Content::text(format!("{tool} failed: {err:#}"))])
+        })?;
+        Ok(format)
+    }
+
+    /// Like [`json_result`], with paths and positions rewritten as `format` asks. In
+    /// text format, `content` holds a summary of the result instead of its JSON.
+    fn formatted_result(
+        &self,
+        tool: &str,
+        format: OutputFormat,
+        response: impl serde::Serialize,
+    ) -> Result<CallToolResult, McpError> {
+        let mut json_value = serde_json::to_value(response)
+            .map_err(|e| McpError::internal_error(format!("serialization failed: {e}"), None))?;
+        let workspace = self.supervisor.workspace();
+        format.apply(workspace, &mut json_value);
+        let summary = match format.format {
+            ResultFormat::Text => summary::render(tool, workspace, &json_value),
+            ResultFormat::Json => None,
+        };
+        let result = CallToolResult::structured(json_value);
+        Ok(match summary {
+            Some(summary) => CallToolResult {
+                content: vec![Content::text(summary)],
+                ..result
+            },
+            None => result,
+        })
+    }
+
+    /// Cuts the structured result of `tool` down to its response limit, redoing the
+    /// text content from what is left and noting how much was dropped.
+    fn limit_response(&self, tool: &str, result: &mut CallToolResult) {
+        let limit = self.configured().tools.response_limit(tool);
+        let Some(structured) = &result.structured_content else {
+            return;
+        };
+        if limit == 0 || truncate::serialized_len(structured) <= limit {
+            return;
+        }
+        let mirrored = mirrors_structured(result);
+        let Some(structured) = &mut result.structured_content else {
+            return;
+        };
+        let dropped = truncate::truncate_json(structured, limit);
+        if dropped == 0 {
+            return;
+        }
+        let text = self.redone_text(tool, structured, mirrored);
+        tracing::info!(tool, limit, dropped, "Truncated oversized tool result");
+        result.content = vec![
+            Content::text(text),
+            Content::text(format!(
+                "Result truncated to {limit} bytes: {dropped} list items omitted (raise tools.maxResponseBytes or narrow the query for the rest)"
+            )),
+        ];
+    }
+
+    /// Passes the result of `tool` back through the middleware, redoing the text
+    /// content if they changed the structured result.
+    fn finish_response(&self, tool: &str, result: &mut CallToolResult) {
+        let configured = self.configured();
+        if configured.middleware.is_empty() {
+            return;
+        }
+        let mirrored = mirrors_structured(result);
+        let original = result.structured_content.clone();
+        configured.middleware.response(tool, result);
+        if let Some(structured) = &result.structured_content
+            && result.structured_content != original
+        {
+            result.content = vec![Content::text(self.redone_text(tool, structured, mirrored))];
+        }
+    }
+
+    /// Text content for the changed structured result of `tool`: its JSON if the text
+    /// content mirrored the JSON before, else a new summary.
+    fn redone_text(&self, tool: &str, structured: &serde_json::Value, mirrored: bool) -> String {
+        let summary = if mirrored {
+            None
+        } else {
+            summary::render(tool, self.supervisor.workspace(), structured)
+        };
+        summary.unwrap_or_else(|| structured.to_string())
+    }
+
+    fn document_result(
+        &self,
+        tool: &str,
+        format: OutputFormat,
+        result: Result<DocumentResponse>,
+    ) -> Result<CallToolResult, McpError> {
+        match result {
+            Ok(response) => self.formatted_result(tool, format, response),
+            Err(err) => Ok(CallToolResult::error(vec![Content::text(format!(
+                "{tool} failed: {err:#}"
+            ))])),
+        }
+    }
+
+    /// Writes a previewed edit and resyncs the documents it changed, then runs its
+    /// command, if any. Refused when `applyEdits` is `reject` (as in read-only mode),
+    /// when a file is outside the sandbox, and when a file has unsaved overlay text,
+    /// which writing the disk would silently replace.
+    async fn apply_planned(&self, lsp: &LspBridge, planned: PlannedEdit) -> Result<EditPreview> {
+        let configured = self.configured();
+        if configured.apply_edits == ApplyEditPolicy::Reject {
+            bail!("edits are disabled (applyEdits = \"reject\"); drop apply to preview them");
+        }
+        let documents = self.supervisor.documents();
+        let overlays = documents.overlay_texts().await;
+        let mut uris = Vec::new();
+        for change in &planned.changes.files {
+            let uri = configured
+                .sandbox
+                .document_uri(&change.path.to_string_lossy())?;
+            if overlays.contains_key(&uri) {
+                bail!("{uri} has unsaved changes from update_document; close it first");
+            }
+            uris.push(uri);
+        }
+        edits::write_changes(&planned.changes)?;
+        tracing::info!(files = uris.len(), "Applied workspace edit from tool");
+        for uri in &uris {
+            if let Err(err) = documents.resync(lsp, uri).await {
+                tracing::warn!(?err, %uri, "Failed to resync edited document");
+            }
+        }
+        if let Some(command) = planned.command {
+            let method = "workspace/executeCommand";
+            let params = serde_json::json!({
+                "command": command.get("command").cloned().unwrap_or_default(),
+                "arguments": command.get("arguments").cloned().unwrap_or_else(|| serde_json::json!([])),
+            });
+            lsp.request_with_timeout(method, params, lsp.tool_timeout("code_action", method))
+                .await
+                .map_err(|err| anyhow!("{method} failed after writing the edit: {err:#}"))?;
+        }
+        Ok(EditPreview {
+            applied: true,
+            ..planned.preview
+        })
+    }
+
+    /// The result of a vendor tool call; failures are recorded as the last error.
+    fn vendor_result(
+        &self,
+        tool: &str,
+        format: OutputFormat,
+        result: Result<impl serde::Serialize>,
+    ) -> Result<CallToolResult, McpError> {
+        match result {
+            Ok(response) => self.formatted_result(tool, format, response),
+            Err(err) => {
+                self.supervisor
+                    .health()
+                    .record_error(format!("{tool}: {err:#}"));
+                Ok(CallToolResult::error(vec![Content::text(format!(
+                    "{tool} failed: {err:#}"
+                ))]))
+            }
+        }
+    }
+}
+
+fn cancelled_result(tool: &str) -> CallToolResult {
+    tracing::debug!("{tool} call cancelled by client");
+    CallToolResult::error(vec![Content::text(format!("{tool} cancelled by client"))])
+}
+
+/// Whether the text content of `result` is its structured result as JSON, rather than
+/// a summary.
+fn mirrors_structured(result: &CallToolResult) -> bool {
+    let Some(structured) = &result.structured_content else {
+        return false;
+    };
+    result
+        .content
+        .first()
+        .and_then(|content| content.as_text())
+        .is_some_and(|text| {
+            serde_json::from_str::<serde_json::Value>(&text.text)
+                .is_ok_and(|value| value == *structured)
+        })
+}
+
+/// Removes the tools `tools` turns off, and vendor tools for servers other than
+/// `vendor` that `tools.enabled` does not ask for, so calls to them fail and
+/// `tools/list` leaves them out. Custom tools in `plugins` may not shadow built-in
+/// ones.
+fn filter_tools(
+    router: &mut ToolRouter<PathfinderService>,
+    plugins: &mut ToolRegistry,
+    tools: &ToolsConfig,
+    vendor: Option<Vendor>,
+) -> Result<()> {
+    let mut available: Vec<String> = router
+        .list_all()
+        .into_iter()
+        .map(|tool| tool.name.into_owned())
+        .collect();
+    if let Some(clash) = plugins
+        .names()
+        .find(|name| available.iter().any(|tool| tool == name))
+    {
+        bail!("custom tool '{clash}' has the name of a built-in tool");
+    }
+    plugins.retain(|name| {
+        let enabled = tools.is_enabled(name);
+        if !enabled {
+            tracing::info!(tool = name, "Tool disabled by configuration");
+        }
+        enabled
+    });
+    available.extend(plugins.names().map(str::to_string));
+    available.sort();
+    if let Some(unknown) = tools
+        .named_tools()
+        .find(|name| !available.iter().any(|tool| tool == name))
+    {
+        bail!(
+            "unknown tool '{unknown}' in tools config (available: {})",
+            available.join(", ")
+        );
+    }
+    for tool in &available {
+        if !tools.is_enabled(tool) {
+            tracing::info!(tool, "Tool disabled by configuration");
+            router.remove_route(tool);
+            continue;
+        }
+        let requested = tools.enabled.iter().flatten().any(|name| name == tool);
+        if let Some(needed) = Vendor::of_tool(tool)
+            && Some(needed) != vendor
+            && !requested
+        {
+            tracing::debug!(tool, "Tool not offered for this language server");
+            router.remove_route(tool);
+        }
+    }
+    Ok(())
+}
+
+/// Sends log records to the client until the session closes.
+///
+/// Nothing here may log through `tracing` at a forwarded level, or each send would
+/// produce another record.
+fn spawn_log_forwarding(mut records: broadcast::Receiver<LogRecord>, peer: Peer<RoleServer>) {
+    tokio::spawn(async move {
+        loop {
+            let record = match records.recv().await {
+                Ok(record) => record,
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let message = LoggingMessageNotificationParam {
+                level: record.level,
+                logger: Some(record.logger),
+                data: record.data,
+            };
+            if peer.notify_logging_message(message).await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Sends the client the diagnostics of documents whose diagnostics changed, while
+/// `diagnostics.push` is on, until the session closes.
+fn spawn_diagnostics_push(
+    supervisor: Arc<LspSupervisor>,
+    configured: watch::Receiver<Arc<Configured>>,
+    peer: Peer<RoleServer>,
+) {
+    let mut updates = supervisor.diagnostics().subscribe();
+    tokio::spawn(async move {
+        loop {
+            let debounce = configured.borrow().diagnostics.debounce();
+            let Some(uris) = diagnostics::next_batch(&mut updates, debounce).await else {
+                break;
+            };
+            let current = configured.borrow().clone();
+            if !current.diagnostics.push {
+                continue;
+            }
+            let lsp = supervisor.bridge();
+            let mut converter =
+                PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+                    .with_texts(supervisor.documents().overlay_texts().await);
+            let format = OutputFormat::resolve(&current.output, &FormatRequest::default());
+            for uri in uris {
+                let published = supervisor.diagnostics().get(&uri);
+                let mut data = diagnostics::update(&mut converter, &uri, &published).await;
+                format.apply(supervisor.workspace(), &mut data);
+                let message = LoggingMessageNotificationParam {
+                    level: if diagnostics::has_errors(&published) {
+                        LoggingLevel::Error
+                    } else {
+                        LoggingLevel::Info
+                    },
+                    logger: Some(diagnostics::DIAGNOSTICS_LOGGER.to_string()),
+                    data,
+                };
+                if peer.notify_logging_message(message).await.is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
+
+/// Tells the client to list the tools again whenever a reload changes them, until
+/// the session closes.
+fn spawn_tool_list_notifications(
+    mut configured: watch::Receiver<Arc<Configured>>,
+    peer: Peer<RoleServer>,
+) {
+    tokio::spawn(async move {
+        let mut listed = configured.borrow_and_update().tool_list();
+        while configured.changed().await.is_ok() {
+            let tools = configured.borrow_and_update().tool_list();
+            if tools == listed {
+                continue;
+            }
+            listed = tools;
+            if peer.notify_tool_list_changed().await.is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Sends MCP progress notifications for one tool call, if the client passed a
+/// progress token.
+///
+/// Forwards both the tool's own [`ToolProgress`] messages and the server's work-done
+/// progress (e.g. indexing) while the call runs.
+struct ProgressForwarder {
+    progress: ToolProgress,
+    task: Option<JoinHandle<()>>,
+}
+
+impl ProgressForwarder {
+    fn start(meta: &Meta, peer: Peer<RoleServer>, lsp: &LspBridge) -> Self {
+        let Some(token) = meta.get_progress_token() else {
+            return Self {
+                progress: ToolProgress::default(),
+                task: None,
+            };
+        };
+        let (progress, mut messages) = ToolProgress::channel();
+        let mut server_work = lsp.progress().subscribe();
+        if !server_work.borrow().is_empty() {
+            server_work.mark_changed();
+        }
+        let task = tokio::spawn(async move {
+            let mut step = 0.0;
+            loop {
+                let message = tokio::select! {
+                    message = messages.recv() => match message {
+                        Some(message) => message,
+                        None => break,
+                    },
+                    changed = server_work.changed() => {
+                        if changed.is_err() {
+                            break;
+                        }
+                        let active = server_work.borrow_and_update();
+                        if active.is_empty() {
+                            "Language server finished its work".to_string()
+                        } else {
+                            let work: Vec<String> =
+                                active.values().map(ProgressEntry::describe).collect();
+                            format!("Language server: {}", work.join("; "))
+                        }
+                    }
+                };
+                step += 1.0;
+                let notification = ProgressNotificationParam {
+                    progress_token: token.clone(),
+                    progress: step,
+                    total: None,
+                    message: Some(message),
+                };
+                if let Err(err) = peer.notify_progress(notification).await {
+                    tracing::debug!(?err, "Failed to send progress notification");
+                    break;
+                }
+            }
+        });
+        Self {
+            progress,
+            task: Some(task),
+        }
+    }
+
+    fn progress(&self) -> ToolProgress {
+        self.progress.clone()
+    }
+
+    /// Flushes pending messages so none arrive after the tool's result. Every other
+    /// clone of the reporter must already be dropped.
+    async fn finish(self) {
+        drop(self.progress);
+        if let Some(task) = self.task {
+            let _ = task.await;
+        }
+    }
+}
+
+/// Schema of a tool's structured result, advertised as its `outputSchema`.
+fn output_schema<T: schemars::JsonSchema + 'static>() -> Arc<JsonObject> {
+    cached_schema_for_type::<T>()
+}
+
+/// Returns `response` as structured content, which tools with an `outputSchema` must
+/// provide, mirrored as JSON text for clients that only read `content`.
+fn json_result(response: impl serde::Serialize) -> Result<CallToolResult, McpError> {
+    let json_value = serde_json::to_value(response)
+        .map_err(|e| McpError::internal_error(format!("serialization failed: {e}"), None))?;
+    Ok(CallToolResult::structured(json_value))
+}
+
 impl ServerHandler for PathfinderService {
     fn get_info(&self) -> ServerInfo {
         ServerInfo {
             protocol_version: ProtocolVersion::V_2024_11_05,
             capabilities: ServerCapabilities::builder()
                 .enable_tools()
+                .enable_tool_list_changed()
+                .enable_logging()
+                .enable_prompts()
+                .enable_resources()
                 .build(),
             server_info: Implementation::from_build_env(),
             instructions: Some("MCP server that bridges to Language Server Protocol (LSP) servers. Provides jump-to-definition and other LSP features.".to_string()),
         }
     }
 
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult {
+            tools: self.configured().tool_list(),
+            next_cursor: None,
+        })
+    }
+
+    /// Routes the call to its tool inside a `tool_call` span, recording its latency
+    /// and outcome.
+    async fn call_tool(
+        &self,
+        mut request: CallToolRequestParam,
+        mut context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        let tool = request.name.clone();
+        let span = tracing::info_span!("tool_call", tool = %tool, mcp.request_id = %context.id);
+        let started = std::time::Instant::now();
+        let configured = self.configured();
+        let refused = configured
+            .middleware
+            .request(&tool, &mut request.arguments)
+            .err();
+        // The document the call is about, for the warning on files that are not UTF-8
+        let uri = request
+            .arguments
+            .as_ref()
+            .and_then(|arguments| arguments.get("uri"))
+            .and_then(serde_json::Value::as_str)
+            .and_then(|uri| configured.sandbox.document_uri(uri).ok());
+        let call = async {
+            if let Some(refused) = refused {
+                return Ok(refused);
+            }
+            match configured.plugins.get(&request.name).cloned() {
+                Some(plugin) => Ok(self.call_plugin(plugin, request.arguments).await),
+                None => {
+                    match self
+                        .prepare(&tool, &mut request.arguments, &context.ct)
+                        .await
+                    {
+                        Ok(Some(prepared)) => {
+                            context.extensions.insert(prepared);
+                        }
+                        Ok(None) => {}
+                        Err(result) => return Ok(result),
+                    }
+                    configured
+                        .tool_router
+                        .call(ToolCallContext::new(self, request, context))
+                        .await
+                }
+            }
+        };
+        let mut result = call.instrument(span).await;
+        if let Ok(result) = &mut result {
+            self.finish_response(&tool, result);
+            self.limit_response(&tool, result);
+            if let Some(uri) = uri
+                && let Some(note) = self.supervisor.documents().decoding_note(&uri).await
+            {
+                result.content.push(Content::text(note));
+            }
+        }
+        let outcome = match &result {
+            Ok(result) if result.is_error != Some(true) => Outcome::Ok,
+            _ => Outcome::Error,
+        };
+        self.supervisor
+            .metrics()
+            .record_tool(&tool, started.elapsed(), outcome);
+        result
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        Ok(ListPromptsResult {
+            prompts: prompts::list(),
+            next_cursor: None,
+        })
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        let mut prompt = PromptRequest::parse(&request.name, request.arguments.as_ref())
+            .map_err(|err| McpError::invalid_params(format!("{err:#}"), None))?;
+        let uri = prompt.uri_mut();
+        *uri = self
+            .configured()
+            .sandbox
+            .document_uri(uri)
+            .map_err(|err| McpError::invalid_params(format!("{err:#}"), None))?;
+        let lsp = self
+            .supervisor
+            .ready_bridge()
+            .await
+            .map_err(|err| McpError::internal_error(format!("{err:#}"), None))?;
+        let documents = self.supervisor.documents();
+        documents
+            .ensure_open_for(&lsp, prompt.uri(), self.session.id())
+            .await
+            .map_err(|err| McpError::internal_error(format!("{err:#}"), None))?;
+        let overlays = documents.overlay_texts().await;
+        prompts::render(&lsp, prompt, overlays)
+            .await
+            .map_err(|err| McpError::internal_error(format!("{err:#}"), None))
+    }
+
+    async fn list_resource_templates(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourceTemplatesResult, McpError> {
+        Ok(ListResourceTemplatesResult {
+            resource_templates: resources::templates(),
+            next_cursor: None,
+        })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        let resource = ResourceRequest::parse(&request.uri)
+            .map_err(|err| McpError::resource_not_found(format!("{err:#}"), None))?;
+        let lsp = self
+            .supervisor
+            .ready_bridge()
+            .await
+            .map_err(|err| McpError::internal_error(format!("{err:#}"), None))?;
+        let result = match resource {
+            ResourceRequest::Symbol { query } => {
+                resources::symbols(&lsp, &query, self.configured().tools.fallback).await
+            }
+            ResourceRequest::Definition(mut definition) => {
+                definition.uri = self
+                    .configured()
+                    .sandbox
+                    .document_uri(&definition.uri)
+                    .map_err(|err| McpError::resource_not_found(format!("{err:#}"), None))?;
+                let documents = self.supervisor.documents();
+                match documents
+                    .ensure_open_for(&lsp, &definition.uri, self.session.id())
+                    .await
+                {
+                    Ok(()) => {
+                        let overlays = documents.overlay_texts().await;
+                        resources::definition(&lsp, definition, overlays).await
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+        };
+        let value = result.map_err(|err| McpError::internal_error(format!("{err:#}"), None))?;
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::TextResourceContents {
+                uri: request.uri,
+                mime_type: Some("application/json".to_string()),
+                text: value.to_string(),
+                meta: None,
+            }],
+        })
+    }
+
+    async fn set_level(
+        &self,
+        request: SetLevelRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<(), McpError> {
+        tracing::debug!(level = ?request.level, "Client set the log level");
+        self.client_log.set_level(request.level);
+        Ok(())
+    }
+
     async fn initialize(
         &self,
         _request: InitializeRequestParam,
-        _context: RequestContext<RoleServer>,
+        context: RequestContext<RoleServer>,
     ) -> Result<InitializeResult, McpError> {
+        spawn_tool_list_notifications(self.configured.subscribe(), context.peer.clone());
+        spawn_diagnostics_push(
+            self.supervisor.clone(),
+            self.configured.subscribe(),
+            context.peer.clone(),
+        );
+        spawn_log_forwarding(self.client_log.subscribe(), context.peer);
         tracing::info!("MCP client connected and initialized");
         Ok(self.get_info())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_tool_declares_an_object_output_schema() {
+        for tool in PathfinderService::tool_router().list_all() {
+            let schema = tool
+                .output_schema
+                .unwrap_or_else(|| panic!("{} has no output schema", tool.name));
+            assert_eq!(schema.get("type"), Some(&serde_json::json!("object")));
+        }
+    }
+
+    #[test]
+    fn every_tool_declares_whether_it_is_read_only() {
+        for tool in PathfinderService::tool_router().list_all() {
+            let annotations = tool
+                .annotations
+                .unwrap_or_else(|| panic!("{} has no annotations", tool.name));
+            let read_only = annotations
+                .read_only_hint
+                .unwrap_or_else(|| panic!("{} has no readOnlyHint", tool.name));
+            if !read_only {
+                assert!(
+                    annotations.destructive_hint.is_some(),
+                    "{} has no destructiveHint",
+                    tool.name
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Per-client state for MCP sessions sharing one language server.
+//!
+//! Over HTTP, several MCP clients talk to the same pathfinder process. They share
+//! the [`LspSupervisor`] (and so the server and its open documents), but each has a
+//! [`Session`] of its own: pagination cursors, the overlay documents it pushed, and
+//! the set of documents it has used. When the session's service is dropped, its
+//! overlays revert to the file on disk and documents no other session uses are
+//! closed.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
+
+use crate::pagination::ResultPages;
+use crate::supervisor::LspSupervisor;
+use crate::tools::locations::DefinitionTarget;
+
+/// Identifies one MCP session within this process.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionId(u64);
+
+impl SessionId {
+    /// A fresh id, never handed out before in this process.
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl fmt::Display for SessionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+pub struct Session {
+    id: SessionId,
+    supervisor: Weak<LspSupervisor>,
+    definition_pages: ResultPages<DefinitionTarget>,
+    reference_pages: ResultPages<DefinitionTarget>,
+}
+
+impl Session {
+    pub fn new(supervisor: &Arc<LspSupervisor>) -> Self {
+        Self {
+            id: SessionId::next(),
+            supervisor: Arc::downgrade(supervisor),
+            definition_pages: ResultPages::new(),
+            reference_pages: ResultPages::new(),
+        }
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    /// Cached pages of `definition` results.
+    pub fn definition_pages(&self) -> &ResultPages<DefinitionTarget> {
+        &self.definition_pages
+    }
+
+    /// Cached pages of `references` results.
+    pub fn reference_pages(&self) -> &ResultPages<DefinitionTarget> {
+        &self.reference_pages
+    }
+}
+
+impl Drop for Session {
+    /// Releases the session's documents in the background.
+    fn drop(&mut self) {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let id = self.id;
+        let supervisor = self.supervisor.clone();
+        runtime.spawn(async move {
+            if let Some(supervisor) = supervisor.upgrade() {
+                supervisor.end_session(id).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_ids_are_unique() {
+        let first = SessionId::next();
+        let second = SessionId::next();
+        assert_ne!(first, second);
+        assert_ne!(first.to_string(), second.to_string());
+    }
+}
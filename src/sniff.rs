@@ -0,0 +1,209 @@
+//! Language detection for files without an extension.
+//!
+//! Servers are configured by file extension, so a script such as `bin/deploy` would
+//! never reach one. For a file with no extension, the language is sniffed from its
+//! contents instead, in this order:
+//!
+//! - a shebang (`#!/usr/bin/env python3`, `#!/bin/bash`), by interpreter name
+//! - a Vim (`vim: set ft=ruby:`) or Emacs (`-*- mode: perl -*-`) modeline in the
+//!   first or last few lines
+//! - a telltale opening, such as `<?php`
+//!
+//! The detected language is reported as the extension its files usually have, so
+//! routing and `languageIds` work the same as for a file that has one.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::utils::{extension_from_uri, uri_to_path};
+
+/// Bytes read from each end of a file when sniffing it.
+const SNIFF_BYTES: u64 = 4096;
+
+/// Lines searched for a modeline at each end of a file, as Vim does by default.
+const MODELINE_LINES: usize = 5;
+
+/// Language names, as used by interpreters and modelines, and their extension.
+const LANGUAGES: &[(&[&str], &str)] = &[
+    (&["python", "pypy"], "py"),
+    (&["node", "nodejs", "javascript", "js"], "js"),
+    (&["deno", "bun", "ts-node", "tsx", "typescript"], "ts"),
+    (&["sh", "bash", "dash", "ksh", "zsh", "shell-script"], "sh"),
+    (&["ruby"], "rb"),
+    (&["perl"], "pl"),
+    (&["php"], "php"),
+    (&["lua", "luajit"], "lua"),
+    (&["rust", "rust-script"], "rs"),
+    (&["go", "gorun"], "go"),
+];
+
+/// The extension that routes the document at `uri`: its own, or for an existing
+/// file without one, the one its contents suggest.
+pub fn document_extension(uri: &str) -> Option<String> {
+    if let Some(extension) = extension_from_uri(uri) {
+        return Some(extension);
+    }
+    let path = uri_to_path(uri).ok()?;
+    let (head, tail) = read_ends(&path)?;
+    sniff(&head, &tail).map(str::to_string)
+}
+
+/// The extension the contents of a file without one suggest; `text` is the whole
+/// file, or its start.
+pub fn sniff_text(text: &str) -> Option<&'static str> {
+    sniff(text, text)
+}
+
+fn sniff(head: &str, tail: &str) -> Option<&'static str> {
+    if let Some(line) = head.lines().next()
+        && let Some(interpreter) = line.strip_prefix("#!")
+    {
+        return shebang(interpreter).and_then(extension_for);
+    }
+    let lines: Vec<&str> = head.lines().take(MODELINE_LINES).collect();
+    let last: Vec<&str> = tail.lines().rev().take(MODELINE_LINES).collect();
+    if let Some(extension) = lines
+        .iter()
+        .chain(&last)
+        .find_map(|line| modeline(line))
+        .and_then(extension_for)
+    {
+        return Some(extension);
+    }
+    head.trim_start().starts_with("<?php").then_some("php")
+}
+
+/// The language an interpreter line names: the program, or with `env`, the program
+/// it runs, without a version suffix (`python3.12` is `python`).
+fn shebang(interpreter: &str) -> Option<&str> {
+    let mut words = interpreter.split_whitespace();
+    let mut program = file_name(words.next()?);
+    if program == "env" {
+        // Skip env's options (`-S`, `-i`) and variable assignments
+        program = file_name(words.find(|word| !word.starts_with('-') && !word.contains('='))?);
+    }
+    Some(program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.'))
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// The language a Vim (`ft=`/`filetype=`/`syntax=`) or Emacs (`mode:`) modeline on
+/// `line` sets.
+fn modeline(line: &str) -> Option<&str> {
+    if let Some(start) = line.find("-*-") {
+        let rest = &line[start + 3..];
+        let vars = &rest[..rest.find("-*-")?];
+        // `-*- python -*-` names the mode alone; otherwise look for `mode: python`
+        if !vars.contains(':') {
+            return Some(vars.trim());
+        }
+        return vars.split(';').find_map(|var| {
+            let (name, value) = var.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case("mode")
+                .then(|| value.trim())
+        });
+    }
+    // Like Vim, only a marker at the start of the line or after a space counts
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        line.match_indices(marker)
+            .find(|(index, _)| {
+                line[..*index]
+                    .chars()
+                    .last()
+                    .is_none_or(char::is_whitespace)
+            })
+            .map(|(index, _)| index + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c == ':' || c.is_whitespace())
+        .find_map(|option| {
+            let (name, value) = option.split_once('=')?;
+            matches!(name, "ft" | "filetype" | "syntax").then_some(value)
+        })
+}
+
+fn extension_for(language: &str) -> Option<&'static str> {
+    let language = language.to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(names, _)| names.contains(&language.as_str()))
+        .map(|(_, extension)| *extension)
+}
+
+/// The first and last [`SNIFF_BYTES`] of the file at `path`, lossily decoded; the
+/// same text twice for a short file.
+fn read_ends(path: &Path) -> Option<(String, String)> {
+    let mut file = File::open(path).ok()?;
+    if !file.metadata().ok()?.is_file() {
+        return None;
+    }
+    let mut head = Vec::new();
+    (&mut file).take(SNIFF_BYTES).read_to_end(&mut head).ok()?;
+    let head = String::from_utf8_lossy(&head).into_owned();
+    let len = file.metadata().ok()?.len();
+    if len <= SNIFF_BYTES {
+        return Some((head.clone(), head));
+    }
+    let mut tail = Vec::new();
+    file.seek(SeekFrom::Start(len - SNIFF_BYTES)).ok()?;
+    file.read_to_end(&mut tail).ok()?;
+    Some((head, String::from_utf8_lossy(&tail).into_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shebangs_name_the_interpreter() {
+        assert_eq!(sniff_text("#!/usr/bin/env python3\nprint(1)\n"), Some("py"));
+        assert_eq!(sniff_text("#!/usr/bin/python3.12 -u\n"), Some("py"));
+        assert_eq!(sniff_text("#!/bin/bash\nset -e\n"), Some("sh"));
+        assert_eq!(sniff_text("#!/usr/bin/env -S deno run\n"), Some("ts"));
+        assert_eq!(
+            sniff_text("#!/usr/bin/env NODE_ENV=production node\n"),
+            Some("js")
+        );
+        assert_eq!(sniff_text("#!/usr/bin/awk -f\n"), None);
+    }
+
+    #[test]
+    fn modelines_and_openings_name_the_language() {
+        assert_eq!(sniff_text("# vim: set ft=ruby ts=2:\nputs 1\n"), Some("rb"));
+        assert_eq!(sniff_text("x = 1\n\n# vi: filetype=python\n"), Some("py"));
+        assert_eq!(
+            sniff_text("; -*- mode: Perl; tab-width: 4 -*-\n"),
+            Some("pl")
+        );
+        assert_eq!(sniff_text("-- -*- lua -*-\n"), Some("lua"));
+        assert_eq!(sniff_text("<?php\necho 1;\n"), Some("php"));
+        assert_eq!(sniff_text("plain notes\n"), None);
+    }
+
+    #[test]
+    fn extensionless_files_are_routed_by_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let script = dir.path().join("deploy");
+        std::fs::write(&script, "#!/usr/bin/env python3\nimport sys\n").unwrap();
+        let uri = url::Url::from_file_path(&script).unwrap();
+        assert_eq!(document_extension(uri.as_str()).as_deref(), Some("py"));
+
+        // A long file keeps its modeline at the end
+        let notes = dir.path().join("notes");
+        let body = "x = 1\n".repeat(2000);
+        std::fs::write(&notes, format!("{body}# vim: ft=ruby\n")).unwrap();
+        let uri = url::Url::from_file_path(&notes).unwrap();
+        assert_eq!(document_extension(uri.as_str()).as_deref(), Some("rb"));
+
+        let missing = url::Url::from_file_path(dir.path().join("missing")).unwrap();
+        assert_eq!(document_extension(missing.as_str()), None);
+        assert_eq!(
+            document_extension("file:///src/main.rs").as_deref(),
+            Some("rs")
+        );
+    }
+}
@@ -0,0 +1,512 @@
+//! Compact text renderings of tool results, for `format = "text"`.
+//!
+//! A summary is rendered from the result as serialized for the client, so it shows
+//! the same line and column numbers (zero- or one-based) as the structured content.
+//! Documents always appear as workspace-relative paths, which every tool accepts
+//! back as input.
+
+use std::path::Path;
+
+use serde_json::Value;
+use url::Url;
+
+use crate::format::relative_path;
+
+/// The text summary of `tool`'s `result`, or `None` for tools without one.
+pub fn render(tool: &str, workspace: &Path, result: &Value) -> Option<String> {
+    let summary = match tool {
+        "definition" | "go_to_source_definition" => definitions(workspace, result),
+        "references" => references(workspace, result),
+        "read_file_range" => read(workspace, result),
+        "search_text" => matches(result),
+        "find_symbol" => symbols(workspace, result),
+        "find_unused_symbols" => unused(workspace, result),
+        "outline" => outline(workspace, result),
+        "dependency_graph" => dependencies(workspace, result),
+        "rename" => edit_preview(result),
+        "code_action" => code_actions(result),
+        "open_document" | "update_document" => format!(
+            "{} is at version {}",
+            path(workspace, string(result, "uri")),
+            number(result, "version")
+        ),
+        "check_edit" => check(workspace, result),
+        "expand_macro" => match result.get("name").and_then(Value::as_str) {
+            Some(name) => format!("{name}! expands to:\n{}", string(result, "expansion")),
+            None => "No macro call at the position".to_string(),
+        },
+        "view_hir" => string(result, "hir").to_string(),
+        "switch_source_header" => match result.get("uri").and_then(Value::as_str) {
+            Some(uri) => path(workspace, uri),
+            None => "No matching header or source file".to_string(),
+        },
+        _ => return None,
+    };
+    Some(summary)
+}
+
+fn definitions(workspace: &Path, result: &Value) -> String {
+    let targets = array(result, "targets");
+    let mut summary = match targets {
+        [] => "No definition found".to_string(),
+        [target] => format!(
+            "Defined at {}{}",
+            location(workspace, target),
+            notes(target)
+        ),
+        _ => format!(
+            "{} definitions:{}",
+            targets.len(),
+            listing(workspace, targets)
+        ),
+    };
+    append_paging(&mut summary, result);
+    summary
+}
+
+fn references(workspace: &Path, result: &Value) -> String {
+    let references = array(result, "references");
+    let total = number(result, "total");
+    let mut summary = if total == 0 {
+        "No references found".to_string()
+    } else if references.len() as u64 == total {
+        format!("{total} references:{}", listing(workspace, references))
+    } else {
+        format!(
+            "{total} references, {} shown:{}",
+            references.len(),
+            listing(workspace, references)
+        )
+    };
+    append_paging(&mut summary, result);
+    summary
+}
+
+fn read(workspace: &Path, result: &Value) -> String {
+    let mut summary = format!(
+        "{} lines {}-{} of {}",
+        path(workspace, string(result, "uri")),
+        number(result, "start_line"),
+        number(result, "end_line"),
+        number(result, "total_lines")
+    );
+    if result.get("truncated") == Some(&Value::Bool(true)) {
+        summary.push_str(" (truncated)");
+    }
+    summary.push('\n');
+    summary.push_str(string(result, "text"));
+    summary
+}
+
+fn matches(result: &Value) -> String {
+    let matches = array(result, "matches");
+    if matches.is_empty() {
+        return "No matches".to_string();
+    }
+    let mut summary = matches
+        .iter()
+        .map(|found| {
+            format!(
+                "{}:{}:{}: {}",
+                string(found, "path"),
+                number(found, "line"),
+                number(found, "column"),
+                string(found, "line_text").trim()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if result.get("truncated") == Some(&Value::Bool(true)) {
+        summary.push_str("\n(more matches not shown)");
+    }
+    summary
+}
+
+fn symbols(workspace: &Path, result: &Value) -> String {
+    let symbols = array(result, "symbols");
+    let mut summary = if symbols.is_empty() {
+        "No symbols found".to_string()
+    } else {
+        symbols
+            .iter()
+            .map(|symbol| {
+                format!(
+                    "{} ({}) {}",
+                    string(symbol, "name"),
+                    string(symbol, "kind"),
+                    location(workspace, symbol)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    if result.get("truncated") == Some(&Value::Bool(true)) {
+        summary.push_str("\n(more symbols not shown)");
+    }
+    if result.get("indexing") == Some(&Value::Bool(true)) {
+        summary.push_str("\n(the index is still being built; results may be incomplete)");
+    }
+    summary
+}
+
+/// One block per file: its path, then `line kind name` indented by nesting.
+fn outline(workspace: &Path, result: &Value) -> String {
+    let files = array(result, "files");
+    let mut lines = Vec::new();
+    for file in files {
+        lines.push(path(workspace, string(file, "uri")));
+        outline_symbols(&mut lines, array(file, "symbols"), 1);
+    }
+    let mut summary = match files {
+        [] => "No symbols found".to_string(),
+        _ => lines.join("\n"),
+    };
+    if result.get("truncated") == Some(&Value::Bool(true)) {
+        summary.push_str("\n(more files than max_files; the rest were not outlined)");
+    }
+    summary
+}
+
+fn outline_symbols(lines: &mut Vec<String>, symbols: &[Value], depth: usize) {
+    for symbol in symbols {
+        lines.push(format!(
+            "{}{} {} {}",
+            "  ".repeat(depth),
+            number(symbol, "line"),
+            string(symbol, "kind"),
+            string(symbol, "name")
+        ));
+        outline_symbols(lines, array(symbol, "children"), depth + 1);
+    }
+}
+
+/// One line per file that imports workspace files, naming them.
+fn dependencies(workspace: &Path, result: &Value) -> String {
+    let files = array(result, "files");
+    let mut summary = format!(
+        "{} imports between workspace files in {} files",
+        number(result, "edges"),
+        files.len()
+    );
+    for file in files {
+        let imports = array(file, "imports");
+        if imports.is_empty() {
+            continue;
+        }
+        let targets: Vec<String> = imports
+            .iter()
+            .map(|import| path(workspace, string(import, "uri")))
+            .collect();
+        summary.push_str(&format!(
+            "\n  {} -> {}",
+            path(workspace, string(file, "uri")),
+            targets.join(", ")
+        ));
+    }
+    if number(result, "failed") > 0 {
+        summary.push_str(&format!(
+            "\n({} files failed and were skipped)",
+            number(result, "failed")
+        ));
+    }
+    if result.get("truncated") == Some(&Value::Bool(true)) {
+        summary.push_str("\n(more files than max_files; the rest were not graphed)");
+    }
+    summary
+}
+
+fn unused(workspace: &Path, result: &Value) -> String {
+    let candidates = array(result, "candidates");
+    let mut summary = format!(
+        "{} unused of {} symbols in {} files",
+        candidates.len(),
+        number(result, "symbols_checked"),
+        number(result, "files_scanned")
+    );
+    for candidate in candidates {
+        summary.push_str(&format!(
+            "\n  {} {} ({})",
+            location(workspace, candidate),
+            string(candidate, "name"),
+            string(candidate, "kind")
+        ));
+    }
+    if number(result, "failed") > 0 {
+        summary.push_str(&format!(
+            "\n({} files or symbols failed and were skipped)",
+            number(result, "failed")
+        ));
+    }
+    if result.get("truncated") == Some(&Value::Bool(true)) {
+        summary.push_str("\n(more files than max_files; the rest were not scanned)");
+    }
+    summary
+}
+
+/// Counts the diagnostics, then lists those introduced (`+`) and resolved (`-`).
+fn check(workspace: &Path, result: &Value) -> String {
+    let (introduced, resolved) = (array(result, "introduced"), array(result, "resolved"));
+    let mut summary = format!(
+        "{}: {} diagnostics, {} introduced, {} resolved",
+        path(workspace, string(result, "uri")),
+        array(result, "diagnostics").len(),
+        introduced.len(),
+        resolved.len()
+    );
+    let marked = introduced
+        .iter()
+        .map(|diagnostic| ('+', diagnostic))
+        .chain(resolved.iter().map(|diagnostic| ('-', diagnostic)));
+    for (mark, diagnostic) in marked {
+        summary.push_str(&format!(
+            "\n  {mark} {}:{} {}: {}",
+            number(diagnostic, "start_line"),
+            number(diagnostic, "start_character"),
+            string(diagnostic, "severity"),
+            string(diagnostic, "message")
+        ));
+    }
+    summary
+}
+
+/// Notes the snapped position, truncation, and the cursor for the next page, if any.
+fn edit_preview(result: &Value) -> String {
+    let files = array(result, "files").len();
+    if files == 0 {
+        return "No changes".to_string();
+    }
+    let applied = result.get("applied").and_then(Value::as_bool) == Some(true);
+    let verb = if applied { "Changed" } else { "Would change" };
+    format!("{verb} {files} files:\n{}", string(result, "diff"))
+}
+
+fn code_actions(result: &Value) -> String {
+    let actions = array(result, "actions");
+    let mut summary = match actions {
+        [] => "No code actions".to_string(),
+        _ => format!("{} code actions:", actions.len()),
+    };
+    for action in actions {
+        summary.push_str(&format!(
+            "\n  [{}] {}",
+            number(action, "index"),
+            string(action, "title")
+        ));
+        if let Some(kind) = action.get("kind").and_then(Value::as_str) {
+            summary.push_str(&format!(" ({kind})"));
+        }
+        if let Some(reason) = action.get("disabled").and_then(Value::as_str) {
+            summary.push_str(&format!(" - disabled: {reason}"));
+        }
+    }
+    if let Some(selected) = result
+        .get("selected")
+        .filter(|selected| !selected.is_null())
+    {
+        summary.push_str(&format!("\n{}", edit_preview(selected)));
+    }
+    summary
+}
+
+fn append_paging(summary: &mut String, result: &Value) {
+    if let Some(snapped) = result.get("snapped") {
+        summary.push_str(&format!(
+            "\n(position snapped to {}:{})",
+            number(snapped, "line"),
+            number(snapped, "character")
+        ));
+    }
+    if result.get("truncated") == Some(&Value::Bool(true)) {
+        summary.push_str("\n(more results beyond max_results not shown)");
+    }
+    if let Some(cursor) = result.get("next_cursor").and_then(Value::as_str) {
+        summary.push_str(&format!("\nMore results with cursor {cursor}"));
+    }
+}
+
+fn listing(workspace: &Path, targets: &[Value]) -> String {
+    targets
+        .iter()
+        .map(|target| format!("\n  {}{}", location(workspace, target), notes(target)))
+        .collect()
+}
+
+/// ` (scope, server)` for a target that has either, else nothing.
+fn notes(target: &Value) -> String {
+    let notes: Vec<&str> = ["scope", "server"]
+        .into_iter()
+        .filter_map(|key| target.get(key).and_then(Value::as_str))
+        .collect();
+    if notes.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", notes.join(", "))
+    }
+}
+
+/// `path:line:character` of a target's start.
+fn location(workspace: &Path, target: &Value) -> String {
+    let range = target.get("range").unwrap_or(&Value::Null);
+    format!(
+        "{}:{}:{}",
+        path(workspace, string(target, "uri")),
+        number(range, "start_line"),
+        number(range, "start_character")
+    )
+}
+
+/// `uri` relative to the workspace, or as a plain path if it is outside it.
+fn path(workspace: &Path, uri: &str) -> String {
+    if let Some(relative) = relative_path(workspace, uri) {
+        return relative;
+    }
+    match Url::parse(uri).ok().and_then(|url| url.to_file_path().ok()) {
+        Some(path) => path.display().to_string(),
+        None => uri.to_string(),
+    }
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> &'a [Value] {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+fn string<'a>(value: &'a Value, key: &str) -> &'a str {
+    value.get(key).and_then(Value::as_str).unwrap_or_default()
+}
+
+fn number(value: &Value, key: &str) -> u64 {
+    value.get(key).and_then(Value::as_u64).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn summarizes_locations_relative_to_the_workspace() {
+        let workspace = std::env::temp_dir().join("project");
+        let uri = Url::from_file_path(workspace.join("src/main.rs")).unwrap();
+        let target = json!({
+            "uri": uri.as_str(),
+            "range": {"start_line": 6, "start_character": 4, "end_line": 6, "end_character": 7},
+        });
+
+        let definition = json!({"targets": [target]});
+        assert_eq!(
+            render("definition", &workspace, &definition).unwrap(),
+            "Defined at src/main.rs:6:4"
+        );
+
+        let references = json!({
+            "references": [target, target],
+            "total": 3,
+            "next_cursor": "abc",
+        });
+        assert_eq!(
+            render("references", &workspace, &references).unwrap(),
+            "3 references, 2 shown:\n  src/main.rs:6:4\n  src/main.rs:6:4\nMore results with cursor abc"
+        );
+
+        assert_eq!(
+            render("definition", &workspace, &json!({"targets": []})).unwrap(),
+            "No definition found"
+        );
+        assert!(render("server_status", &workspace, &json!({})).is_none());
+    }
+
+    #[test]
+    fn notes_the_scope_and_server_of_targets() {
+        let workspace = std::env::temp_dir().join("project");
+        let uri = Url::from_file_path(workspace.join("src/main.rs")).unwrap();
+        let range =
+            json!({"start_line": 1, "start_character": 0, "end_line": 1, "end_character": 3});
+        let references = json!({
+            "references": [
+                {"uri": uri.as_str(), "range": range, "scope": "file", "server": "eslint"},
+                {"uri": uri.as_str(), "range": range, "scope": "file"},
+            ],
+            "total": 2,
+        });
+        assert_eq!(
+            render("references", &workspace, &references).unwrap(),
+            "2 references:\n  src/main.rs:1:0 (file, eslint)\n  src/main.rs:1:0 (file)"
+        );
+    }
+
+    #[test]
+    fn outlines_files_as_indented_trees() {
+        let workspace = std::env::temp_dir().join("project");
+        let uri = Url::from_file_path(workspace.join("src/server.rs")).unwrap();
+        let result = json!({
+            "files": [{
+                "uri": uri.as_str(),
+                "symbols": [
+                    {"name": "Server", "kind": "struct", "line": 2},
+                    {"name": "Server", "kind": "object", "line": 6, "children": [
+                        {"name": "run", "kind": "method", "line": 7},
+                    ]},
+                ],
+            }],
+            "failed": 0,
+            "truncated": false,
+        });
+        assert_eq!(
+            render("outline", &workspace, &result).unwrap(),
+            "src/server.rs\n  2 struct Server\n  6 object Server\n    7 method run"
+        );
+    }
+
+    #[test]
+    fn lists_the_files_each_file_imports() {
+        let workspace = std::env::temp_dir().join("project");
+        let uri = |path: &str| {
+            Url::from_file_path(workspace.join(path))
+                .unwrap()
+                .to_string()
+        };
+        let result = json!({
+            "files": [
+                {"uri": uri("src/config.rs"), "imports": [], "imported_by": [{"uri": uri("src/main.rs"), "line": 1}]},
+                {"uri": uri("src/main.rs"), "imports": [
+                    {"uri": uri("src/config.rs"), "line": 1},
+                    {"uri": uri("src/server.rs"), "line": 2},
+                ], "imported_by": []},
+            ],
+            "edges": 2,
+            "failed": 0,
+            "truncated": false,
+        });
+        assert_eq!(
+            render("dependency_graph", &workspace, &result).unwrap(),
+            "2 imports between workspace files in 2 files\n  src/main.rs -> src/config.rs, src/server.rs"
+        );
+    }
+
+    #[test]
+    fn lists_the_diagnostics_a_check_changed() {
+        let workspace = std::env::temp_dir().join("project");
+        let uri = Url::from_file_path(workspace.join("src/main.rs")).unwrap();
+        let diagnostic = |line: u32, severity: &str, message: &str| {
+            json!({
+                "start_line": line, "start_character": 4, "end_line": line, "end_character": 9,
+                "severity": severity, "message": message,
+            })
+        };
+        let introduced = diagnostic(3, "error", "mismatched types");
+        let result = json!({
+            "uri": uri.as_str(),
+            "diagnostics": [introduced, diagnostic(7, "warning", "unused variable")],
+            "introduced": [introduced],
+            "resolved": [diagnostic(1, "error", "cannot find value")],
+        });
+        assert_eq!(
+            render("check_edit", &workspace, &result).unwrap(),
+            "src/main.rs: 2 diagnostics, 1 introduced, 1 resolved\n  + 3:4 error: mismatched types\n  - 1:4 error: cannot find value"
+        );
+    }
+}
@@ -0,0 +1,740 @@
+//! Language server supervision and crash recovery.
+//!
+//! The supervisor owns the current [`LspBridge`] and watches for the server process
+//! exiting on its own (panic, OOM kill). When that happens it respawns the server
+//! according to the configured [`RestartPolicy`], re-runs `initialize`, and replays
+//! `didOpen` for every document the [`DocumentManager`] had open, so tool calls
+//! recover without restarting pathfinder. The same replacement can be requested by
+//! hand with [`LspSupervisor::restart`] when a server is alive but wedged, and
+//! happens on its own when a reloaded config changes how the server is launched
+//! (see [`LspSupervisor::reconfigure`]).
+
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex as StdMutex, RwLock, Weak};
+use std::time::{Duration, Instant};
+
+use anyhow::{Result, bail};
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::sync::{broadcast, watch};
+
+use crate::config::{RestartPolicy, ServerConfig, WhileRestarting};
+use crate::diagnostics::{self, DiagnosticsStore};
+use crate::documents::DocumentManager;
+use crate::health::{HEALTH_CHECK_INTERVAL, Health, probe};
+use crate::lsp_bridge::LspBridge;
+use crate::metrics::{Metrics, RestartReason};
+use crate::server_log::ServerLog;
+use crate::session::SessionId;
+use crate::sniff::document_extension;
+use crate::symbol_index::SymbolIndex;
+use crate::utils::path_to_uri;
+use crate::warmup;
+use crate::watcher::{FileChange, FileWatcher};
+
+/// Longest gap between idle-document sweeps.
+const MAX_EVICTION_INTERVAL: Duration = Duration::from_secs(60);
+
+/// `server` settings a reloaded config applies to the running server; changes to any
+/// other launch setting restart it.
+const LIVE_SETTINGS: &[&str] = &["extensions", "name", "timeouts", "restart"];
+
+/// Where the supervisor is in its restart cycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RestartState {
+    /// The current bridge is (as far as we know) alive.
+    Running,
+    /// The server exited (or a restart was requested) and a replacement is being
+    /// launched.
+    Restarting,
+    /// The restart limit was reached; the server stays down.
+    Failed,
+}
+
+pub struct LspSupervisor {
+    /// Replaced when the config file is reloaded.
+    server: RwLock<Arc<ServerConfig>>,
+    workspace: PathBuf,
+    bridge: RwLock<Arc<LspBridge>>,
+    documents: Arc<DocumentManager>,
+    restarts: AtomicU32,
+    /// When each recent restart began, for the `maxRestarts` window.
+    restart_times: StdMutex<VecDeque<Instant>>,
+    state: watch::Sender<RestartState>,
+    health: Health,
+    stderr_log: Arc<ServerLog>,
+    metrics: Arc<Metrics>,
+    symbols: Arc<SymbolIndex>,
+    diagnostics: Arc<DiagnosticsStore>,
+    shutting_down: AtomicBool,
+}
+
+impl LspSupervisor {
+    /// Spawns and initializes the server, then starts watching it for crashes.
+    pub async fn start(server: ServerConfig, workspace: PathBuf) -> Result<Arc<Self>> {
+        let stderr_log = Arc::new(ServerLog::default());
+        let metrics = Arc::new(Metrics::default());
+        let bridge = launch(&server, &workspace, &stderr_log, &metrics).await?;
+        let documents = DocumentManager::with_limits(server.documents);
+        let supervisor = Arc::new(Self {
+            server: RwLock::new(Arc::new(server)),
+            workspace,
+            bridge: RwLock::new(bridge.clone()),
+            documents: Arc::new(documents),
+            restarts: AtomicU32::new(0),
+            restart_times: StdMutex::new(VecDeque::new()),
+            state: watch::Sender::new(RestartState::Running),
+            health: Health::default(),
+            stderr_log,
+            metrics,
+            symbols: Arc::new(SymbolIndex::new(bridge.column_encoding())),
+            diagnostics: Arc::new(DiagnosticsStore::default()),
+            shutting_down: AtomicBool::new(false),
+        });
+        supervisor.attach(&bridge);
+        spawn_health_checks(Arc::downgrade(&supervisor));
+        let server = supervisor.config();
+        if let Some(timeout) = server.documents.idle_timeout() {
+            spawn_idle_eviction(Arc::downgrade(&supervisor), timeout);
+        }
+        if server.warmup.is_enabled() {
+            spawn_warmup(Arc::downgrade(&supervisor));
+        }
+        if server.index.enabled {
+            spawn_symbol_index(&supervisor);
+        }
+        if server.watch.enabled {
+            match FileWatcher::start(&supervisor.workspace) {
+                Ok(watcher) => spawn_file_watcher(Arc::downgrade(&supervisor), watcher),
+                Err(err) => tracing::warn!(?err, "File watching disabled"),
+            }
+        }
+        Ok(supervisor)
+    }
+
+    /// The bridge to the currently running server.
+    ///
+    /// After a crash this keeps returning the dead bridge (whose requests fail fast)
+    /// until the replacement has been initialized and swapped in.
+    pub fn bridge(&self) -> Arc<LspBridge> {
+        self.bridge
+            .read()
+            .expect("supervisor bridge lock poisoned")
+            .clone()
+    }
+
+    /// The bridge to use for a tool call, honoring `whileRestarting`.
+    ///
+    /// With `fail`, errors right away while the server is down. With `queue`, waits
+    /// up to `queueTimeoutSecs` for the replacement server. Errors once the restart
+    /// limit has been reached either way.
+    pub async fn ready_bridge(&self) -> Result<Arc<LspBridge>> {
+        let policy = self.restart_policy();
+        let mut state = self.state.subscribe();
+        let waited = async {
+            loop {
+                let current = *state.borrow_and_update();
+                match current {
+                    RestartState::Failed => bail!(
+                        "language server stopped after reaching its restart limit (maxRestarts = {}); call restart_server or restart pathfinder to recover",
+                        policy.max_restarts
+                    ),
+                    RestartState::Running => {
+                        let bridge = self.bridge();
+                        if !bridge.has_exited() {
+                            return Ok(bridge);
+                        }
+                    }
+                    RestartState::Restarting => {}
+                }
+                if policy.while_restarting == WhileRestarting::Fail {
+                    bail!("language server is restarting; try again shortly");
+                }
+                // Running with an exited bridge means the monitor has not caught up
+                // yet; the next state change is the switch to restarting.
+                if state.changed().await.is_err() {
+                    bail!("language server supervisor stopped");
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(policy.queue_timeout_secs), waited)
+            .await
+            .unwrap_or_else(|_| {
+                bail!(
+                    "language server did not restart within {}s",
+                    policy.queue_timeout_secs
+                )
+            })
+    }
+
+    /// Current position in the restart cycle.
+    pub fn restart_state(&self) -> RestartState {
+        *self.state.borrow()
+    }
+
+    /// The configured restart policy.
+    pub fn restart_policy(&self) -> RestartPolicy {
+        self.config().restart.clone()
+    }
+
+    /// The `server` section of the config in effect.
+    pub fn config(&self) -> Arc<ServerConfig> {
+        self.server
+            .read()
+            .expect("supervisor config lock poisoned")
+            .clone()
+    }
+
+    /// The workspace root the server was started in (canonical).
+    pub fn workspace(&self) -> &Path {
+        &self.workspace
+    }
+
+    /// Pathfinder's own symbol index; empty unless `index.enabled` is set.
+    pub fn symbol_index(&self) -> &Arc<SymbolIndex> {
+        &self.symbols
+    }
+
+    /// Whether the server is configured for the document at `uri`, by its extension
+    /// or, for a file without one, by its contents.
+    pub fn serves(&self, uri: &str) -> bool {
+        document_extension(uri).is_some_and(|ext| self.config().extensions.contains(&ext))
+    }
+
+    /// The latest diagnostics the server published for each document.
+    pub fn diagnostics(&self) -> &Arc<DiagnosticsStore> {
+        &self.diagnostics
+    }
+
+    /// Documents opened on the server, shared by all tool calls.
+    pub fn documents(&self) -> &Arc<DocumentManager> {
+        &self.documents
+    }
+
+    /// Records a restart attempt, or returns false if the policy's limit is reached.
+    fn admit_restart(&self) -> bool {
+        let policy = self.restart_policy();
+        let mut times = self
+            .restart_times
+            .lock()
+            .expect("supervisor restart lock poisoned");
+        let now = Instant::now();
+        if policy.window_secs > 0 {
+            let window = Duration::from_secs(policy.window_secs);
+            while times
+                .front()
+                .is_some_and(|started| now.duration_since(*started) >= window)
+            {
+                times.pop_front();
+            }
+        }
+        if times.len() >= policy.max_restarts as usize {
+            return false;
+        }
+        times.push_back(now);
+        true
+    }
+
+    fn give_up(&self) {
+        let max_restarts = self.restart_policy().max_restarts;
+        tracing::error!(
+            max_restarts,
+            "Restart limit reached; giving up on language server"
+        );
+        self.health.record_error(format!(
+            "restart limit reached (maxRestarts = {max_restarts}); language server stays down"
+        ));
+        self.state.send_replace(RestartState::Failed);
+    }
+
+    /// Number of times the server has been restarted after crashing.
+    pub fn restart_count(&self) -> u32 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Probe results and the most recent error.
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Server stderr, kept across restarts.
+    pub fn stderr_log(&self) -> &Arc<ServerLog> {
+        &self.stderr_log
+    }
+
+    /// Tool and LSP request metrics, kept across restarts.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Closes all documents and shuts the server down.
+    ///
+    /// Crash recovery is disabled first so the server's exit is not mistaken for a
+    /// crash. Safe to call more than once; later calls do nothing.
+    pub async fn shutdown(&self) {
+        if self.shutting_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let lsp = self.bridge();
+        if lsp.has_exited() {
+            return;
+        }
+        if let Err(err) = self.documents.close_all(&lsp).await {
+            tracing::warn!(?err, "Failed to close documents during shutdown");
+        }
+        if let Err(err) = lsp.shutdown().await {
+            tracing::warn!(?err, "Language server did not shut down cleanly");
+        }
+    }
+
+    /// Releases the documents `session` used: its overlays revert to the file on
+    /// disk, and documents no other session uses are closed.
+    pub async fn end_session(&self, session: SessionId) {
+        if self.is_shutting_down() {
+            return;
+        }
+        let lsp = self.bridge();
+        let closed = self.documents.release_session(&lsp, session).await;
+        tracing::debug!(%session, closed, "MCP session ended; released its documents");
+    }
+
+    /// Replaces a running (or given-up-on) server with a fresh one.
+    ///
+    /// The current server is shut down gracefully (killed if it does not answer),
+    /// and a new one is launched, initialized, and sent every open document. Manual
+    /// restarts do not count against `maxRestarts`. If the new server fails to start,
+    /// crash recovery takes over and the error is returned.
+    pub async fn restart(self: &Arc<Self>) -> Result<()> {
+        if self.is_shutting_down() {
+            bail!("pathfinder is shutting down");
+        }
+        // Claim the restart so the crash monitor ignores the old server's exit
+        let claimed = self.state.send_if_modified(|state| {
+            if *state == RestartState::Restarting {
+                return false;
+            }
+            *state = RestartState::Restarting;
+            true
+        });
+        if !claimed {
+            bail!("language server is already restarting");
+        }
+
+        let old = self.bridge();
+        tracing::info!(pid = ?old.pid(), "Restarting language server on request");
+        if !old.has_exited()
+            && let Err(err) = old.shutdown().await
+        {
+            tracing::warn!(?err, "Old language server did not shut down cleanly");
+        }
+
+        let bridge = match launch(
+            &self.config(),
+            &self.workspace,
+            &self.stderr_log,
+            &self.metrics,
+        )
+        .await
+        {
+            Ok(bridge) => bridge,
+            Err(err) => {
+                self.health
+                    .record_error(format!("manual restart failed: {err:#}"));
+                let supervisor = Arc::downgrade(self);
+                tokio::spawn(async move { Self::recover(&supervisor).await });
+                return Err(err.context("failed to start a new language server"));
+            }
+        };
+        if self.is_shutting_down() {
+            let _ = bridge.shutdown().await;
+            bail!("pathfinder is shutting down");
+        }
+
+        let swap = || {
+            *self
+                .bridge
+                .write()
+                .expect("supervisor bridge lock poisoned") = bridge.clone();
+        };
+        if let Err(err) = self.documents.reopen_all(&bridge, swap).await {
+            tracing::warn!(?err, "Failed to replay open documents after restart");
+        }
+        self.state.send_replace(RestartState::Running);
+        self.metrics.record_restart(RestartReason::Manual);
+        tracing::info!(pid = ?bridge.pid(), "Language server restarted on request");
+        self.attach(&bridge);
+        Ok(())
+    }
+
+    /// Switches to `server`, the `server` section of a reloaded config.
+    ///
+    /// Extensions, timeouts, and the restart policy apply to the running server;
+    /// any other change restarts it with the new settings, as [`Self::restart`]
+    /// does. Settings read only at startup are the caller's to keep (see
+    /// [`ServerConfig::keep_startup_settings`]).
+    pub async fn reconfigure(self: &Arc<Self>, server: ServerConfig) -> Result<()> {
+        if self.is_shutting_down() {
+            bail!("pathfinder is shutting down");
+        }
+        let relaunch = needs_relaunch(&self.config(), &server)?;
+        let timeouts = server.timeouts.clone();
+        *self
+            .server
+            .write()
+            .expect("supervisor config lock poisoned") = Arc::new(server);
+        if !relaunch {
+            self.bridge().set_timeouts(timeouts);
+            return Ok(());
+        }
+        // A crash recovery under way launches with the new settings anyway
+        if self.restart_state() == RestartState::Restarting {
+            return Ok(());
+        }
+        tracing::info!("Language server settings changed; restarting it");
+        self.restart().await
+    }
+
+    fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Starts the background tasks tied to one bridge: crash monitoring, resyncing
+    /// documents touched by server-applied edits, and collecting diagnostics.
+    fn attach(self: &Arc<Self>, bridge: &Arc<LspBridge>) {
+        spawn_edit_resync(bridge, &self.documents);
+        diagnostics::spawn_collector(bridge, &self.diagnostics);
+        spawn_monitor(Arc::downgrade(self), bridge);
+    }
+
+    /// Respawns the server until it initializes again, then replays open documents.
+    ///
+    /// Every launch attempt counts against `maxRestarts`; once the limit is hit the
+    /// supervisor moves to [`RestartState::Failed`] and stops trying.
+    async fn recover(this: &Weak<Self>) {
+        let mut attempt = 0;
+        loop {
+            let delay = {
+                let Some(supervisor) = this.upgrade() else {
+                    return;
+                };
+                if supervisor.is_shutting_down() {
+                    return;
+                }
+                if !supervisor.admit_restart() {
+                    supervisor.give_up();
+                    return;
+                }
+                supervisor.restart_policy().delay(attempt)
+            };
+            tokio::time::sleep(delay).await;
+            let Some(supervisor) = this.upgrade() else {
+                return;
+            };
+            if supervisor.is_shutting_down() {
+                return;
+            }
+
+            let bridge = match launch(
+                &supervisor.config(),
+                &supervisor.workspace,
+                &supervisor.stderr_log,
+                &supervisor.metrics,
+            )
+            .await
+            {
+                Ok(bridge) => bridge,
+                Err(err) => {
+                    attempt += 1;
+                    let retry_in = supervisor.restart_policy().delay(attempt);
+                    tracing::warn!(?err, ?retry_in, "Failed to restart language server");
+                    supervisor
+                        .health
+                        .record_error(format!("restart failed: {err:#}"));
+                    continue;
+                }
+            };
+
+            if supervisor.is_shutting_down() {
+                let _ = bridge.shutdown().await;
+                return;
+            }
+
+            // Swap inside the replay so no tool call syncs a document to the new
+            // server before the replay has happened.
+            let swap = || {
+                *supervisor
+                    .bridge
+                    .write()
+                    .expect("supervisor bridge lock poisoned") = bridge.clone();
+            };
+            if let Err(err) = supervisor.documents.reopen_all(&bridge, swap).await {
+                tracing::warn!(?err, "Failed to replay open documents after restart");
+            }
+            supervisor.state.send_replace(RestartState::Running);
+
+            let restarts = supervisor.restarts.fetch_add(1, Ordering::Relaxed) + 1;
+            supervisor.metrics.record_restart(RestartReason::Crash);
+            tracing::info!(restarts, "Language server restarted");
+            supervisor.attach(&bridge);
+            return;
+        }
+    }
+}
+
+/// Whether `reloaded` differs from `running` in more than the [`LIVE_SETTINGS`].
+fn needs_relaunch(running: &ServerConfig, reloaded: &ServerConfig) -> Result<bool> {
+    let launch_settings = |server: &ServerConfig| -> Result<Value> {
+        let mut value = serde_json::to_value(server)?;
+        if let Some(settings) = value.as_object_mut() {
+            for key in LIVE_SETTINGS {
+                settings.remove(*key);
+            }
+        }
+        Ok(value)
+    };
+    Ok(launch_settings(running)? != launch_settings(reloaded)?)
+}
+
+async fn launch(
+    server: &ServerConfig,
+    workspace: &Path,
+    stderr_log: &Arc<ServerLog>,
+    metrics: &Arc<Metrics>,
+) -> Result<Arc<LspBridge>> {
+    let mut bridge =
+        LspBridge::from_config_with_log(server, workspace.to_path_buf(), stderr_log.clone())
+            .await?
+            .with_metrics(metrics.clone());
+    bridge.initialize().await?;
+    Ok(Arc::new(bridge))
+}
+
+/// Waits for the bridge's server to exit and triggers recovery.
+///
+/// Holds neither the bridge nor the supervisor strongly, so the task ends quietly
+/// when either is dropped (e.g. on shutdown or after a swap).
+fn spawn_monitor(supervisor: Weak<LspSupervisor>, bridge: &Arc<LspBridge>) {
+    let mut exited = bridge.watch_exit();
+    let history = bridge.history().clone();
+    tokio::spawn(async move {
+        if exited.wait_for(|exited| *exited).await.is_err() {
+            return;
+        }
+        let Some(strong) = supervisor.upgrade() else {
+            return;
+        };
+        // Not running means a manual restart stopped this server on purpose
+        if strong.is_shutting_down() || strong.restart_state() != RestartState::Running {
+            return;
+        }
+        let message = history.annotate("language server exited unexpectedly");
+        tracing::warn!("{message}; restarting");
+        strong.health.record_error(message);
+        strong.state.send_replace(RestartState::Restarting);
+        drop(strong);
+        LspSupervisor::recover(&supervisor).await;
+    });
+}
+
+/// Periodically probes the current server and records the outcome.
+///
+/// Servers that have already exited are skipped; the crash monitor handles those.
+fn spawn_health_checks(supervisor: Weak<LspSupervisor>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(HEALTH_CHECK_INTERVAL);
+        // The first tick fires immediately, right after initialize; skip it.
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let Some(lsp) = supervisor.upgrade().map(|supervisor| supervisor.bridge()) else {
+                return;
+            };
+            if lsp.has_exited() {
+                continue;
+            }
+            let outcome = probe(&lsp).await;
+            match supervisor.upgrade() {
+                Some(supervisor) => supervisor.health.record_probe(&outcome),
+                None => return,
+            }
+        }
+    });
+}
+
+/// Periodically closes documents that have been idle for longer than `timeout`.
+fn spawn_idle_eviction(supervisor: Weak<LspSupervisor>, timeout: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(timeout.min(MAX_EVICTION_INTERVAL));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let Some(supervisor) = supervisor.upgrade() else {
+                return;
+            };
+            let lsp = supervisor.bridge();
+            if lsp.has_exited() {
+                continue;
+            }
+            let evicted = supervisor.documents.evict_idle(&lsp).await;
+            if evicted > 0 {
+                tracing::debug!(evicted, "Closed idle documents");
+            }
+        }
+    });
+}
+
+/// Opens the configured warm-up documents in the background.
+///
+/// Documents are opened one at a time; tool calls on other documents proceed in
+/// between.
+fn spawn_warmup(supervisor: Weak<LspSupervisor>) {
+    tokio::spawn(async move {
+        let Some(strong) = supervisor.upgrade() else {
+            return;
+        };
+        let root = strong.workspace.clone();
+        let server = strong.config();
+        let config = server.warmup.clone();
+        let extensions = server.extensions.clone();
+        let limit = server.documents.max_open;
+        drop(strong);
+
+        let selected = tokio::task::spawn_blocking(move || {
+            warmup::select_files(&root, &config, &extensions, limit)
+        })
+        .await;
+        let files = match selected {
+            Ok(Ok(files)) => files,
+            Ok(Err(err)) => {
+                tracing::warn!(?err, "Failed to select warm-up documents");
+                return;
+            }
+            Err(err) => {
+                tracing::warn!(?err, "Warm-up task failed");
+                return;
+            }
+        };
+
+        let mut opened = 0;
+        for path in files {
+            let Some(supervisor) = supervisor.upgrade() else {
+                return;
+            };
+            let Ok(uri) = path_to_uri(&path) else {
+                continue;
+            };
+            let lsp = supervisor.bridge();
+            match supervisor.documents.ensure_open(&lsp, &uri).await {
+                Ok(()) => opened += 1,
+                Err(err) => tracing::debug!(?err, %uri, "Failed to open warm-up document"),
+            }
+        }
+        tracing::info!(opened, "Opened warm-up documents");
+    });
+}
+
+/// Builds the symbol index in the background.
+fn spawn_symbol_index(supervisor: &Arc<LspSupervisor>) {
+    let index = supervisor.symbols.clone();
+    let root = supervisor.workspace.clone();
+    let max_files = supervisor.config().index.max_files;
+    tokio::task::spawn_blocking(move || {
+        let started = Instant::now();
+        match index.build(&root, max_files) {
+            Ok(()) => tracing::info!(
+                files = index.file_count(),
+                elapsed_ms = started.elapsed().as_millis() as u64,
+                "Built symbol index"
+            ),
+            Err(err) => tracing::warn!(?err, "Failed to build the symbol index"),
+        }
+    });
+}
+
+/// Applies batches of workspace file changes to the current server.
+///
+/// Open documents are resynced (or closed when deleted) first; the changes matching
+/// the server's watcher registrations are then sent as `didChangeWatchedFiles`. The
+/// symbol index, if enabled, re-extracts the changed files.
+fn spawn_file_watcher(supervisor: Weak<LspSupervisor>, mut watcher: FileWatcher) {
+    tokio::spawn(async move {
+        let debounce = match supervisor.upgrade() {
+            Some(supervisor) => supervisor.config().watch.debounce(),
+            None => return,
+        };
+        while let Some(changes) = watcher.next_batch(debounce).await {
+            let Some(supervisor) = supervisor.upgrade() else {
+                return;
+            };
+            if supervisor.config().index.enabled {
+                let index = supervisor.symbols.clone();
+                let paths: Vec<PathBuf> =
+                    changes.iter().map(|change| change.path.clone()).collect();
+                tokio::task::spawn_blocking(move || {
+                    for path in paths {
+                        index.refresh(&path);
+                    }
+                });
+            }
+            let lsp = supervisor.bridge();
+            if lsp.has_exited() {
+                continue;
+            }
+
+            for uri in changes.iter().filter_map(FileChange::uri) {
+                if let Err(err) = supervisor.documents.resync(&lsp, &uri).await {
+                    tracing::debug!(?err, %uri, "Failed to resync changed document");
+                }
+            }
+
+            let events: Vec<Value> = changes
+                .iter()
+                .filter(|change| lsp.watched_files().matches(change))
+                .filter_map(FileChange::to_lsp)
+                .collect();
+            if events.is_empty() {
+                continue;
+            }
+            tracing::debug!(changes = events.len(), "Sending watched file changes");
+            let params = json!({ "changes": events });
+            if let Err(err) = lsp.notify("workspace/didChangeWatchedFiles", params).await {
+                tracing::warn!(?err, "Failed to send watched file changes");
+            }
+        }
+    });
+}
+
+/// Resyncs open documents after the server applies a workspace edit through us.
+///
+/// Holds the bridge weakly so the task ends once the bridge is replaced or dropped.
+fn spawn_edit_resync(lsp: &Arc<LspBridge>, documents: &Arc<DocumentManager>) {
+    let mut applied = lsp.subscribe_applied_edits();
+    let lsp = Arc::downgrade(lsp);
+    let documents = documents.clone();
+    tokio::spawn(async move {
+        loop {
+            let uris = match applied.recv().await {
+                Ok(uris) => uris,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!(
+                        skipped,
+                        "Missed applied-edit batches; documents resync lazily"
+                    );
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            let Some(lsp) = lsp.upgrade() else {
+                break;
+            };
+            for uri in uris {
+                if let Err(err) = documents.resync(&lsp, &uri).await {
+                    tracing::warn!(?err, %uri, "Failed to resync edited document");
+                }
+            }
+        }
+    });
+}
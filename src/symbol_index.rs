@@ -0,0 +1,219 @@
+//! Pathfinder's own workspace symbol index, for fast fuzzy `find_symbol` queries.
+//!
+//! Many servers answer `workspace/symbol` slowly, only for files they have opened, or
+//! not at all. With `index.enabled` in `[server]`, the supervisor walks the workspace
+//! in the background after startup and extracts the declarations of every file with
+//! the [`crate::tags`] patterns, then re-extracts the files the watcher reports
+//! changed. Queries are answered from memory and never reach the server; while the
+//! first walk is still running they see the files indexed so far.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::Result;
+
+use crate::position::PositionEncoding;
+use crate::tags::{self, Tag};
+use crate::utils::walk_source_files;
+
+/// Bonus for matching the whole name, ignoring case.
+const EXACT_BONUS: i64 = 100;
+/// Bonus for matching a prefix of the name.
+const PREFIX_BONUS: i64 = 50;
+/// Bonus for a query character matching right after the previous one.
+const CONSECUTIVE_BONUS: i64 = 5;
+/// Bonus for a query character matching at the start of a word: the name's start,
+/// after `_` or another separator, or a camelCase hump.
+const BOUNDARY_BONUS: i64 = 8;
+
+/// Declarations by file, in the column encoding they were extracted with.
+pub struct SymbolIndex {
+    encoding: PositionEncoding,
+    files: RwLock<HashMap<PathBuf, Vec<Tag>>>,
+    ready: AtomicBool,
+}
+
+/// A declaration matching a query, with its score; higher is better.
+#[derive(Debug, Clone)]
+pub struct Scored {
+    pub tag: Tag,
+    pub score: i64,
+}
+
+impl SymbolIndex {
+    pub fn new(encoding: PositionEncoding) -> Self {
+        Self {
+            encoding,
+            files: RwLock::new(HashMap::new()),
+            ready: AtomicBool::new(false),
+        }
+    }
+
+    /// Walks the workspace under `root` and indexes up to `max_files` files. Blocks;
+    /// run it on the blocking pool.
+    pub fn build(&self, root: &Path, max_files: usize) -> Result<()> {
+        walk_source_files(root, max_files, |entry| self.refresh(&entry.path()))?;
+        self.ready.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Re-extracts the declarations of `path`, or forgets it if it is gone or no
+    /// longer taggable. Blocks.
+    pub fn refresh(&self, path: &Path) {
+        let extension = path.extension().and_then(|ext| ext.to_str());
+        let tags = extension.filter(|ext| tags::supports(ext)).and_then(|ext| {
+            Some(tags::extract(
+                path,
+                ext,
+                &tags::read_taggable(path)?,
+                self.encoding,
+            ))
+        });
+        let mut files = self.files.write().expect("symbol index lock poisoned");
+        match tags {
+            Some(tags) if !tags.is_empty() => {
+                files.insert(path.to_path_buf(), tags);
+            }
+            _ => {
+                files.remove(path);
+            }
+        }
+    }
+
+    /// Whether the first walk of the workspace has finished.
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Ordering::Acquire)
+    }
+
+    /// Files with at least one declaration indexed.
+    pub fn file_count(&self) -> usize {
+        self.files.read().expect("symbol index lock poisoned").len()
+    }
+
+    /// Declarations whose name fuzzily matches `query` (see [`fuzzy_score`]), of
+    /// `kind` if given, best first; at most `limit`. The flag is true if more matched.
+    pub fn search(&self, query: &str, kind: Option<&str>, limit: usize) -> (Vec<Scored>, bool) {
+        let files = self.files.read().expect("symbol index lock poisoned");
+        let mut found: Vec<Scored> = files
+            .values()
+            .flatten()
+            .filter(|tag| kind.is_none_or(|kind| tag.kind == kind))
+            .filter_map(|tag| {
+                Some(Scored {
+                    score: fuzzy_score(query, &tag.name)?,
+                    tag: tag.clone(),
+                })
+            })
+            .collect();
+        drop(files);
+        found.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.tag.name.len().cmp(&b.tag.name.len()))
+                .then_with(|| (&a.tag.path, a.tag.line).cmp(&(&b.tag.path, b.tag.line)))
+        });
+        let truncated = found.len() > limit;
+        found.truncate(limit);
+        (found, truncated)
+    }
+}
+
+/// How well `name` matches `query`, ignoring case, or `None` if the query's
+/// characters do not all appear in the name in order. Consecutive characters,
+/// characters at word starts, prefixes, and short names score higher, so `hreq` ranks
+/// `handle_request` above `hash_required_queue`.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<i64> {
+    let lower = |ch: char| ch.to_lowercase().next().unwrap_or(ch);
+    let query: Vec<char> = query.chars().map(lower).collect();
+    let chars: Vec<char> = name.chars().collect();
+    let mut score = 0;
+    let mut matched = 0;
+    let mut previous = None;
+    for (index, &ch) in chars.iter().enumerate() {
+        if matched == query.len() {
+            break;
+        }
+        if lower(ch) != query[matched] {
+            continue;
+        }
+        score += 1;
+        if index > 0 && previous == Some(index - 1) {
+            score += CONSECUTIVE_BONUS;
+        }
+        if is_word_start(&chars, index) {
+            score += BOUNDARY_BONUS;
+        }
+        previous = Some(index);
+        matched += 1;
+    }
+    if matched < query.len() {
+        return None;
+    }
+    let name: Vec<char> = chars.iter().copied().map(lower).collect();
+    if name == query {
+        score += EXACT_BONUS;
+    } else if name.starts_with(&query) {
+        score += PREFIX_BONUS;
+    }
+    Some(score - (chars.len() - query.len()) as i64)
+}
+
+fn is_word_start(chars: &[char], index: usize) -> bool {
+    let Some(&previous) = index.checked_sub(1).and_then(|before| chars.get(before)) else {
+        return true;
+    };
+    !previous.is_alphanumeric() || (previous.is_lowercase() && chars[index].is_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranks_exact_prefix_and_abbreviated_matches() {
+        let score = |query, name| fuzzy_score(query, name);
+        assert!(score("hreq", "handle_request") > score("hreq", "hash_required_queue"));
+        assert!(score("config", "Config") > score("config", "ConfigLoader"));
+        assert!(score("config", "ConfigLoader") > score("config", "load_config"));
+        assert!(score("pcfg", "parseConfig").is_some());
+        assert_eq!(score("xyz", "handle_request"), None);
+        assert_eq!(score("reqh", "handle_request"), None);
+    }
+
+    #[test]
+    fn indexes_the_workspace_and_follows_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::write(
+            root.join("server.py"),
+            "class RequestHandler:\n    def handle_request(self):\n        pass\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("notes.txt"), "def handle_request():\n").unwrap();
+        let index = SymbolIndex::new(PositionEncoding::Utf16);
+        index.build(&root, 100).unwrap();
+        assert!(index.is_ready());
+        assert_eq!(index.file_count(), 1);
+
+        let (found, truncated) = index.search("hreq", None, 10);
+        assert!(!truncated);
+        assert_eq!(found[0].tag.name, "handle_request");
+        assert_eq!((found[0].tag.line, found[0].tag.start_character), (1, 8));
+        let (classes, _) = index.search("req", Some("class"), 10);
+        assert_eq!(classes.len(), 1);
+        assert_eq!(classes[0].tag.name, "RequestHandler");
+        let (_, truncated) = index.search("r", None, 1);
+        assert!(truncated);
+
+        std::fs::write(root.join("server.py"), "def serve():\n    pass\n").unwrap();
+        index.refresh(&root.join("server.py"));
+        assert!(index.search("hreq", None, 10).0.is_empty());
+        assert_eq!(index.search("serve", None, 10).0.len(), 1);
+
+        std::fs::remove_file(root.join("server.py")).unwrap();
+        index.refresh(&root.join("server.py"));
+        assert_eq!(index.file_count(), 0);
+    }
+}
@@ -0,0 +1,512 @@
+//! Built-in ctags-style symbol extraction, the fallback when the server has no answer.
+//!
+//! For documents the language server is not configured for, or positions it finds no
+//! definition at, `definition` (and the `symbol://` resource) fall back to scanning
+//! the workspace with per-language patterns for declarations: functions, types,
+//! constants, modules. Matching is by name only, so results are best effort: every
+//! declaration of the name is a candidate, and declarations the patterns do not
+//! recognize are missed. Responses built from tags say so with `fallback: true`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result, anyhow};
+use regex::Regex;
+use url::Url;
+
+use crate::encoding;
+use crate::position::{PositionEncoding, byte_to_column, column_to_byte};
+use crate::tools::locations::{DefinitionTarget, TextRange};
+use crate::utils::{uri_to_file_path, walk_source_files};
+
+/// Files scanned per query at most.
+const MAX_TAGGED_FILES: usize = 20_000;
+
+/// Larger files are skipped; they are almost always generated.
+const MAX_TAGGED_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Optional visibility prefix of Rust items.
+const RUST_ITEM: &str = r"^\s*(?:pub(?:\([^)]*\))?\s+)?";
+
+/// Optional `export`/`default` prefix of JavaScript and TypeScript declarations.
+const JS_DECLARATION: &str = r"^\s*(?:export\s+)?(?:default\s+)?(?:declare\s+)?";
+
+/// Java modifiers in any order.
+const JAVA_MODIFIERS: &str =
+    r"^\s*(?:(?:public|protected|private|static|abstract|final|sealed|synchronized|native)\s+)*";
+
+/// Declaration patterns of one language; each captures the declared name as `name`.
+struct Language {
+    extensions: &'static [&'static str],
+    patterns: Vec<(&'static str, Regex)>,
+}
+
+impl Language {
+    fn new(extensions: &'static [&'static str], patterns: &[(&'static str, String)]) -> Self {
+        Self {
+            extensions,
+            patterns: patterns
+                .iter()
+                .map(|(kind, pattern)| (*kind, Regex::new(pattern).expect("valid tag pattern")))
+                .collect(),
+        }
+    }
+}
+
+static LANGUAGES: LazyLock<Vec<Language>> = LazyLock::new(|| {
+    vec![
+        Language::new(
+            &["rs"],
+            &[
+                (
+                    "function",
+                    format!(
+                        r#"{RUST_ITEM}(?:default\s+)?(?:const\s+)?(?:async\s+)?(?:unsafe\s+)?(?:extern\s+"[^"]*"\s+)?fn\s+(?P<name>\w+)"#
+                    ),
+                ),
+                ("struct", format!(r"{RUST_ITEM}(?:struct|union)\s+(?P<name>\w+)")),
+                ("enum", format!(r"{RUST_ITEM}enum\s+(?P<name>\w+)")),
+                (
+                    "interface",
+                    format!(r"{RUST_ITEM}(?:unsafe\s+)?(?:auto\s+)?trait\s+(?P<name>\w+)"),
+                ),
+                ("type", format!(r"{RUST_ITEM}type\s+(?P<name>\w+)")),
+                (
+                    "constant",
+                    format!(r"{RUST_ITEM}(?:const|static(?:\s+mut)?)\s+(?P<name>\w+)\s*:"),
+                ),
+                ("module", format!(r"{RUST_ITEM}mod\s+(?P<name>\w+)")),
+                ("macro", r"^\s*macro_rules!\s*(?P<name>\w+)".to_string()),
+            ],
+        ),
+        Language::new(
+            &["py", "pyi"],
+            &[
+                (
+                    "function",
+                    r"^\s*(?:async\s+)?def\s+(?P<name>\w+)".to_string(),
+                ),
+                ("class", r"^\s*class\s+(?P<name>\w+)".to_string()),
+                (
+                    "constant",
+                    r"^(?P<name>[A-Z_][A-Z0-9_]*)\s*(?::[^=]*)?=[^=]".to_string(),
+                ),
+            ],
+        ),
+        Language::new(
+            &["go"],
+            &[
+                (
+                    "function",
+                    r"^func\s+(?:\([^)]*\)\s*)?(?P<name>\w+)".to_string(),
+                ),
+                ("struct", r"^\s*type\s+(?P<name>\w+)\s+struct\b".to_string()),
+                (
+                    "interface",
+                    r"^\s*type\s+(?P<name>\w+)\s+interface\b".to_string(),
+                ),
+                (
+                    "type",
+                    r"^\s*type\s+(?P<name>\w+)\s+[^si\s]".to_string(),
+                ),
+                ("constant", r"^(?:const|var)\s+(?P<name>\w+)".to_string()),
+            ],
+        ),
+        Language::new(
+            &["js", "jsx", "mjs", "cjs", "ts", "tsx", "mts", "cts"],
+            &[
+                (
+                    "function",
+                    format!(r"{JS_DECLARATION}(?:async\s+)?function\s*\*?\s*(?P<name>\w+)"),
+                ),
+                (
+                    "class",
+                    format!(r"{JS_DECLARATION}(?:abstract\s+)?class\s+(?P<name>\w+)"),
+                ),
+                ("interface", format!(r"{JS_DECLARATION}interface\s+(?P<name>\w+)")),
+                (
+                    "enum",
+                    format!(r"{JS_DECLARATION}(?:const\s+)?enum\s+(?P<name>\w+)"),
+                ),
+                ("type", format!(r"{JS_DECLARATION}type\s+(?P<name>\w+)\s*(?:<[^>]*>)?\s*=")),
+                (
+                    "variable",
+                    r"^(?:export\s+)?(?:const|let|var)\s+(?P<name>\w+)\s*(?::[^=]*)?=".to_string(),
+                ),
+            ],
+        ),
+        Language::new(
+            &["c", "h", "cc", "cpp", "cxx", "hpp", "hh", "hxx"],
+            &[
+                ("macro", r"^\s*#\s*define\s+(?P<name>\w+)".to_string()),
+                (
+                    "struct",
+                    r"^\s*(?:typedef\s+)?(?:struct|union)\s+(?P<name>\w+)\s*(?:\{|$)".to_string(),
+                ),
+                (
+                    "enum",
+                    r"^\s*(?:typedef\s+)?enum(?:\s+class)?\s+(?P<name>\w+)".to_string(),
+                ),
+                (
+                    "class",
+                    r"^\s*(?:template\s*<[^>]*>\s*)?class\s+(?P<name>\w+)\s*(?:final\s*)?(?:[:{]|$)"
+                        .to_string(),
+                ),
+                ("type", r"^\s*typedef\b.*\b(?P<name>\w+)\s*;".to_string()),
+                // A definition starts in the first column and does not end in `;`
+                (
+                    "function",
+                    r"^[A-Za-z_][\w\s\*&<>,:]*?[\s\*&]+(?:\w+::)*(?P<name>~?\w+)\s*\([^;]*$"
+                        .to_string(),
+                ),
+            ],
+        ),
+        Language::new(
+            &["java"],
+            &[
+                (
+                    "class",
+                    format!(r"{JAVA_MODIFIERS}(?:class|record)\s+(?P<name>\w+)"),
+                ),
+                (
+                    "interface",
+                    format!(r"{JAVA_MODIFIERS}@?interface\s+(?P<name>\w+)"),
+                ),
+                ("enum", format!(r"{JAVA_MODIFIERS}enum\s+(?P<name>\w+)")),
+                (
+                    "method",
+                    r"^\s+(?:(?:public|protected|private|static|final|abstract|synchronized|native)\s+)+[\w<>\[\],\s]*?\s(?P<name>\w+)\s*\("
+                        .to_string(),
+                ),
+            ],
+        ),
+        Language::new(
+            &["rb"],
+            &[
+                (
+                    "method",
+                    r"^\s*def\s+(?:self\.)?(?P<name>\w+[?!=]?)".to_string(),
+                ),
+                ("class", r"^\s*class\s+(?:\w+::)*(?P<name>\w+)".to_string()),
+                ("module", r"^\s*module\s+(?:\w+::)*(?P<name>\w+)".to_string()),
+            ],
+        ),
+    ]
+});
+
+/// Names C-like function patterns match that are statements, not declarations.
+const NOT_FUNCTIONS: &[&str] = &["if", "for", "while", "switch", "return", "sizeof", "else"];
+
+/// A declaration found by the patterns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: String,
+    /// `function`, `struct`, `class`, ... in the vocabulary of LSP symbol kinds, plus
+    /// `type` and `macro`.
+    pub kind: &'static str,
+    pub path: PathBuf,
+    /// Zero-based line of the name.
+    pub line: u32,
+    /// Columns of the name, in the encoding the tags were extracted with.
+    pub start_character: u32,
+    pub end_character: u32,
+}
+
+impl Tag {
+    pub fn uri(&self) -> String {
+        Url::from_file_path(&self.path)
+            .map(String::from)
+            .unwrap_or_else(|()| self.path.display().to_string())
+    }
+
+    pub fn target(&self) -> DefinitionTarget {
+        DefinitionTarget::new(
+            self.uri(),
+            TextRange {
+                start_line: self.line,
+                start_character: self.start_character,
+                end_line: self.line,
+                end_character: self.end_character,
+            },
+        )
+    }
+}
+
+/// Whether tags can be extracted from files with `extension`.
+pub fn supports(extension: &str) -> bool {
+    language(extension).is_some()
+}
+
+fn language(extension: &str) -> Option<&'static Language> {
+    LANGUAGES
+        .iter()
+        .find(|language| language.extensions.contains(&extension))
+}
+
+/// The declarations in `text`, a file with `extension`.
+pub fn extract(path: &Path, extension: &str, text: &str, encoding: PositionEncoding) -> Vec<Tag> {
+    let Some(language) = language(extension) else {
+        return Vec::new();
+    };
+    let mut tags = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        for (kind, pattern) in &language.patterns {
+            let Some(name) = pattern.captures(line).and_then(|found| found.name("name")) else {
+                continue;
+            };
+            if NOT_FUNCTIONS.contains(&name.as_str()) {
+                continue;
+            }
+            tags.push(Tag {
+                name: name.as_str().to_string(),
+                kind,
+                path: path.to_path_buf(),
+                line: number as u32,
+                start_character: byte_to_column(line, name.start(), encoding),
+                end_character: byte_to_column(line, name.end(), encoding),
+            });
+            // The first matching pattern decides the kind
+            break;
+        }
+    }
+    tags
+}
+
+/// Declarations in the workspace under `root` whose name `wanted` accepts. `overlays`
+/// (text by file:// URI) stand in for the files on disk.
+pub fn scan(
+    root: &Path,
+    encoding: PositionEncoding,
+    overlays: &HashMap<String, String>,
+    wanted: impl Fn(&str) -> bool,
+) -> Result<Vec<Tag>> {
+    let overlays: HashMap<PathBuf, &str> = overlays
+        .iter()
+        .filter_map(|(uri, text)| Some((uri_to_file_path(uri).ok()?, text.as_str())))
+        .collect();
+    let mut tags = Vec::new();
+    walk_source_files(root, MAX_TAGGED_FILES, |entry| {
+        let path = entry.path();
+        let Some(extension) = path.extension().and_then(|ext| ext.to_str()) else {
+            return;
+        };
+        if !supports(extension) {
+            return;
+        }
+        let text = match overlays.get(&path) {
+            Some(text) => text.to_string(),
+            None => match read_taggable(&path) {
+                Some(text) => text,
+                None => return,
+            },
+        };
+        tags.extend(
+            extract(&path, extension, &text, encoding)
+                .into_iter()
+                .filter(|tag| wanted(&tag.name)),
+        );
+    })?;
+    tags.sort_by(|a, b| (&a.path, a.line).cmp(&(&b.path, b.line)));
+    Ok(tags)
+}
+
+/// Declarations of the identifier at `line`/`character` of `uri` (a column in
+/// `encoding`), the ones in `uri` itself first.
+pub async fn definitions(
+    root: &Path,
+    encoding: PositionEncoding,
+    uri: &str,
+    line: u32,
+    character: u32,
+    overlays: HashMap<String, String>,
+) -> Result<Vec<DefinitionTarget>> {
+    let path = uri_to_file_path(uri)?;
+    let text = match overlays.get(uri) {
+        Some(text) => text.clone(),
+        None => encoding::read_file(&path).await?.text,
+    };
+    let line_text = text
+        .lines()
+        .nth(line as usize)
+        .ok_or_else(|| anyhow!("line {line} is past the end of {}", path.display()))?;
+    let Some(name) = identifier_at(line_text, column_to_byte(line_text, character, encoding))
+    else {
+        return Ok(Vec::new());
+    };
+    let name = name.to_string();
+    let root = root.to_path_buf();
+    let mut tags = tokio::task::spawn_blocking(move || {
+        scan(&root, encoding, &overlays, |found| found == name)
+    })
+    .await
+    .context("tag scan failed")??;
+    tags.sort_by_key(|tag| tag.path != path);
+    Ok(tags.iter().map(Tag::target).collect())
+}
+
+/// Declarations whose name contains `query`, ignoring case, best matches first: exact
+/// names, then prefixes, then the rest. At most `limit`.
+pub async fn symbols(
+    root: &Path,
+    encoding: PositionEncoding,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<Tag>> {
+    let query = query.to_lowercase();
+    let root = root.to_path_buf();
+    let overlays = HashMap::new();
+    let wanted = query.clone();
+    let mut tags = tokio::task::spawn_blocking(move || {
+        scan(&root, encoding, &overlays, |name| {
+            name.to_lowercase().contains(&wanted)
+        })
+    })
+    .await
+    .context("tag scan failed")??;
+    tags.sort_by_key(|tag| {
+        let name = tag.name.to_lowercase();
+        if name == query {
+            0
+        } else if name.starts_with(&query) {
+            1
+        } else {
+            2
+        }
+    });
+    tags.truncate(limit);
+    Ok(tags)
+}
+
+/// The identifier `byte` is on in `line`.
+fn identifier_at(line: &str, byte: usize) -> Option<&str> {
+    let is_identifier = |ch: char| ch.is_alphanumeric() || ch == '_';
+    let start = line[..byte]
+        .char_indices()
+        .rev()
+        .take_while(|(_, ch)| is_identifier(*ch))
+        .last()
+        .map_or(byte, |(offset, _)| offset);
+    let end = line[byte..]
+        .char_indices()
+        .find(|(_, ch)| !is_identifier(*ch))
+        .map_or(line.len(), |(offset, _)| byte + offset);
+    (start < end).then(|| &line[start..end])
+}
+
+/// The file's text, decoded if it is not UTF-8, or `None` if it is too large or
+/// binary.
+pub(crate) fn read_taggable(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if metadata.len() > MAX_TAGGED_FILE_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    encoding::decode_text(&bytes).map(|decoded| decoded.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(extension: &str, text: &str) -> Vec<(String, &'static str, u32, u32)> {
+        extract(
+            Path::new("/src/file"),
+            extension,
+            text,
+            PositionEncoding::Utf16,
+        )
+        .into_iter()
+        .map(|tag| (tag.name, tag.kind, tag.line, tag.start_character))
+        .collect()
+    }
+
+    #[test]
+    fn extracts_declarations_per_language() {
+        let rust = "pub(crate) async fn load() {}\nstruct Config;\n    let x = 1;\nimpl Foo for Bar {}\nconst LIMIT: usize = 3;\nmacro_rules! ensure {";
+        assert_eq!(
+            tags("rs", rust),
+            [
+                ("load".to_string(), "function", 0, 20),
+                ("Config".to_string(), "struct", 1, 7),
+                ("LIMIT".to_string(), "constant", 4, 6),
+                ("ensure".to_string(), "macro", 5, 13),
+            ]
+        );
+        let python =
+            "class Greeter:\n    async def greet(self):\n        if x == 1:\nMAX_SIZE = 10\n";
+        assert_eq!(
+            tags("py", python),
+            [
+                ("Greeter".to_string(), "class", 0, 6),
+                ("greet".to_string(), "function", 1, 14),
+                ("MAX_SIZE".to_string(), "constant", 3, 0),
+            ]
+        );
+        let c = "#define MAX 3\nstatic int add(int a, int b)\n{\n    if (a) {\n    return add(a, b);\nint sub(int a);\n";
+        assert_eq!(
+            tags("c", c),
+            [
+                ("MAX".to_string(), "macro", 0, 8),
+                ("add".to_string(), "function", 1, 11),
+            ]
+        );
+        assert!(tags("txt", "fn main() {}").is_empty());
+    }
+
+    #[test]
+    fn finds_the_identifier_under_a_column() {
+        assert_eq!(identifier_at("let sum = add(1, 2);", 11), Some("add"));
+        assert_eq!(identifier_at("let sum = add(1, 2);", 13), Some("add"));
+        assert_eq!(identifier_at("let sum = add(1, 2);", 9), None);
+        assert_eq!(identifier_at("größe", 0), Some("größe"));
+    }
+
+    #[tokio::test]
+    async fn resolves_definitions_across_the_workspace() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("lib")).unwrap();
+        std::fs::write(
+            root.join("main.go"),
+            "package main\n\nfunc main() {\n\tgreet()\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            root.join("lib/greet.go"),
+            "package main\n\nfunc greet() {}\n",
+        )
+        .unwrap();
+        let uri = Url::from_file_path(root.join("main.go"))
+            .unwrap()
+            .to_string();
+
+        let found = definitions(&root, PositionEncoding::Utf16, &uri, 3, 2, HashMap::new())
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 1);
+        assert!(found[0].uri.ends_with("/lib/greet.go"));
+        assert_eq!(
+            (found[0].range.start_line, found[0].range.start_character),
+            (2, 5)
+        );
+
+        // Overlays are scanned instead of the file on disk
+        let overlays =
+            HashMap::from([(uri.clone(), "func main() {}\nfunc greet() {}\n".to_string())]);
+        let found = definitions(&root, PositionEncoding::Utf16, &uri, 1, 6, overlays)
+            .await
+            .unwrap();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].uri, uri);
+
+        let symbols = symbols(&root, PositionEncoding::Utf16, "GRE", 10)
+            .await
+            .unwrap();
+        assert_eq!(symbols.len(), 1);
+        assert_eq!(
+            (symbols[0].name.as_str(), symbols[0].kind),
+            ("greet", "function")
+        );
+    }
+}
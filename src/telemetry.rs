@@ -0,0 +1,115 @@
+//! Optional OpenTelemetry trace export.
+//!
+//! Tool calls, document syncs, and LSP requests run in nested `tracing` spans
+//! (`tool_call`, `document_sync`, `lsp_request`) carrying the MCP and LSP request ids,
+//! so a tool call's trace shows where its time went. When pathfinder is built with the
+//! `otel` feature and started with `OTEL_EXPORTER_OTLP_ENDPOINT` (or
+//! `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`) set, those spans are exported over OTLP/HTTP;
+//! the other standard `OTEL_*` variables configure the exporter as usual.
+
+use anyhow::Result;
+use tracing::Subscriber;
+use tracing_subscriber::Layer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// Whether this build can export traces.
+pub const AVAILABLE: bool = cfg!(feature = "otel");
+
+/// Variables that turn export on.
+const ENDPOINT_VARS: &[&str] = &[
+    "OTEL_EXPORTER_OTLP_ENDPOINT",
+    "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT",
+];
+
+/// Whether the environment asks for trace export.
+pub fn requested() -> bool {
+    ENDPOINT_VARS
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|value| !value.is_empty()))
+}
+
+/// The layer exporting spans, boxed to fit any subscriber.
+pub type ExportLayer<S> = Box<dyn Layer<S> + Send + Sync>;
+
+/// Handle on the exporter, used to flush pending spans before exiting.
+#[derive(Default)]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Telemetry {
+    /// Sets up export if [`requested`] and [`AVAILABLE`], returning the layer to add
+    /// to the subscriber.
+    pub fn init<S>() -> Result<(Option<ExportLayer<S>>, Self)>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+    {
+        if !requested() {
+            return Ok((None, Self::default()));
+        }
+        #[cfg(feature = "otel")]
+        {
+            let (layer, provider) = otlp::layer()?;
+            Ok((
+                Some(layer),
+                Self {
+                    provider: Some(provider),
+                },
+            ))
+        }
+        #[cfg(not(feature = "otel"))]
+        {
+            Ok((None, Self::default()))
+        }
+    }
+
+    /// Exports spans still buffered. Safe to call when export is off.
+    pub fn shutdown(&self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = &self.provider
+            && let Err(err) = provider.shutdown()
+        {
+            tracing::warn!(?err, "Failed to flush OpenTelemetry spans");
+        }
+    }
+}
+
+#[cfg(feature = "otel")]
+mod otlp {
+    use anyhow::{Context, Result};
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_sdk::Resource;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use tracing::{Level, Subscriber};
+    use tracing_subscriber::Layer;
+    use tracing_subscriber::filter::Targets;
+    use tracing_subscriber::registry::LookupSpan;
+
+    use super::ExportLayer;
+
+    /// A layer exporting pathfinder's own spans, and the provider owning the exporter.
+    pub fn layer<S>() -> Result<(ExportLayer<S>, SdkTracerProvider)>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span> + Send + Sync,
+    {
+        let exporter = opentelemetry_otlp::SpanExporter::builder()
+            .with_http()
+            .build()
+            .context("failed to set up the OTLP span exporter")?;
+        let mut resource = Resource::builder();
+        if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+            resource = resource.with_service_name("pathfinder");
+        }
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource.build())
+            .build();
+        let layer = tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer("pathfinder"))
+            // Spans of rmcp, hyper, and friends would only add noise
+            .with_filter(Targets::new().with_target("pathfinder", Level::INFO))
+            .boxed();
+        Ok((layer, provider))
+    }
+}
@@ -0,0 +1,277 @@
+//! A scriptable in-process mock language server.
+//!
+//! [`MockServer`] speaks LSP over the same framed transport as a real server, on an
+//! in-memory stream, so tests and local experiments need no language server
+//! installed. Out of the box it answers `initialize` (advertising definition and
+//! references), `shutdown`, `textDocument/definition` (the first character of the
+//! requested document), and `textDocument/references` (the requested position and
+//! the first character), and publishes one warning for every opened or changed
+//! document. Each of these can be replaced, and other methods scripted:
+//!
+//! ```no_run
+//! # use pathfinder::testing::MockServer;
+//! # use serde_json::json;
+//! let server = MockServer::new()
+//!     .respond("textDocument/hover", json!({"contents": "fn add(a: i32, b: i32) -> i32"}))
+//!     .diagnostics(Vec::new());
+//! ```
+//!
+//! `connect = "mock"` in the server config (or `--mock` on the command line) runs
+//! pathfinder against the default mock.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::{Value, json};
+use tokio::io::DuplexStream;
+
+use crate::transport::FramedTransport;
+
+/// Buffer between the bridge and the mock.
+const MOCK_BUFFER: usize = 64 * 1024;
+
+/// JSON-RPC error code for requests the mock has no answer for.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Computes the result (or the JSON-RPC error object) for a request's params.
+type Handler = Arc<dyn Fn(&Value) -> Result<Value, Value> + Send + Sync>;
+
+#[derive(Clone)]
+pub struct MockServer {
+    capabilities: Value,
+    handlers: HashMap<String, Handler>,
+    diagnostics: Vec<Value>,
+}
+
+impl Default for MockServer {
+    fn default() -> Self {
+        Self {
+            capabilities: json!({
+                "textDocumentSync": 1,
+                "definitionProvider": true,
+                "referencesProvider": true,
+            }),
+            handlers: HashMap::new(),
+            diagnostics: vec![json!({
+                "range": range_at(0, 0),
+                "severity": 2,
+                "source": "mock",
+                "message": "mock diagnostic",
+            })],
+        }
+        .respond("shutdown", Value::Null)
+        .respond_with(
+            "textDocument/definition",
+            |params| json!([{ "uri": params["textDocument"]["uri"], "range": range_at(0, 0) }]),
+        )
+        .respond_with("textDocument/references", |params| {
+            let position = &params["position"];
+            json!([
+                {
+                    "uri": params["textDocument"]["uri"],
+                    "range": { "start": position, "end": position },
+                },
+                { "uri": params["textDocument"]["uri"], "range": range_at(0, 0) },
+            ])
+        })
+    }
+}
+
+impl MockServer {
+    /// A mock with the default answers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Capabilities returned from `initialize`.
+    pub fn capabilities(mut self, capabilities: Value) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Answers every `method` request with `result`.
+    pub fn respond(self, method: &str, result: Value) -> Self {
+        self.respond_with(method, move |_| result.clone())
+    }
+
+    /// Answers `method` requests with the result of `handler` on their params.
+    pub fn respond_with(
+        mut self,
+        method: &str,
+        handler: impl Fn(&Value) -> Value + Send + Sync + 'static,
+    ) -> Self {
+        self.handlers.insert(
+            method.to_string(),
+            Arc::new(move |params| Ok(handler(params))),
+        );
+        self
+    }
+
+    /// Fails every `method` request with a JSON-RPC error.
+    pub fn fail(mut self, method: &str, code: i64, message: &str) -> Self {
+        let error = json!({ "code": code, "message": message });
+        self.handlers
+            .insert(method.to_string(), Arc::new(move |_| Err(error.clone())));
+        self
+    }
+
+    /// Diagnostics published for each document opened or changed; empty to publish
+    /// none.
+    pub fn diagnostics(mut self, diagnostics: Vec<Value>) -> Self {
+        self.diagnostics = diagnostics;
+        self
+    }
+
+    /// Starts the mock and returns the stream to talk to it over.
+    pub fn spawn(self) -> DuplexStream {
+        let (client, server) = tokio::io::duplex(MOCK_BUFFER);
+        tokio::spawn(self.serve(server));
+        client
+    }
+
+    /// Answers framed messages on `stream` until the client sends `exit` or hangs up.
+    pub async fn serve(self, stream: DuplexStream) {
+        let (reader, writer) = tokio::io::split(stream);
+        let (mut reader, mut writer) = FramedTransport::new(reader, writer).into_split();
+        while let Ok(Some(message)) = reader.read().await {
+            if message.get("method").and_then(Value::as_str) == Some("exit") {
+                break;
+            }
+            for reply in self.answer(&message) {
+                if writer.write(&reply).await.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// The messages the mock sends in reply to `message`.
+    pub fn answer(&self, message: &Value) -> Vec<Value> {
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // A response to a request of ours; the mock sends none
+            return Vec::new();
+        };
+        let params = message.get("params").unwrap_or(&Value::Null);
+        let Some(id) = message.get("id") else {
+            return self.notified(method, params);
+        };
+        let outcome = match self.handlers.get(method) {
+            Some(handler) => handler(params),
+            None if method == "initialize" => Ok(json!({
+                "capabilities": self.capabilities,
+                "serverInfo": { "name": "pathfinder-mock" },
+            })),
+            None => Err(json!({
+                "code": METHOD_NOT_FOUND,
+                "message": format!("mock server has no answer for '{method}'"),
+            })),
+        };
+        let reply = match outcome {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(error) => json!({ "jsonrpc": "2.0", "id": id, "error": error }),
+        };
+        vec![reply]
+    }
+
+    fn notified(&self, method: &str, params: &Value) -> Vec<Value> {
+        if !matches!(method, "textDocument/didOpen" | "textDocument/didChange")
+            || self.diagnostics.is_empty()
+        {
+            return Vec::new();
+        }
+        vec![json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": params["textDocument"]["uri"],
+                "diagnostics": self.diagnostics,
+            },
+        })]
+    }
+}
+
+fn range_at(line: u32, character: u32) -> Value {
+    let position = json!({ "line": line, "character": character });
+    json!({ "start": position, "end": position })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_scripted_and_default_requests() {
+        let stream = MockServer::new()
+            .respond("textDocument/hover", json!({"contents": "docs"}))
+            .fail("workspace/symbol", -32800, "cancelled")
+            .spawn();
+        let (reader, writer) = tokio::io::split(stream);
+        let mut transport = FramedTransport::new(reader, writer);
+        let uri = "file:///src/main.rs";
+        let request = |id: u64, method: &str, params: Value| json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params});
+
+        transport
+            .write(&request(1, "initialize", json!({})))
+            .await
+            .unwrap();
+        let initialized = transport.read().await.unwrap().unwrap();
+        assert_eq!(
+            initialized["result"]["capabilities"]["definitionProvider"],
+            true
+        );
+
+        transport
+            .write(
+                &json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                    "textDocument": {"uri": uri, "languageId": "rust", "version": 0, "text": ""},
+                }}),
+            )
+            .await
+            .unwrap();
+        let published = transport.read().await.unwrap().unwrap();
+        assert_eq!(published["method"], "textDocument/publishDiagnostics");
+        assert_eq!(published["params"]["uri"], uri);
+        assert_eq!(
+            published["params"]["diagnostics"][0]["message"],
+            "mock diagnostic"
+        );
+
+        let position =
+            json!({"textDocument": {"uri": uri}, "position": {"line": 3, "character": 7}});
+        transport
+            .write(&request(2, "textDocument/references", position.clone()))
+            .await
+            .unwrap();
+        let references = transport.read().await.unwrap().unwrap();
+        assert_eq!(references["id"], 2);
+        assert_eq!(references["result"][0]["range"]["start"]["line"], 3);
+        assert_eq!(references["result"][1]["uri"], uri);
+
+        transport
+            .write(&request(3, "textDocument/hover", position))
+            .await
+            .unwrap();
+        let hover = transport.read().await.unwrap().unwrap();
+        assert_eq!(hover["result"]["contents"], "docs");
+
+        transport
+            .write(&request(4, "workspace/symbol", json!({"query": "add"})))
+            .await
+            .unwrap();
+        let failed = transport.read().await.unwrap().unwrap();
+        assert_eq!(failed["error"]["code"], -32800);
+
+        transport
+            .write(&request(5, "textDocument/rename", json!({})))
+            .await
+            .unwrap();
+        let unknown = transport.read().await.unwrap().unwrap();
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+
+        transport
+            .write(&json!({"jsonrpc": "2.0", "method": "exit"}))
+            .await
+            .unwrap();
+        assert!(transport.read().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::capabilities::TextDocumentSyncKind;
+use crate::lsp_bridge::LspBridge;
+
+/// Providers each tool needs the server to advertise. Tools not listed need none.
+const TOOL_PROVIDERS: &[(&str, &[&str])] = &[
+    ("definition", &["definitionProvider"]),
+    ("references", &["referencesProvider"]),
+    ("outline", &["documentSymbolProvider"]),
+    (
+        "find_unused_symbols",
+        &["documentSymbolProvider", "referencesProvider"],
+    ),
+    // documentLinkProvider is used when present, but definition is enough
+    ("dependency_graph", &["definitionProvider"]),
+    ("rename", &["renameProvider"]),
+    ("code_action", &["codeActionProvider"]),
+];
+
+/// What the language server said it can do, returned by `server_capabilities`.
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct CapabilitiesReport {
+    /// `serverInfo` from the initialize result
+    pub server: Option<ServerIdentity>,
+    /// Encoding of the server's columns: `utf-8`, `utf-16`, or `utf-32`
+    pub position_encoding: String,
+    pub text_document_sync: TextDocumentSync,
+    /// Every `*Provider` capability the server enables, by name
+    pub providers: Vec<String>,
+    /// Each offered tool, and whether the server supports what it needs
+    pub tools: Vec<ToolSupport>,
+    /// The capabilities as the server sent them
+    pub raw: Value,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ServerIdentity {
+    pub name: String,
+    pub version: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct TextDocumentSync {
+    /// How edits reach the server: `none`, `full`, or `incremental`
+    pub change: TextDocumentSyncKind,
+    /// Whether the server wants didOpen and didClose
+    pub open_close: bool,
+    /// Whether the server wants didSave
+    pub save: bool,
+    /// Whether didSave carries the saved text
+    pub save_include_text: bool,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, schemars::JsonSchema)]
+pub struct ToolSupport {
+    pub name: String,
+    /// False if the server lacks a provider the tool needs; calls to it fail
+    pub available: bool,
+    /// The providers the tool needs that the server does not advertise
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CapabilitiesTool;
+
+impl CapabilitiesTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Report the language server's capabilities from its initialize result: server name and version, position encoding, document sync, the providers it enables, and which of the offered tools it supports"
+    }
+
+    /// Reports `lsp`'s capabilities, checking each of `offered`, the tools in
+    /// `tools/list`, against them.
+    pub fn execute(&self, lsp: &LspBridge, offered: &[String]) -> CapabilitiesReport {
+        let capabilities = lsp.server_capabilities();
+        let raw = capabilities.raw();
+        let mut providers: Vec<String> = raw
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(name, _)| name)
+            .filter(|name| name.ends_with("Provider") && capabilities.supports(name))
+            .cloned()
+            .collect();
+        providers.sort();
+
+        let mut tools: Vec<ToolSupport> = offered
+            .iter()
+            .map(|name| {
+                let needed = TOOL_PROVIDERS
+                    .iter()
+                    .find(|(tool, _)| *tool == name.as_str())
+                    .map_or(&[][..], |(_, providers)| *providers);
+                let missing: Vec<String> = needed
+                    .iter()
+                    .filter(|provider| !capabilities.supports(provider))
+                    .map(|provider| provider.to_string())
+                    .collect();
+                ToolSupport {
+                    name: name.clone(),
+                    available: missing.is_empty(),
+                    missing,
+                }
+            })
+            .collect();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let server = lsp.server_info().and_then(|info| {
+            Some(ServerIdentity {
+                name: info.get("name")?.as_str()?.to_string(),
+                version: info
+                    .get("version")
+                    .and_then(Value::as_str)
+                    .map(str::to_string),
+            })
+        });
+        let save = capabilities.save_include_text();
+        CapabilitiesReport {
+            server,
+            position_encoding: capabilities.position_encoding().as_lsp().to_string(),
+            text_document_sync: TextDocumentSync {
+                change: capabilities.text_document_sync_kind(),
+                open_close: capabilities.open_close(),
+                save: save.is_some(),
+                save_include_text: save.unwrap_or(false),
+            },
+            providers,
+            tools,
+            raw: raw.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::testing::MockServer;
+
+    #[tokio::test]
+    async fn reports_the_tools_the_server_supports() {
+        let dir = tempfile::tempdir().unwrap();
+        let mock = MockServer::new().capabilities(json!({
+            "definitionProvider": true,
+            "referencesProvider": {"workDoneProgress": false},
+            "renameProvider": false,
+            "textDocumentSync": {"openClose": true, "change": 2, "save": {"includeText": true}},
+        }));
+        let mut lsp = LspBridge::from_mock(mock, dir.path().to_path_buf());
+        lsp.initialize().await.unwrap();
+
+        let offered =
+            ["rename", "definition", "find_unused_symbols", "search_text"].map(str::to_string);
+        let report = CapabilitiesTool::new().execute(&lsp, &offered);
+
+        assert_eq!(report.server.unwrap().name, "pathfinder-mock");
+        assert_eq!(report.position_encoding, "utf-16");
+        assert_eq!(
+            report.providers,
+            ["definitionProvider", "referencesProvider"]
+        );
+        assert_eq!(
+            report.text_document_sync.change,
+            TextDocumentSyncKind::Incremental
+        );
+        assert!(
+            report.text_document_sync.open_close && report.text_document_sync.save_include_text
+        );
+        let support = |name: &str, missing: &[&str]| ToolSupport {
+            name: name.to_string(),
+            available: missing.is_empty(),
+            missing: missing
+                .iter()
+                .map(|provider| provider.to_string())
+                .collect(),
+        };
+        assert_eq!(
+            report.tools,
+            [
+                support("definition", &[]),
+                support("find_unused_symbols", &["documentSymbolProvider"]),
+                support("rename", &["renameProvider"]),
+                support("search_text", &[]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use std::time::Duration;
+
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics::{self, Diagnostic, DiagnosticsStore};
+use crate::documents::DocumentManager;
+use crate::lsp_bridge::LspBridge;
+use crate::position::PositionConverter;
+use crate::session::SessionId;
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct CheckEditRequest {
+    /// file:// URI or absolute or workspace-relative path of the document; the file does
+    /// not need to exist
+    pub uri: String,
+    /// Proposed full content of the document
+    pub text: String,
+}
+
+/// The server's diagnostics for proposed content, compared with its current ones.
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct CheckEditResponse {
+    pub uri: String,
+    /// Every diagnostic of the proposed content
+    pub diagnostics: Vec<Diagnostic>,
+    /// Diagnostics the proposed content has and the current content does not
+    pub introduced: Vec<Diagnostic>,
+    /// Diagnostics of the current content the proposed content no longer has, at
+    /// their positions in the current content
+    pub resolved: Vec<Diagnostic>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CheckTool;
+
+impl CheckTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Check proposed content for a document before writing it: the server analyzes it in memory, and the diagnostics it reports are returned along with those introduced and resolved compared with the current content. The document is left as it was"
+    }
+
+    /// Sends the proposed text as `session`'s overlay, waits up to `timeout` for the
+    /// server to publish diagnostics for it, then puts the document back: to the
+    /// session's earlier overlay text if it had one, otherwise to the file on disk.
+    ///
+    /// The comparison is with the diagnostics last published for the document; if
+    /// the server has published none, a file on disk that is not open yet is opened
+    /// first to learn them.
+    pub async fn execute(
+        &self,
+        lsp: &LspBridge,
+        documents: &DocumentManager,
+        store: &DiagnosticsStore,
+        session: SessionId,
+        request: CheckEditRequest,
+        timeout: Duration,
+    ) -> Result<CheckEditResponse> {
+        let uri = request.uri;
+        let mut notifications = lsp.subscribe();
+        let overlays = documents.overlay_texts().await;
+        let previous = overlays.get(&uri).cloned();
+        let mut current = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+            .with_texts(overlays);
+
+        let before = if store.contains(&uri) {
+            store.get(&uri)
+        } else if previous.is_none()
+            && !documents.is_open(&uri)
+            && documents.ensure_open(lsp, &uri).await.is_ok()
+        {
+            diagnostics::next_published(&mut notifications, &uri, None, timeout)
+                .await
+                .unwrap_or_default()
+        } else {
+            Vec::new()
+        };
+
+        let version = documents
+            .open_overlay(lsp, &uri, session, request.text)
+            .await?;
+        let after =
+            diagnostics::next_published(&mut notifications, &uri, Some(version), timeout).await;
+        let mut proposed = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+            .with_texts(documents.overlay_texts().await);
+        match previous {
+            Some(text) => {
+                documents.open_overlay(lsp, &uri, session, text).await?;
+            }
+            None => documents.discard_overlay(lsp, &uri, session).await,
+        }
+
+        let Some(after) = after else {
+            bail!(
+                "the server published no diagnostics for {uri} within {}ms",
+                timeout.as_millis()
+            );
+        };
+        let (introduced, resolved) = diagnostics::delta(&before, &after);
+        Ok(CheckEditResponse {
+            diagnostics: diagnostics::convert(&mut proposed, &uri, &after).await,
+            introduced: diagnostics::convert(&mut proposed, &uri, &introduced).await,
+            resolved: diagnostics::convert(&mut current, &uri, &resolved).await,
+            uri,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::config::Config;
+    use crate::supervisor::LspSupervisor;
+
+    async fn check(supervisor: &Arc<LspSupervisor>, uri: &url::Url) -> CheckEditResponse {
+        let lsp = supervisor.ready_bridge().await.unwrap();
+        let request = CheckEditRequest {
+            uri: uri.to_string(),
+            text: "fn main() { let x = 1; }\n".to_string(),
+        };
+        CheckTool::new()
+            .execute(
+                &lsp,
+                supervisor.documents(),
+                supervisor.diagnostics(),
+                SessionId::next(),
+                request,
+                Duration::from_secs(5),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn reports_the_diagnostics_of_proposed_content_and_restores_the_document() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+        let config =
+            Config::from_json_str(r#"{"server": {"extensions": ["rs"], "connect": "mock"}}"#)
+                .unwrap();
+        let supervisor = LspSupervisor::start(config.server, dir.path().to_path_buf())
+            .await
+            .unwrap();
+
+        // The mock warns about every version, so nothing changes for a file on disk
+        let existing = url::Url::from_file_path(dir.path().join("main.rs")).unwrap();
+        let checked = check(&supervisor, &existing).await;
+        assert_eq!(checked.diagnostics.len(), 1);
+        assert_eq!(checked.diagnostics[0].severity, "warning");
+        assert!(checked.introduced.is_empty() && checked.resolved.is_empty());
+
+        // A file not written yet had no diagnostics before
+        let new = url::Url::from_file_path(dir.path().join("new.rs")).unwrap();
+        let checked = check(&supervisor, &new).await;
+        assert_eq!(checked.introduced, checked.diagnostics);
+        assert!(checked.resolved.is_empty());
+
+        assert!(supervisor.documents().overlay_texts().await.is_empty());
+        assert!(!supervisor.documents().is_open(new.as_str()));
+        supervisor.shutdown().await;
+    }
+}
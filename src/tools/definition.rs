@@ -1,48 +1,154 @@
-use anyhow::{Context, Result, anyhow};
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use serde_json::{Map, Value, json};
+use serde_json::{Value, json};
 use tokio::time::{Duration, sleep};
 
 use crate::lsp_bridge::LspBridge;
+use crate::pagination::Page;
+use crate::position::PositionConverter;
+use crate::progress::ToolProgress;
+use crate::protocol::{Definition, Request, TextDocumentPositionParams};
+use crate::tools::locations::{DefinitionTarget, targets, targets_to_client};
+use crate::utils::uri_to_file_path;
 
 const MAX_RETRIES: u32 = 3;
 const RETRY_DELAY_MS: u64 = 150;
+const DEFAULT_MAX_TARGETS: usize = 1000;
+const MAX_TARGETS_LIMIT: usize = 10_000;
 
 #[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
 pub struct DefinitionRequest {
-    /// file:// URI of the document
+    /// file:// URI of the document, or its absolute or workspace-relative path
     pub uri: String,
     /// Zero-based line index
     pub line: u32,
-    /// Zero-based character index
+    /// Zero-based character index, in the configured column encoding (UTF-16 by default)
     pub character: u32,
+    /// If nothing is found at the position, retry at the nearest identifier on the
+    /// line (default false)
+    pub snap: Option<bool>,
+    /// Ask only this server, by a name `list_servers` reports (default: the main
+    /// server and any companions configured for the document)
+    pub server: Option<String>,
+    #[serde(flatten)]
+    pub options: TargetOptions,
+}
+
+impl DefinitionRequest {
+    /// Identifies the query for pagination cursors.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "definition {} {}:{} {} {:?} {}",
+            self.uri,
+            self.line,
+            self.character,
+            self.snap(),
+            self.server,
+            self.options.cache_key()
+        )
+    }
+
+    fn snap(&self) -> bool {
+        self.snap.unwrap_or(false)
+    }
 }
 
-#[derive(Debug, Serialize, Clone, Default)]
+#[derive(Debug, Serialize, Clone, Default, schemars::JsonSchema)]
 pub struct DefinitionResponse {
     pub targets: Vec<DefinitionTarget>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// The position the request was retried at, if `snap` moved it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapped: Option<SnappedPosition>,
+    /// True if the server found more targets than `max_results`
+    pub truncated: bool,
+    /// True if the targets come from built-in declaration patterns rather than the
+    /// server; they are candidates sharing the name, not resolved definitions
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub fallback: bool,
 }
 
-#[derive(Debug, Serialize, Clone)]
-pub struct DefinitionTarget {
-    pub uri: String,
-    pub range: TextRange,
+impl From<Page<DefinitionTarget>> for DefinitionResponse {
+    fn from(page: Page<DefinitionTarget>) -> Self {
+        Self {
+            targets: page.items,
+            next_cursor: page.next_cursor,
+            snapped: None,
+            truncated: false,
+            fallback: false,
+        }
+    }
+}
+
+/// How definition and reference targets are filtered and capped.
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct TargetOptions {
+    /// Drop targets outside the workspace root, such as dependencies and the standard
+    /// library (default false)
+    pub workspace_only: Option<bool>,
+    /// Maximum number of targets to keep, across all pages (default 1000, at most 10000)
+    pub max_results: Option<usize>,
 }
 
-#[derive(Debug, Serialize, Clone)]
-pub struct TextRange {
-    pub start_line: u32,
-    pub start_character: u32,
-    pub end_line: u32,
-    pub end_character: u32,
+impl TargetOptions {
+    fn max_results(&self) -> usize {
+        self.max_results
+            .unwrap_or(DEFAULT_MAX_TARGETS)
+            .clamp(1, MAX_TARGETS_LIMIT)
+    }
+
+    pub(crate) fn cache_key(&self) -> String {
+        format!(
+            "{} {}",
+            self.workspace_only.unwrap_or(false),
+            self.max_results()
+        )
+    }
+
+    /// Deduplicates `targets` by location and sorts them by path and position, then
+    /// drops those outside `workspace` if asked to and caps their number. Returns
+    /// true if the cap cut any.
+    pub(crate) fn apply(&self, targets: &mut Vec<DefinitionTarget>, workspace: &Path) -> bool {
+        targets.sort_by(|a, b| a.sort_key().cmp(&b.sort_key()));
+        targets.dedup_by(|a, b| a.sort_key() == b.sort_key());
+        if self.workspace_only.unwrap_or(false) {
+            targets.retain(|target| {
+                uri_to_file_path(&target.uri).is_ok_and(|path| path.starts_with(workspace))
+            });
+        }
+        let max_results = self.max_results();
+        let truncated = targets.len() > max_results;
+        targets.truncate(max_results);
+        truncated
+    }
 }
 
-#[derive(Debug, Default, Clone, Copy)]
-pub struct DefinitionTool;
+/// A requested position moved onto the nearest identifier.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, schemars::JsonSchema)]
+pub struct SnappedPosition {
+    pub line: u32,
+    /// In the configured column encoding
+    pub character: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DefinitionTool {
+    progress: ToolProgress,
+}
 
 impl DefinitionTool {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Reports retries and indexing waits to `progress`.
+    pub fn with_progress(mut self, progress: ToolProgress) -> Self {
+        self.progress = progress;
+        self
     }
 
     pub fn description() -> &'static str {
@@ -78,142 +184,171 @@ impl DefinitionTool {
         })
     }
 
+    /// `overlays` holds the text of unsaved overlay documents, used in place of the
+    /// files on disk when converting columns.
     pub async fn execute(
         &self,
-        lsp: &mut LspBridge,
+        lsp: &LspBridge,
         request: DefinitionRequest,
+        overlays: HashMap<String, String>,
     ) -> Result<DefinitionResponse> {
-        let params = json!({
-            "textDocument": { "uri": request.uri },
-            "position": { "line": request.line, "character": request.character },
-        });
+        lsp.server_capabilities()
+            .ensure("definitionProvider", Definition::METHOD)?;
+
+        // Columns are converted between the caller's and the server's encoding
+        let mut converter = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+            .with_texts(overlays);
+        let character = converter
+            .to_server(&request.uri, request.line, request.character)
+            .await;
+        let mut params = TextDocumentPositionParams::new(&request.uri, request.line, character);
+        let request_timeout = lsp.tool_timeout("definition", Definition::METHOD);
+        let mut snapped = None;
+        let mut snap_pending = request.snap();
 
         // Retry logic for empty results
-        // LSP servers sometimes return empty initially during indexing
-        for attempt in 1..=MAX_RETRIES {
-            let raw = lsp
-                .request("textDocument/definition", params.clone())
+        // LSP servers sometimes return empty initially during indexing. Servers that
+        // report progress are waited on (up to the configured budget) instead of
+        // burning through the short fixed retries.
+        let mut waited_for_indexing = false;
+        let mut attempt = 1;
+        loop {
+            let locations = lsp
+                .call::<Definition>(&params, request_timeout)
                 .await
                 .context("LSP definition request failed")?;
-            let targets = normalize_targets(&raw)?;
+            let mut targets = targets(locations);
 
             if !targets.is_empty() {
-                if attempt > 1 {
+                if attempt > 1 || waited_for_indexing {
                     tracing::debug!(attempt, uri = %request.uri, "Definition succeeded after retry");
                 }
-                return Ok(DefinitionResponse { targets });
+                targets_to_client(&mut converter, &request.uri, &mut targets).await;
+                let truncated = request.options.apply(&mut targets, lsp.workspace());
+                return Ok(DefinitionResponse {
+                    targets,
+                    next_cursor: None,
+                    snapped,
+                    truncated,
+                    fallback: false,
+                });
+            }
+
+            // A position off the identifier is a likelier cause than indexing
+            if std::mem::take(&mut snap_pending)
+                && let Some((position, character)) = snap_position(
+                    &mut converter,
+                    &request.uri,
+                    request.line,
+                    request.character,
+                )
+                .await
+            {
+                tracing::debug!(uri = %request.uri, ?position, "Definition empty, retrying at snapped position");
+                params.position.character = character;
+                snapped = Some(position);
+                lsp.metrics().record_retry("definition");
+                continue;
+            }
+
+            if !waited_for_indexing && lsp.is_indexing() {
+                waited_for_indexing = true;
+                tracing::debug!(uri = %request.uri, "Definition empty while indexing, waiting...");
+                self.progress
+                    .report("Waiting for the language server to finish indexing");
+                if lsp.wait_until_ready().await {
+                    lsp.metrics().record_retry("definition");
+                    continue;
+                }
+                tracing::debug!(uri = %request.uri, "Indexing did not finish within budget");
             }
 
             // Empty result - retry if we have attempts left
-            if attempt < MAX_RETRIES {
-                tracing::debug!(attempt, uri = %request.uri, "Definition empty, retrying...");
-                sleep(Duration::from_millis(RETRY_DELAY_MS)).await;
+            if attempt >= MAX_RETRIES {
+                break;
             }
+            tracing::debug!(attempt, uri = %request.uri, "Definition empty, retrying...");
+            attempt += 1;
+            lsp.metrics().record_retry("definition");
+            self.progress.report(format!(
+                "No definition yet, retrying (attempt {attempt} of {MAX_RETRIES})"
+            ));
+            sleep(Duration::from_millis(RETRY_DELAY_MS)).await;
         }
 
         // All retries returned empty - return empty result
-        Ok(DefinitionResponse { targets: vec![] })
+        Ok(DefinitionResponse {
+            snapped,
+            ..DefinitionResponse::default()
+        })
     }
 }
 
-/// Normalizes LSP definition responses into a consistent format.
-///
-/// LSP servers can return definitions in three formats:
-/// - null (no definition found)
-/// - Location (single result)
-/// - Location[] (multiple results)
-/// - LocationLink[] (alternative format with more info)
-///
-/// This function converts all formats to a Vec<DefinitionTarget>.
-fn normalize_targets(value: &Value) -> Result<Vec<DefinitionTarget>> {
-    match value {
-        Value::Null => Ok(vec![]),
-        Value::Array(entries) => entries.iter().map(convert_location).collect(),
-        Value::Object(_) => Ok(vec![convert_location(value)?]),
-        other => Err(anyhow!("unexpected definition response format: {other:?}")),
-    }
+/// `line`/`character` moved onto the nearest identifier, along with the snapped
+/// column in the server's encoding, if snapping moves it at all.
+pub(crate) async fn snap_position(
+    converter: &mut PositionConverter,
+    uri: &str,
+    line: u32,
+    character: u32,
+) -> Option<(SnappedPosition, u32)> {
+    let character = converter.snap(uri, line, character).await?;
+    let server_character = converter.to_server(uri, line, character).await;
+    Some((SnappedPosition { line, character }, server_character))
 }
 
-/// Converts a single location entry to a DefinitionTarget.
-///
-/// Handles both Location and LocationLink formats:
-/// - Location: { uri, range }
-/// - LocationLink: { targetUri, targetRange, ... }
-fn convert_location(value: &Value) -> Result<DefinitionTarget> {
-    let object = value
-        .as_object()
-        .ok_or_else(|| anyhow!("definition entry must be an object"))?;
-
-    // Try Location format first, then LocationLink format
-    if object.contains_key("uri") {
-        convert_standard_location(object)
-    } else if object.contains_key("targetUri") {
-        convert_location_link(object)
-    } else {
-        Err(anyhow!(
-            "definition entry missing required fields (expected 'uri' or 'targetUri'): {object:?}"
-        ))
-    }
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-fn convert_standard_location(object: &Map<String, Value>) -> Result<DefinitionTarget> {
-    let uri = object
-        .get("uri")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow!("location.uri must be a string"))?;
-    let range_value = object
-        .get("range")
-        .ok_or_else(|| anyhow!("location.range missing"))?;
-    let range = parse_range(range_value)?;
-    Ok(DefinitionTarget {
-        uri: uri.to_string(),
-        range,
-    })
-}
+    use crate::tools::locations::TextRange;
 
-fn convert_location_link(object: &Map<String, Value>) -> Result<DefinitionTarget> {
-    let uri = object
-        .get("targetUri")
-        .and_then(|v| v.as_str())
-        .ok_or_else(|| anyhow!("locationLink.targetUri must be a string"))?;
-    let range_value = object
-        .get("targetRange")
-        .ok_or_else(|| anyhow!("locationLink.targetRange missing"))?;
-    let range = parse_range(range_value)?;
-    Ok(DefinitionTarget {
-        uri: uri.to_string(),
-        range,
-    })
-}
+    fn target(uri: &url::Url, line: u32) -> DefinitionTarget {
+        DefinitionTarget::new(
+            uri.to_string(),
+            TextRange {
+                start_line: line,
+                start_character: 0,
+                end_line: line,
+                end_character: 3,
+            },
+        )
+    }
 
-fn parse_range(value: &Value) -> Result<TextRange> {
-    let obj = value
-        .as_object()
-        .ok_or_else(|| anyhow!("range must be an object"))?;
-    let start = obj
-        .get("start")
-        .ok_or_else(|| anyhow!("range.start missing"))?;
-    let end = obj.get("end").ok_or_else(|| anyhow!("range.end missing"))?;
-
-    Ok(TextRange {
-        start_line: get_coord(start, "line", "start")?,
-        start_character: get_coord(start, "character", "start")?,
-        end_line: get_coord(end, "line", "end")?,
-        end_character: get_coord(end, "character", "end")?,
-    })
-}
+    #[test]
+    fn targets_are_deduplicated_sorted_filtered_and_capped() {
+        let workspace = std::env::temp_dir().join("project");
+        let main = url::Url::from_file_path(workspace.join("src/main.rs")).unwrap();
+        let lib = url::Url::from_file_path(workspace.join("src/lib.rs")).unwrap();
+        let outside = url::Url::from_file_path(std::env::temp_dir().join("std/vec.rs")).unwrap();
+        let found = || {
+            vec![
+                target(&main, 9),
+                target(&outside, 1),
+                target(&lib, 4),
+                target(&main, 2),
+                target(&main, 9),
+            ]
+        };
+        let lines = |targets: &[DefinitionTarget]| {
+            targets
+                .iter()
+                .map(|target| target.range.start_line)
+                .collect::<Vec<_>>()
+        };
 
-fn get_coord(value: &Value, coord: &str, position_label: &str) -> Result<u32> {
-    value
-        .as_object()
-        .and_then(|obj| obj.get(coord))
-        .and_then(|num| num.as_u64())
-        .map(|v| v as u32)
-        .ok_or_else(|| {
-            anyhow!(
-                "range.{}.{} must be an unsigned integer",
-                position_label,
-                coord
-            )
-        })
+        let mut targets = found();
+        assert!(!TargetOptions::default().apply(&mut targets, &workspace));
+        assert_eq!(targets.len(), 4);
+        assert_eq!(targets[0].uri, lib.as_str());
+        assert_eq!(lines(&targets[1..3]), [2, 9]);
+
+        let options = TargetOptions {
+            workspace_only: Some(true),
+            max_results: Some(2),
+        };
+        let mut targets = found();
+        assert!(options.apply(&mut targets, &workspace));
+        assert_eq!(lines(&targets), [4, 2]);
+    }
 }
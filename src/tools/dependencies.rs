@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::documents::DocumentManager;
+use crate::imports::{self, Import};
+use crate::lsp_bridge::LspBridge;
+use crate::progress::ToolProgress;
+use crate::protocol::{
+    Definition, DocumentLinkParams, DocumentLinks, Request, TextDocumentIdentifier,
+    TextDocumentPositionParams,
+};
+use crate::tools::locations::targets;
+use crate::tools::outline::{concurrency, run_limited, source_files};
+use crate::utils::{path_to_uri, strip_path_prefix, uri_to_file_path};
+
+const DEFAULT_MAX_FILES: usize = 200;
+
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct DependencyGraphRequest {
+    /// Directory (or file) whose files to graph, relative to the workspace root
+    /// (default: the whole workspace). Ignored when `files` is given
+    pub path: Option<String>,
+    /// Files to graph instead of a directory: file:// URIs or absolute or
+    /// workspace-relative paths
+    pub files: Option<Vec<String>>,
+    /// Most files to graph (default 200, at most 1000)
+    pub max_files: Option<usize>,
+    /// Files resolved at once (default 4, at most 16)
+    pub concurrency: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct DependencyGraphResponse {
+    /// Every graphed file, by path, with the workspace files it imports and those of
+    /// the graphed files that import it
+    pub files: Vec<FileDependencies>,
+    /// Number of import edges between workspace files
+    pub edges: usize,
+    /// Files the server failed to answer for, which are left out
+    pub failed: usize,
+    /// True if more files matched than `max_files`
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct FileDependencies {
+    pub uri: String,
+    /// Workspace files this file imports, with the line of the first import of each
+    pub imports: Vec<Dependency>,
+    /// Graphed files that import this file, with the line of the import in each
+    pub imported_by: Vec<Dependency>,
+    /// Imports resolved to files outside the workspace (standard library, packages)
+    pub external: usize,
+    /// Imports the server resolved to nothing
+    pub unresolved: usize,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, schemars::JsonSchema)]
+pub struct Dependency {
+    pub uri: String,
+    /// Line of the import statement
+    pub line: u32,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct DependenciesTool {
+    progress: ToolProgress,
+}
+
+impl DependenciesTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports how many files are done to `progress`.
+    pub fn with_progress(mut self, progress: ToolProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn description() -> &'static str {
+        "Build the file-level import graph of a directory or list of files: the import statements of each file are found per language and resolved by the language server (textDocument/documentLink or textDocument/definition), giving the workspace files each one imports and is imported by, to judge the impact of a change"
+    }
+
+    pub async fn execute(
+        &self,
+        lsp: &Arc<LspBridge>,
+        documents: &Arc<DocumentManager>,
+        extensions: &[String],
+        request: DependencyGraphRequest,
+    ) -> Result<DependencyGraphResponse> {
+        let capabilities = lsp.server_capabilities();
+        let links = capabilities.supports("documentLinkProvider");
+        if !links {
+            capabilities.ensure("definitionProvider", Definition::METHOD)?;
+        }
+        let max_files = request.max_files.unwrap_or(DEFAULT_MAX_FILES);
+        let (uris, truncated) = match request.files {
+            Some(mut files) => {
+                let truncated = files.len() > max_files;
+                files.truncate(max_files);
+                (files, truncated)
+            }
+            None => source_files(lsp, request.path, extensions, max_files).await?,
+        };
+        let total = uris.len();
+
+        let mut files = Vec::with_capacity(total);
+        let mut failed = 0;
+        let (lsp, documents) = (lsp.clone(), documents.clone());
+        run_limited(
+            uris,
+            concurrency(request.concurrency),
+            move |uri| {
+                let (lsp, documents) = (lsp.clone(), documents.clone());
+                async move { dependencies(&lsp, &documents, uri, links).await }
+            },
+            |done, file| {
+                match file {
+                    Ok(file) => files.push(file),
+                    Err(err) => {
+                        tracing::debug!("Resolving the imports of a file failed: {err:#}");
+                        failed += 1;
+                    }
+                }
+                self.progress
+                    .report(format!("Resolved the imports of {done}/{total} files"));
+            },
+        )
+        .await;
+
+        let mut importers: BTreeMap<String, Vec<Dependency>> = BTreeMap::new();
+        for file in &files {
+            for import in &file.imports {
+                importers
+                    .entry(import.uri.clone())
+                    .or_default()
+                    .push(Dependency {
+                        uri: file.uri.clone(),
+                        line: import.line,
+                    });
+            }
+        }
+        for file in &mut files {
+            file.imported_by = importers.remove(&file.uri).unwrap_or_default();
+            file.imported_by.sort_by(|a, b| a.uri.cmp(&b.uri));
+        }
+        files.sort_by(|a, b| a.uri.cmp(&b.uri));
+        Ok(DependencyGraphResponse {
+            edges: files.iter().map(|file| file.imports.len()).sum(),
+            files,
+            failed,
+            truncated,
+        })
+    }
+}
+
+/// The imports of the file at `uri`, each resolved through its document link when
+/// `links` is set and the server gave one for its line, else its definition.
+async fn dependencies(
+    lsp: &LspBridge,
+    documents: &DocumentManager,
+    uri: String,
+    links: bool,
+) -> Result<FileDependencies> {
+    let path = uri_to_file_path(&uri)?;
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default();
+    let mut file = FileDependencies {
+        uri: path_to_uri(&path)?,
+        imports: Vec::new(),
+        imported_by: Vec::new(),
+        external: 0,
+        unresolved: 0,
+    };
+    if !imports::supports(extension) {
+        return Ok(file);
+    }
+    documents.ensure_open(lsp, &uri).await?;
+    let text = documents.current_text(&uri, &path).await?;
+    let found = imports::find(extension, &text, lsp.position_encoding());
+    let links = if links && !found.is_empty() {
+        document_links(lsp, &uri).await?
+    } else {
+        Vec::new()
+    };
+
+    let mut seen = HashSet::new();
+    for import in found {
+        let targets = match links.iter().find(|(line, _)| *line == import.line) {
+            Some((_, target)) => vec![target.clone()],
+            None => definitions(lsp, &uri, &import).await.unwrap_or_else(|err| {
+                tracing::debug!("Resolving import {} failed: {err:#}", import.target);
+                Vec::new()
+            }),
+        };
+        if targets.is_empty() {
+            file.unresolved += 1;
+            continue;
+        }
+        let local: Vec<String> = targets
+            .iter()
+            .filter_map(|target| workspace_file(lsp.workspace(), target))
+            .collect();
+        if local.is_empty() {
+            file.external += 1;
+            continue;
+        }
+        // An import resolving to the file itself, such as Rust's `use self::...`, is
+        // no edge
+        if let Some(target) = local.into_iter().find(|target| *target != file.uri)
+            && seen.insert(target.clone())
+        {
+            file.imports.push(Dependency {
+                uri: target,
+                line: import.line,
+            });
+        }
+    }
+    file.imports.sort_by(|a, b| a.uri.cmp(&b.uri));
+    Ok(file)
+}
+
+/// The start line and target URI of each resolved documentLink of `uri`.
+async fn document_links(lsp: &LspBridge, uri: &str) -> Result<Vec<(u32, String)>> {
+    let params = DocumentLinkParams {
+        text_document: TextDocumentIdentifier::new(uri),
+    };
+    let links = lsp
+        .call::<DocumentLinks>(
+            &params,
+            lsp.tool_timeout("dependency_graph", DocumentLinks::METHOD),
+        )
+        .await
+        .context("LSP documentLink request failed")?;
+    Ok(links
+        .into_iter()
+        .flatten()
+        .filter_map(|link| {
+            let target = link.target?;
+            let target = target
+                .split_once('#')
+                .map_or(&*target, |(target, _)| target);
+            Some((link.range.start.line, target.to_string()))
+        })
+        .collect())
+}
+
+/// The URIs the definition of `import` is in.
+async fn definitions(lsp: &LspBridge, uri: &str, import: &Import) -> Result<Vec<String>> {
+    let params = TextDocumentPositionParams::new(uri, import.line, import.character);
+    let locations = lsp
+        .call::<Definition>(
+            &params,
+            lsp.tool_timeout("dependency_graph", Definition::METHOD),
+        )
+        .await
+        .context("LSP definition request failed")?;
+    Ok(targets(locations)
+        .into_iter()
+        .map(|target| target.uri)
+        .collect())
+}
+
+/// `target` in the form file URIs of the graph take, if it names a file in
+/// `workspace`.
+fn workspace_file(workspace: &Path, target: &str) -> Option<String> {
+    let path = uri_to_file_path(target).ok()?;
+    strip_path_prefix(&path, workspace)?;
+    path_to_uri(&path).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::{Value, json};
+
+    use crate::testing::MockServer;
+
+    fn location(uri: &str) -> Value {
+        json!({"uri": uri, "range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 1}}})
+    }
+
+    #[tokio::test]
+    async fn resolves_imports_to_workspace_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().canonicalize().unwrap();
+        std::fs::write(
+            workspace.join("main.rs"),
+            "mod config;\nuse crate::config::Config;\nuse std::fs;\nuse missing::Thing;\n",
+        )
+        .unwrap();
+        std::fs::write(workspace.join("config.rs"), "pub struct Config;\n").unwrap();
+        let config = path_to_uri(&workspace.join("config.rs")).unwrap();
+        let mock = MockServer::new()
+            .capabilities(json!({"definitionProvider": true}))
+            .respond_with(
+                "textDocument/definition",
+                move |params| match params["position"]["line"].as_u64() {
+                    Some(0 | 1) => location(&config),
+                    Some(2) => location("file:///toolchain/library/std/src/fs.rs"),
+                    _ => Value::Null,
+                },
+            );
+        let mut lsp = LspBridge::from_mock(mock, workspace.clone());
+        lsp.initialize().await.unwrap();
+        let (lsp, documents) = (Arc::new(lsp), Arc::new(DocumentManager::new()));
+
+        let response = DependenciesTool::new()
+            .execute(
+                &lsp,
+                &documents,
+                &["rs".to_string()],
+                DependencyGraphRequest::default(),
+            )
+            .await
+            .unwrap();
+
+        let main = path_to_uri(&workspace.join("main.rs")).unwrap();
+        let config = path_to_uri(&workspace.join("config.rs")).unwrap();
+        assert_eq!(response.edges, 1);
+        assert_eq!(response.failed, 0);
+        let [config_file, main_file] = response.files.as_slice() else {
+            panic!("expected two files: {:?}", response.files);
+        };
+        assert_eq!(
+            main_file.imports,
+            [Dependency {
+                uri: config.clone(),
+                line: 0
+            }]
+        );
+        assert_eq!((main_file.external, main_file.unresolved), (1, 1));
+        assert_eq!(config_file.uri, config);
+        assert_eq!(config_file.imported_by, [Dependency { uri: main, line: 0 }]);
+        assert!(config_file.imports.is_empty());
+    }
+}
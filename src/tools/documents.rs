@@ -0,0 +1,107 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::documents::DocumentManager;
+use crate::lsp_bridge::LspBridge;
+use crate::session::SessionId;
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct OpenDocumentRequest {
+    /// file:// URI or absolute or workspace-relative path of the document; the file does
+    /// not need to exist
+    pub uri: String,
+    /// Full document content
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct UpdateDocumentRequest {
+    /// The document as passed to open_document in this session (URI or path)
+    pub uri: String,
+    /// New full content; mutually exclusive with `edits`
+    pub text: Option<String>,
+    /// Range edits against the current content, applied together
+    pub edits: Option<Vec<TextEdit>>,
+}
+
+/// A replacement of one range, with columns in the configured column encoding.
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct TextEdit {
+    pub start_line: u32,
+    pub start_character: u32,
+    pub end_line: u32,
+    pub end_character: u32,
+    pub new_text: String,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct DocumentResponse {
+    pub uri: String,
+    /// Document version as last sent to the server
+    pub version: i32,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DocumentsTool;
+
+impl DocumentsTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub async fn open(
+        &self,
+        lsp: &LspBridge,
+        documents: &DocumentManager,
+        session: SessionId,
+        request: OpenDocumentRequest,
+    ) -> Result<DocumentResponse> {
+        let version = documents
+            .open_overlay(lsp, &request.uri, session, request.text)
+            .await?;
+        Ok(DocumentResponse {
+            uri: request.uri,
+            version,
+        })
+    }
+
+    pub async fn update(
+        &self,
+        lsp: &LspBridge,
+        documents: &DocumentManager,
+        session: SessionId,
+        request: UpdateDocumentRequest,
+    ) -> Result<DocumentResponse> {
+        let version = match (request.text, request.edits) {
+            (Some(text), None) => {
+                documents
+                    .open_overlay(lsp, &request.uri, session, text)
+                    .await?
+            }
+            (None, Some(edits)) => {
+                let edits: Vec<Value> = edits.iter().map(TextEdit::to_lsp).collect();
+                documents
+                    .edit_overlay(lsp, &request.uri, session, &edits)
+                    .await?
+            }
+            _ => bail!("pass exactly one of `text` or `edits`"),
+        };
+        Ok(DocumentResponse {
+            uri: request.uri,
+            version,
+        })
+    }
+}
+
+impl TextEdit {
+    fn to_lsp(&self) -> Value {
+        json!({
+            "range": {
+                "start": { "line": self.start_line, "character": self.start_character },
+                "end": { "line": self.end_line, "character": self.end_character },
+            },
+            "newText": self.new_text,
+        })
+    }
+}
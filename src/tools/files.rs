@@ -0,0 +1,139 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::resolve_workspace_path;
+
+const DEFAULT_MAX_RESULTS: usize = 1000;
+const MAX_RESULTS_LIMIT: usize = 10_000;
+
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct ListFilesRequest {
+    /// Directory to list, relative to the workspace root (default: the whole workspace)
+    pub path: Option<String>,
+    /// Only return files matching one of these globs, relative to the workspace root (e.g. "src/**/*.rs")
+    pub globs: Option<Vec<String>>,
+    /// Only return files with one of these extensions (e.g. ["rs", "toml"])
+    pub extensions: Option<Vec<String>>,
+    /// Maximum number of paths to return (default 1000, at most 10000)
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ListFilesResponse {
+    /// Paths relative to the workspace root, sorted
+    pub files: Vec<String>,
+    /// True if more files matched than were returned
+    pub truncated: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FilesTool;
+
+impl FilesTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "List workspace files, honoring .gitignore, filtered by glob and extension"
+    }
+
+    /// Walks the workspace on a blocking thread.
+    pub async fn execute(
+        &self,
+        root: &Path,
+        request: ListFilesRequest,
+    ) -> Result<ListFilesResponse> {
+        let root = root.to_path_buf();
+        tokio::task::spawn_blocking(move || list_files(&root, request))
+            .await
+            .context("file listing task failed")?
+    }
+}
+
+fn list_files(root: &Path, request: ListFilesRequest) -> Result<ListFilesResponse> {
+    let start = match request.path.as_deref() {
+        Some(path) => resolve_workspace_path(root, path)?,
+        None => root.to_path_buf(),
+    };
+    let globs = build_globs(request.globs.as_deref().unwrap_or_default())?;
+    let extensions: Vec<&str> = request
+        .extensions
+        .iter()
+        .flatten()
+        .map(|ext| ext.trim_start_matches('.'))
+        .collect();
+    let max_results = request
+        .max_results
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .min(MAX_RESULTS_LIMIT);
+
+    let walker = WalkBuilder::new(&start)
+        // Honor .gitignore in workspaces that are not git checkouts
+        .require_git(false)
+        .sort_by_file_name(|a, b| a.cmp(b))
+        .build();
+    let mut files = Vec::new();
+    let mut truncated = false;
+    for entry in walker {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(err) => {
+                tracing::debug!(?err, "Skipping unreadable workspace entry");
+                continue;
+            }
+        };
+        if !entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_file())
+        {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+        if !matches(relative, globs.as_ref(), &extensions) {
+            continue;
+        }
+        if files.len() >= max_results {
+            truncated = true;
+            break;
+        }
+        files.push(display_path(relative));
+    }
+    Ok(ListFilesResponse { files, truncated })
+}
+
+pub(crate) fn build_globs(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut globs = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("invalid glob '{pattern}'"))?;
+        globs.add(glob);
+    }
+    Ok(Some(globs.build().context("invalid globs")?))
+}
+
+pub(crate) fn matches(relative: &Path, globs: Option<&GlobSet>, extensions: &[&str]) -> bool {
+    let extension_ok = extensions.is_empty()
+        || relative
+            .extension()
+            .is_some_and(|ext| extensions.iter().any(|wanted| ext == *wanted));
+    extension_ok && globs.is_none_or(|globs| globs.is_match(relative))
+}
+
+/// Forward-slash path, the same on every platform.
+pub(crate) fn display_path(relative: &Path) -> String {
+    relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
@@ -0,0 +1,194 @@
+//! Locations in LSP results, shared by the tools that return them.
+//!
+//! `definition`, `references`, `dependency_graph`, and the vendor tools all receive
+//! `Location`s or `LocationLink`s from the server, typed in [`crate::protocol`].
+//! They are converted here into one [`DefinitionTarget`] shape, which keeps what a
+//! `LocationLink` adds (the range of the name at the target and the span of the
+//! queried text), and their columns are converted to the client's encoding the same
+//! way.
+
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::position::PositionConverter;
+use crate::protocol::{Location, LocationLink, LocationOrLink, Locations, Range};
+use crate::tools::references::ReferenceScope;
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct DefinitionTarget {
+    pub uri: String,
+    pub range: TextRange,
+    /// The range of the target's name, when the server answered with a
+    /// `LocationLink` (its `targetSelectionRange`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selection_range: Option<TextRange>,
+    /// The span of the queried document the target was found for, when the server
+    /// says (a `LocationLink`'s `originSelectionRange`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin_selection_range: Option<TextRange>,
+    /// Where a reference is relative to the queried document (references only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<ReferenceScope>,
+    /// The server that found the target, when companion servers were queried too
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+}
+
+impl DefinitionTarget {
+    /// A target at `range` of `uri`, with nothing else known about it.
+    pub fn new(uri: impl Into<String>, range: TextRange) -> Self {
+        Self {
+            uri: uri.into(),
+            range,
+            selection_range: None,
+            origin_selection_range: None,
+            scope: None,
+            server: None,
+        }
+    }
+
+    pub(crate) fn sort_key(&self) -> (&str, u32, u32, u32, u32) {
+        let range = &self.range;
+        (
+            &self.uri,
+            range.start_line,
+            range.start_character,
+            range.end_line,
+            range.end_character,
+        )
+    }
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, Eq, schemars::JsonSchema)]
+pub struct TextRange {
+    pub start_line: u32,
+    pub start_character: u32,
+    pub end_line: u32,
+    pub end_character: u32,
+}
+
+/// Converts the columns of `targets`, found for a query on `origin`, from the
+/// server's encoding to the client's.
+pub(crate) async fn targets_to_client(
+    converter: &mut PositionConverter,
+    origin: &str,
+    targets: &mut [DefinitionTarget],
+) {
+    for target in targets {
+        range_to_client(converter, &target.uri, &mut target.range).await;
+        if let Some(range) = &mut target.selection_range {
+            range_to_client(converter, &target.uri, range).await;
+        }
+        if let Some(range) = &mut target.origin_selection_range {
+            range_to_client(converter, origin, range).await;
+        }
+    }
+}
+
+async fn range_to_client(converter: &mut PositionConverter, uri: &str, range: &mut TextRange) {
+    range.start_character = converter
+        .to_client(uri, range.start_line, range.start_character)
+        .await;
+    range.end_character = converter
+        .to_client(uri, range.end_line, range.end_character)
+        .await;
+}
+
+/// Normalizes LSP location results into a consistent format.
+///
+/// LSP servers can return locations in several formats:
+/// - null (nothing found)
+/// - Location (single result)
+/// - Location[] (multiple results)
+/// - LocationLink[] (alternative format with more info)
+///
+/// This function converts all formats to a Vec<DefinitionTarget>. Results of typed
+/// requests are converted with [`targets`] instead.
+pub(crate) fn normalize_targets(value: &Value) -> Result<Vec<DefinitionTarget>> {
+    let locations: Option<Locations> = Deserialize::deserialize(value)
+        .map_err(|err| anyhow!("unexpected location response format: {err}"))?;
+    Ok(targets(locations))
+}
+
+/// The targets of a definition-like result.
+pub(crate) fn targets(locations: Option<Locations>) -> Vec<DefinitionTarget> {
+    locations
+        .map(Locations::into_vec)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|location| match location {
+            LocationOrLink::Location(location) => location.into(),
+            LocationOrLink::Link(link) => link.into(),
+        })
+        .collect()
+}
+
+impl From<Location> for DefinitionTarget {
+    fn from(location: Location) -> Self {
+        DefinitionTarget::new(location.uri, location.range.into())
+    }
+}
+
+impl From<LocationLink> for DefinitionTarget {
+    fn from(link: LocationLink) -> Self {
+        DefinitionTarget {
+            selection_range: link.target_selection_range.map(TextRange::from),
+            origin_selection_range: link.origin_selection_range.map(TextRange::from),
+            ..DefinitionTarget::new(link.target_uri, link.target_range.into())
+        }
+    }
+}
+
+impl From<Range> for TextRange {
+    fn from(range: Range) -> Self {
+        TextRange {
+            start_line: range.start.line,
+            start_character: range.start.character,
+            end_line: range.end.line,
+            end_character: range.end.character,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn range(line: u32) -> Value {
+        json!({"start": {"line": line, "character": 0}, "end": {"line": line, "character": 5}})
+    }
+
+    #[test]
+    fn locations_and_links_share_one_shape() {
+        let location = json!({"uri": "file:///a.rs", "range": range(1)});
+        let [target] = normalize_targets(&location).unwrap().try_into().unwrap();
+        assert_eq!(
+            (target.uri.as_str(), target.range.start_line),
+            ("file:///a.rs", 1)
+        );
+        assert!(target.selection_range.is_none() && target.origin_selection_range.is_none());
+
+        let link = json!([{
+            "targetUri": "file:///b.rs",
+            "targetRange": range(2),
+            "targetSelectionRange": range(3),
+            "originSelectionRange": range(9),
+        }]);
+        let [target] = normalize_targets(&link).unwrap().try_into().unwrap();
+        assert_eq!(target.uri, "file:///b.rs");
+        assert_eq!(target.range.start_line, 2);
+        assert_eq!(target.selection_range.unwrap().start_line, 3);
+        assert_eq!(target.origin_selection_range.unwrap().start_line, 9);
+
+        assert!(normalize_targets(&Value::Null).unwrap().is_empty());
+        let err = normalize_targets(&json!([{"uri": "file:///a.rs", "range": {"start": {}}}]))
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("unexpected location response format")
+        );
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+use crate::server_log::{LogLine, ServerLog};
+
+const DEFAULT_LINES: usize = 100;
+
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct ServerLogsRequest {
+    /// Maximum number of most recent lines to return (default 100)
+    pub lines: Option<usize>,
+    /// Only return lines containing this substring
+    pub filter: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ServerLogsResponse {
+    pub lines: Vec<LogLine>,
+    /// Lines currently held in the buffer, before filtering
+    pub buffered: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LogsTool;
+
+impl LogsTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Return recent stderr output of the language server, optionally filtered by substring"
+    }
+
+    pub fn execute(&self, log: &ServerLog, request: ServerLogsRequest) -> ServerLogsResponse {
+        ServerLogsResponse {
+            lines: log.tail(
+                request.lines.unwrap_or(DEFAULT_LINES),
+                request.filter.as_deref(),
+            ),
+            buffered: log.len(),
+        }
+    }
+}
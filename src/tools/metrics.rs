@@ -0,0 +1,19 @@
+use crate::metrics::MetricsReport;
+use crate::supervisor::LspSupervisor;
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MetricsTool;
+
+impl MetricsTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Report tool call and LSP request counts, errors, timeouts, and latency histograms, plus retries and server restarts"
+    }
+
+    pub fn execute(&self, supervisor: &LspSupervisor) -> MetricsReport {
+        supervisor.metrics().report()
+    }
+}
@@ -1,8 +1,61 @@
 //! MCP tool implementations.
 //!
 //! This module provides the implementation of MCP tools that wrap LSP functionality.
-//! Currently supports jump-to-definition, with room for expansion to other LSP features.
+//! Currently supports jump-to-definition and references, with room for expansion to
+//! other LSP features, plus status, server listing, capability, metrics, log, and
+//! restart tools for the language servers themselves, tools for pushing unsaved
+//! document content and checking proposed content against the server's diagnostics,
+//! and workspace file listing, reading, and text search, and fuzzy symbol search over
+//! pathfinder's own index. `outline` maps a directory's symbols, `find_unused_symbols` lists
+//! dead-code candidates by their reference counts, and `dependency_graph` resolves
+//! import statements into a file-level graph.
+//! `lsp_request` (opt-in) forwards arbitrary requests, `rename` and
+//! `code_action` preview workspace edits as diffs before applying them, and
+//! the vendor tools wrap extension methods of specific servers.
 
+pub mod capabilities;
+pub mod check;
 pub mod definition;
+pub mod dependencies;
+pub mod documents;
+pub mod files;
+pub mod locations;
+pub mod logs;
+pub mod metrics;
+pub mod outline;
+pub mod read;
+pub mod refactor;
+pub mod references;
+pub mod request;
+pub mod restart;
+pub mod search;
+pub mod servers;
+pub mod status;
+pub mod symbols;
+pub mod unused;
+pub mod vendor;
 
+pub use capabilities::{CapabilitiesReport, CapabilitiesTool};
+pub use check::{CheckEditRequest, CheckEditResponse, CheckTool};
 pub use definition::{DefinitionRequest, DefinitionResponse, DefinitionTool};
+pub use dependencies::{DependenciesTool, DependencyGraphRequest, DependencyGraphResponse};
+pub use documents::{
+    DocumentResponse, DocumentsTool, OpenDocumentRequest, TextEdit, UpdateDocumentRequest,
+};
+pub use files::{FilesTool, ListFilesRequest, ListFilesResponse};
+pub use logs::{LogsTool, ServerLogsRequest, ServerLogsResponse};
+pub use metrics::MetricsTool;
+pub use outline::{OutlineRequest, OutlineResponse, OutlineTool};
+pub use read::{ReadFileRequest, ReadFileResponse, ReadTool};
+pub use refactor::{
+    CodeActionRequest, CodeActionResponse, EditPreview, RefactorTool, RenameRequest,
+};
+pub use references::{ReferencesRequest, ReferencesResponse, ReferencesTool};
+pub use request::{LspRequestRequest, LspRequestResponse, LspRequestTool};
+pub use restart::{RestartResponse, RestartTool};
+pub use search::{SearchTextRequest, SearchTextResponse, SearchTool, TextMatch};
+pub use servers::{ListServersResponse, ServersTool};
+pub use status::{ServerStatus, StatusTool};
+pub use symbols::{FindSymbolRequest, FindSymbolResponse, SymbolsTool};
+pub use unused::{UnusedSymbolsRequest, UnusedSymbolsResponse, UnusedTool};
+pub use vendor::{Vendor, VendorTool};
@@ -0,0 +1,371 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::task::JoinSet;
+
+use crate::documents::DocumentManager;
+use crate::lsp_bridge::LspBridge;
+use crate::progress::ToolProgress;
+use crate::protocol::{DocumentSymbolParams, DocumentSymbols, Request, TextDocumentIdentifier};
+use crate::tools::files::{FilesTool, ListFilesRequest};
+use crate::utils::{path_to_uri, symbol_kind_name};
+
+const DEFAULT_MAX_FILES: usize = 200;
+const MAX_FILES_LIMIT: usize = 1000;
+const DEFAULT_CONCURRENCY: usize = 4;
+const MAX_CONCURRENCY: usize = 16;
+const DEFAULT_MAX_DEPTH: usize = 2;
+
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct OutlineRequest {
+    /// Directory (or file) to outline, relative to the workspace root (default: the
+    /// whole workspace)
+    pub path: Option<String>,
+    /// Levels of nesting to include: 1 lists top-level symbols only (default 2)
+    pub max_depth: Option<usize>,
+    /// Most files to outline (default 200, at most 1000)
+    pub max_files: Option<usize>,
+    /// documentSymbol requests in flight at once (default 4, at most 16)
+    pub concurrency: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct OutlineResponse {
+    /// Files with at least one symbol, by path
+    pub files: Vec<FileOutline>,
+    /// Files the server failed to answer for, which are left out
+    pub failed: usize,
+    /// True if more files matched than `max_files`
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct FileOutline {
+    pub uri: String,
+    pub symbols: Vec<OutlineSymbol>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq, schemars::JsonSchema)]
+pub struct OutlineSymbol {
+    pub name: String,
+    pub kind: String,
+    /// Line its name is declared on
+    pub line: u32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<OutlineSymbol>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct OutlineTool {
+    progress: ToolProgress,
+}
+
+impl OutlineTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports how many files are done to `progress`.
+    pub fn with_progress(mut self, progress: ToolProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn description() -> &'static str {
+        "Outline a directory: the symbols (textDocument/documentSymbol) of each of its source files, honoring .gitignore, as a compact tree of name, kind, and line per file"
+    }
+
+    pub async fn execute(
+        &self,
+        lsp: &Arc<LspBridge>,
+        documents: &Arc<DocumentManager>,
+        extensions: &[String],
+        request: OutlineRequest,
+    ) -> Result<OutlineResponse> {
+        lsp.server_capabilities()
+            .ensure("documentSymbolProvider", "textDocument/documentSymbol")?;
+        let max_depth = request.max_depth.unwrap_or(DEFAULT_MAX_DEPTH).max(1);
+        let max_files = request.max_files.unwrap_or(DEFAULT_MAX_FILES);
+        let (uris, truncated) = source_files(lsp, request.path, extensions, max_files).await?;
+        let total = uris.len();
+
+        let mut files = Vec::with_capacity(total);
+        let mut failed = 0;
+        let (lsp, documents) = (lsp.clone(), documents.clone());
+        run_limited(
+            uris,
+            concurrency(request.concurrency),
+            move |uri| {
+                let (lsp, documents) = (lsp.clone(), documents.clone());
+                async move {
+                    documents.ensure_open(&lsp, &uri).await?;
+                    let symbols = document_symbols(&lsp, &uri, "outline").await?;
+                    Ok(FileOutline {
+                        symbols: outline(&symbols, max_depth),
+                        uri,
+                    })
+                }
+            },
+            |done, file| {
+                match file {
+                    Ok(file) if file.symbols.is_empty() => {}
+                    Ok(file) => files.push(file),
+                    Err(err) => {
+                        tracing::debug!("Outlining a file failed: {err:#}");
+                        failed += 1;
+                    }
+                }
+                self.progress
+                    .report(format!("Outlined {done}/{total} files"));
+            },
+        )
+        .await;
+
+        files.sort_by(|a, b| a.uri.cmp(&b.uri));
+        Ok(OutlineResponse {
+            files,
+            failed,
+            truncated,
+        })
+    }
+}
+
+/// `symbols` from a documentSymbol response as a tree `max_depth` levels deep, in
+/// source order. Flat `SymbolInformation` results are nested by `containerName`.
+fn outline(symbols: &[Value], max_depth: usize) -> Vec<OutlineSymbol> {
+    let mut outline = if symbols
+        .iter()
+        .any(|symbol| symbol.get("location").is_some())
+    {
+        nest_by_container(symbols, max_depth)
+    } else {
+        symbols
+            .iter()
+            .filter_map(|symbol| tree(symbol, max_depth))
+            .collect()
+    };
+    outline.sort_by_key(|symbol| symbol.line);
+    outline
+}
+
+fn tree(symbol: &Value, depth: usize) -> Option<OutlineSymbol> {
+    let mut children: Vec<OutlineSymbol> = match depth {
+        1 => Vec::new(),
+        _ => symbol["children"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|child| tree(child, depth - 1))
+            .collect(),
+    };
+    children.sort_by_key(|child| child.line);
+    Some(OutlineSymbol {
+        name: symbol["name"].as_str()?.to_string(),
+        kind: symbol_kind_name(symbol["kind"].as_u64().unwrap_or_default()).to_string(),
+        line: symbol["selectionRange"]["start"]["line"].as_u64()? as u32,
+        children,
+    })
+}
+
+/// Nests `SymbolInformation`s under the symbol their `containerName` names, which is
+/// the closest thing to a tree they carry. Only one level is recovered.
+fn nest_by_container(symbols: &[Value], max_depth: usize) -> Vec<OutlineSymbol> {
+    let flat = |symbol: &Value| {
+        Some(OutlineSymbol {
+            name: symbol["name"].as_str()?.to_string(),
+            kind: symbol_kind_name(symbol["kind"].as_u64().unwrap_or_default()).to_string(),
+            line: symbol["location"]["range"]["start"]["line"].as_u64()? as u32,
+            children: Vec::new(),
+        })
+    };
+    let mut top: Vec<OutlineSymbol> = Vec::new();
+    let mut nested = Vec::new();
+    for symbol in symbols {
+        let Some(entry) = flat(symbol) else {
+            continue;
+        };
+        match symbol["containerName"]
+            .as_str()
+            .filter(|name| !name.is_empty())
+        {
+            Some(container) => nested.push((container.to_string(), entry)),
+            None => top.push(entry),
+        }
+    }
+    for (container, entry) in nested {
+        match top.iter_mut().find(|symbol| symbol.name == container) {
+            Some(parent) if max_depth > 1 => parent.children.push(entry),
+            Some(_) => {}
+            None => top.push(entry),
+        }
+    }
+    for symbol in &mut top {
+        symbol.children.sort_by_key(|child| child.line);
+    }
+    top
+}
+
+/// URIs of the files with one of `extensions` under `path` (the workspace by
+/// default), at most `max_files` of them, and whether there were more.
+pub(crate) async fn source_files(
+    lsp: &LspBridge,
+    path: Option<String>,
+    extensions: &[String],
+    max_files: usize,
+) -> Result<(Vec<String>, bool)> {
+    let listed = FilesTool::new()
+        .execute(
+            lsp.workspace(),
+            ListFilesRequest {
+                path,
+                globs: None,
+                extensions: Some(extensions.to_vec()),
+                max_results: Some(max_files.min(MAX_FILES_LIMIT)),
+            },
+        )
+        .await?;
+    let uris = listed
+        .files
+        .iter()
+        .map(|file| path_to_uri(&lsp.workspace().join(file)))
+        .collect::<Result<Vec<_>>>()?;
+    Ok((uris, listed.truncated))
+}
+
+/// The requested number of requests in flight, within the allowed range.
+pub(crate) fn concurrency(requested: Option<usize>) -> usize {
+    requested
+        .unwrap_or(DEFAULT_CONCURRENCY)
+        .clamp(1, MAX_CONCURRENCY)
+}
+
+/// The top-level entries of the documentSymbol response for `uri`, requested with
+/// `tool`'s timeout.
+pub(crate) async fn document_symbols(lsp: &LspBridge, uri: &str, tool: &str) -> Result<Vec<Value>> {
+    let params = DocumentSymbolParams {
+        text_document: TextDocumentIdentifier::new(uri),
+    };
+    let symbols = lsp
+        .call::<DocumentSymbols>(&params, lsp.tool_timeout(tool, DocumentSymbols::METHOD))
+        .await
+        .context("LSP documentSymbol request failed")?;
+    Ok(symbols.unwrap_or_default())
+}
+
+/// Runs `task` on each of `items`, at most `limit` at a time, handing each outcome
+/// to `done` with the number finished so far.
+pub(crate) async fn run_limited<I, R, F, Fut>(
+    items: Vec<I>,
+    limit: usize,
+    task: F,
+    mut done: impl FnMut(usize, Result<R>),
+) where
+    F: Fn(I) -> Fut,
+    Fut: Future<Output = Result<R>> + Send + 'static,
+    R: Send + 'static,
+{
+    let mut running = JoinSet::new();
+    let mut finished = 0;
+    for item in items {
+        if running.len() >= limit
+            && let Some(joined) = running.join_next().await
+        {
+            finished += 1;
+            done(
+                finished,
+                joined.map_err(anyhow::Error::from).and_then(|r| r),
+            );
+        }
+        running.spawn(task(item));
+    }
+    while let Some(joined) = running.join_next().await {
+        finished += 1;
+        done(
+            finished,
+            joined.map_err(anyhow::Error::from).and_then(|r| r),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    fn range(line: u32) -> Value {
+        json!({"start": {"line": line, "character": 0}, "end": {"line": line, "character": 4}})
+    }
+
+    fn names(symbols: &[OutlineSymbol]) -> Vec<(&str, &str, u32, usize)> {
+        symbols
+            .iter()
+            .map(|symbol| {
+                let children = symbol.children.len();
+                (
+                    symbol.name.as_str(),
+                    symbol.kind.as_str(),
+                    symbol.line,
+                    children,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn nested_symbols_are_cut_at_the_depth() {
+        let method =
+            json!({"name": "run", "kind": 6, "range": range(3), "selectionRange": range(3)});
+        let local = json!({"name": "x", "kind": 13, "range": range(4), "selectionRange": range(4)});
+        let mut method_with_local = method.clone();
+        method_with_local["children"] = json!([local]);
+        let symbols = [
+            json!({"name": "Server", "kind": 5, "range": range(2), "selectionRange": range(2), "children": [method_with_local]}),
+            json!({"name": "main", "kind": 12, "range": range(0), "selectionRange": range(0)}),
+        ];
+
+        let outlined = outline(&symbols, 2);
+        assert_eq!(
+            names(&outlined),
+            [("main", "function", 0, 0), ("Server", "class", 2, 1)]
+        );
+        assert_eq!(names(&outlined[1].children), [("run", "method", 3, 0)]);
+        assert!(
+            outline(&symbols, 1)
+                .iter()
+                .all(|symbol| symbol.children.is_empty())
+        );
+    }
+
+    #[test]
+    fn flat_symbols_nest_by_container() {
+        let information = |name: &str, kind: u32, line: u32, container: Option<&str>| {
+            json!({
+                "name": name,
+                "kind": kind,
+                "location": {"uri": "file:///a.py", "range": range(line)},
+                "containerName": container,
+            })
+        };
+        let symbols = [
+            information("helper", 12, 9, None),
+            information("Handler", 5, 1, None),
+            information("handle", 6, 4, Some("Handler")),
+            information("orphan", 6, 7, Some("Missing")),
+        ];
+
+        let outlined = outline(&symbols, 2);
+        assert_eq!(
+            names(&outlined),
+            [
+                ("Handler", "class", 1, 1),
+                ("orphan", "method", 7, 0),
+                ("helper", "function", 9, 0)
+            ]
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+
+use crate::documents::DocumentManager;
+use crate::lsp_bridge::LspBridge;
+use crate::notebook::{self, Notebook};
+use crate::utils::uri_to_file_path;
+
+/// Most lines returned by one call, context included.
+const MAX_LINES: u32 = 500;
+/// Longer lines (minified code, data) are cut to this many characters.
+const MAX_LINE_CHARS: usize = 2000;
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct ReadFileRequest {
+    /// file:// URI, or a path relative to the workspace root; `nb.ipynb#cell2` reads
+    /// one cell of a notebook
+    pub path: String,
+    /// Zero-based first line
+    pub start_line: u32,
+    /// Zero-based last line, inclusive (default: start_line)
+    pub end_line: Option<u32>,
+    /// Extra lines to include before and after the range (default 0)
+    pub context: Option<u32>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ReadFileResponse {
+    pub uri: String,
+    /// languageId the file is opened with on the language server
+    pub language_id: String,
+    /// Zero-based first line returned
+    pub start_line: u32,
+    /// Zero-based last line returned, inclusive
+    pub end_line: u32,
+    /// Lines in the whole file
+    pub total_lines: u32,
+    pub text: String,
+    /// True if the range or a line in it was cut short
+    pub truncated: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReadTool;
+
+impl ReadTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Read a range of lines from a workspace file, with optional context"
+    }
+
+    /// Reads from `documents` so unsaved overlay content is returned as the language
+    /// server sees it. `request.path` has been through [`Sandbox::document_uri`], so it
+    /// is a file:// URI or a notebook cell URI.
+    ///
+    /// [`Sandbox::document_uri`]: crate::sandbox::Sandbox::document_uri
+    pub async fn execute(
+        &self,
+        lsp: &LspBridge,
+        documents: &DocumentManager,
+        request: ReadFileRequest,
+    ) -> Result<ReadFileResponse> {
+        let (uri, cell) = match notebook::split_cell_uri(&request.path) {
+            Some((notebook, index)) => (notebook, Some(index)),
+            None => (request.path.clone(), None),
+        };
+        let path = uri_to_file_path(&uri)?;
+        if !path.is_file() {
+            return Err(anyhow!("not a file: {}", path.display()));
+        }
+        let text = documents.current_text(&uri, &path).await?;
+        let (uri, text, language_id) = match cell {
+            Some(index) => {
+                let notebook = Notebook::parse(&text)?;
+                let cell = notebook.cell(index)?;
+                let language_id = cell.language.clone();
+                (
+                    notebook::cell_uri(&uri, index),
+                    cell.text.clone(),
+                    language_id,
+                )
+            }
+            None => {
+                let language_id = lsp.language_id(&path, &text).to_string();
+                (uri, text, language_id)
+            }
+        };
+
+        let end_line = request.end_line.unwrap_or(request.start_line);
+        if end_line < request.start_line {
+            return Err(anyhow!(
+                "end_line {end_line} is before start_line {}",
+                request.start_line
+            ));
+        }
+        let context = request.context.unwrap_or(0);
+        let lines: Vec<&str> = text.lines().collect();
+        let total_lines = lines.len() as u32;
+        let first = request.start_line.saturating_sub(context);
+        if first >= total_lines.max(1) {
+            return Err(anyhow!(
+                "start_line {} is past the end of the file ({total_lines} lines)",
+                request.start_line
+            ));
+        }
+        let wanted = end_line
+            .saturating_add(context)
+            .min(total_lines.saturating_sub(1));
+        let last = wanted.min(first + MAX_LINES - 1);
+        let mut truncated = last < wanted;
+
+        let mut excerpt = Vec::new();
+        for line in lines.iter().take(last as usize + 1).skip(first as usize) {
+            match line.char_indices().nth(MAX_LINE_CHARS) {
+                Some((cut, _)) => {
+                    excerpt.push(&line[..cut]);
+                    truncated = true;
+                }
+                None => excerpt.push(line),
+            }
+        }
+        Ok(ReadFileResponse {
+            language_id,
+            uri,
+            start_line: first,
+            end_line: last,
+            total_lines,
+            text: excerpt.join("\n"),
+            truncated,
+        })
+    }
+}
@@ -0,0 +1,373 @@
+//! Rename and code-action tools.
+//!
+//! Both turn the server's `WorkspaceEdit` into a preview first: the edit is applied in
+//! memory to the current contents of every affected file (overlay text where a
+//! document has unsaved edits) and shown as one unified diff. Nothing is written
+//! unless the call passes `apply: true`; the service then writes the previewed
+//! contents, all files or none, and resyncs the documents.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use url::Url;
+
+use crate::edits::{WorkspaceChanges, preview_workspace_edit};
+use crate::lsp_bridge::LspBridge;
+use crate::position::PositionConverter;
+use crate::protocol::{
+    CodeActionContext, CodeActionParams, CodeActionTriggerKind, CodeActions, Position, Range,
+    Rename, RenameParams, Request, TextDocumentIdentifier, TextDocumentPositionParams,
+};
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct RenameRequest {
+    /// file:// URI of the document, or its absolute or workspace-relative path
+    pub uri: String,
+    /// Zero-based line index
+    pub line: u32,
+    /// Zero-based character index, in the configured column encoding (UTF-16 by default)
+    pub character: u32,
+    /// New name for the symbol
+    pub new_name: String,
+    /// Write the changes to disk (default false: only preview them)
+    pub apply: Option<bool>,
+}
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct CodeActionRequest {
+    /// file:// URI of the document, or its absolute or workspace-relative path
+    pub uri: String,
+    /// Zero-based line where the range starts
+    pub start_line: u32,
+    /// Zero-based character where the range starts, in the configured column encoding
+    pub start_character: u32,
+    /// Zero-based line where the range ends (default: start_line)
+    pub end_line: Option<u32>,
+    /// Zero-based character where the range ends (default: start_character)
+    pub end_character: Option<u32>,
+    /// Only actions of this kind or its sub-kinds, e.g. "quickfix" or "refactor.extract"
+    pub kind: Option<String>,
+    /// Index of the action to preview in full (or apply), from the list this tool
+    /// returns for the same range
+    pub action: Option<usize>,
+    /// Write the chosen action's changes to disk and run its command, if any
+    /// (default false: only preview them)
+    pub apply: Option<bool>,
+}
+
+#[derive(Debug, Serialize, Clone, Default, schemars::JsonSchema)]
+pub struct EditPreview {
+    /// Files the edit changes
+    pub files: Vec<ChangedFile>,
+    /// Unified diff of all changes, with paths relative to the workspace
+    pub diff: String,
+    /// True if the changes were written to disk
+    pub applied: bool,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ChangedFile {
+    pub uri: String,
+    pub change: ChangeKind,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Modified,
+    Created,
+    Deleted,
+}
+
+#[derive(Debug, Serialize, Clone, Default, schemars::JsonSchema)]
+pub struct CodeActionResponse {
+    /// Actions the server offers for the range
+    pub actions: Vec<CodeActionSummary>,
+    /// Preview of the action chosen with `action`
+    pub selected: Option<EditPreview>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct CodeActionSummary {
+    /// Pass as `action` to preview or apply this action
+    pub index: usize,
+    pub title: String,
+    pub kind: Option<String>,
+    /// The server's preferred action for the range, e.g. the usual quick fix
+    pub preferred: bool,
+    /// Why the action cannot be applied, if it cannot
+    pub disabled: Option<String>,
+    /// Command the server runs when the action is applied; its changes are not part
+    /// of the preview
+    pub command: Option<String>,
+}
+
+/// An edit worked out in memory, ready to be written.
+#[derive(Debug, Clone, Default)]
+pub struct PlannedEdit {
+    pub preview: EditPreview,
+    pub changes: WorkspaceChanges,
+    /// `Command` to execute on the server once the changes are written.
+    pub command: Option<Value>,
+}
+
+impl PlannedEdit {
+    /// Previews `edit` (a `WorkspaceEdit`, in the server's position encoding).
+    pub fn new(
+        edit: &Value,
+        lsp: &LspBridge,
+        overlays: &HashMap<String, String>,
+        workspace: &Path,
+    ) -> Result<Self> {
+        let changes = preview_workspace_edit(edit, lsp.position_encoding(), overlays)?;
+        let files = changes
+            .files
+            .iter()
+            .map(|change| ChangedFile {
+                uri: Url::from_file_path(&change.path)
+                    .map(String::from)
+                    .unwrap_or_else(|_| change.path.display().to_string()),
+                change: match (&change.before, &change.after) {
+                    (None, _) => ChangeKind::Created,
+                    (_, None) => ChangeKind::Deleted,
+                    _ => ChangeKind::Modified,
+                },
+            })
+            .collect();
+        let diff = changes
+            .files
+            .iter()
+            .map(|change| change.diff(workspace))
+            .collect();
+        Ok(Self {
+            preview: EditPreview {
+                files,
+                diff,
+                applied: false,
+            },
+            changes,
+            command: None,
+        })
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RefactorTool;
+
+impl RefactorTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// `textDocument/rename`, previewed.
+    pub async fn rename(
+        &self,
+        lsp: &LspBridge,
+        request: &RenameRequest,
+        overlays: HashMap<String, String>,
+        workspace: &Path,
+    ) -> Result<PlannedEdit> {
+        let method = Rename::METHOD;
+        lsp.server_capabilities().ensure("renameProvider", method)?;
+        if request.new_name.trim().is_empty() {
+            bail!("new_name must not be empty");
+        }
+        let mut converter = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+            .with_texts(overlays.clone());
+        let character = converter
+            .to_server(&request.uri, request.line, request.character)
+            .await;
+        let params = RenameParams {
+            text_document_position: TextDocumentPositionParams::new(
+                &request.uri,
+                request.line,
+                character,
+            ),
+            new_name: request.new_name.clone(),
+        };
+        let edit = lsp
+            .call::<Rename>(&params, lsp.tool_timeout("rename", method))
+            .await
+            .with_context(|| format!("LSP {method} request failed"))?;
+        let Some(edit) = edit.filter(|edit| !edit.is_null()) else {
+            bail!(
+                "nothing to rename at {}:{}",
+                request.line,
+                request.character
+            );
+        };
+        PlannedEdit::new(&edit, lsp, &overlays, workspace)
+    }
+
+    /// `textDocument/codeAction`, with the action picked by `request.action`
+    /// resolved and previewed.
+    pub async fn code_actions(
+        &self,
+        lsp: &LspBridge,
+        request: &CodeActionRequest,
+        overlays: HashMap<String, String>,
+        workspace: &Path,
+    ) -> Result<(CodeActionResponse, Option<PlannedEdit>)> {
+        let method = CodeActions::METHOD;
+        lsp.server_capabilities()
+            .ensure("codeActionProvider", method)?;
+        let mut converter = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+            .with_texts(overlays.clone());
+        let (start_line, end_line) = (
+            request.start_line,
+            request.end_line.unwrap_or(request.start_line),
+        );
+        let start_character = converter
+            .to_server(&request.uri, start_line, request.start_character)
+            .await;
+        let end_character = converter
+            .to_server(
+                &request.uri,
+                end_line,
+                request.end_character.unwrap_or(request.start_character),
+            )
+            .await;
+        let params = CodeActionParams {
+            text_document: TextDocumentIdentifier::new(&request.uri),
+            range: Range {
+                start: Position {
+                    line: start_line,
+                    character: start_character,
+                },
+                end: Position {
+                    line: end_line,
+                    character: end_character,
+                },
+            },
+            context: CodeActionContext {
+                diagnostics: Vec::new(),
+                only: request.kind.clone().map(|kind| vec![kind]),
+                trigger_kind: Some(CodeActionTriggerKind::Invoked),
+            },
+        };
+        let offered = lsp
+            .call::<CodeActions>(&params, lsp.tool_timeout("code_action", method))
+            .await
+            .with_context(|| format!("LSP {method} request failed"))?
+            .unwrap_or_default();
+        let actions = offered
+            .iter()
+            .enumerate()
+            .map(|(index, action)| summarize(index, action))
+            .collect();
+
+        let Some(index) = request.action else {
+            return Ok((
+                CodeActionResponse {
+                    actions,
+                    selected: None,
+                },
+                None,
+            ));
+        };
+        let chosen = offered.get(index).ok_or_else(|| {
+            anyhow!(
+                "no code action {index}: the server offered {}",
+                offered.len()
+            )
+        })?;
+        let planned = plan_action(lsp, chosen, &overlays, workspace).await?;
+        Ok((
+            CodeActionResponse {
+                actions,
+                selected: Some(planned.preview.clone()),
+            },
+            Some(planned),
+        ))
+    }
+}
+
+/// The list entry for `action`, a `CodeAction` or a bare `Command`.
+fn summarize(index: usize, action: &Value) -> CodeActionSummary {
+    let text = |value: Option<&Value>| value.and_then(Value::as_str).map(str::to_string);
+    // A bare Command has a string `command`; a CodeAction nests a Command object
+    let command = match action.get("command") {
+        Some(Value::String(command)) => Some(command.clone()),
+        nested => text(nested.and_then(|command| command.get("command"))),
+    };
+    CodeActionSummary {
+        index,
+        title: text(action.get("title")).unwrap_or_default(),
+        kind: text(action.get("kind")),
+        preferred: action
+            .get("isPreferred")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+        disabled: text(action.pointer("/disabled/reason")),
+        command,
+    }
+}
+
+/// Resolves `action` if its edit is left out, and previews the edit.
+async fn plan_action(
+    lsp: &LspBridge,
+    action: &Value,
+    overlays: &HashMap<String, String>,
+    workspace: &Path,
+) -> Result<PlannedEdit> {
+    if let Some(reason) = action.pointer("/disabled/reason").and_then(Value::as_str) {
+        bail!("code action is disabled: {reason}");
+    }
+    if action.get("command").is_some_and(Value::is_string) {
+        // A bare Command: nothing to preview, only to run
+        return Ok(PlannedEdit {
+            command: Some(action.clone()),
+            ..PlannedEdit::default()
+        });
+    }
+    let mut action = action.clone();
+    if action.get("edit").is_none() && lsp.server_capabilities().resolves_code_actions() {
+        let method = "codeAction/resolve";
+        action = lsp
+            .request_with_timeout(method, action, lsp.tool_timeout("code_action", method))
+            .await
+            .with_context(|| format!("LSP {method} request failed"))?;
+    }
+    let mut planned = match action.get("edit") {
+        Some(edit) => PlannedEdit::new(edit, lsp, overlays, workspace)?,
+        None => PlannedEdit::default(),
+    };
+    planned.command = action.get("command").cloned();
+    Ok(planned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn summarizes_code_actions_and_commands() {
+        let action = json!({
+            "title": "Extract into function",
+            "kind": "refactor.extract",
+            "isPreferred": true,
+            "command": {"title": "Rename", "command": "editor.action.rename"},
+        });
+        let summary = summarize(0, &action);
+        assert_eq!(summary.title, "Extract into function");
+        assert_eq!(summary.kind.as_deref(), Some("refactor.extract"));
+        assert!(summary.preferred);
+        assert_eq!(summary.command.as_deref(), Some("editor.action.rename"));
+
+        let command = json!({"title": "Organize imports", "command": "organizeImports"});
+        let summary = summarize(1, &command);
+        assert_eq!(summary.kind, None);
+        assert_eq!(summary.command.as_deref(), Some("organizeImports"));
+
+        let disabled = json!({"title": "Inline", "disabled": {"reason": "not a variable"}});
+        assert_eq!(
+            summarize(2, &disabled).disabled.as_deref(),
+            Some("not a variable")
+        );
+    }
+}
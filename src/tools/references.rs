@@ -0,0 +1,411 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use globset::GlobSet;
+use serde::{Deserialize, Serialize};
+
+use crate::init::PRESETS;
+use crate::lsp_bridge::LspBridge;
+use crate::pagination::Page;
+use crate::position::PositionConverter;
+use crate::protocol::{
+    Location, ReferenceContext, ReferenceParams, References, Request, TextDocumentPositionParams,
+};
+use crate::tools::definition::{SnappedPosition, TargetOptions, snap_position};
+use crate::tools::files::build_globs;
+use crate::tools::locations::{DefinitionTarget, targets_to_client};
+use crate::utils::{resolve_workspace_path, strip_path_prefix, uri_to_file_path};
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct ReferencesRequest {
+    /// file:// URI of the document, or its absolute or workspace-relative path
+    pub uri: String,
+    /// Zero-based line index
+    pub line: u32,
+    /// Zero-based character index, in the configured column encoding (UTF-16 by default)
+    pub character: u32,
+    /// Include the symbol's declaration among the references (default true)
+    pub include_declaration: Option<bool>,
+    /// If nothing is found at the position, retry at the nearest identifier on the
+    /// line (default false)
+    pub snap: Option<bool>,
+    /// Ask only this server, by a name `list_servers` reports (default: the main
+    /// server and any companions configured for the document)
+    pub server: Option<String>,
+    #[serde(flatten)]
+    pub filter: ReferenceFilter,
+    #[serde(flatten)]
+    pub options: TargetOptions,
+}
+
+/// Which references to keep, by location.
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct ReferenceFilter {
+    /// Only keep references under this directory, relative to the workspace root
+    pub path: Option<String>,
+    /// Only keep references in files matching one of these globs, relative to the
+    /// workspace root (e.g. "src/**")
+    pub globs: Option<Vec<String>>,
+    /// Drop references in files matching one of these globs (e.g. "**/tests/**")
+    pub exclude_globs: Option<Vec<String>>,
+    /// Only keep references in these scopes
+    pub scopes: Option<Vec<ReferenceScope>>,
+}
+
+impl ReferenceFilter {
+    fn cache_key(&self) -> String {
+        format!(
+            "{:?} {:?} {:?} {:?}",
+            self.path, self.globs, self.exclude_globs, self.scopes
+        )
+    }
+}
+
+/// Where a reference is, seen from the queried document.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Hash, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ReferenceScope {
+    /// The queried document itself
+    File,
+    /// Another file of the same package (the nearest directory with a project
+    /// manifest such as Cargo.toml or package.json)
+    Package,
+    /// Another package in the workspace
+    Workspace,
+    /// Outside the workspace, e.g. in a dependency
+    External,
+}
+
+impl ReferencesRequest {
+    /// Identifies the query for pagination cursors.
+    pub fn cache_key(&self) -> String {
+        format!(
+            "references {} {}:{} {} {} {:?} {} {}",
+            self.uri,
+            self.line,
+            self.character,
+            self.include_declaration(),
+            self.snap(),
+            self.server,
+            self.filter.cache_key(),
+            self.options.cache_key()
+        )
+    }
+
+    fn include_declaration(&self) -> bool {
+        self.include_declaration.unwrap_or(true)
+    }
+
+    fn snap(&self) -> bool {
+        self.snap.unwrap_or(false)
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Default, schemars::JsonSchema)]
+pub struct ReferencesResponse {
+    pub references: Vec<DefinitionTarget>,
+    /// Number of references across all pages
+    pub total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    /// The position the request was retried at, if `snap` moved it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapped: Option<SnappedPosition>,
+    /// True if the server found more references than `max_results`
+    pub truncated: bool,
+}
+
+impl From<Page<DefinitionTarget>> for ReferencesResponse {
+    fn from(page: Page<DefinitionTarget>) -> Self {
+        Self {
+            references: page.items,
+            total: page.total,
+            next_cursor: page.next_cursor,
+            snapped: None,
+            truncated: false,
+        }
+    }
+}
+
+/// Every reference found, before pagination.
+#[derive(Debug, Clone, Default)]
+pub struct FoundReferences {
+    pub references: Vec<DefinitionTarget>,
+    pub snapped: Option<SnappedPosition>,
+    pub truncated: bool,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReferencesTool;
+
+impl ReferencesTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Return LSP-backed references to the symbol at a given URI and position"
+    }
+
+    /// Returns every reference; the service pages through them.
+    ///
+    /// `overlays` holds the text of unsaved overlay documents, used in place of the
+    /// files on disk when converting columns.
+    pub async fn execute(
+        &self,
+        lsp: &LspBridge,
+        request: ReferencesRequest,
+        overlays: HashMap<String, String>,
+    ) -> Result<FoundReferences> {
+        lsp.server_capabilities()
+            .ensure("referencesProvider", References::METHOD)?;
+        let mut classifier = Classifier::new(lsp.workspace(), &request)?;
+
+        let mut converter = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+            .with_texts(overlays);
+        let character = converter
+            .to_server(&request.uri, request.line, request.character)
+            .await;
+        let mut params = ReferenceParams {
+            text_document_position: TextDocumentPositionParams::new(
+                &request.uri,
+                request.line,
+                character,
+            ),
+            context: ReferenceContext {
+                include_declaration: request.include_declaration(),
+            },
+        };
+        let request_timeout = lsp.tool_timeout("references", References::METHOD);
+        let locations = lsp
+            .call::<References>(&params, request_timeout)
+            .await
+            .context("LSP references request failed")?;
+        let mut references = into_targets(locations);
+
+        let mut snapped = None;
+        if references.is_empty()
+            && request.snap()
+            && let Some((position, character)) = snap_position(
+                &mut converter,
+                &request.uri,
+                request.line,
+                request.character,
+            )
+            .await
+        {
+            tracing::debug!(uri = %request.uri, ?position, "References empty, retrying at snapped position");
+            params.text_document_position.position.character = character;
+            lsp.metrics().record_retry("references");
+            let locations = lsp
+                .call::<References>(&params, request_timeout)
+                .await
+                .context("LSP references request failed")?;
+            references = into_targets(locations);
+            snapped = Some(position);
+        }
+
+        targets_to_client(&mut converter, &request.uri, &mut references).await;
+        references.retain_mut(|reference| classifier.keep(reference));
+        let truncated = request.options.apply(&mut references, lsp.workspace());
+        Ok(FoundReferences {
+            references,
+            snapped,
+            truncated,
+        })
+    }
+}
+
+/// Classifies references by scope and applies a [`ReferenceFilter`].
+struct Classifier<'a> {
+    workspace: &'a Path,
+    queried: Option<PathBuf>,
+    queried_package: Option<PathBuf>,
+    directory: Option<PathBuf>,
+    globs: Option<GlobSet>,
+    exclude_globs: Option<GlobSet>,
+    scopes: Option<Vec<ReferenceScope>>,
+    /// Package root of each directory seen so far
+    packages: HashMap<PathBuf, Option<PathBuf>>,
+}
+
+impl<'a> Classifier<'a> {
+    fn new(workspace: &'a Path, request: &ReferencesRequest) -> Result<Self> {
+        let filter = &request.filter;
+        let directory = filter
+            .path
+            .as_deref()
+            .map(|path| resolve_workspace_path(workspace, path))
+            .transpose()?;
+        let mut classifier = Self {
+            workspace,
+            queried: uri_to_file_path(&request.uri).ok(),
+            queried_package: None,
+            directory,
+            globs: build_globs(filter.globs.as_deref().unwrap_or_default())?,
+            exclude_globs: build_globs(filter.exclude_globs.as_deref().unwrap_or_default())?,
+            scopes: filter.scopes.clone(),
+            packages: HashMap::new(),
+        };
+        classifier.queried_package = classifier
+            .queried
+            .clone()
+            .and_then(|queried| classifier.package(&queried));
+        Ok(classifier)
+    }
+
+    /// Sets `reference`'s scope and returns whether the filter keeps it.
+    fn keep(&mut self, reference: &mut DefinitionTarget) -> bool {
+        let Ok(path) = uri_to_file_path(&reference.uri) else {
+            return self.globs.is_none() && self.directory.is_none();
+        };
+        let scope = self.scope(&path);
+        reference.scope = Some(scope);
+        if self
+            .scopes
+            .as_ref()
+            .is_some_and(|scopes| !scopes.contains(&scope))
+        {
+            return false;
+        }
+        if self
+            .directory
+            .as_ref()
+            .is_some_and(|directory| strip_path_prefix(&path, directory).is_none())
+        {
+            return false;
+        }
+        let relative = strip_path_prefix(&path, self.workspace);
+        if let Some(globs) = &self.globs
+            && !relative.is_some_and(|relative| globs.is_match(relative))
+        {
+            return false;
+        }
+        if let Some(exclude_globs) = &self.exclude_globs
+            && relative.is_some_and(|relative| exclude_globs.is_match(relative))
+        {
+            return false;
+        }
+        true
+    }
+
+    fn scope(&mut self, path: &Path) -> ReferenceScope {
+        if self.queried.as_deref() == Some(path) {
+            ReferenceScope::File
+        } else if !path.starts_with(self.workspace) {
+            ReferenceScope::External
+        } else if self.package(path) == self.queried_package {
+            ReferenceScope::Package
+        } else {
+            ReferenceScope::Workspace
+        }
+    }
+
+    /// The nearest directory above `file`, within the workspace, holding a project
+    /// manifest of any known language.
+    fn package(&mut self, file: &Path) -> Option<PathBuf> {
+        let directory = file.parent()?;
+        if let Some(package) = self.packages.get(directory) {
+            return package.clone();
+        }
+        let package = directory
+            .ancestors()
+            .take_while(|ancestor| ancestor.starts_with(self.workspace))
+            .find(|ancestor| {
+                PRESETS
+                    .iter()
+                    .flat_map(|preset| preset.markers)
+                    .any(|marker| ancestor.join(marker).is_file())
+            })
+            .map(Path::to_path_buf);
+        self.packages
+            .insert(directory.to_path_buf(), package.clone());
+        package
+    }
+}
+
+/// The targets of a references result.
+fn into_targets(locations: Option<Vec<Location>>) -> Vec<DefinitionTarget> {
+    locations
+        .into_iter()
+        .flatten()
+        .map(DefinitionTarget::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::locations::TextRange;
+
+    fn reference(path: &Path) -> DefinitionTarget {
+        DefinitionTarget::new(
+            url::Url::from_file_path(path).unwrap().to_string(),
+            TextRange {
+                start_line: 0,
+                start_character: 0,
+                end_line: 0,
+                end_character: 1,
+            },
+        )
+    }
+
+    #[test]
+    fn references_are_classified_and_filtered() {
+        let dir = tempfile::tempdir().unwrap();
+        let workspace = dir.path().canonicalize().unwrap();
+        for package in ["app", "lib"] {
+            std::fs::create_dir_all(workspace.join(package).join("tests")).unwrap();
+            std::fs::write(workspace.join(package).join("Cargo.toml"), "").unwrap();
+        }
+        let queried = workspace.join("app/main.rs");
+        let request = |filter: ReferenceFilter| ReferencesRequest {
+            uri: url::Url::from_file_path(&queried).unwrap().to_string(),
+            line: 0,
+            character: 0,
+            include_declaration: None,
+            snap: None,
+            server: None,
+            filter,
+            options: TargetOptions::default(),
+        };
+        let paths = [
+            queried.clone(),
+            workspace.join("app/tests/it.rs"),
+            workspace.join("lib/lib.rs"),
+            std::env::temp_dir().join("registry/dep.rs"),
+        ];
+        let kept = |filter: ReferenceFilter| {
+            let mut classifier = Classifier::new(&workspace, &request(filter)).unwrap();
+            paths
+                .iter()
+                .filter_map(|path| {
+                    let mut reference = reference(path);
+                    classifier.keep(&mut reference).then_some(reference.scope)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        use ReferenceScope::*;
+        assert_eq!(
+            kept(ReferenceFilter::default()),
+            [Some(File), Some(Package), Some(Workspace), Some(External)]
+        );
+        assert_eq!(
+            kept(ReferenceFilter {
+                exclude_globs: Some(vec!["**/tests/**".to_string()]),
+                scopes: Some(vec![File, Package, Workspace]),
+                ..ReferenceFilter::default()
+            }),
+            [Some(File), Some(Workspace)]
+        );
+        assert_eq!(
+            kept(ReferenceFilter {
+                path: Some("lib".to_string()),
+                ..ReferenceFilter::default()
+            }),
+            [Some(Workspace)]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::lsp_bridge::LspBridge;
+
+/// Methods that would break pathfinder's own session with the server.
+pub(crate) const RESERVED_METHODS: &[&str] = &["initialize", "shutdown"];
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct LspRequestRequest {
+    /// LSP method, e.g. `rust-analyzer/expandMacro` or `textDocument/switchSourceHeader`
+    pub method: String,
+    /// Request params, sent as given: positions use the server's encoding and
+    /// documents are file:// URIs
+    #[serde(default)]
+    pub params: Value,
+    /// Document (file:// URI or path) to sync to the server first, for methods that
+    /// take a `textDocument`
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct LspRequestResponse {
+    /// The server's `result`, unchanged
+    pub result: Value,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LspRequestTool;
+
+impl LspRequestTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Send any request to the language server and return its raw result"
+    }
+
+    pub async fn execute(
+        &self,
+        lsp: &LspBridge,
+        request: LspRequestRequest,
+    ) -> Result<LspRequestResponse> {
+        if RESERVED_METHODS.contains(&request.method.as_str()) {
+            bail!("{} is managed by pathfinder itself", request.method);
+        }
+        let result = lsp.request(&request.method, request.params).await?;
+        Ok(LspRequestResponse { result })
+    }
+}
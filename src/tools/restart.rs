@@ -0,0 +1,40 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::supervisor::LspSupervisor;
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct RestartResponse {
+    /// Process id of the new server
+    pub pid: Option<u32>,
+    /// `serverInfo` from the new server's initialize result
+    pub server: Option<Value>,
+    /// Documents replayed to the new server
+    pub open_documents: usize,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct RestartTool;
+
+impl RestartTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Shut down the language server and start a fresh one, reopening open documents"
+    }
+
+    pub async fn execute(&self, supervisor: &Arc<LspSupervisor>) -> Result<RestartResponse> {
+        supervisor.restart().await?;
+        let lsp = supervisor.bridge();
+        Ok(RestartResponse {
+            pid: lsp.pid(),
+            server: lsp.server_info().cloned(),
+            open_documents: supervisor.documents().open_count(),
+        })
+    }
+}
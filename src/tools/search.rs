@@ -0,0 +1,195 @@
+use std::path::Path;
+use std::sync::Mutex as StdMutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::{Context, Result};
+use ignore::{WalkBuilder, WalkState};
+use regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+
+use crate::encoding;
+use crate::position::{PositionEncoding, byte_to_column};
+use crate::tools::files::{build_globs, display_path, matches};
+use crate::utils::resolve_workspace_path;
+
+const DEFAULT_MAX_RESULTS: usize = 200;
+const MAX_RESULTS_LIMIT: usize = 5000;
+/// Larger files are skipped; they are almost always generated or data.
+const MAX_SEARCHED_FILE_BYTES: u64 = 10 * 1024 * 1024;
+/// Matched lines are cut to this many characters in results.
+const MAX_LINE_CHARS: usize = 500;
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct SearchTextRequest {
+    /// Regular expression (Rust regex syntax), or plain text with literal = true
+    pub pattern: String,
+    /// Treat pattern as plain text instead of a regular expression (default false)
+    pub literal: Option<bool>,
+    /// Match case exactly (default true)
+    pub case_sensitive: Option<bool>,
+    /// Directory to search, relative to the workspace root (default: the whole workspace)
+    pub path: Option<String>,
+    /// Only search files matching one of these globs, relative to the workspace root
+    pub globs: Option<Vec<String>>,
+    /// Only search files with one of these extensions
+    pub extensions: Option<Vec<String>>,
+    /// Maximum number of matches to return (default 200, at most 5000)
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct SearchTextResponse {
+    pub matches: Vec<TextMatch>,
+    /// True if the search stopped at max_results
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct TextMatch {
+    /// Path relative to the workspace root
+    pub path: String,
+    /// Zero-based line
+    pub line: u32,
+    /// Zero-based column of the match start, in the configured column encoding
+    pub column: u32,
+    pub match_text: String,
+    pub line_text: String,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SearchTool;
+
+impl SearchTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Search workspace files for a regular expression or literal text, honoring .gitignore"
+    }
+
+    /// Searches on blocking threads; columns are reported in `encoding`.
+    pub async fn execute(
+        &self,
+        root: &Path,
+        encoding: PositionEncoding,
+        request: SearchTextRequest,
+    ) -> Result<SearchTextResponse> {
+        let root = root.to_path_buf();
+        tokio::task::spawn_blocking(move || search(&root, encoding, request))
+            .await
+            .context("search task failed")?
+    }
+}
+
+fn search(
+    root: &Path,
+    encoding: PositionEncoding,
+    request: SearchTextRequest,
+) -> Result<SearchTextResponse> {
+    let pattern = if request.literal.unwrap_or(false) {
+        regex::escape(&request.pattern)
+    } else {
+        request.pattern.clone()
+    };
+    let regex = RegexBuilder::new(&pattern)
+        .case_insensitive(!request.case_sensitive.unwrap_or(true))
+        .build()
+        .with_context(|| format!("invalid pattern '{}'", request.pattern))?;
+    let start = match request.path.as_deref() {
+        Some(path) => resolve_workspace_path(root, path)?,
+        None => root.to_path_buf(),
+    };
+    let globs = build_globs(request.globs.as_deref().unwrap_or_default())?;
+    let extensions: Vec<&str> = request
+        .extensions
+        .iter()
+        .flatten()
+        .map(|ext| ext.trim_start_matches('.'))
+        .collect();
+    let max_results = request
+        .max_results
+        .unwrap_or(DEFAULT_MAX_RESULTS)
+        .min(MAX_RESULTS_LIMIT);
+
+    // Files are searched in parallel; one match past the limit tells us to stop
+    let found = StdMutex::new(Vec::new());
+    let count = AtomicUsize::new(0);
+    WalkBuilder::new(&start)
+        .require_git(false)
+        .build_parallel()
+        .run(|| {
+            Box::new(|entry| {
+                let Ok(entry) = entry else {
+                    return WalkState::Continue;
+                };
+                if !entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file())
+                {
+                    return WalkState::Continue;
+                }
+                let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+                if !matches(relative, globs.as_ref(), &extensions) {
+                    return WalkState::Continue;
+                }
+                let Some(text) = read_searchable(entry.path()) else {
+                    return WalkState::Continue;
+                };
+
+                let mut file_matches = Vec::new();
+                'lines: for (line_number, line) in text.lines().enumerate() {
+                    for found in regex.find_iter(line) {
+                        file_matches.push(TextMatch {
+                            path: display_path(relative),
+                            line: line_number as u32,
+                            column: byte_to_column(line, found.start(), encoding),
+                            match_text: found.as_str().to_string(),
+                            line_text: truncate_line(line),
+                        });
+                        if file_matches.len() > max_results {
+                            break 'lines;
+                        }
+                    }
+                }
+                if file_matches.is_empty() {
+                    return WalkState::Continue;
+                }
+                let total =
+                    count.fetch_add(file_matches.len(), Ordering::Relaxed) + file_matches.len();
+                found
+                    .lock()
+                    .expect("search matches lock poisoned")
+                    .extend(file_matches);
+                if total > max_results {
+                    WalkState::Quit
+                } else {
+                    WalkState::Continue
+                }
+            })
+        });
+
+    let mut matches = found.into_inner().expect("search matches lock poisoned");
+    matches.sort_by(|a, b| (&a.path, a.line, a.column).cmp(&(&b.path, b.line, b.column)));
+    let truncated = matches.len() > max_results;
+    matches.truncate(max_results);
+    Ok(SearchTextResponse { matches, truncated })
+}
+
+/// The file's text, decoded if it is not UTF-8, or `None` if it is too large or
+/// binary.
+fn read_searchable(path: &Path) -> Option<String> {
+    let metadata = std::fs::metadata(path).ok()?;
+    if metadata.len() > MAX_SEARCHED_FILE_BYTES {
+        return None;
+    }
+    let bytes = std::fs::read(path).ok()?;
+    encoding::decode_text(&bytes).map(|decoded| decoded.text)
+}
+
+fn truncate_line(line: &str) -> String {
+    match line.char_indices().nth(MAX_LINE_CHARS) {
+        Some((cut, _)) => line[..cut].to_string(),
+        None => line.to_string(),
+    }
+}
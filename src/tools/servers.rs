@@ -0,0 +1,65 @@
+use serde::Serialize;
+
+use crate::companions::Companions;
+use crate::supervisor::{LspSupervisor, RestartState};
+
+/// The configured language servers, returned by `list_servers`.
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ListServersResponse {
+    /// The main server first, then the companions in config order
+    pub servers: Vec<ServerEntry>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ServerEntry {
+    /// What to pass as `server` to route a call to this server alone
+    pub name: String,
+    pub role: ServerRole,
+    pub extensions: Vec<String>,
+    /// False once the server process has exited (a restart may be pending)
+    pub alive: bool,
+    pub restart_state: RestartState,
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq, schemars::JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerRole {
+    /// Answers every tool
+    Main,
+    /// Also asked by `definition` and `references`
+    Companion,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ServersTool;
+
+impl ServersTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "List the configured language servers by the name the `server` argument of definition and references takes, with the extensions each handles"
+    }
+
+    pub fn execute(&self, main: &LspSupervisor, companions: &Companions) -> ListServersResponse {
+        let entry = |supervisor: &LspSupervisor, role| {
+            let config = supervisor.config();
+            ServerEntry {
+                name: config.label(),
+                role,
+                extensions: config.extensions.clone(),
+                alive: !supervisor.bridge().has_exited(),
+                restart_state: supervisor.restart_state(),
+            }
+        };
+        let mut servers = vec![entry(main, ServerRole::Main)];
+        servers.extend(
+            companions
+                .servers()
+                .iter()
+                .map(|companion| entry(companion, ServerRole::Companion)),
+        );
+        ListServersResponse { servers }
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::health::HealthReport;
+use crate::progress::ProgressEntry;
+use crate::supervisor::{LspSupervisor, RestartState};
+
+/// Snapshot of the language server returned by `server_status`.
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ServerStatus {
+    /// False once the server process has exited (a restart may be pending)
+    pub alive: bool,
+    pub pid: Option<u32>,
+    pub uptime_secs: u64,
+    /// `serverInfo` from the initialize result
+    pub server: Option<Value>,
+    pub indexing: bool,
+    /// Active work-done progress by token
+    pub progress: BTreeMap<String, ProgressEntry>,
+    pub open_documents: usize,
+    pub restarts: u32,
+    /// `running`, `restarting`, or `failed` once the restart limit is reached
+    pub restart_state: RestartState,
+    /// Restarts allowed per `restart.windowSecs`
+    pub max_restarts: u32,
+    #[serde(flatten)]
+    pub health: HealthReport,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StatusTool;
+
+impl StatusTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Report language server health: process id, uptime, indexing progress, open documents, restarts, and the last error"
+    }
+
+    pub async fn execute(&self, supervisor: &LspSupervisor) -> ServerStatus {
+        let lsp = supervisor.bridge();
+        let open_documents = supervisor.documents().open_count();
+        ServerStatus {
+            alive: !lsp.has_exited(),
+            pid: lsp.pid(),
+            uptime_secs: lsp.uptime().as_secs(),
+            server: lsp.server_info().cloned(),
+            indexing: lsp.is_indexing(),
+            progress: lsp.progress().active(),
+            open_documents,
+            restarts: supervisor.restart_count(),
+            restart_state: supervisor.restart_state(),
+            max_restarts: supervisor.restart_policy().max_restarts,
+            health: supervisor.health().report(),
+        }
+    }
+}
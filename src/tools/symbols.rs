@@ -0,0 +1,88 @@
+use anyhow::{Result, bail};
+use serde::{Deserialize, Serialize};
+
+use crate::symbol_index::SymbolIndex;
+use crate::tools::locations::TextRange;
+
+const DEFAULT_MAX_RESULTS: usize = 50;
+const MAX_RESULTS_LIMIT: usize = 1000;
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct FindSymbolRequest {
+    /// Name or abbreviation to match, ignoring case (e.g. "hreq" for handle_request)
+    pub query: String,
+    /// Only return declarations of this kind: function, method, struct, class, enum,
+    /// interface, type, constant, variable, module, or macro
+    pub kind: Option<String>,
+    /// Maximum number of symbols to return (default 50, at most 1000)
+    pub max_results: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct FindSymbolResponse {
+    /// Best matches first
+    pub symbols: Vec<SymbolMatch>,
+    /// True if more symbols matched than max_results
+    pub truncated: bool,
+    /// True while the first walk of the workspace is still running, so matches in
+    /// files not indexed yet are missing
+    pub indexing: bool,
+    /// Files with declarations in the index
+    pub indexed_files: usize,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct SymbolMatch {
+    pub name: String,
+    pub kind: String,
+    pub uri: String,
+    /// Range of the name, in the configured column encoding
+    pub range: TextRange,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SymbolsTool;
+
+impl SymbolsTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn description() -> &'static str {
+        "Fuzzy-search workspace declarations by name in pathfinder's own symbol index"
+    }
+
+    pub fn execute(
+        &self,
+        index: &SymbolIndex,
+        request: FindSymbolRequest,
+    ) -> Result<FindSymbolResponse> {
+        if request.query.trim().is_empty() {
+            bail!("query must not be empty");
+        }
+        let max_results = request
+            .max_results
+            .unwrap_or(DEFAULT_MAX_RESULTS)
+            .min(MAX_RESULTS_LIMIT);
+        let (found, truncated) =
+            index.search(request.query.trim(), request.kind.as_deref(), max_results);
+        let symbols = found
+            .into_iter()
+            .map(|scored| {
+                let target = scored.tag.target();
+                SymbolMatch {
+                    name: scored.tag.name,
+                    kind: scored.tag.kind.to_string(),
+                    uri: target.uri,
+                    range: target.range,
+                }
+            })
+            .collect();
+        Ok(FindSymbolResponse {
+            symbols,
+            truncated,
+            indexing: !index.is_ready(),
+            indexed_files: index.file_count(),
+        })
+    }
+}
@@ -0,0 +1,396 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::documents::DocumentManager;
+use crate::lsp_bridge::LspBridge;
+use crate::position::PositionConverter;
+use crate::progress::ToolProgress;
+use crate::protocol::{
+    DocumentSymbols, Range, ReferenceContext, ReferenceParams, References, Request,
+    TextDocumentPositionParams,
+};
+use crate::tools::locations::TextRange;
+use crate::tools::outline::{concurrency, document_symbols, run_limited, source_files};
+use crate::utils::symbol_kind_name;
+
+const DEFAULT_MAX_FILES: usize = 100;
+/// Symbols checked between progress reports.
+const PROGRESS_INTERVAL: usize = 25;
+
+/// Kinds checked unless the request names others: declarations whose only uses are
+/// references, unlike fields or variables, which servers also report for locals.
+const DEFAULT_KINDS: &[&str] = &[
+    "class",
+    "constant",
+    "enum",
+    "function",
+    "interface",
+    "method",
+    "struct",
+];
+
+#[derive(Debug, Deserialize, Clone, Default, schemars::JsonSchema)]
+pub struct UnusedSymbolsRequest {
+    /// File or directory to scan, relative to the workspace root (default: the whole
+    /// workspace)
+    pub path: Option<String>,
+    /// Symbol kinds to check, e.g. ["function", "method"] (default: class, constant,
+    /// enum, function, interface, method, struct)
+    pub kinds: Option<Vec<String>>,
+    /// Most files to scan (default 100, at most 1000)
+    pub max_files: Option<usize>,
+    /// Requests to the server in flight at once (default 4, at most 16)
+    pub concurrency: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct UnusedSymbolsResponse {
+    /// Symbols with no reference outside their own declaration, by file and position
+    pub candidates: Vec<UnusedSymbol>,
+    pub files_scanned: usize,
+    pub symbols_checked: usize,
+    /// Files and symbols the server failed to answer for, which are left out
+    pub failed: usize,
+    /// True if more files matched than `max_files`
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct UnusedSymbol {
+    pub name: String,
+    pub kind: String,
+    /// The symbol it is declared in, for members
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container: Option<String>,
+    pub uri: String,
+    /// Where its name is declared
+    pub range: TextRange,
+}
+
+/// A symbol from `textDocument/documentSymbol`, with positions in the server's
+/// encoding.
+#[derive(Debug, Clone)]
+struct Declared {
+    name: String,
+    kind: &'static str,
+    container: Option<String>,
+    uri: String,
+    /// The name.
+    selection: Span,
+    /// The whole declaration, body included.
+    extent: Span,
+}
+
+/// An LSP range as `(line, character)` pairs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    start: (u32, u32),
+    end: (u32, u32),
+}
+
+impl Span {
+    fn parse(range: &Value) -> Option<Self> {
+        let position = |key: &str| {
+            let position = range.get(key)?;
+            Some((
+                position.get("line")?.as_u64()? as u32,
+                position.get("character")?.as_u64()? as u32,
+            ))
+        };
+        Some(Self {
+            start: position("start")?,
+            end: position("end")?,
+        })
+    }
+
+    fn contains(&self, other: &Span) -> bool {
+        self.start <= other.start && other.end <= self.end
+    }
+}
+
+impl From<Range> for Span {
+    fn from(range: Range) -> Self {
+        Self {
+            start: (range.start.line, range.start.character),
+            end: (range.end.line, range.end.character),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct UnusedTool {
+    progress: ToolProgress,
+}
+
+impl UnusedTool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reports how many files and symbols are done to `progress`.
+    pub fn with_progress(mut self, progress: ToolProgress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    pub fn description() -> &'static str {
+        "Find dead-code candidates: the symbols (textDocument/documentSymbol) of a file or directory that have no references outside their own declaration. Entry points, trait implementations, and symbols used only from outside the workspace show up too, so review each before removing it"
+    }
+
+    /// Lists the files with one of `extensions` under the request's path, asks the
+    /// server for each file's symbols, then for each symbol's references, at most
+    /// `concurrency` requests at a time.
+    pub async fn execute(
+        &self,
+        lsp: &Arc<LspBridge>,
+        documents: &Arc<DocumentManager>,
+        extensions: &[String],
+        request: UnusedSymbolsRequest,
+    ) -> Result<UnusedSymbolsResponse> {
+        let capabilities = lsp.server_capabilities();
+        capabilities.ensure("documentSymbolProvider", DocumentSymbols::METHOD)?;
+        capabilities.ensure("referencesProvider", References::METHOD)?;
+        let kinds: Vec<String> = match request.kinds {
+            Some(kinds) => kinds,
+            None => DEFAULT_KINDS.iter().map(|kind| kind.to_string()).collect(),
+        };
+        let concurrency = concurrency(request.concurrency);
+        let max_files = request.max_files.unwrap_or(DEFAULT_MAX_FILES);
+        let (uris, truncated) = source_files(lsp, request.path, extensions, max_files).await?;
+        let files = uris.len();
+
+        let mut failed = 0;
+        let mut declared = Vec::new();
+        let (lsp_for_symbols, documents_for_symbols) = (lsp.clone(), documents.clone());
+        run_limited(
+            uris,
+            concurrency,
+            move |uri| {
+                let (lsp, documents) = (lsp_for_symbols.clone(), documents_for_symbols.clone());
+                async move {
+                    documents.ensure_open(&lsp, &uri).await?;
+                    let symbols = document_symbols(&lsp, &uri, "find_unused_symbols").await?;
+                    let mut declared = Vec::new();
+                    for symbol in &symbols {
+                        collect(&uri, symbol, None, &mut declared);
+                    }
+                    Ok(declared)
+                }
+            },
+            |done, symbols| {
+                match symbols {
+                    Ok(symbols) => declared.extend(
+                        symbols
+                            .into_iter()
+                            .filter(|symbol| kinds.iter().any(|kind| kind == symbol.kind)),
+                    ),
+                    Err(err) => {
+                        tracing::debug!("Listing symbols failed: {err:#}");
+                        failed += 1;
+                    }
+                }
+                self.progress
+                    .report(format!("Listed the symbols of {done}/{files} files"));
+            },
+        )
+        .await;
+
+        let checked = declared.len();
+        let mut unused = Vec::new();
+        let lsp_for_references = lsp.clone();
+        run_limited(
+            declared,
+            concurrency,
+            move |symbol| {
+                let lsp = lsp_for_references.clone();
+                async move {
+                    let referenced = referenced_elsewhere(&lsp, &symbol).await?;
+                    Ok((!referenced).then_some(symbol))
+                }
+            },
+            |done, result| {
+                match result {
+                    Ok(found) => unused.extend(found),
+                    Err(err) => {
+                        tracing::debug!("Finding references failed: {err:#}");
+                        failed += 1;
+                    }
+                }
+                if done % PROGRESS_INTERVAL == 0 || done == checked {
+                    self.progress.report(format!(
+                        "Checked the references of {done}/{checked} symbols"
+                    ));
+                }
+            },
+        )
+        .await;
+
+        unused.sort_by(|a, b| (&a.uri, a.selection.start).cmp(&(&b.uri, b.selection.start)));
+        let mut converter = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+            .with_texts(documents.overlay_texts().await);
+        let mut candidates = Vec::with_capacity(unused.len());
+        for symbol in unused {
+            let (start, end) = (symbol.selection.start, symbol.selection.end);
+            candidates.push(UnusedSymbol {
+                range: TextRange {
+                    start_line: start.0,
+                    start_character: converter.to_client(&symbol.uri, start.0, start.1).await,
+                    end_line: end.0,
+                    end_character: converter.to_client(&symbol.uri, end.0, end.1).await,
+                },
+                name: symbol.name,
+                kind: symbol.kind.to_string(),
+                container: symbol.container,
+                uri: symbol.uri,
+            });
+        }
+        Ok(UnusedSymbolsResponse {
+            candidates,
+            files_scanned: files,
+            symbols_checked: checked,
+            failed,
+            truncated,
+        })
+    }
+}
+
+/// Adds `symbol`, a `DocumentSymbol` or a `SymbolInformation`, and its children.
+fn collect(uri: &str, symbol: &Value, container: Option<&str>, declared: &mut Vec<Declared>) {
+    let Some(name) = symbol["name"].as_str() else {
+        return;
+    };
+    // A `SymbolInformation` has one range, which usually spans the whole declaration
+    let (selection, extent) = match symbol.get("location") {
+        Some(location) => {
+            let range = Span::parse(&location["range"]);
+            (range, range)
+        }
+        None => (
+            Span::parse(&symbol["selectionRange"]),
+            Span::parse(&symbol["range"]),
+        ),
+    };
+    if let (Some(selection), Some(extent)) = (selection, extent) {
+        declared.push(Declared {
+            name: name.to_string(),
+            kind: symbol_kind_name(symbol["kind"].as_u64().unwrap_or_default()),
+            container: symbol["containerName"]
+                .as_str()
+                .or(container)
+                .map(str::to_string),
+            uri: uri.to_string(),
+            selection,
+            extent,
+        });
+    }
+    for child in symbol["children"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+    {
+        collect(uri, child, Some(name), declared);
+    }
+}
+
+/// Whether the server knows a reference to `symbol` outside its declaration.
+async fn referenced_elsewhere(lsp: &LspBridge, symbol: &Declared) -> Result<bool> {
+    let (line, character) = symbol.selection.start;
+    let params = ReferenceParams {
+        text_document_position: TextDocumentPositionParams::new(&symbol.uri, line, character),
+        context: ReferenceContext {
+            include_declaration: false,
+        },
+    };
+    let locations = lsp
+        .call::<References>(
+            &params,
+            lsp.tool_timeout("find_unused_symbols", References::METHOD),
+        )
+        .await
+        .context("LSP references request failed")?;
+    Ok(locations.into_iter().flatten().any(|location| {
+        let inside =
+            location.uri == symbol.uri && symbol.extent.contains(&Span::from(location.range));
+        !inside
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    use crate::testing::MockServer;
+
+    fn symbol(name: &str, kind: u32, line: u32, end_line: u32, children: Value) -> Value {
+        json!({
+            "name": name,
+            "kind": kind,
+            "range": {"start": {"line": line, "character": 0}, "end": {"line": end_line, "character": 1}},
+            "selectionRange": {"start": {"line": line, "character": 3}, "end": {"line": line, "character": 6}},
+            "children": children,
+        })
+    }
+
+    #[tokio::test]
+    async fn reports_symbols_referenced_only_from_their_own_declaration() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.rs"), "fn a() {}\n".repeat(12)).unwrap();
+        let mock = MockServer::new()
+            .capabilities(json!({"documentSymbolProvider": true, "referencesProvider": true}))
+            .respond(
+                "textDocument/documentSymbol",
+                json!([
+                    symbol("used", 12, 0, 2, json!([])),
+                    symbol("Unused", 23, 3, 5, json!([symbol("field", 8, 4, 4, json!([]))])),
+                    symbol("recursive", 12, 6, 8, json!([])),
+                ]),
+            )
+            .respond_with("textDocument/references", |params| {
+                let uri = &params["textDocument"]["uri"];
+                let at = |line: u32| json!({"uri": uri, "range": {"start": {"line": line, "character": 4}, "end": {"line": line, "character": 7}}});
+                match params["position"]["line"].as_u64() {
+                    Some(0) => json!([at(10)]),
+                    Some(6) => json!([at(7)]),
+                    _ => json!([]),
+                }
+            });
+        let mut lsp = LspBridge::from_mock(mock, dir.path().to_path_buf());
+        lsp.initialize().await.unwrap();
+        let (lsp, documents) = (Arc::new(lsp), Arc::new(DocumentManager::new()));
+
+        let response = UnusedTool::new()
+            .execute(
+                &lsp,
+                &documents,
+                &["rs".to_string()],
+                UnusedSymbolsRequest::default(),
+            )
+            .await
+            .unwrap();
+
+        let found: Vec<_> = response
+            .candidates
+            .iter()
+            .map(|candidate| {
+                (
+                    candidate.name.as_str(),
+                    candidate.kind.as_str(),
+                    candidate.range.start_line,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            [("Unused", "struct", 3), ("recursive", "function", 6)]
+        );
+        assert_eq!(response.files_scanned, 1);
+        // The field is not among the default kinds
+        assert_eq!(response.symbols_checked, 3);
+        assert_eq!(response.failed, 0);
+    }
+}
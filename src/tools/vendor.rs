@@ -0,0 +1,251 @@
+//! Tools for extension methods of specific language servers.
+//!
+//! Each tool is only offered when the configured command runs the server it
+//! belongs to (or when `tools.enabled` lists it, e.g. for a server behind a wrapper
+//! pathfinder cannot see through).
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::lsp_bridge::LspBridge;
+use crate::position::PositionConverter;
+use crate::tools::locations::{DefinitionTarget, normalize_targets, targets_to_client};
+
+/// Language servers with dedicated tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Vendor {
+    RustAnalyzer,
+    Clangd,
+    TypeScript,
+}
+
+/// Every vendor tool and the server it needs.
+pub const VENDOR_TOOLS: &[(&str, Vendor)] = &[
+    ("expand_macro", Vendor::RustAnalyzer),
+    ("view_hir", Vendor::RustAnalyzer),
+    ("switch_source_header", Vendor::Clangd),
+    ("go_to_source_definition", Vendor::TypeScript),
+];
+
+impl Vendor {
+    /// The server `command` runs, looking past wrappers such as `npx` or `uv run`.
+    pub fn detect(command: &[String]) -> Option<Self> {
+        command.iter().find_map(|word| {
+            let name = Path::new(word).file_name()?.to_str()?;
+            let name = name.strip_suffix(".exe").unwrap_or(name);
+            match name {
+                "rust-analyzer" => Some(Self::RustAnalyzer),
+                "clangd" => Some(Self::Clangd),
+                // Versioned installs such as clangd-18
+                _ if name.starts_with("clangd-") => Some(Self::Clangd),
+                "typescript-language-server" => Some(Self::TypeScript),
+                _ => None,
+            }
+        })
+    }
+
+    /// The vendor `tool` belongs to, if it is a vendor tool.
+    pub fn of_tool(tool: &str) -> Option<Self> {
+        VENDOR_TOOLS
+            .iter()
+            .find(|(name, _)| *name == tool)
+            .map(|(_, vendor)| *vendor)
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct PositionRequest {
+    /// file:// URI of the document, or its absolute or workspace-relative path
+    pub uri: String,
+    /// Zero-based line index
+    pub line: u32,
+    /// Zero-based character index, in the configured column encoding (UTF-16 by default)
+    pub character: u32,
+}
+
+#[derive(Debug, Deserialize, Clone, schemars::JsonSchema)]
+pub struct DocumentRequest {
+    /// file:// URI of the document, or its absolute or workspace-relative path
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize, Clone, Default, schemars::JsonSchema)]
+pub struct ExpandMacroResponse {
+    /// Name of the macro, or null when there is no macro call at the position
+    pub name: Option<String>,
+    /// The recursively expanded code
+    pub expansion: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct ViewHirResponse {
+    /// rust-analyzer's HIR dump of the function at the position
+    pub hir: String,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct SwitchSourceHeaderResponse {
+    /// The matching header or source file, or null if clangd knows none
+    pub uri: Option<String>,
+}
+
+#[derive(Debug, Serialize, Clone, schemars::JsonSchema)]
+pub struct SourceDefinitionResponse {
+    pub targets: Vec<DefinitionTarget>,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct VendorTool;
+
+impl VendorTool {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// `rust-analyzer/expandMacro`.
+    pub async fn expand_macro(
+        &self,
+        lsp: &LspBridge,
+        request: PositionRequest,
+        overlays: HashMap<String, String>,
+    ) -> Result<ExpandMacroResponse> {
+        let raw = position_request(
+            lsp,
+            "expand_macro",
+            "rust-analyzer/expandMacro",
+            &request,
+            overlays,
+        )
+        .await?;
+        if raw.is_null() {
+            return Ok(ExpandMacroResponse::default());
+        }
+        Ok(ExpandMacroResponse {
+            name: raw.get("name").and_then(Value::as_str).map(str::to_string),
+            expansion: raw
+                .get("expansion")
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    /// `rust-analyzer/viewHir`.
+    pub async fn view_hir(
+        &self,
+        lsp: &LspBridge,
+        request: PositionRequest,
+        overlays: HashMap<String, String>,
+    ) -> Result<ViewHirResponse> {
+        let raw =
+            position_request(lsp, "view_hir", "rust-analyzer/viewHir", &request, overlays).await?;
+        Ok(ViewHirResponse {
+            hir: raw.as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    /// clangd's `textDocument/switchSourceHeader`.
+    pub async fn switch_source_header(
+        &self,
+        lsp: &LspBridge,
+        request: DocumentRequest,
+    ) -> Result<SwitchSourceHeaderResponse> {
+        let method = "textDocument/switchSourceHeader";
+        let raw = lsp
+            .request_with_timeout(
+                method,
+                json!({ "uri": request.uri }),
+                lsp.tool_timeout("switch_source_header", method),
+            )
+            .await
+            .with_context(|| format!("LSP {method} request failed"))?;
+        Ok(SwitchSourceHeaderResponse {
+            uri: raw.as_str().map(str::to_string),
+        })
+    }
+
+    /// typescript-language-server's `_typescript.goToSourceDefinition` command, which
+    /// finds the implementation behind a `.d.ts` declaration.
+    pub async fn go_to_source_definition(
+        &self,
+        lsp: &LspBridge,
+        request: PositionRequest,
+        overlays: HashMap<String, String>,
+    ) -> Result<SourceDefinitionResponse> {
+        let method = "workspace/executeCommand";
+        let mut converter = PositionConverter::new(lsp.column_encoding(), lsp.position_encoding())
+            .with_texts(overlays);
+        let character = converter
+            .to_server(&request.uri, request.line, request.character)
+            .await;
+        let params = json!({
+            "command": "_typescript.goToSourceDefinition",
+            "arguments": [&request.uri, { "line": request.line, "character": character }],
+        });
+        let raw = lsp
+            .request_with_timeout(
+                method,
+                params,
+                lsp.tool_timeout("go_to_source_definition", method),
+            )
+            .await
+            .context("_typescript.goToSourceDefinition failed")?;
+        let mut targets = normalize_targets(&raw)?;
+        targets_to_client(&mut converter, &request.uri, &mut targets).await;
+        Ok(SourceDefinitionResponse { targets })
+    }
+}
+
+/// Sends a `TextDocumentPositionParams` request with the column converted to the
+/// server's encoding.
+async fn position_request(
+    lsp: &LspBridge,
+    tool: &str,
+    method: &str,
+    request: &PositionRequest,
+    overlays: HashMap<String, String>,
+) -> Result<Value> {
+    let mut converter =
+        PositionConverter::new(lsp.column_encoding(), lsp.position_encoding()).with_texts(overlays);
+    let character = converter
+        .to_server(&request.uri, request.line, request.character)
+        .await;
+    let params = json!({
+        "textDocument": { "uri": request.uri },
+        "position": { "line": request.line, "character": character },
+    });
+    lsp.request_with_timeout(method, params, lsp.tool_timeout(tool, method))
+        .await
+        .with_context(|| format!("LSP {method} request failed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    #[test]
+    fn detects_servers_behind_wrappers() {
+        assert_eq!(
+            Vendor::detect(&command(&["/usr/bin/rust-analyzer"])),
+            Some(Vendor::RustAnalyzer)
+        );
+        assert_eq!(
+            Vendor::detect(&command(&["clangd-18", "--background-index"])),
+            Some(Vendor::Clangd)
+        );
+        assert_eq!(
+            Vendor::detect(&command(&["npx", "typescript-language-server", "--stdio"])),
+            Some(Vendor::TypeScript)
+        );
+        assert_eq!(Vendor::detect(&command(&["pyright-langserver"])), None);
+        assert_eq!(Vendor::of_tool("view_hir"), Some(Vendor::RustAnalyzer));
+        assert_eq!(Vendor::of_tool("definition"), None);
+    }
+}
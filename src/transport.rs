@@ -1,20 +1,89 @@
 //! JSON-RPC transport layer for LSP communication.
 //!
 //! This module provides a Content-Length framed transport implementation that handles
-//! reading and writing JSON-RPC messages over stdio streams. The transport is used by
-//! LSP bridges to communicate with language server processes.
+//! reading and writing JSON-RPC messages over stdio or socket streams. The transport is
+//! used by LSP bridges to communicate with language server processes.
+//!
+//! Both halves reuse their buffers across messages. The reader fills one growable
+//! buffer and hands out each body as a [`Bytes`] view of it, so a large response
+//! (semanticTokens, workspace/symbol) is copied once from the stream and not again
+//! before parsing; the writer serializes into a reused buffer and sends header and
+//! body in a single vectored write.
+//!
+//! The reader tolerates what misbehaving servers write to stdout: header names in any
+//! case, extra headers, stray output between messages, and bodies that are not JSON
+//! are skipped with a warning rather than ending the session. Only a Content-Length
+//! above the limit, or EOF in the middle of a message, fails a read.
 
-use std::collections::HashMap;
+use std::fmt;
+use std::io::{IoSlice, Write};
+use std::sync::Arc;
 
 use anyhow::{Context, Result, anyhow};
+use bytes::{Buf, Bytes, BytesMut};
 use serde_json::Value;
-use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tracing::warn;
 
+use crate::history::MessageHistory;
+use crate::recording::{Direction, Recorder};
+
+/// Initial capacity of the read buffer; it grows to fit the largest message.
+const READ_BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// Longest line without a newline kept while looking for headers; output past it
+/// cannot be a header.
+const MAX_HEADER_LINE: usize = 8 * 1024;
+
+/// Bytes of an overlong line kept, in case a header starts at its end.
+const HEADER_TAIL: usize = 64;
+
+/// Largest message body read unless configured otherwise.
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 64 * 1024 * 1024;
+
+/// A message announced a body larger than the reader accepts. The body is never
+/// buffered, so a bogus Content-Length cannot exhaust memory.
+#[derive(Debug, Clone)]
+pub struct MessageTooLarge {
+    pub length: usize,
+    pub limit: usize,
+}
+
+impl fmt::Display for MessageTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LSP message of {} bytes exceeds the {}-byte limit (maxMessageBytes)",
+            self.length, self.limit
+        )
+    }
+}
+
+impl std::error::Error for MessageTooLarge {}
+
 /// Content-Length framed JSON-RPC transport used for LSP streams.
 pub struct FramedTransport<R, W> {
-    reader: BufReader<R>,
+    reader: FramedReader<R>,
+    writer: FramedWriter<W>,
+}
+
+/// Read half of a [`FramedTransport`].
+pub struct FramedReader<R> {
+    reader: R,
+    /// Bytes read from the stream but not yet consumed.
+    buffer: BytesMut,
+    max_message_bytes: usize,
+    recorder: Option<Arc<Recorder>>,
+    history: Option<Arc<MessageHistory>>,
+}
+
+/// Write half of a [`FramedTransport`].
+pub struct FramedWriter<W> {
     writer: W,
+    header: Vec<u8>,
+    body: Vec<u8>,
+    recorder: Option<Arc<Recorder>>,
+    history: Option<Arc<MessageHistory>>,
 }
 
 impl<R, W> FramedTransport<R, W>
@@ -24,87 +93,267 @@ where
 {
     pub fn new(reader: R, writer: W) -> Self {
         Self {
-            reader: BufReader::new(reader),
-            writer,
+            reader: FramedReader::new(reader),
+            writer: FramedWriter::new(writer),
         }
     }
 
     /// Reads the next JSON-RPC payload. Returns Ok(None) on EOF.
     pub async fn read(&mut self) -> Result<Option<Value>> {
-        let headers = match self.read_headers().await? {
-            Some(h) => h,
-            None => return Ok(None),
-        };
-
-        let length = headers
-            .get("content-length")
-            .ok_or_else(|| anyhow!("missing Content-Length header"))?
-            .parse::<usize>()
-            .context("could not parse Content-Length header as usize")?;
-
-        let mut buf = vec![0u8; length];
-        self.reader
-            .read_exact(&mut buf)
-            .await
-            .context("failed to read JSON payload body")?;
+        self.reader.read().await
+    }
 
-        let value = serde_json::from_slice(&buf).context("invalid JSON in framed payload")?;
-        Ok(Some(value))
+    /// Reads the next payload body without parsing it. Returns Ok(None) on EOF.
+    pub async fn read_bytes(&mut self) -> Result<Option<Bytes>> {
+        self.reader.read_bytes().await
     }
 
     /// Writes a JSON-RPC payload with Content-Length header.
     pub async fn write(&mut self, value: &Value) -> Result<()> {
-        let body = serde_json::to_vec(value).context("failed to serialize JSON payload")?;
-        let header = format!("Content-Length: {}\r\n\r\n", body.len());
-        self.writer
-            .write_all(header.as_bytes())
-            .await
-            .context("failed to write framed header")?;
-        self.writer
-            .write_all(&body)
-            .await
-            .context("failed to write framed body")?;
-        self.writer
-            .flush()
-            .await
-            .context("failed to flush writer")?;
-        Ok(())
+        self.writer.write(value).await
+    }
+
+    /// Records every message read or written to `recorder`, if any.
+    pub fn with_recorder(mut self, recorder: Option<Arc<Recorder>>) -> Self {
+        self.reader.recorder = recorder.clone();
+        self.writer.recorder = recorder;
+        self
+    }
+
+    /// Fails reads of messages larger than `limit` bytes with [`MessageTooLarge`].
+    pub fn with_max_message_bytes(mut self, limit: usize) -> Self {
+        self.reader.max_message_bytes = limit;
+        self
+    }
+
+    /// Keeps every message read or written in `history`.
+    pub fn with_history(mut self, history: Arc<MessageHistory>) -> Self {
+        self.reader.history = Some(history.clone());
+        self.writer.history = Some(history);
+        self
     }
 
-    async fn read_headers(&mut self) -> Result<Option<HashMap<String, String>>> {
-        let mut headers = HashMap::new();
-        let mut line = String::new();
+    /// Splits the transport so reads and writes can happen on different tasks.
+    pub fn into_split(self) -> (FramedReader<R>, FramedWriter<W>) {
+        (self.reader, self.writer)
+    }
+}
+
+impl<R> FramedReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: BytesMut::with_capacity(READ_BUFFER_CAPACITY),
+            max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+            recorder: None,
+            history: None,
+        }
+    }
+
+    /// Reads the next JSON-RPC payload. Returns Ok(None) on EOF.
+    ///
+    /// A body that is not valid JSON is logged and skipped rather than failing the
+    /// read, so one malformed message does not end the session.
+    pub async fn read(&mut self) -> Result<Option<Value>> {
         loop {
-            line.clear();
-            let bytes = self
-                .reader
-                .read_line(&mut line)
+            let Some(body) = self.read_bytes().await? else {
+                return Ok(None);
+            };
+            let value: Value = match serde_json::from_slice(&body) {
+                Ok(value) => value,
+                Err(err) => {
+                    warn!(%err, bytes = body.len(), "Skipping LSP message with invalid JSON");
+                    continue;
+                }
+            };
+            if let Some(recorder) = &self.recorder {
+                recorder.record(Direction::Received, &value);
+            }
+            if let Some(history) = &self.history {
+                history.push(Direction::Received, &value);
+            }
+            return Ok(Some(value));
+        }
+    }
+
+    /// Reads the next payload body without parsing it. Returns Ok(None) on EOF.
+    ///
+    /// The body shares the read buffer's allocation rather than being copied out of
+    /// it. Bodies read this way are not recorded or kept in the message history,
+    /// which both need the parsed message.
+    pub async fn read_bytes(&mut self) -> Result<Option<Bytes>> {
+        let Some(length) = self.read_headers().await? else {
+            return Ok(None);
+        };
+        if length > self.max_message_bytes {
+            return Err(MessageTooLarge {
+                length,
+                limit: self.max_message_bytes,
+            }
+            .into());
+        }
+        if self.buffer.len() < length {
+            self.buffer.reserve(length - self.buffer.len());
+        }
+        while self.buffer.len() < length {
+            if self
+                .fill()
                 .await
-                .context("failed to read header line")?;
+                .context("failed to read JSON payload body")?
+                == 0
+            {
+                return Err(anyhow!("unexpected EOF while reading JSON payload body"));
+            }
+        }
+        Ok(Some(self.buffer.split_to(length).freeze()))
+    }
 
-            if bytes == 0 {
-                if headers.is_empty() {
+    /// Consumes the headers of the next message and returns its Content-Length, or
+    /// `None` on EOF before one.
+    ///
+    /// Servers sometimes print to stdout, so lines that are not headers are skipped,
+    /// as are header blocks without a valid Content-Length, and a header glued to the
+    /// end of such output is still found.
+    async fn read_headers(&mut self) -> Result<Option<usize>> {
+        let mut length = None;
+        let mut in_headers = false;
+        loop {
+            let Some(end) = self.buffer.iter().position(|&byte| byte == b'\n') else {
+                if self.buffer.len() > MAX_HEADER_LINE {
+                    // Keep the tail, which may be the start of a header
+                    let discarded = self.buffer.len() - HEADER_TAIL;
+                    warn!(
+                        bytes = discarded,
+                        "Discarding unterminated non-header output from LSP"
+                    );
+                    self.buffer.advance(discarded);
+                }
+                if self.fill().await.context("failed to read header line")? == 0 {
+                    if length.is_some() {
+                        return Err(anyhow!("unexpected EOF while reading headers"));
+                    }
+                    if !self.buffer.is_empty() {
+                        warn!(
+                            bytes = self.buffer.len(),
+                            "Ignoring trailing non-message output from LSP"
+                        );
+                        self.buffer.clear();
+                    }
                     return Ok(None);
                 }
-                return Err(anyhow!("unexpected EOF while reading headers"));
-            }
-
+                continue;
+            };
+            let line = self.buffer.split_to(end + 1);
+            let line = String::from_utf8_lossy(&line);
             let trimmed = line.trim_end_matches(['\r', '\n']);
 
             if trimmed.is_empty() {
-                if headers.is_empty() {
+                if !in_headers {
                     continue;
                 }
-                break;
+                if let Some(length) = length {
+                    return Ok(Some(length));
+                }
+                warn!("Skipping LSP header block without a valid Content-Length");
+                in_headers = false;
+                continue;
+            }
+
+            match content_length(trimmed) {
+                Some(Some(value)) => {
+                    length = Some(value);
+                    in_headers = true;
+                }
+                Some(None) => {
+                    warn!(
+                        "Ignoring invalid Content-Length header from LSP: {}",
+                        trimmed
+                    );
+                    in_headers = true;
+                }
+                None if trimmed.contains(':') => in_headers = true,
+                None => warn!("Ignoring non-header line from LSP: {}", trimmed),
             }
+        }
+    }
+
+    /// Reads more of the stream into the buffer, returning how many bytes arrived.
+    async fn fill(&mut self) -> std::io::Result<usize> {
+        if self.buffer.capacity() == self.buffer.len() {
+            self.buffer.reserve(READ_BUFFER_CAPACITY);
+        }
+        self.reader.read_buf(&mut self.buffer).await
+    }
+}
+
+/// The value of a Content-Length header anywhere in `line` (matched in any case), or
+/// `None` if there is none; `Some(None)` if its value is not a length.
+fn content_length(line: &str) -> Option<Option<usize>> {
+    let start = line.to_ascii_lowercase().find("content-length")?;
+    let rest = line[start + "content-length".len()..].trim_start();
+    Some(
+        rest.strip_prefix(':')
+            .and_then(|value| value.trim().parse().ok()),
+    )
+}
+
+impl<W> FramedWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            header: Vec::new(),
+            body: Vec::new(),
+            recorder: None,
+            history: None,
+        }
+    }
 
-            if let Some((name, value)) = trimmed.split_once(':') {
-                headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
-            } else {
-                warn!("Ignoring non-header line from LSP: {}", trimmed);
+    /// Writes a JSON-RPC payload with Content-Length header.
+    pub async fn write(&mut self, value: &Value) -> Result<()> {
+        self.body.clear();
+        serde_json::to_writer(&mut self.body, value).context("failed to serialize JSON payload")?;
+        if let Some(recorder) = &self.recorder {
+            recorder.record(Direction::Sent, value);
+        }
+        if let Some(history) = &self.history {
+            history.push(Direction::Sent, value);
+        }
+        self.header.clear();
+        write!(self.header, "Content-Length: {}\r\n\r\n", self.body.len())
+            .context("failed to format framed header")?;
+        self.write_frame()
+            .await
+            .context("failed to write framed message")?;
+        self.writer
+            .flush()
+            .await
+            .context("failed to flush writer")?;
+        Ok(())
+    }
+
+    /// Writes the header and body buffers, vectored where the stream supports it.
+    async fn write_frame(&mut self) -> std::io::Result<()> {
+        let mut header = &self.header[..];
+        let mut body = &self.body[..];
+        while !header.is_empty() || !body.is_empty() {
+            let written = self
+                .writer
+                .write_vectored(&[IoSlice::new(header), IoSlice::new(body)])
+                .await?;
+            if written == 0 {
+                return Err(std::io::ErrorKind::WriteZero.into());
             }
+            let from_header = written.min(header.len());
+            header.advance(from_header);
+            body.advance(written - from_header);
         }
-        Ok(Some(headers))
+        Ok(())
     }
 }
 
@@ -135,6 +384,81 @@ mod tests {
         assert_eq!(payload, read_back);
     }
 
+    #[tokio::test]
+    async fn split_halves_round_trip() {
+        let (left, right) = transport_pair();
+        let (_left_reader, mut left_writer) = left.into_split();
+        let (mut right_reader, _right_writer) = right.into_split();
+        let payload = json!({"jsonrpc": "2.0", "method": "initialized", "params": {}});
+        left_writer.write(&payload).await.unwrap();
+        assert_eq!(right_reader.read().await.unwrap().unwrap(), payload);
+    }
+
+    #[tokio::test]
+    async fn reads_consecutive_frames_and_raw_bodies() {
+        let (mut writer, reader) = io::duplex(64);
+        let mut reader = FramedReader::new(reader);
+        let large = json!({"id": 1, "result": {"data": vec![7; 4096]}});
+        let sent = large.clone();
+        tokio::spawn(async move {
+            // Stray blank lines, extra headers, and any header case are accepted
+            writer
+                .write_all(b"\r\ncontent-length: 2\r\nContent-Type: x\r\n\r\n{}")
+                .await
+                .unwrap();
+            let mut framed = FramedWriter::new(writer);
+            framed.write(&sent).await.unwrap();
+            framed.write(&json!({"id": 2})).await.unwrap();
+        });
+
+        assert_eq!(reader.read().await.unwrap().unwrap(), json!({}));
+        let body = reader.read_bytes().await.unwrap().unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&body).unwrap(), large);
+        assert_eq!(reader.read().await.unwrap().unwrap(), json!({"id": 2}));
+        assert!(reader.read().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn oversized_message_is_rejected_before_buffering() {
+        let (mut writer, reader) = io::duplex(64);
+        let mut reader = FramedReader::new(reader);
+        reader.max_message_bytes = 16;
+        writer
+            .write_all(b"Content-Length: 500000000\r\n\r\n")
+            .await
+            .unwrap();
+        let err = reader.read().await.unwrap_err();
+        let too_large = err.downcast_ref::<MessageTooLarge>().unwrap();
+        assert_eq!((too_large.length, too_large.limit), (500_000_000, 16));
+        assert!(reader.buffer.capacity() < 1024 * 1024);
+    }
+
+    #[tokio::test]
+    async fn skips_stray_output_and_malformed_messages() {
+        let (mut writer, reader) = io::duplex(1024);
+        let mut reader = FramedReader::new(reader);
+        writer
+            .write_all(
+                concat!(
+                    "server starting...\n",
+                    "Error: no header block\r\n\r\n",
+                    "Content-Length: many\r\n\r\n",
+                    "Content-Length: 5\r\n\r\n{oops",
+                    "Content-Length: 7\r\n\r\n{\"a\":1}",
+                    "log line without newline",
+                    "CONTENT-LENGTH : 7\r\n\r\n{\"b\":2}",
+                    "trailing junk",
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        drop(writer);
+        assert_eq!(reader.read().await.unwrap().unwrap(), json!({"a": 1}));
+        assert_eq!(reader.read().await.unwrap().unwrap(), json!({"b": 2}));
+        assert!(reader.read().await.unwrap().is_none());
+    }
+
     #[tokio::test]
     async fn eof_returns_none() {
         let (left, mut right) = transport_pair();
@@ -143,3 +467,148 @@ mod tests {
         assert!(next.is_none());
     }
 }
+
+#[cfg(test)]
+mod proptests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::pin::Pin;
+    use std::task::{Context as TaskContext, Poll};
+
+    use proptest::prelude::*;
+    use serde_json::json;
+    use tokio::io::ReadBuf;
+
+    /// Hands out the input in the given pieces, one per read.
+    struct Chunked(VecDeque<Vec<u8>>);
+
+    impl Chunked {
+        /// `input` cut at `cuts` (taken modulo its length).
+        fn new(input: &[u8], cuts: &[usize]) -> Self {
+            let mut cuts: Vec<usize> = cuts
+                .iter()
+                .map(|cut| cut % input.len().max(1))
+                .filter(|&cut| cut > 0)
+                .collect();
+            cuts.sort_unstable();
+            cuts.dedup();
+            let mut chunks = VecDeque::new();
+            let mut start = 0;
+            for cut in cuts.into_iter().chain([input.len()]) {
+                if cut > start {
+                    chunks.push_back(input[start..cut].to_vec());
+                    start = cut;
+                }
+            }
+            Self(chunks)
+        }
+    }
+
+    impl AsyncRead for Chunked {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            _cx: &mut TaskContext<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            if let Some(mut chunk) = self.0.pop_front() {
+                let n = chunk.len().min(buf.remaining());
+                buf.put_slice(&chunk[..n]);
+                if n < chunk.len() {
+                    self.0.push_front(chunk.split_off(n));
+                }
+            }
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Every message `reader` yields until EOF or an error, and the error.
+    fn read_all(mut reader: FramedReader<Chunked>) -> (Vec<Value>, Option<anyhow::Error>) {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut messages = Vec::new();
+            loop {
+                match reader.read().await {
+                    Ok(Some(message)) => messages.push(message),
+                    Ok(None) => return (messages, None),
+                    Err(err) => return (messages, Some(err)),
+                }
+            }
+        })
+    }
+
+    fn message() -> impl Strategy<Value = Value> {
+        (any::<u32>(), "[a-zA-Z/]{1,20}", ".{0,40}").prop_map(
+            |(id, method, text)| json!({"id": id, "method": method, "params": {"text": text}}),
+        )
+    }
+
+    /// Stray output a server might print between messages.
+    fn garbage() -> impl Strategy<Value = String> {
+        "[a-zA-Z0-9 .:!{}\\[\\]\"]{0,60}(\r?\n){0,2}".prop_filter("no Content-Length", |text| {
+            !text.to_ascii_lowercase().contains("content-length")
+        })
+    }
+
+    fn header_name() -> impl Strategy<Value = String> {
+        "[cC][oO][nN][tT][eE][nN][tT]-[lL][eE][nN][gG][tT][hH]"
+    }
+
+    proptest! {
+        #[test]
+        fn reads_every_message_however_framed_and_split(
+            framed in prop::collection::vec(
+                (message(), header_name(), any::<bool>(), any::<bool>(), garbage()),
+                0..8,
+            ),
+            cuts in prop::collection::vec(any::<usize>(), 0..32),
+        ) {
+            let mut input = Vec::new();
+            let mut expected = Vec::new();
+            for (message, name, content_type, bare_newlines, garbage) in framed {
+                let body = message.to_string();
+                let newline = if bare_newlines { "\n" } else { "\r\n" };
+                input.extend_from_slice(garbage.as_bytes());
+                input.extend_from_slice(format!("{name}: {}{newline}", body.len()).as_bytes());
+                if content_type {
+                    input.extend_from_slice(
+                        format!("Content-Type: application/vscode-jsonrpc; charset=utf-8{newline}")
+                            .as_bytes(),
+                    );
+                }
+                input.extend_from_slice(newline.as_bytes());
+                input.extend_from_slice(body.as_bytes());
+                expected.push(message);
+            }
+            let (messages, err) = read_all(FramedReader::new(Chunked::new(&input, &cuts)));
+            prop_assert!(err.is_none(), "read failed: {:?}", err);
+            prop_assert_eq!(messages, expected);
+        }
+
+        #[test]
+        fn oversized_content_length_fails_without_buffering(
+            excess in 1..usize::MAX / 2,
+            cuts in prop::collection::vec(any::<usize>(), 0..8),
+        ) {
+            let limit = 1024;
+            let input = format!("Content-Length: {}\r\n\r\n{{}}", limit + excess);
+            let mut reader = FramedReader::new(Chunked::new(input.as_bytes(), &cuts));
+            reader.max_message_bytes = limit;
+            let (messages, err) = read_all(reader);
+            prop_assert!(messages.is_empty());
+            let err = err.expect("oversized message was read");
+            prop_assert!(err.downcast_ref::<MessageTooLarge>().is_some());
+        }
+
+        #[test]
+        fn arbitrary_input_never_panics(
+            input in prop::collection::vec(any::<u8>(), 0..2048),
+            cuts in prop::collection::vec(any::<usize>(), 0..16),
+        ) {
+            let mut reader = FramedReader::new(Chunked::new(&input, &cuts));
+            reader.max_message_bytes = 4096;
+            read_all(reader);
+        }
+    }
+}
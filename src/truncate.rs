@@ -0,0 +1,124 @@
+//! Capping of oversized tool results.
+//!
+//! A query over a large workspace can produce results far bigger than an MCP client
+//! (or the model reading them) can use. The service limits each tool's structured
+//! result to `maxResponseBytes` (or the tool's entry in `responseLimits`) by dropping
+//! trailing items of its largest lists, which keeps the result valid against the
+//! tool's output schema, and tells the client how many items were dropped.
+
+use std::io;
+
+use serde_json::Value;
+
+/// Shortens the largest arrays in `value`, from the end, until it serializes to at
+/// most `limit` bytes, and returns how many items were dropped. A value that is too
+/// large without any arrays left to shorten is left over the limit.
+pub fn truncate_json(value: &mut Value, limit: usize) -> usize {
+    let mut dropped = 0;
+    loop {
+        let size = serialized_len(value);
+        if size <= limit {
+            break;
+        }
+        let Some(pointer) = largest_array(value, String::new()).map(|(pointer, _)| pointer) else {
+            break;
+        };
+        let Some(Value::Array(items)) = value.pointer_mut(&pointer) else {
+            break;
+        };
+        let mut excess = size - limit;
+        while excess > 0
+            && let Some(item) = items.pop()
+        {
+            // The item and the comma separating it from the one before
+            excess = excess.saturating_sub(serialized_len(&item) + 1);
+            dropped += 1;
+        }
+    }
+    dropped
+}
+
+/// Bytes `value` takes serialized as compact JSON.
+pub fn serialized_len(value: &Value) -> usize {
+    let mut counter = Counter(0);
+    // Writing to a counter cannot fail
+    let _ = serde_json::to_writer(&mut counter, value);
+    counter.0
+}
+
+/// JSON pointer to, and serialized size of, the largest non-empty array in `value`.
+fn largest_array(value: &Value, pointer: String) -> Option<(String, usize)> {
+    let (mut largest, children): (_, Vec<(String, &Value)>) = match value {
+        Value::Array(items) => (
+            (!items.is_empty()).then(|| (pointer.clone(), serialized_len(value))),
+            items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| (format!("{pointer}/{index}"), item))
+                .collect(),
+        ),
+        Value::Object(fields) => (
+            None,
+            fields
+                .iter()
+                .map(|(key, field)| {
+                    let key = key.replace('~', "~0").replace('/', "~1");
+                    (format!("{pointer}/{key}"), field)
+                })
+                .collect(),
+        ),
+        _ => return None,
+    };
+    for (child_pointer, child) in children {
+        if let Some((found, size)) = largest_array(child, child_pointer)
+            && largest.as_ref().is_none_or(|(_, largest)| size > *largest)
+        {
+            largest = Some((found, size));
+        }
+    }
+    largest
+}
+
+struct Counter(usize);
+
+impl io::Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn drops_trailing_items_of_the_largest_list() {
+        let locations: Vec<Value> = (0..100)
+            .map(|line| json!({"uri": "file:///src/lib.rs", "line": line}))
+            .collect();
+        let mut value = json!({
+            "locations": locations,
+            "notes": ["short"],
+            "truncated": false,
+        });
+        let limit = 1000;
+        let dropped = truncate_json(&mut value, limit);
+        assert!(serialized_len(&value) <= limit);
+        let kept = value["locations"].as_array().unwrap();
+        assert_eq!(kept.len() + dropped, 100);
+        assert_eq!(kept[0]["line"], 0);
+        assert_eq!(value["notes"], json!(["short"]));
+
+        // Already small enough, or nothing to shorten
+        assert_eq!(truncate_json(&mut value, limit), 0);
+        let mut text = json!({"contents": "x".repeat(100)});
+        assert_eq!(truncate_json(&mut text, 10), 0);
+        assert_eq!(text["contents"].as_str().unwrap().len(), 100);
+    }
+}
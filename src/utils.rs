@@ -1,13 +1,83 @@
 //! Utility functions for URI and file path handling.
 //!
 //! This module provides common utilities for working with file URIs,
-//! extracting file extensions, and converting between URIs and paths.
+//! extracting file extensions, converting between URIs and paths, normalizing the
+//! document arguments of tools, and walking a workspace's source files.
+//!
+//! On Windows, URIs and paths are brought to one form so the same file is never
+//! tracked twice: drive letters are upper case (`file:///C:/src`, also when a client
+//! escapes the colon as `c%3A`), canonical paths drop the `\\?\` verbatim prefix, and
+//! UNC shares map to `file://server/share/...`. Path prefixes are compared ignoring
+//! case there, as the file system does.
 
-use std::path::{Path, PathBuf};
+use std::env;
+use std::fs::DirEntry;
+use std::path::{Component, Path, PathBuf};
 
 use anyhow::{Context, Result, anyhow};
 use url::Url;
 
+/// Directories that never contain first-party sources worth looking at.
+const SKIP_DIRS: &[&str] = &[
+    "target",
+    "node_modules",
+    "venv",
+    "__pycache__",
+    "build",
+    "dist",
+    "vendor",
+];
+
+/// Visits the regular files under `root`, skipping hidden entries and dependency or
+/// build output directories, and stopping after `max_files` files.
+///
+/// The order is unspecified. Unreadable subdirectories are skipped; only a failure
+/// to read `root` itself is an error.
+pub fn walk_source_files(
+    root: &Path,
+    max_files: usize,
+    mut visit: impl FnMut(&DirEntry),
+) -> Result<()> {
+    let mut visited = 0;
+    let mut stack = vec![root.to_path_buf()];
+
+    while let Some(dir) = stack.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if dir != root => {
+                tracing::debug!(?err, dir = %dir.display(), "Skipping unreadable directory");
+                continue;
+            }
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("failed to read directory {}", dir.display()));
+            }
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') {
+                continue;
+            }
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !SKIP_DIRS.contains(&name.as_ref()) {
+                    stack.push(entry.path());
+                }
+            } else if file_type.is_file() {
+                visit(&entry);
+                visited += 1;
+                if visited >= max_files {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Extracts the file extension from a file:// URI.
 ///
 /// Returns `None` if the URI has no extension or cannot be parsed as a path.
@@ -20,8 +90,10 @@ use url::Url;
 /// assert_eq!(extension_from_uri("file:///path/to/file"), None);
 /// ```
 pub fn extension_from_uri(uri: &str) -> Option<String> {
-    let path = Path::new(uri);
-    path.extension()
+    // The last segment, whichever separator the URI or path uses
+    let name = uri.split(['?', '#']).next()?.rsplit(['/', '\\']).next()?;
+    Path::new(name)
+        .extension()
         .and_then(|ext| ext.to_str())
         .map(|s| s.to_string())
 }
@@ -38,10 +110,7 @@ pub fn extension_from_uri(uri: &str) -> Option<String> {
 /// - The URI is not a file:// scheme
 /// - The resolved path does not exist
 pub fn uri_to_path(uri: &str) -> Result<PathBuf> {
-    let parsed = Url::parse(uri).context("invalid URI")?;
-    let path = parsed
-        .to_file_path()
-        .map_err(|_| anyhow!("only file:// URIs are supported"))?;
+    let path = uri_to_file_path(uri)?;
 
     // Validate the path exists
     if !path.exists() {
@@ -51,6 +120,174 @@ pub fn uri_to_path(uri: &str) -> Result<PathBuf> {
     Ok(path)
 }
 
+/// Like [`uri_to_path`] but without requiring the file to exist.
+///
+/// A drive letter with an escaped colon (`file:///c%3A/src`), as some clients send
+/// it, is understood too.
+pub fn uri_to_file_path(uri: &str) -> Result<PathBuf> {
+    let mut url = Url::parse(uri).context("invalid URI")?;
+    let path = url.path();
+    if let Some(drive) = escaped_drive(path) {
+        let rest = path[5..].to_string();
+        url.set_path(&format!("/{drive}:{rest}"));
+    }
+    url.to_file_path()
+        .map_err(|_| anyhow!("only file:// URIs are supported"))
+}
+
+/// The drive letter of a URI path starting `/c%3A`.
+fn escaped_drive(path: &str) -> Option<char> {
+    let drive = path.strip_prefix('/')?.chars().next()?;
+    let escape = path.get(2..5)?;
+    (drive.is_ascii_alphabetic() && escape.eq_ignore_ascii_case("%3A")).then_some(drive)
+}
+
+/// The file:// URI of `path`, with the drive letter of a Windows path upper case so
+/// that every spelling of a file maps to one URI.
+pub fn path_to_uri(path: &Path) -> Result<String> {
+    let uri = Url::from_file_path(path)
+        .map(String::from)
+        .map_err(|_| anyhow!("cannot express {} as a file:// URI", path.display()))?;
+    Ok(upper_case_drive(uri))
+}
+
+fn upper_case_drive(mut uri: String) -> String {
+    let bytes = uri.as_bytes();
+    if bytes.len() > 9 && uri.starts_with("file:///") && bytes[9] == b':' {
+        uri[8..9].make_ascii_uppercase();
+    }
+    uri
+}
+
+/// Like [`Path::canonicalize`], but without the `\\?\` verbatim prefix Windows puts on
+/// canonical paths, which file:// URIs cannot express and language servers do not
+/// expect.
+pub fn canonicalize(path: &Path) -> std::io::Result<PathBuf> {
+    let canonical = path.canonicalize()?;
+    Ok(if cfg!(windows) {
+        strip_verbatim(&canonical)
+    } else {
+        canonical
+    })
+}
+
+/// `C:\x` for `\\?\C:\x` and `\\server\share\x` for `\\?\UNC\server\share\x`; other paths
+/// are returned unchanged.
+fn strip_verbatim(path: &Path) -> PathBuf {
+    let Some(text) = path.to_str() else {
+        return path.to_path_buf();
+    };
+    if let Some(share) = text.strip_prefix(r"\\?\UNC\") {
+        return PathBuf::from(format!(r"\\{share}"));
+    }
+    match text.strip_prefix(r"\\?\") {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// `path` relative to `base`, if it is under it. Components are compared ignoring
+/// ASCII case on Windows, whose file systems do.
+pub fn strip_path_prefix<'a>(path: &'a Path, base: &Path) -> Option<&'a Path> {
+    strip_prefix_folding(path, base, cfg!(windows))
+}
+
+fn strip_prefix_folding<'a>(path: &'a Path, base: &Path, ignore_case: bool) -> Option<&'a Path> {
+    if !ignore_case {
+        return path.strip_prefix(base).ok();
+    }
+    let mut rest = path.components();
+    for expected in base.components() {
+        let actual = rest.next()?;
+        if !actual
+            .as_os_str()
+            .eq_ignore_ascii_case(expected.as_os_str())
+        {
+            return None;
+        }
+    }
+    Some(rest.as_path())
+}
+
+/// Resolves a tool's `path` argument to an existing path inside `root`.
+///
+/// `path` may be a file:// URI, an absolute path, or a path relative to `root`.
+/// Symlinks are resolved before the check, so links pointing out of the workspace
+/// are refused too. `root` must already be canonical.
+pub fn resolve_workspace_path(root: &Path, path: &str) -> Result<PathBuf> {
+    let path = if path.starts_with("file://") {
+        uri_to_file_path(path)?
+    } else {
+        root.join(path)
+    };
+    let resolved =
+        canonicalize(&path).with_context(|| format!("path does not exist: {}", path.display()))?;
+    if strip_path_prefix(&resolved, root).is_none() {
+        return Err(anyhow!("path is outside the workspace: {}", path.display()));
+    }
+    Ok(resolved)
+}
+
+/// Canonicalizes a tool's document argument to the file:// URI the server knows it by.
+///
+/// `input` may be a file:// URI (with or without escaped spaces and other special
+/// characters), an absolute path, or a path relative to `root`. `.` and `..` are
+/// resolved, and so are symlinks when the file exists; it need not exist, e.g. for
+/// `open_document`. Unlike [`resolve_workspace_path`], documents outside `root` are
+/// allowed, since definitions often lead into dependencies.
+pub fn normalize_document_uri(root: &Path, input: &str) -> Result<String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err(anyhow!("document URI or path is empty"));
+    }
+    let is_file_uri = input
+        .get(..5)
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case("file:"));
+    let path = if is_file_uri {
+        uri_to_file_path(input)?
+    } else if input.contains("://") {
+        return Err(anyhow!("only file:// URIs are supported: {input}"));
+    } else {
+        // An absolute path replaces the root
+        root.join(input)
+    };
+    let path = canonicalize(&path).unwrap_or_else(|_| normalize_lexically(&path));
+    path_to_uri(&path)
+}
+
+/// Drops `.` components and applies `..` without touching the filesystem.
+fn normalize_lexically(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// Resolves the workspace base directory from an optional CLI argument.
+///
+/// Relative paths are resolved against the current directory and canonicalized;
+/// when no argument is given the current directory is used as-is.
+pub fn resolve_workspace_base(workspace: Option<PathBuf>) -> Result<PathBuf> {
+    let Some(path) = workspace else {
+        return env::current_dir().map_err(|err| anyhow!("failed to get current directory: {err}"));
+    };
+    let abs = if path.is_absolute() {
+        path
+    } else {
+        env::current_dir()
+            .map_err(|err| anyhow!("failed to read current directory: {err}"))?
+            .join(path)
+    };
+    canonicalize(&abs).map_err(|err| anyhow!("failed to canonicalize path: {err}"))
+}
+
 /// Determines the LSP language identifier for a given file path.
 ///
 /// Maps common file extensions to their corresponding LSP language identifiers.
@@ -75,7 +312,12 @@ pub fn uri_to_path(uri: &str) -> Result<PathBuf> {
 /// For single-language servers like rust-analyzer, this may seem redundant, but the
 /// protocol is designed to be general-purpose and some servers validate this field.
 pub fn language_id_for_path(path: &Path) -> &str {
-    match path.extension().and_then(|ext| ext.to_str()).unwrap_or("") {
+    language_id_for_extension(path.extension().and_then(|ext| ext.to_str()).unwrap_or(""))
+}
+
+/// The built-in `languageId` for files with `extension` (empty for none).
+pub fn language_id_for_extension(extension: &str) -> &str {
+    match extension {
         "rs" => "rust",
         "go" => "go",
         "py" => "python",
@@ -87,11 +329,53 @@ pub fn language_id_for_path(path: &Path) -> &str {
         "toml" => "toml",
         "yaml" | "yml" => "yaml",
         "md" => "markdown",
+        "sh" | "bash" | "zsh" => "shellscript",
+        "rb" => "ruby",
+        "pl" => "perl",
         "" => "plaintext",
         other => other,
     }
 }
 
+/// Names of LSP `SymbolKind` values, indexed from 1.
+const SYMBOL_KINDS: &[&str] = &[
+    "file",
+    "module",
+    "namespace",
+    "package",
+    "class",
+    "method",
+    "property",
+    "field",
+    "constructor",
+    "enum",
+    "interface",
+    "function",
+    "variable",
+    "constant",
+    "string",
+    "number",
+    "boolean",
+    "array",
+    "object",
+    "key",
+    "null",
+    "enum_member",
+    "struct",
+    "event",
+    "operator",
+    "type_parameter",
+];
+
+/// The name of an LSP `SymbolKind`, such as `function` or `enum_member`.
+pub fn symbol_kind_name(kind: u64) -> &'static str {
+    usize::try_from(kind)
+        .ok()
+        .and_then(|kind| SYMBOL_KINDS.get(kind.checked_sub(1)?))
+        .copied()
+        .unwrap_or("unknown")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,6 +391,149 @@ mod tests {
             Some("py".to_string())
         );
         assert_eq!(extension_from_uri("file:///path/to/file"), None);
+        assert_eq!(extension_from_uri("file:///dir.d/file"), None);
+        assert_eq!(
+            extension_from_uri(r"C:\src.d\main.rs"),
+            Some("rs".to_string())
+        );
+        assert_eq!(
+            extension_from_uri("vscode-notebook-cell:/a/nb.ipynb#cell1"),
+            Some("ipynb".to_string())
+        );
+    }
+
+    #[test]
+    fn windows_paths_and_uris_take_one_form() {
+        assert_eq!(
+            strip_verbatim(Path::new(r"\\?\C:\src\main.rs")),
+            PathBuf::from(r"C:\src\main.rs")
+        );
+        assert_eq!(
+            strip_verbatim(Path::new(r"\\?\UNC\server\share\a.rs")),
+            PathBuf::from(r"\\server\share\a.rs")
+        );
+        assert_eq!(
+            strip_verbatim(Path::new(r"\\?\Volume{1}\a.rs")),
+            PathBuf::from(r"\\?\Volume{1}\a.rs")
+        );
+        assert_eq!(
+            upper_case_drive("file:///c:/src/main.rs".to_string()),
+            "file:///C:/src/main.rs"
+        );
+        assert_eq!(
+            upper_case_drive("file:///usr/lib/x.rs".to_string()),
+            "file:///usr/lib/x.rs"
+        );
+        assert_eq!(escaped_drive("/c%3A/src/main.rs"), Some('c'));
+        assert_eq!(escaped_drive("/c%3a"), Some('c'));
+        assert_eq!(escaped_drive("/usr/lib"), None);
+
+        let path = Path::new("/Work/Project/src/lib.rs");
+        assert_eq!(
+            strip_prefix_folding(path, Path::new("/work/project"), true),
+            Some(Path::new("src/lib.rs"))
+        );
+        assert_eq!(
+            strip_prefix_folding(path, Path::new("/work/project"), false),
+            None
+        );
+        assert_eq!(
+            strip_prefix_folding(path, Path::new("/work/proj"), true),
+            None
+        );
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_uris_round_trip() {
+        for (uri, path) in [
+            ("file:///C:/src/main.rs", r"C:\src\main.rs"),
+            ("file:///c%3A/src/main.rs", r"c:\src\main.rs"),
+            ("file://server/share/a.rs", r"\\server\share\a.rs"),
+        ] {
+            assert_eq!(uri_to_file_path(uri).unwrap(), PathBuf::from(path), "{uri}");
+        }
+        assert_eq!(
+            path_to_uri(Path::new(r"c:\src\main.rs")).unwrap(),
+            "file:///C:/src/main.rs"
+        );
+        assert_eq!(
+            path_to_uri(Path::new(r"\\server\share\a.rs")).unwrap(),
+            "file://server/share/a.rs"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let root = canonicalize(dir.path()).unwrap();
+        assert!(!root.to_string_lossy().starts_with(r"\\?\"));
+        std::fs::write(root.join("Main.rs"), "").unwrap();
+        let expected = path_to_uri(&root.join("Main.rs")).unwrap();
+        let lower = root.join("main.rs").display().to_string().to_lowercase();
+        assert_eq!(normalize_document_uri(&root, &lower).unwrap(), expected);
+        assert_eq!(
+            normalize_document_uri(&root, r"src\..\Main.rs").unwrap(),
+            expected
+        );
+        assert!(resolve_workspace_path(&root, &lower).is_ok());
+    }
+
+    #[test]
+    fn workspace_paths_stay_inside_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(root.join("src/lib.rs"), "").unwrap();
+
+        let file = root.join("src/lib.rs");
+        assert_eq!(resolve_workspace_path(&root, "src/lib.rs").unwrap(), file);
+        let uri = Url::from_file_path(&file).unwrap();
+        assert_eq!(resolve_workspace_path(&root, uri.as_str()).unwrap(), file);
+        assert_eq!(resolve_workspace_path(&root, "").unwrap(), root);
+        assert!(resolve_workspace_path(&root, "..").is_err());
+        assert!(resolve_workspace_path(&root, "/").is_err());
+        assert!(resolve_workspace_path(&root, "missing.rs").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn document_uris_paths_and_relative_paths_normalize_alike() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        std::fs::create_dir(root.join("my src")).unwrap();
+        std::fs::write(root.join("my src/lib.rs"), "").unwrap();
+        let expected = Url::from_file_path(root.join("my src/lib.rs"))
+            .unwrap()
+            .to_string();
+        assert!(expected.ends_with("/my%20src/lib.rs"));
+
+        let absolute = root.join("my src/lib.rs").display().to_string();
+        for input in [
+            "my src/lib.rs",
+            "./my src/../my src/lib.rs",
+            absolute.as_str(),
+            &format!("file://{absolute}"),
+            expected.as_str(),
+            &format!("  {expected}\n"),
+        ] {
+            assert_eq!(
+                normalize_document_uri(&root, input).unwrap(),
+                expected,
+                "{input}"
+            );
+        }
+
+        // Files that do not exist yet are normalized without the filesystem
+        let missing = normalize_document_uri(&root, "new/../draft.rs").unwrap();
+        assert_eq!(
+            missing,
+            Url::from_file_path(root.join("draft.rs")).unwrap().as_str()
+        );
+        // Outside the workspace is fine, other schemes are not
+        assert_eq!(
+            normalize_document_uri(&root, "/usr/lib/x.rs").unwrap(),
+            "file:///usr/lib/x.rs"
+        );
+        assert!(normalize_document_uri(&root, "https://example.com/a.rs").is_err());
+        assert!(normalize_document_uri(&root, " ").is_err());
     }
 
     #[test]
@@ -125,5 +552,6 @@ mod tests {
         );
         assert_eq!(language_id_for_path(Path::new("file.unknown")), "unknown");
         assert_eq!(language_id_for_path(Path::new("file")), "plaintext");
+        assert_eq!(language_id_for_path(Path::new("run.sh")), "shellscript");
     }
 }
@@ -0,0 +1,129 @@
+//! Warm-up preloading of documents.
+//!
+//! Servers index lazily around the documents they have open, so the first tool call
+//! on a fresh server is often the slowest. When configured, pathfinder opens a set
+//! of likely-needed documents right after startup: files matching the warm-up globs
+//! plus the most recently modified source files.
+
+use std::cmp::Reverse;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use globset::{GlobBuilder, GlobSetBuilder};
+
+use crate::config::WarmupConfig;
+use crate::utils::walk_source_files;
+
+/// Upper bound on files inspected while selecting warm-up documents.
+const MAX_SCANNED_FILES: usize = 50_000;
+
+/// Picks the files to open: glob matches first, then the `recentFiles` newest files
+/// whose extension is in `extensions`, without duplicates and at most `limit` in
+/// total (0 means no limit).
+///
+/// Walks the workspace synchronously; call from a blocking context.
+pub fn select_files(
+    root: &Path,
+    config: &WarmupConfig,
+    extensions: &[String],
+    limit: usize,
+) -> Result<Vec<PathBuf>> {
+    let mut globs = GlobSetBuilder::new();
+    for pattern in &config.globs {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("invalid warm-up glob '{pattern}'"))?;
+        globs.add(glob);
+    }
+    let globs = globs.build().context("invalid warm-up globs")?;
+
+    let mut matched = Vec::new();
+    let mut recent = Vec::new();
+    walk_source_files(root, MAX_SCANNED_FILES, |entry| {
+        let path = entry.path();
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        if globs.is_match(relative) {
+            matched.push(path);
+        } else if config.recent_files > 0
+            && path
+                .extension()
+                .is_some_and(|ext| extensions.iter().any(|e| ext == e.as_str()))
+        {
+            let modified = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(SystemTime::UNIX_EPOCH);
+            recent.push((modified, path));
+        }
+    })?;
+
+    matched.sort();
+    recent.sort_by_key(|(modified, _)| Reverse(*modified));
+    let mut files = matched;
+    files.extend(
+        recent
+            .into_iter()
+            .take(config.recent_files)
+            .map(|(_, path)| path),
+    );
+    if limit > 0 {
+        files.truncate(limit);
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn selects_globs_then_recent_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("src/nested")).unwrap();
+        std::fs::create_dir_all(root.join("target")).unwrap();
+        let files = [
+            "src/lib.rs",
+            "src/nested/mod.rs",
+            "src/old.rs",
+            "src/new.rs",
+            "notes.txt",
+            "target/gen.rs",
+        ];
+        for (age, file) in files.iter().enumerate() {
+            let path = root.join(file);
+            std::fs::write(&path, "").unwrap();
+            let modified = SystemTime::now() - Duration::from_secs(100 * age as u64);
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        }
+        // `new.rs` is made the newest of the unmatched files
+        std::fs::File::options()
+            .write(true)
+            .open(root.join("src/new.rs"))
+            .unwrap()
+            .set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+
+        let config = WarmupConfig {
+            globs: vec!["src/**/mod.rs".into(), "src/lib.rs".into()],
+            recent_files: 1,
+        };
+        let selected = select_files(root, &config, &["rs".into()], 0).unwrap();
+        let relative: Vec<_> = selected
+            .iter()
+            .map(|path| path.strip_prefix(root).unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(relative, ["src/lib.rs", "src/nested/mod.rs", "src/new.rs"]);
+
+        let capped = select_files(root, &config, &["rs".into()], 2).unwrap();
+        assert_eq!(capped.len(), 2);
+    }
+}
@@ -0,0 +1,347 @@
+//! Workspace file watching for `workspace/didChangeWatchedFiles`.
+//!
+//! Servers ask to hear about file changes by dynamically registering
+//! `workspace/didChangeWatchedFiles` with glob patterns. A [`WatchRegistry`] (one per
+//! server process) records those registrations, while a [`FileWatcher`] reports
+//! debounced filesystem events for the whole workspace. The supervisor refreshes open
+//! documents from each batch and forwards the changes the server registered for.
+//! [`crate::reload`] watches the config file's directory the same way.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{Context, Result, anyhow};
+use globset::{GlobBuilder, GlobMatcher};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_json::{Value, json};
+use tokio::sync::mpsc;
+
+use crate::utils::{path_to_uri, uri_to_file_path};
+
+/// Directories whose contents are never reported.
+const IGNORED_DIRS: &[&str] = &[".git"];
+
+/// LSP `FileChangeType`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileChangeType {
+    Created = 1,
+    Changed = 2,
+    Deleted = 3,
+}
+
+impl FileChangeType {
+    /// The bit for this change in a `FileSystemWatcher.kind` mask.
+    fn watch_kind(self) -> u64 {
+        match self {
+            Self::Created => 1,
+            Self::Changed => 2,
+            Self::Deleted => 4,
+        }
+    }
+}
+
+/// One coalesced change to a path in the workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub kind: FileChangeType,
+}
+
+impl FileChange {
+    pub fn uri(&self) -> Option<String> {
+        path_to_uri(&self.path).ok()
+    }
+
+    /// The change as an LSP `FileEvent`.
+    pub fn to_lsp(&self) -> Option<Value> {
+        Some(json!({ "uri": self.uri()?, "type": self.kind as u8 }))
+    }
+}
+
+#[derive(Debug)]
+struct FileSystemWatcher {
+    glob: GlobMatcher,
+    /// Set for `RelativePattern`s; the glob then matches paths relative to it.
+    base: Option<PathBuf>,
+    kind: u64,
+}
+
+impl FileSystemWatcher {
+    fn from_lsp(watcher: &Value) -> Result<Self> {
+        let pattern = watcher
+            .get("globPattern")
+            .ok_or_else(|| anyhow!("watcher is missing globPattern"))?;
+        let (base, pattern) = match pattern {
+            Value::String(pattern) => (None, pattern.as_str()),
+            relative => {
+                let base = relative.get("baseUri").and_then(|base| {
+                    // Either a URI or a WorkspaceFolder
+                    base.as_str().or_else(|| base.get("uri")?.as_str())
+                });
+                let base = base
+                    .and_then(|uri| uri_to_file_path(uri).ok())
+                    .ok_or_else(|| anyhow!("relative pattern has no usable baseUri"))?;
+                let pattern = relative
+                    .get("pattern")
+                    .and_then(|p| p.as_str())
+                    .ok_or_else(|| anyhow!("relative pattern is missing pattern"))?;
+                (Some(base), pattern)
+            }
+        };
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("invalid glob pattern '{pattern}'"))?
+            .compile_matcher();
+        Ok(Self {
+            glob,
+            base,
+            kind: watcher.get("kind").and_then(|k| k.as_u64()).unwrap_or(7),
+        })
+    }
+
+    fn matches(&self, path: &Path, change: FileChangeType) -> bool {
+        if self.kind & change.watch_kind() == 0 {
+            return false;
+        }
+        match &self.base {
+            Some(base) => path
+                .strip_prefix(base)
+                .is_ok_and(|relative| self.glob.is_match(relative)),
+            None => self.glob.is_match(path),
+        }
+    }
+}
+
+/// `workspace/didChangeWatchedFiles` registrations made by one server process.
+#[derive(Debug, Default)]
+pub struct WatchRegistry {
+    registrations: Mutex<HashMap<String, Vec<FileSystemWatcher>>>,
+}
+
+impl WatchRegistry {
+    /// Records the watchers of a registration's `registerOptions`.
+    ///
+    /// Watchers with unusable patterns are skipped with a warning.
+    pub fn register(&self, id: &str, options: &Value) {
+        let watchers = options
+            .get("watchers")
+            .and_then(|w| w.as_array())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|watcher| match FileSystemWatcher::from_lsp(watcher) {
+                Ok(watcher) => Some(watcher),
+                Err(err) => {
+                    tracing::warn!(?err, %watcher, "Ignoring file watcher from server");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        tracing::debug!(
+            id,
+            watchers = watchers.len(),
+            "Server registered file watchers"
+        );
+        self.lock().insert(id.to_string(), watchers);
+    }
+
+    pub fn unregister(&self, id: &str) {
+        self.lock().remove(id);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().values().all(Vec::is_empty)
+    }
+
+    /// Whether any registered watcher covers this change.
+    pub fn matches(&self, change: &FileChange) -> bool {
+        self.lock()
+            .values()
+            .flatten()
+            .any(|watcher| watcher.matches(&change.path, change.kind))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Vec<FileSystemWatcher>>> {
+        self.registrations
+            .lock()
+            .expect("watch registry lock poisoned")
+    }
+}
+
+/// Watch over the workspace (or one directory), yielding debounced batches of
+/// changes.
+pub struct FileWatcher {
+    // Dropping the watcher stops the event stream.
+    _watcher: RecommendedWatcher,
+    events: mpsc::UnboundedReceiver<notify::Result<Event>>,
+}
+
+impl FileWatcher {
+    pub fn start(root: &Path) -> Result<Self> {
+        Self::watch(root, RecursiveMode::Recursive)
+    }
+
+    /// Watches only the entries of `dir`, not its subdirectories.
+    pub fn start_dir(dir: &Path) -> Result<Self> {
+        Self::watch(dir, RecursiveMode::NonRecursive)
+    }
+
+    fn watch(root: &Path, mode: RecursiveMode) -> Result<Self> {
+        let (tx, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })
+        .context("failed to create file watcher")?;
+        watcher
+            .watch(root, mode)
+            .with_context(|| format!("failed to watch {}", root.display()))?;
+        Ok(Self {
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Waits for the next changes, collecting events until none arrive for
+    /// `debounce`. Returns `None` once the watcher has stopped.
+    pub async fn next_batch(&mut self, debounce: Duration) -> Option<Vec<FileChange>> {
+        loop {
+            let mut raw = Vec::new();
+            collect(&mut raw, self.events.recv().await?);
+            while let Ok(Some(event)) = tokio::time::timeout(debounce, self.events.recv()).await {
+                collect(&mut raw, event);
+            }
+            let changes = coalesce(raw, Path::exists);
+            if !changes.is_empty() {
+                return Some(changes);
+            }
+        }
+    }
+}
+
+fn collect(raw: &mut Vec<(PathBuf, FileChangeType)>, event: notify::Result<Event>) {
+    let event = match event {
+        Ok(event) => event,
+        Err(err) => {
+            tracing::warn!(?err, "File watcher error");
+            return;
+        }
+    };
+    let paths = event.paths.into_iter().filter(|path| !is_ignored(path));
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            raw.extend(paths.map(|path| (path, FileChangeType::Created)));
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            raw.extend(paths.map(|path| (path, FileChangeType::Deleted)));
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let kinds = [FileChangeType::Deleted, FileChangeType::Created];
+            raw.extend(paths.zip(kinds));
+        }
+        // Permission and timestamp changes do not affect content
+        EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_)) => {}
+        _ => raw.extend(paths.map(|path| (path, FileChangeType::Changed))),
+    }
+}
+
+fn is_ignored(path: &Path) -> bool {
+    path.components().any(|component| {
+        matches!(component, Component::Normal(name) if IGNORED_DIRS.iter().any(|dir| name == *dir))
+    })
+}
+
+/// Reduces raw events to one change per path.
+///
+/// The path's current existence decides the outcome: gone means deleted (or nothing,
+/// for a file created and removed within the batch), present means created if the
+/// first event created it and changed otherwise, which also covers editors that save
+/// by replacing the file.
+fn coalesce(
+    raw: Vec<(PathBuf, FileChangeType)>,
+    exists: impl Fn(&Path) -> bool,
+) -> Vec<FileChange> {
+    let mut first = BTreeMap::new();
+    for (path, kind) in raw {
+        first.entry(path).or_insert(kind);
+    }
+    first
+        .into_iter()
+        .filter_map(|(path, first)| {
+            let kind = match (first, exists(&path)) {
+                (FileChangeType::Created, true) => FileChangeType::Created,
+                (FileChangeType::Created, false) => return None,
+                (_, true) => FileChangeType::Changed,
+                (_, false) => FileChangeType::Deleted,
+            };
+            Some(FileChange { path, kind })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FileChangeType::*;
+
+    fn change(path: &str, kind: FileChangeType) -> FileChange {
+        FileChange {
+            path: PathBuf::from(path),
+            kind,
+        }
+    }
+
+    #[test]
+    fn registrations_filter_by_glob_and_kind() {
+        let registry = WatchRegistry::default();
+        assert!(registry.is_empty());
+        registry.register(
+            "1",
+            &json!({ "watchers": [
+                { "globPattern": "**/*.go" },
+                { "globPattern": { "baseUri": "file:///ws", "pattern": "go.{mod,sum}" }, "kind": 4 },
+                { "globPattern": "[" },
+            ]}),
+        );
+        assert!(registry.matches(&change("/ws/pkg/a.go", Changed)));
+        assert!(!registry.matches(&change("/ws/pkg/a.rs", Changed)));
+        assert!(registry.matches(&change("/ws/go.sum", Deleted)));
+        assert!(!registry.matches(&change("/ws/go.sum", Created)));
+        assert!(!registry.matches(&change("/ws/sub/go.mod", Deleted)));
+
+        registry.unregister("1");
+        assert!(!registry.matches(&change("/ws/pkg/a.go", Changed)));
+    }
+
+    #[test]
+    fn coalesce_uses_first_event_and_existence() {
+        let raw = vec![
+            (PathBuf::from("/ws/saved.rs"), Deleted),
+            (PathBuf::from("/ws/saved.rs"), Created),
+            (PathBuf::from("/ws/new.rs"), Created),
+            (PathBuf::from("/ws/new.rs"), Changed),
+            (PathBuf::from("/ws/tmp.rs"), Created),
+            (PathBuf::from("/ws/tmp.rs"), Deleted),
+            (PathBuf::from("/ws/gone.rs"), Changed),
+        ];
+        let present = ["/ws/saved.rs", "/ws/new.rs"];
+        let changes = coalesce(raw, |path| present.iter().any(|p| path == Path::new(p)));
+        assert_eq!(
+            changes,
+            [
+                change("/ws/gone.rs", Deleted),
+                change("/ws/new.rs", Created),
+                change("/ws/saved.rs", Changed),
+            ]
+        );
+    }
+
+    #[test]
+    fn git_directory_is_ignored() {
+        assert!(is_ignored(Path::new("/ws/.git/HEAD")));
+        assert!(!is_ignored(Path::new("/ws/src/git.rs")));
+    }
+}
@@ -0,0 +1,27 @@
+use std::path::Path;
+
+use anyhow::{Result, bail};
+
+use pathfinder::golden;
+
+/// Replays every case under tests/golden; see `pathfinder::golden` for the layout.
+#[tokio::test]
+async fn recorded_sessions_match_golden_results() -> Result<()> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut cases: Vec<_> = std::fs::read_dir(&root)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    cases.retain(|path| path.is_dir());
+    cases.sort();
+
+    let mut failures = Vec::new();
+    for case in &cases {
+        if let Err(err) = golden::check(case).await {
+            failures.push(format!("{}: {err:#}", case.display()));
+        }
+    }
+    if !failures.is_empty() {
+        bail!("{}", failures.join("\n\n"));
+    }
+    Ok(())
+}
@@ -0,0 +1,8 @@
+fn main() {
+    let sum = add(1, 2);
+    println!("{}", sum);
+}
+
+fn add(a: i32, b: i32) -> i32 {
+    a + b
+}